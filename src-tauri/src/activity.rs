@@ -0,0 +1,164 @@
+// Per-session activity tracking and the idle monitor.
+//
+// Only terminal data counts as activity: input written by the user and
+// output read from the channel. SSH keepalives are answered inside libssh2
+// and telnet negotiation is stripped before output reaches the pipeline, so
+// neither keeps a forgotten session looking busy.
+
+use crate::{
+    history, teardown_session, AppState, SessionClosedPayload, SessionIdlePayload,
+    SessionIdleWarningPayload,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+use tracing::info;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+pub const DEFAULT_IDLE_AFTER_SECS: u64 = 15 * 60;
+// The warning goes out this long before an idle session is disconnected
+const DISCONNECT_WARNING_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct IdleConfig {
+    pub idle_after_secs: u64,       // 0 disables the idle event
+    pub disconnect_after_secs: u64, // 0 disables auto-disconnect
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityInfo {
+    pub last_input_at: u64, // Unix timestamp in milliseconds
+    pub last_output_at: u64,
+    pub idle_secs: u64,
+}
+
+pub struct SessionActivity {
+    last_input: AtomicU64,
+    last_output: AtomicU64,
+    idle_notified: AtomicBool,
+    warned: AtomicBool,
+    // Per-host overrides of the global thresholds
+    idle_after_secs: Option<u64>,
+    disconnect_after_secs: Option<u64>,
+}
+
+impl SessionActivity {
+    pub fn new(idle_after_secs: Option<u64>, disconnect_after_secs: Option<u64>) -> Self {
+        let now = now_millis();
+        Self {
+            last_input: AtomicU64::new(now),
+            last_output: AtomicU64::new(now),
+            idle_notified: AtomicBool::new(false),
+            warned: AtomicBool::new(false),
+            idle_after_secs,
+            disconnect_after_secs,
+        }
+    }
+
+    pub fn touch_input(&self) {
+        self.last_input.store(now_millis(), Ordering::Relaxed);
+        self.reset_notifications();
+    }
+
+    pub fn touch_output(&self) {
+        self.last_output.store(now_millis(), Ordering::Relaxed);
+        self.reset_notifications();
+    }
+
+    fn reset_notifications(&self) {
+        self.idle_notified.store(false, Ordering::Relaxed);
+        self.warned.store(false, Ordering::Relaxed);
+    }
+
+    pub fn info(&self) -> ActivityInfo {
+        let last_input_at = self.last_input.load(Ordering::Relaxed);
+        let last_output_at = self.last_output.load(Ordering::Relaxed);
+        ActivityInfo {
+            last_input_at,
+            last_output_at,
+            idle_secs: now_millis().saturating_sub(last_input_at.max(last_output_at)) / 1000,
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Periodically checks every session against the idle thresholds.
+pub fn spawn_idle_monitor(app_handle: AppHandle) {
+    thread::spawn(move || loop {
+        thread::sleep(CHECK_INTERVAL);
+        check_sessions(&app_handle);
+    });
+}
+
+fn check_sessions(app_handle: &AppHandle) {
+    let state = app_handle.state::<AppState>();
+    let global = state.settings.get();
+    let mut expired = Vec::new();
+
+    for entry in state.sessions.iter() {
+        let activity = &entry.activity;
+        let idle_secs = activity.info().idle_secs;
+        let session_id = entry.key().to_string();
+
+        let idle_after = activity.idle_after_secs.unwrap_or(global.idle_after_secs);
+        if idle_after > 0
+            && idle_secs >= idle_after
+            && !activity.idle_notified.swap(true, Ordering::Relaxed)
+        {
+            entry.owners.emit(
+                app_handle,
+                "session-idle",
+                SessionIdlePayload {
+                    session_id: session_id.clone(),
+                    idle_secs,
+                },
+            );
+        }
+
+        let disconnect_after = activity
+            .disconnect_after_secs
+            .unwrap_or(global.disconnect_after_secs);
+        if disconnect_after == 0 {
+            continue;
+        }
+        if idle_secs >= disconnect_after {
+            expired.push(*entry.key());
+        } else if idle_secs + DISCONNECT_WARNING_SECS >= disconnect_after
+            && !activity.warned.swap(true, Ordering::Relaxed)
+        {
+            entry.owners.emit(
+                app_handle,
+                "session-idle-warning",
+                SessionIdleWarningPayload {
+                    session_id,
+                    disconnect_in_secs: disconnect_after - idle_secs,
+                },
+            );
+        }
+    }
+
+    for uuid in expired {
+        if let Some((_, session)) = state.sessions.remove(&uuid) {
+            let session_id = uuid.to_string();
+            info!(target = "activity", session = %session_id, "Disconnecting idle session");
+            history::finish_session(app_handle, &session.target, "idle timeout");
+            let labels = session.owners.labels();
+            teardown_session(session, &session_id);
+            let payload = SessionClosedPayload {
+                session_id,
+                reason: "idle timeout".to_string(),
+            };
+            for label in labels {
+                let _ = app_handle.emit_to(label.as_str(), "session-closed", payload.clone());
+            }
+        }
+    }
+}
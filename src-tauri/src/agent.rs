@@ -0,0 +1,539 @@
+// The local SSH agent: what it holds, loading keys into it, and signing in
+// with it at connect time.
+//
+// The agent is found by trying its backends in order: SSH_AUTH_SOCK on unix,
+// the Windows OpenSSH pipe then Pageant on Windows. The agent_backend setting
+// picks one instead. Listing and adding keys speak the agent protocol here,
+// through whichever backend answered. Signing in goes through libssh2, which
+// finds the agent on its own and tries Pageant before the OpenSSH pipe, so
+// with both running on Windows the keys come from Pageant.
+
+use crate::error::{AppError, ErrorKind};
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use ssh_encoding::{Decode, Encode};
+use ssh_key::{HashAlg, PrivateKey, PublicKey};
+use std::fs;
+use std::io::{Read, Write};
+use tauri::{AppHandle, Emitter, State};
+use tracing::{info, warn};
+
+// Returned by add_key_to_agent after "key-passphrase-required" is emitted;
+// the frontend asks for the passphrase and calls again with it
+pub const PASSPHRASE_REQUIRED: &str = "passphrase-required";
+
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENT_SUCCESS: u8 = 6;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_ADD_IDENTITY: u8 = 17;
+#[cfg(all(test, feature = "agent-tests"))]
+const SSH_AGENTC_REMOVE_IDENTITY: u8 = 18;
+// Agent replies are small, anything bigger is not an agent
+const MAX_REPLY: usize = 256 * 1024;
+#[cfg(windows)]
+const OPENSSH_PIPE: &str = r"\\.\pipe\openssh-ssh-agent";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentBackend {
+    // Whichever answers first
+    #[default]
+    Auto,
+    // SSH_AUTH_SOCK
+    Unix,
+    // The Windows OpenSSH agent service's named pipe
+    #[serde(rename = "openssh")]
+    OpenSsh,
+    Pageant,
+}
+
+impl AgentBackend {
+    fn label(self) -> &'static str {
+        match self {
+            AgentBackend::Auto => "auto",
+            AgentBackend::Unix => "SSH_AUTH_SOCK",
+            AgentBackend::OpenSsh => "OpenSSH agent pipe",
+            AgentBackend::Pageant => "Pageant",
+        }
+    }
+
+    fn candidates(self) -> Vec<AgentBackend> {
+        match self {
+            AgentBackend::Auto if cfg!(windows) => {
+                vec![AgentBackend::OpenSsh, AgentBackend::Pageant]
+            }
+            AgentBackend::Auto => vec![AgentBackend::Unix],
+            backend => vec![backend],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentIdentity {
+    pub key_type: String,
+    pub comment: String,
+    pub fingerprint: String, // SHA256:...
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum AgentStatus {
+    NotRunning {
+        reason: String,
+    },
+    Running {
+        backend: AgentBackend,
+        identities: Vec<AgentIdentity>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PassphraseRequiredPayload {
+    path: String,
+}
+
+fn identity(public_key: PublicKey) -> AgentIdentity {
+    AgentIdentity {
+        key_type: public_key.algorithm().to_string(),
+        comment: public_key.comment().to_string(),
+        fingerprint: public_key.fingerprint(HashAlg::Sha256).to_string(),
+    }
+}
+
+fn identity_from_blob(blob: &[u8], comment: &str) -> AgentIdentity {
+    match PublicKey::from_bytes(blob) {
+        Ok(mut public_key) => {
+            public_key.set_comment(comment);
+            identity(public_key)
+        }
+        // Key types ssh-key doesn't know still get listed
+        Err(_) => AgentIdentity {
+            key_type: "unknown".to_string(),
+            comment: comment.to_string(),
+            fingerprint: String::new(),
+        },
+    }
+}
+
+trait AgentStream: Read + Write {}
+impl<T: Read + Write> AgentStream for T {}
+
+enum Transport {
+    Stream(Box<dyn AgentStream>),
+    #[cfg(windows)]
+    Pageant,
+}
+
+pub(crate) struct AgentClient {
+    pub backend: AgentBackend,
+    transport: Transport,
+}
+
+fn open_backend(backend: AgentBackend) -> Result<Transport, String> {
+    match backend {
+        #[cfg(unix)]
+        AgentBackend::Unix => {
+            let socket = std::env::var_os("SSH_AUTH_SOCK").ok_or("SSH_AUTH_SOCK is not set")?;
+            let stream =
+                std::os::unix::net::UnixStream::connect(socket).map_err(|e| e.to_string())?;
+            Ok(Transport::Stream(Box::new(stream)))
+        }
+        #[cfg(windows)]
+        AgentBackend::OpenSsh => {
+            let pipe = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(OPENSSH_PIPE)
+                .map_err(|e| format!("the agent service isn't running ({})", e))?;
+            Ok(Transport::Stream(Box::new(pipe)))
+        }
+        #[cfg(windows)]
+        AgentBackend::Pageant => {
+            if !pageant::is_running() {
+                return Err("not running".to_string());
+            }
+            Ok(Transport::Pageant)
+        }
+        AgentBackend::Auto => Err("no backend chosen".to_string()),
+        _ => Err("not available on this platform".to_string()),
+    }
+}
+
+/// Finds the agent, trying each backend `preference` allows. The error
+/// names every backend probed and why it failed.
+pub(crate) fn connect(preference: AgentBackend) -> Result<AgentClient, String> {
+    let mut failures = Vec::new();
+    for backend in preference.candidates() {
+        match open_backend(backend) {
+            Ok(transport) => return Ok(AgentClient { backend, transport }),
+            Err(e) => failures.push(format!("{}: {}", backend.label(), e)),
+        }
+    }
+    Err(format!(
+        "No SSH agent available (tried {})",
+        failures.join("; ")
+    ))
+}
+
+impl AgentClient {
+    // One request, one reply; both framed with a big-endian length
+    fn request(&mut self, request: &[u8]) -> Result<Vec<u8>, String> {
+        let len = u32::try_from(request.len()).map_err(|e| e.to_string())?;
+        let mut framed = len.to_be_bytes().to_vec();
+        framed.extend_from_slice(request);
+        let reply = match &mut self.transport {
+            Transport::Stream(stream) => {
+                stream
+                    .write_all(&framed)
+                    .and_then(|_| stream.flush())
+                    .map_err(|e| e.to_string())?;
+                let mut len = [0u8; 4];
+                stream.read_exact(&mut len).map_err(|e| e.to_string())?;
+                let len = u32::from_be_bytes(len) as usize;
+                if len > MAX_REPLY {
+                    return Err("Unexpected reply from the SSH agent".to_string());
+                }
+                let mut reply = vec![0u8; len];
+                stream.read_exact(&mut reply).map_err(|e| e.to_string())?;
+                reply
+            }
+            #[cfg(windows)]
+            Transport::Pageant => pageant::request(&framed)?,
+        };
+        if reply.is_empty() {
+            return Err("Unexpected reply from the SSH agent".to_string());
+        }
+        Ok(reply)
+    }
+
+    // (key blob, comment) for each key the agent holds
+    fn identities(&mut self) -> Result<Vec<(Vec<u8>, String)>, String> {
+        let reply = self.request(&[SSH_AGENTC_REQUEST_IDENTITIES])?;
+        if reply[0] != SSH_AGENT_IDENTITIES_ANSWER {
+            return Err(format!(
+                "Unexpected reply from the SSH agent ({})",
+                reply[0]
+            ));
+        }
+        let malformed = |_| "Malformed identity list from the SSH agent".to_string();
+        let mut reader = &reply[1..];
+        let count = u32::decode(&mut reader).map_err(malformed)?;
+        let mut identities = Vec::new();
+        for _ in 0..count {
+            let blob = Vec::<u8>::decode(&mut reader).map_err(malformed)?;
+            let comment = String::decode(&mut reader).map_err(malformed)?;
+            identities.push((blob, comment));
+        }
+        Ok(identities)
+    }
+
+    fn add_identity(&mut self, key: &PrivateKey) -> Result<(), String> {
+        // The private key is serialized the same way as in the key file
+        let mut request = vec![SSH_AGENTC_ADD_IDENTITY];
+        key.key_data()
+            .encode(&mut request)
+            .and_then(|_| key.comment().encode(&mut request))
+            .map_err(|e| e.to_string())?;
+        match self.request(&request)?[0] {
+            SSH_AGENT_SUCCESS => Ok(()),
+            SSH_AGENT_FAILURE => Err("The SSH agent refused the key".to_string()),
+            other => Err(format!("Unexpected reply from the SSH agent ({})", other)),
+        }
+    }
+}
+
+/// Signs in with a key from the agent, trying each key it holds. The agent
+/// is probed first so a missing one is reported by backend.
+pub(crate) fn authenticate(
+    session: &ssh2::Session,
+    username: &str,
+    preference: AgentBackend,
+) -> Result<(), AppError> {
+    let backend = connect(preference)
+        .map_err(|e| AppError::new(ErrorKind::AuthFailed, e))?
+        .backend;
+    let mut agent = session.agent()?;
+    agent.connect()?;
+    agent.list_identities()?;
+    let identities = agent.identities()?;
+    if identities.is_empty() {
+        let _ = agent.disconnect();
+        return Err(AppError::new(
+            ErrorKind::AuthFailed,
+            format!("The SSH agent ({}) holds no keys", backend.label()),
+        ));
+    }
+    for key in &identities {
+        match agent.userauth(username, key) {
+            Ok(()) => {
+                info!(
+                    target = "agent",
+                    backend = backend.label(),
+                    comment = key.comment(),
+                    "Authenticated with agent key"
+                );
+                let _ = agent.disconnect();
+                return Ok(());
+            }
+            Err(e) => {
+                warn!(target = "agent", comment = key.comment(), error = %e, "Agent key rejected")
+            }
+        }
+    }
+    let _ = agent.disconnect();
+    Err(AppError::new(
+        ErrorKind::AuthFailed,
+        "The server accepted none of the SSH agent's keys",
+    ))
+}
+
+#[tauri::command]
+pub fn list_agent_identities(state: State<'_, AppState>) -> Result<AgentStatus, AppError> {
+    let mut client = match connect(state.settings.get().agent_backend) {
+        Ok(client) => client,
+        Err(reason) => return Ok(AgentStatus::NotRunning { reason }),
+    };
+    let identities = client
+        .identities()?
+        .iter()
+        .map(|(blob, comment)| identity_from_blob(blob, comment))
+        .collect();
+    Ok(AgentStatus::Running {
+        backend: client.backend,
+        identities,
+    })
+}
+
+/// Loads an OpenSSH private key into the agent. Encrypted keys need their
+/// passphrase; without one this emits "key-passphrase-required" and returns
+/// PASSPHRASE_REQUIRED.
+#[tauri::command]
+pub fn add_key_to_agent(
+    path: String,
+    passphrase: Option<String>,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<AgentIdentity, AppError> {
+    let content = fs::read_to_string(&path)?;
+    let mut key = PrivateKey::from_openssh(&content).map_err(|_| {
+        "Only OpenSSH format keys can be added, convert it with ssh-keygen -p".to_string()
+    })?;
+    if key.is_encrypted() {
+        let Some(passphrase) = passphrase else {
+            let _ = app_handle.emit(
+                "key-passphrase-required",
+                PassphraseRequiredPayload { path: path.clone() },
+            );
+            return Err(AppError::new(
+                ErrorKind::PassphraseRequired,
+                PASSPHRASE_REQUIRED,
+            ));
+        };
+        key = key
+            .decrypt(passphrase)
+            .map_err(|_| "Wrong passphrase".to_string())?;
+    }
+
+    let mut client = connect(state.settings.get().agent_backend)?;
+    client.add_identity(&key)?;
+
+    info!(target = "agent", %path, backend = client.backend.label(), "Added key to the SSH agent");
+    Ok(identity(key.public_key().clone()))
+}
+
+// Pageant's protocol: the request goes into a named file mapping, whose name
+// is sent to Pageant's window in a WM_COPYDATA message; Pageant writes the
+// reply over it. Messages are at most MAX_MESSAGE bytes both ways. Pageant
+// checks the mapping is owned by its own user, so an elevated app may be
+// refused by a non-elevated Pageant.
+#[cfg(windows)]
+mod pageant {
+    use std::ffi::CString;
+    use std::ptr;
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE, HWND, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::DataExchange::COPYDATASTRUCT;
+    use windows_sys::Win32::System::Memory::{
+        CreateFileMappingA, MapViewOfFile, UnmapViewOfFile, FILE_MAP_WRITE,
+        MEMORY_MAPPED_VIEW_ADDRESS, PAGE_READWRITE,
+    };
+    use windows_sys::Win32::System::Threading::GetCurrentThreadId;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{FindWindowA, SendMessageA, WM_COPYDATA};
+
+    const AGENT_COPYDATA_ID: usize = 0x804e_50ba;
+    const MAX_MESSAGE: usize = 8192;
+
+    fn find_window() -> HWND {
+        // SAFETY: both arguments are NUL-terminated strings
+        unsafe { FindWindowA(c"Pageant".as_ptr().cast(), c"Pageant".as_ptr().cast()) }
+    }
+
+    pub fn is_running() -> bool {
+        !find_window().is_null()
+    }
+
+    // Unmaps and closes the mapping however the request ends
+    struct Mapping {
+        handle: HANDLE,
+        view: MEMORY_MAPPED_VIEW_ADDRESS,
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            // SAFETY: both were returned by the calls in request and are
+            // released only here
+            unsafe {
+                if !self.view.Value.is_null() {
+                    UnmapViewOfFile(self.view);
+                }
+                CloseHandle(self.handle);
+            }
+        }
+    }
+
+    // `framed` is the length-prefixed request; returns the reply without
+    // its length
+    pub fn request(framed: &[u8]) -> Result<Vec<u8>, String> {
+        if framed.len() > MAX_MESSAGE {
+            return Err("Request too large for Pageant".to_string());
+        }
+        let window = find_window();
+        if window.is_null() {
+            return Err("Pageant is not running".to_string());
+        }
+        // SAFETY: no preconditions
+        let thread = unsafe { GetCurrentThreadId() };
+        let name =
+            CString::new(format!("PageantRequest{:08x}", thread)).map_err(|e| e.to_string())?;
+
+        // SAFETY: a pagefile-backed mapping of MAX_MESSAGE bytes with a
+        // NUL-terminated name; the handle is checked before use
+        let handle = unsafe {
+            CreateFileMappingA(
+                INVALID_HANDLE_VALUE,
+                ptr::null(),
+                PAGE_READWRITE,
+                0,
+                MAX_MESSAGE as u32,
+                name.as_ptr().cast(),
+            )
+        };
+        if handle.is_null() {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        let mut mapping = Mapping {
+            handle,
+            view: MEMORY_MAPPED_VIEW_ADDRESS {
+                Value: ptr::null_mut(),
+            },
+        };
+        // SAFETY: maps the whole of the mapping created above
+        mapping.view = unsafe { MapViewOfFile(handle, FILE_MAP_WRITE, 0, 0, 0) };
+        if mapping.view.Value.is_null() {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        let view = mapping.view.Value.cast::<u8>();
+        // SAFETY: the view is MAX_MESSAGE bytes and framed is no longer
+        unsafe { ptr::copy_nonoverlapping(framed.as_ptr(), view, framed.len()) };
+
+        let data = COPYDATASTRUCT {
+            dwData: AGENT_COPYDATA_ID,
+            cbData: name.as_bytes_with_nul().len() as u32,
+            lpData: name.as_ptr() as *mut _,
+        };
+        // SAFETY: data and the name it points to outlive the synchronous
+        // SendMessage call
+        let answered = unsafe {
+            SendMessageA(
+                window,
+                WM_COPYDATA,
+                0,
+                &data as *const COPYDATASTRUCT as isize,
+            )
+        };
+        if answered == 0 {
+            return Err("Pageant refused the request".to_string());
+        }
+
+        let mut len = [0u8; 4];
+        // SAFETY: reads stay within the MAX_MESSAGE bytes of the view
+        unsafe { ptr::copy_nonoverlapping(view, len.as_mut_ptr(), 4) };
+        let len = u32::from_be_bytes(len) as usize;
+        if len + 4 > MAX_MESSAGE {
+            return Err("Unexpected reply from Pageant".to_string());
+        }
+        let mut reply = vec![0u8; len];
+        // SAFETY: len + 4 was checked against the view's size
+        unsafe { ptr::copy_nonoverlapping(view.add(4), reply.as_mut_ptr(), len) };
+        Ok(reply)
+    }
+}
+
+// These need a running agent, which build machines don't have; run them with
+// `cargo test --features agent-tests`.
+#[cfg(all(test, feature = "agent-tests"))]
+mod tests {
+    use super::*;
+    use ssh_key::rand_core::OsRng;
+    use ssh_key::Algorithm;
+
+    fn test_key() -> PrivateKey {
+        let mut key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        key.set_comment("terminoda-agent-test");
+        key
+    }
+
+    fn remove_identity(client: &mut AgentClient, key: &PrivateKey) {
+        let mut request = vec![SSH_AGENTC_REMOVE_IDENTITY];
+        key.public_key()
+            .to_bytes()
+            .unwrap()
+            .encode(&mut request)
+            .unwrap();
+        assert_eq!(client.request(&request).unwrap()[0], SSH_AGENT_SUCCESS);
+    }
+
+    fn round_trip(preference: AgentBackend) {
+        let mut client = connect(preference).unwrap();
+        let key = test_key();
+        let blob = key.public_key().to_bytes().unwrap();
+        client.add_identity(&key).unwrap();
+        let listed = client.identities().unwrap();
+        remove_identity(&mut client, &key);
+        assert!(listed
+            .iter()
+            .any(|(b, comment)| *b == blob && comment == key.comment()));
+    }
+
+    #[test]
+    fn auto_finds_an_agent() {
+        let client = connect(AgentBackend::Auto).unwrap();
+        assert_ne!(client.backend, AgentBackend::Auto);
+    }
+
+    #[test]
+    fn adds_lists_and_removes_a_key() {
+        round_trip(AgentBackend::Auto);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn openssh_pipe_round_trip() {
+        round_trip(AgentBackend::OpenSsh);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn pageant_round_trip() {
+        round_trip(AgentBackend::Pageant);
+    }
+
+    #[test]
+    fn error_names_probed_backends() {
+        let error = match connect(AgentBackend::Pageant) {
+            Ok(_) => return,
+            Err(e) => e,
+        };
+        assert!(error.contains("Pageant"), "{}", error);
+    }
+}
@@ -0,0 +1,99 @@
+// Where the config files live.
+//
+// The directory comes from Tauri's path resolver (XDG_CONFIG_HOME on Linux,
+// Application Support on macOS, the roaming AppData on Windows) and is
+// resolved once at startup. Older versions guessed from HOME or APPDATA;
+// files found there are moved over on first launch.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+
+static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+// Everything older versions wrote, each also with a ".bak" copy
+const LEGACY_FILES: [&str; 9] = [
+    "connections.json",
+    "snippets.json",
+    "history.json",
+    "keychain.json",
+    "groups.json",
+    "snippet_usage.json",
+    "vault.json",
+    "credentials.enc",
+    "credentials.key",
+];
+
+/// Resolves the config directory and moves legacy files into it. Called
+/// from setup, before any command can run.
+pub fn init(app_handle: &AppHandle) -> Result<(), String> {
+    let dir = app_handle
+        .path()
+        .app_config_dir()
+        .map_err(|e| format!("Could not resolve the config directory: {}", e))?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    if let Some(legacy) = legacy_dir().filter(|l| *l != dir) {
+        migrate_legacy(&legacy, &dir);
+    }
+    let _ = CONFIG_DIR.set(dir);
+    Ok(())
+}
+
+/// The config directory, created if it has gone missing since startup.
+pub fn config_dir() -> Result<PathBuf, String> {
+    let dir = CONFIG_DIR
+        .get()
+        .ok_or("The config directory is not initialized")?;
+    if !dir.exists() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    }
+    Ok(dir.clone())
+}
+
+fn legacy_dir() -> Option<PathBuf> {
+    match std::env::var_os("HOME") {
+        Some(home) => Some(PathBuf::from(home).join(".config/terminoda")),
+        None => std::env::var_os("APPDATA").map(PathBuf::from),
+    }
+}
+
+// Files already in the new directory win; a failed move leaves the old file
+fn migrate_legacy(legacy: &Path, dir: &Path) {
+    let mut moved = 0;
+    for name in LEGACY_FILES {
+        for name in [name.to_string(), format!("{}.bak", name)] {
+            let from = legacy.join(&name);
+            let to = dir.join(&name);
+            if !from.is_file() || to.exists() {
+                continue;
+            }
+            match move_file(&from, &to) {
+                Ok(()) => moved += 1,
+                Err(e) => {
+                    warn!(target = "config", file = %name, error = %e, "Failed to move legacy config file")
+                }
+            }
+        }
+    }
+    if moved > 0 {
+        info!(
+            target = "config",
+            from = %legacy.display(),
+            to = %dir.display(),
+            files = moved,
+            "Moved config files to the new location"
+        );
+    }
+}
+
+// Renames, falling back to copy and delete across filesystems
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
@@ -0,0 +1,325 @@
+// Append-only audit log of changes made through the file manager.
+//
+// Every delete, rename, chmod, mkdir and upload appends one JSON line to
+// audit.jsonl in the config dir, successful or not. Past MAX_FILE_BYTES the
+// file is rotated to audit.1.jsonl and so on, keeping ROTATED_FILES old
+// ones. Logging never fails the operation itself: a write error is logged
+// and surfaced once as an "audit-log-warning" event, and again only after
+// a write has succeeded in between.
+
+use crate::error::AppError;
+use crate::history::{csv_field, ExportFormat};
+use crate::{config_file, get_config_dir, AppState};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{Emitter, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const FILE_NAME: &str = "audit";
+const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+const ROTATED_FILES: usize = 4;
+const CSV_HEADER: &str =
+    "timestamp,host,username,session_id,operation,paths,parameters,outcome,error";
+
+// Set once a failure has been reported, until a write succeeds
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    Delete,
+    Rename,
+    Chmod,
+    Mkdir,
+    Upload,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: u64, // Unix timestamp
+    pub host: String,
+    pub username: String,
+    pub session_id: String,
+    pub operation: AuditOperation,
+    // Remote paths; for a rename the old then the new one
+    pub paths: Vec<String>,
+    // e.g. {"mode": 420} for chmod, {"local_path": ...} for upload
+    #[serde(default)]
+    pub parameters: Value,
+    pub outcome: AuditOutcome,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditQuery {
+    pub offset: usize,
+    // Every matching entry when unset
+    pub limit: Option<usize>,
+    // Case-insensitive substring of the hostname
+    pub host: Option<String>,
+    pub operation: Option<AuditOperation>,
+    // Unix timestamps, both inclusive
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.host
+            .as_ref()
+            .is_none_or(|h| entry.host.to_lowercase().contains(&h.to_lowercase()))
+            && self.operation.is_none_or(|op| entry.operation == op)
+            && self.since.is_none_or(|t| entry.timestamp >= t)
+            && self.until.is_none_or(|t| entry.timestamp <= t)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    // Matching entries before offset and limit
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct AuditWarning {
+    message: String,
+}
+
+// audit.jsonl for 0, audit.<n>.jsonl for the rotated ones
+fn log_path(generation: usize) -> Result<PathBuf, String> {
+    let name = match generation {
+        0 => format!("{}.jsonl", FILE_NAME),
+        n => format!("{}.{}.jsonl", FILE_NAME, n),
+    };
+    Ok(get_config_dir()?.join(name))
+}
+
+fn rotate() -> Result<(), String> {
+    for generation in (1..=ROTATED_FILES).rev() {
+        let from = log_path(generation - 1)?;
+        if from.exists() {
+            fs::rename(&from, log_path(generation)?).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn append(entry: &AuditEntry) -> Result<(), String> {
+    let path = log_path(0)?;
+    let _lock = config_file::lock(&path)?;
+    if fs::metadata(&path).is_ok_and(|meta| meta.len() >= MAX_FILE_BYTES) {
+        rotate()?;
+    }
+    let mut line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    line.push('\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| e.to_string())?;
+    // One write, so a crash leaves at most a partial last line
+    file.write_all(line.as_bytes()).map_err(|e| e.to_string())
+}
+
+fn entry<T>(
+    host: String,
+    username: String,
+    session_id: &str,
+    operation: AuditOperation,
+    paths: Vec<String>,
+    parameters: Value,
+    result: &Result<T, AppError>,
+) -> AuditEntry {
+    AuditEntry {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        host,
+        username,
+        session_id: session_id.to_string(),
+        operation,
+        paths,
+        parameters,
+        outcome: if result.is_ok() {
+            AuditOutcome::Succeeded
+        } else {
+            AuditOutcome::Failed
+        },
+        error: result.as_ref().err().map(|e| e.message.clone()),
+    }
+}
+
+/// Appends an entry for a file manager operation on `session_id` that
+/// ended with `result`. Never fails; see the module comment.
+pub fn record<T>(
+    window: &Window,
+    state: &AppState,
+    session_id: &str,
+    operation: AuditOperation,
+    paths: Vec<String>,
+    parameters: Value,
+    result: &Result<T, AppError>,
+) {
+    let target = Uuid::parse_str(session_id)
+        .ok()
+        .and_then(|uuid| state.sessions.get(&uuid).map(|s| s.target.clone()));
+    let (host, username) = target.map(|t| (t.host, t.username)).unwrap_or_default();
+    let entry = entry(
+        host, username, session_id, operation, paths, parameters, result,
+    );
+    write(window, &entry);
+}
+
+/// Like record, for an operation done over a connection of its own rather
+/// than an open session. The entry has an empty session id.
+pub fn record_on_host<T>(
+    window: &Window,
+    host: &str,
+    username: &str,
+    operation: AuditOperation,
+    paths: Vec<String>,
+    parameters: Value,
+    result: &Result<T, AppError>,
+) {
+    let entry = entry(
+        host.to_string(),
+        username.to_string(),
+        "",
+        operation,
+        paths,
+        parameters,
+        result,
+    );
+    write(window, &entry);
+}
+
+fn write(window: &Window, entry: &AuditEntry) {
+    match append(entry) {
+        Ok(()) => WARNED.store(false, Ordering::Relaxed),
+        Err(e) => {
+            let operation = entry.operation;
+            warn!(target = "audit", error = %e, ?operation, "Failed to write the audit log");
+            if !WARNED.swap(true, Ordering::Relaxed) {
+                let _ = window.emit(
+                    "audit-log-warning",
+                    AuditWarning {
+                        message: format!("File operations are not being audited: {}", e),
+                    },
+                );
+            }
+        }
+    }
+}
+
+// Every entry, oldest first. Lines that don't parse, like one cut short
+// by a crash, are skipped.
+fn read_all() -> Result<Vec<AuditEntry>, String> {
+    let _lock = config_file::lock(&log_path(0)?)?;
+    let mut entries = Vec::new();
+    for generation in (0..=ROTATED_FILES).rev() {
+        let path = log_path(generation)?;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.to_string()),
+        };
+        entries.extend(
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok()),
+        );
+    }
+    Ok(entries)
+}
+
+/// Returns audit entries matching `query`, newest first.
+#[tauri::command]
+pub fn load_audit_log(query: AuditQuery) -> Result<AuditPage, AppError> {
+    let matching: Vec<AuditEntry> = read_all()?
+        .into_iter()
+        .rev()
+        .filter(|entry| query.matches(entry))
+        .collect();
+    let total = matching.len();
+    let entries = matching
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+    Ok(AuditPage { entries, total })
+}
+
+fn to_csv(entries: &[AuditEntry]) -> String {
+    let mut out = String::from(CSV_HEADER);
+    out.push('\n');
+    for entry in entries {
+        let fields = [
+            entry.timestamp.to_string(),
+            entry.host.clone(),
+            entry.username.clone(),
+            entry.session_id.clone(),
+            serde_json::to_value(entry.operation)
+                .ok()
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_default(),
+            // Paths can hold commas and spaces, JSON keeps them apart
+            serde_json::to_string(&entry.paths).unwrap_or_default(),
+            match &entry.parameters {
+                Value::Null => String::new(),
+                parameters => parameters.to_string(),
+            },
+            match entry.outcome {
+                AuditOutcome::Succeeded => "succeeded".to_string(),
+                AuditOutcome::Failed => "failed".to_string(),
+            },
+            entry.error.clone().unwrap_or_default(),
+        ];
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&line.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Writes the entries matching `query` to `path`, oldest first. Offset and
+/// limit are ignored. Returns how many were written.
+#[tauri::command]
+pub fn export_audit_log(
+    format: ExportFormat,
+    query: AuditQuery,
+    path: String,
+) -> Result<usize, AppError> {
+    let entries: Vec<AuditEntry> = read_all()?
+        .into_iter()
+        .filter(|entry| query.matches(entry))
+        .collect();
+    let content = match format {
+        ExportFormat::Csv => to_csv(&entries),
+        ExportFormat::Json => serde_json::to_string_pretty(&entries)?,
+    };
+    fs::write(&path, content)?;
+    info!(
+        target = "audit",
+        count = entries.len(),
+        ?format,
+        "Exported audit log"
+    );
+    Ok(entries.len())
+}
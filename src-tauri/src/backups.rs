@@ -0,0 +1,406 @@
+// Snapshots of the whole profile in the backups directory.
+//
+// A backup holds every config file byte for byte, so vault-sealed files stay
+// sealed. Host secrets are only included when asked for, encrypted with a
+// password like an exported bundle. Besides manual backups, one is taken
+// silently before each destructive operation; those are rotated by count and
+// total size. Restoring stages and validates every file before swapping the
+// whole set in, and puts the old files back if the swap fails.
+
+use crate::credentials::{self, SecretKind};
+use crate::error::{AppError, ErrorKind};
+use crate::{config_file, crypto, get_config_dir, host_stats, load_saved_hosts, migrations, vault};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const BACKUP_FORMAT: &str = "terminoda-backup";
+const BACKUP_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const MANUAL: &str = "manual";
+
+// Automatic backups kept, newest first, within both limits
+const MAX_AUTO_BACKUPS: usize = 10;
+const MAX_AUTO_BYTES: u64 = 20 * 1024 * 1024;
+
+// Groups before connections, matching the order locks are taken elsewhere
+const PROFILE_FILES: [&str; 9] = [
+    "vault.json",
+    "groups.json",
+    "connections.json",
+    "snippet_folders.json",
+    "snippets.json",
+    "history.json",
+    "keychain.json",
+    "snippet_usage.json",
+    "settings.json",
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct HostSecrets {
+    password: Option<String>,
+    passphrase: Option<String>,
+    totp: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedSecrets {
+    salt: String,
+    data: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupFile {
+    format: String,
+    version: u32,
+    created_at: u64, // Unix timestamp in milliseconds
+    reason: String,
+    files: BTreeMap<String, String>, // Base64 file contents by name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secrets: Option<EncryptedSecrets>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub name: String,
+    pub created_at: u64,
+    pub reason: String,
+    pub automatic: bool,
+    pub size: u64,
+    pub files: usize,
+    pub has_secrets: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreCounts {
+    pub files: usize,
+    pub secrets: usize,
+}
+
+fn backups_dir() -> Result<PathBuf, String> {
+    let dir = get_config_dir()?.join("backups");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn collect_secrets(app_handle: &AppHandle) -> Result<HashMap<String, HostSecrets>, String> {
+    let mut secrets = HashMap::new();
+    for host in load_saved_hosts(app_handle.clone())? {
+        let entry = HostSecrets {
+            password: match host.has_password {
+                true => credentials::load(&host.id, SecretKind::Password)?,
+                false => None,
+            },
+            passphrase: match host.has_passphrase {
+                true => credentials::load(&host.id, SecretKind::Passphrase)?,
+                false => None,
+            },
+            totp: match host.has_totp_secret {
+                true => credentials::load(&host.id, SecretKind::Totp)?,
+                false => None,
+            },
+        };
+        if entry.password.is_some() || entry.passphrase.is_some() || entry.totp.is_some() {
+            secrets.insert(host.id, entry);
+        }
+    }
+    Ok(secrets)
+}
+
+fn write_backup(reason: &str, secrets: Option<EncryptedSecrets>) -> Result<BackupInfo, String> {
+    let dir = get_config_dir()?;
+    let mut files = BTreeMap::new();
+    for name in PROFILE_FILES {
+        // Renames are atomic, so a plain read sees a whole file
+        match fs::read(dir.join(name)) {
+            Ok(bytes) => {
+                files.insert(name.to_string(), BASE64.encode(bytes));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Failed to read {}: {}", name, e)),
+        }
+    }
+    let backup = BackupFile {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        created_at: now_millis(),
+        reason: reason.to_string(),
+        files,
+        secrets,
+    };
+    let name = format!("backup-{}-{}.json", backup.created_at, reason);
+    let content = serde_json::to_vec_pretty(&backup).map_err(|e| e.to_string())?;
+    config_file::write_atomic(&backups_dir()?.join(&name), &content)?;
+    Ok(info_for(name, content.len() as u64, &backup))
+}
+
+fn info_for(name: String, size: u64, backup: &BackupFile) -> BackupInfo {
+    BackupInfo {
+        name,
+        created_at: backup.created_at,
+        reason: backup.reason.clone(),
+        automatic: backup.reason != MANUAL,
+        size,
+        files: backup.files.len(),
+        has_secrets: backup.secrets.is_some(),
+    }
+}
+
+fn read_backup(path: &Path) -> Result<BackupFile, String> {
+    let content = fs::read(path).map_err(|e| e.to_string())?;
+    let backup: BackupFile =
+        serde_json::from_slice(&content).map_err(|_| "Not a Terminoda backup".to_string())?;
+    if backup.format != BACKUP_FORMAT {
+        return Err("Not a Terminoda backup".to_string());
+    }
+    if backup.version > BACKUP_VERSION {
+        return Err(format!(
+            "This backup was written by a newer version of Terminoda (format {}), please update",
+            backup.version
+        ));
+    }
+    Ok(backup)
+}
+
+fn prune_automatic() -> Result<(), String> {
+    let automatic: Vec<BackupInfo> = list_backups()?
+        .into_iter()
+        .filter(|b| b.automatic)
+        .collect();
+    let mut total = 0;
+    for (i, backup) in automatic.into_iter().enumerate() {
+        total += backup.size;
+        // The newest is always kept, however large
+        if i > 0 && (i >= MAX_AUTO_BACKUPS || total > MAX_AUTO_BYTES) {
+            fs::remove_file(backups_dir()?.join(&backup.name)).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Takes an automatic backup before a destructive operation. Never fails the
+/// operation; problems are only logged.
+pub fn auto_backup(reason: &str) {
+    let result = write_backup(reason, None).and_then(|_| prune_automatic());
+    if let Err(e) = result {
+        warn!(target = "backups", reason = %reason, error = %e, "Automatic backup failed");
+    }
+}
+
+#[tauri::command]
+pub fn create_backup(
+    include_secrets: bool,
+    password: Option<String>,
+    app_handle: AppHandle,
+) -> Result<BackupInfo, AppError> {
+    let secrets = match (include_secrets, password.filter(|p| !p.is_empty())) {
+        (false, _) => None,
+        (true, None) => {
+            return Err(AppError::new(
+                ErrorKind::InvalidInput,
+                "A password is required to back up secrets",
+            ))
+        }
+        (true, Some(password)) => {
+            let salt = crypto::random_bytes::<SALT_LEN>();
+            let key = crypto::derive_key(&password, &salt)?;
+            let plaintext = serde_json::to_vec(&collect_secrets(&app_handle)?)?;
+            Some(EncryptedSecrets {
+                salt: BASE64.encode(salt),
+                data: BASE64.encode(crypto::encrypt(&key, &plaintext)?),
+            })
+        }
+    };
+    let backup = write_backup(MANUAL, secrets)?;
+    info!(target = "backups", name = %backup.name, files = backup.files, "Created backup");
+    Ok(backup)
+}
+
+/// Every backup, newest first. Unreadable files in the directory are skipped.
+#[tauri::command]
+pub fn list_backups() -> Result<Vec<BackupInfo>, AppError> {
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(backups_dir()?)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        let Ok(backup) = read_backup(&path) else {
+            continue;
+        };
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        backups.push(info_for(name, size, &backup));
+    }
+    backups.sort_by_key(|b| Reverse(b.created_at));
+    Ok(backups)
+}
+
+// Checks a file from the backup parses as the config file it claims to be
+fn validate(name: &str, bytes: &[u8]) -> Result<(), String> {
+    let invalid = |e: String| format!("{} in the backup is invalid: {}", name, e);
+    let content = std::str::from_utf8(bytes).map_err(|e| invalid(e.to_string()))?;
+    // Sealed files can only be checked for their outer layer while locked
+    if vault::is_sealed(content) {
+        return vault::check_sealed(content).map_err(invalid);
+    }
+    let value = serde_json::from_str(content).map_err(|e| invalid(e.to_string()))?;
+    let (version, data) = migrations::from_envelope(value).map_err(invalid)?;
+    migrations::upgrade(name, version, data).map_err(invalid)?;
+    Ok(())
+}
+
+fn decrypt_secrets(
+    secrets: &EncryptedSecrets,
+    password: &str,
+) -> Result<HashMap<String, HostSecrets>, String> {
+    let corrupted = || "Backup is corrupted".to_string();
+    let salt = BASE64.decode(&secrets.salt).map_err(|_| corrupted())?;
+    let data = BASE64.decode(&secrets.data).map_err(|_| corrupted())?;
+    let key = crypto::derive_key(password, &salt)?;
+    let plaintext =
+        crypto::decrypt(&key, &data).map_err(|_| "Wrong backup password".to_string())?;
+    serde_json::from_slice(&plaintext).map_err(|_| corrupted())
+}
+
+// Moves the staged files over the profile. On failure, everything already
+// moved is put back before returning the error.
+fn swap_in(dir: &Path, staged: &Path, previous: &Path, names: &[&str]) -> Result<(), String> {
+    let mut done: Vec<&str> = Vec::new();
+    let result = (|| {
+        for name in names {
+            let (current, new) = (dir.join(name), staged.join(name));
+            if current.exists() {
+                fs::rename(&current, previous.join(name))?;
+            }
+            done.push(name);
+            if new.exists() {
+                fs::rename(&new, &current)?;
+            }
+        }
+        Ok::<_, std::io::Error>(())
+    })();
+    if let Err(e) = result {
+        for name in done {
+            let _ = fs::remove_file(dir.join(name));
+            if previous.join(name).exists() {
+                let _ = fs::rename(previous.join(name), dir.join(name));
+            }
+        }
+        return Err(format!("Failed to restore the backup: {}", e));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn restore_backup(name: String, password: Option<String>) -> Result<RestoreCounts, AppError> {
+    if name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Invalid backup name",
+        ));
+    }
+    let backup = read_backup(&backups_dir()?.join(&name))?;
+    let secrets = match (&backup.secrets, password.filter(|p| !p.is_empty())) {
+        (None, _) => HashMap::new(),
+        (Some(_), None) => {
+            return Err(AppError::new(
+                ErrorKind::PasswordRequired,
+                "This backup is password protected",
+            ))
+        }
+        (Some(secrets), Some(password)) => decrypt_secrets(secrets, &password)?,
+    };
+
+    let mut files = Vec::new();
+    for (file, encoded) in &backup.files {
+        if !PROFILE_FILES.contains(&file.as_str()) {
+            warn!(target = "backups", file = %file, "Skipping unknown file in backup");
+            continue;
+        }
+        let bytes = BASE64
+            .decode(encoded)
+            .map_err(|_| "Backup is corrupted".to_string())?;
+        validate(file, &bytes)?;
+        files.push((file.as_str(), bytes));
+    }
+
+    auto_backup("restore");
+    let dir = get_config_dir()?;
+    let _locks = PROFILE_FILES
+        .iter()
+        .map(|name| config_file::lock(&dir.join(name)))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let id = Uuid::new_v4();
+    let staged = dir.join(format!(".restore-{}", id));
+    let previous = dir.join(format!(".restore-{}-previous", id));
+    let result = (|| {
+        fs::create_dir(&staged).map_err(|e| e.to_string())?;
+        fs::create_dir(&previous).map_err(|e| e.to_string())?;
+        for (file, bytes) in &files {
+            config_file::write_atomic(&staged.join(file), bytes)?;
+        }
+        swap_in(&dir, &staged, &previous, &PROFILE_FILES)
+    })();
+    let _ = fs::remove_dir_all(&staged);
+    let _ = fs::remove_dir_all(&previous);
+    result?;
+
+    // Backups of the replaced files would bring them back on damage
+    for name in PROFILE_FILES {
+        let (path, backup_path) = (dir.join(name), config_file::backup_path(&dir.join(name)));
+        match fs::read(&path) {
+            Ok(bytes) => config_file::write_atomic(&backup_path, &bytes)?,
+            Err(_) if backup_path.exists() => fs::remove_file(&backup_path)?,
+            Err(_) => {}
+        }
+    }
+    // The restored vault may use a different key
+    vault::lock_vault()?;
+    host_stats::invalidate();
+
+    let mut restored_secrets = 0;
+    for (host_id, host_secrets) in secrets {
+        for (kind, secret) in [
+            (SecretKind::Password, host_secrets.password),
+            (SecretKind::Passphrase, host_secrets.passphrase),
+            (SecretKind::Totp, host_secrets.totp),
+        ] {
+            if let Some(secret) = secret {
+                credentials::store(&host_id, kind, &secret)?;
+                restored_secrets += 1;
+            }
+        }
+    }
+
+    info!(
+        target = "backups",
+        name = %name,
+        files = files.len(),
+        secrets = restored_secrets,
+        "Restored backup"
+    );
+    Ok(RestoreCounts {
+        files: files.len(),
+        secrets: restored_secrets,
+    })
+}
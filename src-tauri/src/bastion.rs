@@ -0,0 +1,488 @@
+// Jump hosts shared by the sessions that go through them.
+//
+// A connection whose details name a proxy_jump reaches its target over a
+// direct-tcpip channel of an SSH session to the jump host. The channel is
+// bridged to a loopback socket by a pump thread, so the target's own
+// session runs on top of it like on any TCP stream. Jump host sessions are
+// pooled in AppState by host, user and port, so five targets behind one
+// bastion share one connection to it. Every session riding a bastion holds
+// a Lease. When the last lease is dropped the bastion is kept for
+// IDLE_GRACE in case another connection follows, then closed. A pooled
+// session is probed with a keepalive before reuse and reconnected if it's
+// dead.
+//
+// The jump spec is "[user@]host[:port]", one hop only. A saved host with
+// that address or name provides the credentials. Otherwise the target's key
+// or agent settings are used with the jump user, which defaults to the
+// target's user; the target's password is never sent to the jump host.
+
+use crate::error::{AppError, ErrorKind};
+use crate::settings::Settings;
+use crate::{authenticate_session, credentials, known_hosts, ConnectionDetails, SavedHost};
+use credentials::SecretKind;
+use dashmap::DashMap;
+use serde::Serialize;
+use ssh2::{Channel, ErrorCode, Session};
+use std::io::{ErrorKind as IoErrorKind, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const IDLE_GRACE: Duration = Duration::from_secs(60);
+const REAP_INTERVAL: Duration = Duration::from_secs(5);
+const KEEPALIVE_SECS: u32 = 15;
+const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+const CHANNEL_TIMEOUT: Duration = Duration::from_secs(15);
+const RETRY_INTERVAL: Duration = Duration::from_millis(5);
+// Longest a pump sleeps between polls once traffic stops
+const PUMP_MAX_SLEEP: Duration = Duration::from_millis(10);
+const LIBSSH2_ERROR_EAGAIN: i32 = -37;
+// The jump host refused the channel, it's still alive
+const LIBSSH2_ERROR_CHANNEL_FAILURE: i32 = -21;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BastionKey {
+    host: String,
+    username: String,
+    port: u16,
+}
+
+// A jump host as resolved for one connection, secrets included
+pub struct JumpHost {
+    key: BastionKey,
+    details: ConnectionDetails,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BastionInfo {
+    // Same for every session sharing the connection
+    pub id: String,
+    pub host: String,
+    pub username: String,
+    pub port: u16,
+    // Sessions riding it now
+    pub sessions: usize,
+}
+
+struct Bastion {
+    id: String,
+    key: BastionKey,
+    // Non-blocking once connected, shared by the pump threads
+    session: Session,
+    // Clone of the socket libssh2 owns, shut down on close
+    socket: TcpStream,
+    refs: AtomicUsize,
+    idle_since: Mutex<Option<Instant>>,
+    // Set on close or when the connection turns out dead; stops the pumps
+    closed: AtomicBool,
+}
+
+impl Bastion {
+    fn info(&self) -> BastionInfo {
+        BastionInfo {
+            id: self.id.clone(),
+            host: self.key.host.clone(),
+            username: self.key.username.clone(),
+            port: self.key.port,
+            sessions: self.refs.load(Ordering::Relaxed),
+        }
+    }
+
+    // Sends a keepalive when one is due, which fails on a dead connection
+    fn probe(&self) -> bool {
+        if self.closed.load(Ordering::Relaxed) {
+            return false;
+        }
+        match self.session.keepalive_send() {
+            Ok(_) => true,
+            Err(e) if e.code() == ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) => true,
+            Err(e) => {
+                warn!(target = "bastion", host = %self.key.host, error = %e, "Jump host connection is dead");
+                self.closed.store(true, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        let deadline = Instant::now() + CHANNEL_TIMEOUT;
+        let _ = retry(deadline, || {
+            self.session
+                .disconnect(None, "Jump host no longer used", None)
+        });
+        let _ = self.socket.shutdown(Shutdown::Both);
+        info!(target = "bastion", host = %self.key.host, id = %self.id, "Closed jump host connection");
+    }
+
+    fn open(&self, host: &str, port: u16) -> Result<Channel, ssh2::Error> {
+        let deadline = Instant::now() + CHANNEL_TIMEOUT;
+        retry(deadline, || {
+            self.session.channel_direct_tcpip(host, port, None)
+        })
+    }
+}
+
+fn retry<T>(
+    deadline: Instant,
+    mut op: impl FnMut() -> Result<T, ssh2::Error>,
+) -> Result<T, ssh2::Error> {
+    loop {
+        match op() {
+            Err(e)
+                if e.code() == ErrorCode::Session(LIBSSH2_ERROR_EAGAIN)
+                    && Instant::now() < deadline =>
+            {
+                thread::sleep(RETRY_INTERVAL);
+            }
+            result => return result,
+        }
+    }
+}
+
+// Two ends of a loopback TCP connection. Only our own connection is
+// accepted, not whatever else reaches the port first.
+fn loopback_pair() -> std::io::Result<(TcpStream, TcpStream)> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let near = TcpStream::connect(listener.local_addr()?)?;
+    let expected = near.local_addr()?;
+    loop {
+        let (far, peer) = listener.accept()?;
+        if peer == expected {
+            return Ok((near, far));
+        }
+    }
+}
+
+// Writes all of `data` to a non-blocking writer
+fn write_fully(writer: &mut impl Write, mut data: &[u8], closed: &AtomicBool) -> bool {
+    while !data.is_empty() {
+        if closed.load(Ordering::Relaxed) {
+            return false;
+        }
+        match writer.write(data) {
+            Ok(0) => return false,
+            Ok(n) => data = &data[n..],
+            Err(e) if e.kind() == IoErrorKind::WouldBlock => thread::sleep(RETRY_INTERVAL),
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+// Copies between the channel and the loopback socket until either side
+// closes
+fn pump(bastion: &Bastion, mut channel: Channel, mut local: TcpStream) {
+    if local.set_nonblocking(true).is_err() {
+        return;
+    }
+    let mut buffer = [0u8; 32 * 1024];
+    let mut sleep = RETRY_INTERVAL;
+    loop {
+        if bastion.closed.load(Ordering::Relaxed) {
+            break;
+        }
+        let mut progressed = false;
+        match local.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => {
+                if !write_fully(&mut channel, &buffer[..n], &bastion.closed) {
+                    break;
+                }
+                progressed = true;
+            }
+            Err(e) if e.kind() == IoErrorKind::WouldBlock => {}
+            Err(_) => break,
+        }
+        match channel.read(&mut buffer) {
+            Ok(0) if channel.eof() => break,
+            Ok(0) => {}
+            Ok(n) => {
+                if !write_fully(&mut local, &buffer[..n], &bastion.closed) {
+                    break;
+                }
+                progressed = true;
+            }
+            Err(e) if e.kind() == IoErrorKind::WouldBlock => {}
+            Err(_) => break,
+        }
+        if progressed {
+            sleep = RETRY_INTERVAL;
+        } else {
+            thread::sleep(sleep);
+            sleep = (sleep * 2).min(PUMP_MAX_SLEEP);
+        }
+    }
+    let _ = local.shutdown(Shutdown::Both);
+    if !bastion.closed.load(Ordering::Relaxed) {
+        let deadline = Instant::now() + CHANNEL_TIMEOUT;
+        let _ = retry(deadline, || channel.close());
+    }
+}
+
+/// Keeps a pooled jump host connection in use; see the module comment.
+pub struct Lease(Arc<Bastion>);
+
+impl Lease {
+    pub fn info(&self) -> BastionInfo {
+        self.0.info()
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if self.0.refs.fetch_sub(1, Ordering::Relaxed) == 1 {
+            *self.0.idle_since.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+        }
+    }
+}
+
+type Slot = Arc<Mutex<Option<Arc<Bastion>>>>;
+
+#[derive(Default)]
+pub struct BastionPool {
+    // One per jump host, locked while it's checked, connected or reaped
+    slots: DashMap<BastionKey, Slot>,
+    reaper_started: AtomicBool,
+}
+
+impl BastionPool {
+    /// Opens a stream to `host`:`port` through the jump host, reusing its
+    /// pooled connection when alive. Keep the lease for as long as the
+    /// stream is used.
+    pub fn connect(
+        self: &Arc<Self>,
+        jump: &JumpHost,
+        host: &str,
+        port: u16,
+        settings: &Settings,
+    ) -> Result<(TcpStream, Lease), AppError> {
+        self.start_reaper();
+        let slot = self.slots.entry(jump.key.clone()).or_default().clone();
+        let mut slot = slot.lock().unwrap_or_else(|e| e.into_inner());
+        let mut reuse = true;
+        loop {
+            let bastion = match slot.as_ref().filter(|b| reuse && b.probe()) {
+                Some(bastion) => {
+                    info!(target = "bastion", host = %jump.key.host, id = %bastion.id, "Reusing jump host connection");
+                    bastion.clone()
+                }
+                None => {
+                    if let Some(old) = slot.take() {
+                        old.close();
+                    }
+                    let bastion = Arc::new(establish(jump, settings)?);
+                    *slot = Some(bastion.clone());
+                    reuse = false;
+                    bastion
+                }
+            };
+            match bastion.open(host, port) {
+                Ok(channel) => {
+                    let (near, far) = loopback_pair()?;
+                    bastion.refs.fetch_add(1, Ordering::Relaxed);
+                    *bastion.idle_since.lock().unwrap_or_else(|e| e.into_inner()) = None;
+                    let pumped = bastion.clone();
+                    thread::spawn(move || pump(&pumped, channel, far));
+                    return Ok((near, Lease(bastion)));
+                }
+                // The connection died since the probe, reconnect once
+                Err(e)
+                    if reuse && e.code() != ErrorCode::Session(LIBSSH2_ERROR_CHANNEL_FAILURE) =>
+                {
+                    warn!(target = "bastion", host = %jump.key.host, error = %e, "Pooled jump host failed, reconnecting");
+                    bastion.closed.store(true, Ordering::Relaxed);
+                    reuse = false;
+                }
+                Err(e) => {
+                    return Err(AppError::from(e)
+                        .or_kind(ErrorKind::ConnectionFailed)
+                        .context(&format!("{} via {}", host, jump.key.host)))
+                }
+            }
+        }
+    }
+
+    // Closes bastions idle past the grace period and keeps the others alive
+    fn reap(&self) {
+        let slots: Vec<Slot> = self.slots.iter().map(|s| s.value().clone()).collect();
+        for slot in slots {
+            let Ok(mut slot) = slot.try_lock() else {
+                continue;
+            };
+            let Some(bastion) = slot.as_ref() else {
+                continue;
+            };
+            let idle = bastion.refs.load(Ordering::Relaxed) == 0
+                && bastion
+                    .idle_since
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .is_some_and(|since| since.elapsed() >= IDLE_GRACE);
+            if idle || !bastion.probe() {
+                if let Some(bastion) = slot.take() {
+                    bastion.close();
+                }
+            }
+        }
+    }
+
+    fn start_reaper(self: &Arc<Self>) {
+        if self.reaper_started.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let pool: Weak<Self> = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            thread::sleep(REAP_INTERVAL);
+            match pool.upgrade() {
+                Some(pool) => pool.reap(),
+                None => break,
+            }
+        });
+    }
+}
+
+fn establish(jump: &JumpHost, settings: &Settings) -> Result<Bastion, AppError> {
+    let key = &jump.key;
+    let details = &jump.details;
+    info!(target = "bastion", host = %key.host, port = key.port, "Connecting to jump host");
+    let context = format!("Jump host {}", key.host);
+    let tcp = TcpStream::connect((key.host.as_str(), key.port)).map_err(|e| {
+        AppError::from(e)
+            .or_kind(ErrorKind::ConnectionFailed)
+            .context(&context)
+    })?;
+    let socket = tcp.try_clone()?;
+    let mut session = Session::new()?;
+    session.set_tcp_stream(tcp);
+    session.set_timeout(details.timeout.unwrap_or(DEFAULT_TIMEOUT_MS));
+    session.handshake().map_err(|e| {
+        AppError::from(e)
+            .or_kind(ErrorKind::HandshakeFailed)
+            .context(&context)
+    })?;
+
+    // Nobody can be asked about a changed key from here
+    let files = known_hosts::files(
+        &settings.known_hosts_files,
+        details.known_hosts_file.as_deref(),
+    )?;
+    if let Some((blob, _)) = session.host_key() {
+        if known_hosts::check_host_key(&files, &key.host, key.port, blob).is_some() {
+            return Err(AppError::new(
+                ErrorKind::HostKeyChanged,
+                format!("The host key of jump host {} has changed", key.host),
+            ));
+        }
+    }
+    authenticate_session(&session, details, settings, None).map_err(|e| e.context(&context))?;
+
+    session.set_keepalive(true, KEEPALIVE_SECS);
+    session.set_blocking(false);
+    let id = Uuid::new_v4().to_string();
+    info!(target = "bastion", host = %key.host, %id, "Jump host connected");
+    Ok(Bastion {
+        id,
+        key: key.clone(),
+        session,
+        socket,
+        refs: AtomicUsize::new(0),
+        idle_since: Mutex::new(None),
+        closed: AtomicBool::new(false),
+    })
+}
+
+// "host", "host:port" or "[v6]:port"
+fn split_port(spec: &str) -> Result<(&str, Option<u16>), String> {
+    let invalid = || format!("Invalid jump host port in {}", spec);
+    if let Some(rest) = spec.strip_prefix('[') {
+        let (host, port) = rest
+            .split_once(']')
+            .ok_or_else(|| format!("Invalid jump host {}", spec))?;
+        return match port.strip_prefix(':') {
+            Some(port) => Ok((host, Some(port.parse().map_err(|_| invalid())?))),
+            None => Ok((host, None)),
+        };
+    }
+    match spec.split_once(':') {
+        Some((host, port)) => Ok((host, Some(port.parse().map_err(|_| invalid())?))),
+        None => Ok((spec, None)),
+    }
+}
+
+/// The jump host a connection goes through, if its details name one.
+/// `hosts` are the saved hosts, searched for the jump host's credentials.
+pub fn resolve(
+    details: &ConnectionDetails,
+    hosts: &[SavedHost],
+) -> Result<Option<JumpHost>, AppError> {
+    let Some(spec) = details
+        .proxy_jump
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty() && !s.eq_ignore_ascii_case("none"))
+    else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Only one jump host is supported",
+        ));
+    }
+    let (user, address) = match spec.rsplit_once('@') {
+        Some((user, address)) => (Some(user), address),
+        None => (None, spec),
+    };
+    let (host, port) =
+        split_port(address).map_err(|e| AppError::new(ErrorKind::InvalidInput, e))?;
+
+    let saved = hosts.iter().find(|h| {
+        (h.name == host || h.details.host == host)
+            && port.is_none_or(|p| h.details.port.unwrap_or(22) == p)
+            && user.is_none_or(|u| h.details.username == u)
+    });
+    let jump_details = match saved {
+        Some(saved) => {
+            let mut jump_details = ConnectionDetails {
+                // Its own jump host isn't followed, one hop only
+                proxy_jump: None,
+                ..saved.details.clone()
+            };
+            if saved.has_password && jump_details.password.is_none() {
+                jump_details.password = credentials::load(&saved.id, SecretKind::Password)?;
+            }
+            if saved.has_passphrase && jump_details.passphrase.is_none() {
+                jump_details.passphrase = credentials::load(&saved.id, SecretKind::Passphrase)?;
+            }
+            if jump_details.totp_autofill == Some(true) && jump_details.totp_secret.is_none() {
+                jump_details.totp_secret = credentials::load(&saved.id, SecretKind::Totp)?;
+            }
+            jump_details
+        }
+        None => ConnectionDetails {
+            host: host.to_string(),
+            port,
+            username: user.unwrap_or(&details.username).to_string(),
+            private_key_path: details.private_key_path.clone(),
+            passphrase: details.passphrase.clone(),
+            auth_method: details.auth_method.clone(),
+            timeout: details.timeout,
+            known_hosts_file: details.known_hosts_file.clone(),
+            ..ConnectionDetails::default()
+        },
+    };
+    let key = BastionKey {
+        host: jump_details.host.clone(),
+        username: jump_details.username.clone(),
+        port: jump_details.port.unwrap_or(22),
+    };
+    Ok(Some(JumpHost {
+        key,
+        details: jump_details,
+    }))
+}
@@ -0,0 +1,377 @@
+// Exporting and importing the whole configuration as one portable file, for
+// moving to another machine.
+//
+// The bundle is versioned JSON. With a password, its contents are encrypted
+// with an Argon2id-derived key. Secrets are only included when asked for,
+// and only into an encrypted bundle.
+
+use crate::activity::IdleConfig;
+use crate::backups;
+use crate::credentials::{self, SecretKind};
+use crate::error::{AppError, ErrorKind};
+use crate::groups::{self, HostGroup};
+use crate::output::OutputBatchConfig;
+use crate::settings;
+use crate::{
+    config_file, crypto, get_snippets_path, load_saved_hosts, load_snippets, lock_saved_hosts,
+    stash_host_secrets, write_saved_hosts, AppState, SavedHost, Snippet,
+};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Map;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::atomic::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, State};
+use tracing::info;
+use uuid::Uuid;
+
+const BUNDLE_FORMAT: &str = "terminoda-config";
+const BUNDLE_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    KeepExisting,
+    Overwrite,
+    Duplicate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleSettings {
+    pub output_batching: OutputBatchConfig,
+    pub idle: IdleConfig,
+    pub scrollback_limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BundleContents {
+    hosts: Vec<SavedHost>,
+    #[serde(default)]
+    groups: Vec<HostGroup>,
+    snippets: Vec<Snippet>,
+    settings: BundleSettings,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedContents {
+    salt: String,
+    data: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleFile {
+    format: String,
+    version: u32,
+    exported_at: u64, // Unix timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contents: Option<BundleContents>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encrypted: Option<EncryptedContents>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportCounts {
+    pub hosts: usize,
+    pub snippets: usize,
+    pub secrets: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportCounts {
+    pub groups_created: usize,
+    pub groups_overwritten: usize,
+    pub groups_skipped: usize,
+    pub hosts_created: usize,
+    pub hosts_overwritten: usize,
+    pub hosts_skipped: usize,
+    pub snippets_created: usize,
+    pub snippets_overwritten: usize,
+    pub snippets_skipped: usize,
+    pub secrets: usize,
+    pub settings_applied: bool,
+}
+
+// Fills in (or clears) the secrets of a host about to be exported
+fn attach_secrets(host: &mut SavedHost, include: bool) -> Result<usize, String> {
+    host.details.password = None;
+    host.details.passphrase = None;
+    host.details.totp_secret = None;
+    if !include {
+        host.has_password = false;
+        host.has_passphrase = false;
+        host.has_totp_secret = false;
+        return Ok(0);
+    }
+
+    let mut count = 0;
+    if host.has_password {
+        host.details.password = credentials::load(&host.id, SecretKind::Password)?;
+        count += host.details.password.is_some() as usize;
+    }
+    if host.has_passphrase {
+        host.details.passphrase = credentials::load(&host.id, SecretKind::Passphrase)?;
+        count += host.details.passphrase.is_some() as usize;
+    }
+    if host.has_totp_secret {
+        host.details.totp_secret = credentials::load(&host.id, SecretKind::Totp)?;
+        count += host.details.totp_secret.is_some() as usize;
+    }
+    host.has_password = host.details.password.is_some();
+    host.has_passphrase = host.details.passphrase.is_some();
+    host.has_totp_secret = host.details.totp_secret.is_some();
+    Ok(count)
+}
+
+#[tauri::command]
+pub fn export_config(
+    path: String,
+    include_secrets: bool,
+    password: Option<String>,
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<ExportCounts, AppError> {
+    let mut hosts = load_saved_hosts(app_handle.clone())?;
+    let groups = groups::load(&app_handle)?;
+    let snippets = load_snippets(app_handle)?;
+
+    let mut secrets = 0;
+    for host in hosts.iter_mut() {
+        secrets += attach_secrets(host, include_secrets)?;
+    }
+    let counts = ExportCounts {
+        hosts: hosts.len(),
+        snippets: snippets.len(),
+        secrets,
+    };
+
+    let current = state.settings.get();
+    let contents = BundleContents {
+        hosts,
+        groups,
+        snippets,
+        settings: BundleSettings {
+            output_batching: state.output_batching.get(),
+            idle: IdleConfig {
+                idle_after_secs: current.idle_after_secs,
+                disconnect_after_secs: current.disconnect_after_secs,
+            },
+            scrollback_limit: state.scrollback_limit.load(Ordering::Relaxed),
+        },
+    };
+    let mut bundle = BundleFile {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        exported_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        contents: None,
+        encrypted: None,
+    };
+    match password.filter(|p| !p.is_empty()) {
+        Some(password) => {
+            let salt = crypto::random_bytes::<SALT_LEN>();
+            let key = crypto::derive_key(&password, &salt)?;
+            let plaintext = serde_json::to_vec(&contents)?;
+            bundle.encrypted = Some(EncryptedContents {
+                salt: BASE64.encode(salt),
+                data: BASE64.encode(crypto::encrypt(&key, &plaintext)?),
+            });
+        }
+        None if include_secrets => {
+            return Err(AppError::new(
+                ErrorKind::InvalidInput,
+                "A password is required to export secrets",
+            ));
+        }
+        None => bundle.contents = Some(contents),
+    }
+
+    let content = serde_json::to_string_pretty(&bundle)?;
+    fs::write(&path, content)?;
+    info!(
+        target = "bundle",
+        hosts = counts.hosts,
+        snippets = counts.snippets,
+        secrets = counts.secrets,
+        "Exported configuration"
+    );
+    Ok(counts)
+}
+
+fn read_bundle(path: &str, password: Option<&str>) -> Result<BundleContents, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let value: serde_json::Value =
+        serde_json::from_str(&content).map_err(|_| "Not a configuration bundle".to_string())?;
+    if value.get("format").and_then(|f| f.as_str()) != Some(BUNDLE_FORMAT) {
+        return Err("Not a configuration bundle".to_string());
+    }
+    // Check the version before the shape, newer bundles may not parse
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version > BUNDLE_VERSION as u64 {
+        return Err(format!(
+            "This bundle was written by a newer version of Terminoda (format {}), please update",
+            version
+        ));
+    }
+    let bundle: BundleFile = serde_json::from_value(value).map_err(|e| e.to_string())?;
+
+    if let Some(contents) = bundle.contents {
+        return Ok(contents);
+    }
+    let encrypted = bundle.encrypted.ok_or("Bundle has no contents")?;
+    let password = password.ok_or("This bundle is password protected")?;
+    let corrupted = || "Bundle is corrupted".to_string();
+    let salt = BASE64.decode(&encrypted.salt).map_err(|_| corrupted())?;
+    let data = BASE64.decode(&encrypted.data).map_err(|_| corrupted())?;
+    let key = crypto::derive_key(password, &salt)?;
+    // GCM can't tell a wrong key from tampering; a wrong password is far likelier
+    let plaintext =
+        crypto::decrypt(&key, &data).map_err(|_| "Wrong bundle password".to_string())?;
+    serde_json::from_slice(&plaintext).map_err(|_| corrupted())
+}
+
+#[tauri::command]
+pub fn import_config(
+    path: String,
+    password: Option<String>,
+    policy: ConflictPolicy,
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<ImportCounts, AppError> {
+    let contents = read_bundle(&path, password.as_deref())?;
+    backups::auto_backup("import");
+    let mut counts = ImportCounts::default();
+
+    let snippets_path = get_snippets_path(&app_handle)?;
+    let _locks = (
+        groups::lock_groups()?,
+        lock_saved_hosts(&app_handle)?,
+        config_file::lock(&snippets_path)?,
+    );
+    let mut hosts = load_saved_hosts(app_handle.clone())?;
+    let mut groups = groups::load(&app_handle)?;
+    // Duplicated groups get new ids, which bundle hosts must follow
+    let mut renamed: HashMap<String, String> = HashMap::new();
+    for mut group in contents.groups {
+        match groups.iter().position(|g| g.id == group.id) {
+            Some(_) if policy == ConflictPolicy::KeepExisting => counts.groups_skipped += 1,
+            Some(pos) if policy == ConflictPolicy::Overwrite => {
+                groups[pos] = group;
+                counts.groups_overwritten += 1;
+            }
+            existing => {
+                if existing.is_some() {
+                    let id = Uuid::new_v4().to_string();
+                    renamed.insert(group.id.clone(), id.clone());
+                    group.id = id;
+                }
+                groups.push(group);
+                counts.groups_created += 1;
+            }
+        }
+    }
+    for group in groups.iter_mut() {
+        if let Some(id) = group.parent_id.as_ref().and_then(|p| renamed.get(p)) {
+            group.parent_id = Some(id.clone());
+        }
+    }
+
+    for mut host in contents.hosts {
+        if let Some(id) = host.group.as_ref().and_then(|g| renamed.get(g)) {
+            host.group = Some(id.clone());
+        }
+        let secrets = host.details.password.is_some() as usize
+            + host.details.passphrase.is_some() as usize
+            + host.details.totp_secret.is_some() as usize;
+        host.has_password = false;
+        host.has_passphrase = false;
+        host.has_totp_secret = false;
+        match hosts.iter().position(|h| h.id == host.id) {
+            Some(_) if policy == ConflictPolicy::KeepExisting => {
+                counts.hosts_skipped += 1;
+                continue;
+            }
+            Some(pos) if policy == ConflictPolicy::Overwrite => {
+                stash_host_secrets(&mut host, Some(&hosts[pos]))?;
+                hosts[pos] = host;
+                counts.hosts_overwritten += 1;
+            }
+            existing => {
+                if existing.is_some() {
+                    host.id = Uuid::new_v4().to_string();
+                }
+                stash_host_secrets(&mut host, None)?;
+                hosts.push(host);
+                counts.hosts_created += 1;
+            }
+        }
+        counts.secrets += secrets;
+    }
+
+    let mut snippets = load_snippets(app_handle.clone())?;
+    for mut snippet in contents.snippets {
+        match snippets.iter().position(|s| s.id == snippet.id) {
+            Some(_) if policy == ConflictPolicy::KeepExisting => counts.snippets_skipped += 1,
+            Some(pos) if policy == ConflictPolicy::Overwrite => {
+                snippets[pos] = snippet;
+                counts.snippets_overwritten += 1;
+            }
+            existing => {
+                if existing.is_some() {
+                    snippet.id = Uuid::new_v4().to_string();
+                }
+                snippets.push(snippet);
+                counts.snippets_created += 1;
+            }
+        }
+    }
+
+    groups::write_groups(&groups)?;
+    write_saved_hosts(&app_handle, &hosts)?;
+    config_file::write(&snippets_path, &snippets)?;
+
+    // Settings are a single record, only replaced when overwriting
+    if policy == ConflictPolicy::Overwrite {
+        let settings = contents.settings;
+        let patch = Map::from_iter([
+            (
+                "output_batching".to_string(),
+                serde_json::to_value(settings.output_batching).map_err(|e| e.to_string())?,
+            ),
+            (
+                "idle_after_secs".to_string(),
+                settings.idle.idle_after_secs.into(),
+            ),
+            (
+                "disconnect_after_secs".to_string(),
+                settings.idle.disconnect_after_secs.into(),
+            ),
+        ]);
+        settings::update(&app_handle, &state, patch)?;
+        state
+            .scrollback_limit
+            .store(settings.scrollback_limit, Ordering::Relaxed);
+        for session in state.sessions.iter() {
+            session
+                .scrollback
+                .lock()
+                .map_err(|e| e.to_string())?
+                .set_limit(settings.scrollback_limit);
+        }
+        counts.settings_applied = true;
+    }
+
+    info!(
+        target = "bundle",
+        created = counts.hosts_created,
+        overwritten = counts.hosts_overwritten,
+        skipped = counts.hosts_skipped,
+        "Imported configuration"
+    );
+    Ok(counts)
+}
@@ -0,0 +1,168 @@
+// Sessions opened as extra channels on an existing SSH connection.
+//
+// Container shells and duplicated tabs don't connect again: they open a new
+// channel with its own PTY on the connection of a session that is already
+// open, and become sessions of their own with the usual output and input
+// plumbing. Such a session is marked shared, so closing it never cuts the
+// connection under its parent, and it holds the connection (and any jump
+// host lease) while open, even after the parent closes. It inherits the
+// parent's target, charset, read-only state and guard exemption.
+
+use crate::bastion;
+use crate::error::{AppError, ErrorKind};
+use crate::output::{OutputBatchSettings, OutputPipeline};
+use crate::ownership;
+use crate::readiness::SocketReadiness;
+use crate::session_meta::SessionMeta;
+use crate::side_channel::retry;
+use crate::{
+    emit_session_opened, read_ssh_channel, AppState, SessionSetup, SessionState, SessionTarget,
+    SessionTransport,
+};
+use dashmap::DashMap;
+use ssh2::{Channel, Session};
+use std::net::TcpStream;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::Window;
+use tracing::info;
+use uuid::Uuid;
+
+const OPEN_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// What a new channel takes over from the session whose connection it uses.
+pub struct Connection {
+    ssh: Arc<Mutex<Session>>,
+    socket: TcpStream,
+    bastion: Option<Arc<bastion::Lease>>,
+    target: SessionTarget,
+    encoding: &'static encoding_rs::Encoding,
+    terminal_type: String,
+    read_only: bool,
+    guard_exempt: bool,
+    sessions: Arc<DashMap<Uuid, SessionState>>,
+    batch_settings: Arc<OutputBatchSettings>,
+    scrollback_limit: usize,
+}
+
+impl Connection {
+    /// The connection of `session_id`, which `window` must be allowed to
+    /// use. Fails with `not_ssh` for serial and telnet sessions.
+    pub fn of(
+        state: &AppState,
+        session_id: &str,
+        window: &Window,
+        not_ssh: &str,
+    ) -> Result<Self, AppError> {
+        let uuid = Uuid::parse_str(session_id)?;
+        let parent = state
+            .sessions
+            .get(&uuid)
+            .ok_or_else(|| AppError::session_not_found(session_id))?;
+        ownership::check(&parent, session_id, window)?;
+        let SessionTransport::Ssh {
+            session,
+            socket,
+            bastion,
+            ..
+        } = &parent.transport
+        else {
+            return Err(AppError::new(ErrorKind::InvalidInput, not_ssh));
+        };
+        Ok(Self {
+            ssh: session.clone(),
+            socket: socket.try_clone()?,
+            bastion: bastion.clone(),
+            target: parent.target.clone(),
+            encoding: parent.charset.get(),
+            terminal_type: state.settings.get().default_terminal_type,
+            // A new channel is no safer than the one it was opened from
+            read_only: parent.read_only.is_enabled(),
+            guard_exempt: parent.guard.is_exempt(),
+            sessions: state.sessions.clone(),
+            batch_settings: state.output_batching.clone(),
+            scrollback_limit: state.scrollback_limit.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Opens a channel with a PTY, runs `start` on it (a shell or a command)
+    /// and registers it as a new session. Blocks; returns the session's id.
+    pub fn open_session(
+        self,
+        window: &Window,
+        size: (u32, u32),
+        meta: SessionMeta,
+        mut start: impl FnMut(&mut Channel) -> Result<(), ssh2::Error>,
+    ) -> Result<Uuid, AppError> {
+        // The connection is in non-blocking mode for its own reader
+        let deadline = Instant::now() + OPEN_TIMEOUT;
+        let mut channel = {
+            let session = self.ssh.lock().unwrap_or_else(|e| e.into_inner());
+            retry(deadline, || session.channel_session())?
+        };
+        let dim = Some((size.0, size.1, 0, 0));
+        retry(deadline, || {
+            channel.request_pty(&self.terminal_type, None, dim)
+        })?;
+        retry(deadline, || start(&mut channel))?;
+        let (mut readiness, waker) = SocketReadiness::new(&self.socket)?;
+
+        let session_id = Uuid::new_v4();
+        let channel_arc = Arc::new(Mutex::new(channel));
+        let target = SessionTarget {
+            // Not a connection of its own, so nothing goes to history
+            history_id: None,
+            timeline: None,
+            connected_at: Some(Instant::now()),
+            ..self.target
+        };
+
+        let session = SessionState::new(
+            session_id,
+            window,
+            SessionSetup {
+                transport: SessionTransport::Ssh {
+                    channel: channel_arc.clone(),
+                    session: self.ssh,
+                    waker,
+                    socket: self.socket,
+                    shared: true,
+                    bastion: self.bastion,
+                },
+                target,
+                meta: Arc::new(meta),
+                encoding: self.encoding,
+                scrollback_limit: self.scrollback_limit,
+                idle_after_secs: None,
+                disconnect_after_secs: None,
+                read_only: self.read_only,
+                guard_exempt: self.guard_exempt,
+                startup: None,
+            },
+        );
+        let reader_ctx = session.reader_context(session_id, window);
+        let health_arc = session.health.clone();
+        let shutdown_arc = session.shutdown.clone();
+        self.sessions.insert(session_id, session);
+
+        let reader_shutdown = shutdown_arc.clone();
+        let batch_settings = self.batch_settings;
+        let reader = thread::spawn(move || {
+            let mut pipeline = OutputPipeline::new(reader_ctx, batch_settings);
+            let reason = read_ssh_channel(
+                &mut pipeline,
+                &channel_arc,
+                &mut readiness,
+                &reader_shutdown,
+                &health_arc,
+            );
+            pipeline.flush();
+            info!(target = "session", session = %session_id, %reason, "Channel session ended");
+        });
+        shutdown_arc.set_reader(reader);
+        emit_session_opened(window, &session_id.to_string());
+        Ok(session_id)
+    }
+}
@@ -0,0 +1,121 @@
+// Transcoding for servers that don't speak UTF-8.
+//
+// The frontend always sees UTF-8: output is decoded from the session charset
+// before it is scanned and emitted, input is encoded back on the way out.
+// Unmappable or invalid sequences are replaced instead of failing.
+
+use encoding_rs::{Decoder, EncoderResult, Encoding, UTF_8};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+pub fn lookup(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| format!("Unknown charset: {}", label))
+}
+
+pub struct SessionCharset {
+    encoding: Mutex<&'static Encoding>,
+    // Bumped on every change so the reader knows to rebuild its decoder
+    generation: AtomicU64,
+}
+
+impl SessionCharset {
+    pub fn new(encoding: &'static Encoding) -> Self {
+        Self {
+            encoding: Mutex::new(encoding),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self) -> &'static Encoding {
+        *self.encoding.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set(&self, encoding: &'static Encoding) {
+        *self.encoding.lock().unwrap_or_else(|e| e.into_inner()) = encoding;
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Encodes UTF-8 input for the remote side, None if no conversion is needed.
+    pub fn encode_input(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let encoding = self.get();
+        if encoding == UTF_8 {
+            return None;
+        }
+        let text = String::from_utf8_lossy(data);
+        let mut encoder = encoding.new_encoder();
+        let mut out = Vec::with_capacity(data.len());
+        let mut remaining: &str = &text;
+        loop {
+            let capacity = encoder
+                .max_buffer_length_from_utf8_without_replacement(remaining.len())
+                .unwrap_or(remaining.len() * 4)
+                .max(16);
+            let start = out.len();
+            out.resize(start + capacity, 0);
+            let (result, read, written) =
+                encoder.encode_from_utf8_without_replacement(remaining, &mut out[start..], true);
+            out.truncate(start + written);
+            remaining = &remaining[read..];
+            match result {
+                EncoderResult::InputEmpty => break,
+                EncoderResult::OutputFull => {}
+                EncoderResult::Unmappable(_) => out.push(b'?'),
+            }
+        }
+        Some(out)
+    }
+
+    /// Decodes a remote filename, replacing invalid sequences.
+    pub fn decode_name(&self, path: &Path) -> String {
+        let encoding = self.get();
+        if encoding == UTF_8 {
+            return path.to_string_lossy().into_owned();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            encoding
+                .decode_without_bom_handling(path.as_os_str().as_bytes())
+                .0
+                .into_owned()
+        }
+        #[cfg(not(unix))]
+        {
+            path.to_string_lossy().into_owned()
+        }
+    }
+}
+
+/// Streaming output decoder that keeps multi-byte sequences split across
+/// reads intact and follows live charset changes.
+#[derive(Default)]
+pub struct OutputDecoder {
+    generation: Option<u64>,
+    decoder: Option<Decoder>,
+}
+
+impl OutputDecoder {
+    /// Returns the UTF-8 text, or None when the session is already UTF-8.
+    pub fn decode(&mut self, charset: &SessionCharset, data: &[u8]) -> Option<String> {
+        let generation = charset.generation();
+        if self.generation != Some(generation) {
+            self.generation = Some(generation);
+            let encoding = charset.get();
+            self.decoder = (encoding != UTF_8).then(|| encoding.new_decoder_without_bom_handling());
+        }
+
+        let decoder = self.decoder.as_mut()?;
+        let capacity = decoder
+            .max_utf8_buffer_length(data.len())
+            .unwrap_or(data.len() * 4);
+        let mut out = String::with_capacity(capacity);
+        let _ = decoder.decode_to_string(data, &mut out, false);
+        Some(out)
+    }
+}
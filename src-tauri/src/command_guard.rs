@@ -0,0 +1,271 @@
+// Confirmation before dangerous commands are submitted.
+//
+// Opt-in through the command_guard setting. Terminal input is mirrored
+// into a per-session line buffer: printable characters append, backspace
+// and Ctrl+U/Ctrl+C edit it, escape sequences are skipped. When Enter
+// arrives and the buffered line matches one of the patterns, everything up
+// to the Enter is sent, the Enter and anything after it are held, and
+// "dangerous-command" is emitted. confirm_dangerous_command then sends the
+// Enter, or on cancel sends Ctrl+U so the shell drops the typed line.
+//
+// The buffer only sees what was typed through send_terminal_input, so a
+// line recalled from shell history or completed with Tab isn't checked.
+// Hosts opt out with skip_command_guard in their connection details.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{ownership, AppState, SessionState};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::{LazyLock, Mutex};
+use tauri::{State, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const CLEAR_LINE: &str = "\x15";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandGuardConfig {
+    pub enabled: bool,
+    // Regexes matched against the whole submitted line
+    pub patterns: Vec<String>,
+}
+
+impl Default for CommandGuardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            patterns: [
+                // Recursive rm of /, ~, a system directory or one level below
+                r"\brm\s+(?:-\S*\s+)*-\S*[rR]\S*\s+(?:-\S*\s+)*(?:/(?:(?:bin|boot|dev|etc|home|lib\w*|opt|root|sbin|srv|usr|var)(?:/[^/\s]*)?)?/?\*?|~/?\*?)(?:\s|$)",
+                r"\bmkfs(?:\.\w+)?\b",
+                r"\bdd\b.*\bof=/dev/",
+                r":\(\)\s*\{\s*:\s*\|\s*:\s*&\s*\}\s*;\s*:",
+            ]
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        }
+    }
+}
+
+impl CommandGuardConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for pattern in &self.patterns {
+            Regex::new(pattern)
+                .map_err(|e| format!("Invalid dangerous command pattern {}: {}", pattern, e))?;
+        }
+        Ok(())
+    }
+}
+
+// Compiled patterns, rebuilt when the setting changes
+static COMPILED: LazyLock<Mutex<(Vec<String>, Vec<Regex>)>> =
+    LazyLock::new(|| Mutex::new((Vec::new(), Vec::new())));
+
+fn compiled(patterns: &[String]) -> Vec<Regex> {
+    let mut compiled = COMPILED.lock().unwrap_or_else(|e| e.into_inner());
+    if compiled.0 != patterns {
+        // validate() already rejected bad patterns, this only skips them
+        let regexes = patterns.iter().filter_map(|p| Regex::new(p).ok()).collect();
+        *compiled = (patterns.to_vec(), regexes);
+    }
+    compiled.1.clone()
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DangerousCommand {
+    session_id: String,
+    confirmation_id: String,
+    line: String,
+    pattern: String,
+}
+
+struct Held {
+    confirmation_id: String,
+    // Starts with the Enter that was held back
+    rest: String,
+}
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum Escape {
+    #[default]
+    None,
+    // Just after ESC
+    Start,
+    // In a CSI or SS3 sequence, until its final byte
+    Sequence,
+}
+
+#[derive(Default)]
+struct GuardState {
+    line: String,
+    escape: Escape,
+    held: Option<Held>,
+}
+
+pub struct CommandGuard {
+    exempt: bool,
+    state: Mutex<GuardState>,
+}
+
+impl CommandGuard {
+    pub fn new(exempt: bool) -> Self {
+        Self {
+            exempt,
+            state: Mutex::new(GuardState::default()),
+        }
+    }
+
+    pub fn is_exempt(&self) -> bool {
+        self.exempt
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, GuardState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Returns what can be sent now. Holds the rest and emits the event when a
+// submitted line matches.
+fn screen(
+    session: &SessionState,
+    data: &str,
+    patterns: &[Regex],
+    session_id: &str,
+    window: &Window,
+) -> String {
+    let mut state = session.guard.lock();
+    for (i, c) in data.char_indices() {
+        match state.escape {
+            Escape::Start => {
+                // Anything else after ESC is an Alt+key, one character
+                state.escape = if c == '[' || c == 'O' {
+                    Escape::Sequence
+                } else {
+                    Escape::None
+                };
+                continue;
+            }
+            Escape::Sequence => {
+                if ('\x40'..='\x7e').contains(&c) {
+                    state.escape = Escape::None;
+                }
+                continue;
+            }
+            Escape::None => {}
+        }
+        match c {
+            '\r' | '\n' => {
+                let line = std::mem::take(&mut state.line);
+                let Some(pattern) = patterns.iter().find(|p| p.is_match(line.trim())) else {
+                    continue;
+                };
+                let confirmation_id = Uuid::new_v4().to_string();
+                info!(target = "command_guard", session = %session_id, pattern = %pattern.as_str(), "Holding a dangerous command");
+                session.owners.emit(
+                    window,
+                    "dangerous-command",
+                    DangerousCommand {
+                        session_id: session_id.to_string(),
+                        confirmation_id: confirmation_id.clone(),
+                        line,
+                        pattern: pattern.as_str().to_string(),
+                    },
+                );
+                state.held = Some(Held {
+                    confirmation_id,
+                    rest: data[i..].to_string(),
+                });
+                return data[..i].to_string();
+            }
+            '\x7f' | '\x08' => {
+                state.line.pop();
+            }
+            '\x15' | '\x03' => state.line.clear(),
+            '\x1b' => state.escape = Escape::Start,
+            c if !c.is_control() => state.line.push(c),
+            _ => {}
+        }
+    }
+    data.to_string()
+}
+
+/// Passes terminal input through the guard. Returns the part to write now,
+/// which may be empty. Fails while an earlier command awaits confirmation.
+pub fn filter_input(
+    session: &SessionState,
+    session_id: &str,
+    data: &str,
+    config: &CommandGuardConfig,
+    window: &Window,
+) -> Result<String, AppError> {
+    if !config.enabled || session.guard.exempt {
+        return Ok(data.to_string());
+    }
+    if session.guard.lock().held.is_some() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "A dangerous command is waiting for confirmation",
+        )
+        .with_session(session_id));
+    }
+    Ok(screen(
+        session,
+        data,
+        &compiled(&config.patterns),
+        session_id,
+        window,
+    ))
+}
+
+/// Answers a "dangerous-command" event: sends the held Enter if `confirm`,
+/// otherwise clears the typed line with Ctrl+U. Input typed after the Enter
+/// in the same chunk is screened again on confirm and dropped on cancel.
+#[tauri::command]
+pub fn confirm_dangerous_command(
+    session_id: String,
+    confirmation_id: String,
+    confirm: bool,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    ownership::check(&session, &session_id, &window)?;
+
+    let held = {
+        let mut guard = session.guard.lock();
+        match guard.held.take() {
+            Some(held) if held.confirmation_id == confirmation_id => held,
+            other => {
+                guard.held = other;
+                return Err(AppError::new(
+                    ErrorKind::NotFound,
+                    "No command is waiting for this confirmation",
+                )
+                .with_session(&session_id));
+            }
+        }
+    };
+    let data = if confirm {
+        info!(target = "command_guard", session = %session_id, "Dangerous command confirmed");
+        let (enter, rest) = held.rest.split_at(1);
+        let patterns = compiled(&state.settings.get().command_guard.patterns);
+        format!(
+            "{}{}",
+            enter,
+            screen(&session, rest, &patterns, &session_id, &window)
+        )
+    } else {
+        info!(target = "command_guard", session = %session_id, "Dangerous command cancelled");
+        CLEAR_LINE.to_string()
+    };
+    session.write_input(data.as_bytes()).map_err(|e| {
+        warn!(target = "command_guard", session = %session_id, error = %e, "Failed to send held input");
+        AppError::from(e).with_session(&session_id)
+    })
+}
@@ -0,0 +1,422 @@
+// Tab-completion hints for the terminal and the snippet editor.
+//
+// Three sources, each built on first use and cached per session: commands
+// on the remote PATH ("compgen -c", or a find over each PATH entry without
+// bash), recent shell history (the tails of ~/.bash_history and
+// ~/.zsh_history over SFTP), and directory entries for filename completion.
+// Every source is filtered by the prefix and paged on its own, and a source
+// that fails reports its error in its page without failing the others.
+// invalidate_remote_completions drops cached sources, e.g. after installing
+// a tool.
+
+use crate::error::{AppError, ErrorKind};
+use crate::side_channel::run_on_side_channel;
+use crate::{ownership, sftp_ops, AppState};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tauri::{async_runtime, AppHandle, Manager, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+// bash -c so compgen exists even when the login shell is another one
+const LIST_COMMANDS: &str = "if command -v bash >/dev/null 2>&1; then bash -c 'compgen -c'; \
+    else IFS=:; for d in $PATH; do [ -d \"$d\" ] && find \"$d\" -maxdepth 1 ! -type d -perm -u+x 2>/dev/null | sed 's#.*/##'; done; fi";
+const COMMANDS_TIMEOUT: Duration = Duration::from_secs(20);
+const HISTORY_FILES: &[&str] = &[".bash_history", ".zsh_history"];
+// Only the end of a history file is read
+const HISTORY_TAIL_BYTES: u64 = 256 * 1024;
+const MAX_COMMANDS: usize = 20_000;
+const MAX_HISTORY: usize = 5_000;
+const MAX_DIR_ENTRIES: usize = 5_000;
+// Past this many listed directories the listings are forgotten
+const MAX_CACHED_DIRS: usize = 64;
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1_000;
+
+static CACHES: LazyLock<DashMap<String, SessionCache>> = LazyLock::new(DashMap::new);
+
+#[derive(Default)]
+struct SessionCache {
+    commands: Option<Arc<Vec<String>>>,
+    history: Option<Arc<Vec<String>>>,
+    home: Option<String>,
+    // Directory path -> (name, is_dir), sorted by name
+    dirs: HashMap<String, Arc<Vec<(String, bool)>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionSource {
+    Commands,
+    History,
+    Paths,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionKind {
+    Command,
+    History,
+    File,
+    Directory,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionItem {
+    // What replaces the prefix; directories end in "/"
+    pub text: String,
+    pub kind: CompletionKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionPage {
+    pub source: CompletionSource,
+    pub items: Vec<CompletionItem>,
+    // Matches across all pages
+    pub total: usize,
+    pub has_more: bool,
+    // Set when the source failed, or partly failed for history
+    pub error: Option<String>,
+}
+
+fn check_owner(app_handle: &AppHandle, session_id: &str, window: &Window) -> Result<(), AppError> {
+    let state = app_handle.state::<AppState>();
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    ownership::check(&session, session_id, window)
+}
+
+fn cached<T>(session_id: &str, get: impl FnOnce(&SessionCache) -> Option<T>) -> Option<T> {
+    CACHES.get(session_id).and_then(|cache| get(&cache))
+}
+
+fn store(app_handle: &AppHandle, session_id: &str, update: impl FnOnce(&mut SessionCache)) {
+    let state = app_handle.state::<AppState>();
+    // Forget sessions that have closed since
+    CACHES.retain(|id, _| Uuid::parse_str(id).is_ok_and(|uuid| state.sessions.contains_key(&uuid)));
+    update(&mut CACHES.entry(session_id.to_string()).or_default());
+}
+
+fn sftp_timeout(app_handle: &AppHandle) -> Duration {
+    let state = app_handle.state::<AppState>();
+    Duration::from_secs(state.settings.get().sftp_timeout_secs)
+}
+
+async fn commands(app_handle: &AppHandle, session_id: &str) -> Result<Arc<Vec<String>>, AppError> {
+    if let Some(commands) = cached(session_id, |c| c.commands.clone()) {
+        return Ok(commands);
+    }
+    let handle = app_handle.clone();
+    let id = session_id.to_string();
+    let output = async_runtime::spawn_blocking(move || {
+        let state = handle.state::<AppState>();
+        run_on_side_channel(&state.sessions, &id, LIST_COMMANDS, COMMANDS_TIMEOUT)
+    })
+    .await
+    .map_err(|e| AppError::from(e.to_string()))??;
+
+    let mut names: Vec<String> = output
+        .stdout
+        .lines()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    names.sort();
+    names.truncate(MAX_COMMANDS);
+    info!(target = "completions", session = %session_id, count = names.len(), "Listed remote commands");
+    let names = Arc::new(names);
+    store(app_handle, session_id, |c| c.commands = Some(names.clone()));
+    Ok(names)
+}
+
+async fn home(
+    app_handle: &AppHandle,
+    session_id: &str,
+    window: &Window,
+) -> Result<String, AppError> {
+    if let Some(home) = cached(session_id, |c| c.home.clone()) {
+        return Ok(home);
+    }
+    let op = sftp_ops::Operation::new("realpath", sftp_timeout(app_handle), None);
+    let state = app_handle.state::<AppState>();
+    let home = sftp_ops::with_session(&state, session_id.to_string(), window, move |session| {
+        sftp_ops::run(session, &op, |sftp| sftp.realpath(Path::new(".")))
+    })
+    .await?
+    .to_string_lossy()
+    .into_owned();
+    store(app_handle, session_id, |c| c.home = Some(home.clone()));
+    Ok(home)
+}
+
+// Commands from one history file, oldest first
+fn parse_history(data: &[u8], truncated: bool) -> Vec<String> {
+    let text = String::from_utf8_lossy(data);
+    let mut lines = text.lines();
+    // The first line of a tail is likely cut off
+    if truncated {
+        lines.next();
+    }
+    lines
+        .filter_map(|line| {
+            // zsh extended history: ": <start>:<elapsed>;<command>"
+            let command = match line.strip_prefix(": ") {
+                Some(rest) => rest.split_once(';').map_or(line, |(_, command)| command),
+                None => line,
+            };
+            let command = command.trim();
+            // bash's HISTTIMEFORMAT timestamps
+            let timestamp = command
+                .strip_prefix('#')
+                .is_some_and(|t| !t.is_empty() && t.bytes().all(|b| b.is_ascii_digit()));
+            (!command.is_empty() && !timestamp).then(|| command.to_string())
+        })
+        .collect()
+}
+
+// Newest first, without repeats. A missing file is fine; one that can't be
+// read is reported alongside what the other gave.
+async fn history(
+    app_handle: &AppHandle,
+    session_id: &str,
+    window: &Window,
+) -> Result<(Arc<Vec<String>>, Option<String>), AppError> {
+    if let Some(history) = cached(session_id, |c| c.history.clone()) {
+        return Ok((history, None));
+    }
+    let home = home(app_handle, session_id, window).await?;
+    let timeout = sftp_timeout(app_handle);
+    let state = app_handle.state::<AppState>();
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+    for file in HISTORY_FILES {
+        let path = Path::new(&home).join(file);
+        let op = sftp_ops::Operation::new("read", timeout, None);
+        let read = sftp_ops::with_session(&state, session_id.to_string(), window, move |session| {
+            sftp_ops::read_tail(session, &op, &path, HISTORY_TAIL_BYTES)
+        })
+        .await;
+        match read {
+            Ok(data) => {
+                let truncated = data.len() as u64 == HISTORY_TAIL_BYTES;
+                entries.extend(parse_history(&data, truncated).into_iter().rev());
+            }
+            Err(e) if e.kind == ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!(target = "completions", session = %session_id, file, error = %e, "Could not read shell history");
+                errors.push(format!("~/{}: {}", file, e.message));
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    entries.retain(|entry| seen.insert(entry.clone()));
+    entries.truncate(MAX_HISTORY);
+    let entries = Arc::new(entries);
+    // Not cached after a failure, the next request tries again
+    if errors.is_empty() {
+        store(app_handle, session_id, |c| {
+            c.history = Some(entries.clone())
+        });
+    }
+    Ok((entries, (!errors.is_empty()).then(|| errors.join("; "))))
+}
+
+async fn directory(
+    app_handle: &AppHandle,
+    session_id: &str,
+    window: &Window,
+    dir: String,
+) -> Result<Arc<Vec<(String, bool)>>, AppError> {
+    if let Some(entries) = cached(session_id, |c| c.dirs.get(&dir).cloned()) {
+        return Ok(entries);
+    }
+    let op = sftp_ops::Operation::new("listing", sftp_timeout(app_handle), None);
+    let state = app_handle.state::<AppState>();
+    let path = dir.clone();
+    let listed = sftp_ops::with_session(&state, session_id.to_string(), window, move |session| {
+        sftp_ops::read_dir(session, &op, Path::new(&path))
+    })
+    .await?;
+    let mut entries: Vec<(String, bool)> = listed
+        .into_iter()
+        .filter_map(|(path, stat)| {
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            Some((name, stat.is_dir()))
+        })
+        .collect();
+    entries.sort();
+    entries.truncate(MAX_DIR_ENTRIES);
+    let entries = Arc::new(entries);
+    store(app_handle, session_id, |c| {
+        if c.dirs.len() >= MAX_CACHED_DIRS {
+            c.dirs.clear();
+        }
+        c.dirs.insert(dir, entries.clone());
+    });
+    Ok(entries)
+}
+
+// Entries of the directory `prefix` points into whose names start with the
+// rest of it; "~/" and relative prefixes are resolved from the home dir
+async fn paths(
+    app_handle: &AppHandle,
+    session_id: &str,
+    window: &Window,
+    prefix: &str,
+) -> Result<Vec<CompletionItem>, AppError> {
+    let (typed_dir, partial) = match prefix.rfind('/') {
+        Some(i) => prefix.split_at(i + 1),
+        None => ("", prefix),
+    };
+    let dir = if typed_dir.starts_with('/') {
+        typed_dir.to_string()
+    } else {
+        let home = home(app_handle, session_id, window).await?;
+        let relative = typed_dir
+            .strip_prefix("~/")
+            .or_else(|| (typed_dir == "~").then_some(""))
+            .unwrap_or(typed_dir);
+        format!("{}/{}", home.trim_end_matches('/'), relative)
+    };
+    let entries = directory(app_handle, session_id, window, dir).await?;
+    Ok(entries
+        .iter()
+        // Dotfiles only when asked for, as shells do
+        .filter(|(name, _)| {
+            name.starts_with(partial) && (partial.starts_with('.') || !name.starts_with('.'))
+        })
+        .map(|(name, is_dir)| CompletionItem {
+            text: format!("{}{}{}", typed_dir, name, if *is_dir { "/" } else { "" }),
+            kind: if *is_dir {
+                CompletionKind::Directory
+            } else {
+                CompletionKind::File
+            },
+        })
+        .collect())
+}
+
+fn page(
+    source: CompletionSource,
+    items: Result<Vec<CompletionItem>, AppError>,
+    offset: usize,
+    limit: usize,
+) -> CompletionPage {
+    match items {
+        Ok(items) => CompletionPage {
+            source,
+            total: items.len(),
+            has_more: items.len() > offset + limit,
+            items: items.into_iter().skip(offset).take(limit).collect(),
+            error: None,
+        },
+        Err(e) => CompletionPage {
+            source,
+            items: Vec::new(),
+            total: 0,
+            has_more: false,
+            error: Some(e.message),
+        },
+    }
+}
+
+fn matching(entries: &[String], prefix: &str, kind: CompletionKind) -> Vec<CompletionItem> {
+    entries
+        .iter()
+        .filter(|entry| entry.starts_with(prefix))
+        .map(|entry| CompletionItem {
+            text: entry.clone(),
+            kind,
+        })
+        .collect()
+}
+
+/// Returns completions for `prefix` from each of `sources` (all by default),
+/// each paged with `offset` and `limit` (100 by default, at most 1000). For
+/// paths the prefix is the path typed so far.
+#[tauri::command]
+pub async fn get_remote_completions(
+    session_id: String,
+    prefix: Option<String>,
+    sources: Option<Vec<CompletionSource>>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<Vec<CompletionPage>, AppError> {
+    check_owner(&app_handle, &session_id, &window)?;
+    let prefix = prefix.unwrap_or_default();
+    let sources = sources.unwrap_or_else(|| {
+        vec![
+            CompletionSource::Commands,
+            CompletionSource::History,
+            CompletionSource::Paths,
+        ]
+    });
+    let offset = offset.unwrap_or(0);
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let mut pages = Vec::new();
+    for source in sources {
+        let result = match source {
+            CompletionSource::Commands => page(
+                source,
+                commands(&app_handle, &session_id)
+                    .await
+                    .map(|names| matching(&names, &prefix, CompletionKind::Command)),
+                offset,
+                limit,
+            ),
+            CompletionSource::History => match history(&app_handle, &session_id, &window).await {
+                Ok((entries, error)) => CompletionPage {
+                    error,
+                    ..page(
+                        source,
+                        Ok(matching(&entries, &prefix, CompletionKind::History)),
+                        offset,
+                        limit,
+                    )
+                },
+                Err(e) => page(source, Err(e), offset, limit),
+            },
+            CompletionSource::Paths => page(
+                source,
+                paths(&app_handle, &session_id, &window, &prefix).await,
+                offset,
+                limit,
+            ),
+        };
+        pages.push(result);
+    }
+    Ok(pages)
+}
+
+/// Drops cached completions of a session, for `sources` or all of them;
+/// they are rebuilt on the next request.
+#[tauri::command]
+pub fn invalidate_remote_completions(session_id: String, sources: Option<Vec<CompletionSource>>) {
+    let Some(sources) = sources else {
+        CACHES.remove(&session_id);
+        return;
+    };
+    if let Some(mut cache) = CACHES.get_mut(&session_id) {
+        for source in sources {
+            match source {
+                CompletionSource::Commands => cache.commands = None,
+                CompletionSource::History => cache.history = None,
+                CompletionSource::Paths => cache.dirs.clear(),
+            }
+        }
+    }
+}
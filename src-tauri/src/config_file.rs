@@ -0,0 +1,357 @@
+// Crash-safe reading and writing of the JSON config files.
+//
+// Writes go to a temp file in the same directory, are fsynced and renamed
+// over the original, so a crash or a full disk leaves either the old or the
+// new file, never a truncated one. Each successful write also refreshes a
+// ".bak" copy; if the main file later fails to parse, loading falls back to
+// the copy, puts it back in place and emits "config-recovered".
+//
+// Mutations hold a per-file lock from load to write: an in-process lock,
+// re-entrant on the same thread, plus an advisory lock on a ".lock" file so
+// a second instance can't interleave with this one.
+
+use crate::{migrations, vault, ConfigRecoveredPayload};
+use fs2::FileExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, LazyLock, Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tracing::{info, warn};
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    sibling(path, ".bak")
+}
+
+/// Returned when another window or instance holds a config file too long.
+pub const BUSY: &str = "config-busy";
+
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const LOCK_POLL: Duration = Duration::from_millis(50);
+
+struct Holder {
+    thread: ThreadId,
+    depth: usize,
+}
+
+#[derive(Default)]
+struct LockTable {
+    held: Mutex<HashMap<PathBuf, Holder>>,
+    released: Condvar,
+}
+
+impl LockTable {
+    fn held(&self) -> MutexGuard<'_, HashMap<PathBuf, Holder>> {
+        // The map stays consistent even if a holder panicked
+        self.held.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn release(&self, path: &Path) {
+        let mut held = self.held();
+        if let Some(holder) = held.get_mut(path) {
+            holder.depth -= 1;
+            if holder.depth == 0 {
+                held.remove(path);
+                self.released.notify_all();
+            }
+        }
+    }
+}
+
+static LOCKS: LazyLock<LockTable> = LazyLock::new(LockTable::default);
+
+/// Exclusive access to one config file, released on drop.
+pub struct ConfigLock {
+    path: PathBuf,
+    file: Option<File>, // Only the outermost lock holds the advisory lock
+}
+
+impl Drop for ConfigLock {
+    fn drop(&mut self) {
+        if let Some(file) = self.file.take() {
+            let _ = FileExt::unlock(&file);
+        }
+        LOCKS.release(&self.path);
+    }
+}
+
+/// Locks `path` for a read-modify-write. Nested calls on the same thread
+/// succeed immediately; anyone else waits up to LOCK_TIMEOUT.
+pub fn lock(path: &Path) -> Result<ConfigLock, String> {
+    let deadline = Instant::now() + LOCK_TIMEOUT;
+    let current = thread::current().id();
+    let mut held = LOCKS.held();
+    loop {
+        match held.get_mut(path) {
+            Some(holder) if holder.thread == current => {
+                holder.depth += 1;
+                return Ok(ConfigLock {
+                    path: path.to_path_buf(),
+                    file: None,
+                });
+            }
+            Some(_) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(BUSY.to_string());
+                }
+                held = LOCKS
+                    .released
+                    .wait_timeout(held, deadline - now)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+            }
+            None => break,
+        }
+    }
+    held.insert(
+        path.to_path_buf(),
+        Holder {
+            thread: current,
+            depth: 1,
+        },
+    );
+    drop(held);
+
+    // From here the guard releases the in-process lock on any error
+    let mut guard = ConfigLock {
+        path: path.to_path_buf(),
+        file: None,
+    };
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sibling(path, ".lock"))
+        .map_err(|e| e.to_string())?;
+    while file.try_lock_exclusive().is_err() {
+        if Instant::now() >= deadline {
+            return Err(BUSY.to_string());
+        }
+        thread::sleep(LOCK_POLL);
+    }
+    guard.file = Some(file);
+    Ok(guard)
+}
+
+/// Replaces `path` with `content` via temp file, fsync and rename.
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), String> {
+    let tmp = sibling(path, ".tmp");
+    let result = (|| {
+        let mut file = File::create(&tmp)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        // Persist the rename itself; not possible on Windows
+        #[cfg(unix)]
+        if let Some(dir) = path.parent() {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok::<_, std::io::Error>(())
+    })();
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("Failed to write {}: {}", path.display(), e));
+    }
+    Ok(())
+}
+
+/// Writes already-encoded bytes to the file and its backup.
+pub fn write_raw(path: &Path, content: &[u8]) -> Result<(), String> {
+    let _lock = lock(path)?;
+    write_atomic(path, content)?;
+    write_atomic(&backup_path(path), content)
+}
+
+/// Writes a config file in a versioned envelope, sealed when it belongs to
+/// an enabled vault.
+pub fn write<T: Serialize + ?Sized>(path: &Path, data: &T) -> Result<(), String> {
+    let content = migrations::to_envelope(&data)?;
+    write_raw(path, vault::seal_for(path, &content)?.as_bytes())
+}
+
+/// Removes a config file together with its backup.
+pub fn remove(path: &Path) -> Result<(), String> {
+    let _lock = lock(path)?;
+    for file in [path.to_path_buf(), backup_path(path)] {
+        if file.exists() {
+            fs::remove_file(&file).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+enum ReadError {
+    Fatal(String),   // The backup can't help, or would lose data
+    Damaged(String), // Unreadable, worth trying the backup
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// Parses and upgrades a file; the upgraded data is returned when it changed
+fn read_file<T: DeserializeOwned>(path: &Path) -> Result<Option<(T, Option<Value>)>, ReadError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = vault::read_to_string(path).map_err(|e| match e.as_str() {
+        vault::LOCKED => ReadError::Fatal(e),
+        _ => ReadError::Damaged(e),
+    })?;
+    let damaged = |e: String| ReadError::Damaged(format!("{} is corrupted: {}", path.display(), e));
+    let value = serde_json::from_str(&content).map_err(|e| damaged(e.to_string()))?;
+    let (version, data) = migrations::from_envelope(value).map_err(damaged)?;
+    // A newer file must not be replaced by an older backup
+    let data = migrations::upgrade(&file_name(path), version, data).map_err(ReadError::Fatal)?;
+    let parsed = T::deserialize(&data).map_err(|e| damaged(e.to_string()))?;
+    let upgraded = (version < migrations::CURRENT_VERSION).then_some(data);
+    Ok(Some((parsed, upgraded)))
+}
+
+/// Loads a config file, None if it doesn't exist yet. Older schema versions
+/// are upgraded and written back.
+pub fn load<T: DeserializeOwned>(app_handle: &AppHandle, path: &Path) -> Result<Option<T>, String> {
+    let Some((value, recovered)) = load_or_recover(path)? else {
+        return Ok(None);
+    };
+    if let Some(error) = recovered {
+        let file = file_name(path);
+        warn!(target = "config", file = %file, error = %error, "Recovered config from backup");
+        let _ = app_handle.emit("config-recovered", ConfigRecoveredPayload { file, error });
+    }
+    Ok(Some(value))
+}
+
+// Reads `path`, or its backup when it's damaged, which then replaces it.
+// Also returns what was wrong with the file when the backup was used.
+fn load_or_recover<T: DeserializeOwned>(
+    path: &Path,
+) -> Result<Option<(T, Option<String>)>, String> {
+    let error = match read_file(path) {
+        Ok(Some((value, upgraded))) => {
+            save_upgraded(path, upgraded)?;
+            return Ok(Some((value, None)));
+        }
+        Ok(None) => return Ok(None),
+        Err(ReadError::Fatal(e)) => return Err(e),
+        Err(ReadError::Damaged(e)) => e,
+    };
+
+    let backup = backup_path(path);
+    let Ok(Some((value, upgraded))) = read_file::<T>(&backup) else {
+        return Err(error);
+    };
+    let bytes = fs::read(&backup).map_err(|e| e.to_string())?;
+    write_atomic(path, &bytes)?;
+    save_upgraded(path, upgraded)?;
+    Ok(Some((value, Some(error))))
+}
+
+fn save_upgraded(path: &Path, upgraded: Option<Value>) -> Result<(), String> {
+    let Some(data) = upgraded else {
+        return Ok(());
+    };
+    write(path, &data)?;
+    info!(
+        target = "config",
+        file = %file_name(path),
+        version = migrations::CURRENT_VERSION,
+        "Upgraded config schema"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // A fresh directory per test, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "terminoda-config-test-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn hosts() -> Vec<String> {
+        vec!["alpha".to_string(), "beta".to_string()]
+    }
+
+    #[test]
+    fn recovers_a_truncated_file_from_its_backup() {
+        let dir = TempDir::new();
+        let path = dir.0.join("settings.json");
+        write(&path, &hosts()).unwrap();
+        let content = fs::read(&path).unwrap();
+        fs::write(&path, &content[..content.len() / 2]).unwrap();
+
+        let (value, recovered) = load_or_recover::<Vec<String>>(&path).unwrap().unwrap();
+        assert_eq!(value, hosts());
+        assert!(recovered.unwrap().contains("corrupted"));
+        // The backup was put back in place
+        assert_eq!(fs::read(&path).unwrap(), content);
+        let (_, recovered) = load_or_recover::<Vec<String>>(&path).unwrap().unwrap();
+        assert!(recovered.is_none());
+    }
+
+    #[test]
+    fn corrupt_file_without_a_backup_is_an_error() {
+        let dir = TempDir::new();
+        let path = dir.0.join("settings.json");
+        fs::write(&path, "{\"version\": 1, \"data\": [").unwrap();
+        assert!(load_or_recover::<Vec<String>>(&path).is_err());
+    }
+
+    #[test]
+    fn missing_file_loads_as_none() {
+        let dir = TempDir::new();
+        let path = dir.0.join("settings.json");
+        assert!(load_or_recover::<Vec<String>>(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn failed_write_leaves_the_original_intact() {
+        let dir = TempDir::new();
+        let path = dir.0.join("settings.json");
+        write(&path, &hosts()).unwrap();
+        let content = fs::read(&path).unwrap();
+        // The temp file can't be created where a directory is in the way
+        fs::create_dir(sibling(&path, ".tmp")).unwrap();
+
+        assert!(write_atomic(&path, b"[\"gamma\"]").is_err());
+        assert_eq!(fs::read(&path).unwrap(), content);
+        let (value, _) = load_or_recover::<Vec<String>>(&path).unwrap().unwrap();
+        assert_eq!(value, hosts());
+    }
+}
@@ -0,0 +1,219 @@
+// Guards against connect storms to one host.
+//
+// Attempts are keyed by user@host:port. A second connect_ssh from the same
+// window while one is running doesn't open another connection; it waits for
+// the first and gets its result. After `max_failures` failures in a row the
+// host is refused for `cooldown_secs` with a rate-limited error carrying
+// retry_after_secs, so a retry loop can't trip fail2ban. Connecting with
+// force skips the cool-down. A success clears the count.
+
+use crate::error::{AppError, ErrorDetails, ErrorKind};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CooldownPolicy {
+    // Consecutive failures before the cool-down starts
+    pub max_failures: u32,
+    // 0 turns the cool-down off
+    pub cooldown_secs: u64,
+}
+
+impl Default for CooldownPolicy {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            cooldown_secs: 60,
+        }
+    }
+}
+
+impl CooldownPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=100).contains(&self.max_failures) {
+            return Err("Connect failures before cool-down must be between 1 and 100".to_string());
+        }
+        if self.cooldown_secs > 3600 {
+            return Err("Connect cool-down can be at most 3600 seconds".to_string());
+        }
+        Ok(())
+    }
+}
+
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+// The outcome of a running attempt, for callers waiting on it
+#[derive(Default)]
+pub struct Attempt {
+    result: Mutex<Option<Result<String, AppError>>>,
+    done: Condvar,
+}
+
+impl Attempt {
+    /// Blocks until the leading attempt finishes.
+    pub fn wait(&self) -> Result<String, AppError> {
+        let mut result = self.result.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(result) = result.as_ref() {
+                return result.clone();
+            }
+            result = self.done.wait(result).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn complete(&self, outcome: Result<String, AppError>) {
+        *self.result.lock().unwrap_or_else(|e| e.into_inner()) = Some(outcome);
+        self.done.notify_all();
+    }
+}
+
+#[derive(Default)]
+pub struct ConnectLimiter {
+    failures: DashMap<String, Failures>,
+    // Keyed by window label and host, since the session goes to that window
+    in_flight: DashMap<(String, String), Arc<Attempt>>,
+}
+
+pub enum Slot {
+    /// Nothing was running; connect and report through finish()
+    Lead(Lead),
+    /// The same window is already connecting, wait() for its result
+    Follow(Arc<Attempt>),
+}
+
+impl ConnectLimiter {
+    /// Joins a running attempt or, unless the host is cooling down, starts
+    /// one.
+    pub fn begin(
+        self: &Arc<Self>,
+        host_key: String,
+        window_label: &str,
+        policy: CooldownPolicy,
+        force: bool,
+    ) -> Result<Slot, AppError> {
+        let key = (window_label.to_string(), host_key);
+        let entry = match self.in_flight.entry(key.clone()) {
+            Entry::Occupied(running) => {
+                info!(target = "connect_limit", host = %key.1, "Joining running connection attempt");
+                return Ok(Slot::Follow(running.get().clone()));
+            }
+            Entry::Vacant(entry) => entry,
+        };
+
+        if !force {
+            if let Some(remaining) = self.cooldown_remaining(&key.1, policy) {
+                let retry_after_secs = remaining.as_secs() as u32 + 1;
+                warn!(target = "connect_limit", host = %key.1, retry_after_secs, "Connection attempt rate-limited");
+                return Err(AppError {
+                    details: Some(Box::new(ErrorDetails {
+                        retry_after_secs: Some(retry_after_secs),
+                        ..ErrorDetails::default()
+                    })),
+                    ..AppError::new(
+                        ErrorKind::RateLimited,
+                        format!(
+                            "Too many failed attempts to {}, retry after {} seconds",
+                            key.1, retry_after_secs
+                        ),
+                    )
+                });
+            }
+        }
+
+        let attempt = Arc::new(Attempt::default());
+        entry.insert(attempt.clone());
+        Ok(Slot::Lead(Lead {
+            limiter: self.clone(),
+            key,
+            attempt,
+            finished: false,
+        }))
+    }
+
+    fn cooldown_remaining(&self, host_key: &str, policy: CooldownPolicy) -> Option<Duration> {
+        let failures = self.failures.get(host_key)?;
+        if policy.cooldown_secs == 0 || failures.count < policy.max_failures {
+            return None;
+        }
+        Duration::from_secs(policy.cooldown_secs).checked_sub(failures.last.elapsed())
+    }
+
+    fn record(&self, host_key: &str, result: &Result<String, AppError>) {
+        match result {
+            Ok(_) => {
+                self.failures.remove(host_key);
+            }
+            Err(e) if counts_as_failure(e.kind) => {
+                let mut failures = self
+                    .failures
+                    .entry(host_key.to_string())
+                    .or_insert(Failures {
+                        count: 0,
+                        last: Instant::now(),
+                    });
+                failures.count += 1;
+                failures.last = Instant::now();
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+// Failures that are waiting on the user rather than the server don't count
+fn counts_as_failure(kind: ErrorKind) -> bool {
+    !matches!(
+        kind,
+        ErrorKind::InvalidInput
+            | ErrorKind::HostKeyChanged
+            | ErrorKind::PasswordRequired
+            | ErrorKind::PassphraseRequired
+            | ErrorKind::VaultLocked
+            | ErrorKind::Cancelled
+    )
+}
+
+/// A running attempt. Dropping it without finish() releases anyone waiting
+/// with a cancelled error.
+pub struct Lead {
+    limiter: Arc<ConnectLimiter>,
+    key: (String, String),
+    attempt: Arc<Attempt>,
+    finished: bool,
+}
+
+impl Lead {
+    pub fn finish(mut self, result: &Result<String, AppError>) {
+        self.limiter.record(&self.key.1, result);
+        self.release(result.clone());
+    }
+
+    fn release(&mut self, outcome: Result<String, AppError>) {
+        self.finished = true;
+        self.limiter.in_flight.remove(&self.key);
+        self.attempt.complete(outcome);
+    }
+}
+
+impl Drop for Lead {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.release(Err(AppError::new(
+                ErrorKind::Cancelled,
+                "The connection attempt was abandoned",
+            )));
+        }
+    }
+}
+
+/// The key attempts to one account on one server share.
+pub fn host_key(username: &str, host: &str, port: u16) -> String {
+    format!("{}@{}:{}", username, host, port)
+}
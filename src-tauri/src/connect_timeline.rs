@@ -0,0 +1,169 @@
+// Where the time of an SSH connect went.
+//
+// connect_ssh times each phase of an attempt: name resolution, the TCP
+// connect (through the jump host when there is one, which then covers its
+// resolution too), the handshake, the host key check, every authentication
+// attempt, and opening the channel, PTY and shell. Each finished phase is
+// emitted as "connect-progress" for a live indicator. The whole timeline is
+// kept with the session for get_connection_timeline and get_session_info,
+// and a failed connect returns it, up to the failing phase, in its error's
+// details.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{ownership, AppState};
+use serde::Serialize;
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tauri::{Emitter, State, Window};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Resolve,
+    TcpConnect,
+    Handshake,
+    HostKey,
+    Auth,
+    Channel,
+    Pty,
+    Shell,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PhaseRecord {
+    pub phase: Phase,
+    // The auth method, or "jump host" for a tunnelled connect
+    pub detail: Option<String>,
+    // Since the attempt started
+    pub started_ms: u64,
+    pub duration_ms: u64,
+    // Set when the phase failed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectTimeline {
+    // Matches the attempt_id of its connect-progress events
+    pub attempt_id: String,
+    // Unix timestamp in milliseconds
+    pub started_at: u64,
+    pub phases: Vec<PhaseRecord>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ConnectProgress {
+    attempt_id: String,
+    host: String,
+    #[serde(flatten)]
+    record: PhaseRecord,
+}
+
+/// Records the phases of one connect attempt as they finish.
+pub struct Timeline {
+    attempt_id: String,
+    host: String,
+    window: Window,
+    start: Instant,
+    started_at: u64,
+    phases: Mutex<Vec<PhaseRecord>>,
+}
+
+impl Timeline {
+    pub fn new(host: &str, window: Window) -> Self {
+        Self {
+            attempt_id: Uuid::new_v4().to_string(),
+            host: host.to_string(),
+            window,
+            start: Instant::now(),
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            phases: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Records a phase that began at `started` and emits it.
+    pub fn record(
+        &self,
+        phase: Phase,
+        detail: Option<&str>,
+        started: Instant,
+        error: Option<String>,
+    ) {
+        let record = PhaseRecord {
+            phase,
+            detail: detail.map(str::to_string),
+            started_ms: started.saturating_duration_since(self.start).as_millis() as u64,
+            duration_ms: started.elapsed().as_millis() as u64,
+            error,
+        };
+        self.phases
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(record.clone());
+        let _ = self.window.emit(
+            "connect-progress",
+            ConnectProgress {
+                attempt_id: self.attempt_id.clone(),
+                host: self.host.clone(),
+                record,
+            },
+        );
+    }
+
+    /// Runs `f` as `phase`.
+    pub fn time<T, E: Display>(
+        &self,
+        phase: Phase,
+        detail: Option<&str>,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let started = Instant::now();
+        let result = f();
+        let error = result.as_ref().err().map(|e| e.to_string());
+        self.record(phase, detail, started, error);
+        result
+    }
+
+    pub fn snapshot(&self) -> ConnectTimeline {
+        ConnectTimeline {
+            attempt_id: self.attempt_id.clone(),
+            started_at: self.started_at,
+            phases: self
+                .phases
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        }
+    }
+
+    /// Adds the timeline so far to a connect error.
+    pub fn attach(&self, mut error: AppError) -> AppError {
+        error.details.get_or_insert_with(Default::default).timeline = Some(self.snapshot());
+        error
+    }
+}
+
+/// How the session's SSH connection was made, phase by phase.
+#[tauri::command]
+pub fn get_connection_timeline(
+    session_id: String,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<ConnectTimeline, AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    ownership::check(&session, &session_id, &window)?;
+    session.target.timeline.as_deref().cloned().ok_or_else(|| {
+        AppError::new(
+            ErrorKind::NotFound,
+            "The session wasn't opened by its own SSH connect",
+        )
+    })
+}
@@ -0,0 +1,156 @@
+// Host secrets (passwords, key passphrases, TOTP secrets) live in the OS
+// credential store, keyed by host id; connections.json only records that a
+// secret exists.
+//
+// Linux setups without a Secret Service fall back to an AES-GCM encrypted
+// file whose key sits next to it with owner-only permissions. That keeps
+// secrets out of backups and casual view, not away from the user account.
+
+use crate::{config_file, crypto, get_config_dir};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+const SERVICE: &str = "terminoda";
+
+#[derive(Debug, Clone, Copy)]
+pub enum SecretKind {
+    Password,
+    Passphrase,
+    // Base32, see totp
+    Totp,
+}
+
+impl SecretKind {
+    pub const ALL: [SecretKind; 3] = [
+        SecretKind::Password,
+        SecretKind::Passphrase,
+        SecretKind::Totp,
+    ];
+
+    fn account(self, host_id: &str) -> String {
+        let suffix = match self {
+            SecretKind::Password => "password",
+            SecretKind::Passphrase => "passphrase",
+            SecretKind::Totp => "totp",
+        };
+        format!("{}:{}", host_id, suffix)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Keyring,
+    File,
+}
+
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+fn backend() -> Backend {
+    *BACKEND.get_or_init(|| {
+        let probe = keyring::Entry::new(SERVICE, "backend-probe").and_then(|e| e.get_password());
+        match probe {
+            Ok(_) | Err(keyring::Error::NoEntry) => Backend::Keyring,
+            Err(e) => {
+                warn!(target = "credentials", error = %e, "OS credential store unavailable, using encrypted file");
+                Backend::File
+            }
+        }
+    })
+}
+
+pub fn store(host_id: &str, kind: SecretKind, secret: &str) -> Result<(), String> {
+    let account = kind.account(host_id);
+    match backend() {
+        Backend::Keyring => keyring::Entry::new(SERVICE, &account)
+            .and_then(|e| e.set_password(secret))
+            .map_err(|e| format!("Failed to store credential: {}", e)),
+        Backend::File => {
+            let mut secrets = load_file_store()?;
+            secrets.insert(account, secret.to_string());
+            save_file_store(&secrets)
+        }
+    }
+}
+
+pub fn load(host_id: &str, kind: SecretKind) -> Result<Option<String>, String> {
+    let account = kind.account(host_id);
+    match backend() {
+        Backend::Keyring => {
+            match keyring::Entry::new(SERVICE, &account).and_then(|e| e.get_password()) {
+                Ok(secret) => Ok(Some(secret)),
+                Err(keyring::Error::NoEntry) => Ok(None),
+                Err(e) => Err(format!("Failed to read credential: {}", e)),
+            }
+        }
+        Backend::File => Ok(load_file_store()?.remove(&account)),
+    }
+}
+
+pub fn delete(host_id: &str, kind: SecretKind) -> Result<(), String> {
+    let account = kind.account(host_id);
+    match backend() {
+        Backend::Keyring => {
+            match keyring::Entry::new(SERVICE, &account).and_then(|e| e.delete_credential()) {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(format!("Failed to delete credential: {}", e)),
+            }
+        }
+        Backend::File => {
+            let mut secrets = load_file_store()?;
+            if secrets.remove(&account).is_some() {
+                save_file_store(&secrets)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn file_store_paths() -> Result<(PathBuf, PathBuf), String> {
+    let dir = get_config_dir()?;
+    Ok((dir.join("credentials.enc"), dir.join("credentials.key")))
+}
+
+fn file_key() -> Result<crypto::SecretKey, String> {
+    let (_, key_path) = file_store_paths()?;
+    if let Ok(bytes) = fs::read(&key_path) {
+        return bytes
+            .try_into()
+            .map_err(|_| "Credential key file is corrupted".to_string());
+    }
+
+    let key = crypto::random_key();
+    fs::write(&key_path, key).map_err(|e| e.to_string())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&key_path, fs::Permissions::from_mode(0o600))
+            .map_err(|e| e.to_string())?;
+    }
+    info!(target = "credentials", "Created credential file key");
+    Ok(key)
+}
+
+fn load_file_store() -> Result<BTreeMap<String, String>, String> {
+    let (store_path, _) = file_store_paths()?;
+    if !store_path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let encoded = fs::read_to_string(&store_path).map_err(|e| e.to_string())?;
+    let encrypted = BASE64
+        .decode(encoded.trim())
+        .map_err(|_| "Credential file is corrupted".to_string())?;
+    let plaintext = crypto::decrypt(&file_key()?, &encrypted)?;
+    serde_json::from_slice(&plaintext).map_err(|e| e.to_string())
+}
+
+fn save_file_store(secrets: &BTreeMap<String, String>) -> Result<(), String> {
+    let (store_path, _) = file_store_paths()?;
+    let plaintext = serde_json::to_vec(secrets).map_err(|e| e.to_string())?;
+    let encrypted = crypto::encrypt(&file_key()?, &plaintext)?;
+    config_file::write_atomic(&store_path, BASE64.encode(encrypted).as_bytes())
+}
@@ -0,0 +1,396 @@
+// The remote user's crontab, as text and as rows for a table.
+//
+// Reading is "crontab -l", where a user without a crontab gets exit 1 and
+// "no crontab for <user>", shown as an empty one. Writing checks every line
+// first and refuses the whole content on the first bad one, with its line
+// number in details.line. The content goes to a temp file over stdin, is
+// installed with "crontab <file>" and read back to confirm the jobs landed.
+// Comments and blank lines are kept as rows so a round trip loses nothing.
+
+use crate::error::{AppError, ErrorDetails, ErrorKind};
+use crate::side_channel::{run_on_side_channel, run_with_input};
+use crate::{ownership, read_only, AppState};
+use serde::Serialize;
+use std::time::Duration;
+use tauri::{async_runtime, AppHandle, Manager, Window};
+use tracing::info;
+use uuid::Uuid;
+
+const EXEC_TIMEOUT: Duration = Duration::from_secs(20);
+// The temp file is removed whatever crontab said
+const INSTALL: &str =
+    "t=$(mktemp) || exit 1; cat > \"$t\"; crontab \"$t\"; s=$?; rm -f \"$t\"; exit $s";
+const SPECIALS: &[&str] = &[
+    "@reboot",
+    "@yearly",
+    "@annually",
+    "@monthly",
+    "@weekly",
+    "@daily",
+    "@midnight",
+    "@hourly",
+];
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CronEntryKind {
+    Job,
+    // NAME=value, e.g. MAILTO or PATH
+    Variable,
+    Comment,
+    Blank,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CronEntry {
+    pub line: usize, // 1-based
+    pub kind: CronEntryKind,
+    // Five fields, or one "@daily"-style shorthand
+    pub schedule: Vec<String>,
+    pub command: Option<String>,
+    pub comment: Option<String>,
+    pub raw: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Crontab {
+    pub content: String,
+    pub entries: Vec<CronEntry>,
+}
+
+// Splits off the first whitespace-separated field
+fn next_field(rest: &mut &str) -> Option<String> {
+    let trimmed = rest.trim_start();
+    let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+    let (field, tail) = trimmed.split_at(end);
+    *rest = tail;
+    (!field.is_empty()).then(|| field.to_string())
+}
+
+fn is_variable(line: &str) -> bool {
+    line.split_once('=').is_some_and(|(name, _)| {
+        let name = name.trim();
+        !name.is_empty()
+            && !name.contains(char::is_whitespace)
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+fn parse_line(index: usize, raw: &str) -> CronEntry {
+    let trimmed = raw.trim();
+    let mut entry = CronEntry {
+        line: index + 1,
+        kind: CronEntryKind::Blank,
+        schedule: Vec::new(),
+        command: None,
+        comment: None,
+        raw: raw.to_string(),
+    };
+    if trimmed.is_empty() {
+        return entry;
+    }
+    if let Some(comment) = trimmed.strip_prefix('#') {
+        entry.kind = CronEntryKind::Comment;
+        entry.comment = Some(comment.trim().to_string());
+        return entry;
+    }
+    if is_variable(trimmed) {
+        entry.kind = CronEntryKind::Variable;
+        return entry;
+    }
+    entry.kind = CronEntryKind::Job;
+    let mut rest = trimmed;
+    let fields = if trimmed.starts_with('@') { 1 } else { 5 };
+    for _ in 0..fields {
+        match next_field(&mut rest) {
+            Some(field) => entry.schedule.push(field),
+            None => break,
+        }
+    }
+    entry.command = Some(rest.trim().to_string()).filter(|c| !c.is_empty());
+    entry
+}
+
+pub fn parse(content: &str) -> Vec<CronEntry> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| parse_line(i, line))
+        .collect()
+}
+
+// A value, a name where the field allows them, or "*"
+fn check_value(value: &str, min: u32, max: u32, names: &[&str]) -> bool {
+    if value == "*" {
+        return true;
+    }
+    if let Some(i) = names.iter().position(|n| n.eq_ignore_ascii_case(value)) {
+        // Months count from 1, days from 0
+        let n = i as u32 + min;
+        return n >= min && n <= max;
+    }
+    value.parse::<u32>().is_ok_and(|n| n >= min && n <= max)
+}
+
+// Lists of values or ranges, each with an optional step
+fn check_field(field: &str, min: u32, max: u32, names: &[&str]) -> bool {
+    field.split(',').all(|item| {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (item, None),
+        };
+        if step.is_some_and(|s| s.parse::<u32>().map_or(true, |s| s == 0)) {
+            return false;
+        }
+        match range.split_once('-') {
+            Some((from, to)) => {
+                check_value(from, min, max, names) && check_value(to, min, max, names)
+            }
+            None => check_value(range, min, max, names),
+        }
+    })
+}
+
+// Value of one end of a range, Sunday as 0 in the day-of-week field
+fn field_value(value: &str, min: u32, max: u32, names: &[&str]) -> Option<u32> {
+    let n = match names.iter().position(|n| n.eq_ignore_ascii_case(value)) {
+        Some(i) => i as u32 + min,
+        None => value.parse().ok()?,
+    };
+    (n >= min && n <= max).then_some(n)
+}
+
+// The values a field matches, as bits
+fn expand_field(field: &str, min: u32, max: u32, names: &[&str]) -> Option<u64> {
+    let mut bits = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<usize>().ok().filter(|s| *s > 0)?)),
+            None => (item, None),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (
+                field_value(from, min, max, names)?,
+                field_value(to, min, max, names)?,
+            ),
+            // "5/15" runs from 5 to the end
+            None => {
+                let from = field_value(range, min, max, names)?;
+                (from, if step.is_some() { max } else { from })
+            }
+        };
+        if from > to {
+            return None;
+        }
+        for value in (from..=to).step_by(step.unwrap_or(1)) {
+            bits |= 1 << (if max == 7 { value % 7 } else { value });
+        }
+    }
+    Some(bits)
+}
+
+/// A parsed five-field cron schedule (or @hourly, @daily, ...), for running
+/// things locally on the same schedule syntax as crontab.
+#[derive(Debug, Clone, Copy)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // Cron matches either day field when both are restricted
+    any_day: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expanded = match expression.trim().to_ascii_lowercase().as_str() {
+            "@yearly" | "@annually" => "0 0 1 1 *".to_string(),
+            "@monthly" => "0 0 1 * *".to_string(),
+            "@weekly" => "0 0 * * 0".to_string(),
+            "@daily" | "@midnight" => "0 0 * * *".to_string(),
+            "@hourly" => "0 * * * *".to_string(),
+            other => other.to_string(),
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            return Err("Expected five schedule fields".to_string());
+        };
+        let field = |value: &str, min, max, names, what| {
+            expand_field(value, min, max, names)
+                .ok_or_else(|| format!("Invalid {} field {}", what, value))
+        };
+        Ok(Self {
+            minutes: field(minute, 0, 59, &[][..], "minute")?,
+            hours: field(hour, 0, 23, &[][..], "hour")?,
+            days: field(day, 1, 31, &[][..], "day of month")?,
+            months: field(month, 1, 12, MONTHS, "month")?,
+            weekdays: field(weekday, 0, 7, DAYS, "day of week")?,
+            any_day: *day != "*" && *weekday != "*",
+        })
+    }
+
+    /// `weekday` counts from Sunday as 0.
+    pub fn matches(&self, minute: u32, hour: u32, day: u32, month: u32, weekday: u32) -> bool {
+        let has = |bits: u64, value: u32| bits & (1 << value) != 0;
+        let day_ok = if self.any_day {
+            has(self.days, day) || has(self.weekdays, weekday)
+        } else {
+            has(self.days, day) && has(self.weekdays, weekday)
+        };
+        has(self.minutes, minute) && has(self.hours, hour) && has(self.months, month) && day_ok
+    }
+}
+
+fn validate_job(entry: &CronEntry) -> Result<(), String> {
+    if entry.command.is_none() {
+        return Err("missing command".to_string());
+    }
+    if let [special] = entry.schedule.as_slice() {
+        if SPECIALS.contains(&special.to_ascii_lowercase().as_str()) {
+            return Ok(());
+        }
+        return Err(format!("unknown schedule {}", special));
+    }
+    let [minute, hour, day, month, weekday] = entry.schedule.as_slice() else {
+        return Err("expected five schedule fields".to_string());
+    };
+    let checks = [
+        (minute, 0, 59, &[][..], "minute"),
+        (hour, 0, 23, &[][..], "hour"),
+        (day, 1, 31, &[][..], "day of month"),
+        (month, 1, 12, MONTHS, "month"),
+        // 7 is Sunday too
+        (weekday, 0, 7, DAYS, "day of week"),
+    ];
+    for (field, min, max, names, what) in checks {
+        if !check_field(field, min, max, names) {
+            return Err(format!("invalid {} field {}", what, field));
+        }
+    }
+    Ok(())
+}
+
+fn validate(entries: &[CronEntry]) -> Result<(), AppError> {
+    for entry in entries {
+        if let CronEntryKind::Job = entry.kind {
+            if let Err(reason) = validate_job(entry) {
+                return Err(AppError {
+                    details: Some(Box::new(ErrorDetails {
+                        line: Some(entry.line as u32),
+                        ..ErrorDetails::default()
+                    })),
+                    ..AppError::new(
+                        ErrorKind::InvalidInput,
+                        format!("Line {}: {}", entry.line, reason),
+                    )
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+// Jobs and variables, for comparing what was written with what is installed;
+// some crontabs add header comments of their own
+fn effective(entries: &[CronEntry]) -> Vec<&str> {
+    entries
+        .iter()
+        .filter(|e| matches!(e.kind, CronEntryKind::Job | CronEntryKind::Variable))
+        .map(|e| e.raw.trim())
+        .collect()
+}
+
+fn check_owner(app_handle: &AppHandle, session_id: &str, window: &Window) -> Result<(), AppError> {
+    let state = app_handle.state::<AppState>();
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    ownership::check(&session, session_id, window)
+}
+
+fn read(app_handle: &AppHandle, session_id: &str) -> Result<Crontab, AppError> {
+    let state = app_handle.state::<AppState>();
+    let output = run_on_side_channel(&state.sessions, session_id, "crontab -l", EXEC_TIMEOUT)?;
+    let content = match output.exit_status {
+        0 => output.stdout,
+        1 if output.stderr.to_lowercase().contains("no crontab") => String::new(),
+        127 => {
+            return Err(AppError::new(
+                ErrorKind::Unsupported,
+                "crontab is not installed on this host",
+            ))
+        }
+        _ => return Err(format!("crontab -l failed: {}", output.stderr.trim()).into()),
+    };
+    Ok(Crontab {
+        entries: parse(&content),
+        content,
+    })
+}
+
+#[tauri::command]
+pub async fn get_remote_crontab(
+    session_id: String,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<Crontab, AppError> {
+    check_owner(&app_handle, &session_id, &window)?;
+    async_runtime::spawn_blocking(move || read(&app_handle, &session_id))
+        .await
+        .map_err(|e| AppError::from(e.to_string()))?
+}
+
+/// Installs `content` as the user's crontab and returns it as read back.
+#[tauri::command]
+pub async fn set_remote_crontab(
+    session_id: String,
+    content: String,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<Crontab, AppError> {
+    check_owner(&app_handle, &session_id, &window)?;
+    read_only::check(&app_handle.state::<AppState>(), &session_id)?;
+    let entries = parse(&content);
+    validate(&entries)?;
+    // cron ignores a last line without a newline
+    let mut content = content;
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+
+    async_runtime::spawn_blocking(move || {
+        let state = app_handle.state::<AppState>();
+        let output = run_with_input(
+            &state.sessions,
+            &session_id,
+            INSTALL,
+            content.as_bytes(),
+            EXEC_TIMEOUT,
+        )?;
+        if output.exit_status != 0 {
+            return Err(AppError::new(
+                ErrorKind::InvalidInput,
+                format!("crontab rejected the content: {}", output.stderr.trim()),
+            ));
+        }
+        let installed = read(&app_handle, &session_id)?;
+        if effective(&installed.entries) != effective(&entries) {
+            return Err(AppError::new(
+                ErrorKind::Other,
+                "The installed crontab differs from what was written",
+            ));
+        }
+        info!(target = "crontab", session = %session_id, lines = entries.len(), "Installed crontab");
+        Ok(installed)
+    })
+    .await
+    .map_err(|e| AppError::from(e.to_string()))?
+}
@@ -0,0 +1,194 @@
+// Line diffs between two files, each remote (over SFTP) or local.
+//
+// Both sides are read whole, up to MAX_DIFF_BYTES, and diffed here with
+// similar. The result is unified-diff hunks with line numbers on both sides,
+// context_lines around each change (settings.diff_context_lines by
+// default). Files with a NUL byte in the first block or that aren't UTF-8
+// are reported as binary, without hunks.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{sftp_ops, AppState};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tauri::{State, Window};
+
+const MAX_DIFF_BYTES: u64 = 5 * 1024 * 1024;
+const MAX_CONTEXT_LINES: usize = 100;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum DiffSource {
+    Remote { session_id: String, path: String },
+    Local { path: String },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffLineKind {
+    Context,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    // 1-based; None on the side the line isn't in
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+    // "\ No newline at end of file"
+    pub missing_newline: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffHunk {
+    // As in the "@@ -a,b +c,d @@" header
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDiff {
+    pub identical: bool,
+    pub binary: bool,
+    pub old_size: u64,
+    pub new_size: u64,
+    pub added: usize,
+    pub removed: usize,
+    pub hunks: Vec<DiffHunk>,
+}
+
+fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(8192)].contains(&0)
+}
+
+// Unified diffs number an empty range from the line before it
+fn hunk_start(start: usize, len: usize) -> usize {
+    if len == 0 {
+        start
+    } else {
+        start + 1
+    }
+}
+
+/// Diffs two file contents. Also used where an edited copy is compared with
+/// the remote original.
+pub fn diff_texts(old: &[u8], new: &[u8], context_lines: usize) -> FileDiff {
+    let mut result = FileDiff {
+        identical: old == new,
+        binary: false,
+        old_size: old.len() as u64,
+        new_size: new.len() as u64,
+        added: 0,
+        removed: 0,
+        hunks: Vec::new(),
+    };
+    if result.identical {
+        return result;
+    }
+    let (Ok(old), Ok(new)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        result.binary = true;
+        return result;
+    };
+    if is_binary(old.as_bytes()) || is_binary(new.as_bytes()) {
+        result.binary = true;
+        return result;
+    }
+
+    let diff = TextDiff::from_lines(old, new);
+    for group in diff.grouped_ops(context_lines) {
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            continue;
+        };
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        let mut hunk = DiffHunk {
+            old_start: hunk_start(old_range.start, old_range.len()),
+            old_lines: old_range.len(),
+            new_start: hunk_start(new_range.start, new_range.len()),
+            new_lines: new_range.len(),
+            lines: Vec::new(),
+        };
+        for op in &group {
+            for change in diff.iter_changes(op) {
+                let kind = match change.tag() {
+                    ChangeTag::Equal => DiffLineKind::Context,
+                    ChangeTag::Insert => {
+                        result.added += 1;
+                        DiffLineKind::Insert
+                    }
+                    ChangeTag::Delete => {
+                        result.removed += 1;
+                        DiffLineKind::Delete
+                    }
+                };
+                let text = change.value();
+                hunk.lines.push(DiffLine {
+                    kind,
+                    old_line: change.old_index().map(|i| i + 1),
+                    new_line: change.new_index().map(|i| i + 1),
+                    text: text
+                        .strip_suffix('\n')
+                        .map(|t| t.strip_suffix('\r').unwrap_or(t))
+                        .unwrap_or(text)
+                        .to_string(),
+                    missing_newline: change.missing_newline(),
+                });
+            }
+        }
+        result.hunks.push(hunk);
+    }
+    result
+}
+
+async fn read_source(
+    source: DiffSource,
+    window: &Window,
+    state: &State<'_, AppState>,
+) -> Result<Vec<u8>, AppError> {
+    match source {
+        DiffSource::Remote { session_id, path } => {
+            let timeout = Duration::from_secs(state.settings.get().sftp_timeout_secs);
+            let op = sftp_ops::Operation::new("read", timeout, None);
+            sftp_ops::with_session(state, session_id, window, move |session| {
+                sftp_ops::read_file(session, &op, Path::new(&path), MAX_DIFF_BYTES)
+            })
+            .await
+        }
+        DiffSource::Local { path } => {
+            let size = fs::metadata(&path)?.len();
+            if size > MAX_DIFF_BYTES {
+                return Err(AppError::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} is larger than {} bytes", path, MAX_DIFF_BYTES),
+                ));
+            }
+            Ok(fs::read(&path)?)
+        }
+    }
+}
+
+/// Diffs `old` against `new`; each is {session_id, path} for a remote file
+/// or {path} for a local one.
+#[tauri::command]
+pub async fn diff_files(
+    old: DiffSource,
+    new: DiffSource,
+    context_lines: Option<usize>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<FileDiff, AppError> {
+    let context_lines = context_lines
+        .unwrap_or_else(|| state.settings.get().diff_context_lines)
+        .min(MAX_CONTEXT_LINES);
+    let old = read_source(old, &window, &state).await?;
+    let new = read_source(new, &window, &state).await?;
+    Ok(diff_texts(&old, &new, context_lines))
+}
@@ -0,0 +1,534 @@
+// Finding SSH hosts on the local network.
+//
+// discover_hosts browses mDNS for _ssh._tcp services and, given a CIDR, also
+// sweeps it for an open port 22. Each host found is emitted as
+// "host-discovered" with the SSH greeting it sent and its mDNS name, and
+// "discovery-finished" follows when the scan stops, times out or runs out of
+// addresses. To stay polite on shared networks a sweep covers at most a /22,
+// probes MAX_WORKERS addresses at a time and never runs past its timeout.
+// There's no mDNS crate in the tree, so the few DNS messages involved are
+// built and parsed here. import_discovered_hosts turns results into saved
+// hosts through the usual import path.
+
+use crate::error::{AppError, ErrorKind};
+use crate::host_import::{self, local_username, ImportResult};
+use crate::{load_saved_hosts, ConnectionDetails, SavedHost};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const SSH_SERVICE: &str = "_ssh._tcp.local";
+const MDNS_ADDR: (Ipv4Addr, u16) = (Ipv4Addr::new(224, 0, 0, 251), 5353);
+const DEFAULT_TIMEOUT_SECS: u64 = 15;
+const MAX_TIMEOUT_SECS: u64 = 120;
+// A /22
+const MAX_SWEEP_ADDRESSES: u32 = 1024;
+const MAX_WORKERS: usize = 32;
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+const BANNER_TIMEOUT: Duration = Duration::from_secs(1);
+const NAME_TIMEOUT: Duration = Duration::from_millis(500);
+const REQUERY_INTERVAL: Duration = Duration::from_secs(3);
+const DISCOVERED_GROUP: &str = "Discovered";
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_SRV: u16 = 33;
+
+// Stop flags of running scans, one per window
+static SCANS: LazyLock<DashMap<String, Arc<AtomicBool>>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveredHost {
+    pub address: String,
+    pub port: u16,
+    // mDNS instance or host name, when the host has one
+    pub name: Option<String>,
+    // First line the server sent, e.g. "SSH-2.0-OpenSSH_9.6"
+    pub banner: Option<String>,
+    pub source: String, // "mdns" or "sweep"
+    // A saved host with the same address and port
+    #[serde(default)]
+    pub saved_host_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct HostDiscoveredPayload {
+    scan_id: String,
+    host: DiscoveredHost,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DiscoveryFinishedPayload {
+    scan_id: String,
+    found: usize,
+    reason: String, // "completed", "stopped" or "timeout"
+}
+
+struct Scan {
+    window: Window,
+    scan_id: String,
+    stop: Arc<AtomicBool>,
+    deadline: Instant,
+    saved: Vec<SavedHost>,
+    seen: Mutex<HashSet<(Ipv4Addr, u16)>>,
+}
+
+impl Scan {
+    fn running(&self) -> bool {
+        !self.stop.load(Ordering::Relaxed) && Instant::now() < self.deadline
+    }
+
+    // Emits a host the first time it's found
+    fn report(&self, addr: Ipv4Addr, port: u16, name: Option<String>, source: &str) {
+        if !self
+            .seen
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert((addr, port))
+        {
+            return;
+        }
+        let banner = TcpStream::connect_timeout(&SocketAddr::from((addr, port)), CONNECT_TIMEOUT)
+            .ok()
+            .and_then(read_banner);
+        let address = addr.to_string();
+        let saved_host_id = find_saved(&self.saved, &address, name.as_deref(), port);
+        let _ = self.window.emit(
+            "host-discovered",
+            HostDiscoveredPayload {
+                scan_id: self.scan_id.clone(),
+                host: DiscoveredHost {
+                    address,
+                    port,
+                    name,
+                    banner,
+                    source: source.to_string(),
+                    saved_host_id,
+                },
+            },
+        );
+    }
+
+    fn found(&self) -> usize {
+        self.seen.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+fn find_saved(saved: &[SavedHost], address: &str, name: Option<&str>, port: u16) -> Option<String> {
+    saved
+        .iter()
+        .find(|h| {
+            let host = &h.details.host;
+            h.details.port.unwrap_or(22) == port
+                && (host.eq_ignore_ascii_case(address)
+                    || name.is_some_and(|n| host.eq_ignore_ascii_case(n)))
+        })
+        .map(|h| h.id.clone())
+}
+
+fn read_banner(mut stream: TcpStream) -> Option<String> {
+    stream.set_read_timeout(Some(BANNER_TIMEOUT)).ok()?;
+    let mut buf = [0u8; 256];
+    let n = stream.read(&mut buf).ok()?;
+    let text = String::from_utf8_lossy(&buf[..n]);
+    let line: String = text
+        .lines()
+        .next()?
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+    (!line.is_empty()).then_some(line)
+}
+
+// Addresses in an IPv4 CIDR, without the network and broadcast addresses
+fn parse_cidr(cidr: &str) -> Result<Vec<Ipv4Addr>, String> {
+    let invalid = || format!("Invalid IPv4 CIDR: {}", cidr);
+    let (addr, prefix) = cidr.trim().split_once('/').unwrap_or((cidr.trim(), "32"));
+    let addr: Ipv4Addr = addr.parse().map_err(|_| invalid())?;
+    let prefix: u32 = prefix.parse().map_err(|_| invalid())?;
+    if prefix > 32 {
+        return Err(invalid());
+    }
+    let size = 1u64 << (32 - prefix);
+    if size > MAX_SWEEP_ADDRESSES as u64 {
+        return Err(format!(
+            "{} covers {} addresses, sweeps are limited to {}",
+            cidr, size, MAX_SWEEP_ADDRESSES
+        ));
+    }
+    let network = u32::from(addr) & !((size - 1) as u32);
+    let range = if size > 2 { 1..size - 1 } else { 0..size };
+    Ok(range.map(|i| Ipv4Addr::from(network + i as u32)).collect())
+}
+
+// DNS messages, only as much as mDNS browsing needs
+
+fn dns_query(questions: &[(&str, u16)]) -> Vec<u8> {
+    let mut msg = vec![0, 0, 0, 0, 0, questions.len() as u8, 0, 0, 0, 0, 0, 0];
+    for (name, qtype) in questions {
+        for label in name.split('.').filter(|l| !l.is_empty()) {
+            msg.push(label.len().min(63) as u8);
+            msg.extend_from_slice(&label.as_bytes()[..label.len().min(63)]);
+        }
+        msg.push(0);
+        msg.extend_from_slice(&qtype.to_be_bytes());
+        msg.extend_from_slice(&1u16.to_be_bytes()); // IN
+    }
+    msg
+}
+
+fn be16(msg: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*msg.get(pos)?, *msg.get(pos + 1)?]))
+}
+
+// Reads a possibly compressed name; returns it and where the field ends
+fn read_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    let mut jumps = 0;
+    loop {
+        let len = *msg.get(pos)? as usize;
+        if len == 0 {
+            pos += 1;
+            break;
+        }
+        if len & 0xc0 == 0xc0 {
+            end.get_or_insert(pos + 2);
+            jumps += 1;
+            if jumps > 16 {
+                return None;
+            }
+            pos = ((len & 0x3f) << 8) | *msg.get(pos + 1)? as usize;
+            continue;
+        }
+        let label = msg.get(pos + 1..pos + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        pos += 1 + len;
+    }
+    Some((labels.join("."), end.unwrap_or(pos)))
+}
+
+enum RecordData {
+    Ptr(String),
+    Srv { port: u16, target: String },
+    A(Ipv4Addr),
+    Other,
+}
+
+struct Record {
+    name: String,
+    data: RecordData,
+}
+
+fn parse_records(msg: &[u8]) -> Option<Vec<Record>> {
+    let questions = be16(msg, 4)?;
+    let total = be16(msg, 6)? as usize + be16(msg, 8)? as usize + be16(msg, 10)? as usize;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(msg, pos)?.1 + 4;
+    }
+    let mut records = Vec::with_capacity(total);
+    for _ in 0..total {
+        let (name, next) = read_name(msg, pos)?;
+        let kind = be16(msg, next)?;
+        let len = be16(msg, next + 8)? as usize;
+        let start = next + 10;
+        let rdata = msg.get(start..start + len)?;
+        let data = match kind {
+            TYPE_PTR => RecordData::Ptr(read_name(msg, start)?.0),
+            TYPE_SRV if len >= 6 => RecordData::Srv {
+                port: be16(msg, start + 4)?,
+                target: read_name(msg, start + 6)?.0,
+            },
+            TYPE_A if len == 4 => {
+                RecordData::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]))
+            }
+            _ => RecordData::Other,
+        };
+        records.push(Record { name, data });
+        pos = start + len;
+    }
+    Some(records)
+}
+
+// Queries from a port other than 5353 get unicast replies (RFC 6762 6.7), so
+// nothing has to join the multicast group
+fn mdns_socket(timeout: Duration) -> std::io::Result<UdpSocket> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(timeout))?;
+    Ok(socket)
+}
+
+// Host name an address answers to over mDNS, if it runs a responder
+fn mdns_name(addr: Ipv4Addr) -> Option<String> {
+    let [a, b, c, d] = addr.octets();
+    let reverse = format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a);
+    let socket = mdns_socket(NAME_TIMEOUT).ok()?;
+    socket
+        .send_to(&dns_query(&[(&reverse, TYPE_PTR)]), MDNS_ADDR)
+        .ok()?;
+    let started = Instant::now();
+    let mut buf = [0u8; 1500];
+    while started.elapsed() < NAME_TIMEOUT {
+        let Ok((n, _)) = socket.recv_from(&mut buf) else {
+            break;
+        };
+        let name = parse_records(&buf[..n])?
+            .into_iter()
+            .find_map(|r| match r.data {
+                RecordData::Ptr(target) if r.name.eq_ignore_ascii_case(&reverse) => Some(target),
+                _ => None,
+            });
+        if name.is_some() {
+            return name;
+        }
+    }
+    None
+}
+
+fn browse_mdns(scan: &Scan) {
+    let socket = match mdns_socket(Duration::from_millis(250)) {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!(target = "discovery", error = %e, "Failed to open mDNS socket");
+            return;
+        }
+    };
+    // Instance name -> SRV port and target; target -> address
+    let mut instances: HashMap<String, Option<(u16, String)>> = HashMap::new();
+    let mut addresses: HashMap<String, Ipv4Addr> = HashMap::new();
+    let mut reported: HashSet<String> = HashSet::new();
+    let mut last_query: Option<Instant> = None;
+    let mut buf = [0u8; 9000];
+
+    while scan.running() {
+        if last_query.is_none_or(|t| t.elapsed() >= REQUERY_INTERVAL) {
+            // Ask again for whatever the first answers left out
+            let mut questions = vec![(SSH_SERVICE.to_string(), TYPE_PTR)];
+            for (instance, srv) in &instances {
+                match srv {
+                    None => questions.push((instance.clone(), TYPE_SRV)),
+                    Some((_, target)) if !addresses.contains_key(&target.to_lowercase()) => {
+                        questions.push((target.clone(), TYPE_A))
+                    }
+                    Some(_) => {}
+                }
+            }
+            let questions: Vec<(&str, u16)> = questions
+                .iter()
+                .take(255)
+                .map(|(name, qtype)| (name.as_str(), *qtype))
+                .collect();
+            let _ = socket.send_to(&dns_query(&questions), MDNS_ADDR);
+            last_query = Some(Instant::now());
+        }
+        let Ok((n, _)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        for record in parse_records(&buf[..n]).unwrap_or_default() {
+            match record.data {
+                RecordData::Ptr(instance) if record.name.eq_ignore_ascii_case(SSH_SERVICE) => {
+                    instances.entry(instance).or_insert(None);
+                }
+                RecordData::Srv { port, target } => {
+                    instances.insert(record.name, Some((port, target)));
+                }
+                RecordData::A(addr) => {
+                    addresses.insert(record.name.to_lowercase(), addr);
+                }
+                _ => {}
+            }
+        }
+        for (instance, srv) in &instances {
+            let Some((port, target)) = srv else {
+                continue;
+            };
+            let Some(addr) = addresses.get(&target.to_lowercase()) else {
+                continue;
+            };
+            if reported.insert(instance.clone()) {
+                let name = instance
+                    .strip_suffix(&format!(".{}", SSH_SERVICE))
+                    .unwrap_or(instance);
+                scan.report(*addr, *port, Some(name.to_string()), "mdns");
+            }
+        }
+    }
+}
+
+fn sweep(scan: &Scan, targets: &[Ipv4Addr]) {
+    let next = AtomicUsize::new(0);
+    thread::scope(|s| {
+        for _ in 0..MAX_WORKERS.min(targets.len()) {
+            s.spawn(|| {
+                while scan.running() {
+                    let Some(addr) = targets.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    let socket_addr = SocketAddr::from((*addr, 22));
+                    if TcpStream::connect_timeout(&socket_addr, CONNECT_TIMEOUT).is_ok() {
+                        scan.report(*addr, 22, mdns_name(*addr), "sweep");
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Starts a scan and returns its id, which the events carry. A window runs
+/// one scan at a time; starting another stops the previous one. Browsing
+/// mDNS is on by default and runs until the timeout, a sweep needs `cidr`.
+#[tauri::command]
+pub fn discover_hosts(
+    cidr: Option<String>,
+    mdns: Option<bool>,
+    timeout_secs: Option<u64>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<String, AppError> {
+    let targets = match cidr.as_deref().filter(|c| !c.trim().is_empty()) {
+        Some(cidr) => parse_cidr(cidr).map_err(|e| AppError::new(ErrorKind::InvalidInput, e))?,
+        None => Vec::new(),
+    };
+    let mdns = mdns.unwrap_or(true);
+    if targets.is_empty() && !mdns {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Nothing to scan: give a CIDR or enable mDNS",
+        ));
+    }
+    let timeout = Duration::from_secs(
+        timeout_secs
+            .unwrap_or(DEFAULT_TIMEOUT_SECS)
+            .clamp(1, MAX_TIMEOUT_SECS),
+    );
+    let label = window.label().to_string();
+    let stop = Arc::new(AtomicBool::new(false));
+    if let Some(previous) = SCANS.insert(label.clone(), stop.clone()) {
+        previous.store(true, Ordering::Relaxed);
+    }
+    let scan_id = Uuid::new_v4().to_string();
+    info!(
+        target = "discovery",
+        scan = %scan_id,
+        sweep = targets.len(),
+        mdns,
+        "Starting host discovery"
+    );
+
+    let scan = Scan {
+        window,
+        scan_id: scan_id.clone(),
+        stop: stop.clone(),
+        deadline: Instant::now() + timeout,
+        saved: load_saved_hosts(app_handle.clone())?,
+        seen: Mutex::new(HashSet::new()),
+    };
+    thread::spawn(move || {
+        thread::scope(|s| {
+            if mdns {
+                s.spawn(|| browse_mdns(&scan));
+            }
+            sweep(&scan, &targets);
+        });
+        let reason = if stop.load(Ordering::Relaxed) {
+            "stopped"
+        } else if Instant::now() >= scan.deadline {
+            "timeout"
+        } else {
+            "completed"
+        };
+        let found = scan.found();
+        info!(target = "discovery", scan = %scan.scan_id, found, reason, "Host discovery finished");
+        let _ = scan.window.emit(
+            "discovery-finished",
+            DiscoveryFinishedPayload {
+                scan_id: scan.scan_id.clone(),
+                found,
+                reason: reason.to_string(),
+            },
+        );
+        SCANS.remove_if(&label, |_, flag| Arc::ptr_eq(flag, &stop));
+    });
+    Ok(scan_id)
+}
+
+#[tauri::command]
+pub fn stop_discovery(window: Window) {
+    if let Some((_, stop)) = SCANS.remove(window.label()) {
+        stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Saves discovered hosts in the "Discovered" group, or `group`. Hosts that
+/// match a saved host's address and port are skipped unless
+/// `include_duplicates` is set.
+#[tauri::command]
+pub fn import_discovered_hosts(
+    hosts: Vec<DiscoveredHost>,
+    username: Option<String>,
+    group: Option<String>,
+    include_duplicates: Option<bool>,
+    app_handle: AppHandle,
+) -> Result<ImportResult, AppError> {
+    let saved = load_saved_hosts(app_handle.clone())?;
+    let username = username
+        .filter(|u| !u.is_empty())
+        .unwrap_or_else(local_username);
+    let group = group.unwrap_or_else(|| DISCOVERED_GROUP.to_string());
+    let include_duplicates = include_duplicates.unwrap_or(false);
+
+    let new_hosts = hosts
+        .into_iter()
+        .filter(|h| {
+            include_duplicates
+                || find_saved(&saved, &h.address, h.name.as_deref(), h.port).is_none()
+        })
+        .map(|h| {
+            if h.address.parse::<IpAddr>().is_err() {
+                return Err(AppError::new(
+                    ErrorKind::InvalidInput,
+                    format!("Invalid address: {}", h.address),
+                ));
+            }
+            Ok(SavedHost {
+                id: Uuid::new_v4().to_string(),
+                name: h.name.unwrap_or_else(|| h.address.clone()),
+                group: Some(group.clone()),
+                tags: Vec::new(),
+                details: ConnectionDetails {
+                    host: h.address,
+                    port: (h.port != 22).then_some(h.port),
+                    username: username.clone(),
+                    ..Default::default()
+                },
+                has_password: false,
+                has_passphrase: false,
+                has_totp_secret: false,
+                pinned: false,
+                sort_order: 0,
+                notes: None,
+                color: None,
+                metadata: h
+                    .banner
+                    .map(|banner| HashMap::from([("ssh_banner".to_string(), banner)]))
+                    .unwrap_or_default(),
+                mac_address: None,
+                broadcast_address: None,
+                reachability_disabled: false,
+                tunnels: Vec::new(),
+            })
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+    host_import::commit_host_import(new_hosts, app_handle)
+}
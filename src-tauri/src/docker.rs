@@ -0,0 +1,333 @@
+// Containers on SSH hosts: listing them and opening a shell inside one.
+//
+// Docker and podman share a code path; whichever is installed is probed on
+// the remote, docker first. Listing runs on a side channel, preferring
+// "ps --format '{{json .}}'" and falling back to the plain table for engines
+// too old for --format. A container shell is a channel session (see
+// channel_session) on the host's connection running "<runtime> exec -it".
+
+use crate::channel_session::Connection;
+use crate::error::{AppError, ErrorKind};
+use crate::session_meta::SessionMeta;
+use crate::side_channel::{run_on_side_channel, ExecOutput};
+use crate::{ownership, AppState};
+use serde::Serialize;
+use serde_json::Value;
+use std::time::Duration;
+use tauri::{async_runtime, AppHandle, Manager, State, Window};
+use tracing::info;
+use uuid::Uuid;
+
+const EXEC_TIMEOUT: Duration = Duration::from_secs(20);
+const RUNTIMES: &[&str] = &["docker", "podman"];
+const MISSING: &str = "@missing";
+const TABLE: &str = "@table";
+// bash when the image has it
+const DEFAULT_SHELL: &str = "sh -c 'command -v bash >/dev/null && exec bash || exec sh'";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Container {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    pub status: String,
+    // "running", "exited"...; the table output doesn't have it
+    pub state: Option<String>,
+    pub ports: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContainerList {
+    pub runtime: String,
+    pub containers: Vec<Container>,
+}
+
+// Picks the requested runtime, or the first one installed
+fn select_runtime(runtime: Option<&str>) -> Result<String, AppError> {
+    match runtime {
+        Some(name) if RUNTIMES.contains(&name) => Ok(format!(
+            "if command -v {0} >/dev/null 2>&1; then rt={0}; else echo {1}; exit 127; fi",
+            name, MISSING
+        )),
+        Some(name) => Err(AppError::new(
+            ErrorKind::InvalidInput,
+            format!("Unsupported container runtime: {}", name),
+        )),
+        None => Ok(format!(
+            "if command -v docker >/dev/null 2>&1; then rt=docker; \
+             elif command -v podman >/dev/null 2>&1; then rt=podman; \
+             else echo {}; exit 127; fi",
+            MISSING
+        )),
+    }
+}
+
+// Tells a missing runtime and a denied socket apart from other failures
+fn runtime_error(output: &ExecOutput, runtime: Option<&str>) -> AppError {
+    if output.stdout.lines().any(|l| l.trim() == MISSING) {
+        let message = match runtime {
+            Some(name) => format!("{} is not installed on this host", name),
+            None => "Neither docker nor podman is installed on this host".to_string(),
+        };
+        return AppError::new(ErrorKind::NotFound, message);
+    }
+    let stderr = output.stderr.trim();
+    let lower = stderr.to_lowercase();
+    if lower.contains("permission denied") {
+        AppError::new(
+            ErrorKind::PermissionDenied,
+            format!(
+                "Permission denied by the container runtime; the user may need to be in the docker group: {}",
+                stderr
+            ),
+        )
+    } else if lower.contains("cannot connect to the docker daemon")
+        || lower.contains("is the docker daemon running")
+    {
+        AppError::new(ErrorKind::ConnectionFailed, stderr.to_string())
+    } else {
+        AppError::new(
+            ErrorKind::Other,
+            format!("Listing containers failed: {}", stderr),
+        )
+    }
+}
+
+fn string_field(value: &Value, keys: &[&str]) -> String {
+    keys.iter()
+        .find_map(|key| match value.get(key)? {
+            Value::String(s) => Some(s.clone()),
+            // podman lists names as an array
+            Value::Array(items) => items.first()?.as_str().map(str::to_string),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+// docker prints ports as a string, podman as objects
+fn ports_field(value: &Value) -> String {
+    match value.get("Ports") {
+        Some(Value::String(ports)) => ports.clone(),
+        Some(Value::Array(ports)) => ports
+            .iter()
+            .map(|port| {
+                let field = |key: &str| match port.get(key) {
+                    Some(Value::String(s)) => s.clone(),
+                    Some(Value::Number(n)) => n.to_string(),
+                    _ => String::new(),
+                };
+                let host_ip = field("host_ip");
+                let host = if host_ip.is_empty() {
+                    field("host_port")
+                } else {
+                    format!("{}:{}", host_ip, field("host_port"))
+                };
+                format!(
+                    "{}->{}/{}",
+                    host,
+                    field("container_port"),
+                    field("protocol")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => String::new(),
+    }
+}
+
+fn parse_json_line(line: &str) -> Option<Container> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    Some(Container {
+        id: string_field(&value, &["ID", "Id"]),
+        name: string_field(&value, &["Names", "Name"]),
+        image: string_field(&value, &["Image"]),
+        status: string_field(&value, &["Status"]),
+        state: Some(string_field(&value, &["State"])).filter(|s| !s.is_empty()),
+        ports: ports_field(&value),
+    })
+}
+
+// The table pads columns to the header, so fields are cut at the header's
+// column offsets
+fn parse_table(lines: &[&str]) -> Vec<Container> {
+    let Some((header, rows)) = lines.split_first() else {
+        return Vec::new();
+    };
+    let columns = [
+        "CONTAINER ID",
+        "IMAGE",
+        "COMMAND",
+        "CREATED",
+        "STATUS",
+        "PORTS",
+        "NAMES",
+    ];
+    let mut starts: Vec<(usize, &str)> = columns
+        .iter()
+        .filter_map(|name| header.find(name).map(|start| (start, *name)))
+        .collect();
+    starts.sort();
+    rows.iter()
+        .map(|row| {
+            let field = |name: &str| {
+                let Some(i) = starts.iter().position(|(_, n)| *n == name) else {
+                    return String::new();
+                };
+                let start = starts[i].0;
+                let end = starts.get(i + 1).map_or(row.len(), |(end, _)| *end);
+                row.get(start..end.min(row.len()))
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string()
+            };
+            Container {
+                id: field("CONTAINER ID"),
+                name: field("NAMES"),
+                image: field("IMAGE"),
+                status: field("STATUS"),
+                state: None,
+                ports: field("PORTS"),
+            }
+        })
+        .filter(|c| !c.id.is_empty())
+        .collect()
+}
+
+fn list(
+    state: &AppState,
+    session_id: &str,
+    all: bool,
+    runtime: Option<&str>,
+) -> Result<ContainerList, AppError> {
+    let all = if all { " -a" } else { "" };
+    let command = format!(
+        "{}; echo \"$rt\"; $rt ps{all} --no-trunc --format '{{{{json .}}}}' 2>/dev/null \
+         || {{ echo {TABLE}; $rt ps{all} --no-trunc; }}",
+        select_runtime(runtime)?,
+    );
+    let output = run_on_side_channel(&state.sessions, session_id, &command, EXEC_TIMEOUT)?;
+    if output.exit_status != 0 {
+        return Err(runtime_error(&output, runtime));
+    }
+    let mut lines = output.stdout.lines().filter(|l| !l.trim().is_empty());
+    let runtime = lines.next().unwrap_or_default().trim().to_string();
+    let lines: Vec<&str> = lines.collect();
+    let containers = match lines.iter().position(|l| l.trim() == TABLE) {
+        Some(table) => parse_table(&lines[table + 1..]),
+        None => lines.iter().filter_map(|l| parse_json_line(l)).collect(),
+    };
+    Ok(ContainerList {
+        runtime,
+        containers,
+    })
+}
+
+fn check_owner(state: &AppState, session_id: &str, window: &Window) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    ownership::check(&session, session_id, window)
+}
+
+fn valid_word(word: &str, extra: &[char]) -> bool {
+    !word.is_empty()
+        && word.chars().all(|c| {
+            c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') || extra.contains(&c)
+        })
+}
+
+/// Lists the host's running containers, or all of them with `all`. The
+/// runtime is detected unless given ("docker" or "podman").
+#[tauri::command]
+pub async fn list_remote_containers(
+    session_id: String,
+    all: Option<bool>,
+    runtime: Option<String>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<ContainerList, AppError> {
+    check_owner(&app_handle.state::<AppState>(), &session_id, &window)?;
+    async_runtime::spawn_blocking(move || {
+        list(
+            &app_handle.state::<AppState>(),
+            &session_id,
+            all.unwrap_or(false),
+            runtime.as_deref(),
+        )
+    })
+    .await
+    .map_err(|e| AppError::from(e.to_string()))?
+}
+
+/// Opens a shell in a container as a new session on the same connection and
+/// returns its id. `shell` defaults to bash, or sh when the image has no bash.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn open_container_shell(
+    session_id: String,
+    container: String,
+    shell: Option<String>,
+    runtime: Option<String>,
+    cols: Option<u32>,
+    rows: Option<u32>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    if !valid_word(&container, &[]) {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid container name: {}", container),
+        ));
+    }
+    let shell = match shell.filter(|s| !s.trim().is_empty()) {
+        Some(shell) if valid_word(&shell, &['/']) => shell,
+        Some(shell) => {
+            return Err(AppError::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid shell: {}", shell),
+            ))
+        }
+        None => DEFAULT_SHELL.to_string(),
+    };
+
+    let connection = Connection::of(
+        &state,
+        &session_id,
+        &window,
+        "Container shells need an SSH session",
+    )?;
+    let sessions = state.sessions.clone();
+
+    async_runtime::spawn_blocking(move || {
+        let runtime = match runtime {
+            Some(runtime) => runtime,
+            None => {
+                let command = format!("{}; echo \"$rt\"", select_runtime(None)?);
+                let output = run_on_side_channel(&sessions, &session_id, &command, EXEC_TIMEOUT)?;
+                if output.exit_status != 0 {
+                    return Err(runtime_error(&output, None));
+                }
+                output.stdout.trim().to_string()
+            }
+        };
+        if !RUNTIMES.contains(&runtime.as_str()) {
+            return Err(AppError::new(
+                ErrorKind::InvalidInput,
+                format!("Unsupported container runtime: {}", runtime),
+            ));
+        }
+        let command = format!("{} exec -it {} {}", runtime, container, shell);
+
+        let size = (cols.unwrap_or(80), rows.unwrap_or(24));
+        let shell_id =
+            connection.open_session(&window, size, SessionMeta::default(), |channel| {
+                channel.exec(&command)
+            })?;
+        info!(target = "docker", parent = %session_id, session = %shell_id, %container, %runtime, "Opened container shell");
+        Ok(shell_id.to_string())
+    })
+    .await
+    .map_err(|e| AppError::from(e.to_string()))?
+}
@@ -0,0 +1,144 @@
+// Duplicating an open SSH session into a new one.
+//
+// The copy prefers a new shell channel on the same connection (see
+// channel_session), which needs no new login and is instant. When the
+// server refuses another channel (a MaxSessions limit, say), a session of a
+// saved host falls back to connecting again with the host's details; an
+// ad-hoc session has nothing to reconnect with. Either way the copy keeps
+// the title and color, and starts in the original's working directory when
+// the shell reported one. The SFTP panel's path can be carried over too:
+// it's only returned, for the frontend to list in the new tab.
+//
+// Telnet and serial sessions can't be duplicated.
+
+use crate::channel_session::Connection;
+use crate::error::{AppError, ErrorKind};
+use crate::session_meta::SessionMeta;
+use crate::side_channel::shell_quote;
+use crate::{connect_ssh, load_saved_hosts, ownership, AppState};
+use serde::Serialize;
+use tauri::{async_runtime, AppHandle, State, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicatedSession {
+    pub session_id: String,
+    // The original's SFTP path, when asked for and known
+    pub sftp_path: Option<String>,
+    // Whether the copy shares the original's connection
+    pub shared_connection: bool,
+}
+
+/// Opens a copy of a session in the same directory and returns it.
+/// `inherit_sftp_path` also returns the SFTP panel's current path.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn duplicate_session(
+    session_id: String,
+    inherit_sftp_path: Option<bool>,
+    cols: Option<u32>,
+    rows: Option<u32>,
+    window: Window,
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<DuplicatedSession, AppError> {
+    let (cwd, sftp_path, label, host_id) = {
+        let uuid = Uuid::parse_str(&session_id)?;
+        let session = state
+            .sessions
+            .get(&uuid)
+            .ok_or_else(|| AppError::session_not_found(&session_id))?;
+        ownership::check(&session, &session_id, &window)?;
+        let cwd = session.lock(&session.cwd, "cwd").clone();
+        let sftp_path = if inherit_sftp_path.unwrap_or(false) {
+            session.listings.last_path()
+        } else {
+            None
+        };
+        (
+            cwd,
+            sftp_path,
+            session.meta.label(),
+            session.target.host_id.clone(),
+        )
+    };
+    let cd = cwd.map(|cwd| format!("cd {}", shell_quote(&cwd)));
+
+    let connection = Connection::of(
+        &state,
+        &session_id,
+        &window,
+        "Only SSH sessions can be duplicated",
+    )?;
+    let meta = SessionMeta::new(Some(label.clone()))?;
+    let size = (cols.unwrap_or(80), rows.unwrap_or(24));
+    let shell_window = window.clone();
+    let opened = async_runtime::spawn_blocking(move || {
+        connection.open_session(&shell_window, size, meta, |channel| channel.shell())
+    })
+    .await
+    .map_err(|e| AppError::from(e.to_string()))?;
+
+    match opened {
+        Ok(copy) => {
+            info!(target = "duplicate", parent = %session_id, session = %copy, "Duplicated session on its connection");
+            if let Some(cd) = &cd {
+                let sent = state
+                    .sessions
+                    .get(&copy)
+                    .map(|s| s.write_input(format!("{}\n", cd).as_bytes()));
+                // The copy is open either way, just in the home directory
+                if let Some(Err(e)) = sent {
+                    warn!(target = "duplicate", session = %copy, error = %e, "Failed to change directory");
+                }
+            }
+            Ok(DuplicatedSession {
+                session_id: copy.to_string(),
+                sftp_path,
+                shared_connection: true,
+            })
+        }
+        Err(e) => {
+            let Some(host_id) = host_id else {
+                return Err(e.context("Opening another channel on the connection"));
+            };
+            warn!(target = "duplicate", parent = %session_id, error = %e, "No channel on the connection, connecting again");
+            let host = load_saved_hosts(app_handle.clone())?
+                .into_iter()
+                .find(|h| h.id == host_id)
+                .ok_or_else(|| {
+                    AppError::new(
+                        ErrorKind::HostDeleted,
+                        "The session's host was deleted, it can't be connected again",
+                    )
+                })?;
+            let mut details = host.details;
+            if let Some(cd) = cd {
+                // After the host's own initial command
+                details.initial_command = Some(
+                    match details.initial_command.filter(|c| !c.trim().is_empty()) {
+                        Some(command) => format!("{}\n{}", command.trim_end(), cd),
+                        None => cd,
+                    },
+                );
+            }
+            let copy = connect_ssh(
+                details,
+                Some(host_id),
+                None,
+                None,
+                Some(label),
+                state,
+                window,
+                app_handle,
+            )
+            .await?;
+            Ok(DuplicatedSession {
+                session_id: copy,
+                sftp_path,
+                shared_connection: false,
+            })
+        }
+    }
+}
@@ -0,0 +1,132 @@
+// Hides the terminal's echo of input the app typed for the user, such as a
+// host's initial command, so it stays out of the output and scrollback.
+//
+// Matching is best effort: an echoed line is dropped only when it comes back
+// exactly as sent, anything else passes through untouched. The filter gives
+// up after ECHO_TIMEOUT, so a shell that echoes differently never holds
+// output back for long.
+
+use std::collections::VecDeque;
+use std::mem;
+use std::time::{Duration, Instant};
+
+const ECHO_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct EchoFilter {
+    // Echoed lines still expected, the next one first
+    expected: VecDeque<Vec<u8>>,
+    // Output matching the start of the next line, held back until it
+    // either completes or stops matching
+    held: Vec<u8>,
+    deadline: Instant,
+}
+
+impl EchoFilter {
+    /// Expects each line of `sent`, as written to the channel, to come back
+    /// ending in CRLF.
+    pub fn new(sent: &str) -> Self {
+        Self {
+            expected: sent
+                .lines()
+                .map(|line| format!("{}\r\n", line).into_bytes())
+                .collect(),
+            held: Vec::new(),
+            deadline: Instant::now() + ECHO_TIMEOUT,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.expected.is_empty() && self.held.is_empty()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// Returns `data` with the echo removed. Once expired, everything held
+    /// back is returned as well.
+    pub fn filter(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        if self.is_expired() {
+            out.append(&mut self.release());
+            out.extend_from_slice(data);
+            return out;
+        }
+        for &byte in data {
+            self.feed(byte, &mut out);
+        }
+        out
+    }
+
+    /// Gives up on the remaining lines, returning what was held back.
+    pub fn release(&mut self) -> Vec<u8> {
+        self.expected.clear();
+        mem::take(&mut self.held)
+    }
+
+    fn feed(&mut self, byte: u8, out: &mut Vec<u8>) {
+        let Some(line) = self.expected.front() else {
+            out.push(byte);
+            return;
+        };
+        if line[self.held.len()] == byte {
+            self.held.push(byte);
+            if self.held.len() == line.len() {
+                self.held.clear();
+                self.expected.pop_front();
+            }
+            return;
+        }
+        if self.held.is_empty() {
+            out.push(byte);
+            return;
+        }
+        // Not the echo after all: the first held byte is output, the rest
+        // may still start a match
+        let held = mem::take(&mut self.held);
+        out.push(held[0]);
+        for &byte in &held[1..] {
+            self.feed(byte, out);
+        }
+        self.feed(byte, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(filter: &mut EchoFilter, chunks: &[&[u8]]) -> Vec<u8> {
+        chunks
+            .iter()
+            .flat_map(|chunk| filter.filter(chunk))
+            .collect()
+    }
+
+    #[test]
+    fn drops_the_echo_around_other_output() {
+        let mut filter = EchoFilter::new(" cd /srv\n sudo -i\n");
+        let out = run(
+            &mut filter,
+            &[b"Welcome\r\n cd /s", b"rv\r\n sudo -i\r", b"\n$ "],
+        );
+        assert_eq!(out, b"Welcome\r\n$ ");
+        assert!(filter.is_done());
+    }
+
+    #[test]
+    fn passes_through_output_that_only_starts_like_the_echo() {
+        let mut filter = EchoFilter::new(" cd /srv\n");
+        let out = run(&mut filter, &[b" cd /tmp\r\n", b" cd /srv\r\n", b"ok"]);
+        assert_eq!(out, b" cd /tmp\r\nok");
+    }
+
+    #[test]
+    fn release_returns_a_partial_match() {
+        let mut filter = EchoFilter::new(" ls\n");
+        assert_eq!(filter.filter(b"x l"), b"x");
+        assert_eq!(filter.release(), b" l");
+        assert!(filter.is_done());
+        assert_eq!(filter.filter(b" ls\r\n"), b" ls\r\n");
+    }
+}
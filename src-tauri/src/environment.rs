@@ -0,0 +1,151 @@
+// The remote environment for the session info panel.
+//
+// Variables come from "env -0" on a side channel and are split on NUL, so
+// values spanning lines stay whole; an env without -0 falls back to one
+// variable per line. The login shell is read from the passwd entry
+// (getent, else $SHELL) along with the hostname and FQDN. Results are
+// cached per session until a refresh. Variables whose names look like
+// secrets are masked unless include_sensitive is set.
+
+use crate::error::AppError;
+use crate::side_channel::run_on_side_channel;
+use crate::{ownership, AppState};
+use dashmap::DashMap;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{async_runtime, AppHandle, Manager, Window};
+use tracing::info;
+use uuid::Uuid;
+
+const ENV_COMMAND: &str = "env -0 2>/dev/null || env";
+// Always three lines, empty where unknown
+const INFO_COMMAND: &str = "printf '%s\\n' \
+    \"$(getent passwd \"$(id -un)\" 2>/dev/null | cut -d: -f7)\" \
+    \"$(hostname 2>/dev/null || uname -n)\" \
+    \"$(hostname -f 2>/dev/null)\"";
+const EXEC_TIMEOUT: Duration = Duration::from_secs(15);
+const REDACTED: &str = "[redacted]";
+
+static SENSITIVE_NAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)token|secret|passw(or)?d|passphrase|api_?key|private_?key|credential")
+        .expect("valid sensitive name pattern")
+});
+
+// Unredacted, by session
+static CACHE: LazyLock<DashMap<String, RemoteEnvironment>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteEnvironment {
+    pub variables: BTreeMap<String, String>,
+    // Names whose values were masked
+    pub redacted: Vec<String>,
+    pub login_shell: Option<String>,
+    pub hostname: Option<String>,
+    pub fqdn: Option<String>,
+    pub fetched_at_ms: u64,
+}
+
+fn check_owner(app_handle: &AppHandle, session_id: &str, window: &Window) -> Result<(), AppError> {
+    let state = app_handle.state::<AppState>();
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    ownership::check(&session, session_id, window)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn parse_env(output: &str) -> BTreeMap<String, String> {
+    let separator = if output.contains('\0') { '\0' } else { '\n' };
+    output
+        .split(separator)
+        .filter_map(|entry| {
+            let (name, value) = entry.split_once('=')?;
+            (!name.is_empty()).then(|| (name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+fn non_empty(line: Option<&str>) -> Option<String> {
+    line.map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+}
+
+fn fetch(app_handle: &AppHandle, session_id: &str) -> Result<RemoteEnvironment, AppError> {
+    let state = app_handle.state::<AppState>();
+    let env = run_on_side_channel(&state.sessions, session_id, ENV_COMMAND, EXEC_TIMEOUT)?;
+    let variables = parse_env(&env.stdout);
+    let info = run_on_side_channel(&state.sessions, session_id, INFO_COMMAND, EXEC_TIMEOUT)?;
+    let mut lines = info.stdout.lines();
+    let login_shell = non_empty(lines.next()).or_else(|| variables.get("SHELL").cloned());
+    let hostname = non_empty(lines.next());
+    // hostname -f may print just the short name when there's no domain
+    let fqdn = non_empty(lines.next());
+    info!(target = "environment", session = %session_id, count = variables.len(), "Read remote environment");
+    Ok(RemoteEnvironment {
+        variables,
+        redacted: Vec::new(),
+        login_shell,
+        hostname,
+        fqdn,
+        fetched_at_ms: now_millis(),
+    })
+}
+
+fn redact(mut environment: RemoteEnvironment) -> RemoteEnvironment {
+    for (name, value) in environment.variables.iter_mut() {
+        if SENSITIVE_NAME.is_match(name) {
+            *value = REDACTED.to_string();
+            environment.redacted.push(name.clone());
+        }
+    }
+    environment
+}
+
+/// Returns the session's environment variables, login shell and host
+/// names, cached until `refresh`. Values of variables named like secrets
+/// (TOKEN, SECRET, PASSWORD, ...) are masked unless `include_sensitive`.
+#[tauri::command]
+pub async fn get_remote_environment(
+    session_id: String,
+    refresh: Option<bool>,
+    include_sensitive: Option<bool>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<RemoteEnvironment, AppError> {
+    check_owner(&app_handle, &session_id, &window)?;
+    let cached = CACHE.get(&session_id).map(|e| e.clone());
+    let environment = match cached {
+        Some(environment) if !refresh.unwrap_or(false) => environment,
+        _ => {
+            let handle = app_handle.clone();
+            let id = session_id.clone();
+            let environment = async_runtime::spawn_blocking(move || fetch(&handle, &id))
+                .await
+                .map_err(|e| AppError::from(e.to_string()))??;
+            let state = app_handle.state::<AppState>();
+            // Forget sessions that have closed since
+            CACHE.retain(|id, _| {
+                Uuid::parse_str(id).is_ok_and(|uuid| state.sessions.contains_key(&uuid))
+            });
+            CACHE.insert(session_id, environment.clone());
+            environment
+        }
+    };
+    Ok(if include_sensitive.unwrap_or(false) {
+        environment
+    } else {
+        redact(environment)
+    })
+}
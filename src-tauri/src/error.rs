@@ -0,0 +1,352 @@
+// The error every command returns to the frontend.
+//
+// Serialized as {kind, message, details, session_id}. `kind` is stable and
+// is what the frontend branches on; `message` is for display and may change
+// wording at any time. Helpers inside the backend still return String
+// errors; those convert with kind "other", except the error codes modules
+// already return (config_file::BUSY and friends), which keep a kind of their
+// own. ssh2, SFTP and io errors are classified by their codes.
+
+use crate::connect_timeline::ConnectTimeline;
+use crate::{agent, config_file, history, vault, zmodem};
+use serde::Serialize;
+use thiserror::Error;
+
+/// What went wrong, for the frontend to act on. Never rename a variant; the
+/// kebab-case names are part of the command API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// Not classified; only the message says more
+    Other,
+    /// A command argument was rejected
+    InvalidInput,
+    /// A file, host, entry or remote path doesn't exist
+    NotFound,
+    AlreadyExists,
+    PermissionDenied,
+    /// The session id doesn't name an open session
+    SessionNotFound,
+    SftpNotInitialized,
+    /// The server couldn't be reached
+    ConnectionFailed,
+    /// An established connection or channel went away; reconnecting may help
+    ConnectionLost,
+    Timeout,
+    /// Key exchange or protocol negotiation failed
+    HandshakeFailed,
+    AuthFailed,
+    /// connect_ssh stopped at a changed host key, see accept_changed_host_key
+    HostKeyChanged,
+    /// The remote disk or quota is full
+    NoSpace,
+    /// Another window or instance holds a config file (config_file::BUSY)
+    ConfigBusy,
+    /// The vault must be unlocked first (vault::LOCKED)
+    VaultLocked,
+    /// Retry with a password (history::PASSWORD_REQUIRED)
+    PasswordRequired,
+    /// Retry with the key's passphrase (agent::PASSPHRASE_REQUIRED)
+    PassphraseRequired,
+    /// The saved host a history entry points to was deleted
+    HostDeleted,
+    /// Stopped by a cancel command before it finished
+    Cancelled,
+    /// Too many recent failures; details.retry_after_secs says when to retry
+    RateLimited,
+    /// The remote host lacks what the feature needs, e.g. systemd
+    Unsupported,
+    /// The session is read-only, see read_only::set_session_readonly
+    ReadOnly,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ErrorDetails {
+    // OS error number of an io error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errno: Option<i32>,
+    // libssh2 error code (negative)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh_code: Option<i32>,
+    // SFTP status code from the server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sftp_status: Option<i32>,
+    // Seconds until a rate-limited call may be retried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u32>,
+    // Something the frontend can offer to fix it, e.g. wol::WAKE_ACTION
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    // 1-based line of submitted text that was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    // A failed connect's phases up to the failing one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeline: Option<ConnectTimeline>,
+}
+
+#[derive(Debug, Clone, Error, Serialize)]
+#[error("{message}")]
+pub struct AppError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub details: Option<Box<ErrorDetails>>,
+    pub session_id: Option<String>,
+}
+
+// libssh2 session error codes, from libssh2.h
+const LIBSSH2_ERROR_BANNER_RECV: i32 = -2;
+const LIBSSH2_ERROR_BANNER_SEND: i32 = -3;
+const LIBSSH2_ERROR_KEX_FAILURE: i32 = -5;
+const LIBSSH2_ERROR_SOCKET_SEND: i32 = -7;
+const LIBSSH2_ERROR_KEY_EXCHANGE_FAILURE: i32 = -8;
+const LIBSSH2_ERROR_TIMEOUT: i32 = -9;
+const LIBSSH2_ERROR_HOSTKEY_INIT: i32 = -10;
+const LIBSSH2_ERROR_SOCKET_DISCONNECT: i32 = -13;
+const LIBSSH2_ERROR_AUTHENTICATION_FAILED: i32 = -18;
+const LIBSSH2_ERROR_PUBLICKEY_UNVERIFIED: i32 = -19;
+const LIBSSH2_ERROR_CHANNEL_FAILURE: i32 = -21;
+const LIBSSH2_ERROR_CHANNEL_CLOSED: i32 = -26;
+const LIBSSH2_ERROR_CHANNEL_EOF_SENT: i32 = -27;
+const LIBSSH2_ERROR_SOCKET_TIMEOUT: i32 = -30;
+const LIBSSH2_ERROR_SOCKET_RECV: i32 = -43;
+
+// SFTP status codes, from draft-ietf-secsh-filexfer
+const SSH_FX_NO_SUCH_FILE: i32 = 2;
+const SSH_FX_PERMISSION_DENIED: i32 = 3;
+const SSH_FX_NO_CONNECTION: i32 = 6;
+const SSH_FX_CONNECTION_LOST: i32 = 7;
+const SSH_FX_NO_SUCH_PATH: i32 = 10;
+const SSH_FX_FILE_ALREADY_EXISTS: i32 = 11;
+const SSH_FX_WRITE_PROTECT: i32 = 12;
+const SSH_FX_NO_SPACE_ON_FILESYSTEM: i32 = 14;
+const SSH_FX_QUOTA_EXCEEDED: i32 = 15;
+
+impl AppError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            details: None,
+            session_id: None,
+        }
+    }
+
+    pub fn session_not_found(session_id: &str) -> Self {
+        Self::new(ErrorKind::SessionNotFound, "Session not found").with_session(session_id)
+    }
+
+    pub fn with_session(mut self, session_id: impl Into<String>) -> Self {
+        self.session_id = Some(session_id.into());
+        self
+    }
+
+    /// Prefixes the message, e.g. "Key authentication failed: <error>".
+    pub fn context(mut self, context: &str) -> Self {
+        self.message = format!("{}: {}", context, self.message);
+        self
+    }
+
+    /// Classifies an error no code identified, from where it happened.
+    pub fn or_kind(mut self, kind: ErrorKind) -> Self {
+        if self.kind == ErrorKind::Other {
+            self.kind = kind;
+        }
+        self
+    }
+}
+
+fn ssh_kind(code: i32) -> ErrorKind {
+    match code {
+        LIBSSH2_ERROR_AUTHENTICATION_FAILED | LIBSSH2_ERROR_PUBLICKEY_UNVERIFIED => {
+            ErrorKind::AuthFailed
+        }
+        LIBSSH2_ERROR_TIMEOUT | LIBSSH2_ERROR_SOCKET_TIMEOUT => ErrorKind::Timeout,
+        LIBSSH2_ERROR_BANNER_RECV
+        | LIBSSH2_ERROR_BANNER_SEND
+        | LIBSSH2_ERROR_KEX_FAILURE
+        | LIBSSH2_ERROR_KEY_EXCHANGE_FAILURE
+        | LIBSSH2_ERROR_HOSTKEY_INIT => ErrorKind::HandshakeFailed,
+        LIBSSH2_ERROR_SOCKET_SEND
+        | LIBSSH2_ERROR_SOCKET_RECV
+        | LIBSSH2_ERROR_SOCKET_DISCONNECT
+        | LIBSSH2_ERROR_CHANNEL_FAILURE
+        | LIBSSH2_ERROR_CHANNEL_CLOSED
+        | LIBSSH2_ERROR_CHANNEL_EOF_SENT => ErrorKind::ConnectionLost,
+        _ => ErrorKind::Other,
+    }
+}
+
+fn sftp_kind(status: i32) -> ErrorKind {
+    match status {
+        SSH_FX_NO_SUCH_FILE | SSH_FX_NO_SUCH_PATH => ErrorKind::NotFound,
+        SSH_FX_PERMISSION_DENIED | SSH_FX_WRITE_PROTECT => ErrorKind::PermissionDenied,
+        SSH_FX_FILE_ALREADY_EXISTS => ErrorKind::AlreadyExists,
+        SSH_FX_NO_SPACE_ON_FILESYSTEM | SSH_FX_QUOTA_EXCEEDED => ErrorKind::NoSpace,
+        SSH_FX_NO_CONNECTION | SSH_FX_CONNECTION_LOST => ErrorKind::ConnectionLost,
+        _ => ErrorKind::Other,
+    }
+}
+
+impl From<ssh2::Error> for AppError {
+    fn from(error: ssh2::Error) -> Self {
+        let (kind, details) = match error.code() {
+            ssh2::ErrorCode::Session(code) => (
+                ssh_kind(code),
+                ErrorDetails {
+                    ssh_code: Some(code),
+                    ..ErrorDetails::default()
+                },
+            ),
+            ssh2::ErrorCode::SFTP(status) => (
+                sftp_kind(status),
+                ErrorDetails {
+                    sftp_status: Some(status),
+                    ..ErrorDetails::default()
+                },
+            ),
+        };
+        Self {
+            details: Some(Box::new(details)),
+            ..Self::new(kind, error.message())
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(error: std::io::Error) -> Self {
+        use std::io::ErrorKind as Io;
+        // ssh2 hands its errors out as io errors from Read and Write
+        let error = match error.downcast::<ssh2::Error>() {
+            Ok(inner) => return Self::from(inner),
+            Err(error) => error,
+        };
+        let kind = match error.kind() {
+            Io::NotFound => ErrorKind::NotFound,
+            Io::PermissionDenied => ErrorKind::PermissionDenied,
+            Io::AlreadyExists => ErrorKind::AlreadyExists,
+            Io::TimedOut => ErrorKind::Timeout,
+            Io::ConnectionRefused | Io::AddrNotAvailable => ErrorKind::ConnectionFailed,
+            Io::ConnectionReset | Io::ConnectionAborted | Io::BrokenPipe | Io::UnexpectedEof => {
+                ErrorKind::ConnectionLost
+            }
+            Io::InvalidInput | Io::InvalidData => ErrorKind::InvalidInput,
+            _ => ErrorKind::Other,
+        };
+        Self {
+            details: error.raw_os_error().map(|errno| {
+                Box::new(ErrorDetails {
+                    errno: Some(errno),
+                    ..ErrorDetails::default()
+                })
+            }),
+            ..Self::new(kind, error.to_string())
+        }
+    }
+}
+
+impl From<&zmodem::ZmodemError> for AppError {
+    fn from(error: &zmodem::ZmodemError) -> Self {
+        let kind = match error {
+            zmodem::ZmodemError::Cancelled | zmodem::ZmodemError::RemoteAborted => {
+                ErrorKind::Cancelled
+            }
+            zmodem::ZmodemError::Timeout => ErrorKind::Timeout,
+            zmodem::ZmodemError::Protocol(_) | zmodem::ZmodemError::Io(_) => ErrorKind::Other,
+        };
+        Self::new(kind, error.to_string())
+    }
+}
+
+impl From<uuid::Error> for AppError {
+    fn from(_: uuid::Error) -> Self {
+        Self::new(ErrorKind::InvalidInput, "Invalid session identifier")
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::new(ErrorKind::InvalidInput, error.to_string())
+    }
+}
+
+// Plain string errors, recognizing the codes modules return as strings
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        let kind = match message.as_str() {
+            config_file::BUSY => ErrorKind::ConfigBusy,
+            vault::LOCKED => ErrorKind::VaultLocked,
+            history::PASSWORD_REQUIRED => ErrorKind::PasswordRequired,
+            history::HOST_DELETED => ErrorKind::HostDeleted,
+            agent::PASSPHRASE_REQUIRED => ErrorKind::PassphraseRequired,
+            _ => ErrorKind::Other,
+        };
+        Self::new(kind, message)
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        Self::from(message.to_string())
+    }
+}
+
+// Commands are also called as plain functions from String-returning helpers
+impl From<AppError> for String {
+    fn from(error: AppError) -> Self {
+        error.message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn libssh2_codes_map_to_kinds() {
+        let cases = [
+            (-18, ErrorKind::AuthFailed),
+            (-19, ErrorKind::AuthFailed),
+            (-9, ErrorKind::Timeout),
+            (-30, ErrorKind::Timeout),
+            (-2, ErrorKind::HandshakeFailed),
+            (-5, ErrorKind::HandshakeFailed),
+            (-8, ErrorKind::HandshakeFailed),
+            (-10, ErrorKind::HandshakeFailed),
+            (-7, ErrorKind::ConnectionLost),
+            (-13, ErrorKind::ConnectionLost),
+            (-43, ErrorKind::ConnectionLost),
+            (-26, ErrorKind::ConnectionLost),
+            (-1, ErrorKind::Other),
+        ];
+        for (code, kind) in cases {
+            assert_eq!(ssh_kind(code), kind, "libssh2 code {}", code);
+        }
+    }
+
+    #[test]
+    fn sftp_statuses_map_to_kinds() {
+        let cases = [
+            (2, ErrorKind::NotFound),
+            (10, ErrorKind::NotFound),
+            (3, ErrorKind::PermissionDenied),
+            (12, ErrorKind::PermissionDenied),
+            (11, ErrorKind::AlreadyExists),
+            (14, ErrorKind::NoSpace),
+            (15, ErrorKind::NoSpace),
+            (6, ErrorKind::ConnectionLost),
+            (7, ErrorKind::ConnectionLost),
+            (4, ErrorKind::Other),
+        ];
+        for (status, kind) in cases {
+            assert_eq!(sftp_kind(status), kind, "SFTP status {}", status);
+        }
+    }
+
+    #[test]
+    fn ssh2_errors_keep_their_code_in_the_details() {
+        let error = AppError::from(ssh2::Error::new(ssh2::ErrorCode::SFTP(2), "No such file"));
+        assert_eq!(error.kind, ErrorKind::NotFound);
+        assert_eq!(error.details.and_then(|d| d.sftp_status), Some(2));
+    }
+}
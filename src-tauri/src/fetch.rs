@@ -0,0 +1,406 @@
+// Downloads a URL straight onto the remote host, skipping the round trip
+// through this machine.
+//
+// curl (or wget when curl is missing) runs on a side channel and writes to
+// "<destination>.terminoda-part"; its progress meter on stderr is parsed into
+// the usual transfer-progress events, wrapped in a Transfer for the
+// completed/failed event. Once the download exits cleanly the part file
+// must be non-empty and, when an expected SHA-256 is given, match it;
+// only then is it moved over the destination. Cancelling or timing out
+// closes the channel, which takes curl down with it, and the part file is
+// removed.
+
+use crate::error::{AppError, ErrorKind};
+use crate::progress::ProgressReporter;
+use crate::side_channel::{run_on_side_channel, shell_quote, stream_on_side_channel};
+use crate::transfer_events::Transfer;
+use crate::{ownership, AppState};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{async_runtime, AppHandle, Manager, Window};
+use tracing::{info, warn};
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30 * 60;
+const MAX_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+const EXEC_TIMEOUT: Duration = Duration::from_secs(30);
+// Hashing a large download takes a while
+const CHECKSUM_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const PART_SUFFIX: &str = ".terminoda-part";
+const SCHEMES: &[&str] = &["http://", "https://", "ftp://"];
+
+// Stop flags of running fetches, by transfer id
+static FETCHES: LazyLock<DashMap<String, Arc<AtomicBool>>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteFetch {
+    pub transfer_id: String,
+    pub path: String,
+    pub size: u64,
+    // Only computed when an expected hash was given
+    pub sha256: Option<String>,
+}
+
+// "512", "4608k", "10.0M" as curl's meter prints them
+fn parse_size(value: &str) -> Option<u64> {
+    let (number, unit) = match value.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => value.split_at(i),
+        None => (value, ""),
+    };
+    let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        "t" => 1 << 40,
+        "p" => 1 << 50,
+        _ => return None,
+    };
+    Some((number.parse::<f64>().ok()? * multiplier as f64) as u64)
+}
+
+#[derive(Default)]
+struct Progress {
+    total: u64,
+    received: u64,
+    // The last line that wasn't progress, for the error message
+    last_message: Option<String>,
+}
+
+impl Progress {
+    // curl: "  45 10.0M   45 4608k    0     0  1234k      0  0:00:08 ..."
+    // wget: "Length: 10485760 (10M) [...]" then "  4096K ........ 40% 1.2M 5s"
+    fn parse_line(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if let Some(length) = line.strip_prefix("Length: ") {
+            if let Some(total) = length
+                .split_whitespace()
+                .next()
+                .and_then(|t| t.parse().ok())
+            {
+                self.total = total;
+            }
+        } else if fields.len() >= 4
+            && fields[0].parse::<u8>().is_ok()
+            && fields[2].parse::<u8>().is_ok()
+        {
+            if let (Some(total), Some(received)) = (parse_size(fields[1]), parse_size(fields[3])) {
+                self.total = total;
+                self.received = received;
+            }
+        } else if let Some(percent) = fields
+            .iter()
+            .find_map(|f| f.strip_suffix('%'))
+            .filter(|_| line.contains(". "))
+            .and_then(|p| p.parse::<u64>().ok())
+        {
+            self.received = self.total * percent / 100;
+        } else if !line.starts_with('%') && !line.contains("Dload") {
+            self.last_message = Some(line.to_string());
+        }
+    }
+}
+
+fn fetch_command(url: &str, part: &str, timeout_secs: u64) -> String {
+    let url = shell_quote(url);
+    let part = shell_quote(part);
+    format!(
+        "if command -v curl >/dev/null 2>&1; then curl -fL --max-time {timeout} -o {part} {url}; \
+         elif command -v wget >/dev/null 2>&1; then wget -T 60 --progress=dot:mega -O {part} {url}; \
+         else echo 'Neither curl nor wget is installed' >&2; exit 127; fi",
+        timeout = timeout_secs,
+        part = part,
+        url = url,
+    )
+}
+
+/// SHA-256 of a remote file, with whichever of sha256sum, shasum or openssl
+/// the host has. Blocks, so call it from a blocking task.
+pub(crate) fn remote_sha256(
+    app_handle: &AppHandle,
+    session_id: &str,
+    path: &str,
+) -> Result<String, AppError> {
+    let path = shell_quote(path);
+    let command = format!(
+        "sha256sum {path} 2>/dev/null || shasum -a 256 {path} 2>/dev/null || openssl dgst -sha256 -r {path}",
+        path = path
+    );
+    let state = app_handle.state::<AppState>();
+    let output = run_on_side_channel(&state.sessions, session_id, &command, CHECKSUM_TIMEOUT)?;
+    output
+        .stdout
+        .split_whitespace()
+        .next()
+        .filter(|hash| hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+        .map(str::to_ascii_lowercase)
+        .ok_or_else(|| {
+            AppError::new(
+                ErrorKind::Unsupported,
+                "No sha256sum, shasum or openssl on the remote host",
+            )
+        })
+}
+
+fn run(app_handle: &AppHandle, session_id: &str, command: &str) -> Result<String, AppError> {
+    let state = app_handle.state::<AppState>();
+    let output = run_on_side_channel(&state.sessions, session_id, command, EXEC_TIMEOUT)?;
+    if output.exit_status != 0 {
+        return Err(AppError::from(output.stderr.trim().to_string()));
+    }
+    Ok(output.stdout)
+}
+
+struct Fetch<'a> {
+    app_handle: &'a AppHandle,
+    session_id: &'a str,
+    url: &'a str,
+    destination: &'a str,
+    expected_sha256: Option<String>,
+    timeout: Duration,
+    stop: &'a AtomicBool,
+}
+
+impl Fetch<'_> {
+    fn part(&self) -> String {
+        format!("{}{}", self.destination, PART_SUFFIX)
+    }
+
+    fn download(&self, progress: &ProgressReporter) -> Result<(), AppError> {
+        let state = self.app_handle.state::<AppState>();
+        let command = fetch_command(self.url, &self.part(), self.timeout.as_secs());
+        let done = AtomicBool::new(false);
+        let timed_out = AtomicBool::new(false);
+        let mut parsed = Progress::default();
+        let mut pending = Vec::new();
+
+        let status = thread::scope(|scope| {
+            // Closing the channel is what stops curl, for a timeout too
+            scope.spawn(|| {
+                let deadline = Instant::now() + self.timeout;
+                while !done.load(Ordering::Relaxed) {
+                    if Instant::now() >= deadline {
+                        timed_out.store(true, Ordering::Relaxed);
+                        self.stop.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(200));
+                }
+            });
+            let status = stream_on_side_channel(
+                &state.sessions,
+                self.session_id,
+                &command,
+                self.stop,
+                |chunk| {
+                    pending.extend_from_slice(chunk);
+                    // curl redraws its meter with \r
+                    while let Some(end) = pending.iter().position(|&b| b == b'\r' || b == b'\n') {
+                        let line: Vec<u8> = pending.drain(..=end).collect();
+                        parsed.parse_line(&String::from_utf8_lossy(&line));
+                    }
+                    progress.set(self.destination, parsed.received, parsed.total);
+                },
+            );
+            done.store(true, Ordering::Relaxed);
+            status
+        })?;
+
+        if timed_out.load(Ordering::Relaxed) {
+            return Err(AppError::new(
+                ErrorKind::Timeout,
+                format!("The download took longer than {}s", self.timeout.as_secs()),
+            ));
+        }
+        match status {
+            None => Err(AppError::new(
+                ErrorKind::Cancelled,
+                "The download was cancelled",
+            )),
+            Some(0) => Ok(()),
+            Some(127) => Err(AppError::new(
+                ErrorKind::Unsupported,
+                "Neither curl nor wget is installed on the remote host",
+            )),
+            Some(code) => Err(AppError::new(
+                ErrorKind::Other,
+                parsed
+                    .last_message
+                    .unwrap_or_else(|| format!("The download failed with exit status {}", code)),
+            )),
+        }
+    }
+
+    // Checks the part file and moves it into place; returns its size and hash
+    fn finish(&self) -> Result<(u64, Option<String>), AppError> {
+        let part = self.part();
+        let size = run(
+            self.app_handle,
+            self.session_id,
+            &format!("wc -c < {}", shell_quote(&part)),
+        )?
+        .trim()
+        .parse::<u64>()
+        .unwrap_or(0);
+        if size == 0 {
+            return Err(AppError::new(
+                ErrorKind::Other,
+                "The downloaded file is empty",
+            ));
+        }
+        let sha256 = match &self.expected_sha256 {
+            Some(expected) => {
+                let actual = remote_sha256(self.app_handle, self.session_id, &part)?;
+                if actual != *expected {
+                    return Err(AppError::new(
+                        ErrorKind::Other,
+                        format!("Checksum mismatch: expected {}, got {}", expected, actual),
+                    ));
+                }
+                Some(actual)
+            }
+            None => None,
+        };
+        run(
+            self.app_handle,
+            self.session_id,
+            &format!(
+                "mv -f {} {}",
+                shell_quote(&part),
+                shell_quote(self.destination)
+            ),
+        )?;
+        Ok((size, sha256))
+    }
+
+    fn remove_part(&self) {
+        let command = format!("rm -f {}", shell_quote(&self.part()));
+        if let Err(e) = run(self.app_handle, self.session_id, &command) {
+            warn!(target = "fetch", session = %self.session_id, error = %e, "Could not remove partial download");
+        }
+    }
+}
+
+/// Runs curl (or wget) on the remote host to download `url` to
+/// `destination`, emitting the usual transfer events under `transfer_id`.
+/// With `expected_sha256` the file must match it before it replaces the
+/// destination. Stopped by cancel_remote_fetch or after `timeout_secs`
+/// (30 minutes by default).
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn remote_fetch_url(
+    session_id: String,
+    url: String,
+    destination: String,
+    expected_sha256: Option<String>,
+    timeout_secs: Option<u64>,
+    transfer_id: Option<String>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<RemoteFetch, AppError> {
+    let owners = ownership::authorize(&app_handle.state::<AppState>(), &session_id, &window)?;
+    if !SCHEMES.iter().any(|scheme| url.starts_with(scheme)) {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Only http, https and ftp URLs can be fetched",
+        ));
+    }
+    if destination.trim().is_empty() || destination.ends_with('/') {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "The destination must be a file path",
+        ));
+    }
+    let expected_sha256 = expected_sha256
+        .map(|hash| hash.trim().to_ascii_lowercase())
+        .filter(|hash| !hash.is_empty());
+    if let Some(hash) = &expected_sha256 {
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(AppError::new(
+                ErrorKind::InvalidInput,
+                "The expected SHA-256 must be 64 hex digits",
+            ));
+        }
+    }
+    let timeout = Duration::from_secs(
+        timeout_secs
+            .unwrap_or(DEFAULT_TIMEOUT_SECS)
+            .clamp(1, MAX_TIMEOUT_SECS),
+    );
+
+    let transfer = Transfer::start(
+        &window,
+        owners.clone(),
+        transfer_id,
+        &session_id,
+        &url,
+        &destination,
+    );
+    let transfer_id = transfer.id().to_string();
+    let stop = Arc::new(AtomicBool::new(false));
+    FETCHES.insert(transfer_id.clone(), stop.clone());
+    let bytes = transfer.bytes();
+    let id = transfer_id.clone();
+    let result = async_runtime::spawn_blocking(move || {
+        let fetch = Fetch {
+            app_handle: &app_handle,
+            session_id: &session_id,
+            url: &url,
+            destination: &destination,
+            expected_sha256,
+            timeout,
+            stop: &stop,
+        };
+        info!(target = "fetch", session = %session_id, %url, %destination, "Starting remote fetch");
+        let progress = ProgressReporter::start(
+            window.clone(),
+            owners,
+            session_id.clone(),
+            destination.clone(),
+            0,
+        );
+        let result = fetch.download(&progress).and_then(|_| fetch.finish());
+        match &result {
+            Ok((size, _)) => {
+                bytes.store(*size, Ordering::Relaxed);
+                progress.finish();
+                info!(target = "fetch", session = %session_id, size, "Remote fetch complete");
+            }
+            Err(e) => {
+                warn!(target = "fetch", session = %session_id, error = %e, "Remote fetch failed");
+                fetch.remove_part();
+            }
+        }
+        result.map(|(size, sha256)| RemoteFetch {
+            transfer_id: id,
+            path: destination.clone(),
+            size,
+            sha256,
+        })
+    })
+    .await
+    .map_err(|e| AppError::from(e.to_string()))
+    .and_then(|result| result);
+    FETCHES.remove(&transfer_id);
+    transfer.finish(&result.as_ref().map(|_| ()).map_err(Clone::clone));
+    result
+}
+
+/// Stops a running remote_fetch_url. Returns false if it already finished.
+#[tauri::command]
+pub fn cancel_remote_fetch(transfer_id: String) -> bool {
+    match FETCHES.get(&transfer_id) {
+        Some(stop) => {
+            stop.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
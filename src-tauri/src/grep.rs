@@ -0,0 +1,565 @@
+// "Find in files" under a remote directory, for the SFTP panel.
+//
+// Searches run rg when the host has it and "grep -r" otherwise; which one is
+// looked up once per session. Both print the path NUL-terminated so paths
+// with colons parse. rg is told to ignore .gitignore and hidden-file rules,
+// to find what grep would. Where exec isn't allowed (an SFTP-only account),
+// the tree is walked over SFTP and files up to FALLBACK_MAX_FILE_BYTES are
+// downloaded and searched here. Symlinks are followed only with
+// follow_symlinks: rg -L and grep -R then, remote_walk's rules for the
+// fallback, whose skipped links go in the summary.
+//
+// Matches go out as "grep-matches" events in batches, and the search stops
+// at max_matches. "grep-finished" carries the summary: binary files that
+// matched (their lines are never sent), files too large for the fallback and
+// errors like unreadable directories. cancel_grep stops a search early.
+
+use crate::error::{AppError, ErrorKind};
+use crate::remote_walk::{SkippedLink, WalkOptions, Walker};
+use crate::side_channel::{run_on_side_channel, shell_quote, stream_on_side_channel};
+use crate::{ownership, sftp_ops, AppState, SessionState};
+use dashmap::DashMap;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const DETECT: &str =
+    "command -v rg >/dev/null 2>&1 && echo rg || { command -v grep >/dev/null 2>&1 && echo grep; }";
+const DETECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_MAX_MATCHES: usize = 1_000;
+const MAX_MATCHES: usize = 10_000;
+const BATCH_SIZE: usize = 100;
+const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+// Longer matched lines are cut, minified files would flood the panel
+const MAX_LINE_CHARS: usize = 500;
+const MAX_ERRORS: usize = 20;
+const FALLBACK_MAX_FILE_BYTES: u64 = 1024 * 1024;
+const FALLBACK_MAX_FILES: usize = 2_000;
+const FALLBACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+// The search tool each session was found to have
+static TOOLS: LazyLock<DashMap<String, Tool>> = LazyLock::new(DashMap::new);
+// Cancel flags of running searches
+static SEARCHES: LazyLock<DashMap<String, Arc<AtomicBool>>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tool {
+    Rg,
+    Grep,
+    // No usable exec: search over SFTP
+    Sftp,
+}
+
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Rg => "rg",
+            Tool::Grep => "grep",
+            Tool::Sftp => "sftp",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Query {
+    root: String,
+    pattern: String,
+    regex: bool,
+    glob: Option<String>,
+    case_sensitive: bool,
+    follow_symlinks: bool,
+    max_matches: usize,
+    // For the SFTP fallback
+    matcher: Regex,
+    glob_matcher: Option<Regex>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GrepMatch {
+    pub path: String,
+    pub line: u64,
+    pub text: String,
+}
+
+#[derive(Clone, Serialize)]
+struct GrepMatchesPayload {
+    search_id: String,
+    session_id: String,
+    matches: Vec<GrepMatch>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GrepSummary {
+    pub search_id: String,
+    pub session_id: String,
+    // "rg", "grep" or "sftp"
+    pub method: String,
+    pub matches: usize,
+    pub files: usize,
+    // Matched, but not shown
+    pub binary_files: Vec<String>,
+    // Not searched by the SFTP fallback
+    pub skipped_large_files: usize,
+    // Symlinks the SFTP fallback didn't follow
+    pub skipped_links: Vec<SkippedLink>,
+    // The first MAX_ERRORS, e.g. unreadable directories
+    pub errors: Vec<String>,
+    // Stopped at max_matches
+    pub truncated: bool,
+    pub cancelled: bool,
+    pub error: Option<String>,
+}
+
+// Batches matches into events and keeps the summary
+struct Collector<'a> {
+    window: &'a Window,
+    max_matches: usize,
+    batch: Vec<GrepMatch>,
+    last_emit: Instant,
+    files: HashSet<String>,
+    binary: HashSet<String>,
+    summary: GrepSummary,
+}
+
+impl<'a> Collector<'a> {
+    fn new(window: &'a Window, search_id: &str, session_id: &str, max_matches: usize) -> Self {
+        Self {
+            window,
+            max_matches,
+            batch: Vec::new(),
+            last_emit: Instant::now(),
+            files: HashSet::new(),
+            binary: HashSet::new(),
+            summary: GrepSummary {
+                search_id: search_id.to_string(),
+                session_id: session_id.to_string(),
+                ..GrepSummary::default()
+            },
+        }
+    }
+
+    fn full(&self) -> bool {
+        self.summary.matches >= self.max_matches
+    }
+
+    // Returns false once the cap is reached
+    fn add(&mut self, path: String, line: u64, text: &str) -> bool {
+        if self.full() {
+            self.summary.truncated = true;
+            return false;
+        }
+        let mut text = text.trim_end_matches('\r').to_string();
+        if let Some((cut, _)) = text.char_indices().nth(MAX_LINE_CHARS) {
+            text.truncate(cut);
+        }
+        self.files.insert(path.clone());
+        self.batch.push(GrepMatch { path, line, text });
+        self.summary.matches += 1;
+        if self.batch.len() >= BATCH_SIZE || self.last_emit.elapsed() >= BATCH_INTERVAL {
+            self.flush();
+        }
+        true
+    }
+
+    fn add_binary(&mut self, path: String) {
+        if self.binary.insert(path.clone()) {
+            self.summary.binary_files.push(path);
+        }
+    }
+
+    fn add_error(&mut self, error: String) {
+        if self.summary.errors.len() < MAX_ERRORS {
+            self.summary.errors.push(error);
+        }
+    }
+
+    fn flush(&mut self) {
+        self.last_emit = Instant::now();
+        if self.batch.is_empty() {
+            return;
+        }
+        let _ = self.window.emit(
+            "grep-matches",
+            GrepMatchesPayload {
+                search_id: self.summary.search_id.clone(),
+                session_id: self.summary.session_id.clone(),
+                matches: std::mem::take(&mut self.batch),
+            },
+        );
+    }
+
+    fn finish(mut self) -> GrepSummary {
+        self.flush();
+        self.summary.files = self.files.len();
+        self.summary
+    }
+}
+
+fn build_command(tool: Tool, query: &Query) -> String {
+    let mut command = match tool {
+        Tool::Rg => {
+            "rg -n --null --no-heading --color never --no-ignore --hidden --binary".to_string()
+        }
+        _ => "grep -nZ".to_string(),
+    };
+    command.push_str(match (tool, query.follow_symlinks) {
+        (Tool::Rg, true) => " -L",
+        (Tool::Rg, false) => "",
+        (_, true) => " -R",
+        (_, false) => " -r",
+    });
+    command.push_str(match (tool, query.regex) {
+        (_, false) => " -F",
+        (Tool::Rg, true) => "",
+        (_, true) => " -E",
+    });
+    if !query.case_sensitive {
+        command.push_str(" -i");
+    }
+    if let Some(glob) = &query.glob {
+        let flag = if tool == Tool::Rg { "-g" } else { "--include" };
+        command.push_str(&format!(" {} {}", flag, shell_quote(glob)));
+    }
+    command.push_str(&format!(
+        " -e {} -- {}",
+        shell_quote(&query.pattern),
+        shell_quote(&query.root)
+    ));
+    command
+}
+
+// One line of grep or rg output
+fn parse_line(line: &[u8], collector: &mut Collector) -> bool {
+    if let Some(nul) = line.iter().position(|&b| b == 0) {
+        let path = String::from_utf8_lossy(&line[..nul]).into_owned();
+        let rest = String::from_utf8_lossy(&line[nul + 1..]);
+        // rg --binary reports a binary match where the line would be
+        if rest.contains("binary file matches") || rest.contains("stopped searching binary file") {
+            collector.add_binary(path);
+            return true;
+        }
+        return match rest.split_once(':') {
+            Some((number, text)) => match number.parse() {
+                Ok(number) => collector.add(path, number, text),
+                Err(_) => true,
+            },
+            None => true,
+        };
+    }
+
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_end();
+    // "Binary file X matches" before GNU grep 3.5, "grep: X: binary file
+    // matches" after
+    if let Some(path) = line
+        .strip_prefix("Binary file ")
+        .and_then(|l| l.strip_suffix(" matches"))
+    {
+        collector.add_binary(path.to_string());
+    } else if let Some(path) = line
+        .strip_prefix("grep: ")
+        .and_then(|l| l.strip_suffix(": binary file matches"))
+    {
+        collector.add_binary(path.to_string());
+    } else if !line.is_empty() {
+        collector.add_error(line.to_string());
+    }
+    true
+}
+
+fn detect_tool(app_handle: &AppHandle, session_id: &str) -> Tool {
+    if let Some(tool) = TOOLS.get(session_id) {
+        return *tool;
+    }
+    let state = app_handle.state::<AppState>();
+    let tool = match run_on_side_channel(&state.sessions, session_id, DETECT, DETECT_TIMEOUT) {
+        Ok(output) => match output.stdout.trim() {
+            "rg" => Tool::Rg,
+            "grep" => Tool::Grep,
+            _ => Tool::Sftp,
+        },
+        // Not cached; exec may only have failed this once
+        Err(e) => {
+            warn!(target = "grep", session = %session_id, error = %e, "Search tool detection failed");
+            return Tool::Sftp;
+        }
+    };
+    // Forget sessions that have closed since
+    TOOLS.retain(|id, _| Uuid::parse_str(id).is_ok_and(|uuid| state.sessions.contains_key(&uuid)));
+    TOOLS.insert(session_id.to_string(), tool);
+    info!(target = "grep", session = %session_id, tool = tool.name(), "Detected search tool");
+    tool
+}
+
+// Returns Err only when the command couldn't run at all
+fn search_exec(
+    app_handle: &AppHandle,
+    session_id: &str,
+    tool: Tool,
+    query: &Query,
+    stop: &AtomicBool,
+    collector: &mut Collector,
+) -> Result<(), String> {
+    let state = app_handle.state::<AppState>();
+    let command = build_command(tool, query);
+    let mut pending = Vec::new();
+    let mut capped = false;
+    let result = stream_on_side_channel(&state.sessions, session_id, &command, stop, |chunk| {
+        if capped {
+            return;
+        }
+        pending.extend_from_slice(chunk);
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            if !parse_line(&line[..end], collector) {
+                capped = true;
+                // Ends the stream at the next poll
+                stop.store(true, Ordering::Relaxed);
+                return;
+            }
+        }
+    });
+    match result {
+        Ok(Some(status)) if status > 1 && collector.summary.errors.is_empty() => {
+            collector.add_error(format!("{} exited with status {}", tool.name(), status));
+            Ok(())
+        }
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Shell-style "*" and "?", matched against the file name
+fn glob_regex(glob: &str) -> Result<Regex, AppError> {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).map_err(|e| AppError::new(ErrorKind::InvalidInput, e.to_string()))
+}
+
+fn search_sftp(
+    sessions: &DashMap<Uuid, SessionState>,
+    uuid: &Uuid,
+    query: &Query,
+    stop: &AtomicBool,
+    collector: &mut Collector,
+) {
+    let op = sftp_ops::Operation::new("search", FALLBACK_TIMEOUT, None);
+    let options = WalkOptions {
+        follow_symlinks: query.follow_symlinks,
+        ..WalkOptions::default()
+    };
+    let mut walker = Walker::new(sessions, *uuid, &op, Path::new(&query.root), options);
+    let mut searched = 0;
+    for entry in &mut walker {
+        if stop.load(Ordering::Relaxed) || collector.full() {
+            break;
+        }
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if e.kind == ErrorKind::SessionNotFound => {
+                collector.summary.error = Some(e.message);
+                break;
+            }
+            Err(e) => {
+                collector.add_error(e.message);
+                continue;
+            }
+        };
+        let path = entry.path;
+        if !entry.stat.is_file()
+            || !query
+                .glob_matcher
+                .as_ref()
+                .is_none_or(|g| matches_name(g, &path))
+        {
+            continue;
+        }
+        if entry.stat.size.unwrap_or(0) > FALLBACK_MAX_FILE_BYTES || searched >= FALLBACK_MAX_FILES
+        {
+            collector.summary.skipped_large_files += 1;
+            continue;
+        }
+        searched += 1;
+        let data = sessions
+            .get(uuid)
+            .ok_or_else(|| AppError::new(ErrorKind::SessionNotFound, "Session not found"))
+            .and_then(|s| sftp_ops::read_file(&s, &op, &path, FALLBACK_MAX_FILE_BYTES));
+        match data {
+            Ok(data) => {
+                if !search_data(&path, &data, &query.matcher, collector) {
+                    break;
+                }
+            }
+            Err(e) => collector.add_error(format!("{}: {}", path.display(), e.message)),
+        }
+    }
+    collector.summary.skipped_links = walker.into_skipped();
+}
+
+fn matches_name(glob: &Regex, path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| glob.is_match(&name.to_string_lossy()))
+}
+
+// Returns false once the cap is reached
+fn search_data(path: &Path, data: &[u8], matcher: &Regex, collector: &mut Collector) -> bool {
+    let path = path.to_string_lossy().into_owned();
+    // grep's test: a NUL byte in the first block
+    let binary = data[..data.len().min(8192)].contains(&0);
+    let text = String::from_utf8_lossy(data);
+    for (i, line) in text.lines().enumerate() {
+        if !matcher.is_match(line) {
+            continue;
+        }
+        if binary {
+            collector.add_binary(path);
+            return true;
+        }
+        if !collector.add(path.clone(), i as u64 + 1, line) {
+            return false;
+        }
+    }
+    true
+}
+
+fn check_owner(app_handle: &AppHandle, session_id: &str, window: &Window) -> Result<(), AppError> {
+    let state = app_handle.state::<AppState>();
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    ownership::check(&session, session_id, window)
+}
+
+fn run_search(
+    app_handle: &AppHandle,
+    window: &Window,
+    search_id: &str,
+    session_id: &str,
+    query: &Query,
+    stop: &AtomicBool,
+) -> GrepSummary {
+    let mut collector = Collector::new(window, search_id, session_id, query.max_matches);
+    let mut tool = detect_tool(app_handle, session_id);
+    if tool != Tool::Sftp {
+        if let Err(e) = search_exec(app_handle, session_id, tool, query, stop, &mut collector) {
+            warn!(target = "grep", session = %session_id, error = %e, "Remote search failed, searching over SFTP");
+            if collector.summary.matches > 0 {
+                collector.summary.error = Some(e);
+            } else {
+                tool = Tool::Sftp;
+            }
+        }
+    }
+    if tool == Tool::Sftp {
+        let state = app_handle.state::<AppState>();
+        match Uuid::parse_str(session_id) {
+            Ok(uuid) => search_sftp(&state.sessions, &uuid, query, stop, &mut collector),
+            Err(e) => collector.summary.error = Some(e.to_string()),
+        }
+    }
+    collector.summary.method = tool.name().to_string();
+    collector.summary.truncated |= collector.full();
+    collector.summary.cancelled = stop.load(Ordering::Relaxed) && !collector.summary.truncated;
+    collector.finish()
+}
+
+/// Searches files under `root` for `pattern` and returns the search id.
+/// Matches arrive as "grep-matches" events and the summary as
+/// "grep-finished".
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn grep_remote(
+    session_id: String,
+    root: String,
+    pattern: String,
+    regex: Option<bool>,
+    glob: Option<String>,
+    case_sensitive: Option<bool>,
+    max_matches: Option<usize>,
+    follow_symlinks: Option<bool>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<String, AppError> {
+    check_owner(&app_handle, &session_id, &window)?;
+    if pattern.is_empty() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Search pattern is empty",
+        ));
+    }
+    if root.trim().is_empty() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Search root is empty",
+        ));
+    }
+    let regex = regex.unwrap_or(false);
+    let case_sensitive = case_sensitive.unwrap_or(true);
+    let glob = glob.filter(|g| !g.trim().is_empty());
+    // Checked up front for the SFTP fallback; grep and rg use near enough
+    // the same syntax
+    let source = if regex {
+        pattern.clone()
+    } else {
+        regex::escape(&pattern)
+    };
+    let matcher = RegexBuilder::new(&source)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| AppError::new(ErrorKind::InvalidInput, format!("Invalid pattern: {}", e)))?;
+    let query = Query {
+        root,
+        pattern,
+        regex,
+        glob_matcher: glob.as_deref().map(glob_regex).transpose()?,
+        glob,
+        case_sensitive,
+        follow_symlinks: follow_symlinks.unwrap_or(false),
+        max_matches: max_matches
+            .unwrap_or(DEFAULT_MAX_MATCHES)
+            .clamp(1, MAX_MATCHES),
+        matcher,
+    };
+
+    let search_id = Uuid::new_v4().to_string();
+    let stop = Arc::new(AtomicBool::new(false));
+    SEARCHES.insert(search_id.clone(), stop.clone());
+    let id = search_id.clone();
+    thread::spawn(move || {
+        info!(target = "grep", session = %session_id, search = %id, root = %query.root, "Remote search started");
+        let summary = run_search(&app_handle, &window, &id, &session_id, &query, &stop);
+        SEARCHES.remove(&id);
+        info!(target = "grep", session = %session_id, search = %id, matches = summary.matches, method = %summary.method, "Remote search finished");
+        let _ = window.emit("grep-finished", summary);
+    });
+    Ok(search_id)
+}
+
+/// Stops a running search. Returns false if it already finished.
+#[tauri::command]
+pub fn cancel_grep(search_id: String) -> bool {
+    match SEARCHES.get(&search_id) {
+        Some(stop) => {
+            stop.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
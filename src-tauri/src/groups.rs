@@ -0,0 +1,299 @@
+// Host groups, persisted in groups.json next to connections.json.
+//
+// SavedHost.group holds a group id. Groups nest one level deep: a group may
+// have a top-level parent, but a group with a parent can't have children.
+// Files from before groups existed stored free-text names on each host; those
+// are turned into groups the first time hosts are loaded.
+
+use crate::backups;
+use crate::config_file::{self, ConfigLock};
+use crate::error::{AppError, ErrorKind};
+use crate::{get_config_dir, load_saved_hosts, lock_saved_hosts, write_saved_hosts, SavedHost};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::AppHandle;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostGroup {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    pub sort_order: u32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeleteGroupPolicy {
+    MoveToParent, // Hosts and subgroups move up a level
+    Ungroup,      // Hosts lose their group, subgroups become top-level
+}
+
+fn get_groups_path() -> Result<PathBuf, String> {
+    Ok(get_config_dir()?.join("groups.json"))
+}
+
+// Held from loading the groups to writing them back
+pub fn lock_groups() -> Result<ConfigLock, String> {
+    config_file::lock(&get_groups_path()?)
+}
+
+fn read_groups(app_handle: &AppHandle) -> Result<Vec<HostGroup>, String> {
+    Ok(config_file::load(app_handle, &get_groups_path()?)?.unwrap_or_default())
+}
+
+pub fn write_groups(groups: &[HostGroup]) -> Result<(), String> {
+    config_file::write(&get_groups_path()?, groups)
+}
+
+/// Loads the groups, running the string-group migration if it hasn't run.
+pub fn load(app_handle: &AppHandle) -> Result<Vec<HostGroup>, String> {
+    if !get_groups_path()?.exists() {
+        load_saved_hosts(app_handle.clone())?;
+    }
+    let mut groups = read_groups(app_handle)?;
+    groups.sort_by_key(|g| g.sort_order);
+    Ok(groups)
+}
+
+/// Turns free-text group names into groups. Runs once, when groups.json
+/// doesn't exist yet; returns whether any host changed.
+pub fn migrate_string_groups(hosts: &mut [SavedHost]) -> Result<bool, String> {
+    if get_groups_path()?.exists() {
+        return Ok(false);
+    }
+    let mut groups: Vec<HostGroup> = Vec::new();
+    let mut changed = false;
+    for host in hosts.iter_mut() {
+        let Some(name) = host.group.take().filter(|n| !n.trim().is_empty()) else {
+            continue;
+        };
+        let id = match groups.iter().find(|g| g.name == name) {
+            Some(group) => group.id.clone(),
+            None => {
+                let group = new_group(name, None, groups.len() as u32);
+                let id = group.id.clone();
+                groups.push(group);
+                id
+            }
+        };
+        host.group = Some(id);
+        changed = true;
+    }
+    write_groups(&groups)?;
+    if !groups.is_empty() {
+        info!(
+            target = "groups",
+            count = groups.len(),
+            "Migrated host groups"
+        );
+    }
+    Ok(changed)
+}
+
+fn new_group(name: String, parent_id: Option<String>, sort_order: u32) -> HostGroup {
+    HostGroup {
+        id: Uuid::new_v4().to_string(),
+        name,
+        parent_id,
+        sort_order,
+    }
+}
+
+fn next_sort_order(groups: &[HostGroup], parent_id: Option<&str>) -> u32 {
+    groups
+        .iter()
+        .filter(|g| g.parent_id.as_deref() == parent_id)
+        .map(|g| g.sort_order + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Maps a group reference from the frontend or an importer to a group id.
+/// Accepts an id or a top-level group name, creating the group if needed.
+pub fn resolve(app_handle: &AppHandle, group: Option<String>) -> Result<Option<String>, String> {
+    let Some(group) = group.filter(|g| !g.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let _lock = lock_groups()?;
+    let mut groups = load(app_handle)?;
+    if groups.iter().any(|g| g.id == group) {
+        return Ok(Some(group));
+    }
+    if let Some(existing) = groups
+        .iter()
+        .find(|g| g.parent_id.is_none() && g.name == group)
+    {
+        return Ok(Some(existing.id.clone()));
+    }
+    let created = new_group(group, None, next_sort_order(&groups, None));
+    let id = created.id.clone();
+    groups.push(created);
+    write_groups(&groups)?;
+    Ok(Some(id))
+}
+
+fn check_parent(groups: &[HostGroup], parent_id: &str) -> Result<(), AppError> {
+    let parent = groups
+        .iter()
+        .find(|g| g.id == parent_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Parent group not found"))?;
+    if parent.parent_id.is_some() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Groups can only be nested one level deep",
+        ));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn load_groups(app_handle: AppHandle) -> Result<Vec<HostGroup>, AppError> {
+    Ok(load(&app_handle)?)
+}
+
+#[tauri::command]
+pub fn create_group(
+    name: String,
+    parent_id: Option<String>,
+    app_handle: AppHandle,
+) -> Result<HostGroup, AppError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Group name cannot be empty",
+        ));
+    }
+    let _lock = lock_groups()?;
+    let mut groups = load(&app_handle)?;
+    if let Some(parent_id) = &parent_id {
+        check_parent(&groups, parent_id)?;
+    }
+    let sort_order = next_sort_order(&groups, parent_id.as_deref());
+    let group = new_group(name, parent_id, sort_order);
+    groups.push(group.clone());
+    write_groups(&groups)?;
+    Ok(group)
+}
+
+#[tauri::command]
+pub fn rename_group(
+    group_id: String,
+    name: String,
+    app_handle: AppHandle,
+) -> Result<HostGroup, AppError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Group name cannot be empty",
+        ));
+    }
+    let _lock = lock_groups()?;
+    let mut groups = load(&app_handle)?;
+    let group = groups
+        .iter_mut()
+        .find(|g| g.id == group_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Group not found"))?;
+    group.name = name;
+    let renamed = group.clone();
+    write_groups(&groups)?;
+    Ok(renamed)
+}
+
+#[tauri::command]
+pub fn delete_group(
+    group_id: String,
+    policy: DeleteGroupPolicy,
+    app_handle: AppHandle,
+) -> Result<(), AppError> {
+    backups::auto_backup("delete-group");
+    let _lock = lock_groups()?;
+    let mut groups = load(&app_handle)?;
+    let pos = groups
+        .iter()
+        .position(|g| g.id == group_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Group not found"))?;
+    let deleted = groups.remove(pos);
+    let new_home = match policy {
+        DeleteGroupPolicy::MoveToParent => deleted.parent_id.clone(),
+        DeleteGroupPolicy::Ungroup => None,
+    };
+
+    // Only top-level groups have children, and those move to the top level
+    let mut next = next_sort_order(&groups, None);
+    for group in groups.iter_mut() {
+        if group.parent_id.as_deref() == Some(deleted.id.as_str()) {
+            group.parent_id = None;
+            group.sort_order = next;
+            next += 1;
+        }
+    }
+
+    // Hosts first: a failure here leaves the group in place, not orphans
+    let _hosts_lock = lock_saved_hosts(&app_handle)?;
+    let mut hosts = load_saved_hosts(app_handle.clone())?;
+    let mut moved = 0;
+    for host in hosts.iter_mut() {
+        if host.group.as_deref() == Some(deleted.id.as_str()) {
+            host.group = new_home.clone();
+            moved += 1;
+        }
+    }
+    if moved > 0 {
+        write_saved_hosts(&app_handle, &hosts)?;
+    }
+    write_groups(&groups)?;
+    info!(target = "groups", group = %group_id, hosts = moved, "Deleted group");
+    Ok(())
+}
+
+#[tauri::command]
+pub fn move_host_to_group(
+    host_id: String,
+    group_id: Option<String>,
+    app_handle: AppHandle,
+) -> Result<SavedHost, AppError> {
+    if let Some(group_id) = &group_id {
+        if !load(&app_handle)?.iter().any(|g| &g.id == group_id) {
+            return Err(AppError::new(ErrorKind::NotFound, "Group not found"));
+        }
+    }
+    let _lock = lock_saved_hosts(&app_handle)?;
+    let mut hosts = load_saved_hosts(app_handle.clone())?;
+    let host = hosts
+        .iter_mut()
+        .find(|h| h.id == host_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Host not found"))?;
+    host.group = group_id;
+    let moved = host.clone();
+    write_saved_hosts(&app_handle, &hosts)?;
+    Ok(moved)
+}
+
+/// Orders groups by their position in `group_ids`; groups not listed keep
+/// their relative order after the listed ones. Unknown ids are ignored.
+#[tauri::command]
+pub fn reorder_groups(
+    group_ids: Vec<String>,
+    app_handle: AppHandle,
+) -> Result<Vec<HostGroup>, AppError> {
+    let _lock = lock_groups()?;
+    let mut groups = load(&app_handle)?;
+    groups.sort_by_key(|g| {
+        (
+            group_ids
+                .iter()
+                .position(|id| *id == g.id)
+                .unwrap_or(usize::MAX),
+            g.sort_order,
+        )
+    });
+    for (i, group) in groups.iter_mut().enumerate() {
+        group.sort_order = i as u32;
+    }
+    write_groups(&groups)?;
+    Ok(groups)
+}
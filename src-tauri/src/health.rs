@@ -0,0 +1,123 @@
+// Recovering session locks left poisoned by a panicking thread.
+//
+// The data behind a session's locks (the channel, the SFTP handle, the
+// scrollback) stays structurally valid when a holder panics, so the guard is
+// taken back instead of failing every later command on the session. Each
+// recovery is counted; past DEGRADED_AFTER the session is marked degraded and
+// "session-degraded" is emitted once, so the user can choose to reconnect.
+
+use crate::ownership::SessionOwners;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tauri::Window;
+use tracing::warn;
+
+const DEGRADED_AFTER: u32 = 3;
+
+#[derive(Debug, Clone, Serialize)]
+struct SessionDegradedPayload {
+    session_id: String,
+    poison_events: u32,
+}
+
+pub struct SessionHealth {
+    session_id: String,
+    on_degraded: Box<dyn Fn(SessionDegradedPayload) + Send + Sync>,
+    poison_events: AtomicU32,
+    degraded: AtomicBool,
+}
+
+impl SessionHealth {
+    pub fn new(session_id: String, window: Window, owners: Arc<SessionOwners>) -> Self {
+        Self::with_notifier(session_id, move |payload| {
+            owners.emit(&window, "session-degraded", payload);
+        })
+    }
+
+    fn with_notifier(
+        session_id: String,
+        on_degraded: impl Fn(SessionDegradedPayload) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            session_id,
+            on_degraded: Box::new(on_degraded),
+            poison_events: AtomicU32::new(0),
+            degraded: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    /// Locks `mutex`, recovering the guard if a panic poisoned it. `what`
+    /// names the lock in the log.
+    pub fn lock<'a, T>(&self, mutex: &'a Mutex<T>, what: &str) -> MutexGuard<'a, T> {
+        mutex.lock().unwrap_or_else(|poisoned| {
+            // Cleared so one panic counts once
+            mutex.clear_poison();
+            self.record_poison(what);
+            poisoned.into_inner()
+        })
+    }
+
+    fn record_poison(&self, what: &str) {
+        let events = self.poison_events.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!(target = "health", session = %self.session_id, lock = what, events, "Recovered poisoned lock");
+        if events >= DEGRADED_AFTER && !self.degraded.swap(true, Ordering::Relaxed) {
+            warn!(target = "health", session = %self.session_id, "Session degraded");
+            (self.on_degraded)(SessionDegradedPayload {
+                session_id: self.session_id.clone(),
+                poison_events: events,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn poison(mutex: &Arc<Mutex<Vec<u8>>>) {
+        let mutex = mutex.clone();
+        let _ = thread::spawn(move || {
+            let _guard = mutex.lock().unwrap();
+            panic!("holder panicked");
+        })
+        .join();
+    }
+
+    #[test]
+    fn recovers_a_lock_poisoned_by_a_panicking_thread() {
+        let health = SessionHealth::with_notifier("s".to_string(), |_| {});
+        let mutex = Arc::new(Mutex::new(vec![1, 2, 3]));
+        poison(&mutex);
+        assert!(mutex.is_poisoned());
+
+        let guard = health.lock(&mutex, "test");
+        assert_eq!(*guard, vec![1, 2, 3]);
+        drop(guard);
+        assert!(!mutex.is_poisoned());
+        assert_eq!(health.poison_events.load(Ordering::Relaxed), 1);
+        assert!(!health.is_degraded());
+    }
+
+    #[test]
+    fn repeated_poisoning_degrades_the_session_once() {
+        let notified = Arc::new(Mutex::new(Vec::new()));
+        let sink = notified.clone();
+        let health = SessionHealth::with_notifier("s".to_string(), move |payload| {
+            sink.lock().unwrap().push(payload.poison_events);
+        });
+        let mutex = Arc::new(Mutex::new(Vec::new()));
+        for _ in 0..DEGRADED_AFTER + 1 {
+            poison(&mutex);
+            drop(health.lock(&mutex, "test"));
+        }
+        assert!(health.is_degraded());
+        assert_eq!(*notified.lock().unwrap(), vec![DEGRADED_AFTER]);
+    }
+}
@@ -0,0 +1,441 @@
+// The connection history: one entry per connection attempt.
+//
+// history.json is kept oldest first; queries return newest first. An attempt
+// writes its entry as "Connecting..." and updates it in place once the
+// outcome is known. Sessions carry the id of that entry, and the first close
+// path to run fills in how long the session lasted and why it ended.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{
+    backups, config_file, connect_ssh, get_history_path, host_stats, load_saved_hosts, AppState,
+    ConnectionDetails, ConnectionLog, SessionTarget,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State, Window};
+use tracing::{info, warn};
+
+pub const CONNECTING: &str = "Connecting...";
+const TIMED_OUT: &str = "Failed (Timeout)";
+// Errors the frontend acts on: drop the entry's host link, or ask for a
+// password and call reconnect_from_history again
+pub const HOST_DELETED: &str = "host no longer exists";
+pub const PASSWORD_REQUIRED: &str = "password-required";
+// Attempts still connecting after this long were cut short, by a crash or a
+// hung connect, and are closed out on the next load
+const STALE_AFTER_SECS: u64 = 5 * 60;
+const CSV_HEADER: &str = "id,timestamp,host,port,username,protocol,status,auth_method,duration_seconds,disconnect_reason,error,host_id";
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HistoryQuery {
+    pub offset: usize,
+    // Every matching entry when unset
+    pub limit: Option<usize>,
+    // Case-insensitive substring of the hostname
+    pub host: Option<String>,
+    // Case-insensitive prefix, so "failed" matches "Failed (Auth)"
+    pub status: Option<String>,
+    // Unix timestamps, both inclusive
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl HistoryQuery {
+    fn matches(&self, entry: &ConnectionLog) -> bool {
+        self.host
+            .as_ref()
+            .is_none_or(|h| entry.host.to_lowercase().contains(&h.to_lowercase()))
+            && self
+                .status
+                .as_ref()
+                .is_none_or(|s| entry.status.to_lowercase().starts_with(&s.to_lowercase()))
+            && self.since.is_none_or(|t| entry.timestamp >= t)
+            && self.until.is_none_or(|t| entry.timestamp <= t)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryPage {
+    pub entries: Vec<ConnectionLog>,
+    // Matching entries before offset and limit
+    pub total: usize,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn expire_stale(history: &mut [ConnectionLog]) -> bool {
+    let now = now();
+    let mut changed = false;
+    for entry in history
+        .iter_mut()
+        .filter(|e| e.status == CONNECTING && now.saturating_sub(e.timestamp) > STALE_AFTER_SECS)
+    {
+        entry.status = TIMED_OUT.to_string();
+        changed = true;
+    }
+    changed
+}
+
+/// The history oldest first, as stored, with stale attempts closed out.
+pub fn read(app_handle: &AppHandle) -> Result<Vec<ConnectionLog>, String> {
+    let path = get_history_path(app_handle)?;
+    let _lock = config_file::lock(&path)?;
+    let mut history: Vec<ConnectionLog> = config_file::load(app_handle, &path)?.unwrap_or_default();
+    if expire_stale(&mut history) {
+        config_file::write(&path, &history)?;
+        host_stats::invalidate();
+    }
+    Ok(history)
+}
+
+/// Adds an entry, dropping the oldest past the configured cap. Returns the
+/// entry id.
+pub fn append(app_handle: &AppHandle, log: ConnectionLog) -> Result<String, String> {
+    let max_entries = app_handle
+        .state::<AppState>()
+        .settings
+        .get()
+        .history_max_entries;
+    let path = get_history_path(app_handle)?;
+    let _lock = config_file::lock(&path)?;
+    let mut history = read(app_handle)?;
+    let id = log.id.clone();
+    history.push(log);
+    if let Some(max) = max_entries.filter(|max| history.len() > *max) {
+        history.drain(..history.len() - max);
+    }
+    config_file::write(&path, &history)?;
+    host_stats::invalidate();
+    Ok(id)
+}
+
+// Missing entries are ignored, they can be pruned or cleared at any time
+fn update(
+    app_handle: &AppHandle,
+    id: &str,
+    apply: impl FnOnce(&mut ConnectionLog) -> bool,
+) -> Result<(), String> {
+    let path = get_history_path(app_handle)?;
+    let _lock = config_file::lock(&path)?;
+    let mut history = read(app_handle)?;
+    let Some(entry) = history.iter_mut().find(|e| e.id == id) else {
+        return Ok(());
+    };
+    if apply(entry) {
+        config_file::write(&path, &history)?;
+        host_stats::invalidate();
+    }
+    Ok(())
+}
+
+/// The history entry of one connection attempt. Logging is best effort: a
+/// history that can't be written never fails the connection.
+pub struct Attempt {
+    app_handle: AppHandle,
+    id: Option<String>,
+}
+
+impl Attempt {
+    pub fn start(app_handle: &AppHandle, log: ConnectionLog) -> Self {
+        let log = ConnectionLog {
+            status: CONNECTING.to_string(),
+            ..log
+        };
+        let id = append(app_handle, log)
+            .inspect_err(
+                |e| warn!(target = "history", error = %e, "Failed to log connection attempt"),
+            )
+            .ok();
+        Self {
+            app_handle: app_handle.clone(),
+            id,
+        }
+    }
+
+    pub fn id(&self) -> Option<String> {
+        self.id.clone()
+    }
+
+    fn set(&self, apply: impl FnOnce(&mut ConnectionLog)) {
+        let Some(id) = &self.id else {
+            return;
+        };
+        let result = update(&self.app_handle, id, |entry| {
+            apply(entry);
+            true
+        });
+        if let Err(e) = result {
+            warn!(target = "history", entry = %id, error = %e, "Failed to update connection attempt");
+        }
+    }
+
+    pub fn succeed(&self, auth_method: Option<&str>) {
+        self.set(|entry| {
+            entry.status = "Success".to_string();
+            entry.auth_method = auth_method.map(str::to_string);
+        });
+    }
+
+    /// Marks the attempt "Failed (<stage>)" with the error text, and hands
+    /// the error back for the caller to return.
+    pub fn fail(&self, stage: &str, error: impl Into<AppError>) -> AppError {
+        let error = error.into();
+        self.set(|entry| {
+            entry.status = format!("Failed ({})", stage);
+            entry.error = Some(error.message.clone());
+        });
+        error
+    }
+
+    /// Marks the attempt "Blocked (<reason>)", for connections stopped on
+    /// purpose rather than by an error. Hands `error` back like fail.
+    pub fn block(&self, reason: &str, detail: String, error: AppError) -> AppError {
+        self.set(|entry| {
+            entry.status = format!("Blocked ({})", reason);
+            entry.error = Some(detail);
+        });
+        error
+    }
+}
+
+/// Appends a note to an entry's error text, e.g. who overrode a block.
+pub fn add_note(app_handle: &AppHandle, id: &str, note: &str) {
+    let result = update(app_handle, id, |entry| {
+        entry.error = Some(match entry.error.take() {
+            Some(error) => format!("{}; {}", error, note),
+            None => note.to_string(),
+        });
+        true
+    });
+    if let Err(e) = result {
+        warn!(target = "history", entry = %id, error = %e, "Failed to annotate history entry");
+    }
+}
+
+#[tauri::command]
+pub fn query_history(query: HistoryQuery, app_handle: AppHandle) -> Result<HistoryPage, AppError> {
+    let matching: Vec<ConnectionLog> = read(&app_handle)?
+        .into_iter()
+        .rev()
+        .filter(|entry| query.matches(entry))
+        .collect();
+    let total = matching.len();
+    let entries = matching
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+    Ok(HistoryPage { entries, total })
+}
+
+/// Records the duration and disconnect reason on the session's history
+/// entry. Only the first call for an entry has any effect.
+pub fn finish_session(app_handle: &AppHandle, target: &SessionTarget, reason: &str) {
+    let (Some(id), Some(connected_at)) = (&target.history_id, target.connected_at) else {
+        return;
+    };
+    let result = update(app_handle, id, |entry| {
+        if entry.duration_seconds.is_some() {
+            return false;
+        }
+        entry.duration_seconds = Some(connected_at.elapsed().as_secs());
+        entry.disconnect_reason = Some(reason.to_string());
+        true
+    });
+    if let Err(e) = result {
+        warn!(target = "history", entry = %id, error = %e, "Failed to record session end");
+    }
+}
+
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn to_csv(entries: &[ConnectionLog]) -> String {
+    let mut out = String::from(CSV_HEADER);
+    out.push('\n');
+    for entry in entries {
+        let fields = [
+            entry.id.clone(),
+            entry.timestamp.to_string(),
+            entry.host.clone(),
+            entry.port.map(|p| p.to_string()).unwrap_or_default(),
+            entry.username.clone(),
+            entry.protocol.clone().unwrap_or_default(),
+            entry.status.clone(),
+            entry.auth_method.clone().unwrap_or_default(),
+            entry
+                .duration_seconds
+                .map(|d| d.to_string())
+                .unwrap_or_default(),
+            entry.disconnect_reason.clone().unwrap_or_default(),
+            entry.error.clone().unwrap_or_default(),
+            entry.host_id.clone().unwrap_or_default(),
+        ];
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&line.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Writes the entries in the optional date range (Unix timestamps, both
+/// inclusive) to `path`, oldest first. Returns how many were written.
+#[tauri::command]
+pub fn export_history(
+    format: ExportFormat,
+    since: Option<u64>,
+    until: Option<u64>,
+    path: String,
+    app_handle: AppHandle,
+) -> Result<usize, AppError> {
+    let query = HistoryQuery {
+        since,
+        until,
+        ..HistoryQuery::default()
+    };
+    let entries: Vec<ConnectionLog> = read(&app_handle)?
+        .into_iter()
+        .filter(|entry| query.matches(entry))
+        .collect();
+    let content = match format {
+        ExportFormat::Csv => to_csv(&entries),
+        ExportFormat::Json => serde_json::to_string_pretty(&entries)?,
+    };
+    fs::write(&path, content)?;
+    info!(
+        target = "history",
+        count = entries.len(),
+        ?format,
+        "Exported history"
+    );
+    Ok(entries.len())
+}
+
+fn prune(app_handle: &AppHandle, days: u32) -> Result<usize, String> {
+    let cutoff = now().saturating_sub(u64::from(days) * 86_400);
+    let path = get_history_path(app_handle)?;
+    let _lock = config_file::lock(&path)?;
+    let mut history = read(app_handle)?;
+    let before = history.len();
+    history.retain(|entry| entry.timestamp >= cutoff);
+    let removed = before - history.len();
+    if removed > 0 {
+        config_file::write(&path, &history)?;
+        host_stats::invalidate();
+        info!(target = "history", removed, days, "Pruned history");
+    }
+    Ok(removed)
+}
+
+/// Deletes entries older than `days`. Returns how many were removed.
+#[tauri::command]
+pub fn prune_history(days: u32, app_handle: AppHandle) -> Result<usize, AppError> {
+    if days == 0 {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Days must be at least 1",
+        ));
+    }
+    backups::auto_backup("prune-history");
+    Ok(prune(&app_handle, days)?)
+}
+
+/// Applies the retention setting, if any. Called from setup after the
+/// settings are loaded; failures are only logged.
+pub fn apply_retention(app_handle: &AppHandle) {
+    let Some(days) = app_handle
+        .state::<AppState>()
+        .settings
+        .get()
+        .history_retention_days
+    else {
+        return;
+    };
+    if let Err(e) = prune(app_handle, days) {
+        warn!(target = "history", error = %e, "Failed to apply history retention");
+    }
+}
+
+/// The details of an ad-hoc connection as stored in its history entry.
+pub fn redacted(details: &ConnectionDetails) -> ConnectionDetails {
+    ConnectionDetails {
+        password: None,
+        passphrase: None,
+        totp_secret: None,
+        ..details.clone()
+    }
+}
+
+/// Connects again to where a history entry connected: its SavedHost, or the
+/// stored settings of an ad-hoc connection. Secrets that weren't persisted
+/// are passed in after PASSWORD_REQUIRED.
+#[tauri::command]
+pub async fn reconnect_from_history(
+    entry_id: String,
+    password: Option<String>,
+    passphrase: Option<String>,
+    state: State<'_, AppState>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<String, AppError> {
+    let entry = read(&app_handle)?
+        .into_iter()
+        .find(|e| e.id == entry_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "History entry not found"))?;
+    if entry.protocol.as_deref().is_some_and(|p| p != "ssh") {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Only SSH connections can be reconnected from history",
+        ));
+    }
+
+    let (mut details, has_password) = match &entry.host_id {
+        Some(host_id) => {
+            let host = load_saved_hosts(app_handle.clone())?
+                .into_iter()
+                .find(|h| &h.id == host_id)
+                .ok_or(HOST_DELETED)?;
+            (host.details, host.has_password)
+        }
+        // Entries from before snapshots were kept only know where they went
+        None => (
+            entry.details.unwrap_or_else(|| ConnectionDetails {
+                host: entry.host.clone(),
+                port: entry.port,
+                username: entry.username.clone(),
+                ..ConnectionDetails::default()
+            }),
+            false,
+        ),
+    };
+    if password.is_none() && !has_password && details.private_key_path.is_none() {
+        return Err(AppError::new(
+            ErrorKind::PasswordRequired,
+            PASSWORD_REQUIRED,
+        ));
+    }
+    details.password = password.or(details.password);
+    details.passphrase = passphrase.or(details.passphrase);
+
+    info!(target = "history", entry = %entry_id, host = %details.host, "Reconnecting from history");
+    connect_ssh(details, entry.host_id, None, None, None, state, window, app_handle).await
+}
@@ -0,0 +1,129 @@
+// Changes applied to many saved hosts at once.
+//
+// Each command is a single read-modify-write of connections.json, preceded by
+// one automatic backup. Ids that don't match a host are reported back rather
+// than failing the whole operation.
+
+use crate::credentials::{self, SecretKind};
+use crate::error::AppError;
+use crate::{backups, groups, load_saved_hosts, lock_saved_hosts, tags, write_saved_hosts};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BulkResult {
+    pub affected: usize,
+    pub not_found: Vec<String>,
+}
+
+// Unset fields are left alone on every host
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BulkHostChanges {
+    // A group id or top-level name; an empty string removes the hosts from
+    // their group
+    pub group: Option<String>,
+    // Replaces the tags; add_tags and remove_tags apply after it
+    pub tags: Option<Vec<String>>,
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub keepalive_interval: Option<u32>,
+}
+
+#[tauri::command]
+pub fn bulk_delete_hosts(
+    host_ids: Vec<String>,
+    app_handle: AppHandle,
+) -> Result<BulkResult, AppError> {
+    let _lock = lock_saved_hosts(&app_handle)?;
+    let mut hosts = load_saved_hosts(app_handle.clone())?;
+    let not_found: Vec<String> = host_ids
+        .iter()
+        .filter(|id| !hosts.iter().any(|h| &h.id == *id))
+        .cloned()
+        .collect();
+    if not_found.len() == host_ids.len() {
+        return Ok(BulkResult {
+            affected: 0,
+            not_found,
+        });
+    }
+    backups::auto_backup("bulk-delete");
+
+    let before = hosts.len();
+    hosts.retain(|h| !host_ids.contains(&h.id));
+    let affected = before - hosts.len();
+    write_saved_hosts(&app_handle, &hosts)?;
+
+    for host_id in host_ids.iter().filter(|id| !not_found.contains(id)) {
+        for kind in SecretKind::ALL {
+            if let Err(e) = credentials::delete(host_id, kind) {
+                warn!(target = "credentials", host = %host_id, error = %e, "Failed to remove stored secret");
+            }
+        }
+    }
+    info!(target = "hosts", affected, "Deleted hosts");
+    Ok(BulkResult {
+        affected,
+        not_found,
+    })
+}
+
+#[tauri::command]
+pub fn bulk_update_hosts(
+    host_ids: Vec<String>,
+    changes: BulkHostChanges,
+    app_handle: AppHandle,
+) -> Result<BulkResult, AppError> {
+    let group = match changes.group {
+        Some(group) => Some(groups::resolve(&app_handle, Some(group))?),
+        None => None,
+    };
+    let _lock = lock_saved_hosts(&app_handle)?;
+    let mut hosts = load_saved_hosts(app_handle.clone())?;
+    let not_found: Vec<String> = host_ids
+        .iter()
+        .filter(|id| !hosts.iter().any(|h| &h.id == *id))
+        .cloned()
+        .collect();
+    if not_found.len() == host_ids.len() {
+        return Ok(BulkResult {
+            affected: 0,
+            not_found,
+        });
+    }
+    backups::auto_backup("bulk-update");
+
+    let mut affected = 0;
+    for host in hosts.iter_mut().filter(|h| host_ids.contains(&h.id)) {
+        if let Some(group) = &group {
+            host.group = group.clone();
+        }
+        if let Some(replacement) = &changes.tags {
+            host.tags = replacement.clone();
+        }
+        host.tags.extend(changes.add_tags.iter().cloned());
+        host.tags
+            .retain(|t| !changes.remove_tags.iter().any(|r| r.trim() == t));
+        host.tags = tags::normalize(std::mem::take(&mut host.tags));
+        if let Some(port) = changes.port {
+            host.details.port = Some(port);
+        }
+        if let Some(username) = &changes.username {
+            host.details.username = username.clone();
+        }
+        if let Some(keepalive) = changes.keepalive_interval {
+            host.details.keepalive_interval = Some(keepalive);
+        }
+        affected += 1;
+    }
+    write_saved_hosts(&app_handle, &hosts)?;
+    info!(target = "hosts", affected, "Updated hosts");
+    Ok(BulkResult {
+        affected,
+        not_found,
+    })
+}
@@ -0,0 +1,53 @@
+// Filtering saved hosts for the sidebar search.
+
+use crate::error::AppError;
+use crate::{load_saved_hosts, SavedHost};
+use serde::Deserialize;
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HostQuery {
+    // Hosts must carry at least one of these
+    #[serde(default)]
+    pub any_tags: Vec<String>,
+    // Hosts must carry every one of these
+    #[serde(default)]
+    pub all_tags: Vec<String>,
+    // Case-insensitive match on name, host, username and notes
+    pub text: Option<String>,
+}
+
+fn matches(host: &SavedHost, query: &HostQuery, text: Option<&str>) -> bool {
+    let has = |tag: &String| host.tags.contains(tag);
+    if !query.any_tags.is_empty() && !query.any_tags.iter().any(has) {
+        return false;
+    }
+    if !query.all_tags.iter().all(has) {
+        return false;
+    }
+    let Some(text) = text else {
+        return true;
+    };
+    let notes = host.notes.as_deref().unwrap_or_default();
+    [
+        host.name.as_str(),
+        &host.details.host,
+        &host.details.username,
+        notes,
+    ]
+    .iter()
+    .any(|field| field.to_lowercase().contains(text))
+}
+
+#[tauri::command]
+pub fn find_hosts(query: HostQuery, app_handle: AppHandle) -> Result<Vec<SavedHost>, AppError> {
+    let text = query
+        .text
+        .as_deref()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty());
+    Ok(load_saved_hosts(app_handle)?
+        .into_iter()
+        .filter(|host| matches(host, &query, text.as_deref()))
+        .collect())
+}
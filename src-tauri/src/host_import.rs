@@ -0,0 +1,147 @@
+// Shared two-step flow for importing hosts from other tools.
+//
+// Each importer parses its source into SavedHost entries and returns a
+// preview; commit_host_import then writes the hosts the user confirmed.
+// Hosts are matched by name within their group, so re-running an import
+// updates entries instead of duplicating them. Importers put hosts in a group
+// by name, which commit creates if it doesn't exist yet.
+
+use crate::error::AppError;
+use crate::{
+    backups, groups, host_order, load_saved_hosts, lock_saved_hosts, write_saved_hosts, SavedHost,
+};
+use serde::Serialize;
+use tauri::AppHandle;
+use tracing::info;
+use uuid::Uuid;
+
+/// Something in the source that was skipped or only partly imported.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportWarning {
+    pub source: String, // File or session the warning is about
+    pub line: Option<usize>,
+    pub text: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportPreviewEntry {
+    pub host: SavedHost,
+    // Set when a saved host with the same name will be updated
+    pub existing_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportPreview {
+    pub hosts: Vec<ImportPreviewEntry>,
+    pub warnings: Vec<ImportWarning>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportResult {
+    pub created: usize,
+    pub updated: usize,
+}
+
+/// Local account name, used like ssh does when a source has no user.
+pub fn local_username() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+fn same_host(saved: &SavedHost, imported: &SavedHost) -> bool {
+    saved.name == imported.name && saved.group == imported.group
+}
+
+pub fn build_preview(
+    hosts: Vec<SavedHost>,
+    warnings: Vec<ImportWarning>,
+    app_handle: AppHandle,
+) -> Result<ImportPreview, String> {
+    let existing = load_saved_hosts(app_handle.clone())?;
+    let known_groups = groups::load(&app_handle)?;
+    let hosts = hosts
+        .into_iter()
+        .map(|mut host| {
+            // Importers name their group; it is only created on commit
+            if let Some(name) = &host.group {
+                if let Some(group) = known_groups
+                    .iter()
+                    .find(|g| g.parent_id.is_none() && &g.name == name)
+                {
+                    host.group = Some(group.id.clone());
+                }
+            }
+            let existing_id = existing
+                .iter()
+                .find(|h| same_host(h, &host))
+                .map(|h| h.id.clone());
+            if let Some(id) = &existing_id {
+                host.id = id.clone();
+            }
+            ImportPreviewEntry { host, existing_id }
+        })
+        .collect();
+    Ok(ImportPreview { hosts, warnings })
+}
+
+/// Saves confirmed hosts from a preview. Existing hosts only get their
+/// connection fields replaced; tags, settings and secrets stay.
+#[tauri::command]
+pub fn commit_host_import(
+    hosts: Vec<SavedHost>,
+    app_handle: AppHandle,
+) -> Result<ImportResult, AppError> {
+    backups::auto_backup("import");
+    let mut hosts = hosts;
+    for host in hosts.iter_mut() {
+        host.group = groups::resolve(&app_handle, host.group.take())?;
+    }
+    let _lock = lock_saved_hosts(&app_handle)?;
+    let mut saved = load_saved_hosts(app_handle.clone())?;
+    let mut result = ImportResult {
+        created: 0,
+        updated: 0,
+    };
+
+    for imported in hosts {
+        match saved.iter_mut().find(|h| same_host(h, &imported)) {
+            Some(existing) => {
+                let details = &mut existing.details;
+                details.host = imported.details.host;
+                details.port = imported.details.port;
+                details.username = imported.details.username;
+                details.private_key_path = imported.details.private_key_path;
+                details.proxy_jump = imported.details.proxy_jump;
+                result.updated += 1;
+            }
+            None => {
+                let mut host = imported;
+                host.details.password = None;
+                host.details.passphrase = None;
+                host.details.totp_secret = None;
+                host.has_password = false;
+                host.has_passphrase = false;
+                host.has_totp_secret = false;
+                if saved.iter().any(|h| h.id == host.id) {
+                    host.id = Uuid::new_v4().to_string();
+                }
+                host.pinned = false;
+                host.sort_order = host_order::next_sort_order(&saved);
+                saved.push(host);
+                result.created += 1;
+            }
+        }
+    }
+
+    write_saved_hosts(&app_handle, &saved)?;
+
+    info!(
+        target = "host_import",
+        created = result.created,
+        updated = result.updated,
+        "Imported hosts"
+    );
+    Ok(result)
+}
@@ -0,0 +1,61 @@
+// Manual ordering and pinning of saved hosts. load_saved_hosts returns hosts
+// already sorted, pinned first, so the frontend can render them as is.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{load_saved_hosts, lock_saved_hosts, write_saved_hosts, SavedHost};
+use tauri::AppHandle;
+
+pub fn sort_hosts(hosts: &mut [SavedHost]) {
+    hosts.sort_by(|a, b| {
+        b.pinned
+            .cmp(&a.pinned)
+            .then(a.sort_order.cmp(&b.sort_order))
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+}
+
+/// Sort position for a host appended after the existing ones.
+pub fn next_sort_order(hosts: &[SavedHost]) -> u32 {
+    hosts.iter().map(|h| h.sort_order + 1).max().unwrap_or(0)
+}
+
+/// Orders hosts by their position in `host_ids`. Ids of hosts deleted in the
+/// meantime are ignored, and hosts not listed keep their relative order after
+/// the listed ones.
+#[tauri::command]
+pub fn reorder_hosts(
+    host_ids: Vec<String>,
+    app_handle: AppHandle,
+) -> Result<Vec<SavedHost>, AppError> {
+    let _lock = lock_saved_hosts(&app_handle)?;
+    let mut hosts = load_saved_hosts(app_handle.clone())?;
+    hosts.sort_by_key(|h| {
+        (
+            host_ids
+                .iter()
+                .position(|id| *id == h.id)
+                .unwrap_or(usize::MAX),
+            h.sort_order,
+        )
+    });
+    for (i, host) in hosts.iter_mut().enumerate() {
+        host.sort_order = i as u32;
+    }
+    write_saved_hosts(&app_handle, &hosts)?;
+    sort_hosts(&mut hosts);
+    Ok(hosts)
+}
+
+#[tauri::command]
+pub fn toggle_pin(host_id: String, app_handle: AppHandle) -> Result<SavedHost, AppError> {
+    let _lock = lock_saved_hosts(&app_handle)?;
+    let mut hosts = load_saved_hosts(app_handle.clone())?;
+    let host = hosts
+        .iter_mut()
+        .find(|h| h.id == host_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Host not found"))?;
+    host.pinned = !host.pinned;
+    let updated = host.clone();
+    write_saved_hosts(&app_handle, &hosts)?;
+    Ok(updated)
+}
@@ -0,0 +1,94 @@
+// Per-host connection statistics, aggregated from the connection history.
+//
+// Nothing is stored besides the history itself; the aggregate is cached and
+// dropped whenever the history is written or cleared.
+
+use crate::error::AppError;
+use crate::{load_history, ConnectionLog};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::AppHandle;
+
+static CACHE: Mutex<Option<HashMap<String, HostStats>>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HostStats {
+    pub host_id: String,
+    pub total_connections: usize,
+    pub successes: usize,
+    pub failures: usize,
+    pub last_connected_at: Option<u64>, // Unix timestamp of the last success
+    pub last_failure_at: Option<u64>,
+    pub last_failure_reason: Option<String>,
+}
+
+pub fn invalidate() {
+    *CACHE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+// "Failed (Auth)" -> "Auth"; a bare "Failed" has no reason
+fn failure_reason(status: &str) -> Option<String> {
+    status
+        .strip_prefix("Failed (")
+        .and_then(|r| r.strip_suffix(')'))
+        .map(str::to_string)
+}
+
+fn aggregate(history: &[ConnectionLog]) -> HashMap<String, HostStats> {
+    let mut stats: HashMap<String, HostStats> = HashMap::new();
+    for entry in history {
+        let Some(host_id) = &entry.host_id else {
+            continue;
+        };
+        // Only outcomes count, not the "Connecting..." marker
+        let success = entry.status.starts_with("Success");
+        if !success && !entry.status.starts_with("Failed") {
+            continue;
+        }
+        let host = stats.entry(host_id.clone()).or_insert_with(|| HostStats {
+            host_id: host_id.clone(),
+            ..Default::default()
+        });
+        host.total_connections += 1;
+        if success {
+            host.successes += 1;
+            if host.last_connected_at.is_none_or(|t| entry.timestamp > t) {
+                host.last_connected_at = Some(entry.timestamp);
+            }
+        } else {
+            host.failures += 1;
+            if host.last_failure_at.is_none_or(|t| entry.timestamp > t) {
+                host.last_failure_at = Some(entry.timestamp);
+                host.last_failure_reason = failure_reason(&entry.status);
+            }
+        }
+    }
+    stats
+}
+
+/// Stats for the given hosts, or every host with history when none are given.
+#[tauri::command]
+pub fn get_host_stats(
+    host_ids: Option<Vec<String>>,
+    app_handle: AppHandle,
+) -> Result<Vec<HostStats>, AppError> {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if cache.is_none() {
+        *cache = Some(aggregate(&load_history(app_handle)?));
+    }
+    let stats = cache.as_ref().expect("cache filled above");
+
+    Ok(match host_ids {
+        Some(ids) => ids
+            .into_iter()
+            .map(|id| {
+                stats.get(&id).cloned().unwrap_or(HostStats {
+                    host_id: id,
+                    ..Default::default()
+                })
+            })
+            .collect(),
+        None => stats.values().cloned().collect(),
+    })
+}
@@ -0,0 +1,240 @@
+// Queued terminal input.
+//
+// Writing keystrokes straight to the channel meant a remote that stopped
+// reading (Ctrl+S, a saturated link) blocked the command while it held the
+// channel lock, and every later keystroke piled up behind it. Input now goes
+// into a bounded per-session queue and the command returns at once. A writer
+// thread drains the queue in order, retries WouldBlock without holding the
+// lock between attempts and flushes once per batch. It exits after a quiet
+// spell and the next push starts a new one.
+
+use crate::health::SessionHealth;
+use crate::ownership::SessionOwners;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::Window;
+use tracing::warn;
+
+// Queued bytes above which "input-backpressure" is raised
+const HIGH_WATER: usize = 64 * 1024;
+// Queued bytes above which input is refused
+const CAPACITY: usize = 1024 * 1024;
+const RETRY_DELAY: Duration = Duration::from_millis(5);
+const WRITER_IDLE: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize)]
+struct InputBackpressurePayload {
+    session_id: String,
+    queued_bytes: usize,
+    active: bool,
+}
+
+// Where the writer sends input, cloned from the session's transport
+pub enum InputSink {
+    Ssh(Arc<Mutex<ssh2::Channel>>),
+    Serial(Arc<Mutex<Box<dyn serialport::SerialPort>>>),
+    Telnet(Arc<Mutex<TcpStream>>),
+}
+
+impl InputSink {
+    fn write(&self, health: &SessionHealth, data: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Ssh(channel) => health.lock(channel, "channel").write(data),
+            Self::Serial(port) => health.lock(port, "serial port").write(data),
+            Self::Telnet(stream) => health.lock(stream, "telnet stream").write(data),
+        }
+    }
+
+    fn flush(&self, health: &SessionHealth) -> io::Result<()> {
+        match self {
+            Self::Ssh(channel) => health.lock(channel, "channel").flush(),
+            Self::Serial(port) => health.lock(port, "serial port").flush(),
+            Self::Telnet(stream) => health.lock(stream, "telnet stream").flush(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    chunks: VecDeque<Vec<u8>>,
+    // Queued or being written, released once a batch is done
+    bytes: usize,
+    writer: bool,
+    backpressure: bool,
+    closed: bool,
+}
+
+pub struct InputQueue {
+    session_id: String,
+    window: Window,
+    owners: Arc<SessionOwners>,
+    pending: Mutex<Pending>,
+    ready: Condvar,
+}
+
+impl InputQueue {
+    pub fn new(session_id: String, window: Window, owners: Arc<SessionOwners>) -> Self {
+        Self {
+            session_id,
+            window,
+            owners,
+            pending: Mutex::new(Pending::default()),
+            ready: Condvar::new(),
+        }
+    }
+
+    fn pending(&self) -> std::sync::MutexGuard<'_, Pending> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Queues `data` behind earlier input. `sink` is only called when no
+    /// writer is running and one has to be started.
+    pub fn push(
+        self: &Arc<Self>,
+        data: Vec<u8>,
+        sink: impl FnOnce() -> (InputSink, Arc<SessionHealth>),
+    ) -> Result<(), String> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let mut pending = self.pending();
+        if pending.closed {
+            return Err("Session is closed".to_string());
+        }
+        if pending.bytes + data.len() > CAPACITY {
+            warn!(target = "input", session = %self.session_id, queued = pending.bytes, "Input queue full");
+            return Err(format!(
+                "Input queue is full ({} bytes waiting), the remote side is not reading",
+                pending.bytes
+            ));
+        }
+        pending.bytes += data.len();
+        pending.chunks.push_back(data);
+        if pending.bytes > HIGH_WATER && !pending.backpressure {
+            pending.backpressure = true;
+            self.emit_backpressure(pending.bytes, true);
+        }
+        let start = !std::mem::replace(&mut pending.writer, true);
+        drop(pending);
+
+        if start {
+            let (sink, health) = sink();
+            let queue = self.clone();
+            thread::spawn(move || queue.run_writer(sink, &health));
+        } else {
+            self.ready.notify_one();
+        }
+        Ok(())
+    }
+
+    /// Drops queued input and stops the writer.
+    pub fn close(&self) {
+        let mut pending = self.pending();
+        pending.closed = true;
+        pending.chunks.clear();
+        self.ready.notify_all();
+    }
+
+    fn run_writer(&self, sink: InputSink, health: &SessionHealth) {
+        while let Some(batch) = self.next_batch() {
+            let result = self.write_batch(&sink, health, &batch);
+            if let Err(e) = &result {
+                warn!(target = "input", session = %self.session_id, error = %e, "Writing terminal input failed");
+            }
+            self.finish_batch(batch.len(), result.is_err());
+        }
+    }
+
+    // Everything queued so far as one write, or None once the writer should exit
+    fn next_batch(&self) -> Option<Vec<u8>> {
+        let mut pending = self.pending();
+        loop {
+            if pending.closed {
+                pending.writer = false;
+                return None;
+            }
+            if !pending.chunks.is_empty() {
+                let mut batch = Vec::new();
+                for chunk in pending.chunks.drain(..) {
+                    batch.extend_from_slice(&chunk);
+                }
+                return Some(batch);
+            }
+            let (guard, timeout) = self
+                .ready
+                .wait_timeout(pending, WRITER_IDLE)
+                .unwrap_or_else(|e| e.into_inner());
+            pending = guard;
+            if timeout.timed_out() && pending.chunks.is_empty() {
+                pending.writer = false;
+                return None;
+            }
+        }
+    }
+
+    fn write_batch(&self, sink: &InputSink, health: &SessionHealth, data: &[u8]) -> io::Result<()> {
+        let mut written = 0;
+        while written < data.len() {
+            match sink.write(health, &data[written..]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => written += n,
+                Err(e) if should_retry(&e) => self.wait_retry()?,
+                Err(e) => return Err(e),
+            }
+        }
+        loop {
+            match sink.flush(health) {
+                Ok(()) => return Ok(()),
+                Err(e) if should_retry(&e) => self.wait_retry()?,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Backs off before retrying a write the remote isn't ready for
+    fn wait_retry(&self) -> io::Result<()> {
+        if self.pending().closed {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "session closed"));
+        }
+        thread::sleep(RETRY_DELAY);
+        Ok(())
+    }
+
+    fn finish_batch(&self, len: usize, failed: bool) {
+        let mut pending = self.pending();
+        pending.bytes = pending.bytes.saturating_sub(len);
+        // Later input would only make sense after what was lost
+        if failed {
+            pending.chunks.clear();
+            pending.bytes = 0;
+        }
+        if pending.backpressure && pending.bytes <= HIGH_WATER {
+            pending.backpressure = false;
+            self.emit_backpressure(pending.bytes, false);
+        }
+    }
+
+    fn emit_backpressure(&self, queued_bytes: usize, active: bool) {
+        self.owners.emit(
+            &self.window,
+            "input-backpressure",
+            InputBackpressurePayload {
+                session_id: self.session_id.clone(),
+                queued_bytes,
+                active,
+            },
+        );
+    }
+}
+
+fn should_retry(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
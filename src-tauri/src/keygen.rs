@@ -0,0 +1,223 @@
+// SSH key pair generation, so a key doesn't need ssh-keygen.
+//
+// Keys are written in OpenSSH format under ~/.ssh, the private key with 0600
+// permissions and the public key beside it as <name>.pub. Each generated key
+// is added to the keychain so the host editor can pick it right away.
+
+use crate::error::AppError;
+use crate::local_keys::ssh_dir;
+use crate::{config_file, get_keychain_path, load_ssh_keys, SshKeyEntry};
+use serde::{Deserialize, Serialize};
+use ssh_key::private::{EcdsaKeypair, Ed25519Keypair, KeypairData, RsaKeypair};
+use ssh_key::rand_core::OsRng;
+use ssh_key::{EcdsaCurve, HashAlg, LineEnding, PrivateKey};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{async_runtime, AppHandle};
+use tracing::info;
+use uuid::Uuid;
+
+const RSA_BITS: usize = 4096;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum KeyType {
+    #[serde(rename = "ed25519")]
+    Ed25519,
+    #[serde(rename = "rsa-4096")]
+    Rsa4096,
+    // NIST P-256
+    #[serde(rename = "ecdsa")]
+    Ecdsa,
+}
+
+impl KeyType {
+    fn label(self) -> &'static str {
+        match self {
+            KeyType::Ed25519 => "ED25519",
+            KeyType::Rsa4096 => "RSA 4096",
+            KeyType::Ecdsa => "ECDSA P-256",
+        }
+    }
+
+    fn generate(self) -> Result<KeypairData, ssh_key::Error> {
+        Ok(match self {
+            KeyType::Ed25519 => KeypairData::from(Ed25519Keypair::random(&mut OsRng)),
+            KeyType::Rsa4096 => KeypairData::from(RsaKeypair::random(&mut OsRng, RSA_BITS)?),
+            KeyType::Ecdsa => {
+                KeypairData::from(EcdsaKeypair::random(&mut OsRng, EcdsaCurve::NistP256)?)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratedKey {
+    pub public_key: String,
+    pub fingerprint: String, // SHA256:...
+    pub path: String,
+    pub key: SshKeyEntry,
+}
+
+/// Resolves `path` against ~/.ssh. Absolute paths are accepted only inside
+/// it, and ".." is never allowed.
+fn resolve_key_path(ssh_dir: &Path, path: &str) -> Result<PathBuf, String> {
+    let path = Path::new(path.trim());
+    if path.as_os_str().is_empty() || path.file_name().is_none() {
+        return Err("A key file name is required".to_string());
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err("Key path cannot contain \"..\"".to_string());
+    }
+    let resolved = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        ssh_dir.join(path)
+    };
+    if !resolved.starts_with(ssh_dir) {
+        return Err(format!("Keys must be saved under {}", ssh_dir.display()));
+    }
+    Ok(resolved)
+}
+
+fn create_ssh_dir(dir: &Path) -> Result<(), String> {
+    if dir.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700)).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// create_new unless overwriting, so a file appearing meanwhile isn't clobbered
+fn write_key_file(path: &Path, content: &[u8], mode: u32, force: bool) -> Result<(), String> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    // An existing file keeps its mode when truncated
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(mode))
+            .map_err(|e| e.to_string())?;
+    }
+    file.write_all(content)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
+
+fn generate(
+    key_type: KeyType,
+    passphrase: Option<String>,
+    comment: String,
+    path: String,
+    force: bool,
+) -> Result<(PathBuf, String, String), String> {
+    let dir = ssh_dir()?;
+    let private_path = resolve_key_path(&dir, &path)?;
+    let mut public_path = private_path.clone().into_os_string();
+    public_path.push(".pub");
+    let public_path = PathBuf::from(public_path);
+    if !force {
+        for existing in [&private_path, &public_path] {
+            if existing.exists() {
+                return Err(format!(
+                    "{} already exists, choose another name or overwrite it",
+                    existing.display()
+                ));
+            }
+        }
+    }
+
+    let key_data = key_type.generate().map_err(|e| e.to_string())?;
+    let mut key = PrivateKey::new(key_data, comment).map_err(|e| e.to_string())?;
+    let public_key = key.public_key().to_openssh().map_err(|e| e.to_string())?;
+    let fingerprint = key.fingerprint(HashAlg::Sha256).to_string();
+    if let Some(passphrase) = passphrase.filter(|p| !p.is_empty()) {
+        key = key
+            .encrypt(&mut OsRng, passphrase)
+            .map_err(|e| e.to_string())?;
+    }
+    let private_pem = key.to_openssh(LineEnding::LF).map_err(|e| e.to_string())?;
+
+    if let Some(parent) = private_path.parent() {
+        create_ssh_dir(parent)?;
+    }
+    write_key_file(&private_path, private_pem.as_bytes(), 0o600, force)?;
+    write_key_file(
+        &public_path,
+        format!("{}\n", public_key).as_bytes(),
+        0o644,
+        force,
+    )?;
+    Ok((private_path, public_key, fingerprint))
+}
+
+/// Generates a key pair under ~/.ssh and adds it to the keychain. Existing
+/// files are only replaced with `force`.
+#[tauri::command]
+pub async fn generate_ssh_key(
+    key_type: KeyType,
+    passphrase: Option<String>,
+    comment: String,
+    path: String,
+    force: Option<bool>,
+    app_handle: AppHandle,
+) -> Result<GeneratedKey, AppError> {
+    // RSA generation takes a few seconds
+    let (private_path, public_key, fingerprint) = async_runtime::spawn_blocking(move || {
+        generate(key_type, passphrase, comment, path, force.unwrap_or(false))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    let path = private_path.to_string_lossy().to_string();
+
+    let keychain_path = get_keychain_path(&app_handle)?;
+    let _lock = config_file::lock(&keychain_path)?;
+    let mut keys = load_ssh_keys(app_handle.clone())?;
+    // Overwriting a key replaces its keychain entry
+    keys.retain(|k| k.path.as_deref() != Some(path.as_str()));
+    let entry = SshKeyEntry {
+        id: Uuid::new_v4().to_string(),
+        name: private_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        key_type: key_type.label().to_string(),
+        fingerprint: fingerprint.clone(),
+        path: Some(path.clone()),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    };
+    keys.push(entry.clone());
+    config_file::write(&keychain_path, &keys)?;
+
+    info!(target = "keygen", %path, key_type = key_type.label(), "Generated SSH key");
+    Ok(GeneratedKey {
+        public_key,
+        fingerprint,
+        path,
+        key: entry,
+    })
+}
@@ -0,0 +1,585 @@
+// Management of the user's known_hosts files.
+//
+// ~/.ssh/known_hosts unless the known_hosts_files setting lists others, like
+// ssh's UserKnownHostsFile. Entries from all of them are merged, each with
+// the file it came from; keys are only ever added to the first, the primary
+// file. A host can name its own file instead, so hosts with rotating keys
+// stay out of the main one.
+//
+// With HashKnownHosts the hostnames field is "|1|<salt>|<hash>", the
+// HMAC-SHA1 of the hostname keyed with the salt, both base64. Such entries
+// can't be listed by name, but a given hostname can still be checked against
+// them, which is all deleting by hostname needs.
+//
+// Every change writes a timestamped copy of the file next to it first.
+//
+// connect_ssh checks the presented host key here. A key that differs from a
+// stored one of the same type stops the connection until the user accepts
+// the new key with accept_changed_host_key, which rewrites the entry.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{history, AppState};
+use base64::engine::general_purpose::{STANDARD as BASE64, STANDARD_NO_PAD as BASE64_NO_PAD};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use ssh_key::sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, State};
+use tracing::{info, warn};
+
+const HASH_MAGIC: &str = "|1|";
+
+// Keys that were blocked as changed, by "host:port", until accepted. Only a
+// key the user was shown can be accepted.
+static PENDING: Mutex<Option<HashMap<String, PendingKey>>> = Mutex::new(None);
+
+struct PendingKey {
+    key_type: String,
+    blob: Vec<u8>,
+    fingerprint: String,
+    // The files the connection checked, the primary first
+    files: Vec<PathBuf>,
+    history_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostKeyChange {
+    pub host: String,
+    pub port: u16,
+    pub old_key_type: String,
+    pub old_fingerprint: String,
+    pub new_key_type: String,
+    pub new_fingerprint: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnownHostEntry {
+    pub line_number: usize,
+    pub marker: String,
+    pub hostnames: String,
+    pub key_type: String,
+    pub key_preview: String,
+    #[serde(default)]
+    pub hashed: bool,
+    // The known_hosts file the line is in
+    #[serde(default)]
+    pub file: String,
+}
+
+impl KnownHostEntry {
+    // Same line content, wherever the line now sits
+    fn same_key(&self, other: &KnownHostEntry) -> bool {
+        self.marker == other.marker
+            && self.hostnames == other.hostnames
+            && self.key_type == other.key_type
+            && self.key_preview == other.key_preview
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RemovedHostKey {
+    #[serde(flatten)]
+    pub entry: KnownHostEntry,
+    // False when only the hostname was dropped from a list of names
+    pub line_removed: bool,
+}
+
+fn home_dir() -> Result<PathBuf, String> {
+    std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .map(PathBuf::from)
+        .map_err(|_| "Could not find home directory".to_string())
+}
+
+/// The known_hosts files to use, the primary first: the host's own file when
+/// it names one, else the known_hosts_files setting, else ~/.ssh/known_hosts.
+pub fn files(configured: &[String], host_file: Option<&str>) -> Result<Vec<PathBuf>, String> {
+    let home = home_dir()?;
+    let expand = |path: &str| match path.trim().strip_prefix("~/") {
+        Some(rest) => home.join(rest),
+        None => PathBuf::from(path.trim()),
+    };
+    if let Some(file) = host_file.filter(|f| !f.trim().is_empty()) {
+        return Ok(vec![expand(file)]);
+    }
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in configured.iter().filter(|p| !p.trim().is_empty()) {
+        let path = expand(path);
+        if !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    if files.is_empty() {
+        files.push(home.join(".ssh").join("known_hosts"));
+    }
+    Ok(files)
+}
+
+fn configured_files(state: &AppState) -> Result<Vec<PathBuf>, String> {
+    files(&state.settings.get().known_hosts_files, None)
+}
+
+// Format mostly: [marker] hostnames keytype key comment
+fn split_line(line: &str) -> Option<(&str, &str, &str, &str)> {
+    if line.trim().is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let (marker, rest) = match parts.first() {
+        Some(first) if first.starts_with('@') => (*first, &parts[1..]),
+        _ => ("", &parts[..]),
+    };
+    let [hostnames, key_type, key, ..] = rest else {
+        return None;
+    };
+    Some((marker, hostnames, key_type, key))
+}
+
+fn parse_line(file: &Path, line_number: usize, line: &str) -> Option<KnownHostEntry> {
+    let (marker, hostnames, key_type, key) = split_line(line)?;
+
+    let key_preview = if key.len() > 20 {
+        format!("{}...{}", &key[0..10], &key[key.len() - 10..])
+    } else {
+        key.to_string()
+    };
+    Some(KnownHostEntry {
+        line_number, // 1-based index for specific line targeting
+        marker: marker.to_string(),
+        hostnames: hostnames.to_string(),
+        key_type: key_type.to_string(),
+        key_preview,
+        hashed: hostnames.starts_with(HASH_MAGIC),
+        file: file.to_string_lossy().into_owned(),
+    })
+}
+
+/// How ssh writes a host into known_hosts: bare on port 22, otherwise
+/// "[host]:port".
+fn host_pattern(hostname: &str, port: Option<u16>) -> String {
+    match port {
+        Some(port) if port != 22 => format!("[{}]:{}", hostname, port),
+        _ => hostname.to_string(),
+    }
+}
+
+fn hashed_matches(field: &str, pattern: &str) -> bool {
+    let mut parts = field[HASH_MAGIC.len()..].splitn(2, '|');
+    let (Some(salt), Some(hash)) = (parts.next(), parts.next()) else {
+        return false;
+    };
+    let (Ok(salt), Ok(hash)) = (BASE64.decode(salt), BASE64.decode(hash)) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha1>::new_from_slice(&salt) else {
+        return false;
+    };
+    mac.update(pattern.as_bytes());
+    mac.verify_slice(&hash).is_ok()
+}
+
+/// Whether a hostnames field names `pattern`, hashed or as one of a comma
+/// separated list. Hostnames compare case-insensitively, as in ssh.
+fn field_matches(field: &str, pattern: &str) -> bool {
+    if field.starts_with(HASH_MAGIC) {
+        return hashed_matches(field, &pattern.to_lowercase());
+    }
+    field
+        .split(',')
+        .any(|name| name.eq_ignore_ascii_case(pattern))
+}
+
+// A missing file has no entries
+fn read_entries(path: &Path) -> Result<Vec<KnownHostEntry>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| parse_line(path, i + 1, line))
+        .collect())
+}
+
+fn load_all(files: &[PathBuf]) -> Result<Vec<KnownHostEntry>, String> {
+    let mut entries = Vec::new();
+    for path in files {
+        entries.extend(read_entries(path)?);
+    }
+    Ok(entries)
+}
+
+/// The entries of every configured known_hosts file, primary first.
+#[tauri::command]
+pub fn load_known_hosts(state: State<'_, AppState>) -> Result<Vec<KnownHostEntry>, AppError> {
+    Ok(load_all(&configured_files(&state)?)?)
+}
+
+/// The entries for `hostname` (on `port`, 22 when unset), hashed or plain.
+#[tauri::command]
+pub fn match_known_host(
+    hostname: String,
+    port: Option<u16>,
+    state: State<'_, AppState>,
+) -> Result<Vec<KnownHostEntry>, AppError> {
+    let pattern = host_pattern(&hostname, port);
+    Ok(load_all(&configured_files(&state)?)?
+        .into_iter()
+        .filter(|entry| field_matches(&entry.hostnames, &pattern))
+        .collect())
+}
+
+// <file name>.<unix time>.bak beside the file
+fn backup(path: &Path) -> Result<(), String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "known_hosts".to_string());
+    let backup = path.with_file_name(format!("{}.{}.bak", name, timestamp));
+    fs::copy(path, &backup)
+        .map(|_| ())
+        .map_err(|e| format!("Could not back up {}: {}", path.display(), e))
+}
+
+// Writes the file back after a backup, keeping a trailing newline if it had one
+fn write_lines<S: AsRef<str>>(
+    path: &Path,
+    lines: &[S],
+    trailing_newline: bool,
+) -> Result<(), String> {
+    backup(path)?;
+    let mut content = lines
+        .iter()
+        .map(AsRef::as_ref)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if trailing_newline {
+        content.push('\n');
+    }
+    fs::write(path, content).map_err(|e| e.to_string())
+}
+
+/// Deletes a line by number from the entry's file, after checking it still
+/// holds `expected`, the entry as the UI last loaded it. Other tools may have
+/// edited the file since.
+#[tauri::command]
+pub fn delete_known_host_entry(
+    line_number: usize,
+    expected: KnownHostEntry,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let files = configured_files(&state)?;
+    // Entries loaded before files were tracked are from the primary
+    let path = match expected.file.as_str() {
+        "" => files[0].clone(),
+        file => PathBuf::from(file),
+    };
+    if !files.contains(&path) {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            format!("{} is not a configured known_hosts file", path.display()),
+        ));
+    }
+    let content = fs::read_to_string(&path)?;
+    let mut lines: Vec<&str> = content.lines().collect();
+
+    // Converting 1-based line_number back to 0-based index
+    if line_number == 0 || line_number > lines.len() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Invalid line number",
+        ));
+    }
+    let current = parse_line(&path, line_number, lines[line_number - 1]);
+    if !current.is_some_and(|entry| entry.same_key(&expected)) {
+        return Err("known_hosts changed since it was loaded, reload and try again".into());
+    }
+    lines.remove(line_number - 1);
+    Ok(write_lines(&path, &lines, content.ends_with('\n'))?)
+}
+
+// The line without `pattern` in its list of names, None when no name is left
+fn without_name(line: &str, entry: &KnownHostEntry, pattern: &str) -> Option<String> {
+    let names: Vec<&str> = entry
+        .hostnames
+        .split(',')
+        .filter(|name| !name.eq_ignore_ascii_case(pattern))
+        .collect();
+    if names.is_empty() {
+        return None;
+    }
+    // Only the hostnames field is replaced, spacing and comment stay as they were
+    let start = line.find(&entry.hostnames)?;
+    Some(format!(
+        "{}{}{}",
+        &line[..start],
+        names.join(","),
+        &line[start + entry.hostnames.len()..]
+    ))
+}
+
+/// Removes `hostname` (on `port`, 22 when unset) from every configured
+/// known_hosts file. Hashed lines and lines naming only this host are
+/// dropped; from a list of names just the hostname is removed. Returns what
+/// was removed.
+#[tauri::command]
+pub fn delete_known_host_for_host(
+    hostname: String,
+    port: Option<u16>,
+    state: State<'_, AppState>,
+) -> Result<Vec<RemovedHostKey>, AppError> {
+    let pattern = host_pattern(&hostname, port);
+    let mut removed = Vec::new();
+    for path in configured_files(&state)? {
+        removed.extend(delete_host_from(&path, &pattern)?);
+    }
+    Ok(removed)
+}
+
+fn delete_host_from(path: &Path, pattern: &str) -> Result<Vec<RemovedHostKey>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut kept: Vec<String> = Vec::new();
+    let mut removed = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let entry = match parse_line(path, i + 1, line) {
+            Some(entry) if field_matches(&entry.hostnames, pattern) => entry,
+            _ => {
+                kept.push(line.to_string());
+                continue;
+            }
+        };
+        let rewritten = if entry.hashed {
+            None
+        } else {
+            without_name(line, &entry, pattern)
+        };
+        let line_removed = rewritten.is_none();
+        kept.extend(rewritten);
+        removed.push(RemovedHostKey {
+            entry,
+            line_removed,
+        });
+    }
+    if !removed.is_empty() {
+        write_lines(path, &kept, content.ends_with('\n'))?;
+        info!(target = "known_hosts", host = %pattern, file = %path.display(), removed = removed.len(), "Removed known host keys");
+    }
+    Ok(removed)
+}
+
+/// SHA256 fingerprint of a key blob, as ssh prints it.
+fn fingerprint(blob: &[u8]) -> String {
+    format!("SHA256:{}", BASE64_NO_PAD.encode(Sha256::digest(blob)))
+}
+
+// A key blob starts with its type as an SSH string
+fn blob_key_type(blob: &[u8]) -> Option<&str> {
+    let len = u32::from_be_bytes(blob.get(..4)?.try_into().ok()?) as usize;
+    std::str::from_utf8(blob.get(4..4 + len)?).ok()
+}
+
+fn pending_key(host: &str, port: u16) -> String {
+    format!("{}:{}", host, port)
+}
+
+/// Compares the key a server presented with the given known_hosts files.
+/// Returns the change when a stored key of the same type differs and none in
+/// any file matches. Unknown hosts and unreadable files are not changes.
+pub fn check_host_key(
+    files: &[PathBuf],
+    host: &str,
+    port: u16,
+    blob: &[u8],
+) -> Option<HostKeyChange> {
+    let key_type = blob_key_type(blob)?;
+    let pattern = host_pattern(host, Some(port));
+    let mut old = None;
+    for path in files {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                if path.exists() {
+                    warn!(target = "known_hosts", file = %path.display(), error = %e, "Could not read known_hosts to check the host key");
+                }
+                continue;
+            }
+        };
+        for (marker, hostnames, stored_type, key) in content.lines().filter_map(split_line) {
+            // Revoked keys and CAs are not host keys
+            if !marker.is_empty() || stored_type != key_type || !field_matches(hostnames, &pattern)
+            {
+                continue;
+            }
+            let Ok(stored) = BASE64.decode(key) else {
+                continue;
+            };
+            if stored == blob {
+                return None;
+            }
+            old.get_or_insert(stored);
+        }
+    }
+    old.map(|old| HostKeyChange {
+        host: host.to_string(),
+        port,
+        old_key_type: key_type.to_string(),
+        old_fingerprint: fingerprint(&old),
+        new_key_type: key_type.to_string(),
+        new_fingerprint: fingerprint(blob),
+    })
+}
+
+/// Remembers a blocked key so accept_changed_host_key can store it.
+pub fn hold_changed_key(
+    change: &HostKeyChange,
+    blob: &[u8],
+    files: Vec<PathBuf>,
+    history_id: Option<String>,
+) {
+    let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    pending.get_or_insert_with(HashMap::new).insert(
+        pending_key(&change.host, change.port),
+        PendingKey {
+            key_type: change.new_key_type.clone(),
+            blob: blob.to_vec(),
+            fingerprint: change.new_fingerprint.clone(),
+            files,
+            history_id,
+        },
+    );
+}
+
+/// Trusts the changed key connect_ssh last blocked for the host. Its old
+/// entries of that key type in the primary file are replaced: hashed lines
+/// get the new key in place, plain ones lose the hostname and a new line is
+/// appended. Other files are left alone; the new key matching is enough. The
+/// override is noted on the blocked history entry.
+#[tauri::command]
+pub fn accept_changed_host_key(
+    hostname: String,
+    port: Option<u16>,
+    fingerprint: String,
+    app_handle: AppHandle,
+) -> Result<(), AppError> {
+    let port = port.unwrap_or(22);
+    let key = pending_key(&hostname, port);
+    let held = {
+        let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+        let held = pending.as_mut().and_then(|p| p.remove(&key));
+        match held {
+            Some(held) if held.fingerprint == fingerprint => held,
+            Some(held) => {
+                pending.get_or_insert_with(HashMap::new).insert(key, held);
+                return Err(AppError::new(
+                    ErrorKind::HostKeyChanged,
+                    "The host key changed again, reconnect to review it",
+                ));
+            }
+            None => {
+                return Err(AppError::new(
+                    ErrorKind::NotFound,
+                    "No changed host key is waiting for this host",
+                ))
+            }
+        }
+    };
+
+    let path = &held.files[0];
+    let pattern = host_pattern(&hostname, Some(port));
+    let content = fs::read_to_string(path).unwrap_or_default();
+    let new_key = BASE64.encode(&held.blob);
+    let mut lines: Vec<String> = Vec::new();
+    let mut rewritten_hashed = false;
+    for (i, line) in content.lines().enumerate() {
+        let entry = match parse_line(path, i + 1, line) {
+            Some(entry)
+                if entry.marker.is_empty()
+                    && entry.key_type == held.key_type
+                    && field_matches(&entry.hostnames, &pattern) =>
+            {
+                entry
+            }
+            _ => {
+                lines.push(line.to_string());
+                continue;
+            }
+        };
+        if entry.hashed {
+            lines.push(format!("{} {} {}", entry.hostnames, held.key_type, new_key));
+            rewritten_hashed = true;
+        } else {
+            lines.extend(without_name(line, &entry, &pattern));
+        }
+    }
+    if !rewritten_hashed {
+        lines.push(format!("{} {} {}", pattern, held.key_type, new_key));
+    }
+    if path.exists() {
+        write_lines(path, &lines, true)?;
+    } else {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, lines.join("\n") + "\n")?;
+    }
+
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown user".to_string());
+    if let Some(id) = &held.history_id {
+        history::add_note(
+            &app_handle,
+            id,
+            &format!("new key {} accepted by {}", fingerprint, user),
+        );
+    }
+    warn!(target = "known_hosts", host = %pattern, file = %path.display(), %fingerprint, %user, "Accepted changed host key");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Written by `ssh-keygen -H` for example.com and [example.com]:2222
+    const HASHED_DEFAULT_PORT: &str =
+        "|1|SyTy+vonb6AI4soEhrscCOGSdFg=|YTtfffrv9rvrIiCkHsI9owEFNyc=";
+    const HASHED_OTHER_PORT: &str = "|1|zptt9W4xAdsfTrBQcfigFwNyDo8=|CNY9f2CYBLpYFvje3oFGfn95Xi0=";
+
+    #[test]
+    fn hashed_entries_match_their_host_only() {
+        let default_port = host_pattern("example.com", Some(22));
+        let other_port = host_pattern("example.com", Some(2222));
+        assert!(hashed_matches(HASHED_DEFAULT_PORT, &default_port));
+        assert!(!hashed_matches(HASHED_DEFAULT_PORT, &other_port));
+        assert!(hashed_matches(HASHED_OTHER_PORT, &other_port));
+        assert!(!hashed_matches(HASHED_OTHER_PORT, &default_port));
+        assert!(!hashed_matches(HASHED_DEFAULT_PORT, "example.org"));
+    }
+
+    #[test]
+    fn hashed_entries_compare_case_insensitively() {
+        assert!(field_matches(HASHED_DEFAULT_PORT, "Example.COM"));
+        assert!(field_matches(HASHED_OTHER_PORT, "[EXAMPLE.com]:2222"));
+    }
+
+    #[test]
+    fn malformed_hashed_entries_never_match() {
+        assert!(!hashed_matches("|1|", "example.com"));
+        assert!(!hashed_matches("|1|not base64|also not", "example.com"));
+    }
+}
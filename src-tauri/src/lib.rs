@@ -1,39 +1,397 @@
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
-use ssh2::{Session, Sftp};
-use std::fs::{self, File};
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use serde_json::{json, Value};
+use ssh2::{OpenFlags, OpenType, Session, Sftp};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tauri::async_runtime;
-use tauri::{AppHandle, Emitter, State, Window};
+use tauri::{AppHandle, Emitter, Manager, State, Window};
 use thiserror::Error;
 use tracing::{error, info, warn};
-use tracing_subscriber::FmtSubscriber;
 use uuid::Uuid;
 
+mod activity;
+mod agent;
+mod app_paths;
+mod audit;
+mod backups;
+mod bastion;
+mod bundle;
+mod channel_session;
+mod charset;
+mod command_guard;
+mod completions;
+mod config_file;
+mod connect_limit;
+mod connect_timeline;
+mod credentials;
+mod crontab;
+mod crypto;
+mod diff;
+mod discovery;
+mod docker;
+mod duplicate;
+mod echo_filter;
+mod environment;
+mod fetch;
+mod error;
+mod grep;
+mod groups;
+mod health;
+mod history;
+mod host_bulk;
+mod host_filter;
+mod host_import;
+mod host_order;
+mod host_stats;
+mod input;
+mod keygen;
+mod known_hosts;
+mod listing_cache;
+mod local_keys;
+mod local_shells;
+mod logging;
+mod logs;
+mod migrations;
+mod monitor;
+mod multi_push;
+mod notify;
+mod osc;
+mod output;
+mod ownership;
+mod preview;
+mod processes;
+mod progress;
+mod putty;
+mod reachability;
+mod read_only;
+mod readiness;
+mod reattach;
+mod remote_modes;
+mod remote_walk;
+mod resize;
+mod retry;
+mod schedules;
+mod search;
+mod serial;
+mod session_meta;
+mod settings;
+mod shell_integration;
+mod shutdown;
+mod sftp_ops;
+mod side_channel;
+mod stats;
+mod systemd;
+mod snippet_folders;
+mod snippet_pack;
+mod snippets;
+mod ssh_config;
+mod tags;
+mod telnet;
+mod termius;
+mod totp;
+mod transfer_events;
+mod transfer_jobs;
+mod tray;
+mod tunnels;
+mod triggers;
+mod vault;
+mod wol;
+mod zmodem;
+
+use audit::AuditOperation;
+use charset::SessionCharset;
+use command_guard::CommandGuard;
+use connect_limit::{ConnectLimiter, Slot};
+use connect_timeline::{ConnectTimeline, Phase, Timeline};
+use credentials::SecretKind;
+use echo_filter::EchoFilter;
+use error::{AppError, ErrorKind};
+use health::SessionHealth;
+use input::{InputQueue, InputSink};
+use listing_cache::ListingCache;
+use notify::CommandNotifier;
+use activity::{ActivityInfo, SessionActivity};
+use output::{OutputBatchConfig, OutputBatchSettings, OutputFlow, OutputPipeline, ReaderContext, Scrollback};
+use progress::ProgressReporter;
+use read_only::ReadOnly;
+use remote_modes::ModeOverride;
+use session_meta::{SessionLabel, SessionMeta};
+use settings::SettingsStore;
+use readiness::SocketReadiness;
+use resize::ResizeQueue;
+use retry::RetryPolicy;
+use shell_integration::{CommandRecord, CommandTracker};
+use shutdown::{ReaderShutdown, CLOSE_TIMEOUT};
+use side_channel::{ExecOutput, ExecPool, SideChannelMetrics};
+use stats::SessionStats;
+use transfer_events::Transfer;
+use transfer_jobs::{JobSpec, TransferDirection};
+use ownership::SessionOwners;
+use triggers::{StartupCommand, StartupSequence, StartupStatus, SudoAutofill, SudoAutofillConfig};
+use zmodem::{ZmodemCommand, ZmodemControl};
+
+pub enum SessionTransport {
+    Ssh {
+        channel: Arc<Mutex<ssh2::Channel>>,
+        session: Arc<Mutex<Session>>,
+        // Interrupts the reader while it waits for socket readiness
+        waker: Arc<mio::Waker>,
+        // Clone of the TCP socket libssh2 owns, shut down to force a close
+        socket: TcpStream,
+        // A channel opened on another session's connection (container
+        // shells), which a forced close must not cut
+        shared: bool,
+        // The pooled jump host connection it runs through, see bastion
+        bastion: Option<Arc<bastion::Lease>>,
+    },
+    Serial {
+        port: Arc<Mutex<Box<dyn serialport::SerialPort>>>,
+    },
+    Telnet {
+        stream: Arc<Mutex<TcpStream>>,
+        telnet: Arc<Mutex<telnet::TelnetState>>,
+    },
+}
+
+// Where a session is connected, for display and snippet variables
+#[derive(Debug, Clone, Default)]
+pub struct SessionTarget {
+    pub host: String,
+    pub username: String,
+    // History entry completed by history::finish_session
+    pub history_id: Option<String>,
+    pub connected_at: Option<Instant>,
+    // The saved host it was opened from
+    pub host_id: Option<String>,
+    // The host's remote_modes overrides
+    pub modes: Option<ModeOverride>,
+    // How the SSH connection was made, see connect_timeline
+    pub timeline: Option<Arc<ConnectTimeline>>,
+}
+
 pub struct SessionState {
-    pub channel: Arc<Mutex<ssh2::Channel>>,
-    pub session: Arc<Mutex<Session>>,
+    pub transport: SessionTransport,
+    pub target: SessionTarget,
     pub sftp: Arc<Mutex<Option<Sftp>>>,
+    pub cwd: Arc<Mutex<Option<String>>>,
+    pub commands: Arc<Mutex<CommandTracker>>,
+    pub zmodem: Arc<ZmodemControl>,
+    pub flow: Arc<OutputFlow>,
+    pub startup: Arc<Mutex<Option<StartupStatus>>>,
+    pub scrollback: Arc<Mutex<Scrollback>>,
+    pub activity: Arc<SessionActivity>,
+    pub charset: Arc<SessionCharset>,
+    pub exec_pool: Arc<ExecPool>,
+    // Stops and joins the reader thread on close
+    pub shutdown: Arc<ReaderShutdown>,
+    // Counts poisoned locks recovered by SessionState::lock
+    pub health: Arc<SessionHealth>,
+    // Coalesces resize_terminal calls
+    pub resize: Arc<ResizeQueue>,
+    // Terminal input waiting for the writer thread
+    pub input: Arc<InputQueue>,
+    // Traffic counters for get_session_stats
+    pub stats: Arc<SessionStats>,
+    // Windows allowed to use the session
+    pub owners: Arc<SessionOwners>,
+    // Armed by arm_notification
+    pub notify: Arc<CommandNotifier>,
+    // Refuses input and SFTP changes while on
+    pub read_only: Arc<ReadOnly>,
+    // Holds back dangerous commands until confirmed
+    pub guard: Arc<CommandGuard>,
+    // Directory listings, see listing_cache
+    pub listings: Arc<ListingCache>,
+    // Title and color shown for the session
+    pub meta: Arc<SessionMeta>,
+}
+
+// What differs between transports when registering a session; everything
+// else in SessionState starts out the same
+pub struct SessionSetup {
+    pub transport: SessionTransport,
+    pub target: SessionTarget,
+    pub meta: Arc<SessionMeta>,
+    pub encoding: &'static encoding_rs::Encoding,
+    pub scrollback_limit: usize,
+    // Per-host idle overrides, see SessionActivity
+    pub idle_after_secs: Option<u64>,
+    pub disconnect_after_secs: Option<u64>,
+    pub read_only: bool,
+    pub guard_exempt: bool,
+    pub startup: Option<StartupStatus>,
+}
+
+impl SessionTransport {
+    fn protocol(&self) -> &'static str {
+        match self {
+            SessionTransport::Ssh { .. } => "ssh",
+            SessionTransport::Serial { .. } => "serial",
+            SessionTransport::Telnet { .. } => "telnet",
+        }
+    }
+}
+
+impl SessionState {
+    /// Builds the state for a session owned by `window`.
+    pub fn new(session_id: Uuid, window: &Window, setup: SessionSetup) -> Self {
+        let owners = Arc::new(SessionOwners::new(window.label()));
+        Self {
+            transport: setup.transport,
+            notify: Arc::new(CommandNotifier::new(&setup.target.host, window.clone())),
+            target: setup.target,
+            sftp: Arc::new(Mutex::new(None)),
+            cwd: Arc::new(Mutex::new(None)),
+            commands: Arc::new(Mutex::new(CommandTracker::default())),
+            zmodem: Arc::new(ZmodemControl::default()),
+            flow: Arc::new(OutputFlow::default()),
+            startup: Arc::new(Mutex::new(setup.startup)),
+            scrollback: Arc::new(Mutex::new(Scrollback::new(setup.scrollback_limit))),
+            activity: Arc::new(SessionActivity::new(setup.idle_after_secs, setup.disconnect_after_secs)),
+            charset: Arc::new(SessionCharset::new(setup.encoding)),
+            exec_pool: Arc::new(ExecPool::default()),
+            shutdown: Arc::new(ReaderShutdown::default()),
+            health: Arc::new(SessionHealth::new(session_id.to_string(), window.clone(), owners.clone())),
+            resize: Arc::new(ResizeQueue::default()),
+            input: Arc::new(InputQueue::new(session_id.to_string(), window.clone(), owners.clone())),
+            stats: Arc::new(SessionStats::default()),
+            owners,
+            read_only: Arc::new(ReadOnly::new(setup.read_only)),
+            guard: Arc::new(CommandGuard::new(setup.guard_exempt)),
+            listings: Arc::new(ListingCache::default()),
+            meta: setup.meta,
+        }
+    }
+
+    /// The shared state the session's reader thread works with.
+    pub fn reader_context(&self, session_id: Uuid, window: &Window) -> ReaderContext {
+        ReaderContext {
+            window: window.clone(),
+            session_id: session_id.to_string(),
+            cwd: self.cwd.clone(),
+            commands: self.commands.clone(),
+            zmodem: self.zmodem.clone(),
+            flow: self.flow.clone(),
+            scrollback: self.scrollback.clone(),
+            activity: self.activity.clone(),
+            charset: self.charset.clone(),
+            stats: self.stats.clone(),
+            owners: self.owners.clone(),
+            notify: self.notify.clone(),
+        }
+    }
+
+    // SFTP and exec features are only available on SSH sessions
+    fn ssh_session(&self) -> Option<&Arc<Mutex<Session>>> {
+        match &self.transport {
+            SessionTransport::Ssh { session, .. } => Some(session),
+            _ => None,
+        }
+    }
+
+    /// Locks one of the session's mutexes, recovering it if poisoned.
+    pub fn lock<'a, T>(&self, mutex: &'a Mutex<T>, what: &str) -> MutexGuard<'a, T> {
+        self.health.lock(mutex, what)
+    }
+
+    // Cuts the connection under a close that stalled, failing whatever
+    // blocking call holds the transport
+    fn force_close(&self) {
+        match &self.transport {
+            SessionTransport::Ssh { socket, shared: false, .. } => {
+                let _ = socket.shutdown(std::net::Shutdown::Both);
+            }
+            SessionTransport::Ssh { shared: true, .. } => {}
+            SessionTransport::Telnet { stream, .. } => {
+                if let Ok(stream) = stream.try_lock() {
+                    let _ = stream.shutdown(std::net::Shutdown::Both);
+                }
+            }
+            SessionTransport::Serial { .. } => {}
+        }
+    }
+
+    fn apply_resize(&self, cols: u32, rows: u32) -> Result<(), String> {
+        match &self.transport {
+            SessionTransport::Ssh { channel, .. } => self
+                .lock(channel, "channel")
+                .request_pty_size(cols, rows, None, None)
+                .map_err(|e| e.to_string()),
+            SessionTransport::Telnet { stream, telnet } => {
+                let update = self.lock(telnet, "telnet state").resize(cols, rows);
+                if let Some(message) = update {
+                    let mut stream = self.lock(stream, "telnet stream");
+                    stream.write_all(&message).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            }
+            // Serial consoles have no window size to negotiate
+            SessionTransport::Serial { .. } => Ok(()),
+        }
+    }
+
+    // Queues input for the writer thread, returning without waiting for
+    // the remote to accept it
+    fn write_input(&self, data: &[u8]) -> Result<(), String> {
+        self.activity.touch_input();
+        let encoded = self.charset.encode_input(data);
+        let data = encoded.as_deref().unwrap_or(data);
+        let data = match &self.transport {
+            SessionTransport::Telnet { .. } => telnet::encode_input(data),
+            _ => data.to_vec(),
+        };
+        self.stats.add_input(data.len());
+        self.input.push(data, || (self.input_sink(), self.health.clone()))
+    }
+
+    // Drops the SFTP handle so the next ensure_sftp opens a fresh channel
+    fn reset_sftp(&self) {
+        *self.lock(&self.sftp, "sftp") = None;
+    }
+
+    fn input_sink(&self) -> InputSink {
+        match &self.transport {
+            SessionTransport::Ssh { channel, .. } => InputSink::Ssh(channel.clone()),
+            SessionTransport::Serial { port } => InputSink::Serial(port.clone()),
+            SessionTransport::Telnet { stream, .. } => InputSink::Telnet(stream.clone()),
+        }
+    }
 }
 
 pub struct AppState {
     pub sessions: Arc<DashMap<Uuid, SessionState>>,
+    pub output_batching: Arc<OutputBatchSettings>,
+    // Scrollback size for new sessions, in bytes
+    pub scrollback_limit: Arc<AtomicUsize>,
+    pub settings: Arc<SettingsStore>,
+    pub connect_limiter: Arc<ConnectLimiter>,
+    pub scheduler: Arc<schedules::Scheduler>,
+    pub bastions: Arc<bastion::BastionPool>,
 }
 
 impl Default for AppState {
     fn default() -> Self {
         Self {
             sessions: Arc::new(DashMap::new()),
+            output_batching: Arc::new(OutputBatchSettings::default()),
+            scrollback_limit: Arc::new(AtomicUsize::new(output::DEFAULT_SCROLLBACK_BYTES)),
+            settings: Arc::new(SettingsStore::default()),
+            connect_limiter: Arc::new(ConnectLimiter::default()),
+            scheduler: Arc::new(schedules::Scheduler::default()),
+            bastions: Arc::new(bastion::BastionPool::default()),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct SftpFile {
     pub name: String,
     pub is_dir: bool,
@@ -48,10 +406,45 @@ pub struct ConnectionLog {
     pub host: String,
     pub username: String,
     pub timestamp: u64, // Unix timestamp
-    pub status: String, // "Success" or "Failed"
+    pub status: String, // "Connecting...", "Success" or "Failed (<stage>)"
+    pub protocol: Option<String>, // "ssh" or "telnet", missing on older entries
+    pub host_id: Option<String>, // Set when connecting to a SavedHost
+    // The fields below are missing on older entries
+    pub port: Option<u16>,
+    pub auth_method: Option<String>, // "password" or "publickey"
+    // Filled in when the session closes
+    pub duration_seconds: Option<u64>,
+    pub disconnect_reason: Option<String>,
+    // Why a failed attempt failed
+    pub error: Option<String>,
+    // Ad-hoc connections keep their settings, minus secrets, for reconnecting
+    pub details: Option<ConnectionDetails>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ConnectionLog {
+    pub fn new(host: &str, username: &str, protocol: &str) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            host: host.to_string(),
+            username: username.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            status: history::CONNECTING.to_string(),
+            protocol: Some(protocol.to_string()),
+            host_id: None,
+            port: None,
+            auth_method: None,
+            duration_seconds: None,
+            disconnect_reason: None,
+            error: None,
+            details: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ConnectionDetails {
     pub host: String,
     pub port: Option<u16>,
@@ -60,11 +453,48 @@ pub struct ConnectionDetails {
     #[serde(rename = "private_key_path")]
     pub private_key_path: Option<String>,
     pub passphrase: Option<String>,
+    // "agent" signs in with the SSH agent; otherwise the key or password
     #[serde(rename = "authMethod")]
-    #[allow(dead_code)]
     pub auth_method: Option<String>,
     pub keepalive_interval: Option<u32>,
     pub timeout: Option<u32>,
+    pub env: Option<HashMap<String, String>>,
+    // Typed into the shell once it starts, echoed like typed input
+    pub initial_command: Option<String>,
+    // Keeps that echo out of the output and scrollback, best effort
+    pub hide_initial_command: Option<bool>,
+    // Opt-in, answers sudo prompts with the stored password
+    pub sudo_autofill: Option<SudoAutofillConfig>,
+    // Typed into the shell once the first prompt shows up
+    pub startup_commands: Option<Vec<StartupCommand>>,
+    // Override the global idle thresholds for this host, 0 disables
+    pub idle_timeout_secs: Option<u64>,
+    pub idle_disconnect_secs: Option<u64>,
+    // Remote charset label, e.g. "iso-8859-1" or "gbk"; UTF-8 when unset
+    pub charset: Option<String>,
+    // Jump host spec ("user@host:port"), as imported from ssh config
+    pub proxy_jump: Option<String>,
+    // Checked instead of the known_hosts_files setting, and where accepted
+    // keys go
+    pub known_hosts_file: Option<String>,
+    // Opens the session read-only, see read_only
+    pub read_only: Option<bool>,
+    // Exempt from the dangerous command guard
+    pub skip_command_guard: Option<bool>,
+    // Base32; moved to the credential store on save, like the password
+    pub totp_secret: Option<String>,
+    // Answers verification code prompts with the TOTP code, see totp
+    pub totp_autofill: Option<bool>,
+    // Overrides parts of the remote_modes setting for this host
+    pub remote_modes: Option<ModeOverride>,
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -72,8 +502,36 @@ pub struct SavedHost {
     pub id: String,
     pub name: String,
     pub group: Option<String>,
-    pub tags: Option<Vec<String>>,
+    // Older files may have no tags or an explicit null
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub tags: Vec<String>,
     pub details: ConnectionDetails,
+    // Secrets are kept in the credential store, these only say they exist
+    #[serde(default)]
+    pub has_password: bool,
+    #[serde(default)]
+    pub has_passphrase: bool,
+    #[serde(default)]
+    pub has_totp_secret: bool,
+    // Pinned hosts come first, then by sort_order, then by name
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub sort_order: u32,
+    pub notes: Option<String>, // Markdown
+    pub color: Option<String>, // Label color, e.g. "#e11d48"
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub metadata: HashMap<String, String>,
+    // For wake_host; the broadcast address defaults to 255.255.255.255
+    #[serde(default)]
+    pub mac_address: Option<String>,
+    #[serde(default)]
+    pub broadcast_address: Option<String>,
+    // Left out of reachability monitoring
+    #[serde(default)]
+    pub reachability_disabled: bool,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub tunnels: Vec<tunnels::SavedTunnel>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -81,15 +539,20 @@ pub struct Snippet {
     pub id: String,
     pub name: String,
     pub command: String,
-}
-
-#[derive(Serialize)]
-pub struct KnownHostEntry {
-    pub line_number: usize,
-    pub marker: String,
-    pub hostnames: String,
-    pub key_type: String,
-    pub key_preview: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub tags: Vec<String>,
+    // Folder id, None for the root
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub sort_order: u32,
+    // Hosts and groups the snippet is meant for; both empty means everywhere
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub host_ids: Vec<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub group_ids: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -102,6 +565,21 @@ pub struct SshKeyEntry {
     pub created_at: u64,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub session_id: String,
+    pub protocol: String,
+    pub cwd: Option<String>,
+    pub output_paused: bool,
+    pub startup: Option<StartupStatus>,
+    // Set after repeated poisoned locks, reconnecting is advised
+    pub degraded: bool,
+    pub read_only: bool,
+    // The jump host connection the session runs through
+    pub bastion: Option<bastion::BastionInfo>,
+    pub connect_timeline: Option<ConnectTimeline>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 struct TerminalOutputPayload {
     session_id: String,
@@ -109,11 +587,85 @@ struct TerminalOutputPayload {
 }
 
 #[derive(Debug, Clone, Serialize)]
-struct TransferProgressPayload {
+struct TerminalBellPayload {
+    session_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TerminalTitlePayload {
     session_id: String,
-    file_path: String,
-    transferred_bytes: u64,
-    total_bytes: u64,
+    title: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CwdChangedPayload {
+    session_id: String,
+    cwd: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CommandFinishedPayload {
+    session_id: String,
+    record: CommandRecord,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SessionClosedPayload {
+    session_id: String,
+    reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SessionOpenedPayload {
+    session_id: String,
+}
+
+// Lets the tray and other windows know a session was added
+pub(crate) fn emit_session_opened(window: &Window, session_id: &str) {
+    let _ = window.emit(
+        "session-opened",
+        SessionOpenedPayload {
+            session_id: session_id.to_string(),
+        },
+    );
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct OutputThrottledPayload {
+    session_id: String,
+    bytes_per_second: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SessionIdlePayload {
+    session_id: String,
+    idle_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SessionIdleWarningPayload {
+    session_id: String,
+    disconnect_in_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ZmodemDetectedPayload {
+    session_id: String,
+    direction: String, // "receive" (remote ran sz) or "send" (remote ran rz)
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ZmodemFinishedPayload {
+    session_id: String,
+    success: bool,
+    files: Vec<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigRecoveredPayload {
+    pub file: String,
+    pub error: String, // Why the main file could not be loaded
 }
 
 #[derive(Debug, Error)]
@@ -124,10 +676,45 @@ enum TransferError {
     SftpNotInitialized,
     #[error("Invalid session identifier")]
     InvalidSessionId,
+    // SFTP and remote file errors, classified by their codes
+    #[error("{0}")]
+    Remote(AppError),
     #[error("{0}")]
     Io(String),
 }
 
+impl TransferError {
+    // Worth another attempt: the connection or channel failed, not the
+    // request itself
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::SftpNotInitialized => true,
+            Self::Remote(error) => !matches!(
+                error.kind,
+                ErrorKind::PermissionDenied
+                    | ErrorKind::NotFound
+                    | ErrorKind::AlreadyExists
+                    | ErrorKind::InvalidInput
+                    | ErrorKind::NoSpace
+                    | ErrorKind::AuthFailed
+            ),
+            Self::SessionMissing | Self::InvalidSessionId | Self::Io(_) => false,
+        }
+    }
+}
+
+impl From<TransferError> for AppError {
+    fn from(value: TransferError) -> Self {
+        match value {
+            TransferError::SessionMissing => AppError::new(ErrorKind::SessionNotFound, value.to_string()),
+            TransferError::SftpNotInitialized => AppError::new(ErrorKind::SftpNotInitialized, value.to_string()),
+            TransferError::InvalidSessionId => AppError::new(ErrorKind::InvalidInput, value.to_string()),
+            TransferError::Remote(error) => error,
+            TransferError::Io(message) => AppError::from(message),
+        }
+    }
+}
+
 impl From<std::io::Error> for TransferError {
     fn from(value: std::io::Error) -> Self {
         Self::Io(value.to_string())
@@ -140,104 +727,248 @@ impl From<uuid::Error> for TransferError {
     }
 }
 
-fn get_history_path(_app_handle: &AppHandle) -> Result<PathBuf, String> {
-    let config_dir = std::env::var("HOME")
-        .map(|h| PathBuf::from(h).join(".config/terminoda"))
-        .unwrap_or_else(|_| {
-            PathBuf::from(std::env::var("APPDATA").unwrap_or_else(|_| ".".to_string()))
-        });
-    Ok(config_dir.join("history.json"))
+pub(crate) fn get_history_path(_app_handle: &AppHandle) -> Result<PathBuf, String> {
+    Ok(get_config_dir()?.join("history.json"))
 }
 
 #[tauri::command]
-fn load_history(app_handle: AppHandle) -> Result<Vec<ConnectionLog>, String> {
-    let path = get_history_path(&app_handle)?;
-    if !path.exists() {
-        return Ok(Vec::new());
-    }
-    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    let history: Vec<ConnectionLog> = serde_json::from_str(&content).map_err(|e| e.to_string())?;
-    
+fn load_history(app_handle: AppHandle) -> Result<Vec<ConnectionLog>, AppError> {
     // Return reversed (newest first)
-    Ok(history.into_iter().rev().collect())
+    Ok(history::read(&app_handle)?.into_iter().rev().collect())
 }
 
 #[tauri::command]
-fn clear_history(app_handle: AppHandle) -> Result<(), String> {
+fn clear_history(app_handle: AppHandle) -> Result<(), AppError> {
+    backups::auto_backup("clear-history");
     let path = get_history_path(&app_handle)?;
-    if path.exists() {
-        fs::remove_file(path).map_err(|e| e.to_string())?;
-    }
+    config_file::remove(&path)?;
+    host_stats::invalidate();
     Ok(())
 }
 
-// Helper to log connection
-fn log_connection_attempt(
-    app_handle: &AppHandle,
-    details: &ConnectionDetails,
-    status: &str
-) -> Result<(), String> {
-    let mut history = load_history(app_handle.clone()).unwrap_or_default();
-    
-    // Revert the reverse for appending
-    history.reverse();
+// Whether the server still accepts `method` for the user
+fn offers_auth(sess: &Session, username: &str, method: &str) -> bool {
+    sess.auth_methods(username)
+        .is_ok_and(|methods| methods.split(',').any(|m| m == method))
+}
 
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+fn keyboard_interactive(sess: &Session, details: &ConnectionDetails, prompter: &mut totp::Prompter) -> Result<(), AppError> {
+    sess.userauth_keyboard_interactive(&details.username, prompter)
+        .map_err(|e| {
+            error!(target = "connect_ssh", error = %e, "Keyboard-interactive authentication failed");
+            AppError::from(e).or_kind(ErrorKind::AuthFailed).context("Keyboard-interactive authentication failed")
+        })
+}
 
-    let log = ConnectionLog {
-        id: Uuid::new_v4().to_string(),
-        host: details.host.clone(),
-        username: details.username.clone(),
-        timestamp,
-        status: status.to_string(),
+// Signs in with the agent, the key or the password, whichever the details
+// ask for, then answers a TOTP prompt if the server wants one and the host
+// has auto-fill on. Returns the method used, as history records it.
+pub(crate) fn authenticate_session(
+    sess: &Session,
+    details: &ConnectionDetails,
+    settings: &settings::Settings,
+    // Records each attempt when connect_ssh is timing the connect
+    timeline: Option<&Timeline>,
+) -> Result<&'static str, AppError> {
+    let mut prompter = totp::Prompter::new(details, &settings.totp_prompt_patterns);
+    // A failed attempt's error names the method
+    let record = |started: Instant, method: Option<&str>, error: Option<&AppError>| {
+        if let Some(timeline) = timeline {
+            timeline.record(Phase::Auth, method, started, error.map(|e| e.message.clone()));
+        }
+    };
+    let started = Instant::now();
+    let first = if details.auth_method.as_deref() == Some("agent") {
+        info!(target = "connect_ssh", "Authenticating with SSH agent");
+        agent::authenticate(sess, &details.username, settings.agent_backend)
+            .map(|_| "agent")
+            .map_err(|e| {
+                error!(target = "connect_ssh", error = %e, "Agent authentication failed");
+                e.or_kind(ErrorKind::AuthFailed).context("Agent authentication failed")
+            })
+    } else if let Some(key_path) = &details.private_key_path {
+        info!(target = "connect_ssh", "Authenticating with key");
+        sess.userauth_pubkey_file(
+            &details.username,
+            None,
+            Path::new(key_path),
+            details.passphrase.as_deref(),
+        )
+        .map(|_| "publickey")
+        .map_err(|e| {
+            error!(target = "connect_ssh", error = %e, "Key authentication failed");
+            AppError::from(e).or_kind(ErrorKind::AuthFailed).context("Key authentication failed")
+        })
+    } else if let Some(password) = &details.password {
+        // Servers behind PAM often take the password only as a prompt
+        if !offers_auth(sess, &details.username, "password")
+            && offers_auth(sess, &details.username, "keyboard-interactive")
+        {
+            info!(target = "connect_ssh", "Authenticating with password over keyboard-interactive");
+            keyboard_interactive(sess, details, &mut prompter).map(|_| "keyboard-interactive")
+        } else {
+            info!(target = "connect_ssh", "Authenticating with password");
+            sess.userauth_password(&details.username, password)
+                .map(|_| "password")
+                .map_err(|e| {
+                    error!(target = "connect_ssh", error = %e, "Password authentication failed");
+                    AppError::from(e).or_kind(ErrorKind::AuthFailed).context("Password authentication failed")
+                })
+        }
+    } else {
+        Err(AppError::new(ErrorKind::PasswordRequired, "No password or private key provided"))
     };
 
-    history.push(log);
-    
-    // Keep only last 100 entries
-    if history.len() > 100 {
-        history.remove(0);
+    // Nothing was tried without a password or key
+    if !matches!(&first, Err(e) if e.kind == ErrorKind::PasswordRequired) {
+        record(started, first.as_ref().ok().copied(), first.as_ref().err());
     }
 
-    let path = get_history_path(app_handle)?;
-    let content = serde_json::to_string_pretty(&history).map_err(|e| e.to_string())?;
-    fs::write(path, content).map_err(|e| e.to_string())?;
-    Ok(())
+    // A partial success, or a bastion that wants only the code, goes on to
+    // the verification code prompt
+    let auth_method = match first {
+        Ok(method) if sess.authenticated() => method,
+        first if prompter.answers_codes() && offers_auth(sess, &details.username, "keyboard-interactive") => {
+            info!(target = "connect_ssh", "Answering verification code prompts");
+            let started = Instant::now();
+            let codes = keyboard_interactive(sess, details, &mut prompter);
+            record(started, Some("totp"), codes.as_ref().err());
+            match (codes, first) {
+                (Ok(()), Ok("agent")) => "agent+totp",
+                (Ok(()), Ok("publickey")) => "publickey+totp",
+                (Ok(()), Ok(_)) => "password+totp",
+                (Ok(()), Err(_)) => "totp",
+                // The first method's error says more
+                (Err(_), Err(e)) => return Err(e),
+                (Err(e), Ok(_)) => return Err(e),
+            }
+        }
+        first => first?,
+    };
+
+    if !sess.authenticated() {
+        return Err(AppError::new(ErrorKind::AuthFailed, "Authentication failed"));
+    }
+    Ok(auth_method)
 }
 
 #[tauri::command]
-async fn connect_ssh(
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn connect_ssh(
     details: ConnectionDetails,
+    host_id: Option<String>,
     terminal_type: Option<String>,
+    // Skips the cool-down after repeated failures
+    force: Option<bool>,
+    // Title and color to show, see session_meta
+    label: Option<SessionLabel>,
     state: State<'_, AppState>,
     window: Window,
     app_handle: AppHandle,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
+    let meta = Arc::new(SessionMeta::new(label)?);
+    // Saved hosts don't carry their secrets, fetch them from the credential store
+    let mut details = details;
+    let defaults = state.settings.get();
+    let known_hosts_files = known_hosts::files(&defaults.known_hosts_files, details.known_hosts_file.as_deref())?;
+    let terminal_type = terminal_type.or(Some(defaults.default_terminal_type.clone()));
+    details.keepalive_interval = details.keepalive_interval.or(Some(defaults.default_keepalive_secs));
+    if let Some(host_id) = &host_id {
+        if details.password.is_none() {
+            details.password = credentials::load(host_id, SecretKind::Password)?;
+        }
+        if details.passphrase.is_none() {
+            details.passphrase = credentials::load(host_id, SecretKind::Passphrase)?;
+        }
+        if details.totp_autofill == Some(true) && details.totp_secret.is_none() {
+            details.totp_secret = credentials::load(host_id, SecretKind::Totp)?;
+        }
+    }
+
     let sessions = state.sessions.clone();
+    let batch_settings = state.output_batching.clone();
+    let scrollback_limit = state.scrollback_limit.load(Ordering::Relaxed);
     let window_clone = window.clone();
     let details_clone = details.clone();
     let app_handle_clone = app_handle.clone();
 
-    // Log the attempt start
-    let _ = log_connection_attempt(&app_handle, &details, "Connecting...");
+    let sudo_autofill = SudoAutofill::from_config(details.sudo_autofill.as_ref(), details.password.as_deref())?;
+    let encoding = match details.charset.as_deref() {
+        Some(label) => charset::lookup(label)?,
+        None => encoding_rs::UTF_8,
+    };
+    let startup_commands = details
+        .startup_commands
+        .as_deref()
+        .filter(|steps| !steps.is_empty())
+        .map(|steps| resolve_startup_commands(&app_handle, steps));
+
+    let host_key = connect_limit::host_key(&details.username, &details.host, details.port.unwrap_or(22));
+    let lead = match state.connect_limiter.begin(host_key, window.label(), defaults.connect_cooldown, force.unwrap_or(false))? {
+        Slot::Lead(lead) => lead,
+        Slot::Follow(running) => {
+            return async_runtime::spawn_blocking(move || running.wait())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    };
 
-    async_runtime::spawn_blocking(move || {
+    // One history entry per attempt, updated once the outcome is known
+    let attempt = history::Attempt::start(
+        &app_handle,
+        ConnectionLog {
+            host_id: host_id.clone(),
+            port: Some(details.port.unwrap_or(22)),
+            details: host_id.is_none().then(|| history::redacted(&details)),
+            ..ConnectionLog::new(&details.host, &details.username, "ssh")
+        },
+    );
+
+    let jump = match &details.proxy_jump {
+        Some(_) => bastion::resolve(&details, &load_saved_hosts(app_handle.clone())?)
+            .map_err(|e| attempt.fail("Connect", e))?,
+        None => None,
+    };
+    let bastions = state.bastions.clone();
+    let session_host_id = host_id.clone();
+    let timeline = Arc::new(Timeline::new(&details.host, window.clone()));
+    let attempt_timeline = timeline.clone();
+    let result = async_runtime::spawn_blocking(move || {
+        let timeline = attempt_timeline;
         info!(target = "connect_ssh", host = %details.host, "Starting SSH connection");
         let session_id = Uuid::new_v4();
-        let host = details.host;
+        let host = details.host.clone();
         let port = details.port.unwrap_or(22);
         let addr = format!("{}:{}", host, port);
 
-        info!(target = "connect_ssh", %addr, "Connecting TCP");
-        let tcp = TcpStream::connect(&addr).map_err(|e| {
-            error!(target = "connect_ssh", error = %e, "TCP connect failed");
-            e.to_string()
-        })?;
+        let (tcp, bastion) = match &jump {
+            Some(jump) => {
+                info!(target = "connect_ssh", %addr, "Connecting through jump host");
+                let (tcp, lease) = timeline.time(Phase::TcpConnect, Some("jump host"), || bastions.connect(jump, &host, port, &defaults)).map_err(|e| {
+                    error!(target = "connect_ssh", error = %e, "Jump host connect failed");
+                    attempt.fail("Connect", e)
+                })?;
+                (tcp, Some(Arc::new(lease)))
+            }
+            None => {
+                info!(target = "connect_ssh", %addr, "Resolving");
+                let addrs: Vec<SocketAddr> = timeline.time(Phase::Resolve, None, || addr.to_socket_addrs().map(Iterator::collect)).map_err(|e| {
+                    error!(target = "connect_ssh", error = %e, "Name resolution failed");
+                    attempt.fail("Connect", AppError::from(e).or_kind(ErrorKind::ConnectionFailed))
+                })?;
+                info!(target = "connect_ssh", %addr, "Connecting TCP");
+                let tcp = timeline.time(Phase::TcpConnect, None, || TcpStream::connect(&addrs[..])).map_err(|e| {
+                    error!(target = "connect_ssh", error = %e, "TCP connect failed");
+                    attempt.fail("Connect", AppError::from(e).or_kind(ErrorKind::ConnectionFailed))
+                })?;
+                (tcp, None)
+            }
+        };
         info!(target = "connect_ssh", "TCP connected");
-        let mut sess = Session::new().map_err(|e| e.to_string())?;
+        let (mut readiness, waker) =
+            SocketReadiness::new(&tcp).map_err(|e| attempt.fail("Connect", e))?;
+        let socket = tcp.try_clone().map_err(|e| attempt.fail("Connect", e))?;
+        let mut sess = Session::new().map_err(|e| attempt.fail("Connect", e))?;
         sess.set_tcp_stream(tcp);
 
         if let Some(timeout_ms) = details.timeout {
@@ -253,139 +984,682 @@ async fn connect_ssh(
         }
 
         info!(target = "connect_ssh", "Performing SSH handshake");
-        sess.handshake().map_err(|e| {
+        timeline.time(Phase::Handshake, None, || sess.handshake()).map_err(|e| {
             error!(target = "connect_ssh", error = %e, "Handshake failed");
-            e.to_string()
+            attempt.fail("Handshake", AppError::from(e).or_kind(ErrorKind::HandshakeFailed))
         })?;
         info!(target = "connect_ssh", "Handshake complete");
 
-        if let Some(key_path) = details.private_key_path {
-            info!(target = "connect_ssh", "Authenticating with key");
-            sess.userauth_pubkey_file(
-                &details.username,
-                None,
-                Path::new(&key_path),
-                details.passphrase.as_deref(),
-            )
-            .map_err(|e| {
-                error!(target = "connect_ssh", error = %e, "Key authentication failed");
-                format!("Key authentication failed: {}", e)
-            })?;
-        } else if let Some(password) = details.password {
-            info!(target = "connect_ssh", "Authenticating with password");
-            sess.userauth_password(&details.username, &password)
-                .map_err(|e| {
-                    error!(target = "connect_ssh", error = %e, "Password authentication failed");
-                    format!("Password authentication failed: {}", e)
-                })?;
-        } else {
-            return Err("No password or private key provided".to_string());
-        }
-
-        if !sess.authenticated() {
-            let _ = log_connection_attempt(&app_handle_clone, &details_clone, "Failed (Auth)");
-            return Err("Authentication failed".to_string());
+        // A changed key stops here, before any credentials are sent
+        if let Some((blob, _)) = sess.host_key() {
+            let started = Instant::now();
+            let change = known_hosts::check_host_key(&known_hosts_files, &host, port, blob);
+            timeline.record(Phase::HostKey, None, started, change.as_ref().map(|_| "host key changed".to_string()));
+            if let Some(change) = change {
+                warn!(target = "connect_ssh", %host, old = %change.old_fingerprint, new = %change.new_fingerprint, "Host key changed");
+                known_hosts::hold_changed_key(&change, blob, known_hosts_files, attempt.id());
+                let detail = format!("{} changed to {}", change.old_fingerprint, change.new_fingerprint);
+                let _ = window_clone.emit("host-key-changed", &change);
+                let error = AppError::new(ErrorKind::HostKeyChanged, format!("The host key of {} has changed", host));
+                return Err(attempt.block("host key changed", detail, error));
+            }
         }
 
-        // Success
-        let _ = log_connection_attempt(&app_handle_clone, &details_clone, "Success");
+        let auth_method = authenticate_session(&sess, &details, &defaults, Some(&timeline))
+            .map_err(|e| attempt.fail("Auth", e))?;
 
         info!(target = "connect_ssh", "Opening channel session");
-        let mut channel = sess.channel_session().map_err(|e| {
+        let mut channel = timeline.time(Phase::Channel, None, || sess.channel_session()).map_err(|e| {
             error!(target = "connect_ssh", error = %e, "Channel creation failed");
-            e.to_string()
+            attempt.fail("Channel", e)
         })?;
         let term_env = terminal_type.as_deref().unwrap_or("xterm-256color");
-        channel
-            .request_pty(term_env, None, None)
+        timeline
+            .time(Phase::Pty, None, || channel.request_pty(term_env, None, None))
             .map_err(|e| {
                 error!(target = "connect_ssh", error = %e, "PTY request failed");
-                e.to_string()
+                attempt.fail("Channel", e)
             })?;
-        channel.shell().map_err(|e| {
+
+        // Most servers restrict AcceptEnv, so a rejected variable is not fatal
+        if let Some(env) = &details.env {
+            for (name, value) in env {
+                if let Err(e) = channel.setenv(name, value) {
+                    warn!(target = "connect_ssh", var = %name, error = %e, "Server rejected environment variable");
+                }
+            }
+        }
+
+        timeline.time(Phase::Shell, None, || channel.shell()).map_err(|e| {
             error!(target = "connect_ssh", error = %e, "Shell start failed");
-            e.to_string()
+            attempt.fail("Channel", e)
         })?;
+
+        // The PTY echoes whatever arrives before the shell can turn echo off,
+        // so the command shows in the terminal like typed input unless
+        // hide_initial_command filters the echo out. Each line gets a leading
+        // space to keep it out of shell history (HISTCONTROL=ignorespace).
+        let mut echo_filter = None;
+        if let Some(command) = details.initial_command.as_deref().filter(|c| !c.trim().is_empty()) {
+            info!(target = "connect_ssh", "Sending initial command");
+            let lines: String = command.trim_end().lines().map(|line| format!(" {}\n", line)).collect();
+            if details.hide_initial_command.unwrap_or(false) {
+                echo_filter = Some(EchoFilter::new(&lines));
+            }
+            channel
+                .write_all(lines.as_bytes())
+                .and_then(|_| channel.flush())
+                .map_err(|e| {
+                    error!(target = "connect_ssh", error = %e, "Initial command failed");
+                    attempt.fail("Channel", e)
+                })?;
+        }
         info!(target = "connect_ssh", "Channel ready");
+        attempt.succeed(Some(auth_method));
 
         let channel_arc = Arc::new(Mutex::new(channel));
         sess.set_blocking(false);
         let session_arc = Arc::new(Mutex::new(sess));
+        let startup_status = startup_commands.as_ref().map(|(commands, missing)| StartupStatus {
+            total: commands.len(),
+            sent: 0,
+            applied: commands.is_empty(),
+            missing_snippets: missing.clone(),
+        });
+        let target = SessionTarget {
+            host: details_clone.host.clone(),
+            username: details_clone.username.clone(),
+            history_id: attempt.id(),
+            connected_at: Some(Instant::now()),
+            host_id: session_host_id,
+            modes: details_clone.remote_modes,
+            timeline: Some(Arc::new(timeline.snapshot())),
+        };
+        let reader_target = target.clone();
 
-        sessions.insert(
+        let session = SessionState::new(
             session_id,
-            SessionState {
-                channel: channel_arc.clone(),
-                session: session_arc.clone(),
-                sftp: Arc::new(Mutex::new(None)),
+            &window_clone,
+            SessionSetup {
+                transport: SessionTransport::Ssh {
+                    channel: channel_arc.clone(),
+                    session: session_arc.clone(),
+                    waker,
+                    socket,
+                    shared: false,
+                    bastion,
+                },
+                target,
+                meta,
+                encoding,
+                scrollback_limit,
+                idle_after_secs: details_clone.idle_timeout_secs,
+                disconnect_after_secs: details_clone.idle_disconnect_secs,
+                read_only: details_clone.read_only.unwrap_or(false),
+                guard_exempt: details_clone.skip_command_guard.unwrap_or(false),
+                startup: startup_status,
             },
         );
-
-        let reader_window = window_clone.clone();
-        let reader_session_id = session_id.to_string();
-        thread::spawn(move || {
-            let mut buffer = [0; 4096];
-            loop {
-                match channel_arc.lock() {
-                    Ok(mut channel_lock) => {
-                        match channel_lock.read(&mut buffer) {
-                            Ok(bytes_read) => {
-                                if bytes_read == 0 {
-                                    info!(target = "connect_ssh", session = %reader_session_id, "SSH stream closed");
-                                    break;
-                                }
-                                let data = buffer[..bytes_read].to_vec();
-                                let _ = reader_window.emit(
-                                    "terminal-output",
-                                    TerminalOutputPayload {
-                                        session_id: reader_session_id.clone(),
-                                        data,
-                                    },
-                                );
-                            }
-                            Err(e) => {
-                                if e.kind() == std::io::ErrorKind::WouldBlock {
-                                    drop(channel_lock);
-                                    thread::sleep(Duration::from_millis(10));
-                                    continue;
-                                }
-                                warn!(target = "connect_ssh", session = %reader_session_id, error = %e, "Error reading SSH stream");
-                                break;
-                            }
-                        }
-                    },
-                    Err(e) => {
-                        warn!(target = "connect_ssh", session = %reader_session_id, error = %e, "Channel lock poisoned");
-                        break;
-                    }
-                }
+        let startup = startup_commands
+            .map(|(commands, _)| StartupSequence::new(commands, session.startup.clone()))
+            .filter(|sequence| !sequence.is_done());
+        let reader_ctx = session.reader_context(session_id, &window_clone);
+        let health_arc = session.health.clone();
+        let shutdown_arc = session.shutdown.clone();
+        sessions.insert(session_id, session);
+
+        let reader_sessions = sessions.clone();
+        let reader_shutdown = shutdown_arc.clone();
+        let reader = thread::spawn(move || {
+            let mut pipeline = OutputPipeline::new(reader_ctx, batch_settings);
+            pipeline.set_sudo_autofill(sudo_autofill);
+            pipeline.set_startup(startup);
+            pipeline.set_echo_filter(echo_filter);
+            let reason = read_ssh_channel(&mut pipeline, &channel_arc, &mut readiness, &reader_shutdown, &health_arc);
+            pipeline.flush();
+            // Sessions closed from the app record their own reason
+            if reader_sessions.contains_key(&session_id) {
+                history::finish_session(&app_handle_clone, &reader_target, &reason);
             }
         });
+        shutdown_arc.set_reader(reader);
 
         info!(target = "connect_ssh", session = %session_id, "SSH connection established");
+        emit_session_opened(&window_clone, &session_id.to_string());
         Ok(session_id.to_string())
     })
     .await
+    .map_err(|e| AppError::from(e.to_string()))
+    .and_then(|result| result)
+    .map_err(|e| timeline.attach(e))
+    .map_err(|e| wol::offer_wake(&app_handle, host_id.as_deref(), e));
+    lead.finish(&result);
+    result
+}
+
+// The SSH reader loop: feeds channel output to the pipeline until the
+// channel closes or a close is requested, returning the reason
+pub(crate) fn read_ssh_channel(
+    pipeline: &mut OutputPipeline,
+    channel: &Arc<Mutex<ssh2::Channel>>,
+    readiness: &mut SocketReadiness,
+    shutdown: &ReaderShutdown,
+    health: &SessionHealth,
+) -> String {
+    let mut buffer = [0; 4096];
+    let session_id = pipeline.ctx.session_id.clone();
+    let mut zmodem = zmodem::Detector::default();
+    loop {
+        if shutdown.is_requested() || !pipeline.wait_if_paused() {
+            return "closed".to_string();
+        }
+        let mut channel_lock = health.lock(channel, "channel");
+        match channel_lock.read(&mut buffer) {
+            Ok(bytes_read) => {
+                if bytes_read == 0 {
+                    info!(target = "connect_ssh", session = %session_id, "SSH stream closed");
+                    return "connection closed".to_string();
+                }
+                let chunk = &buffer[..bytes_read];
+                if let Some((offset, header, direction)) = zmodem.scan(chunk) {
+                    drop(channel_lock);
+                    pipeline.push(&chunk[..offset]);
+                    pipeline.flush();
+                    let remaining = run_zmodem(&pipeline.ctx, channel, header, direction);
+                    pipeline.push(&remaining);
+                    continue;
+                }
+                pipeline.push(chunk);
+            }
+            Err(e) => {
+                if e.kind() == std::io::ErrorKind::WouldBlock {
+                    if let Some(reply) = pipeline.idle() {
+                        let _ = channel_lock.write_all(&reply).and_then(|_| channel_lock.flush());
+                    }
+                    drop(channel_lock);
+                    let timeout = pipeline.idle_timeout(readiness::FALLBACK_TIMEOUT);
+                    if let Err(e) = readiness.wait(timeout) {
+                        warn!(target = "connect_ssh", session = %session_id, error = %e, "Waiting for SSH socket failed");
+                        return e.to_string();
+                    }
+                    continue;
+                }
+                warn!(target = "connect_ssh", session = %session_id, error = %e, "Error reading SSH stream");
+                return e.to_string();
+            }
+        }
+    }
+}
+
+// Snippets are looked up once at connect time, missing ones are reported
+fn resolve_startup_commands(app_handle: &AppHandle, steps: &[StartupCommand]) -> (Vec<(String, bool)>, Vec<String>) {
+    let snippets = load_snippets(app_handle.clone()).unwrap_or_default();
+    let mut commands = Vec::new();
+    let mut missing = Vec::new();
+
+    for step in steps {
+        let command = match (&step.snippet_id, &step.command) {
+            (Some(id), _) => match snippets.iter().find(|s| &s.id == id) {
+                Some(snippet) => snippet.command.clone(),
+                None => {
+                    warn!(target = "startup", snippet = %id, "Startup snippet not found");
+                    missing.push(id.clone());
+                    continue;
+                }
+            },
+            (None, Some(command)) => command.clone(),
+            (None, None) => continue,
+        };
+        if !command.trim().is_empty() {
+            commands.push((command, step.wait_for_prompt));
+        }
+    }
+    (commands, missing)
+}
+
+// Runs a ZMODEM transfer on the reader thread, returning any bytes that
+// arrived after the transfer so they can be forwarded to the terminal.
+fn run_zmodem(
+    ctx: &ReaderContext,
+    channel: &Arc<Mutex<ssh2::Channel>>,
+    prefetched: Vec<u8>,
+    direction: zmodem::Direction,
+) -> Vec<u8> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    if let Ok(mut pending) = ctx.zmodem.pending.lock() {
+        *pending = Some(tx);
+    }
+    ctx.zmodem.cancel.store(false, Ordering::SeqCst);
+
+    info!(target = "zmodem", session = %ctx.session_id, ?direction, "ZMODEM transfer detected");
+    ctx.emit(
+        "zmodem-detected",
+        ZmodemDetectedPayload {
+            session_id: ctx.session_id.clone(),
+            direction: match direction {
+                zmodem::Direction::Receive => "receive".to_string(),
+                zmodem::Direction::Send => "send".to_string(),
+            },
+        },
+    );
+
+    let command = rx
+        .recv_timeout(Duration::from_secs(120))
+        .unwrap_or(ZmodemCommand::Cancel);
+    if let Ok(mut pending) = ctx.zmodem.pending.lock() {
+        *pending = None;
+    }
+
+    let mut port = zmodem::Port::new(channel.clone(), prefetched, ctx.zmodem.clone());
+    let mut files = Vec::new();
+    let reporter = ProgressReporter::start(ctx.window.clone(), ctx.owners.clone(), ctx.session_id.clone(), String::new(), 0);
+    // Source and destination of a file by its ZMODEM name
+    let (save_dir, local_paths) = match &command {
+        ZmodemCommand::Receive { save_dir } => (Some(PathBuf::from(save_dir)), Vec::new()),
+        ZmodemCommand::Send { paths } => (None, paths.clone()),
+        ZmodemCommand::Cancel => (None, Vec::new()),
+    };
+    let endpoints = |name: &str| match &save_dir {
+        Some(dir) => (name.to_string(), dir.join(name).to_string_lossy().into_owned()),
+        None => {
+            let local = local_paths
+                .iter()
+                .find(|p| Path::new(p).file_name().is_some_and(|n| n.to_string_lossy() == name))
+                .cloned()
+                .unwrap_or_else(|| name.to_string());
+            (local, name.to_string())
+        }
+    };
+    // Each file is a transfer of its own, done when the next one starts
+    let mut transfer: Option<(String, Transfer)> = None;
+    let mut progress = |name: &str, transferred_bytes: u64, total_bytes: u64| {
+        reporter.set(name, transferred_bytes, total_bytes);
+        if transfer.as_ref().is_none_or(|(current, _)| current != name) {
+            if let Some((_, done)) = transfer.take() {
+                done.finish(&Ok(()));
+            }
+            let (source, destination) = endpoints(name);
+            let next = Transfer::start(&ctx.window, ctx.owners.clone(), None, &ctx.session_id, &source, &destination);
+            transfer = Some((name.to_string(), next));
+        }
+        if let Some((_, current)) = &transfer {
+            current.bytes().store(transferred_bytes, Ordering::Relaxed);
+        }
+    };
+
+    let result = match command {
+        ZmodemCommand::Receive { save_dir } => {
+            zmodem::receive(&mut port, Path::new(&save_dir), &mut progress).map(|paths| {
+                files = paths
+                    .iter()
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .collect();
+            })
+        }
+        ZmodemCommand::Send { paths } => {
+            let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+            files = paths
+                .iter()
+                .map(|p| p.to_string_lossy().into_owned())
+                .collect();
+            zmodem::send(&mut port, &paths, &mut progress)
+        }
+        ZmodemCommand::Cancel => Err(zmodem::ZmodemError::Cancelled),
+    };
+    if let Some((_, last)) = transfer {
+        last.finish(&result.as_ref().map_err(AppError::from).copied());
+    }
+
+    let remaining = match &result {
+        Ok(()) => {
+            info!(target = "zmodem", session = %ctx.session_id, "ZMODEM transfer complete");
+            port.into_remaining()
+        }
+        Err(e) => {
+            warn!(target = "zmodem", session = %ctx.session_id, error = %e, "ZMODEM transfer aborted");
+            // Abort the remote sz/rz so the shell comes back
+            if let Ok(mut channel_lock) = channel.lock() {
+                let _ = channel_lock.write_all(zmodem::CANCEL_SEQUENCE);
+                let _ = channel_lock.flush();
+            }
+            Vec::new()
+        }
+    };
+
+    ctx.emit(
+        "zmodem-finished",
+        ZmodemFinishedPayload {
+            session_id: ctx.session_id.clone(),
+            success: result.is_ok(),
+            files: if result.is_ok() { files } else { Vec::new() },
+            error: result.err().map(|e| e.to_string()),
+        },
+    );
+    remaining
+}
+
+fn send_zmodem_command(
+    session_id: &str,
+    command: ZmodemCommand,
+    window: &Window,
+    state: &AppState,
+) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    ownership::check(&session, session_id, window)?;
+    let pending = session.lock(&session.zmodem.pending, "zmodem");
+    match pending.as_ref() {
+        Some(tx) => tx.send(command).map_err(|_| {
+            AppError::new(ErrorKind::NotFound, "ZMODEM transfer is no longer waiting")
+        }),
+        None => Err(AppError::new(
+            ErrorKind::NotFound,
+            "No ZMODEM transfer is waiting for a response",
+        )),
+    }
+}
+
+#[tauri::command]
+fn zmodem_receive(
+    session_id: String,
+    save_dir: String,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    send_zmodem_command(&session_id, ZmodemCommand::Receive { save_dir }, &window, &state)
+}
+
+#[tauri::command]
+fn zmodem_send(
+    session_id: String,
+    paths: Vec<String>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    send_zmodem_command(&session_id, ZmodemCommand::Send { paths }, &window, &state)
+}
+
+#[tauri::command]
+fn zmodem_cancel(session_id: String, window: Window, state: State<'_, AppState>) -> Result<(), AppError> {
+    match send_zmodem_command(&session_id, ZmodemCommand::Cancel, &window, &state) {
+        Err(e) if e.kind == ErrorKind::NotFound => {}
+        result => return result,
+    }
+
+    // Transfer already running, interrupt it
+    let uuid = Uuid::parse_str(&session_id)?;
+    if let Some(session) = state.sessions.get(&uuid) {
+        session.value().zmodem.cancel.store(true, Ordering::SeqCst);
+        Ok(())
+    } else {
+        Err(AppError::session_not_found(&session_id))
+    }
+}
+
+#[tauri::command]
+fn get_output_batching(state: State<'_, AppState>) -> OutputBatchConfig {
+    state.output_batching.get()
+}
+
+#[tauri::command]
+fn set_output_batching(
+    config: OutputBatchConfig,
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<OutputBatchConfig, AppError> {
+    settings::update_one(&app_handle, &state, "output_batching", config)?;
+    Ok(state.output_batching.get())
+}
+
+fn session_flow(state: &AppState, session_id: &str) -> Result<Arc<OutputFlow>, AppError> {
+    let uuid = Uuid::parse_str(session_id)?;
+    state
+        .sessions
+        .get(&uuid)
+        .map(|session| session.flow.clone())
+        .ok_or_else(|| AppError::session_not_found(session_id))
+}
+
+#[tauri::command]
+fn pause_session_output(session_id: String, state: State<'_, AppState>) -> Result<(), AppError> {
+    session_flow(&state, &session_id)?.pause();
+    Ok(())
+}
+
+#[tauri::command]
+fn resume_session_output(session_id: String, state: State<'_, AppState>) -> Result<(), AppError> {
+    session_flow(&state, &session_id)?.resume();
+    Ok(())
+}
+
+// None or 0 turns automatic throttling off for the session
+#[tauri::command]
+fn set_output_throttle(
+    session_id: String,
+    max_bytes_per_second: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    session_flow(&state, &session_id)?.set_throttle_limit(max_bytes_per_second.unwrap_or(0));
+    Ok(())
+}
+
+#[tauri::command]
+fn get_session_info(session_id: String, state: State<'_, AppState>) -> Result<SessionInfo, AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state.sessions.get(&uuid).ok_or_else(|| AppError::session_not_found(&session_id))?;
+    let cwd = session.lock(&session.cwd, "cwd").clone();
+    let startup = session.lock(&session.startup, "startup").clone();
+    Ok(SessionInfo {
+        session_id,
+        protocol: session.transport.protocol().to_string(),
+        cwd,
+        output_paused: session.flow.is_paused(),
+        startup,
+        degraded: session.health.is_degraded(),
+        read_only: session.read_only.is_enabled(),
+        bastion: match &session.transport {
+            SessionTransport::Ssh { bastion: Some(lease), .. } => Some(lease.info()),
+            _ => None,
+        },
+        connect_timeline: session.target.timeline.as_deref().cloned(),
+    })
+}
+
+#[tauri::command]
+fn get_scrollback(session_id: String, window: Window, state: State<'_, AppState>) -> Result<Vec<u8>, AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state.sessions.get(&uuid).ok_or_else(|| AppError::session_not_found(&session_id))?;
+    ownership::check(&session, &session_id, &window)?;
+    let contents = session.lock(&session.scrollback, "scrollback").contents();
+    Ok(contents)
+}
+
+#[tauri::command]
+fn clear_scrollback(session_id: String, window: Window, state: State<'_, AppState>) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state.sessions.get(&uuid).ok_or_else(|| AppError::session_not_found(&session_id))?;
+    ownership::check(&session, &session_id, &window)?;
+    session.lock(&session.scrollback, "scrollback").clear();
+    Ok(())
+}
+
+// Applies to open sessions as well as new ones
+#[tauri::command]
+fn set_scrollback_limit(bytes: usize, state: State<'_, AppState>) -> Result<(), AppError> {
+    state.scrollback_limit.store(bytes, Ordering::Relaxed);
+    for session in state.sessions.iter() {
+        session.lock(&session.scrollback, "scrollback").set_limit(bytes);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn get_session_activity(session_id: String, state: State<'_, AppState>) -> Result<ActivityInfo, AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state.sessions.get(&uuid).ok_or_else(|| AppError::session_not_found(&session_id))?;
+    Ok(session.activity.info())
+}
+
+#[tauri::command]
+fn set_session_charset(
+    session_id: String,
+    charset: String,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state.sessions.get(&uuid).ok_or_else(|| AppError::session_not_found(&session_id))?;
+    ownership::check(&session, &session_id, &window)?;
+    session.charset.set(charset::lookup(&charset)?);
+    Ok(())
+}
+
+#[tauri::command]
+async fn run_background_command(
+    session_id: String,
+    command: String,
+    timeout_secs: Option<u64>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<ExecOutput, AppError> {
+    ownership::authorize(&state, &session_id, &window)?;
+    // Any command could change the host
+    read_only::check(&state, &session_id)?;
+    let sessions = state.sessions.clone();
+    let timeout = Duration::from_secs(timeout_secs.unwrap_or(30));
+    async_runtime::spawn_blocking(move || {
+        side_channel::run_on_side_channel(&sessions, &session_id, &command, timeout)
+    })
+    .await
+    .map_err(|e| AppError::from(e.to_string()))?
+}
+
+#[tauri::command]
+fn get_side_channel_metrics(session_id: String, state: State<'_, AppState>) -> Result<SideChannelMetrics, AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state.sessions.get(&uuid).ok_or_else(|| AppError::session_not_found(&session_id))?;
+    Ok(session.exec_pool.metrics())
+}
+
+#[tauri::command]
+fn get_session_cwd(session_id: String, window: Window, state: State<'_, AppState>) -> Result<Option<String>, AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+
+    if let Some(session) = state.sessions.get(&uuid) {
+        ownership::check(&session, &session_id, &window)?;
+        let cwd = session.lock(&session.cwd, "cwd");
+        Ok(cwd.clone())
+    } else {
+        Err(AppError::session_not_found(&session_id))
+    }
+}
+
+#[tauri::command]
+fn get_command_history(
+    session_id: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<CommandRecord>, AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+
+    if let Some(session) = state.sessions.get(&uuid) {
+        let tracker = session.lock(&session.commands, "commands");
+        Ok(tracker.records())
+    } else {
+        Err(AppError::session_not_found(&session_id))
+    }
+}
+
+#[tauri::command]
+async fn install_shell_integration(
+    session_id: String,
+    shell: String,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let sessions = state.sessions.clone();
+
+    async_runtime::spawn_blocking(move || {
+        let (script, script_name, rc_name) = match shell.as_str() {
+            "bash" => (shell_integration::BASH_INTEGRATION, ".terminoda_integration.bash", ".bashrc"),
+            "zsh" => (shell_integration::ZSH_INTEGRATION, ".terminoda_integration.zsh", ".zshrc"),
+            other => return Err(TransferError::Io(format!("Unsupported shell: {}", other))),
+        };
+
+        let uuid = Uuid::parse_str(&session_id).map_err(TransferError::from)?;
+        let session_entry = sessions
+            .get(&uuid)
+            .ok_or(TransferError::SessionMissing)?;
+        let session_state = session_entry.value();
+
+        ensure_sftp(session_state)?;
+        let sftp_lock = session_state.lock(&session_state.sftp, "sftp");
+        let sftp = sftp_lock
+            .as_ref()
+            .ok_or(TransferError::SftpNotInitialized)?;
+
+        let home = sftp
+            .realpath(Path::new("."))
+            .map_err(|e| TransferError::Remote(e.into()))?;
+        let script_path = home.join(script_name);
+        let rc_path = home.join(rc_name);
+
+        let mut script_file = sftp
+            .create(&script_path)
+            .map_err(|e| TransferError::Remote(e.into()))?;
+        script_file.write_all(script.as_bytes())?;
+
+        let source_line = format!("[ -f ~/{0} ] && . ~/{0}", script_name);
+        let mut rc_content = String::new();
+        if let Ok(mut rc_file) = sftp.open(&rc_path) {
+            let _ = rc_file.read_to_string(&mut rc_content);
+        }
+
+        if !rc_content.contains(&source_line) {
+            let mut rc_file = sftp
+                .open_mode(
+                    &rc_path,
+                    ssh2::OpenFlags::WRITE | ssh2::OpenFlags::CREATE | ssh2::OpenFlags::APPEND,
+                    0o644,
+                    ssh2::OpenType::File,
+                )
+                .map_err(|e| TransferError::Remote(e.into()))?;
+            let prefix = if rc_content.is_empty() || rc_content.ends_with('\n') { "" } else { "\n" };
+            rc_file.write_all(format!("{}{}\n", prefix, source_line).as_bytes())?;
+        }
+
+        info!(target = "shell_integration", session = %session_id, rc = %rc_path.display(), "Installed shell integration");
+        Ok(rc_path.to_string_lossy().into_owned())
+    })
+    .await
     .map_err(|e| e.to_string())?
+    .map_err(|e: TransferError| AppError::from(e))
 }
 
 #[tauri::command]
 fn send_terminal_input(
     session_id: String,
     data: String,
+    window: Window,
     state: State<'_, AppState>,
-) -> Result<(), String> {
-    let uuid = Uuid::parse_str(&session_id).map_err(|e| e.to_string())?;
+) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
 
     if let Some(session) = state.sessions.get(&uuid) {
-        let mut channel = session.value().channel.lock().map_err(|e| e.to_string())?;
-        channel
-            .write_all(data.as_bytes())
-            .map_err(|e| e.to_string())?;
-        channel.flush().map_err(|e| e.to_string())?;
-        Ok(())
+        ownership::check(&session, &session_id, &window)?;
+        let settings = state.settings.get();
+        session
+            .read_only
+            .check_input(&data, &settings.read_only_allowed_input)
+            .map_err(|e| e.with_session(&session_id))?;
+        let data = command_guard::filter_input(&session, &session_id, &data, &settings.command_guard, &window)?;
+        if data.is_empty() {
+            return Ok(());
+        }
+        Ok(session.value().write_input(data.as_bytes())?)
     } else {
-        Err(format!("Session not found: {}", session_id))
+        Err(AppError::session_not_found(&session_id))
     }
 }
 
@@ -394,267 +1668,510 @@ fn resize_terminal(
     session_id: String,
     rows: u32,
     cols: u32,
+    window: Window,
     state: State<'_, AppState>,
-) -> Result<(u32, u32), String> {
-    let uuid = Uuid::parse_str(&session_id).map_err(|e| e.to_string())?;
-
-    if let Some(session) = state.sessions.get(&uuid) {
-        let mut channel = session.value().channel.lock().map_err(|e| e.to_string())?;
-        channel
-            .request_pty_size(cols, rows, None, None)
-            .map_err(|e| e.to_string())?;
-        Ok((rows, cols))
-    } else {
-        // Return input if session not found (UI sync only)
-        Ok((rows, cols))
+) -> Result<(u32, u32), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    ownership::check(&session, &session_id, &window)?;
+
+    // Another call is applying sizes and will pick this one up
+    if !session.resize.submit(cols, rows) {
+        return Ok((rows, cols));
+    }
+    while let Some((cols, rows)) = session.resize.next() {
+        if let Err(e) = session.apply_resize(cols, rows) {
+            session.resize.abort();
+            return Err(AppError::from(e).with_session(&session_id));
+        }
     }
+    Ok((rows, cols))
 }
 
-fn get_connections_path(_app_handle: &AppHandle) -> Result<std::path::PathBuf, String> {
-    let config_dir = std::env::var("HOME")
-        .map(|h| std::path::PathBuf::from(h).join(".config/terminoda"))
-        .unwrap_or_else(|_| {
-            std::path::PathBuf::from(
-                std::env::var("APPDATA").unwrap_or_else(|_| ".".to_string())
-            )
-        });
-
-    if !config_dir.exists() {
-        fs::create_dir_all(&config_dir).map_err(|e| e.to_string())?;
-    }
+pub(crate) fn get_config_dir() -> Result<PathBuf, String> {
+    app_paths::config_dir()
+}
 
-    Ok(config_dir.join("connections.json"))
+fn get_connections_path(_app_handle: &AppHandle) -> Result<PathBuf, String> {
+    Ok(get_config_dir()?.join("connections.json"))
 }
 
 fn get_snippets_path(_app_handle: &AppHandle) -> Result<PathBuf, String> {
-    let config_dir = std::env::var("HOME")
-        .map(|h| PathBuf::from(h).join(".config/terminoda"))
-        .unwrap_or_else(|_| {
-            PathBuf::from(std::env::var("APPDATA").unwrap_or_else(|_| ".".to_string()))
-        });
-
-    if !config_dir.exists() {
-        fs::create_dir_all(&config_dir).map_err(|e| e.to_string())?;
-    }
-    Ok(config_dir.join("snippets.json"))
+    Ok(get_config_dir()?.join("snippets.json"))
 }
 
 fn get_keychain_path(_app_handle: &AppHandle) -> Result<PathBuf, String> {
-    let config_dir = std::env::var("HOME")
-        .map(|h| PathBuf::from(h).join(".config/terminoda"))
-        .unwrap_or_else(|_| {
-            PathBuf::from(std::env::var("APPDATA").unwrap_or_else(|_| ".".to_string()))
-        });
-
-    if !config_dir.exists() {
-        fs::create_dir_all(&config_dir).map_err(|e| e.to_string())?;
-    }
-
-    Ok(config_dir.join("keychain.json"))
+    Ok(get_config_dir()?.join("keychain.json"))
 }
 
 #[tauri::command]
-fn load_snippets(app_handle: AppHandle) -> Result<Vec<Snippet>, String> {
+fn load_snippets(app_handle: AppHandle) -> Result<Vec<Snippet>, AppError> {
     let path = get_snippets_path(&app_handle)?;
-    if !path.exists() {
-        return Ok(Vec::new());
-    }
-    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    let snippets: Vec<Snippet> = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    let mut snippets: Vec<Snippet> = config_file::load(&app_handle, &path)?.unwrap_or_default();
+    snippet_folders::sort_snippets(&mut snippets, &snippet_folders::load(&app_handle)?);
     Ok(snippets)
 }
 
 #[tauri::command]
-fn save_snippet(snippet: Snippet, app_handle: AppHandle) -> Result<Snippet, String> {
+fn save_snippet(snippet: Snippet, app_handle: AppHandle) -> Result<Snippet, AppError> {
+    let mut snippet = snippet;
+    snippet.tags = tags::normalize(std::mem::take(&mut snippet.tags));
+    snippet.host_ids = tags::normalize(std::mem::take(&mut snippet.host_ids));
+    snippet.group_ids = tags::normalize(std::mem::take(&mut snippet.group_ids));
+    let path = get_snippets_path(&app_handle)?;
+    let _lock = config_file::lock(&path)?;
     let mut snippets = load_snippets(app_handle.clone())?;
     
-    // Check if updating or new
+    // Check if updating or new. Folder and position only change through
+    // move_snippet_to_folder and reorder_snippets.
     if let Some(pos) = snippets.iter().position(|s| s.id == snippet.id) {
+        snippet.folder = snippets[pos].folder.take();
+        snippet.sort_order = snippets[pos].sort_order;
         snippets[pos] = snippet.clone();
     } else {
+        snippet_folders::check_folder(&app_handle, snippet.folder.as_deref())?;
+        snippet.sort_order = snippet_folders::next_sort_order(&snippets, snippet.folder.as_deref());
         snippets.push(snippet.clone());
     }
 
-    let path = get_snippets_path(&app_handle)?;
-    let content = serde_json::to_string_pretty(&snippets).map_err(|e| e.to_string())?;
-    fs::write(path, content).map_err(|e| e.to_string())?;
+    config_file::write(&path, &snippets)?;
     
     Ok(snippet)
 }
 
 #[tauri::command]
-fn delete_snippet(snippet_id: String, app_handle: AppHandle) -> Result<(), String> {
+fn delete_snippet(snippet_id: String, app_handle: AppHandle) -> Result<(), AppError> {
+    let path = get_snippets_path(&app_handle)?;
+    let _lock = config_file::lock(&path)?;
     let mut snippets = load_snippets(app_handle.clone())?;
     snippets.retain(|s| s.id != snippet_id);
     
-    let path = get_snippets_path(&app_handle)?;
-    let content = serde_json::to_string_pretty(&snippets).map_err(|e| e.to_string())?;
-    fs::write(path, content).map_err(|e| e.to_string())?;
+    config_file::write(&path, &snippets)?;
     Ok(())
 }
 
 #[tauri::command]
-fn load_saved_hosts(app_handle: AppHandle) -> Result<Vec<SavedHost>, String> {
+fn load_saved_hosts(app_handle: AppHandle) -> Result<Vec<SavedHost>, AppError> {
     let path = get_connections_path(&app_handle)?;
-    if !path.exists() {
+    let Some(mut hosts) = config_file::load::<Vec<SavedHost>>(&app_handle, &path)? else {
         return Ok(Vec::new());
+    };
+
+    // One-time migration of plaintext secrets written by older versions
+    let plaintext = hosts
+        .iter()
+        .any(|h| h.details.password.is_some() || h.details.passphrase.is_some());
+    if plaintext {
+        for host in hosts.iter_mut() {
+            stash_host_secrets(host, None)?;
+        }
+        write_saved_hosts(&app_handle, &hosts)?;
+        info!(target = "credentials", "Moved plaintext host secrets into the credential store");
+    }
+    if groups::migrate_string_groups(&mut hosts)? {
+        write_saved_hosts(&app_handle, &hosts)?;
     }
-    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    let hosts: Vec<SavedHost> = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    host_order::sort_hosts(&mut hosts);
     Ok(hosts)
 }
 
+pub(crate) fn write_saved_hosts(app_handle: &AppHandle, hosts: &[SavedHost]) -> Result<(), String> {
+    let path = get_connections_path(app_handle)?;
+    config_file::write(&path, hosts)?;
+    // Pinned hosts are listed in the tray
+    tray::refresh(app_handle);
+    Ok(())
+}
+
+// Held from loading the hosts to writing them back. Take the groups lock
+// first when both are needed.
+pub(crate) fn lock_saved_hosts(app_handle: &AppHandle) -> Result<config_file::ConfigLock, String> {
+    config_file::lock(&get_connections_path(app_handle)?)
+}
+
+// Rejects a saved host whose file or directory mode override isn't a valid
+// permission mode
+fn validate_remote_modes(details: &ConnectionDetails) -> Result<(), AppError> {
+    match &details.remote_modes {
+        Some(modes) => modes.validate().map_err(|e| AppError::new(ErrorKind::InvalidInput, e)),
+        None => Ok(()),
+    }
+}
+
+// Moves secrets from the host details into the credential store. A missing
+// secret keeps whatever is stored, an empty one removes it.
+fn stash_host_secrets(host: &mut SavedHost, existing: Option<&SavedHost>) -> Result<(), String> {
+    if let Some(secret) = host.details.totp_secret.as_mut().filter(|s| !s.is_empty()) {
+        *secret = totp::normalize_secret(secret)?;
+    }
+    for kind in SecretKind::ALL {
+        let (field, flag, existing_flag) = match kind {
+            SecretKind::Password => (
+                &mut host.details.password,
+                &mut host.has_password,
+                existing.is_some_and(|e| e.has_password),
+            ),
+            SecretKind::Passphrase => (
+                &mut host.details.passphrase,
+                &mut host.has_passphrase,
+                existing.is_some_and(|e| e.has_passphrase),
+            ),
+            SecretKind::Totp => (
+                &mut host.details.totp_secret,
+                &mut host.has_totp_secret,
+                existing.is_some_and(|e| e.has_totp_secret),
+            ),
+        };
+        match field.take() {
+            Some(secret) if secret.is_empty() => {
+                credentials::delete(&host.id, kind)?;
+                *flag = false;
+            }
+            Some(secret) => {
+                credentials::store(&host.id, kind, &secret)?;
+                *flag = true;
+            }
+            None => *flag = *flag || existing_flag,
+        }
+    }
+    Ok(())
+}
+
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 fn save_new_host(
     name: String,
     group: Option<String>,
     tags: Option<Vec<String>>,
     details: ConnectionDetails,
+    notes: Option<String>,
+    color: Option<String>,
+    metadata: Option<HashMap<String, String>>,
+    mac_address: Option<String>,
+    broadcast_address: Option<String>,
     app_handle: AppHandle,
-) -> Result<SavedHost, String> {
+) -> Result<SavedHost, AppError> {
+    let group = groups::resolve(&app_handle, group)?;
+    let _lock = lock_saved_hosts(&app_handle)?;
     let mut hosts = load_saved_hosts(app_handle.clone())?;
 
-    let new_host = SavedHost {
+    let mut new_host = SavedHost {
         id: Uuid::new_v4().to_string(),
         name,
         group,
-        tags,
+        tags: tags::normalize(tags.unwrap_or_default()),
         details,
+        has_password: false,
+        has_passphrase: false,
+        has_totp_secret: false,
+        pinned: false,
+        sort_order: host_order::next_sort_order(&hosts),
+        notes,
+        color,
+        metadata: metadata.unwrap_or_default(),
+        mac_address,
+        broadcast_address,
+        reachability_disabled: false,
+        tunnels: Vec::new(),
     };
+    wol::validate(&new_host).map_err(|e| AppError::new(ErrorKind::InvalidInput, e))?;
+    validate_remote_modes(&new_host.details)?;
+    stash_host_secrets(&mut new_host, None)?;
 
     hosts.push(new_host.clone());
 
-    let path = get_connections_path(&app_handle)?;
-    let content = serde_json::to_string_pretty(&hosts).map_err(|e| e.to_string())?;
-    fs::write(path, content).map_err(|e| e.to_string())?;
+    write_saved_hosts(&app_handle, &hosts)?;
 
     Ok(new_host)
 }
 
-#[tauri::command]
-fn close_session(session_id: String, state: State<'_, AppState>) -> Result<(), String> {
-    let uuid = Uuid::parse_str(&session_id).map_err(|e| e.to_string())?;
-    
-    if let Some((_, session)) = state.sessions.remove(&uuid) {
-        let mut channel = session.channel.lock().unwrap();
-        if let Err(e) = channel.send_eof() {
-            eprintln!("Failed to send EOF for session {}: {}", session_id, e);
-        }
-        if let Err(e) = channel.close() {
-            eprintln!("Failed to close channel for session {}: {}", session_id, e);
+// Runs shutdown_session with a hard cap: past CLOSE_TIMEOUT the socket is
+// cut and the stalled teardown finishes on its own
+pub(crate) fn teardown_session(session: SessionState, session_id: &str) {
+    let session = Arc::new(session);
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    let worker = session.clone();
+    let worker_id = session_id.to_string();
+    thread::spawn(move || {
+        shutdown_session(&worker, &worker_id);
+        let _ = done_tx.send(());
+    });
+    if done_rx.recv_timeout(CLOSE_TIMEOUT).is_err() {
+        warn!(target = "session", session = %session_id, "Graceful close stalled, forcing it");
+        session.force_close();
+    }
+}
+
+// Tears down the transport of a session already removed from the registry
+// and stops its reader thread
+fn shutdown_session(session: &SessionState, session_id: &str) {
+    session.shutdown.request();
+    session.input.close();
+    session.flow.close();
+    match &session.transport {
+        SessionTransport::Ssh { channel, waker, .. } => {
+            let _ = waker.wake();
+            let mut channel = session.lock(channel, "channel");
+            if let Err(e) = channel.send_eof() {
+                warn!(target = "session", session = %session_id, error = %e, "Failed to send EOF");
+            }
+            if let Err(e) = channel.close() {
+                warn!(target = "session", session = %session_id, error = %e, "Failed to close channel");
+            }
+            if let Err(e) = channel.wait_close() {
+                warn!(target = "session", session = %session_id, error = %e, "Failed to wait for channel close");
+            }
         }
-        if let Err(e) = channel.wait_close() {
-            eprintln!("Failed to wait for channel close for session {}: {}", session_id, e);
+        SessionTransport::Telnet { stream, .. } => {
+            let _ = session.lock(stream, "telnet stream").shutdown(std::net::Shutdown::Both);
         }
-        println!("Closed and removed session {}", session_id);
-    } else {
-        println!("Attempted to close non-existent session {}", session_id);
+        // Dropping a serial session releases the port handle
+        SessionTransport::Serial { .. } => {}
+    }
+    if !session.shutdown.join(shutdown::JOIN_TIMEOUT) {
+        warn!(target = "session", session = %session_id, "Reader thread did not stop in time");
+    }
+}
+
+#[tauri::command]
+async fn close_session(
+    session_id: String,
+    window: Window,
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    if let Some(session) = state.sessions.get(&uuid) {
+        ownership::check(&session, &session_id, &window)?;
+    }
+    if !start_close(&app_handle, uuid, "closed") {
+        info!(target = "session", session = %session_id, "Attempted to close non-existent session");
     }
     Ok(())
 }
 
+// Removes a session and tears it down in the background. The tab goes away
+// now, "session-closed" follows once cleanup is done. False if it was
+// already closed.
+pub(crate) fn start_close(app_handle: &AppHandle, uuid: Uuid, reason: &str) -> bool {
+    // Removed up front, so closing the same id again is a no-op
+    let Some((_, session)) = app_handle.state::<AppState>().sessions.remove(&uuid) else {
+        return false;
+    };
+    history::finish_session(app_handle, &session.target, reason);
+
+    let session_id = uuid.to_string();
+    let reason = reason.to_string();
+    let app_handle = app_handle.clone();
+    // Taken now, the session is gone once torn down
+    let labels = session.owners.labels();
+    async_runtime::spawn(async move {
+        let id = session_id.clone();
+        let _ = async_runtime::spawn_blocking(move || teardown_session(session, &id)).await;
+        info!(target = "session", session = %session_id, %reason, "Closed and removed session");
+        let payload = SessionClosedPayload { session_id, reason };
+        for label in labels {
+            let _ = app_handle.emit_to(label.as_str(), "session-closed", payload.clone());
+        }
+    });
+    true
+}
+
 #[tauri::command]
 fn update_host(
     updated_host: SavedHost,
     app_handle: AppHandle,
-) -> Result<SavedHost, String> {
+) -> Result<SavedHost, AppError> {
+    let mut updated_host = updated_host;
+    updated_host.group = groups::resolve(&app_handle, updated_host.group.take())?;
+    updated_host.tags = tags::normalize(std::mem::take(&mut updated_host.tags));
+    wol::validate(&updated_host).map_err(|e| AppError::new(ErrorKind::InvalidInput, e))?;
+    validate_remote_modes(&updated_host.details)?;
+    tunnels::validate_all(&updated_host.tunnels)?;
+    let _lock = lock_saved_hosts(&app_handle)?;
     let mut hosts = load_saved_hosts(app_handle.clone())?;
     
     if let Some(pos) = hosts.iter().position(|h| h.id == updated_host.id) {
+        stash_host_secrets(&mut updated_host, Some(&hosts[pos]))?;
         hosts[pos] = updated_host.clone();
     } else {
-        return Err("Host to update not found".to_string());
+        return Err(AppError::new(ErrorKind::NotFound, "Host to update not found"));
     }
 
-    let path = get_connections_path(&app_handle)?;
-    let content = serde_json::to_string_pretty(&hosts).map_err(|e| e.to_string())?;
-    fs::write(path, content).map_err(|e| e.to_string())?;
+    write_saved_hosts(&app_handle, &hosts)?;
     
     Ok(updated_host)
 }
 
 #[tauri::command]
-fn delete_host(host_id: String, app_handle: AppHandle) -> Result<(), String> {
+fn delete_host(host_id: String, app_handle: AppHandle) -> Result<(), AppError> {
+    let _lock = lock_saved_hosts(&app_handle)?;
     let mut hosts = load_saved_hosts(app_handle.clone())?;
     
     hosts.retain(|h| h.id != host_id);
+    for kind in SecretKind::ALL {
+        if let Err(e) = credentials::delete(&host_id, kind) {
+            warn!(target = "credentials", host = %host_id, error = %e, "Failed to remove stored secret");
+        }
+    }
 
-    let path = get_connections_path(&app_handle)?;
-    let content = serde_json::to_string_pretty(&hosts).map_err(|e| e.to_string())?;
-    fs::write(path, content).map_err(|e| e.to_string())?;
+    write_saved_hosts(&app_handle, &hosts)?;
     
     Ok(())
 }
 
+// Fields a duplicate can change in the same call
+#[derive(Debug, Clone, Default, Deserialize)]
+struct HostOverrides {
+    host: Option<String>,
+    username: Option<String>,
+    port: Option<u16>,
+    group: Option<String>,
+}
+
 #[tauri::command]
-fn list_directory(session_id: String, path: String, state: State<'_, AppState>) -> Result<Vec<SftpFile>, String> {
-    let uuid = Uuid::parse_str(&session_id).map_err(|e| e.to_string())?;
-    
-    if let Some(session_state) = state.sessions.get(&uuid) {
-        // Check if SFTP is already initialized
-        let mut sftp_lock = session_state.sftp.lock().unwrap();
-        
-        // Lazy initialization: create SFTP if it doesn't exist
-        if sftp_lock.is_none() {
-            let session_lock = session_state.session.lock().unwrap();
-            match session_lock.sftp() {
-                Ok(sftp) => {
-                    *sftp_lock = Some(sftp);
-                }
-                Err(e) => {
-                    return Err(format!("Failed to initialize SFTP: {}", e));
-                }
+fn duplicate_host(
+    host_id: String,
+    overrides: Option<HostOverrides>,
+    copy_secrets: Option<bool>,
+    app_handle: AppHandle,
+) -> Result<SavedHost, AppError> {
+    let overrides = overrides.unwrap_or_default();
+    let group = match overrides.group {
+        Some(group) => Some(groups::resolve(&app_handle, Some(group))?),
+        None => None,
+    };
+    let _lock = lock_saved_hosts(&app_handle)?;
+    let mut hosts = load_saved_hosts(app_handle.clone())?;
+    let source = hosts
+        .iter()
+        .find(|h| h.id == host_id)
+        .cloned()
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Host not found"))?;
+
+    let mut copy = source.clone();
+    copy.id = Uuid::new_v4().to_string();
+    copy.name = format!("Copy of {}", source.name);
+    copy.pinned = false;
+    copy.sort_order = host_order::next_sort_order(&hosts);
+    if let Some(host) = overrides.host {
+        copy.details.host = host;
+    }
+    if let Some(username) = overrides.username {
+        copy.details.username = username;
+    }
+    if overrides.port.is_some() {
+        copy.details.port = overrides.port;
+    }
+    if let Some(group) = group {
+        copy.group = group;
+    }
+
+    copy.has_password = false;
+    copy.has_passphrase = false;
+    copy.has_totp_secret = false;
+    if copy_secrets.unwrap_or(true) {
+        for (kind, stored, flag) in [
+            (SecretKind::Password, source.has_password, &mut copy.has_password),
+            (SecretKind::Passphrase, source.has_passphrase, &mut copy.has_passphrase),
+            (SecretKind::Totp, source.has_totp_secret, &mut copy.has_totp_secret),
+        ] {
+            if !stored {
+                continue;
+            }
+            if let Some(secret) = credentials::load(&source.id, kind)? {
+                credentials::store(&copy.id, kind, &secret)?;
+                *flag = true;
             }
         }
-        
-        if let Some(sftp) = &*sftp_lock {
-            let entries = sftp.readdir(PathBuf::from(&path).as_path()).map_err(|e| e.to_string())?;
-            
-            let mut files: Vec<SftpFile> = entries.into_iter().map(|(entry_path, stat)| {
-                let name = entry_path
-                    .file_name()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .to_string();
-                
-                let permissions = stat
-                    .perm
-                    .map(|p| format!("{:03o}", p))
-                    .unwrap_or_else(|| "---------".to_string());
-                
-                SftpFile {
-                    name,
-                    is_dir: stat.is_dir(),
-                    size: stat.size.unwrap_or(0),
-                    modified: stat.mtime.unwrap_or(0),
-                    permissions,
-                }
-            }).collect();
+    }
 
-            files.sort_by(|a, b| {
-                if a.is_dir != b.is_dir {
-                    return b.is_dir.cmp(&a.is_dir);
-                }
-                a.name.cmp(&b.name)
-            });
+    hosts.push(copy.clone());
+    write_saved_hosts(&app_handle, &hosts)?;
+    Ok(copy)
+}
 
-            Ok(files)
-        } else {
-            Err("SFTP session not available".to_string())
+#[tauri::command]
+async fn list_directory(
+    session_id: String,
+    path: String,
+    operation_id: Option<String>,
+    // Skips the listing cache
+    force_refresh: Option<bool>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<Vec<SftpFile>, AppError> {
+    let op = sftp_ops::Operation::new("listing", sftp_timeout(&state), operation_id);
+    let ttl = Duration::from_secs(state.settings.get().listing_cache_ttl_secs);
+    let sessions = state.sessions.clone();
+    let window_clone = window.clone();
+    sftp_ops::with_session(&state, session_id.clone(), &window, move |session_state| {
+        listing_cache::list(
+            &sessions,
+            session_state,
+            &session_id,
+            &path,
+            ttl,
+            force_refresh.unwrap_or(false),
+            &op,
+            &window_clone,
+        )
+    })
+    .await
+}
+
+// Lists a directory, directories first, then by name
+fn read_listing(session_state: &SessionState, op: &sftp_ops::Operation, path: &str) -> Result<Vec<SftpFile>, AppError> {
+    let entries = sftp_ops::read_dir(session_state, op, Path::new(path))?;
+
+    let mut files: Vec<SftpFile> = entries.into_iter().map(|(entry_path, stat)| {
+        let name = session_state
+            .charset
+            .decode_name(Path::new(entry_path.file_name().unwrap_or_default()));
+
+        let permissions = stat
+            .perm
+            .map(|p| format!("{:03o}", p))
+            .unwrap_or_else(|| "---------".to_string());
+
+        SftpFile {
+            name,
+            is_dir: stat.is_dir(),
+            size: stat.size.unwrap_or(0),
+            modified: stat.mtime.unwrap_or(0),
+            permissions,
         }
-    } else {
-        Err("Session not found".to_string())
-    }
+    }).collect();
+
+    files.sort_by(|a, b| {
+        if a.is_dir != b.is_dir {
+            return b.is_dir.cmp(&a.is_dir);
+        }
+        a.name.cmp(&b.name)
+    });
+
+    Ok(files)
+}
+
+// Per-operation limit for SFTP metadata calls, from settings
+fn sftp_timeout(state: &AppState) -> Duration {
+    Duration::from_secs(state.settings.get().sftp_timeout_secs)
 }
 
 fn ensure_sftp(session_state: &SessionState) -> Result<(), TransferError> {
-    let mut sftp_lock = session_state.sftp.lock().unwrap();
+    let mut sftp_lock = session_state.lock(&session_state.sftp, "sftp");
 
     if sftp_lock.is_none() {
-        let session_lock = session_state.session.lock().unwrap();
+        let session = session_state
+            .ssh_session()
+            .ok_or_else(|| TransferError::Io("SFTP is not available for this session".to_string()))?;
+        let session_lock = session_state.lock(session, "ssh session");
         let sftp = session_lock
             .sftp()
-            .map_err(|e| TransferError::Io(format!("Failed to initialize SFTP: {}", e)))?;
+            .map_err(|e| TransferError::Remote(AppError::from(e).context("Failed to initialize SFTP")))?;
         info!(target = "sftp", "Initialized SFTP session");
         *sftp_lock = Some(sftp);
     }
@@ -662,83 +2179,135 @@ fn ensure_sftp(session_state: &SessionState) -> Result<(), TransferError> {
     Ok(())
 }
 
-fn emit_transfer_progress(window: &Window, payload: TransferProgressPayload) {
-    let _ = window.emit("transfer-progress", payload);
-}
-
 #[tauri::command]
 async fn download_file(
     session_id: String,
     remote_path: String,
     local_path: String,
+    retry: Option<RetryPolicy>,
+    transfer_id: Option<String>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    // No job or events for a session the window can't use
+    let owners = ownership::authorize(&state, &session_id, &window)?;
+    let transfer = Transfer::start(&window, owners, transfer_id, &session_id, &remote_path, &local_path);
+    let spec = JobSpec {
+        id: transfer.id(),
+        direction: TransferDirection::Download,
+        session_id: &session_id,
+        source_path: &remote_path,
+        destination_path: &local_path,
+        total_bytes: None,
+        retry: retry.clone(),
+    };
+    transfer_jobs::begin(window.app_handle(), spec, transfer.bytes());
+    let app_handle = window.app_handle().clone();
+    let job_id = transfer.id().to_string();
+    let result = download(session_id, remote_path, local_path, retry, transfer.bytes(), window, state).await;
+    transfer_jobs::finish(&app_handle, &job_id, &result);
+    transfer.finish(&result);
+    result
+}
+
+async fn download(
+    session_id: String,
+    remote_path: String,
+    local_path: String,
+    retry: Option<RetryPolicy>,
+    // Non-zero when resuming a job, see transfer_jobs
+    bytes: Arc<AtomicU64>,
     window: Window,
     state: State<'_, AppState>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
+    let owners = ownership::authorize(&state, &session_id, &window)?;
     let sessions = state.sessions.clone();
     let window_clone = window.clone();
+    let policy = retry.unwrap_or_else(|| state.settings.get().transfer_retry);
+    policy.validate()?;
 
     async_runtime::spawn_blocking(move || {
         let uuid = Uuid::parse_str(&session_id).map_err(TransferError::from)?;
-        let session_entry = sessions
-            .get(&uuid)
-            .ok_or(TransferError::SessionMissing)?;
-        let session_state = session_entry.value();
-
-        ensure_sftp(session_state)?;
         info!(target = "sftp_download", session = %session_id, remote = %remote_path, local = %local_path, "Starting download");
 
         let remote_path_buf = PathBuf::from(&remote_path);
-        let mut remote_file = {
-            let sftp_lock = session_state.sftp.lock().unwrap();
-            let sftp = sftp_lock
-                .as_ref()
-                .ok_or(TransferError::SftpNotInitialized)?;
-            sftp.open(&remote_path_buf)
-                .map_err(|e| TransferError::Io(e.to_string()))?
+        let start = bytes.load(Ordering::Relaxed);
+        let mut local_file = if start > 0 {
+            OpenOptions::new().write(true).open(&local_path)
+        } else {
+            File::create(&local_path)
+        }
+        .map_err(TransferError::from)?;
+        let progress = ProgressReporter::start(window_clone.clone(), owners.clone(), session_id.clone(), remote_path.clone(), 0);
+        progress.add(start);
+        // Bytes safely in the local file, where a retry resumes
+        let mut offset = start;
+        let target = retry::RetryTarget {
+            window: &window_clone,
+            owners: &owners,
+            session_id: &session_id,
+            file_path: &remote_path,
         };
 
-        let mut local_file = File::create(&local_path).map_err(TransferError::from)?;
-
-        let total_bytes = remote_file
-            .stat()
-            .ok()
-            .and_then(|s| s.size)
-            .unwrap_or(0);
-        let mut transferred_bytes = 0u64;
-        let mut buffer = [0u8; 32 * 1024];
-
-        loop {
-            let bytes_read = remote_file
-                .read(&mut buffer)
-                .map_err(|e| TransferError::Io(e.to_string()))?;
-
-            if bytes_read == 0 {
-                break;
+        retry::run(&policy, &target, TransferError::is_retryable, |attempt| {
+            let session_entry = sessions
+                .get(&uuid)
+                .ok_or(TransferError::SessionMissing)?;
+            let session_state = session_entry.value();
+            if attempt > 1 {
+                session_state.reset_sftp();
+            }
+            ensure_sftp(session_state)?;
+
+            let mut remote_file = {
+                let sftp_lock = session_state.lock(&session_state.sftp, "sftp");
+                let sftp = sftp_lock
+                    .as_ref()
+                    .ok_or(TransferError::SftpNotInitialized)?;
+                sftp.open(&remote_path_buf)
+                    .map_err(|e| TransferError::Remote(e.into()))?
+            };
+            if let Some(size) = remote_file.stat().ok().and_then(|s| s.size) {
+                progress.set_total(size);
+            }
+            if offset > 0 {
+                info!(target = "sftp_download", session = %session_id, offset, "Resuming download");
+                remote_file
+                    .seek(SeekFrom::Start(offset))
+                    .map_err(|e| TransferError::Remote(e.into()))?;
+                local_file.set_len(offset).map_err(TransferError::from)?;
+                local_file.seek(SeekFrom::Start(offset)).map_err(TransferError::from)?;
             }
 
-            local_file
-                .write_all(&buffer[..bytes_read])
-                .map_err(TransferError::from)?;
+            let mut buffer = [0u8; 32 * 1024];
+            loop {
+                let bytes_read = remote_file
+                    .read(&mut buffer)
+                    .map_err(|e| TransferError::Remote(e.into()))?;
 
-            transferred_bytes += bytes_read as u64;
+                if bytes_read == 0 {
+                    break;
+                }
 
-            emit_transfer_progress(
-                &window_clone,
-                TransferProgressPayload {
-                    session_id: session_id.clone(),
-                    file_path: remote_path_buf.to_string_lossy().into_owned(),
-                    transferred_bytes,
-                    total_bytes,
-                },
-            );
-        }
+                local_file
+                    .write_all(&buffer[..bytes_read])
+                    .map_err(TransferError::from)?;
+
+                offset += bytes_read as u64;
+                progress.add(bytes_read as u64);
+                bytes.store(offset, Ordering::Relaxed);
+                session_state.stats.add_sftp_download(bytes_read);
+            }
+            Ok(())
+        })?;
+        progress.finish();
 
         info!(target = "sftp_download", session = %session_id, "Download complete");
         Ok(())
     })
     .await
     .map_err(|e| e.to_string())?
-    .map_err(|e: TransferError| e.to_string())
+    .map_err(|e: TransferError| AppError::from(e))
 }
 
 #[tauri::command]
@@ -746,92 +2315,175 @@ async fn upload_file(
     session_id: String,
     local_path: String,
     remote_path: String,
+    retry: Option<RetryPolicy>,
+    transfer_id: Option<String>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let owners = ownership::authorize(&state, &session_id, &window)?;
+    let mut transfer = Transfer::start(&window, owners, transfer_id, &session_id, &local_path, &remote_path);
+    let paths = vec![remote_path.clone()];
+    let parameters = json!({ "local_path": &local_path });
+    let spec = JobSpec {
+        id: transfer.id(),
+        direction: TransferDirection::Upload,
+        session_id: &session_id,
+        source_path: &local_path,
+        destination_path: &remote_path,
+        total_bytes: std::fs::metadata(&local_path).map(|m| m.len()).ok(),
+        retry: retry.clone(),
+    };
+    transfer_jobs::begin(window.app_handle(), spec, transfer.bytes());
+    let job_id = transfer.id().to_string();
+    let result = upload(session_id.clone(), local_path, remote_path, retry, transfer.bytes(), window.clone(), state.clone())
+        .await
+        .map(|warning| {
+            if let Some(warning) = warning {
+                transfer.warn(warning);
+            }
+        });
+    transfer_jobs::finish(window.app_handle(), &job_id, &result);
+    transfer.finish(&result);
+    audit::record(&window, &state, &session_id, AuditOperation::Upload, paths, parameters, &result);
+    result
+}
+
+async fn upload(
+    session_id: String,
+    local_path: String,
+    remote_path: String,
+    retry: Option<RetryPolicy>,
+    // Non-zero when resuming a job, see transfer_jobs
+    bytes: Arc<AtomicU64>,
     window: Window,
     state: State<'_, AppState>,
-) -> Result<(), String> {
+) -> Result<Option<String>, AppError> {
+    let owners = ownership::authorize(&state, &session_id, &window)?;
+    read_only::check(&state, &session_id)?;
     let sessions = state.sessions.clone();
     let window_clone = window.clone();
+    let settings = state.settings.get();
+    let policy = retry.unwrap_or(settings.transfer_retry);
+    policy.validate()?;
+    let op = sftp_ops::Operation::new("chmod", sftp_timeout(&state), None);
 
     async_runtime::spawn_blocking(move || {
         let uuid = Uuid::parse_str(&session_id).map_err(TransferError::from)?;
-        let session_entry = sessions
-            .get(&uuid)
-            .ok_or(TransferError::SessionMissing)?;
-        let session_state = session_entry.value();
-
-        ensure_sftp(session_state)?;
         info!(target = "sftp_upload", session = %session_id, local = %local_path, remote = %remote_path, "Starting upload");
 
         let remote_path_buf = PathBuf::from(&remote_path);
-        let mut remote_file = {
-            let sftp_lock = session_state.sftp.lock().unwrap();
-            let sftp = sftp_lock
-                .as_ref()
-                .ok_or(TransferError::SftpNotInitialized)?;
-            sftp.create(&remote_path_buf)
-                .map_err(|e| TransferError::Io(e.to_string()))?
-        };
-
         let mut local_file = File::open(&local_path).map_err(TransferError::from)?;
-
         let total_bytes = local_file.metadata().map(|meta| meta.len()).unwrap_or(0);
-        let mut transferred_bytes = 0u64;
-        let mut buffer = [0u8; 32 * 1024];
-
-        loop {
-            let bytes_read = local_file
-                .read(&mut buffer)
-                .map_err(TransferError::from)?;
+        let progress = ProgressReporter::start(window_clone.clone(), owners.clone(), session_id.clone(), local_path.clone(), total_bytes);
+        // Bytes written to the remote file, where a retry resumes
+        let mut offset = bytes.load(Ordering::Relaxed);
+        let target = retry::RetryTarget {
+            window: &window_clone,
+            owners: &owners,
+            session_id: &session_id,
+            file_path: &local_path,
+        };
 
-            if bytes_read == 0 {
-                break;
+        let result = retry::run(&policy, &target, TransferError::is_retryable, |attempt| {
+            let session_entry = sessions
+                .get(&uuid)
+                .ok_or(TransferError::SessionMissing)?;
+            let session_state = session_entry.value();
+            if attempt > 1 {
+                session_state.reset_sftp();
+            }
+            ensure_sftp(session_state)?;
+
+            let mut remote_file = {
+                let sftp_lock = session_state.lock(&session_state.sftp, "sftp");
+                let sftp = sftp_lock
+                    .as_ref()
+                    .ok_or(TransferError::SftpNotInitialized)?;
+                if offset == 0 {
+                    sftp.create(&remote_path_buf)
+                } else {
+                    sftp.open_mode(&remote_path_buf, OpenFlags::WRITE, 0o644, OpenType::File)
+                }
+                .map_err(|e| TransferError::Remote(e.into()))?
+            };
+            if offset > 0 {
+                // Writes in flight when the connection dropped may not have landed
+                let landed = remote_file.stat().ok().and_then(|s| s.size).unwrap_or(0);
+                offset = offset.min(landed);
+                info!(target = "sftp_upload", session = %session_id, offset, "Resuming upload");
+                progress.set(&local_path, offset, total_bytes);
+                remote_file
+                    .seek(SeekFrom::Start(offset))
+                    .map_err(|e| TransferError::Remote(e.into()))?;
+                local_file.seek(SeekFrom::Start(offset)).map_err(TransferError::from)?;
             }
 
-            remote_file
-                .write_all(&buffer[..bytes_read])
-                .map_err(|e| TransferError::Io(e.to_string()))?;
+            let mut buffer = [0u8; 32 * 1024];
+            loop {
+                let bytes_read = local_file
+                    .read(&mut buffer)
+                    .map_err(TransferError::from)?;
 
-            transferred_bytes += bytes_read as u64;
+                if bytes_read == 0 {
+                    break;
+                }
 
-            emit_transfer_progress(
-                &window_clone,
-                TransferProgressPayload {
-                    session_id: session_id.clone(),
-                    file_path: local_path.clone(),
-                    transferred_bytes,
-                    total_bytes,
-                },
-            );
+                remote_file
+                    .write_all(&buffer[..bytes_read])
+                    .map_err(|e| TransferError::Remote(e.into()))?;
+
+                offset += bytes_read as u64;
+                progress.add(bytes_read as u64);
+                bytes.store(offset, Ordering::Relaxed);
+                session_state.stats.add_sftp_upload(bytes_read);
+            }
+            Ok(())
+        });
+        // Even a failed upload may have left a partial file
+        let session_entry = sessions.get(&uuid);
+        if let Some(session_state) = &session_entry {
+            session_state.listings.invalidate(&remote_path);
         }
+        result?;
+        progress.finish();
 
         info!(target = "sftp_upload", session = %session_id, "Upload complete");
-        Ok(())
+        let Some(session_state) = session_entry else {
+            return Ok(None);
+        };
+        let modes = settings.remote_modes.with(session_state.target.modes.as_ref());
+        if !modes.apply_on_upload {
+            return Ok(None);
+        }
+        Ok(remote_modes::apply_file_mode(&session_state, &op, &remote_path_buf, modes.file_mode))
     })
     .await
     .map_err(|e| e.to_string())?
-    .map_err(|e: TransferError| e.to_string())
+    .map_err(|e: TransferError| AppError::from(e))
 }
 
 #[tauri::command]
 async fn create_directory(
     session_id: String,
     path: String,
+    window: Window,
     state: State<'_, AppState>,
-) -> Result<(), String> {
-    let uuid = Uuid::parse_str(&session_id).map_err(|e| e.to_string())?;
-    
-    if let Some(session_state) = state.sessions.get(&uuid) {
-        let sftp_lock = session_state.sftp.lock().unwrap();
-        if let Some(sftp) = &*sftp_lock {
-            // 0o755 is standard directory permission (rwxr-xr-x)
-            sftp.mkdir(Path::new(&path), 0o755).map_err(|e| e.to_string())?;
-            Ok(())
-        } else {
-            Err("SFTP not initialized".to_string())
-        }
-    } else {
-        Err("Session not found".to_string())
-    }
+) -> Result<(), AppError> {
+    let op = sftp_ops::Operation::new("mkdir", sftp_timeout(&state), None);
+    let paths = vec![path.clone()];
+    let defaults = state.settings.get().remote_modes;
+    let result = sftp_ops::with_session(&state, session_id.clone(), &window, move |session_state| {
+        session_state.read_only.check()?;
+        let mode = defaults.with(session_state.target.modes.as_ref()).dir_mode;
+        let result = sftp_ops::run(session_state, &op, |sftp| sftp.mkdir(Path::new(&path), mode as i32));
+        session_state.listings.invalidate(&path);
+        result.map(|()| mode)
+    })
+    .await;
+    let parameters = json!({ "mode": result.as_ref().ok() });
+    let result = result.map(|_| ());
+    audit::record(&window, &state, &session_id, AuditOperation::Mkdir, paths, parameters, &result);
+    result
 }
 
 #[tauri::command]
@@ -839,26 +2491,28 @@ async fn delete_item(
     session_id: String,
     path: String,
     is_dir: bool,
+    window: Window,
     state: State<'_, AppState>,
-) -> Result<(), String> {
-    let uuid = Uuid::parse_str(&session_id).map_err(|e| e.to_string())?;
-    
-    if let Some(session_state) = state.sessions.get(&uuid) {
-        let sftp_lock = session_state.sftp.lock().unwrap();
-        if let Some(sftp) = &*sftp_lock {
-            let path_obj = Path::new(&path);
+) -> Result<(), AppError> {
+    let op = sftp_ops::Operation::new("delete", sftp_timeout(&state), None);
+    let paths = vec![path.clone()];
+    let result = sftp_ops::with_session(&state, session_id.clone(), &window, move |session_state| {
+        session_state.read_only.check()?;
+        let path_obj = Path::new(&path);
+        let result = sftp_ops::run(session_state, &op, |sftp| {
             if is_dir {
-                sftp.rmdir(path_obj).map_err(|e| e.to_string())?;
+                sftp.rmdir(path_obj)
             } else {
-                sftp.unlink(path_obj).map_err(|e| e.to_string())?;
+                sftp.unlink(path_obj)
             }
-            Ok(())
-        } else {
-            Err("SFTP not initialized".to_string())
-        }
-    } else {
-        Err("Session not found".to_string())
-    }
+        });
+        session_state.listings.invalidate(&path);
+        result
+    })
+    .await;
+    let parameters = json!({ "is_dir": is_dir });
+    audit::record(&window, &state, &session_id, AuditOperation::Delete, paths, parameters, &result);
+    result
 }
 
 #[tauri::command]
@@ -866,26 +2520,26 @@ async fn chmod_item(
     session_id: String,
     path: String,
     mode: u32,
+    window: Window,
     state: State<'_, AppState>,
-) -> Result<(), String> {
-    let uuid = Uuid::parse_str(&session_id).map_err(|e| e.to_string())?;
-    
-    if let Some(session_state) = state.sessions.get(&uuid) {
-        let sftp_lock = session_state.sftp.lock().unwrap();
-        if let Some(sftp) = &*sftp_lock {
-            let path_obj = Path::new(&path);
-            
-            let mut stat = sftp.stat(path_obj).map_err(|e| e.to_string())?;
-            stat.perm = Some(mode);
-            
-            sftp.setstat(path_obj, stat).map_err(|e| e.to_string())?;
-            Ok(())
-        } else {
-            Err("SFTP not initialized".to_string())
-        }
-    } else {
-        Err("Session not found".to_string())
-    }
+) -> Result<(), AppError> {
+    let op = sftp_ops::Operation::new("chmod", sftp_timeout(&state), None);
+    let paths = vec![path.clone()];
+    let result = sftp_ops::with_session(&state, session_id.clone(), &window, move |session_state| {
+        session_state.read_only.check()?;
+        let path_obj = Path::new(&path);
+
+        let mut stat = sftp_ops::run(session_state, &op, |sftp| sftp.stat(path_obj))?;
+        stat.perm = Some(mode);
+
+        let result = sftp_ops::run(session_state, &op, |sftp| sftp.setstat(path_obj, stat.clone()));
+        session_state.listings.invalidate(&path);
+        result
+    })
+    .await;
+    let parameters = json!({ "mode": mode });
+    audit::record(&window, &state, &session_id, AuditOperation::Chmod, paths, parameters, &result);
+    result
 }
 
 #[tauri::command]
@@ -893,149 +2547,91 @@ async fn rename_item(
     session_id: String,
     old_path: String,
     new_path: String,
+    window: Window,
     state: State<'_, AppState>,
-) -> Result<(), String> {
-    let uuid = Uuid::parse_str(&session_id).map_err(|e| e.to_string())?;
-    
-    if let Some(session_state) = state.sessions.get(&uuid) {
-        let sftp_lock = session_state.sftp.lock().unwrap();
-        if let Some(sftp) = &*sftp_lock {
+) -> Result<(), AppError> {
+    let op = sftp_ops::Operation::new("rename", sftp_timeout(&state), None);
+    let paths = vec![old_path.clone(), new_path.clone()];
+    let result = sftp_ops::with_session(&state, session_id.clone(), &window, move |session_state| {
+        session_state.read_only.check()?;
+        let result = sftp_ops::run(session_state, &op, |sftp| {
             sftp.rename(Path::new(&old_path), Path::new(&new_path), None)
-                .map_err(|e| e.to_string())?;
-            Ok(())
-        } else {
-            Err("SFTP not initialized".to_string())
-        }
-    } else {
-        Err("Session not found".to_string())
-    }
-}
-
-#[tauri::command]
-fn load_known_hosts() -> Result<Vec<KnownHostEntry>, String> {
-    let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"))
-        .map_err(|_| "Could not find home directory".to_string())?;
-    let path = Path::new(&home).join(".ssh").join("known_hosts");
-    
-    if !path.exists() {
-        return Ok(Vec::new());
-    }
-
-    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    
-    let mut entries = Vec::new();
-    for (i, line) in content.lines().enumerate() {
-        if line.trim().is_empty() || line.starts_with('#') {
-            continue;
-        }
-        
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        // Format mostly: [marker] hostnames keytype key comment
-        
-        if parts.len() >= 3 {
-            let (marker, hostnames, key_type, key) = if parts[0].starts_with('@') {
-                (parts[0].to_string(), parts[1].to_string(), parts[2].to_string(), parts[3].to_string())
-            } else {
-                ("".to_string(), parts[0].to_string(), parts[1].to_string(), parts[2].to_string())
-            };
-
-            let key_len = key.len();
-            let key_preview = if key_len > 20 {
-                format!("{}...{}", &key[0..10], &key[key_len-10..])
-            } else {
-                key
-            };
-
-            entries.push(KnownHostEntry {
-                line_number: i + 1, // 1-based index for specific line targeting
-                marker,
-                hostnames,
-                key_type,
-                key_preview,
-            });
-        }
-    }
-    
-    Ok(entries)
+        });
+        session_state.listings.invalidate(&old_path);
+        session_state.listings.invalidate(&new_path);
+        result
+    })
+    .await;
+    audit::record(&window, &state, &session_id, AuditOperation::Rename, paths, Value::Null, &result);
+    result
 }
 
 #[tauri::command]
-fn load_ssh_keys(app_handle: AppHandle) -> Result<Vec<SshKeyEntry>, String> {
+fn load_ssh_keys(app_handle: AppHandle) -> Result<Vec<SshKeyEntry>, AppError> {
     let path = get_keychain_path(&app_handle)?;
-    if !path.exists() {
-        return Ok(Vec::new());
-    }
-    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    let keys: Vec<SshKeyEntry> = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    let keys: Vec<SshKeyEntry> = config_file::load(&app_handle, &path)?.unwrap_or_default();
     Ok(keys)
 }
 
 #[tauri::command]
-fn save_ssh_key(key: SshKeyEntry, app_handle: AppHandle) -> Result<SshKeyEntry, String> {
+fn save_ssh_key(key: SshKeyEntry, app_handle: AppHandle) -> Result<SshKeyEntry, AppError> {
+    let path = get_keychain_path(&app_handle)?;
+    let _lock = config_file::lock(&path)?;
     let mut keys = load_ssh_keys(app_handle.clone())?;
     keys.push(key.clone());
     
-    let path = get_keychain_path(&app_handle)?;
-    let content = serde_json::to_string_pretty(&keys).map_err(|e| e.to_string())?;
-    fs::write(path, content).map_err(|e| e.to_string())?;
+    config_file::write(&path, &keys)?;
     Ok(key)
 }
 
 #[tauri::command]
-fn delete_ssh_key(id: String, app_handle: AppHandle) -> Result<(), String> {
+fn delete_ssh_key(id: String, app_handle: AppHandle) -> Result<(), AppError> {
+    let path = get_keychain_path(&app_handle)?;
+    let _lock = config_file::lock(&path)?;
     let mut keys = load_ssh_keys(app_handle.clone())?;
     keys.retain(|k| k.id != id);
     
-    let path = get_keychain_path(&app_handle)?;
-    let content = serde_json::to_string_pretty(&keys).map_err(|e| e.to_string())?;
-    fs::write(path, content).map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-#[tauri::command]
-fn delete_known_host_entry(line_number: usize) -> Result<(), String> {
-    let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"))
-        .map_err(|_| "Could not find home directory".to_string())?;
-    let path = Path::new(&home).join(".ssh").join("known_hosts");
-    
-    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
-    let lines: Vec<&str> = content.lines().collect();
-    
-    // Filter out the line (converting 1-based line_number back to 0-based index)
-    if line_number == 0 || line_number > lines.len() {
-        return Err("Invalid line number".to_string());
-    }
-
-    let new_content = lines.iter().enumerate()
-        .filter(|(i, _)| *i != (line_number - 1)) 
-        .map(|(_, line)| *line)
-        .collect::<Vec<&str>>()
-        .join("\n");
-        
-    // Preserve trailing newline if it existed
-    let final_content = if content.ends_with('\n') {
-        new_content + "\n"
-    } else {
-        new_content
-    };
-
-    fs::write(path, final_content).map_err(|e| e.to_string())?;
-    
+    config_file::write(&path, &keys)?;
     Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(tracing::Level::INFO)
-        .finish();
-    let _ = tracing::subscriber::set_global_default(subscriber);
-
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .manage(AppState::default())
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
+        .on_page_load(|webview, payload| {
+            if payload.event() == tauri::webview::PageLoadEvent::Started {
+                reattach::window_reloaded(webview.app_handle(), webview.label());
+            }
+        })
+        .on_window_event(|window, event| {
+            match event {
+                tauri::WindowEvent::CloseRequested { api, .. } if tray::hide_on_close(window) => {
+                    api.prevent_close();
+                }
+                tauri::WindowEvent::Destroyed => {
+                    ownership::close_window_sessions(window.app_handle(), window.label());
+                }
+                _ => {}
+            }
+        })
+        .setup(|app| {
+            logging::init(app.handle());
+            app_paths::init(app.handle())?;
+            settings::init(app.handle());
+            history::apply_retention(app.handle());
+            schedules::init(app.handle());
+            transfer_jobs::init(app.handle());
+            activity::spawn_idle_monitor(app.handle().clone());
+            stats::spawn_stats_monitor(app.handle().clone());
+            notify::spawn_silence_monitor(app.handle().clone());
+            tray::init(app.handle());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             connect_ssh,
             send_terminal_input,
@@ -1055,13 +2651,160 @@ pub fn run() {
             create_directory,
             delete_item,
             rename_item,
-            load_known_hosts,
-            delete_known_host_entry,
+            keygen::generate_ssh_key,
+            local_keys::list_local_keys,
+            agent::list_agent_identities,
+            agent::add_key_to_agent,
+            local_shells::list_local_shells,
+            completions::get_remote_completions,
+            completions::invalidate_remote_completions,
+            environment::get_remote_environment,
+            fetch::remote_fetch_url,
+            fetch::cancel_remote_fetch,
+            known_hosts::load_known_hosts,
+            known_hosts::match_known_host,
+            known_hosts::delete_known_host_entry,
+            known_hosts::delete_known_host_for_host,
+            known_hosts::accept_changed_host_key,
             load_history,
+            history::query_history,
+            history::export_history,
+            history::prune_history,
+            history::reconnect_from_history,
             clear_history,
             load_ssh_keys,
             save_ssh_key,
-            delete_ssh_key
+            delete_ssh_key,
+            get_session_cwd,
+            get_command_history,
+            install_shell_integration,
+            zmodem_receive,
+            zmodem_send,
+            zmodem_cancel,
+            schedules::list_schedules,
+            schedules::create_schedule,
+            schedules::update_schedule,
+            schedules::delete_schedule,
+            search::search_all,
+            audit::load_audit_log,
+            audit::export_audit_log,
+            read_only::set_session_readonly,
+            command_guard::confirm_dangerous_command,
+            transfer_jobs::list_resumable_transfers,
+            transfer_jobs::resume_transfer,
+            transfer_jobs::abandon_transfer,
+            multi_push::multi_push,
+            multi_push::cancel_multi_push,
+            totp::generate_totp,
+            session_meta::list_sessions,
+            session_meta::set_session_meta,
+            reattach::attach_session,
+            duplicate::duplicate_session,
+            connect_timeline::get_connection_timeline,
+            serial::list_serial_ports,
+            serial::connect_serial,
+            telnet::connect_telnet,
+            get_output_batching,
+            set_output_batching,
+            pause_session_output,
+            resume_session_output,
+            set_output_throttle,
+            get_session_info,
+            snippets::run_snippet,
+            snippets::get_snippet_variables,
+            snippets::get_snippets_for_host,
+            snippet_folders::load_snippet_folders,
+            snippet_folders::create_snippet_folder,
+            snippet_folders::rename_snippet_folder,
+            snippet_folders::delete_snippet_folder,
+            snippet_folders::move_snippet_to_folder,
+            snippet_folders::reorder_snippets,
+            snippet_folders::reorder_snippet_folders,
+            snippet_pack::export_snippets,
+            snippet_pack::import_snippets,
+            get_scrollback,
+            clear_scrollback,
+            set_scrollback_limit,
+            get_session_activity,
+            stats::get_session_stats,
+            stats::subscribe_session_stats,
+            logging::get_log_level,
+            logging::set_log_level,
+            logging::get_recent_logs,
+            logging::get_log_dir,
+            sftp_ops::cancel_sftp_operation,
+            ownership::share_session,
+            notify::arm_notification,
+            notify::disarm_notification,
+            notify::set_notification_focus,
+            wol::wake_host,
+            reachability::check_host_reachable,
+            reachability::start_host_monitor,
+            reachability::stop_host_monitor,
+            discovery::discover_hosts,
+            discovery::stop_discovery,
+            discovery::import_discovered_hosts,
+            processes::get_remote_processes,
+            processes::kill_remote_process,
+            processes::subscribe_remote_processes,
+            processes::unsubscribe_remote_processes,
+            monitor::start_resource_monitor,
+            monitor::stop_resource_monitor,
+            docker::list_remote_containers,
+            docker::open_container_shell,
+            systemd::get_remote_services,
+            systemd::service_action,
+            systemd::get_service_logs,
+            systemd::stop_service_logs,
+            crontab::get_remote_crontab,
+            crontab::set_remote_crontab,
+            logs::open_log_stream,
+            logs::pause_log_stream,
+            logs::resume_log_stream,
+            logs::close_log_stream,
+            grep::grep_remote,
+            grep::cancel_grep,
+            diff::diff_files,
+            preview::preview_remote_file,
+            tunnels::list_host_tunnels,
+            tunnels::add_host_tunnel,
+            tunnels::update_host_tunnel,
+            tunnels::delete_host_tunnel,
+            set_session_charset,
+            run_background_command,
+            get_side_channel_metrics,
+            vault::set_master_password,
+            vault::unlock_vault,
+            vault::lock_vault,
+            vault::get_vault_status,
+            ssh_config::import_ssh_config,
+            putty::import_putty_sessions,
+            termius::import_termius_csv,
+            host_import::commit_host_import,
+            bundle::export_config,
+            bundle::import_config,
+            groups::load_groups,
+            groups::create_group,
+            groups::rename_group,
+            groups::delete_group,
+            groups::move_host_to_group,
+            groups::reorder_groups,
+            tags::add_host_tags,
+            tags::remove_host_tags,
+            tags::list_tags,
+            tags::rename_tag,
+            host_filter::find_hosts,
+            host_order::reorder_hosts,
+            host_order::toggle_pin,
+            duplicate_host,
+            host_bulk::bulk_delete_hosts,
+            host_bulk::bulk_update_hosts,
+            host_stats::get_host_stats,
+            backups::create_backup,
+            backups::list_backups,
+            backups::restore_backup,
+            settings::load_settings,
+            settings::update_settings
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
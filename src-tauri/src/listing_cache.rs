@@ -0,0 +1,245 @@
+// Per-session cache of directory listings.
+//
+// Enabled by a non-zero listing_cache_ttl_secs. A listing younger than the
+// TTL is returned at once and revalidated in the background: the directory
+// is stat'ed, and only when its mtime moved is it listed again. If the new
+// listing differs, the cache is updated and "directory-changed" is emitted
+// so the frontend can list it again. Changes made through this app
+// (upload, delete, rename, mkdir, chmod) invalidate the affected paths, and
+// list_directory's force_refresh skips the cache altogether.
+//
+// A directory's mtime doesn't move when a file inside it is only rewritten,
+// so a changed size shows up once the entry expires rather than on
+// revalidation.
+//
+// The last directory listed is kept, cache or not, as the SFTP panel's
+// current path for duplicate_session to carry over.
+
+use crate::error::AppError;
+use crate::{sftp_ops, SessionState, SftpFile};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::Window;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+struct Entry {
+    files: Vec<SftpFile>,
+    // The directory's own mtime when listed
+    mtime: Option<u64>,
+    fetched_at: Instant,
+    // One background revalidation per path at a time
+    revalidating: bool,
+}
+
+#[derive(Default)]
+pub struct ListingCache {
+    entries: Mutex<HashMap<String, Entry>>,
+    last_path: Mutex<Option<String>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DirectoryChanged {
+    session_id: String,
+    path: String,
+}
+
+// "/srv/www/" and "/srv/www" share an entry
+fn key(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" if path.starts_with('/') => "/",
+        trimmed => trimmed,
+    }
+}
+
+fn parent(path: &str) -> &str {
+    match key(path).rsplit_once('/') {
+        Some(("", _)) => "/",
+        Some((parent, _)) => parent,
+        None => "",
+    }
+}
+
+impl ListingCache {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // A listing younger than `ttl`, and whether the caller should start
+    // revalidating it
+    fn fresh(&self, path: &str, ttl: Duration) -> Option<(Vec<SftpFile>, bool)> {
+        let mut entries = self.lock();
+        let entry = entries
+            .get_mut(key(path))
+            .filter(|e| e.fetched_at.elapsed() < ttl)?;
+        let revalidate = !std::mem::replace(&mut entry.revalidating, true);
+        Some((entry.files.clone(), revalidate))
+    }
+
+    fn store(&self, path: &str, files: Vec<SftpFile>, mtime: Option<u64>) {
+        self.lock().insert(
+            key(path).to_string(),
+            Entry {
+                files,
+                mtime,
+                fetched_at: Instant::now(),
+                revalidating: false,
+            },
+        );
+    }
+
+    /// The directory last listed successfully.
+    pub fn last_path(&self) -> Option<String> {
+        self.last_path
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Drops `path`, everything below it and the listing of its parent.
+    pub fn invalidate(&self, path: &str) {
+        let path = key(path);
+        let below = format!("{}/", path.trim_end_matches('/'));
+        let parent = parent(path);
+        self.lock()
+            .retain(|cached, _| cached != path && cached != parent && !cached.starts_with(&below));
+    }
+}
+
+/// Lists `path` through the cache when `ttl` is non-zero. A fresh hit is
+/// returned as is and revalidated on another thread; `force_refresh` always
+/// goes to the server.
+#[allow(clippy::too_many_arguments)]
+pub fn list(
+    sessions: &Arc<DashMap<Uuid, SessionState>>,
+    session: &SessionState,
+    session_id: &str,
+    path: &str,
+    ttl: Duration,
+    force_refresh: bool,
+    op: &sftp_ops::Operation,
+    window: &Window,
+) -> Result<Vec<SftpFile>, AppError> {
+    let files = list_cached(
+        sessions,
+        session,
+        session_id,
+        path,
+        ttl,
+        force_refresh,
+        op,
+        window,
+    )?;
+    *session
+        .listings
+        .last_path
+        .lock()
+        .unwrap_or_else(|e| e.into_inner()) = Some(path.to_string());
+    Ok(files)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn list_cached(
+    sessions: &Arc<DashMap<Uuid, SessionState>>,
+    session: &SessionState,
+    session_id: &str,
+    path: &str,
+    ttl: Duration,
+    force_refresh: bool,
+    op: &sftp_ops::Operation,
+    window: &Window,
+) -> Result<Vec<SftpFile>, AppError> {
+    if ttl.is_zero() {
+        return crate::read_listing(session, op, path);
+    }
+    let cached = if force_refresh {
+        None
+    } else {
+        session.listings.fresh(path, ttl)
+    };
+    if let Some((files, revalidate)) = cached {
+        if revalidate {
+            spawn_revalidation(
+                sessions.clone(),
+                session_id.to_string(),
+                path.to_string(),
+                op.timeout(),
+                window.clone(),
+            );
+        }
+        return Ok(files);
+    }
+    // Stat first so a change made during the listing isn't taken as seen
+    let mtime = sftp_ops::run(session, op, |sftp| sftp.stat(Path::new(path)))?.mtime;
+    let files = crate::read_listing(session, op, path)?;
+    session.listings.store(path, files.clone(), mtime);
+    Ok(files)
+}
+
+// Returns whether the listing changed
+fn revalidate(session: &SessionState, path: &str, timeout: Duration) -> Result<bool, AppError> {
+    let op = sftp_ops::Operation::new("revalidation", timeout, None);
+    let mtime = sftp_ops::run(session, &op, |sftp| sftp.stat(Path::new(path)))?.mtime;
+    {
+        let mut entries = session.listings.lock();
+        match entries.get_mut(key(path)) {
+            Some(entry) if mtime.is_some() && entry.mtime == mtime => {
+                entry.fetched_at = Instant::now();
+                entry.revalidating = false;
+                return Ok(false);
+            }
+            Some(_) => {}
+            // Invalidated meanwhile, the next listing fetches it
+            None => return Ok(false),
+        }
+    }
+    let files = crate::read_listing(session, &op, path)?;
+    let changed = session
+        .listings
+        .lock()
+        .get(key(path))
+        .is_some_and(|entry| entry.files != files);
+    session.listings.store(path, files, mtime);
+    Ok(changed)
+}
+
+fn spawn_revalidation(
+    sessions: Arc<DashMap<Uuid, SessionState>>,
+    session_id: String,
+    path: String,
+    timeout: Duration,
+    window: Window,
+) {
+    thread::spawn(move || {
+        let Ok(uuid) = Uuid::parse_str(&session_id) else {
+            return;
+        };
+        let Some(session) = sessions.get(&uuid) else {
+            return;
+        };
+        match revalidate(&session, &path, timeout) {
+            Ok(true) => {
+                info!(target = "listing_cache", session = %session_id, path = %path, "Directory changed");
+                session.owners.emit(
+                    &window,
+                    "directory-changed",
+                    DirectoryChanged {
+                        session_id: session_id.clone(),
+                        path,
+                    },
+                );
+            }
+            Ok(false) => {}
+            Err(e) => {
+                // The next listing goes to the server and reports the error
+                debug!(target = "listing_cache", session = %session_id, path = %path, error = %e, "Revalidation failed");
+                session.listings.invalidate(&path);
+            }
+        }
+    });
+}
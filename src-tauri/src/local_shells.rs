@@ -0,0 +1,314 @@
+// Shells installed on this machine, offered as local terminal profiles.
+//
+// On Windows that's every WSL distribution (from "wsl.exe -l -q"), PowerShell
+// 7, Windows PowerShell, cmd and Git Bash, whichever are present; elsewhere
+// the shells in /etc/shells. Each profile has the program, arguments and
+// starting directory to launch it with: the user's home, or the distro's
+// home for WSL. Detection runs once and is cached until a refresh; the
+// custom_shells setting adds the user's own entries after the detected ones.
+
+use crate::error::AppError;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use tauri::{async_runtime, State};
+use tracing::info;
+
+static DETECTED: LazyLock<Mutex<Option<Vec<LocalShell>>>> = LazyLock::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShellKind {
+    Wsl,
+    Pwsh,
+    WindowsPowershell,
+    Cmd,
+    GitBash,
+    Unix,
+    Custom,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalShell {
+    // Stable across refreshes, e.g. "wsl:Ubuntu" or "custom:<id>"
+    pub id: String,
+    pub name: String,
+    pub kind: ShellKind,
+    pub program: String,
+    pub args: Vec<String>,
+    // None leaves it to the shell, as WSL does with --cd ~
+    pub cwd: Option<String>,
+    // For display, e.g. "wsl.exe -d Ubuntu --cd ~"
+    pub command_line: String,
+}
+
+// A user-defined profile from settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomShell {
+    pub id: String,
+    pub name: String,
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+}
+
+/// Checks the custom_shells setting: names and programs set, ids unique.
+pub fn validate_custom(shells: &[CustomShell]) -> Result<(), String> {
+    for (i, shell) in shells.iter().enumerate() {
+        if shell.id.trim().is_empty() {
+            return Err("Custom shells need an id".to_string());
+        }
+        if shell.name.trim().is_empty() {
+            return Err(format!("Custom shell {} needs a name", shell.id));
+        }
+        if shell.program.trim().is_empty() {
+            return Err(format!("Custom shell {} needs a program", shell.name));
+        }
+        if shells[..i].iter().any(|s| s.id == shell.id) {
+            return Err(format!("Two custom shells have the id {}", shell.id));
+        }
+    }
+    Ok(())
+}
+
+fn home_dir() -> Option<String> {
+    std::env::var("USERPROFILE")
+        .or_else(|_| std::env::var("HOME"))
+        .ok()
+}
+
+fn quote_arg(arg: &str) -> String {
+    if arg.is_empty() || arg.contains([' ', '\t', '"']) {
+        format!("\"{}\"", arg.replace('"', "\\\""))
+    } else {
+        arg.to_string()
+    }
+}
+
+fn command_line(program: &str, args: &[String]) -> String {
+    std::iter::once(program)
+        .chain(args.iter().map(String::as_str))
+        .map(quote_arg)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn profile(id: String, name: String, kind: ShellKind, program: &Path, args: &[&str]) -> LocalShell {
+    let program = program.to_string_lossy().into_owned();
+    let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+    LocalShell {
+        command_line: command_line(&program, &args),
+        id,
+        name,
+        kind,
+        program,
+        cwd: match kind {
+            ShellKind::Wsl => None,
+            _ => home_dir(),
+        },
+        args,
+    }
+}
+
+impl From<&CustomShell> for LocalShell {
+    fn from(custom: &CustomShell) -> Self {
+        LocalShell {
+            id: format!("custom:{}", custom.id),
+            name: custom.name.clone(),
+            kind: ShellKind::Custom,
+            program: custom.program.clone(),
+            args: custom.args.clone(),
+            cwd: custom.cwd.clone().or_else(home_dir),
+            command_line: command_line(&custom.program, &custom.args),
+        }
+    }
+}
+
+// wsl.exe writes UTF-16LE unless WSL_UTF8=1 is set, so both are accepted.
+// Entries can carry a BOM, NULs and \r.
+#[cfg(windows)]
+fn decode_wsl_list(output: &[u8]) -> Vec<String> {
+    // Distro names are ASCII, so UTF-16 shows as a NUL second byte
+    let utf16 = output.starts_with(&[0xff, 0xfe]) || output.get(1) == Some(&0);
+    let text = if utf16 {
+        let (text, _, _) = encoding_rs::UTF_16LE.decode(output);
+        text.into_owned()
+    } else {
+        String::from_utf8_lossy(output).into_owned()
+    };
+    text.lines()
+        .map(|line| line.trim_matches(|c: char| c == '\u{feff}' || c == '\0' || c.is_whitespace()))
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(windows)]
+fn wsl_distributions() -> Vec<String> {
+    use std::os::windows::process::CommandExt;
+    use std::process::Command;
+    use tracing::warn;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    // Without WSL, or without distros, wsl.exe prints help and fails
+    match Command::new("wsl.exe")
+        .args(["-l", "-q"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+    {
+        Ok(output) if output.status.success() => decode_wsl_list(&output.stdout),
+        Ok(_) => Vec::new(),
+        Err(e) => {
+            warn!(target = "local_shells", error = %e, "Could not list WSL distributions");
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn wsl_distributions() -> Vec<String> {
+    Vec::new()
+}
+
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+fn env_path(var: &str, rest: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(std::env::var_os(var)?).join(rest);
+    path.is_file().then_some(path)
+}
+
+fn detect_windows() -> Vec<LocalShell> {
+    let mut shells = Vec::new();
+    let wsl =
+        env_path("SystemRoot", r"System32\wsl.exe").unwrap_or_else(|| PathBuf::from("wsl.exe"));
+    for distro in wsl_distributions() {
+        shells.push(profile(
+            format!("wsl:{}", distro),
+            format!("{} (WSL)", distro),
+            ShellKind::Wsl,
+            &wsl,
+            &["-d", &distro, "--cd", "~"],
+        ));
+    }
+    if let Some(pwsh) =
+        find_in_path("pwsh.exe").or_else(|| env_path("ProgramFiles", r"PowerShell\7\pwsh.exe"))
+    {
+        shells.push(profile(
+            "pwsh".into(),
+            "PowerShell".into(),
+            ShellKind::Pwsh,
+            &pwsh,
+            &["-NoLogo"],
+        ));
+    }
+    if let Some(powershell) = env_path(
+        "SystemRoot",
+        r"System32\WindowsPowerShell\v1.0\powershell.exe",
+    ) {
+        shells.push(profile(
+            "powershell".into(),
+            "Windows PowerShell".into(),
+            ShellKind::WindowsPowershell,
+            &powershell,
+            &["-NoLogo"],
+        ));
+    }
+    if let Some(cmd) = std::env::var_os("ComSpec")
+        .map(PathBuf::from)
+        .filter(|p| p.is_file())
+        .or_else(|| env_path("SystemRoot", r"System32\cmd.exe"))
+    {
+        shells.push(profile(
+            "cmd".into(),
+            "Command Prompt".into(),
+            ShellKind::Cmd,
+            &cmd,
+            &[],
+        ));
+    }
+    if let Some(bash) = env_path("ProgramFiles", r"Git\bin\bash.exe")
+        .or_else(|| env_path("LOCALAPPDATA", r"Programs\Git\bin\bash.exe"))
+    {
+        shells.push(profile(
+            "git-bash".into(),
+            "Git Bash".into(),
+            ShellKind::GitBash,
+            &bash,
+            &["--login", "-i"],
+        ));
+    }
+    shells
+}
+
+// $SHELL first, then the rest of /etc/shells that exist
+fn detect_unix() -> Vec<LocalShell> {
+    let listed = std::fs::read_to_string("/etc/shells").unwrap_or_default();
+    let mut paths: Vec<String> = std::env::var("SHELL").into_iter().collect();
+    for line in listed.lines().map(str::trim) {
+        if line.starts_with('/') && !paths.iter().any(|p| p == line) {
+            paths.push(line.to_string());
+        }
+    }
+    paths
+        .iter()
+        .map(Path::new)
+        .filter(|path| path.is_file())
+        .map(|path| {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string());
+            profile(
+                format!("unix:{}", path.display()),
+                name,
+                ShellKind::Unix,
+                path,
+                &["-l"],
+            )
+        })
+        .collect()
+}
+
+fn detect() -> Vec<LocalShell> {
+    let shells = if cfg!(windows) {
+        detect_windows()
+    } else {
+        detect_unix()
+    };
+    info!(
+        target = "local_shells",
+        count = shells.len(),
+        "Detected local shells"
+    );
+    shells
+}
+
+/// Lists the local shell profiles: detected ones, cached until `refresh`,
+/// followed by the custom_shells setting.
+#[tauri::command]
+pub async fn list_local_shells(
+    refresh: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<LocalShell>, AppError> {
+    let custom = state.settings.get().custom_shells;
+    let refresh = refresh.unwrap_or(false);
+    // wsl.exe can take a while to answer when the WSL VM is cold
+    let mut shells = async_runtime::spawn_blocking(move || {
+        let mut cached = DETECTED.lock().unwrap_or_else(|e| e.into_inner());
+        if refresh || cached.is_none() {
+            *cached = Some(detect());
+        }
+        cached.clone().unwrap_or_default()
+    })
+    .await
+    .map_err(|e| AppError::from(e.to_string()))?;
+    shells.extend(custom.iter().map(LocalShell::from));
+    Ok(shells)
+}
@@ -0,0 +1,384 @@
+// Log viewer streams: the journal or a log file, followed on a side channel.
+//
+// A journal stream runs "journalctl -f -o json" with optional unit and
+// priority filters and sends parsed entries; a file stream runs "tail -F" and
+// sends raw lines. Either can be narrowed by a grep on the remote side (for
+// the journal it matches anywhere in the JSON entry). A session can have any
+// number of streams; each ends with its session, on close_log_stream or when
+// the command exits.
+//
+// Lines queue up to MAX_QUEUED and go out as "log-entries" events every
+// FLUSH_INTERVAL, at most MAX_BATCH at a time. A paused stream keeps reading
+// and queueing. Whatever doesn't fit in the queue is dropped and counted, and
+// the next batch starts with a "N lines skipped" marker entry.
+
+use crate::error::{AppError, ErrorKind};
+use crate::side_channel::{shell_quote, stream_on_side_channel};
+use crate::systemd::{ensure_systemd, validate_unit};
+use crate::{ownership, AppState};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{async_runtime, AppHandle, Emitter, Manager, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const DEFAULT_LINES: u32 = 100;
+const MAX_LINES: u32 = 10_000;
+const MAX_QUEUED: usize = 5_000;
+const MAX_BATCH: usize = 500;
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+const PRIORITIES: &[&str] = &[
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+static STREAMS: LazyLock<DashMap<String, Arc<LogStream>>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LogSource {
+    Journal {
+        unit: Option<String>,
+        // A name or number, or a range like "err..warning"
+        priority: Option<String>,
+    },
+    File {
+        path: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEntry {
+    // Milliseconds since the epoch; journal entries only
+    pub timestamp_ms: Option<u64>,
+    pub unit: Option<String>,
+    // 0 (emerg) to 7 (debug)
+    pub priority: Option<u8>,
+    pub message: String,
+    // Set on the marker entry that stands for dropped lines
+    pub skipped: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+struct LogEntriesPayload {
+    stream_id: String,
+    session_id: String,
+    entries: Vec<LogEntry>,
+}
+
+#[derive(Clone, Serialize)]
+struct LogStreamEndPayload {
+    stream_id: String,
+    session_id: String,
+    // None when closed
+    exit_status: Option<i32>,
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct LogQueue {
+    entries: VecDeque<LogEntry>,
+    skipped: u64,
+}
+
+struct LogStream {
+    stop: AtomicBool,
+    paused: AtomicBool,
+    queue: Mutex<LogQueue>,
+}
+
+impl LogStream {
+    fn push(&self, entry: LogEntry) {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        if queue.entries.len() >= MAX_QUEUED {
+            queue.skipped += 1;
+        } else {
+            queue.entries.push_back(entry);
+        }
+    }
+
+    // The next batch, led by a marker when lines were dropped before it
+    fn take_batch(&self) -> Vec<LogEntry> {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        let mut batch = Vec::new();
+        if queue.skipped > 0 {
+            batch.push(LogEntry {
+                timestamp_ms: None,
+                unit: None,
+                priority: None,
+                message: format!("{} lines skipped", queue.skipped),
+                skipped: Some(queue.skipped),
+            });
+            queue.skipped = 0;
+        }
+        let n = queue.entries.len().min(MAX_BATCH);
+        batch.extend(queue.entries.drain(..n));
+        batch
+    }
+}
+
+fn validate_priority(priority: &str) -> Result<(), AppError> {
+    let valid = priority.split("..").all(|p| {
+        PRIORITIES.contains(&p) || p.parse::<u8>().is_ok_and(|n| n < PRIORITIES.len() as u8)
+    }) && priority.split("..").count() <= 2;
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid priority: {}", priority),
+        ))
+    }
+}
+
+fn build_command(source: &LogSource, lines: u32, grep: Option<&str>) -> String {
+    let base = match source {
+        LogSource::Journal { unit, priority } => {
+            let mut journalctl = format!("journalctl -f -n {} --no-pager -o json", lines);
+            if let Some(unit) = unit {
+                journalctl.push_str(&format!(" -u {}", unit));
+            }
+            if let Some(priority) = priority {
+                journalctl.push_str(&format!(" -p {}", priority));
+            }
+            // Root sees the whole journal, others only what their groups allow
+            format!("{{ sudo -n {0} 2>/dev/null || {0}; }}", journalctl)
+        }
+        LogSource::File { path } => format!("tail -n {} -F {}", lines, shell_quote(path)),
+    };
+    match grep {
+        Some(pattern) => format!(
+            "{} | grep --line-buffered -e {}",
+            base,
+            shell_quote(pattern)
+        ),
+        None => base,
+    }
+}
+
+// MESSAGE is a string, or an array of bytes when it isn't valid UTF-8
+fn journal_message(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(bytes) => {
+            let bytes: Vec<u8> = bytes
+                .iter()
+                .filter_map(|b| b.as_u64().map(|b| b as u8))
+                .collect();
+            String::from_utf8_lossy(&bytes).into_owned()
+        }
+        _ => String::new(),
+    }
+}
+
+fn parse_journal_line(line: &str) -> LogEntry {
+    let Ok(Value::Object(fields)) = serde_json::from_str::<Value>(line) else {
+        // Not JSON, e.g. "-- No entries --"
+        return parse_raw_line(line);
+    };
+    let text = |key: &str| fields.get(key).and_then(Value::as_str);
+    LogEntry {
+        timestamp_ms: text("__REALTIME_TIMESTAMP")
+            .and_then(|t| t.parse::<u64>().ok())
+            .map(|micros| micros / 1000),
+        unit: text("_SYSTEMD_UNIT")
+            .or_else(|| text("SYSLOG_IDENTIFIER"))
+            .map(str::to_string),
+        priority: text("PRIORITY").and_then(|p| p.parse().ok()),
+        message: fields
+            .get("MESSAGE")
+            .map(journal_message)
+            .unwrap_or_default(),
+        skipped: None,
+    }
+}
+
+fn parse_raw_line(line: &str) -> LogEntry {
+    LogEntry {
+        timestamp_ms: None,
+        unit: None,
+        priority: None,
+        message: line.to_string(),
+        skipped: None,
+    }
+}
+
+fn check_owner(app_handle: &AppHandle, session_id: &str, window: &Window) -> Result<(), AppError> {
+    let state = app_handle.state::<AppState>();
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    ownership::check(&session, session_id, window)
+}
+
+fn flush(window: &Window, stream_id: &str, session_id: &str, stream: &LogStream) {
+    loop {
+        let entries = stream.take_batch();
+        if entries.is_empty() {
+            return;
+        }
+        let full = entries.len() >= MAX_BATCH;
+        let _ = window.emit(
+            "log-entries",
+            LogEntriesPayload {
+                stream_id: stream_id.to_string(),
+                session_id: session_id.to_string(),
+                entries,
+            },
+        );
+        if !full {
+            return;
+        }
+    }
+}
+
+/// Starts streaming a log and returns its stream id. The last `lines` lines
+/// come first, then everything new, as "log-entries" events until
+/// "log-stream-end".
+#[tauri::command]
+pub async fn open_log_stream(
+    session_id: String,
+    source: LogSource,
+    grep: Option<String>,
+    lines: Option<u32>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<String, AppError> {
+    check_owner(&app_handle, &session_id, &window)?;
+    match &source {
+        LogSource::Journal { unit, priority } => {
+            if let Some(unit) = unit {
+                validate_unit(unit)?;
+            }
+            if let Some(priority) = priority {
+                validate_priority(priority)?;
+            }
+        }
+        LogSource::File { path } if path.trim().is_empty() => {
+            return Err(AppError::new(
+                ErrorKind::InvalidInput,
+                "Log file path is empty",
+            ));
+        }
+        LogSource::File { .. } => {}
+    }
+    let grep = grep.filter(|g| !g.is_empty());
+    let lines = lines.unwrap_or(DEFAULT_LINES).min(MAX_LINES);
+    let command = build_command(&source, lines, grep.as_deref());
+    let journal = matches!(source, LogSource::Journal { .. });
+
+    async_runtime::spawn_blocking(move || {
+        if journal {
+            ensure_systemd(&app_handle, &session_id)?;
+        }
+        let stream_id = Uuid::new_v4().to_string();
+        let stream = Arc::new(LogStream {
+            stop: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            queue: Mutex::new(LogQueue::default()),
+        });
+        STREAMS.insert(stream_id.clone(), stream.clone());
+        info!(target = "logs", session = %session_id, stream = %stream_id, "Log stream opened");
+
+        // Sends what the reader queues while the stream runs
+        let done = Arc::new(AtomicBool::new(false));
+        {
+            let (window, stream_id, session_id) =
+                (window.clone(), stream_id.clone(), session_id.clone());
+            let (stream, done) = (stream.clone(), done.clone());
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    thread::sleep(FLUSH_INTERVAL);
+                    if !stream.paused.load(Ordering::Relaxed) {
+                        flush(&window, &stream_id, &session_id, &stream);
+                    }
+                }
+            });
+        }
+
+        let id = stream_id.clone();
+        thread::spawn(move || {
+            let state = app_handle.state::<AppState>();
+            let mut pending = Vec::new();
+            let result = stream_on_side_channel(
+                &state.sessions,
+                &session_id,
+                &command,
+                &stream.stop,
+                |chunk| {
+                    pending.extend_from_slice(chunk);
+                    // Only whole lines; the rest waits for the next chunk
+                    let Some(end) = pending.iter().rposition(|&b| b == b'\n') else {
+                        return;
+                    };
+                    let text: Vec<u8> = pending.drain(..=end).collect();
+                    for line in String::from_utf8_lossy(&text).lines() {
+                        stream.push(if journal {
+                            parse_journal_line(line)
+                        } else {
+                            parse_raw_line(line)
+                        });
+                    }
+                },
+            );
+            done.store(true, Ordering::Relaxed);
+            STREAMS.remove(&id);
+            if let Err(e) = &result {
+                warn!(target = "logs", session = %session_id, error = %e, "Log stream failed");
+            }
+            // What was read before the end still goes out, unless closed
+            if !stream.stop.load(Ordering::Relaxed) {
+                flush(&window, &id, &session_id, &stream);
+            }
+            let (exit_status, error) = match result {
+                Ok(status) => (status, None),
+                Err(e) => (None, Some(e.message)),
+            };
+            let _ = window.emit(
+                "log-stream-end",
+                LogStreamEndPayload {
+                    stream_id: id,
+                    session_id,
+                    exit_status,
+                    error,
+                },
+            );
+        });
+        Ok(stream_id)
+    })
+    .await
+    .map_err(|e| AppError::from(e.to_string()))?
+}
+
+/// Holds back events; lines keep queueing, up to the skip limit.
+#[tauri::command]
+pub fn pause_log_stream(stream_id: String) -> Result<(), AppError> {
+    let stream = STREAMS
+        .get(&stream_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Log stream not found"))?;
+    stream.paused.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn resume_log_stream(stream_id: String) -> Result<(), AppError> {
+    let stream = STREAMS
+        .get(&stream_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Log stream not found"))?;
+    stream.paused.store(false, Ordering::Relaxed);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn close_log_stream(stream_id: String) {
+    if let Some((_, stream)) = STREAMS.remove(&stream_id) {
+        stream.stop.store(true, Ordering::Relaxed);
+    }
+}
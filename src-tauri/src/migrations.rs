@@ -0,0 +1,143 @@
+// Schema versions of the config files and the steps between them.
+//
+// Every file is stored as {"version": N, "data": ...}. Files from before the
+// envelope existed hold the bare data and count as version 0. Loading runs
+// each step from the file's version up to CURRENT_VERSION in order; a file
+// from a newer app is refused rather than guessed at.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+pub const CURRENT_VERSION: u32 = 1;
+
+// MIGRATIONS[n] upgrades the data of a version n file to version n + 1
+type Migration = fn(file: &str, data: Value) -> Result<Value, String>;
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1];
+
+#[derive(Serialize)]
+struct Envelope<'a, T: Serialize> {
+    version: u32,
+    data: &'a T,
+}
+
+/// Wraps data in a current-version envelope.
+pub fn to_envelope<T: Serialize>(data: &T) -> Result<String, String> {
+    let envelope = Envelope {
+        version: CURRENT_VERSION,
+        data,
+    };
+    serde_json::to_string_pretty(&envelope).map_err(|e| e.to_string())
+}
+
+/// Splits a parsed file into its version and data.
+pub fn from_envelope(value: Value) -> Result<(u32, Value), String> {
+    let Value::Object(mut fields) = value else {
+        return Ok((0, value));
+    };
+    match (fields.remove("version"), fields.remove("data")) {
+        (Some(version), Some(data)) => {
+            let version = version
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or("Invalid schema version")?;
+            Ok((version, data))
+        }
+        (version, data) => {
+            // Not an envelope, put the fields back
+            fields.extend(version.map(|v| ("version".to_string(), v)));
+            fields.extend(data.map(|d| ("data".to_string(), d)));
+            Ok((0, Value::Object(fields)))
+        }
+    }
+}
+
+/// Upgrades `data` from `version` to the current version.
+pub fn upgrade(file: &str, version: u32, mut data: Value) -> Result<Value, String> {
+    if version > CURRENT_VERSION {
+        return Err(format!(
+            "{} was written by a newer version of Terminoda (schema {}), please update",
+            file, version
+        ));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        data = migration(file, data)?;
+    }
+    Ok(data)
+}
+
+// Older hosts could have null or missing tags and metadata
+fn v0_to_v1(file: &str, mut data: Value) -> Result<Value, String> {
+    if file != "connections.json" {
+        return Ok(data);
+    }
+    for host in data.as_array_mut().into_iter().flatten() {
+        let Some(host) = host.as_object_mut() else {
+            continue;
+        };
+        for (field, empty) in [
+            ("tags", Value::Array(Vec::new())),
+            ("metadata", Value::Object(Map::new())),
+        ] {
+            if host.get(field).is_none_or(Value::is_null) {
+                host.insert(field.to_string(), empty);
+            }
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // connections.json as written before the envelope, with the null and
+    // missing fields older versions left
+    fn legacy_hosts() -> Value {
+        json!([
+            {"id": "1", "name": "web", "tags": null},
+            {"id": "2", "name": "db", "tags": ["prod"], "metadata": {"rack": "b2"}},
+            {"id": "3", "name": "old"}
+        ])
+    }
+
+    #[test]
+    fn upgrades_an_unversioned_hosts_file() {
+        let (version, data) = from_envelope(legacy_hosts()).unwrap();
+        assert_eq!(version, 0);
+        let data = upgrade("connections.json", version, data).unwrap();
+        assert_eq!(
+            data,
+            json!([
+                {"id": "1", "name": "web", "tags": [], "metadata": {}},
+                {"id": "2", "name": "db", "tags": ["prod"], "metadata": {"rack": "b2"}},
+                {"id": "3", "name": "old", "tags": [], "metadata": {}}
+            ])
+        );
+    }
+
+    #[test]
+    fn current_version_passes_through_unchanged() {
+        let file: Value = serde_json::from_str(&to_envelope(&legacy_hosts()).unwrap()).unwrap();
+        let (version, data) = from_envelope(file).unwrap();
+        assert_eq!(version, CURRENT_VERSION);
+        assert_eq!(
+            upgrade("connections.json", version, data).unwrap(),
+            legacy_hosts()
+        );
+    }
+
+    #[test]
+    fn future_version_is_refused() {
+        let file = json!({"version": CURRENT_VERSION + 1, "data": []});
+        let (version, data) = from_envelope(file).unwrap();
+        let error = upgrade("connections.json", version, data).unwrap_err();
+        assert!(error.contains("newer version"), "{}", error);
+    }
+
+    #[test]
+    fn object_without_an_envelope_is_version_zero() {
+        let settings = json!({"version": "2.1", "theme": "dark"});
+        assert_eq!(from_envelope(settings.clone()).unwrap(), (0, settings));
+    }
+}
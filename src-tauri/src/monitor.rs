@@ -0,0 +1,287 @@
+// CPU, memory, load and disk samples for the tab header sparklines.
+//
+// Each round is a single exec of PROBE on a side channel, which prints the
+// sources it could read under "@section" markers: /proc on Linux, sysctl on
+// the BSDs and macOS, df everywhere. Whatever a target can't answer is left
+// out of the sample instead of failing it, so macOS, which has no cheap CPU
+// counter, still gets memory, load and disk. CPU percent is the change in
+// busy ticks between two rounds, so the first sample has none. The monitor
+// stops by itself once the session is gone.
+
+use crate::error::{AppError, ErrorKind};
+use crate::side_channel::run_on_side_channel;
+use crate::{ownership, AppState};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{Emitter, Manager, State, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const PROBE: &str = "echo @stat; head -n 1 /proc/stat 2>/dev/null; \
+    echo @meminfo; cat /proc/meminfo 2>/dev/null; \
+    echo @loadavg; cat /proc/loadavg 2>/dev/null; \
+    echo @sysctl; sysctl hw.physmem hw.memsize hw.pagesize vm.stats.vm.v_free_count \
+    vm.stats.vm.v_inactive_count vm.stats.vm.v_cache_count vm.loadavg kern.cp_time 2>/dev/null; \
+    echo @df; df -Pk / 2>/dev/null | tail -n 1";
+const EXEC_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_INTERVAL_SECS: u64 = 5;
+const MIN_INTERVAL_SECS: u64 = 1;
+
+// Running monitors by session
+static MONITORS: LazyLock<DashMap<Uuid, Arc<AtomicBool>>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResourceSample {
+    pub session_id: String,
+    pub timestamp: u64, // Unix timestamp in milliseconds
+    pub cpu_percent: Option<f64>,
+    pub mem_used_bytes: Option<u64>,
+    pub mem_total_bytes: Option<u64>,
+    // 1, 5 and 15 minutes
+    pub load_average: Option<[f64; 3]>,
+    // Filesystem holding "/"
+    pub disk_used_bytes: Option<u64>,
+    pub disk_total_bytes: Option<u64>,
+}
+
+// Busy and total CPU ticks since boot
+#[derive(Debug, Clone, Copy)]
+struct CpuTicks {
+    busy: u64,
+    total: u64,
+}
+
+fn sections(output: &str) -> HashMap<&str, Vec<&str>> {
+    let mut sections: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut current = "";
+    for line in output.lines() {
+        match line.strip_prefix('@') {
+            Some(name) => current = name.trim(),
+            None if !line.trim().is_empty() => sections.entry(current).or_default().push(line),
+            None => {}
+        }
+    }
+    sections
+}
+
+// "cpu  user nice system idle iowait irq softirq steal ..."
+fn proc_stat_ticks(line: &str) -> Option<CpuTicks> {
+    let values: Vec<u64> = line
+        .strip_prefix("cpu ")?
+        .split_whitespace()
+        .take(8)
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    if values.len() < 4 {
+        return None;
+    }
+    let total = values.iter().sum();
+    let idle = values[3] + values.get(4).copied().unwrap_or(0);
+    Some(CpuTicks {
+        busy: total - idle,
+        total,
+    })
+}
+
+// kern.cp_time is "user nice sys intr idle"
+fn cp_time_ticks(value: &str) -> Option<CpuTicks> {
+    let values: Vec<u64> = value
+        .split_whitespace()
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    if values.len() != 5 {
+        return None;
+    }
+    let total = values.iter().sum();
+    Some(CpuTicks {
+        busy: total - values[4],
+        total,
+    })
+}
+
+fn meminfo(lines: &[&str]) -> (Option<u64>, Option<u64>) {
+    let kb = |key: &str| {
+        lines.iter().find_map(|line| {
+            let value = line.strip_prefix(key)?.strip_prefix(':')?;
+            value.split_whitespace().next()?.parse::<u64>().ok()
+        })
+    };
+    let total = kb("MemTotal");
+    // Kernels before 3.14 have no MemAvailable
+    let available = kb("MemAvailable")
+        .or_else(|| Some(kb("MemFree")? + kb("Buffers").unwrap_or(0) + kb("Cached").unwrap_or(0)));
+    match (total, available) {
+        (Some(total), Some(available)) => (
+            Some(total.saturating_sub(available) * 1024),
+            Some(total * 1024),
+        ),
+        (total, _) => (None, total.map(|t| t * 1024)),
+    }
+}
+
+fn parse_load(text: &str) -> Option<[f64; 3]> {
+    let values: Vec<f64> = text
+        .trim_matches(|c: char| c == '{' || c == '}' || c.is_whitespace())
+        .split_whitespace()
+        .take(3)
+        .filter_map(|v| v.replace(',', ".").parse().ok())
+        .collect();
+    values.try_into().ok()
+}
+
+// "Filesystem 1024-blocks Used Available Capacity Mounted-on"
+fn parse_df(line: &str) -> Option<(u64, u64)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    // The device name may contain spaces, so count from the end
+    let n = fields.len();
+    if n < 6 {
+        return None;
+    }
+    let total: u64 = fields[n - 5].parse().ok()?;
+    let used: u64 = fields[n - 4].parse().ok()?;
+    Some((used * 1024, total * 1024))
+}
+
+fn parse_probe(output: &str) -> (ResourceSample, Option<CpuTicks>) {
+    let sections = sections(output);
+    let section = |name: &str| sections.get(name).map(Vec::as_slice).unwrap_or_default();
+    let sysctl: HashMap<&str, &str> = section("sysctl")
+        .iter()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':').or_else(|| line.split_once('='))?;
+            Some((key.trim(), value.trim()))
+        })
+        .collect();
+    let sysctl_u64 = |key: &str| sysctl.get(key).and_then(|v| v.parse::<u64>().ok());
+
+    let mut sample = ResourceSample::default();
+    let ticks = section("stat")
+        .first()
+        .and_then(|line| proc_stat_ticks(line))
+        .or_else(|| sysctl.get("kern.cp_time").and_then(|v| cp_time_ticks(v)));
+
+    let (used, total) = meminfo(section("meminfo"));
+    sample.mem_used_bytes = used;
+    sample.mem_total_bytes = total;
+    if sample.mem_total_bytes.is_none() {
+        sample.mem_total_bytes = sysctl_u64("hw.physmem").or_else(|| sysctl_u64("hw.memsize"));
+        // FreeBSD counts free pages; inactive and cache pages are reclaimable
+        let free_pages = sysctl_u64("vm.stats.vm.v_free_count").map(|free| {
+            free + sysctl_u64("vm.stats.vm.v_inactive_count").unwrap_or(0)
+                + sysctl_u64("vm.stats.vm.v_cache_count").unwrap_or(0)
+        });
+        if let (Some(total), Some(free), Some(page)) = (
+            sample.mem_total_bytes,
+            free_pages,
+            sysctl_u64("hw.pagesize"),
+        ) {
+            sample.mem_used_bytes = Some(total.saturating_sub(free * page));
+        }
+    }
+
+    sample.load_average = section("loadavg")
+        .first()
+        .and_then(|line| parse_load(line))
+        .or_else(|| sysctl.get("vm.loadavg").and_then(|v| parse_load(v)));
+    if let Some((used, total)) = section("df").first().and_then(|line| parse_df(line)) {
+        sample.disk_used_bytes = Some(used);
+        sample.disk_total_bytes = Some(total);
+    }
+    (sample, ticks)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Emits "resource-sample" for the session every `interval_secs` until
+/// stopped or the session closes. Starting again replaces the interval.
+#[tauri::command]
+pub fn start_resource_monitor(
+    session_id: String,
+    interval_secs: Option<u64>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    {
+        let session = state
+            .sessions
+            .get(&uuid)
+            .ok_or_else(|| AppError::session_not_found(&session_id))?;
+        ownership::check(&session, &session_id, &window)?;
+        if session.ssh_session().is_none() {
+            return Err(AppError::new(
+                ErrorKind::InvalidInput,
+                "Resource monitoring needs an SSH session",
+            ));
+        }
+    }
+    let interval = Duration::from_secs(
+        interval_secs
+            .unwrap_or(DEFAULT_INTERVAL_SECS)
+            .max(MIN_INTERVAL_SECS),
+    );
+    let stop = Arc::new(AtomicBool::new(false));
+    if let Some(previous) = MONITORS.insert(uuid, stop.clone()) {
+        previous.store(true, Ordering::Relaxed);
+    }
+    info!(target = "monitor", session = %session_id, ?interval, "Starting resource monitor");
+
+    thread::spawn(move || {
+        let app_handle = window.app_handle().clone();
+        let mut previous: Option<CpuTicks> = None;
+        loop {
+            let state = app_handle.state::<AppState>();
+            if stop.load(Ordering::Relaxed) || !state.sessions.contains_key(&uuid) {
+                break;
+            }
+            let started = Instant::now();
+            match run_on_side_channel(&state.sessions, &session_id, PROBE, EXEC_TIMEOUT) {
+                Ok(output) => {
+                    let (mut sample, ticks) = parse_probe(&output.stdout);
+                    if let (Some(prev), Some(now)) = (previous, ticks) {
+                        let total = now.total.saturating_sub(prev.total);
+                        if total > 0 {
+                            let busy = now.busy.saturating_sub(prev.busy);
+                            sample.cpu_percent = Some(busy as f64 * 100.0 / total as f64);
+                        }
+                    }
+                    previous = ticks;
+                    sample.session_id = session_id.clone();
+                    sample.timestamp = now_millis();
+                    if !stop.load(Ordering::Relaxed) {
+                        let _ = window.emit("resource-sample", sample);
+                    }
+                }
+                Err(e) => {
+                    warn!(target = "monitor", session = %session_id, error = %e, "Resource probe failed")
+                }
+            }
+            let round_end = started + interval.max(started.elapsed());
+            while Instant::now() < round_end && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(250));
+            }
+        }
+        MONITORS.remove_if(&uuid, |_, flag| Arc::ptr_eq(flag, &stop));
+        info!(target = "monitor", session = %session_id, "Resource monitor stopped");
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_resource_monitor(session_id: String) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    if let Some((_, stop)) = MONITORS.remove(&uuid) {
+        stop.store(true, Ordering::Relaxed);
+    }
+    Ok(())
+}
@@ -0,0 +1,479 @@
+// Pushing one local file to many saved hosts at once.
+//
+// multi_push uploads the file to every host in host_ids, at most
+// `concurrency` at a time (transfer_concurrency by default). A host with an
+// SSH session already open in the calling window is uploaded to over that
+// session's SFTP channel; any other host gets a short-lived connection of
+// its own, signed in with the host's stored credentials and closed when the
+// upload ends; a pooled jump host connection is shared, see bastion. Nobody
+// is there to answer prompts, so a changed host key fails the host instead
+// of asking.
+//
+// Progress is emitted as "multi-push-progress" and each outcome as
+// "multi-push-host-completed", both keyed by host id. Each host's upload is
+// also a transfer of its own, ending in "transfer-completed" or
+// "transfer-failed" with the transfer id <push id>/<host id>, see
+// transfer_events. Events go to the calling window only. A failing host
+// doesn't stop the others. cancel_multi_push skips the hosts not started
+// yet; uploads already running finish.
+
+use crate::audit::{self, AuditOperation};
+use crate::bastion;
+use crate::credentials::{self, SecretKind};
+use crate::error::{AppError, ErrorKind};
+use crate::ownership::SessionOwners;
+use crate::settings::Settings;
+use crate::transfer_events::Transfer;
+use crate::{
+    authenticate_session, ensure_sftp, known_hosts, load_saved_hosts, ownership, AppState,
+    SavedHost, SessionState, TransferError,
+};
+use dashmap::DashMap;
+use serde::Serialize;
+use serde_json::json;
+use ssh2::Session;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{async_runtime, AppHandle, Manager, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const MAX_CONCURRENCY: usize = 16;
+const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+// Cancel flags of running pushes, by push id
+static PUSHES: LazyLock<DashMap<String, Arc<AtomicBool>>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PushOutcome {
+    Succeeded,
+    Failed,
+    // Cancelled before it started, or not a saved host
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostPushResult {
+    pub host_id: String,
+    // None when no saved host has the id
+    pub name: Option<String>,
+    pub outcome: PushOutcome,
+    pub error: Option<String>,
+    pub bytes: u64,
+    // Went over a session that was already open
+    pub reused_session: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PushSummary {
+    pub push_id: String,
+    // In the order of host_ids
+    pub results: Vec<HostPushResult>,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PushProgress {
+    push_id: String,
+    host_id: String,
+    transferred: u64,
+    total: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct HostCompleted {
+    push_id: String,
+    result: HostPushResult,
+}
+
+struct Push<'a> {
+    push_id: &'a str,
+    window: &'a Window,
+    // The calling window alone, whatever sessions the push goes over
+    owners: Arc<SessionOwners>,
+    state: &'a AppState,
+    local_path: &'a str,
+    remote_path: &'a str,
+    total: u64,
+    settings: &'a Settings,
+    hosts: &'a [SavedHost],
+    cancel: &'a AtomicBool,
+}
+
+impl Push<'_> {
+    // Copies the local file to `remote`, emitting progress for the host
+    fn copy(
+        &self,
+        host_id: &str,
+        remote: &mut impl Write,
+        bytes: &AtomicU64,
+        mut on_write: impl FnMut(usize),
+    ) -> Result<u64, AppError> {
+        let mut local = File::open(self.local_path)?;
+        let mut buffer = [0u8; 32 * 1024];
+        let mut transferred = 0u64;
+        let mut last_emit = Instant::now();
+        loop {
+            let read = local.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            remote.write_all(&buffer[..read])?;
+            transferred += read as u64;
+            bytes.store(transferred, Ordering::Relaxed);
+            on_write(read);
+            if last_emit.elapsed() >= PROGRESS_INTERVAL {
+                last_emit = Instant::now();
+                self.progress(host_id, transferred);
+            }
+        }
+        self.progress(host_id, transferred);
+        Ok(transferred)
+    }
+
+    fn progress(&self, host_id: &str, transferred: u64) {
+        self.owners.emit(
+            self.window,
+            "multi-push-progress",
+            PushProgress {
+                push_id: self.push_id.to_string(),
+                host_id: host_id.to_string(),
+                transferred,
+                total: self.total,
+            },
+        );
+    }
+
+    // An SSH session to the host that the window may use
+    fn open_session(&self, host_id: &str) -> Option<Uuid> {
+        self.state
+            .sessions
+            .iter()
+            .find(|entry| {
+                entry.target.host_id.as_deref() == Some(host_id)
+                    && entry.ssh_session().is_some()
+                    && ownership::check(entry, &entry.key().to_string(), self.window).is_ok()
+            })
+            .map(|entry| *entry.key())
+    }
+
+    fn over_session(
+        &self,
+        host_id: &str,
+        session: &SessionState,
+        bytes: &AtomicU64,
+    ) -> Result<u64, AppError> {
+        session.read_only.check()?;
+        ensure_sftp(session)?;
+        let mut remote = {
+            let sftp = session.lock(&session.sftp, "sftp");
+            let sftp = sftp.as_ref().ok_or(TransferError::SftpNotInitialized)?;
+            sftp.create(Path::new(self.remote_path))?
+        };
+        let result = self.copy(host_id, &mut remote, bytes, |n| {
+            session.stats.add_sftp_upload(n)
+        });
+        session.listings.invalidate(self.remote_path);
+        result
+    }
+
+    fn over_own_connection(&self, host: &SavedHost, bytes: &AtomicU64) -> Result<u64, AppError> {
+        if host.details.read_only == Some(true) {
+            return Err(AppError::new(ErrorKind::ReadOnly, "Host is read-only"));
+        }
+        // The lease keeps a jump host connection up until the upload ends
+        let (sess, _lease) = self.connect(host)?;
+        let result = sess
+            .sftp()
+            .map_err(|e| AppError::from(e).context("Failed to initialize SFTP"))
+            .and_then(|sftp| {
+                let mut remote = sftp.create(Path::new(self.remote_path))?;
+                self.copy(&host.id, &mut remote, bytes, |_| {})
+            });
+        let _ = sess.disconnect(None, "Push finished", None);
+        result
+    }
+
+    fn connect(&self, host: &SavedHost) -> Result<(Session, Option<bastion::Lease>), AppError> {
+        let mut details = host.details.clone();
+        if details.password.is_none() {
+            details.password = credentials::load(&host.id, SecretKind::Password)?;
+        }
+        if details.passphrase.is_none() {
+            details.passphrase = credentials::load(&host.id, SecretKind::Passphrase)?;
+        }
+        if details.totp_autofill == Some(true) && details.totp_secret.is_none() {
+            details.totp_secret = credentials::load(&host.id, SecretKind::Totp)?;
+        }
+        let port = details.port.unwrap_or(22);
+        let files = known_hosts::files(
+            &self.settings.known_hosts_files,
+            details.known_hosts_file.as_deref(),
+        )?;
+
+        let (tcp, lease) = match bastion::resolve(&details, self.hosts)? {
+            Some(jump) => {
+                let (tcp, lease) =
+                    self.state
+                        .bastions
+                        .connect(&jump, &details.host, port, self.settings)?;
+                (tcp, Some(lease))
+            }
+            None => {
+                let tcp = TcpStream::connect((details.host.as_str(), port))
+                    .map_err(|e| AppError::from(e).or_kind(ErrorKind::ConnectionFailed))?;
+                (tcp, None)
+            }
+        };
+        let mut sess = Session::new()?;
+        sess.set_tcp_stream(tcp);
+        sess.set_timeout(details.timeout.unwrap_or(DEFAULT_TIMEOUT_MS));
+        sess.handshake()
+            .map_err(|e| AppError::from(e).or_kind(ErrorKind::HandshakeFailed))?;
+        if let Some((blob, _)) = sess.host_key() {
+            if known_hosts::check_host_key(&files, &details.host, port, blob).is_some() {
+                return Err(AppError::new(
+                    ErrorKind::HostKeyChanged,
+                    format!("The host key of {} has changed", details.host),
+                ));
+            }
+        }
+        authenticate_session(&sess, &details, self.settings, None)?;
+        Ok((sess, lease))
+    }
+
+    fn push_to(&self, host_id: &str) -> HostPushResult {
+        let mut result = HostPushResult {
+            host_id: host_id.to_string(),
+            name: None,
+            outcome: PushOutcome::Skipped,
+            error: None,
+            bytes: 0,
+            reused_session: false,
+        };
+        if self.cancel.load(Ordering::Relaxed) {
+            result.error = Some("Cancelled".to_string());
+            return result;
+        }
+        let Some(host) = self.hosts.iter().find(|h| h.id == host_id) else {
+            result.error = Some("No saved host with this id".to_string());
+            return result;
+        };
+        result.name = Some(host.name.clone());
+
+        let session_id = self.open_session(host_id);
+        let transfer = Transfer::start(
+            self.window,
+            self.owners.clone(),
+            Some(format!("{}/{}", self.push_id, host_id)),
+            // Empty over a connection of the push's own
+            &session_id.map(|uuid| uuid.to_string()).unwrap_or_default(),
+            self.local_path,
+            self.remote_path,
+        );
+        let bytes = transfer.bytes();
+        let pushed = match session_id.and_then(|uuid| self.state.sessions.get(&uuid)) {
+            Some(session) => {
+                result.reused_session = true;
+                self.over_session(host_id, &session, &bytes)
+            }
+            None => self.over_own_connection(host, &bytes),
+        };
+        transfer.finish(&pushed.as_ref().map(|_| ()).map_err(AppError::clone));
+        let parameters = json!({ "local_path": self.local_path, "push_id": self.push_id });
+        let paths = vec![self.remote_path.to_string()];
+        match session_id.filter(|_| result.reused_session) {
+            Some(uuid) => audit::record(
+                self.window,
+                self.state,
+                &uuid.to_string(),
+                AuditOperation::Upload,
+                paths,
+                parameters,
+                &pushed,
+            ),
+            None => audit::record_on_host(
+                self.window,
+                &host.details.host,
+                &host.details.username,
+                AuditOperation::Upload,
+                paths,
+                parameters,
+                &pushed,
+            ),
+        }
+        match pushed {
+            Ok(bytes) => {
+                info!(target = "multi_push", push = %self.push_id, host = %host.details.host, bytes, "Pushed file");
+                result.outcome = PushOutcome::Succeeded;
+                result.bytes = bytes;
+            }
+            Err(e) => {
+                warn!(target = "multi_push", push = %self.push_id, host = %host.details.host, error = %e, "Push failed");
+                result.outcome = PushOutcome::Failed;
+                result.error = Some(e.message);
+            }
+        }
+        result
+    }
+}
+
+// A destination ending in / is a directory, the file keeps its name
+fn destination(local_path: &Path, remote_path: &str) -> Result<String, AppError> {
+    let remote_path = remote_path.trim();
+    if remote_path.is_empty() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "A remote path is required",
+        ));
+    }
+    if !remote_path.ends_with('/') {
+        return Ok(remote_path.to_string());
+    }
+    let name = local_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| AppError::new(ErrorKind::InvalidInput, "The local path has no file name"))?;
+    Ok(format!("{}{}", remote_path, name))
+}
+
+/// Uploads `local_path` to `remote_path` on each of `host_ids`, at most
+/// `concurrency` hosts at a time. A `remote_path` ending in / keeps the
+/// file's name. Returns every host's outcome once all are done; see the
+/// module comment for the events emitted meanwhile.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn multi_push(
+    local_path: String,
+    remote_path: String,
+    host_ids: Vec<String>,
+    concurrency: Option<usize>,
+    push_id: Option<String>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<PushSummary, AppError> {
+    let metadata = std::fs::metadata(&local_path)?;
+    if !metadata.is_file() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Only a single file can be pushed",
+        ));
+    }
+    let remote_path = destination(&PathBuf::from(&local_path), &remote_path)?;
+    let mut seen = HashSet::new();
+    let host_ids: Vec<String> = host_ids
+        .into_iter()
+        .filter(|id| seen.insert(id.clone()))
+        .collect();
+    if host_ids.is_empty() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "No hosts to push to",
+        ));
+    }
+    let settings = app_handle.state::<AppState>().settings.get();
+    let concurrency = concurrency
+        .unwrap_or(settings.transfer_concurrency)
+        .clamp(1, MAX_CONCURRENCY)
+        .min(host_ids.len());
+    let push_id = push_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+    let cancel = Arc::new(AtomicBool::new(false));
+    if PUSHES.contains_key(&push_id) {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "A push with this id is already running",
+        ));
+    }
+    PUSHES.insert(push_id.clone(), cancel.clone());
+    let hosts = match load_saved_hosts(app_handle.clone()) {
+        Ok(hosts) => hosts,
+        Err(e) => {
+            PUSHES.remove(&push_id);
+            return Err(e);
+        }
+    };
+
+    info!(target = "multi_push", push = %push_id, hosts = host_ids.len(), concurrency, %remote_path, "Starting push");
+    let id = push_id.clone();
+    let results = async_runtime::spawn_blocking(move || {
+        let state = app_handle.state::<AppState>();
+        let push = Push {
+            push_id: &id,
+            window: &window,
+            owners: Arc::new(SessionOwners::new(window.label())),
+            state: &state,
+            local_path: &local_path,
+            remote_path: &remote_path,
+            total: metadata.len(),
+            settings: &settings,
+            hosts: &hosts,
+            cancel: &cancel,
+        };
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<HostPushResult>>> = Mutex::new(vec![None; host_ids.len()]);
+        thread::scope(|s| {
+            for _ in 0..concurrency {
+                s.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(host_id) = host_ids.get(i) else {
+                        break;
+                    };
+                    let result = push.push_to(host_id);
+                    push.owners.emit(
+                        &window,
+                        "multi-push-host-completed",
+                        HostCompleted {
+                            push_id: id.clone(),
+                            result: result.clone(),
+                        },
+                    );
+                    results.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(result);
+                });
+            }
+        });
+        results
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+    })
+    .await;
+    PUSHES.remove(&push_id);
+    let results = results.map_err(|e| AppError::from(e.to_string()))?;
+
+    let count = |outcome| results.iter().filter(|r| r.outcome == outcome).count();
+    let summary = PushSummary {
+        push_id,
+        succeeded: count(PushOutcome::Succeeded),
+        failed: count(PushOutcome::Failed),
+        skipped: count(PushOutcome::Skipped),
+        results,
+    };
+    info!(target = "multi_push", push = %summary.push_id, succeeded = summary.succeeded, failed = summary.failed, skipped = summary.skipped, "Push finished");
+    Ok(summary)
+}
+
+/// Cancels a running multi_push: hosts not started yet are skipped.
+/// Returns false if it already finished.
+#[tauri::command]
+pub fn cancel_multi_push(push_id: String) -> bool {
+    match PUSHES.get(&push_id) {
+        Some(cancel) => {
+            cancel.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,270 @@
+// Desktop notifications for long-running commands.
+//
+// A tab arms its session with arm_notification. With on_prompt, a command
+// reported by shell integration (OSC 133;D) that ran longer than
+// min_duration_secs raises a notification. With on_silence, output that kept
+// coming for at least min_activity_secs and then stopped for silence_secs
+// does, for shells without prompt markers. Nothing is shown while the tab is
+// the focused one in a focused window; the frontend reports tab focus through
+// set_notification_focus. The body is the last line of output.
+
+use crate::error::{AppError, ErrorKind};
+use crate::shell_integration::CommandRecord;
+use crate::triggers::strip_escapes;
+use crate::{ownership, AppState};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State, Window};
+use tauri_plugin_notification::NotificationExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// Enough of the output to find its last line
+const TAIL_LEN: usize = 1024;
+const MAX_BODY_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub on_prompt: bool,
+    pub on_silence: bool,
+    // Shorter commands don't notify
+    pub min_duration_secs: u64,
+    pub silence_secs: u64,
+    // How long output must have been flowing before silence counts
+    pub min_activity_secs: u64,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            on_prompt: true,
+            on_silence: false,
+            min_duration_secs: 30,
+            silence_secs: 10,
+            min_activity_secs: 10,
+        }
+    }
+}
+
+impl NotifyConfig {
+    fn validate(&self) -> Result<(), String> {
+        if !self.on_prompt && !self.on_silence {
+            return Err("Choose at least one of on_prompt and on_silence".to_string());
+        }
+        if self.silence_secs == 0 {
+            return Err("Silence must be at least 1 second".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub struct CommandNotifier {
+    window: Window,
+    host: String,
+    config: Mutex<Option<NotifyConfig>>,
+    // Checked on every output chunk, so it doesn't take the lock
+    armed: AtomicBool,
+    tab_focused: AtomicBool,
+    tail: Mutex<Vec<u8>>,
+    // Unix milliseconds; a burst start of 0 means no output since the last
+    // silence
+    burst_start: AtomicU64,
+    last_output: AtomicU64,
+}
+
+impl CommandNotifier {
+    pub fn new(host: &str, window: Window) -> Self {
+        Self {
+            window,
+            host: host.to_string(),
+            config: Mutex::new(None),
+            armed: AtomicBool::new(false),
+            tab_focused: AtomicBool::new(false),
+            tail: Mutex::new(Vec::new()),
+            burst_start: AtomicU64::new(0),
+            last_output: AtomicU64::new(0),
+        }
+    }
+
+    fn config(&self) -> Option<NotifyConfig> {
+        *self.config.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_config(&self, config: Option<NotifyConfig>) {
+        *self.config.lock().unwrap_or_else(|e| e.into_inner()) = config;
+        self.armed.store(config.is_some(), Ordering::Relaxed);
+        self.burst_start.store(0, Ordering::Relaxed);
+        self.tail.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// Called by the reader with decoded output.
+    pub fn on_output(&self, data: &[u8]) {
+        if !self.armed.load(Ordering::Relaxed) {
+            return;
+        }
+        let now = now_millis();
+        let silence_ms = self.config().map_or(0, |c| c.silence_secs * 1000);
+        let last = self.last_output.swap(now, Ordering::Relaxed);
+        if self.burst_start.load(Ordering::Relaxed) == 0 || now.saturating_sub(last) >= silence_ms {
+            self.burst_start.store(now, Ordering::Relaxed);
+        }
+
+        let mut tail = self.tail.lock().unwrap_or_else(|e| e.into_inner());
+        tail.extend_from_slice(data);
+        if tail.len() > TAIL_LEN {
+            let excess = tail.len() - TAIL_LEN;
+            tail.drain(..excess);
+        }
+    }
+
+    /// Called when shell integration reports a finished command.
+    pub fn command_finished(&self, record: &CommandRecord) {
+        let Some(config) = self.config() else {
+            return;
+        };
+        // The output that followed was the command's, not a burst to report
+        self.burst_start.store(0, Ordering::Relaxed);
+        if !config.on_prompt || record.duration_ms < config.min_duration_secs * 1000 {
+            return;
+        }
+        let title = match (&record.command, record.exit_code) {
+            (Some(command), Some(0) | None) => format!("{} finished on {}", command, self.host),
+            (Some(command), Some(code)) => {
+                format!("{} failed on {} (exit {})", command, self.host, code)
+            }
+            (None, Some(0) | None) => format!("Command finished on {}", self.host),
+            (None, Some(code)) => format!("Command failed on {} (exit {})", self.host, code),
+        };
+        self.show(&title);
+    }
+
+    // Called by the monitor; true if the session went quiet after a burst
+    fn check_silence(&self) -> bool {
+        let Some(config) = self.config().filter(|c| c.on_silence) else {
+            return false;
+        };
+        let burst_start = self.burst_start.load(Ordering::Relaxed);
+        if burst_start == 0 {
+            return false;
+        }
+        let last = self.last_output.load(Ordering::Relaxed);
+        if now_millis().saturating_sub(last) < config.silence_secs * 1000 {
+            return false;
+        }
+        self.burst_start.store(0, Ordering::Relaxed);
+        last.saturating_sub(burst_start) >= config.min_activity_secs * 1000
+    }
+
+    fn last_line(&self) -> String {
+        let tail = self.tail.lock().unwrap_or_else(|e| e.into_inner());
+        let text = strip_escapes(&tail);
+        let line = text
+            .lines()
+            .map(str::trim)
+            .rfind(|line| !line.is_empty())
+            .unwrap_or_default();
+        line.chars().take(MAX_BODY_CHARS).collect()
+    }
+
+    fn show(&self, title: &str) {
+        let window_focused = self.window.is_focused().unwrap_or(false);
+        if window_focused && self.tab_focused.load(Ordering::Relaxed) {
+            return;
+        }
+        let result = self
+            .window
+            .notification()
+            .builder()
+            .title(title)
+            .body(self.last_line())
+            .show();
+        if let Err(e) = result {
+            warn!(target = "notify", error = %e, "Failed to show notification");
+        }
+    }
+}
+
+/// Checks armed sessions for output that went quiet.
+pub fn spawn_silence_monitor(app_handle: AppHandle) {
+    thread::spawn(move || loop {
+        thread::sleep(CHECK_INTERVAL);
+        let state = app_handle.state::<AppState>();
+        for entry in state.sessions.iter() {
+            if entry.notify.check_silence() {
+                entry
+                    .notify
+                    .show(&format!("Output stopped on {}", entry.notify.host));
+            }
+        }
+    });
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn with_notifier(
+    state: &AppState,
+    session_id: &str,
+    window: &Window,
+    f: impl FnOnce(&CommandNotifier),
+) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    ownership::check(&session, session_id, window)?;
+    f(&session.notify);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn arm_notification(
+    session_id: String,
+    config: Option<NotifyConfig>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let config = config.unwrap_or_default();
+    config
+        .validate()
+        .map_err(|e| AppError::new(ErrorKind::InvalidInput, e))?;
+    with_notifier(&state, &session_id, &window, |notifier| {
+        notifier.set_config(Some(config))
+    })?;
+    info!(target = "notify", session = %session_id, ?config, "Armed notifications");
+    Ok(())
+}
+
+#[tauri::command]
+pub fn disarm_notification(
+    session_id: String,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    with_notifier(&state, &session_id, &window, |notifier| {
+        notifier.set_config(None)
+    })
+}
+
+// Notifications are held back while the tab is the active one
+#[tauri::command]
+pub fn set_notification_focus(
+    session_id: String,
+    focused: bool,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    with_notifier(&state, &session_id, &window, |notifier| {
+        notifier.tab_focused.store(focused, Ordering::Relaxed)
+    })
+}
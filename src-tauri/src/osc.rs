@@ -0,0 +1,144 @@
+// Incremental scanner for OSC (Operating System Command) escape sequences.
+//
+// The reader thread feeds raw output chunks through the scanner; sequences
+// that are split across reads are buffered until their terminator arrives.
+// The scanner only observes the stream, the bytes forwarded to xterm.js are
+// left untouched.
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+// Anything longer than this is not a sequence we care about
+const MAX_OSC_LEN: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OscEvent {
+    /// A complete OSC payload, e.g. "7;file://host/path"
+    Osc(String),
+    /// A BEL outside of any escape sequence
+    Bell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScanState {
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+pub struct OscScanner {
+    state: ScanState,
+    payload: Vec<u8>,
+    overflowed: bool,
+}
+
+impl Default for OscScanner {
+    fn default() -> Self {
+        Self {
+            state: ScanState::Ground,
+            payload: Vec::new(),
+            overflowed: false,
+        }
+    }
+}
+
+impl OscScanner {
+    pub fn feed(&mut self, data: &[u8]) -> Vec<OscEvent> {
+        let mut events = Vec::new();
+
+        for &byte in data {
+            match self.state {
+                ScanState::Ground => match byte {
+                    ESC => self.state = ScanState::Escape,
+                    BEL => events.push(OscEvent::Bell),
+                    _ => {}
+                },
+                ScanState::Escape => {
+                    if byte == b']' {
+                        self.payload.clear();
+                        self.overflowed = false;
+                        self.state = ScanState::Osc;
+                    } else if byte != ESC {
+                        self.state = ScanState::Ground;
+                    }
+                }
+                ScanState::Osc => match byte {
+                    BEL => self.finish(&mut events),
+                    ESC => self.state = ScanState::OscEscape,
+                    _ => self.push(byte),
+                },
+                ScanState::OscEscape => {
+                    if byte == b'\\' {
+                        self.finish(&mut events);
+                    } else {
+                        // Any other escape aborts the OSC and starts a new sequence
+                        self.payload.clear();
+                        self.state = if byte == b']' {
+                            ScanState::Osc
+                        } else {
+                            ScanState::Ground
+                        };
+                    }
+                }
+            }
+        }
+
+        events
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.payload.len() < MAX_OSC_LEN {
+            self.payload.push(byte);
+        } else {
+            self.overflowed = true;
+        }
+    }
+
+    fn finish(&mut self, events: &mut Vec<OscEvent>) {
+        if !self.overflowed {
+            events.push(OscEvent::Osc(
+                String::from_utf8_lossy(&self.payload).into_owned(),
+            ));
+        }
+        self.payload.clear();
+        self.overflowed = false;
+        self.state = ScanState::Ground;
+    }
+}
+
+/// Extracts the title from an OSC 0 (icon and title) or OSC 2 payload.
+pub fn parse_title(payload: &str) -> Option<String> {
+    let title = payload
+        .strip_prefix("0;")
+        .or_else(|| payload.strip_prefix("2;"))?;
+    Some(title.to_string())
+}
+
+/// Extracts the path from an OSC 7 payload ("7;file://host/path").
+pub fn parse_osc7_cwd(payload: &str) -> Option<String> {
+    let uri = payload.strip_prefix("7;")?;
+    let rest = uri.strip_prefix("file://")?;
+    // Skip the hostname, the path starts at the first slash
+    let path = &rest[rest.find('/')?..];
+    Some(percent_decode(path))
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(value) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(value);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
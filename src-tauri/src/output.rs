@@ -0,0 +1,639 @@
+// Terminal output pipeline shared by all session reader threads.
+//
+// Raw chunks are scanned for escape sequences as soon as they arrive, then
+// coalesced so bulk output (cat of a large file, `yes`) is emitted as a few
+// large terminal-output events instead of one event per read.
+
+use crate::activity::SessionActivity;
+use crate::charset::{OutputDecoder, SessionCharset};
+use crate::echo_filter::EchoFilter;
+use crate::notify::CommandNotifier;
+use crate::osc::{self, OscEvent, OscScanner};
+use crate::ownership::SessionOwners;
+use crate::stats::SessionStats;
+use crate::triggers::{self, OutputTail, StartupSequence, SudoAutofill};
+use crate::{
+    CommandFinishedPayload, CommandTracker, CwdChangedPayload, OutputThrottledPayload,
+    TerminalBellPayload, TerminalOutputPayload, TerminalTitlePayload, ZmodemControl,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tauri::Window;
+use tracing::info;
+
+const DEFAULT_MAX_BATCH_BYTES: usize = 32 * 1024;
+const DEFAULT_MAX_BATCH_DELAY_MS: u64 = 12;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct OutputBatchConfig {
+    pub max_bytes: usize,
+    pub max_delay_ms: u64,
+}
+
+impl Default for OutputBatchConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_BATCH_BYTES,
+            max_delay_ms: DEFAULT_MAX_BATCH_DELAY_MS,
+        }
+    }
+}
+
+// Live thresholds, read by every reader thread on each chunk
+pub struct OutputBatchSettings {
+    max_bytes: AtomicUsize,
+    max_delay_ms: AtomicU64,
+}
+
+impl Default for OutputBatchSettings {
+    fn default() -> Self {
+        Self {
+            max_bytes: AtomicUsize::new(DEFAULT_MAX_BATCH_BYTES),
+            max_delay_ms: AtomicU64::new(DEFAULT_MAX_BATCH_DELAY_MS),
+        }
+    }
+}
+
+impl OutputBatchSettings {
+    pub fn get(&self) -> OutputBatchConfig {
+        OutputBatchConfig {
+            max_bytes: self.max_bytes.load(Ordering::Relaxed),
+            max_delay_ms: self.max_delay_ms.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn set(&self, config: OutputBatchConfig) {
+        // A zero size would flush on every byte, a zero delay disables batching
+        self.max_bytes
+            .store(config.max_bytes.max(1024), Ordering::Relaxed);
+        self.max_delay_ms
+            .store(config.max_delay_ms.min(1000), Ordering::Relaxed);
+    }
+}
+
+pub struct OutputBatcher {
+    settings: Arc<OutputBatchSettings>,
+    buffer: Vec<u8>,
+    first_byte_at: Option<Instant>,
+}
+
+impl OutputBatcher {
+    pub fn new(settings: Arc<OutputBatchSettings>) -> Self {
+        Self {
+            settings,
+            buffer: Vec::new(),
+            first_byte_at: None,
+        }
+    }
+
+    /// Appends data, returning a batch when the size threshold is reached.
+    pub fn push(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.is_empty() {
+            return None;
+        }
+        if self.first_byte_at.is_none() {
+            self.first_byte_at = Some(Instant::now());
+        }
+        self.buffer.extend_from_slice(data);
+
+        let config = self.settings.get();
+        if self.buffer.len() >= config.max_bytes || config.max_delay_ms == 0 {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the pending batch once its delay has elapsed.
+    pub fn poll(&mut self) -> Option<Vec<u8>> {
+        match self.time_until_flush() {
+            Some(remaining) if remaining.is_zero() => self.flush(),
+            _ => None,
+        }
+    }
+
+    /// How long the reader may wait before the pending batch is due.
+    pub fn time_until_flush(&self) -> Option<Duration> {
+        let first = self.first_byte_at?;
+        let delay = Duration::from_millis(self.settings.get().max_delay_ms);
+        Some(delay.saturating_sub(first.elapsed()))
+    }
+
+    pub fn flush(&mut self) -> Option<Vec<u8>> {
+        self.first_byte_at = None;
+        if self.buffer.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut self.buffer))
+        }
+    }
+}
+
+// Raw output kept per session so a reattaching terminal can replay it
+pub const DEFAULT_SCROLLBACK_BYTES: usize = 4 * 1024 * 1024;
+
+pub struct Scrollback {
+    buffer: VecDeque<u8>,
+    limit: usize,
+}
+
+impl Scrollback {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            buffer: VecDeque::new(),
+            limit,
+        }
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend(data);
+        // Trim in chunks rather than on every push
+        if self.buffer.len() > self.limit + self.limit / 8 {
+            self.trim();
+        }
+    }
+
+    fn trim(&mut self) {
+        if self.buffer.len() <= self.limit {
+            return;
+        }
+        self.buffer.drain(..self.buffer.len() - self.limit);
+        // Start on a fresh line so replay never begins inside an escape
+        // sequence or a multi-byte character
+        let search = self.buffer.len().min(4096);
+        let cut = match self.buffer.range(..search).position(|&b| b == b'\n') {
+            Some(newline) => newline + 1,
+            None => self
+                .buffer
+                .iter()
+                .position(|&b| (b & 0xc0) != 0x80)
+                .unwrap_or(0),
+        };
+        self.buffer.drain(..cut);
+    }
+
+    pub fn contents(&self) -> Vec<u8> {
+        let (front, back) = self.buffer.as_slices();
+        [front, back].concat()
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+#[derive(Default)]
+struct FlowState {
+    paused: bool,
+    closed: bool,
+}
+
+// Pausing stops the reader from pulling data off the transport, so the
+// backpressure reaches the remote end instead of piling up in memory.
+#[derive(Default)]
+pub struct OutputFlow {
+    state: Mutex<FlowState>,
+    changed: Condvar,
+    // Bytes per second before output is paused automatically, 0 disables
+    throttle_limit: AtomicU64,
+}
+
+impl OutputFlow {
+    fn lock(&self) -> std::sync::MutexGuard<'_, FlowState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns true if the session was not already paused.
+    pub fn pause(&self) -> bool {
+        let mut state = self.lock();
+        !std::mem::replace(&mut state.paused, true)
+    }
+
+    pub fn resume(&self) {
+        self.lock().paused = false;
+        self.changed.notify_all();
+    }
+
+    /// Wakes a paused reader so it can exit.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.changed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.lock().paused
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    /// Blocks while paused, returns false once the session is closed.
+    pub fn wait_until_resumed(&self) -> bool {
+        let mut state = self.lock();
+        while state.paused && !state.closed {
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        !state.closed
+    }
+
+    pub fn throttle_limit(&self) -> u64 {
+        self.throttle_limit.load(Ordering::Relaxed)
+    }
+
+    pub fn set_throttle_limit(&self, bytes_per_second: u64) {
+        self.throttle_limit
+            .store(bytes_per_second, Ordering::Relaxed);
+    }
+}
+
+// Per-session state the reader thread updates while scanning output
+pub struct ReaderContext {
+    pub window: Window,
+    pub session_id: String,
+    pub cwd: Arc<Mutex<Option<String>>>,
+    pub commands: Arc<Mutex<CommandTracker>>,
+    pub zmodem: Arc<ZmodemControl>,
+    pub flow: Arc<OutputFlow>,
+    pub scrollback: Arc<Mutex<Scrollback>>,
+    pub activity: Arc<SessionActivity>,
+    pub charset: Arc<SessionCharset>,
+    pub stats: Arc<SessionStats>,
+    pub owners: Arc<SessionOwners>,
+    pub notify: Arc<CommandNotifier>,
+}
+
+impl ReaderContext {
+    /// Emits to the windows using the session rather than to all of them.
+    pub fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        self.owners.emit(&self.window, event, payload);
+    }
+}
+
+// Bell and title events are capped so a hostile stream can't flood the UI
+const BELL_MIN_INTERVAL: Duration = Duration::from_millis(200);
+const TITLE_MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct OutputPipeline {
+    pub ctx: ReaderContext,
+    decoder: OutputDecoder,
+    scanner: OscScanner,
+    batcher: OutputBatcher,
+    rate_window_start: Instant,
+    rate_window_bytes: u64,
+    tail: OutputTail,
+    // Set by an OSC 133;A prompt marker, consumed by the startup sequence
+    prompt_marker: bool,
+    sudo: Option<SudoAutofill>,
+    startup: Option<StartupSequence>,
+    echo: Option<EchoFilter>,
+    last_bell: Option<Instant>,
+    title: Option<String>,
+    last_title_at: Option<Instant>,
+    // Title changes that arrived too quickly, emitted on a later poll
+    pending_title: Option<String>,
+}
+
+impl OutputPipeline {
+    pub fn new(ctx: ReaderContext, batch_settings: Arc<OutputBatchSettings>) -> Self {
+        Self {
+            ctx,
+            decoder: OutputDecoder::default(),
+            scanner: OscScanner::default(),
+            batcher: OutputBatcher::new(batch_settings),
+            rate_window_start: Instant::now(),
+            rate_window_bytes: 0,
+            tail: OutputTail::default(),
+            prompt_marker: false,
+            sudo: None,
+            startup: None,
+            echo: None,
+            last_bell: None,
+            title: None,
+            last_title_at: None,
+            pending_title: None,
+        }
+    }
+
+    pub fn set_sudo_autofill(&mut self, sudo: Option<SudoAutofill>) {
+        self.sudo = sudo;
+    }
+
+    pub fn set_startup(&mut self, startup: Option<StartupSequence>) {
+        self.startup = startup;
+    }
+
+    pub fn set_echo_filter(&mut self, echo: Option<EchoFilter>) {
+        self.echo = echo;
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        self.ctx.activity.touch_output();
+        self.ctx.stats.add_output(data.len());
+        let decoded = self.decoder.decode(&self.ctx.charset, data);
+        let data = decoded.as_ref().map_or(data, |text| text.as_bytes());
+        match self.echo.as_mut() {
+            Some(echo) => {
+                let filtered = echo.filter(data);
+                if echo.is_done() {
+                    self.echo = None;
+                }
+                self.process(&filtered);
+            }
+            None => self.process(data),
+        }
+    }
+
+    // Everything after decoding, for output the user gets to see
+    fn process(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            self.poll();
+            return;
+        }
+        for event in self.scanner.feed(data) {
+            self.handle_osc_event(event);
+        }
+        self.tail.push(data);
+        self.ctx.notify.on_output(data);
+        if let Some(batch) = self.batcher.push(data) {
+            self.emit(batch);
+        } else {
+            self.poll();
+        }
+        self.check_throttle(data.len() as u64);
+    }
+
+    /// Called by the reader before each read. Flushes what was already read
+    /// and blocks while output is paused; false means the session closed.
+    pub fn wait_if_paused(&mut self) -> bool {
+        if !self.ctx.flow.is_paused() {
+            return !self.ctx.flow.is_closed();
+        }
+        self.flush();
+        let open = self.ctx.flow.wait_until_resumed();
+        self.rate_window_start = Instant::now();
+        self.rate_window_bytes = 0;
+        open
+    }
+
+    fn check_throttle(&mut self, len: u64) {
+        let limit = self.ctx.flow.throttle_limit();
+        if limit == 0 {
+            return;
+        }
+        if self.rate_window_start.elapsed() >= Duration::from_secs(1) {
+            self.rate_window_start = Instant::now();
+            self.rate_window_bytes = 0;
+        }
+        self.rate_window_bytes += len;
+        if self.rate_window_bytes > limit && self.ctx.flow.pause() {
+            self.ctx.emit(
+                "output-throttled",
+                OutputThrottledPayload {
+                    session_id: self.ctx.session_id.clone(),
+                    bytes_per_second: limit,
+                },
+            );
+        }
+    }
+
+    /// Emits the pending batch if its delay elapsed.
+    pub fn poll(&mut self) {
+        // A partial echo match that never completed is output after all
+        if self.echo.as_ref().is_some_and(EchoFilter::is_expired) {
+            if let Some(mut echo) = self.echo.take() {
+                self.process(&echo.release());
+            }
+        }
+        if let Some(batch) = self.batcher.poll() {
+            self.emit(batch);
+        }
+        if self.pending_title.is_some() && Self::elapsed(self.last_title_at, TITLE_MIN_INTERVAL) {
+            if let Some(title) = self.pending_title.take() {
+                self.emit_title(title);
+            }
+        }
+    }
+
+    fn elapsed(since: Option<Instant>, interval: Duration) -> bool {
+        since.is_none_or(|at| at.elapsed() >= interval)
+    }
+
+    fn ring_bell(&mut self) {
+        if !Self::elapsed(self.last_bell, BELL_MIN_INTERVAL) {
+            return;
+        }
+        self.last_bell = Some(Instant::now());
+        self.ctx.emit(
+            "terminal-bell",
+            TerminalBellPayload {
+                session_id: self.ctx.session_id.clone(),
+            },
+        );
+    }
+
+    fn set_title(&mut self, title: String) {
+        if self.title.as_deref() == Some(title.as_str()) {
+            self.pending_title = None;
+            return;
+        }
+        if Self::elapsed(self.last_title_at, TITLE_MIN_INTERVAL) {
+            self.emit_title(title);
+        } else {
+            self.pending_title = Some(title);
+        }
+    }
+
+    fn emit_title(&mut self, title: String) {
+        self.last_title_at = Some(Instant::now());
+        self.title = Some(title.clone());
+        self.ctx.emit(
+            "terminal-title-changed",
+            TerminalTitlePayload {
+                session_id: self.ctx.session_id.clone(),
+                title,
+            },
+        );
+    }
+
+    /// Called when the transport has nothing more to read for now. Returns
+    /// input a trigger wants written back to the session.
+    pub fn idle(&mut self) -> Option<Vec<u8>> {
+        self.poll();
+        let tail = self.tail.take_changed();
+
+        if let (Some(sudo), Some(tail)) = (self.sudo.as_mut(), tail.as_deref()) {
+            if let Some(reply) = sudo.check(tail) {
+                self.tail.clear();
+                // The password itself never reaches the logs or the output stream
+                info!(target = "sudo", session = %self.ctx.session_id, "Answering sudo prompt");
+                return Some(reply);
+            }
+        }
+
+        let startup = self.startup.as_mut()?;
+        let at_prompt = std::mem::take(&mut self.prompt_marker)
+            || tail.as_deref().is_some_and(triggers::looks_like_prompt);
+        let input = startup.next(at_prompt)?;
+        if startup.is_done() {
+            info!(target = "startup", session = %self.ctx.session_id, "Startup commands applied");
+            self.startup = None;
+        }
+        Some(input)
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(batch) = self.batcher.flush() {
+            self.emit(batch);
+        }
+    }
+
+    /// How long a reader can block before output must be flushed.
+    pub fn idle_timeout(&self, max: Duration) -> Duration {
+        self.batcher
+            .time_until_flush()
+            .map_or(max, |remaining| remaining.min(max))
+    }
+
+    fn emit(&self, data: Vec<u8>) {
+        // Held while emitting, so attach_session's replay and the live output
+        // neither overlap nor leave a gap
+        let _scrollback = self.ctx.scrollback.lock().ok().map(|mut scrollback| {
+            scrollback.push(&data);
+            scrollback
+        });
+        self.ctx.emit(
+            "terminal-output",
+            TerminalOutputPayload {
+                session_id: self.ctx.session_id.clone(),
+                data,
+            },
+        );
+    }
+
+    fn handle_osc_event(&mut self, event: OscEvent) {
+        match event {
+            OscEvent::Bell => self.ring_bell(),
+            OscEvent::Osc(payload) => {
+                if payload == "133;A" || payload.starts_with("133;A;") {
+                    self.prompt_marker = true;
+                }
+                if let Some(title) = osc::parse_title(&payload) {
+                    self.set_title(title);
+                    return;
+                }
+                let ctx = &self.ctx;
+                if let Some(path) = osc::parse_osc7_cwd(&payload) {
+                    if let Ok(mut current) = ctx.cwd.lock() {
+                        if current.as_deref() == Some(path.as_str()) {
+                            return;
+                        }
+                        *current = Some(path.clone());
+                    }
+                    ctx.emit(
+                        "cwd-changed",
+                        CwdChangedPayload {
+                            session_id: ctx.session_id.clone(),
+                            cwd: path,
+                        },
+                    );
+                    return;
+                }
+
+                let finished = ctx
+                    .commands
+                    .lock()
+                    .ok()
+                    .and_then(|mut tracker| tracker.handle_osc(&payload));
+                if let Some(record) = finished {
+                    ctx.notify.command_finished(&record);
+                    ctx.emit(
+                        "command-finished",
+                        CommandFinishedPayload {
+                            session_id: ctx.session_id.clone(),
+                            record,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batcher(max_bytes: usize, max_delay_ms: u64) -> OutputBatcher {
+        let settings = Arc::new(OutputBatchSettings::default());
+        settings.set(OutputBatchConfig {
+            max_bytes,
+            max_delay_ms,
+        });
+        OutputBatcher::new(settings)
+    }
+
+    // Chunks of varied sizes, some past the size threshold, each byte
+    // numbered so reordering shows
+    fn chunks() -> Vec<Vec<u8>> {
+        let mut next = 0u32;
+        [1, 700, 5, 2048, 0, 300, 1024, 3, 4000, 17]
+            .iter()
+            .map(|&len| {
+                (0..len)
+                    .map(|_| {
+                        next += 1;
+                        (next % 251) as u8
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn run(mut batcher: OutputBatcher) {
+        let input = chunks();
+        let mut output = Vec::new();
+        for (i, chunk) in input.iter().enumerate() {
+            output.extend(batcher.push(chunk).unwrap_or_default());
+            // Interleave the reader's idle polls and forced flushes
+            if i % 3 == 0 {
+                output.extend(batcher.poll().unwrap_or_default());
+            }
+            if i % 4 == 1 {
+                output.extend(batcher.flush().unwrap_or_default());
+            }
+        }
+        output.extend(batcher.flush().unwrap_or_default());
+        assert_eq!(output, input.concat());
+        assert!(batcher.flush().is_none());
+    }
+
+    #[test]
+    fn batches_keep_every_byte_in_order() {
+        run(batcher(1024, 12));
+    }
+
+    #[test]
+    fn unbatched_output_keeps_every_byte_in_order() {
+        run(batcher(1024, 0));
+    }
+
+    #[test]
+    fn size_threshold_flushes_on_push() {
+        let mut batcher = batcher(1024, 1000);
+        assert!(batcher.push(&[1; 1000]).is_none());
+        assert_eq!(batcher.push(&[2; 24]).map(|b| b.len()), Some(1024));
+        assert!(batcher.time_until_flush().is_none());
+    }
+}
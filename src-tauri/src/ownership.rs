@@ -0,0 +1,191 @@
+// Which windows may use a session.
+//
+// A session belongs to the window that opened it. Commands acting on a
+// session check the caller's label, share_session lets the owner grant
+// another window access, and events about the session (its output, state
+// changes, transfers) go only to these windows rather than to every window.
+// Destroying a window closes the sessions it owns and revokes what was
+// shared with it. A window whose page reloaded gets no events until it calls
+// attach_session, see reattach.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{history, teardown_session, tray, AppState, SessionState};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Instant;
+use tauri::{AppHandle, Emitter, Manager, State, Window};
+use tracing::info;
+use uuid::Uuid;
+
+pub struct SessionOwners {
+    owner: String,
+    shared: RwLock<Vec<String>>,
+    // Windows that reloaded and haven't attached again, since when
+    detached: Mutex<HashMap<String, Instant>>,
+}
+
+impl SessionOwners {
+    pub fn new(owner: &str) -> Self {
+        Self {
+            owner: owner.to_string(),
+            shared: RwLock::new(Vec::new()),
+            detached: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Label of the window that opened the session.
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    pub fn allows(&self, label: &str) -> bool {
+        self.owner == label || self.shared().iter().any(|l| l == label)
+    }
+
+    fn shared(&self) -> std::sync::RwLockReadGuard<'_, Vec<String>> {
+        self.shared.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn share(&self, label: &str) {
+        let mut shared = self.shared.write().unwrap_or_else(|e| e.into_inner());
+        if self.owner != label && !shared.iter().any(|l| l == label) {
+            shared.push(label.to_string());
+        }
+    }
+
+    fn revoke(&self, label: &str) {
+        self.shared
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|l| l != label);
+    }
+
+    fn detached(&self) -> std::sync::MutexGuard<'_, HashMap<String, Instant>> {
+        self.detached.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Stops events to `label` until it attaches. The first detach time is
+    /// kept. Returns false if the window can't use the session.
+    pub fn detach(&self, label: &str) -> bool {
+        if !self.allows(label) {
+            return false;
+        }
+        self.detached()
+            .entry(label.to_string())
+            .or_insert_with(Instant::now);
+        true
+    }
+
+    pub fn attach(&self, label: &str) {
+        self.detached().remove(label);
+    }
+
+    /// When `label` detached, if it hasn't attached since.
+    pub fn detached_since(&self, label: &str) -> Option<Instant> {
+        self.detached().get(label).copied()
+    }
+
+    /// Labels of the windows allowed to use the session, the owner first.
+    pub fn labels(&self) -> Vec<String> {
+        std::iter::once(&self.owner)
+            .chain(self.shared().iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Emits `event` to each window allowed to use the session.
+    pub fn emit<S: Serialize + Clone>(&self, emitter: &impl Emitter, event: &str, payload: S) {
+        let detached = self.detached();
+        let shared = self.shared();
+        let labels = std::iter::once(&self.owner).chain(shared.iter());
+        for label in labels.filter(|l| !detached.contains_key(*l)) {
+            let _ = emitter.emit_to(label.as_str(), event, payload.clone());
+        }
+    }
+}
+
+/// Fails unless `window` may use the session.
+pub fn check(session: &SessionState, session_id: &str, window: &Window) -> Result<(), AppError> {
+    if session.owners.allows(window.label()) {
+        Ok(())
+    } else {
+        Err(AppError::new(
+            ErrorKind::PermissionDenied,
+            "The session belongs to another window",
+        )
+        .with_session(session_id))
+    }
+}
+
+/// Looks up a session and checks `window` may use it. Returns the session's
+/// owners, for events about it that outlive the lookup.
+pub fn authorize(
+    state: &AppState,
+    session_id: &str,
+    window: &Window,
+) -> Result<Arc<SessionOwners>, AppError> {
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    check(&session, session_id, window)?;
+    Ok(session.owners.clone())
+}
+
+/// Closes the sessions a destroyed window owned.
+pub fn close_window_sessions(app_handle: &AppHandle, label: &str) {
+    let state = app_handle.state::<AppState>();
+    let mut owned = Vec::new();
+    for entry in state.sessions.iter() {
+        if entry.owners.owner == label {
+            owned.push(*entry.key());
+        } else {
+            entry.owners.revoke(label);
+        }
+    }
+
+    for uuid in owned {
+        if let Some((_, session)) = state.sessions.remove(&uuid) {
+            let session_id = uuid.to_string();
+            info!(target = "session", session = %session_id, window = label, "Closing session of destroyed window");
+            history::finish_session(app_handle, &session.target, "window closed");
+            // Window events run on the main thread
+            thread::spawn(move || teardown_session(session, &session_id));
+        }
+    }
+    tray::refresh(app_handle);
+}
+
+// Only the owner can share, and only with a window that exists
+#[tauri::command]
+pub fn share_session(
+    session_id: String,
+    target_window: String,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    if session.owners.owner != window.label() {
+        return Err(AppError::new(
+            ErrorKind::PermissionDenied,
+            "Only the window that opened the session can share it",
+        )
+        .with_session(&session_id));
+    }
+    if window.app_handle().get_window(&target_window).is_none() {
+        return Err(AppError::new(
+            ErrorKind::NotFound,
+            format!("No window named {}", target_window),
+        ));
+    }
+    session.owners.share(&target_window);
+    info!(target = "session", session = %session_id, window = %target_window, "Shared session");
+    Ok(())
+}
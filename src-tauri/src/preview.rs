@@ -0,0 +1,240 @@
+// Hover previews for the SFTP panel: the start of a file, read cheaply.
+//
+// The file is stat'ed first and anything but a regular file is refused, since
+// opening a FIFO or a device can block on the server. Then only the head is
+// read: max_bytes for text, MAX_IMAGE_BYTES for images, whatever the file's
+// size. Images under the cap come back as base64 for a thumbnail; larger
+// ones only as their type. Text is decoded from a BOM, as UTF-8, or else
+// from the session charset, falling back to windows-1252. Everything else
+// is "binary" with a type guessed from its magic number.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{sftp_ops, AppState};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use serde::Serialize;
+use ssh2::FileType;
+use std::path::Path;
+use std::time::Duration;
+use tauri::{State, Window};
+
+const DEFAULT_TEXT_BYTES: u64 = 16 * 1024;
+const MAX_TEXT_BYTES: u64 = 256 * 1024;
+const MAX_IMAGE_BYTES: u64 = 2 * 1024 * 1024;
+// Enough for every signature in MAGIC and tar's at 257
+const SNIFF_BYTES: usize = 512;
+
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("bmp", "image/bmp"),
+    ("ico", "image/x-icon"),
+    ("svg", "image/svg+xml"),
+];
+
+// (offset, signature, description)
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (0, b"\xff\xd8\xff", "JPEG image"),
+    (0, b"GIF8", "GIF image"),
+    (0, b"%PDF-", "PDF document"),
+    (0, b"PK\x03\x04", "ZIP archive"),
+    (0, b"\x1f\x8b", "gzip archive"),
+    (0, b"BZh", "bzip2 archive"),
+    (0, b"\xfd7zXZ\x00", "xz archive"),
+    (0, b"\x28\xb5\x2f\xfd", "zstd archive"),
+    (0, b"7z\xbc\xaf\x27\x1c", "7-Zip archive"),
+    (0, b"Rar!\x1a\x07", "RAR archive"),
+    (257, b"ustar", "tar archive"),
+    (0, b"\x7fELF", "ELF executable"),
+    (0, b"\xcf\xfa\xed\xfe", "Mach-O executable"),
+    (
+        0,
+        b"\xca\xfe\xba\xbe",
+        "Mach-O universal binary or Java class",
+    ),
+    (0, b"MZ", "Windows executable"),
+    (0, b"\x00asm", "WebAssembly module"),
+    (0, b"SQLite format 3\x00", "SQLite database"),
+    (0, b"OggS", "Ogg media"),
+    (0, b"ID3", "MP3 audio"),
+    (0, b"fLaC", "FLAC audio"),
+    (4, b"ftyp", "MP4 media"),
+    (0, b"\x1a\x45\xdf\xa3", "Matroska media"),
+];
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewKind {
+    Text,
+    Image,
+    Binary,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilePreview {
+    pub kind: PreviewKind,
+    pub size: u64,
+    // Less than the whole file was read
+    pub truncated: bool,
+    pub text: Option<String>,
+    pub encoding: Option<String>,
+    pub mime: Option<String>,
+    // Image bytes, only when the whole image fit under the cap
+    pub data_base64: Option<String>,
+    // From the magic number, e.g. "gzip archive"
+    pub file_type: Option<String>,
+}
+
+fn image_mime(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    IMAGE_TYPES
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, mime)| *mime)
+}
+
+fn guess_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"RIFF") && data.len() >= 12 {
+        return match &data[8..12] {
+            b"WEBP" => Some("WebP image"),
+            b"WAVE" => Some("WAV audio"),
+            b"AVI " => Some("AVI video"),
+            _ => Some("RIFF data"),
+        };
+    }
+    MAGIC
+        .iter()
+        .find(|(offset, signature, _)| {
+            data.get(*offset..offset + signature.len()) == Some(*signature)
+        })
+        .map(|(_, _, description)| *description)
+}
+
+fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(8192)].contains(&0)
+}
+
+// A read cut off mid-character isn't invalid UTF-8
+fn utf8_prefix(data: &[u8], truncated: bool) -> Option<&str> {
+    match std::str::from_utf8(data) {
+        Ok(text) => Some(text),
+        Err(e) if truncated && e.error_len().is_none() => {
+            std::str::from_utf8(&data[..e.valid_up_to()]).ok()
+        }
+        Err(_) => None,
+    }
+}
+
+fn decode_text(
+    data: &[u8],
+    truncated: bool,
+    session_charset: &'static Encoding,
+) -> (String, String) {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(data) {
+        let (text, _) = encoding.decode_without_bom_handling(&data[bom_len..]);
+        return (text.into_owned(), encoding.name().to_string());
+    }
+    if let Some(text) = utf8_prefix(data, truncated) {
+        return (text.to_string(), UTF_8.name().to_string());
+    }
+    let encoding = if session_charset == UTF_8 {
+        WINDOWS_1252
+    } else {
+        session_charset
+    };
+    let (text, _) = encoding.decode_without_bom_handling(data);
+    (text.into_owned(), encoding.name().to_string())
+}
+
+fn describe(file_type: FileType) -> &'static str {
+    match file_type {
+        FileType::Directory => "a directory",
+        FileType::NamedPipe => "a named pipe",
+        FileType::CharDevice => "a character device",
+        FileType::BlockDevice => "a block device",
+        FileType::Socket => "a socket",
+        _ => "not a regular file",
+    }
+}
+
+/// Returns the start of a remote file for a preview: text, an image for a
+/// thumbnail, or a binary type guess. Reads at most `max_bytes` of text
+/// (16 KiB by default) and refuses anything that isn't a regular file.
+#[tauri::command]
+pub async fn preview_remote_file(
+    session_id: String,
+    path: String,
+    max_bytes: Option<u64>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<FilePreview, AppError> {
+    let max_text = max_bytes
+        .unwrap_or(DEFAULT_TEXT_BYTES)
+        .clamp(SNIFF_BYTES as u64, MAX_TEXT_BYTES);
+    let timeout = Duration::from_secs(state.settings.get().sftp_timeout_secs);
+    let op = sftp_ops::Operation::new("preview", timeout, None);
+
+    sftp_ops::with_session(&state, session_id, &window, move |session| {
+        let path = Path::new(&path);
+        // stat follows symlinks, so a link to a FIFO is refused too
+        let stat = sftp_ops::run(session, &op, |sftp| sftp.stat(path))?;
+        let file_type = stat.file_type();
+        if !file_type.is_file() {
+            return Err(AppError::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Cannot preview {}: it is {}",
+                    path.display(),
+                    describe(file_type)
+                ),
+            ));
+        }
+        let size = stat.size.unwrap_or(0);
+        let mime = image_mime(path);
+
+        let max = if mime.is_some() {
+            MAX_IMAGE_BYTES
+        } else {
+            max_text
+        };
+        // Too large to show; the head is enough to tell the type
+        let read = if mime.is_some() && size > MAX_IMAGE_BYTES {
+            SNIFF_BYTES as u64
+        } else {
+            max
+        };
+        let data = sftp_ops::read_head(session, &op, path, read)?;
+        let truncated = size > data.len() as u64;
+        let guessed = guess_type(&data).map(str::to_string);
+
+        let mut preview = FilePreview {
+            kind: PreviewKind::Binary,
+            size,
+            truncated,
+            text: None,
+            encoding: None,
+            mime: mime.map(str::to_string),
+            data_base64: None,
+            file_type: guessed,
+        };
+        if mime.is_some() {
+            preview.kind = PreviewKind::Image;
+            if !truncated {
+                preview.data_base64 = Some(BASE64.encode(&data));
+            }
+        // UTF-16 text is full of NULs, but starts with a BOM
+        } else if Encoding::for_bom(&data).is_some() || !is_binary(&data) {
+            let (text, encoding) = decode_text(&data, truncated, session.charset.get());
+            preview.kind = PreviewKind::Text;
+            preview.text = Some(text);
+            preview.encoding = Some(encoding);
+        }
+        Ok(preview)
+    })
+    .await
+}
@@ -0,0 +1,327 @@
+// Remote process list for the processes panel.
+//
+// Everything runs on side channels, so the interactive shell never sees it.
+// GNU ps sorts for us; BSD and macOS ps reject --sort, and busybox knows
+// neither axo nor --sort, so the command falls back through those. Columns
+// are matched by their header rather than position since those differ too
+// (macOS prints "COMM", busybox has no %CPU or %MEM), and the list is sorted
+// again here whichever ps answered. subscribe_remote_processes refreshes the
+// list on an interval and emits "remote-processes".
+
+use crate::error::{AppError, ErrorKind};
+use crate::side_channel::run_on_side_channel;
+use crate::{ownership, read_only, AppState};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{async_runtime, AppHandle, Emitter, Manager, State, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const PS_COMMAND: &str = "ps axo pid,ppid,user,%cpu,%mem,etime,comm --sort=-%cpu 2>/dev/null \
+    || ps axo pid,ppid,user,%cpu,%mem,etime,comm 2>/dev/null \
+    || ps -o pid,ppid,user,etime,comm";
+const EXEC_TIMEOUT: Duration = Duration::from_secs(15);
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 1000;
+const DEFAULT_INTERVAL_SECS: u64 = 3;
+const MIN_INTERVAL_SECS: u64 = 1;
+const SIGNALS: &[&str] = &[
+    "TERM", "KILL", "HUP", "INT", "QUIT", "STOP", "CONT", "USR1", "USR2",
+];
+
+// Running subscriptions by session
+static SUBSCRIPTIONS: LazyLock<DashMap<Uuid, Arc<AtomicBool>>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteProcess {
+    pub pid: u32,
+    pub ppid: Option<u32>,
+    pub user: Option<String>,
+    pub cpu_percent: Option<f64>,
+    pub mem_percent: Option<f64>,
+    pub elapsed_secs: Option<u64>,
+    pub command: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessList {
+    // The top processes by CPU, then memory
+    pub processes: Vec<RemoteProcess>,
+    // Totals cover every process, not just the ones returned
+    pub total_processes: usize,
+    pub total_cpu_percent: f64,
+    pub total_mem_percent: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RemoteProcessesPayload {
+    session_id: String,
+    list: Option<ProcessList>,
+    error: Option<AppError>,
+}
+
+enum Column {
+    Pid,
+    Ppid,
+    User,
+    Cpu,
+    Mem,
+    Elapsed,
+    Command,
+    Unknown,
+}
+
+impl Column {
+    fn from_header(header: &str) -> Self {
+        match header.to_ascii_uppercase().as_str() {
+            "PID" => Self::Pid,
+            "PPID" => Self::Ppid,
+            "USER" | "UID" => Self::User,
+            "%CPU" | "CPU" => Self::Cpu,
+            "%MEM" | "MEM" => Self::Mem,
+            "ELAPSED" | "ETIME" => Self::Elapsed,
+            "COMMAND" | "COMM" | "UCOMM" | "CMD" => Self::Command,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+// "[[dd-]hh:]mm:ss", the elapsed time format every ps shares
+fn parse_elapsed(etime: &str) -> Option<u64> {
+    let (days, clock) = match etime.split_once('-') {
+        Some((days, clock)) => (days.parse::<u64>().ok()?, clock),
+        None => (0, etime),
+    };
+    let mut secs = 0;
+    for part in clock.split(':') {
+        secs = secs * 60 + part.parse::<u64>().ok()?;
+    }
+    Some(days * 86400 + secs)
+}
+
+fn parse_ps(output: &str) -> Result<Vec<RemoteProcess>, String> {
+    let mut lines = output.lines().filter(|l| !l.trim().is_empty());
+    let header = lines.next().ok_or("ps printed nothing")?;
+    let columns: Vec<Column> = header.split_whitespace().map(Column::from_header).collect();
+    if !columns.iter().any(|c| matches!(c, Column::Pid)) {
+        return Err(format!("Unrecognized ps output: {}", header.trim()));
+    }
+
+    let mut processes = Vec::new();
+    for line in lines {
+        // The command is last and may contain spaces
+        let mut fields = Vec::with_capacity(columns.len());
+        let mut rest = line.trim_start();
+        for i in 0..columns.len() {
+            if i + 1 == columns.len() {
+                fields.push(rest.trim_end());
+                break;
+            }
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            fields.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+
+        let mut process = RemoteProcess {
+            pid: 0,
+            ppid: None,
+            user: None,
+            cpu_percent: None,
+            mem_percent: None,
+            elapsed_secs: None,
+            command: String::new(),
+        };
+        for (column, field) in columns.iter().zip(fields) {
+            match column {
+                Column::Pid => process.pid = field.parse().unwrap_or(0),
+                Column::Ppid => process.ppid = field.parse().ok(),
+                Column::User => process.user = Some(field.to_string()),
+                // Some locales print "0,5"
+                Column::Cpu => process.cpu_percent = field.replace(',', ".").parse().ok(),
+                Column::Mem => process.mem_percent = field.replace(',', ".").parse().ok(),
+                Column::Elapsed => process.elapsed_secs = parse_elapsed(field),
+                Column::Command => process.command = field.to_string(),
+                Column::Unknown => {}
+            }
+        }
+        if process.pid != 0 {
+            processes.push(process);
+        }
+    }
+    Ok(processes)
+}
+
+fn list(state: &AppState, session_id: &str, limit: usize) -> Result<ProcessList, AppError> {
+    let output = run_on_side_channel(&state.sessions, session_id, PS_COMMAND, EXEC_TIMEOUT)?;
+    if output.exit_status != 0 {
+        return Err(format!("ps failed: {}", output.stderr.trim()).into());
+    }
+    let mut processes = parse_ps(&output.stdout)?;
+    let total_cpu_percent = processes.iter().filter_map(|p| p.cpu_percent).sum();
+    let total_mem_percent = processes.iter().filter_map(|p| p.mem_percent).sum();
+    let total_processes = processes.len();
+    processes.sort_by(|a, b| {
+        let key = |p: &RemoteProcess| (p.cpu_percent.unwrap_or(0.0), p.mem_percent.unwrap_or(0.0));
+        key(b)
+            .partial_cmp(&key(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    processes.truncate(limit);
+    Ok(ProcessList {
+        processes,
+        total_processes,
+        total_cpu_percent,
+        total_mem_percent,
+    })
+}
+
+fn check_owner(state: &AppState, session_id: &str, window: &Window) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    ownership::check(&session, session_id, window)
+}
+
+fn limit(limit: Option<usize>) -> usize {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+#[tauri::command]
+pub async fn get_remote_processes(
+    session_id: String,
+    limit: Option<usize>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<ProcessList, AppError> {
+    check_owner(&app_handle.state::<AppState>(), &session_id, &window)?;
+    let limit = self::limit(limit);
+    async_runtime::spawn_blocking(move || list(&app_handle.state::<AppState>(), &session_id, limit))
+        .await
+        .map_err(|e| AppError::from(e.to_string()))?
+}
+
+// signal defaults to TERM
+#[tauri::command]
+pub async fn kill_remote_process(
+    session_id: String,
+    pid: u32,
+    signal: Option<String>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<(), AppError> {
+    check_owner(&app_handle.state::<AppState>(), &session_id, &window)?;
+    read_only::check(&app_handle.state::<AppState>(), &session_id)?;
+    let signal = signal
+        .as_deref()
+        .unwrap_or("TERM")
+        .trim_start_matches("SIG")
+        .to_ascii_uppercase();
+    if !SIGNALS.contains(&signal.as_str()) {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            format!("Unsupported signal: {}", signal),
+        ));
+    }
+    if pid <= 1 {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            format!("Refusing to signal pid {}", pid),
+        ));
+    }
+    let command = format!("kill -s {} {}", signal, pid);
+    let output = async_runtime::spawn_blocking(move || {
+        let state = app_handle.state::<AppState>();
+        run_on_side_channel(&state.sessions, &session_id, &command, EXEC_TIMEOUT)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    if output.exit_status != 0 {
+        let message = output.stderr.trim();
+        let kind = if message.contains("not permitted") {
+            ErrorKind::PermissionDenied
+        } else if message.contains("No such process") {
+            ErrorKind::NotFound
+        } else {
+            ErrorKind::Other
+        };
+        return Err(AppError::new(kind, format!("kill failed: {}", message)));
+    }
+    info!(target = "processes", pid, %signal, "Signalled remote process");
+    Ok(())
+}
+
+/// Emits "remote-processes" for the session every `interval_secs` until
+/// unsubscribed or the session closes. Subscribing again replaces the
+/// previous interval and limit.
+#[tauri::command]
+pub fn subscribe_remote_processes(
+    session_id: String,
+    interval_secs: Option<u64>,
+    limit: Option<usize>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    check_owner(&state, &session_id, &window)?;
+    let interval = Duration::from_secs(
+        interval_secs
+            .unwrap_or(DEFAULT_INTERVAL_SECS)
+            .max(MIN_INTERVAL_SECS),
+    );
+    let limit = self::limit(limit);
+    let stop = Arc::new(AtomicBool::new(false));
+    if let Some(previous) = SUBSCRIPTIONS.insert(uuid, stop.clone()) {
+        previous.store(true, Ordering::Relaxed);
+    }
+
+    thread::spawn(move || {
+        let app_handle = window.app_handle().clone();
+        loop {
+            let state = app_handle.state::<AppState>();
+            if stop.load(Ordering::Relaxed) || !state.sessions.contains_key(&uuid) {
+                break;
+            }
+            let started = Instant::now();
+            let payload = match list(&state, &session_id, limit) {
+                Ok(list) => RemoteProcessesPayload {
+                    session_id: session_id.clone(),
+                    list: Some(list),
+                    error: None,
+                },
+                Err(error) => {
+                    warn!(target = "processes", session = %session_id, error = %error, "Process refresh failed");
+                    RemoteProcessesPayload {
+                        session_id: session_id.clone(),
+                        list: None,
+                        error: Some(error),
+                    }
+                }
+            };
+            if !stop.load(Ordering::Relaxed) {
+                let _ = window.emit("remote-processes", payload);
+            }
+            // A slow ps stretches the interval instead of piling up
+            let round_end = started + interval.max(started.elapsed());
+            while Instant::now() < round_end && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(250));
+            }
+        }
+        SUBSCRIPTIONS.remove_if(&uuid, |_, flag| Arc::ptr_eq(flag, &stop));
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn unsubscribe_remote_processes(session_id: String) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    if let Some((_, stop)) = SUBSCRIPTIONS.remove(&uuid) {
+        stop.store(true, Ordering::Relaxed);
+    }
+    Ok(())
+}
@@ -0,0 +1,263 @@
+// Throttled "transfer-progress" events.
+//
+// Emitting from the copy loop meant thousands of events per second on a fast
+// link, lagging the webview and slowing the copy itself. The loop now only
+// bumps counters; a ticker thread per transfer emits when they changed, a few
+// times per second. Completion is always reported, either by finish() or
+// when a file's counter reaches its size.
+
+use crate::ownership::SessionOwners;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tauri::Window;
+
+// At most ~7 events per second per transfer
+const TICK: Duration = Duration::from_millis(150);
+
+#[derive(Debug, Clone, Serialize)]
+struct TransferProgressPayload {
+    session_id: String,
+    file_path: String,
+    transferred_bytes: u64,
+    total_bytes: u64,
+}
+
+struct Shared {
+    sink: Box<dyn Fn(TransferProgressPayload) + Send + Sync>,
+    session_id: String,
+    file_path: Mutex<String>,
+    transferred: AtomicU64,
+    total: AtomicU64,
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl Shared {
+    fn emit(&self) {
+        let file_path = self
+            .file_path
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        (self.sink)(TransferProgressPayload {
+            session_id: self.session_id.clone(),
+            file_path,
+            transferred_bytes: self.transferred.load(Ordering::Relaxed),
+            total_bytes: self.total.load(Ordering::Relaxed),
+        });
+    }
+}
+
+pub struct ProgressReporter {
+    shared: Arc<Shared>,
+    ticker: Option<JoinHandle<()>>,
+}
+
+impl ProgressReporter {
+    pub fn start(
+        window: Window,
+        owners: Arc<SessionOwners>,
+        session_id: String,
+        file_path: String,
+        total_bytes: u64,
+    ) -> Self {
+        Self::start_with(
+            move |payload| owners.emit(&window, "transfer-progress", payload),
+            session_id,
+            file_path,
+            total_bytes,
+        )
+    }
+
+    fn start_with(
+        sink: impl Fn(TransferProgressPayload) + Send + Sync + 'static,
+        session_id: String,
+        file_path: String,
+        total_bytes: u64,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            sink: Box::new(sink),
+            session_id,
+            file_path: Mutex::new(file_path),
+            transferred: AtomicU64::new(0),
+            total: AtomicU64::new(total_bytes),
+            stopped: Mutex::new(false),
+            wake: Condvar::new(),
+        });
+        let ticker_shared = shared.clone();
+        let ticker = thread::spawn(move || {
+            let mut last = 0;
+            loop {
+                let stopped = ticker_shared
+                    .stopped
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner());
+                let (stopped, _) = ticker_shared
+                    .wake
+                    .wait_timeout_while(stopped, TICK, |stopped| !*stopped)
+                    .unwrap_or_else(|e| e.into_inner());
+                if *stopped {
+                    break;
+                }
+                drop(stopped);
+                let current = ticker_shared.transferred.load(Ordering::Relaxed);
+                if current != last {
+                    last = current;
+                    ticker_shared.emit();
+                }
+            }
+        });
+        Self {
+            shared,
+            ticker: Some(ticker),
+        }
+    }
+
+    /// Counts bytes copied in the hot loop.
+    pub fn add(&self, bytes: u64) {
+        self.shared.transferred.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Updates the size once it is known.
+    pub fn set_total(&self, total_bytes: u64) {
+        self.shared.total.store(total_bytes, Ordering::Relaxed);
+    }
+
+    /// Replaces the counters, for transfers that move through several
+    /// files. A completed file is reported right away.
+    pub fn set(&self, file_path: &str, transferred_bytes: u64, total_bytes: u64) {
+        {
+            let mut current = self
+                .shared
+                .file_path
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            if *current != file_path {
+                *current = file_path.to_string();
+            }
+        }
+        self.shared
+            .transferred
+            .store(transferred_bytes, Ordering::Relaxed);
+        self.shared.total.store(total_bytes, Ordering::Relaxed);
+        if total_bytes > 0 && transferred_bytes >= total_bytes {
+            self.shared.emit();
+        }
+    }
+
+    /// Stops the ticker and emits the final, complete event.
+    pub fn finish(mut self) {
+        self.stop();
+        let transferred = self.shared.transferred.load(Ordering::Relaxed);
+        // Files can change size while being copied
+        self.shared.total.fetch_max(transferred, Ordering::Relaxed);
+        self.shared
+            .transferred
+            .store(self.shared.total.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.shared.emit();
+    }
+
+    fn stop(&mut self) {
+        *self
+            .shared
+            .stopped
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = true;
+        self.shared.wake.notify_all();
+        if let Some(ticker) = self.ticker.take() {
+            let _ = ticker.join();
+        }
+    }
+}
+
+// A failed transfer stops ticking without claiming completion
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    // (transferred, total) of each emitted event
+    type Events = Arc<Mutex<Vec<(u64, u64)>>>;
+
+    fn recording(total_bytes: u64) -> (ProgressReporter, Events) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let reporter = ProgressReporter::start_with(
+            move |payload| {
+                sink.lock()
+                    .unwrap()
+                    .push((payload.transferred_bytes, payload.total_bytes));
+            },
+            "s".to_string(),
+            "/tmp/file".to_string(),
+            total_bytes,
+        );
+        (reporter, events)
+    }
+
+    #[test]
+    fn adds_within_a_tick_emit_at_most_once_before_finishing() {
+        let (reporter, events) = recording(10_000);
+        for _ in 0..10_000 {
+            reporter.add(1);
+        }
+        // Only the finish event, unless a tick happened to fall in between
+        assert!(events.lock().unwrap().len() <= 1);
+        reporter.finish();
+        let events = events.lock().unwrap();
+        assert!(events.len() <= 2);
+        assert_eq!(events.last(), Some(&(10_000, 10_000)));
+    }
+
+    // The old loop emitted every 32KB chunk inline; with a sink this slow
+    // that would take 4000 * 10ms. Counting must not wait on it at all.
+    #[test]
+    fn copy_loop_does_not_wait_on_emit() {
+        const CHUNK: usize = 32 * 1024;
+        const CHUNKS: usize = 4000;
+        let reporter = ProgressReporter::start_with(
+            |_| thread::sleep(Duration::from_millis(10)),
+            "s".to_string(),
+            "/tmp/file".to_string(),
+            (CHUNK * CHUNKS) as u64,
+        );
+        let source = vec![7u8; CHUNK];
+        let mut dest = vec![0u8; CHUNK];
+        let started = Instant::now();
+        for _ in 0..CHUNKS {
+            dest.copy_from_slice(&source);
+            reporter.add(CHUNK as u64);
+        }
+        let elapsed = started.elapsed();
+        assert_eq!(dest, source);
+        assert!(
+            elapsed < Duration::from_secs(1),
+            "copy loop took {:?}",
+            elapsed
+        );
+        reporter.finish();
+    }
+
+    #[test]
+    fn finish_reports_the_completed_totals() {
+        let (reporter, events) = recording(0);
+        reporter.add(300);
+        reporter.add(200);
+        reporter.finish();
+        assert_eq!(events.lock().unwrap().last(), Some(&(500, 500)));
+
+        let (reporter, events) = recording(1000);
+        reporter.add(400);
+        reporter.finish();
+        assert_eq!(events.lock().unwrap().last(), Some(&(1000, 1000)));
+    }
+}
@@ -0,0 +1,277 @@
+// Importing saved sessions from PuTTY, either from a .reg export or, on
+// Windows, straight from the registry (via `reg export`).
+//
+// Only SSH sessions become hosts. Settings with no equivalent here are
+// reported as warnings rather than failing the import.
+
+use crate::error::AppError;
+use crate::host_import::{build_preview, local_username, ImportPreview, ImportWarning};
+use crate::{ConnectionDetails, SavedHost};
+use std::collections::HashMap;
+use std::fs;
+use tauri::AppHandle;
+use uuid::Uuid;
+
+const SESSIONS_KEY: &str = r"Software\SimonTatham\PuTTY\Sessions\";
+const GROUP: &str = "PuTTY";
+
+// Options that change how a session behaves; flagged when set
+const UNSUPPORTED: [(&str, &str); 5] = [
+    ("ProxyMethod", "Proxy settings are not imported"),
+    ("PortForwardings", "Port forwardings are not imported"),
+    ("X11Forward", "X11 forwarding is not supported"),
+    ("AgentFwd", "Agent forwarding is not supported"),
+    ("RemoteCommand", "Remote command is not imported"),
+];
+
+// Session name and its values
+type Session = (String, HashMap<String, RegValue>);
+
+enum RegValue {
+    Str(String),
+    Dword(u32),
+    Other,
+}
+
+impl RegValue {
+    fn is_set(&self) -> bool {
+        match self {
+            RegValue::Str(s) => !s.is_empty(),
+            RegValue::Dword(d) => *d != 0,
+            RegValue::Other => true,
+        }
+    }
+}
+
+// .reg files written by regedit are UTF-16LE with a BOM
+fn decode_reg(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        encoding_rs::UTF_16LE.decode(bytes).0.into_owned()
+    } else {
+        encoding_rs::UTF_8.decode(bytes).0.into_owned()
+    }
+}
+
+fn percent_decode(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// Parses `"Name"=value`, returning None for lines that aren't values
+fn parse_value(line: &str) -> Option<(String, RegValue)> {
+    let rest = line.strip_prefix('"')?;
+    let end = rest.find("\"=")?;
+    let name = rest[..end].to_string();
+    let raw = &rest[end + 2..];
+
+    let value = if let Some(quoted) = raw.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => value.extend(chars.next()),
+                '"' => break,
+                c => value.push(c),
+            }
+        }
+        RegValue::Str(value)
+    } else if let Some(hex) = raw.strip_prefix("dword:") {
+        RegValue::Dword(u32::from_str_radix(hex.trim(), 16).ok()?)
+    } else {
+        RegValue::Other
+    };
+    Some((name, value))
+}
+
+fn parse_reg(content: &str, source: &str) -> (Vec<Session>, Vec<ImportWarning>) {
+    let mut sessions: Vec<Session> = Vec::new();
+    let mut warnings = Vec::new();
+    let mut in_session = false;
+    let mut pending = String::new();
+
+    for (i, raw) in content.lines().enumerate() {
+        // Long hex values continue on the next line after a backslash
+        let line = raw.trim();
+        if let Some(head) = line.strip_suffix('\\') {
+            pending.push_str(head);
+            continue;
+        }
+        pending.push_str(line);
+        let line = std::mem::take(&mut pending);
+
+        if line.is_empty() || line.starts_with(';') || line.starts_with("Windows Registry") {
+            continue;
+        }
+        if let Some(key) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_session = false;
+            if let Some(pos) = key.find(SESSIONS_KEY) {
+                let name = &key[pos + SESSIONS_KEY.len()..];
+                if !name.is_empty() && !name.contains('\\') {
+                    sessions.push((percent_decode(name), HashMap::new()));
+                    in_session = true;
+                }
+            }
+            continue;
+        }
+        if !in_session {
+            continue;
+        }
+        match parse_value(&line) {
+            Some((name, value)) => {
+                if let Some((_, values)) = sessions.last_mut() {
+                    values.insert(name, value);
+                }
+            }
+            None => warnings.push(ImportWarning {
+                source: source.to_string(),
+                line: Some(i + 1),
+                text: line.clone(),
+                reason: "Could not parse line".to_string(),
+            }),
+        }
+    }
+    (sessions, warnings)
+}
+
+fn to_saved_host(
+    name: String,
+    values: &HashMap<String, RegValue>,
+    warnings: &mut Vec<ImportWarning>,
+) -> Option<SavedHost> {
+    let string = |key: &str| match values.get(key) {
+        Some(RegValue::Str(s)) if !s.is_empty() => Some(s.clone()),
+        _ => None,
+    };
+    let mut warn = |text: &str, reason: &str| {
+        warnings.push(ImportWarning {
+            source: name.clone(),
+            line: None,
+            text: text.to_string(),
+            reason: reason.to_string(),
+        })
+    };
+
+    let protocol = string("Protocol").unwrap_or_else(|| "ssh".to_string());
+    if protocol != "ssh" {
+        warn(&protocol, "Only SSH sessions are imported");
+        return None;
+    }
+    let Some(host_name) = string("HostName") else {
+        warn("HostName", "Session has no host name");
+        return None;
+    };
+    // PuTTY accepts "user@host" in the host field
+    let (user, host) = match host_name.rsplit_once('@') {
+        Some((user, host)) => (Some(user.to_string()), host.to_string()),
+        None => (None, host_name),
+    };
+    let port = match values.get("PortNumber") {
+        Some(RegValue::Dword(port)) => u16::try_from(*port).ok(),
+        _ => None,
+    };
+
+    let private_key_path = string("PublicKeyFile");
+    if private_key_path
+        .as_deref()
+        .is_some_and(|p| p.to_ascii_lowercase().ends_with(".ppk"))
+    {
+        warn(
+            "PublicKeyFile",
+            "PuTTY keys (.ppk) must be converted to OpenSSH format before use",
+        );
+    }
+    for (key, reason) in UNSUPPORTED {
+        if values.get(key).is_some_and(RegValue::is_set) {
+            warn(key, reason);
+        }
+    }
+
+    Some(SavedHost {
+        id: Uuid::new_v4().to_string(),
+        name,
+        group: Some(GROUP.to_string()),
+        tags: Vec::new(),
+        details: ConnectionDetails {
+            host,
+            port,
+            username: string("UserName").or(user).unwrap_or_else(local_username),
+            private_key_path,
+            ..Default::default()
+        },
+        has_password: false,
+        has_passphrase: false,
+        has_totp_secret: false,
+        pinned: false,
+        sort_order: 0,
+        notes: None,
+        color: None,
+        metadata: HashMap::new(),
+        mac_address: None,
+        broadcast_address: None,
+        reachability_disabled: false,
+        tunnels: Vec::new(),
+    })
+}
+
+#[cfg(windows)]
+fn export_registry() -> Result<Vec<u8>, String> {
+    let path = std::env::temp_dir().join(format!("terminoda-putty-{}.reg", Uuid::new_v4()));
+    let status = std::process::Command::new("reg")
+        .args([
+            "export",
+            r"HKCU\Software\SimonTatham\PuTTY\Sessions",
+            &path.to_string_lossy(),
+            "/y",
+        ])
+        .status()
+        .map_err(|e| e.to_string())?;
+    let bytes = fs::read(&path);
+    let _ = fs::remove_file(&path);
+    if !status.success() {
+        return Err("No PuTTY sessions found in the registry".to_string());
+    }
+    bytes.map_err(|e| e.to_string())
+}
+
+#[cfg(not(windows))]
+fn export_registry() -> Result<Vec<u8>, String> {
+    Err("Reading PuTTY sessions from the registry needs Windows, choose a .reg export".to_string())
+}
+
+/// Previews PuTTY sessions from a .reg file, or the registry when no path is given.
+#[tauri::command]
+pub fn import_putty_sessions(
+    path: Option<String>,
+    app_handle: AppHandle,
+) -> Result<ImportPreview, AppError> {
+    let (bytes, source) = match path {
+        Some(path) => (fs::read(&path)?, path),
+        None => (export_registry()?, "registry".to_string()),
+    };
+    let (sessions, mut warnings) = parse_reg(&decode_reg(&bytes), &source);
+
+    let hosts = sessions
+        .into_iter()
+        .filter(|(name, _)| name != "Default Settings")
+        .filter_map(|(name, values)| to_saved_host(name, &values, &mut warnings))
+        .collect();
+    Ok(build_preview(hosts, warnings, app_handle)?)
+}
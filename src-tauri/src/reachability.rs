@@ -0,0 +1,252 @@
+// Live/dead indicators for the host list.
+//
+// A probe is a TCP connect to the host's SSH port, timed for latency; no
+// handshake is attempted and nothing is written to connection history. When
+// asked, an unreachable host is also pinged with the system ping, which tells
+// a closed port from a host that is down where ICMP is allowed.
+// start_host_monitor probes a set of saved hosts on an interval, a bounded
+// number at a time, and emits "host-status-changed" when a host's status
+// differs from the last round. Hosts with reachability_disabled are skipped.
+
+use crate::error::{AppError, ErrorKind};
+use crate::load_saved_hosts;
+use dashmap::DashMap;
+use serde::Serialize;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, Window};
+use tracing::info;
+
+const DEFAULT_TIMEOUT_MS: u64 = 2000;
+const MAX_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_INTERVAL_SECS: u64 = 30;
+const MIN_INTERVAL_SECS: u64 = 5;
+// Probes running at once in a monitor round
+const MAX_WORKERS: usize = 8;
+
+// Stop flags of running monitors, one per window
+static MONITORS: LazyLock<DashMap<String, Arc<AtomicBool>>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Reachability {
+    pub reachable: bool,
+    // Time to connect, when reachable
+    pub latency_ms: Option<u64>,
+    // Only set when a ping was asked for and the port didn't answer
+    pub ping: Option<bool>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct HostStatusPayload {
+    host_id: String,
+    status: Reachability,
+}
+
+fn probe(host: &str, port: u16, timeout: Duration, ping: bool) -> Reachability {
+    let started = Instant::now();
+    let result = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())
+        .and_then(|mut addrs| {
+            addrs
+                .next()
+                .ok_or_else(|| format!("Could not resolve {}", host))
+        })
+        .and_then(|addr| TcpStream::connect_timeout(&addr, timeout).map_err(|e| e.to_string()));
+    match result {
+        Ok(_) => Reachability {
+            reachable: true,
+            latency_ms: Some(started.elapsed().as_millis() as u64),
+            ping: None,
+            error: None,
+        },
+        Err(error) => Reachability {
+            reachable: false,
+            latency_ms: None,
+            ping: ping.then(|| system_ping(host, timeout)),
+            error: Some(error),
+        },
+    }
+}
+
+// Raw ICMP sockets need privileges, the system ping usually has them
+fn system_ping(host: &str, timeout: Duration) -> bool {
+    let secs = timeout.as_secs().max(1).to_string();
+    let mut command = Command::new("ping");
+    if cfg!(windows) {
+        command.args(["-n", "1", "-w", &timeout.as_millis().to_string()]);
+    } else if cfg!(target_os = "macos") {
+        command.args(["-c", "1", "-t", &secs]);
+    } else {
+        command.args(["-c", "1", "-W", &secs]);
+    }
+    command
+        .arg(host)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn timeout(timeout_ms: Option<u64>) -> Duration {
+    Duration::from_millis(
+        timeout_ms
+            .unwrap_or(DEFAULT_TIMEOUT_MS)
+            .clamp(100, MAX_TIMEOUT_MS),
+    )
+}
+
+struct Target {
+    host_id: String,
+    host: String,
+    port: u16,
+}
+
+// Probes every target, at most MAX_WORKERS at a time
+fn probe_all(targets: &[Target], timeout: Duration, ping: bool) -> Vec<Reachability> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; targets.len()]);
+    thread::scope(|scope| {
+        for _ in 0..MAX_WORKERS.min(targets.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(target) = targets.get(index) else {
+                    break;
+                };
+                let status = probe(&target.host, target.port, timeout, ping);
+                results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(status);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+fn monitored_targets(app_handle: &AppHandle, host_ids: &[String]) -> Vec<Target> {
+    load_saved_hosts(app_handle.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|h| host_ids.contains(&h.id) && !h.reachability_disabled)
+        .map(|h| Target {
+            host_id: h.id,
+            host: h.details.host,
+            port: h.details.port.unwrap_or(22),
+        })
+        .collect()
+}
+
+/// Probes a saved host by id, or any host and port.
+#[tauri::command]
+pub async fn check_host_reachable(
+    host_id: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    timeout_ms: Option<u64>,
+    ping: Option<bool>,
+    app_handle: AppHandle,
+) -> Result<Reachability, AppError> {
+    let (host, port) = match (host_id, host) {
+        (Some(id), _) => {
+            let saved = load_saved_hosts(app_handle)?
+                .into_iter()
+                .find(|h| h.id == id)
+                .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Host not found"))?;
+            (saved.details.host, port.or(saved.details.port))
+        }
+        (None, Some(host)) => (host, port),
+        (None, None) => {
+            return Err(AppError::new(
+                ErrorKind::InvalidInput,
+                "Pass a host id or a host",
+            ))
+        }
+    };
+    let timeout = timeout(timeout_ms);
+    let ping = ping.unwrap_or(false);
+    tauri::async_runtime::spawn_blocking(move || probe(&host, port.unwrap_or(22), timeout, ping))
+        .await
+        .map_err(|e| AppError::from(e.to_string()))
+}
+
+/// Starts probing `host_ids` every `interval_secs`, replacing the window's
+/// previous monitor. The first round reports every host.
+#[tauri::command]
+pub fn start_host_monitor(
+    host_ids: Vec<String>,
+    interval_secs: Option<u64>,
+    timeout_ms: Option<u64>,
+    ping: Option<bool>,
+    window: Window,
+) -> Result<(), AppError> {
+    let interval = Duration::from_secs(
+        interval_secs
+            .unwrap_or(DEFAULT_INTERVAL_SECS)
+            .max(MIN_INTERVAL_SECS),
+    );
+    let timeout = timeout(timeout_ms);
+    let ping = ping.unwrap_or(false);
+    let label = window.label().to_string();
+    let stop = Arc::new(AtomicBool::new(false));
+    if let Some(previous) = MONITORS.insert(label.clone(), stop.clone()) {
+        previous.store(true, Ordering::Relaxed);
+    }
+    info!(target = "reachability", window = %label, hosts = host_ids.len(), "Starting host monitor");
+
+    thread::spawn(move || {
+        let app_handle = window.app_handle().clone();
+        let mut last: Vec<(String, Reachability)> = Vec::new();
+        while !stop.load(Ordering::Relaxed) && app_handle.get_window(&label).is_some() {
+            let targets = monitored_targets(&app_handle, &host_ids);
+            let statuses = probe_all(&targets, timeout, ping);
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let mut current = Vec::with_capacity(targets.len());
+            for (target, status) in targets.into_iter().zip(statuses) {
+                // Latency alone changing isn't a status change
+                let changed = last
+                    .iter()
+                    .find(|(id, _)| *id == target.host_id)
+                    .is_none_or(|(_, previous)| {
+                        previous.reachable != status.reachable || previous.ping != status.ping
+                    });
+                if changed {
+                    let _ = window.emit(
+                        "host-status-changed",
+                        HostStatusPayload {
+                            host_id: target.host_id.clone(),
+                            status: status.clone(),
+                        },
+                    );
+                }
+                current.push((target.host_id, status));
+            }
+            last = current;
+
+            let round_end = Instant::now() + interval;
+            while Instant::now() < round_end && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(250));
+            }
+        }
+        MONITORS.remove_if(&label, |_, flag| Arc::ptr_eq(flag, &stop));
+        info!(target = "reachability", window = %label, "Host monitor stopped");
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_host_monitor(window: Window) {
+    if let Some((_, stop)) = MONITORS.remove(window.label()) {
+        stop.store(true, Ordering::Relaxed);
+    }
+}
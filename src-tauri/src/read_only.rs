@@ -0,0 +1,117 @@
+// Read-only sessions, a safety latch for fragile hosts.
+//
+// A session starts read-only when its connection details say so, and
+// set_session_readonly switches it at runtime; switching it off needs an
+// explicit confirm. While on, terminal input is refused unless the whole
+// chunk is one of the read_only_allowed_input sequences (arrows, paging and
+// Ctrl+C by default), and SFTP commands that change anything fail with
+// ErrorKind::ReadOnly, as do the commands that run something that changes
+// the host over a side channel: run_background_command, crontab installs,
+// service actions and process kills. Listings, stat, downloads and the
+// read-only views (processes, logs, services) keep working.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{ownership, AppState};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{State, Window};
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize)]
+struct ReadOnlyChanged {
+    session_id: String,
+    read_only: bool,
+}
+
+/// The default for the read_only_allowed_input setting: Ctrl+C, arrows in
+/// both cursor modes, Page Up/Down, Home and End.
+pub fn default_allowed_input() -> Vec<String> {
+    [
+        "\x03", "\x1b[A", "\x1b[B", "\x1b[C", "\x1b[D", "\x1bOA", "\x1bOB", "\x1bOC", "\x1bOD",
+        "\x1b[5~", "\x1b[6~", "\x1b[H", "\x1b[F", "\x1bOH", "\x1bOF",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct ReadOnly(AtomicBool);
+
+impl ReadOnly {
+    pub fn new(enabled: bool) -> Self {
+        Self(AtomicBool::new(enabled))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails while read-only; for commands that change the remote side.
+    pub fn check(&self) -> Result<(), AppError> {
+        if self.is_enabled() {
+            return Err(AppError::new(ErrorKind::ReadOnly, "Session is read-only"));
+        }
+        Ok(())
+    }
+
+    /// Lets terminal input through unless read-only, or if it's exactly one
+    /// of the `allowed` sequences.
+    pub fn check_input(&self, data: &str, allowed: &[String]) -> Result<(), AppError> {
+        if allowed.iter().any(|a| a == data) {
+            return Ok(());
+        }
+        self.check()
+    }
+}
+
+/// ReadOnly::check for a session by id, for commands that don't hold it.
+pub fn check(state: &AppState, session_id: &str) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    session
+        .read_only
+        .check()
+        .map_err(|e| e.with_session(session_id))
+}
+
+/// Switches a session's read-only mode. Switching it off is refused
+/// unless `confirm` is true. Emits "session-readonly-changed".
+#[tauri::command]
+pub fn set_session_readonly(
+    session_id: String,
+    read_only: bool,
+    confirm: Option<bool>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    ownership::check(&session, &session_id, &window)?;
+    if !read_only && session.read_only.is_enabled() && confirm != Some(true) {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Turning read-only off must be confirmed",
+        )
+        .with_session(&session_id));
+    }
+    if session.read_only.0.swap(read_only, Ordering::Relaxed) != read_only {
+        info!(target = "read_only", session = %session_id, read_only, "Changed read-only mode");
+        session.owners.emit(
+            &window,
+            "session-readonly-changed",
+            ReadOnlyChanged {
+                session_id,
+                read_only,
+            },
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+// Blocks the SSH reader thread until the socket has data instead of
+// sleep-polling the channel.
+//
+// libssh2 owns the socket, so a cloned handle is registered with mio purely
+// to observe readiness. Another thread (SFTP, exec) can drain socket data
+// that belongs to the terminal channel into libssh2's buffers without the
+// reader seeing an event, hence the fallback timeout.
+
+use mio::{Events, Interest, Poll, Token, Waker};
+use std::io;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+
+const SOCKET: Token = Token(0);
+const WAKE: Token = Token(1);
+
+pub const FALLBACK_TIMEOUT: Duration = Duration::from_millis(250);
+
+pub struct SocketReadiness {
+    poll: Poll,
+    events: Events,
+    socket: mio::net::TcpStream,
+}
+
+impl SocketReadiness {
+    /// Returns the waiter and a waker that interrupts a pending wait.
+    pub fn new(stream: &TcpStream) -> io::Result<(Self, Arc<Waker>)> {
+        let poll = Poll::new()?;
+        let mut socket = mio::net::TcpStream::from_std(stream.try_clone()?);
+        poll.registry()
+            .register(&mut socket, SOCKET, Interest::READABLE)?;
+        let waker = Arc::new(Waker::new(poll.registry(), WAKE)?);
+        Ok((
+            Self {
+                poll,
+                events: Events::with_capacity(4),
+                socket,
+            },
+            waker,
+        ))
+    }
+
+    /// Waits until the socket is readable, the waker fires or the timeout
+    /// elapses. Spurious returns are harmless, the caller just reads again.
+    pub fn wait(&mut self, timeout: Duration) -> io::Result<()> {
+        // Peeking through mio also re-arms the registration on platforms
+        // where readiness is only reported after a WouldBlock
+        match self.socket.peek(&mut [0u8; 1]) {
+            Ok(_) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        match self.poll.poll(&mut self.events, Some(timeout)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+// Reattaching a reloaded webview to the sessions it still has.
+//
+// A reload (devtools, a renderer crash) loses the frontend's tabs while the
+// sessions live on. When a window's page starts loading again, it's
+// detached from its sessions: output keeps going into the scrollback but no
+// events are sent to it. The frontend calls list_sessions and then
+// attach_session for each one it restores, which returns the scrollback
+// and resumes events in a single step under the scrollback lock, so nothing
+// is shown twice or lost in between. Attaching again just replays the
+// scrollback. With the reattach_grace_secs setting, sessions the window
+// owns and hasn't reattached by then are closed.
+
+use crate::error::AppError;
+use crate::session_meta::{self, OpenSession};
+use crate::{ownership, start_close, AppState};
+use serde::Serialize;
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State, Window};
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachedSession {
+    pub session: OpenSession,
+    pub scrollback: Vec<u8>,
+}
+
+/// Detaches a window whose page started loading from its sessions, and
+/// schedules closing them if the setting asks for it.
+pub fn window_reloaded(app_handle: &AppHandle, label: &str) {
+    let state = app_handle.state::<AppState>();
+    let detached = state
+        .sessions
+        .iter()
+        .filter(|entry| entry.owners.detach(label))
+        .count();
+    if detached == 0 {
+        return;
+    }
+    info!(
+        target = "reattach",
+        window = label,
+        sessions = detached,
+        "Window reloaded, sessions detached"
+    );
+    let Some(grace) = state.settings.get().reattach_grace_secs else {
+        return;
+    };
+    let grace = Duration::from_secs(grace);
+    let app_handle = app_handle.clone();
+    let label = label.to_string();
+    thread::spawn(move || {
+        thread::sleep(grace);
+        let state = app_handle.state::<AppState>();
+        // A later reload keeps the first detach time, so this can't close
+        // sessions early
+        let stale: Vec<Uuid> = state
+            .sessions
+            .iter()
+            .filter(|entry| {
+                entry.owners.owner() == label
+                    && entry
+                        .owners
+                        .detached_since(&label)
+                        .is_some_and(|since| since.elapsed() >= grace)
+            })
+            .map(|entry| *entry.key())
+            .collect();
+        for uuid in stale {
+            info!(target = "reattach", session = %uuid, window = %label, "Closing session that wasn't reattached");
+            start_close(&app_handle, uuid, "not reattached");
+        }
+    });
+}
+
+/// Binds a session to the calling window again after a reload: returns its
+/// details and scrollback, and from then on its events reach the window.
+#[tauri::command]
+pub fn attach_session(
+    session_id: String,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<AttachedSession, AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    ownership::check(&session, &session_id, &window)?;
+    let scrollback = {
+        // Output is emitted under this lock, see OutputPipeline::emit
+        let scrollback = session.lock(&session.scrollback, "scrollback");
+        session.owners.attach(window.label());
+        scrollback.contents()
+    };
+    Ok(AttachedSession {
+        session: session_meta::describe(&uuid, &session),
+        scrollback,
+    })
+}
@@ -0,0 +1,107 @@
+// Permissions given to uploaded files and new remote directories.
+//
+// The remote_modes setting holds the defaults and a host can override any
+// part of it in its connection details. create_directory passes the
+// directory mode to mkdir, where the server's umask may still clear bits.
+// With apply_on_upload on, an upload ends with a setstat of the file mode;
+// otherwise the file keeps whatever the server gave it. Some servers, often
+// ones backed by object storage, refuse setstat: the upload still succeeds
+// and the transfer reports a warning instead.
+
+use crate::error::AppError;
+use crate::sftp_ops;
+use crate::SessionState;
+use serde::{Deserialize, Serialize};
+use ssh2::FileStat;
+use std::path::Path;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteModes {
+    pub file_mode: u32,
+    pub dir_mode: u32,
+    pub apply_on_upload: bool,
+}
+
+impl Default for RemoteModes {
+    fn default() -> Self {
+        Self {
+            file_mode: 0o644,
+            dir_mode: 0o755,
+            apply_on_upload: false,
+        }
+    }
+}
+
+// A host's overrides, unset parts come from the setting
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ModeOverride {
+    pub file_mode: Option<u32>,
+    pub dir_mode: Option<u32>,
+    pub apply_on_upload: Option<bool>,
+}
+
+fn validate_mode(mode: u32, what: &str) -> Result<(), String> {
+    if mode > 0o7777 {
+        return Err(format!("The {} mode must be at most 7777 (octal)", what));
+    }
+    Ok(())
+}
+
+impl RemoteModes {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_mode(self.file_mode, "file")?;
+        validate_mode(self.dir_mode, "directory")
+    }
+
+    /// The modes for a host with `host` overrides.
+    pub fn with(self, host: Option<&ModeOverride>) -> Self {
+        let Some(host) = host else {
+            return self;
+        };
+        Self {
+            file_mode: host.file_mode.unwrap_or(self.file_mode),
+            dir_mode: host.dir_mode.unwrap_or(self.dir_mode),
+            apply_on_upload: host.apply_on_upload.unwrap_or(self.apply_on_upload),
+        }
+    }
+}
+
+impl ModeOverride {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(mode) = self.file_mode {
+            validate_mode(mode, "file")?;
+        }
+        if let Some(mode) = self.dir_mode {
+            validate_mode(mode, "directory")?;
+        }
+        Ok(())
+    }
+}
+
+/// Sets `mode` on an uploaded file. A failure comes back as the warning to
+/// report rather than an error.
+pub fn apply_file_mode(
+    session: &SessionState,
+    op: &sftp_ops::Operation,
+    path: &Path,
+    mode: u32,
+) -> Option<String> {
+    let stat = FileStat {
+        size: None,
+        uid: None,
+        gid: None,
+        perm: Some(mode),
+        atime: None,
+        mtime: None,
+    };
+    let result: Result<(), AppError> =
+        sftp_ops::run(session, op, |sftp| sftp.setstat(path, stat.clone()));
+    let e = result.err()?;
+    warn!(target = "sftp_upload", path = %path.display(), mode = format!("{:o}", mode), error = %e, "Failed to set the uploaded file's mode");
+    Some(format!(
+        "Uploaded, but the mode could not be set to {:o}: {}",
+        mode, e.message
+    ))
+}
@@ -0,0 +1,470 @@
+// Walking a remote directory tree over SFTP.
+//
+// Recursive features share this walker instead of each queueing directories
+// themselves. Entries come from readdir, whose attributes describe a link
+// itself rather than its target (lstat), so a symlink is never entered by
+// accident. By default links are skipped. With follow_symlinks on, a link
+// is stat'ed and resolved with realpath, and it's skipped when it dangles,
+// resolves outside the root (a link to / would otherwise walk the whole
+// server), leads to a directory already walked or to one of its own
+// ancestors, or when links are nested more than max_link_depth deep. SFTP
+// doesn't report device and inode numbers, so directories are told apart by
+// their resolved path. Every skipped link is kept for the operation's
+// summary. The SFTP calls go through RemoteFs, so the rules can be tested
+// against a tree in memory.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{sftp_ops, SessionState};
+use dashmap::DashMap;
+use serde::Serialize;
+use ssh2::FileStat;
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const DEFAULT_MAX_LINK_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct WalkOptions {
+    pub follow_symlinks: bool,
+    // Followed links inside followed links, counted along a path
+    pub max_link_depth: usize,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self {
+            follow_symlinks: false,
+            max_link_depth: DEFAULT_MAX_LINK_DEPTH,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    // follow_symlinks is off
+    NotFollowed,
+    Dangling,
+    OutsideRoot,
+    Cycle,
+    TooDeep,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedLink {
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+pub struct WalkEntry {
+    // Under the root as walked, through any followed links
+    pub path: PathBuf,
+    // Of the link's target for a followed link
+    pub stat: FileStat,
+}
+
+struct Dir {
+    path: PathBuf,
+    // With links resolved
+    resolved: PathBuf,
+    link_depth: usize,
+}
+
+/// The SFTP calls the walker makes.
+pub trait RemoteFs {
+    /// The entries of a directory, with lstat attributes.
+    fn read_dir(&self, path: &Path) -> Result<Vec<(PathBuf, FileStat)>, AppError>;
+    fn stat(&self, path: &Path) -> Result<FileStat, AppError>;
+    fn realpath(&self, path: &Path) -> Result<PathBuf, AppError>;
+}
+
+/// The SFTP connection of an open session.
+pub struct SessionFs<'a> {
+    sessions: &'a DashMap<Uuid, SessionState>,
+    uuid: Uuid,
+    op: &'a sftp_ops::Operation,
+}
+
+impl SessionFs<'_> {
+    // Looks the session up per call, so a long walk doesn't hold up closing it
+    fn on_session<T>(
+        &self,
+        f: impl FnOnce(&SessionState) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let session = self
+            .sessions
+            .get(&self.uuid)
+            .ok_or_else(|| AppError::new(ErrorKind::SessionNotFound, "Session not found"))?;
+        f(&session)
+    }
+}
+
+impl RemoteFs for SessionFs<'_> {
+    fn read_dir(&self, path: &Path) -> Result<Vec<(PathBuf, FileStat)>, AppError> {
+        self.on_session(|s| sftp_ops::read_dir(s, self.op, path))
+    }
+
+    fn stat(&self, path: &Path) -> Result<FileStat, AppError> {
+        self.on_session(|s| sftp_ops::run(s, self.op, |sftp| sftp.stat(path)))
+    }
+
+    fn realpath(&self, path: &Path) -> Result<PathBuf, AppError> {
+        self.on_session(|s| sftp_ops::run(s, self.op, |sftp| sftp.realpath(path)))
+    }
+}
+
+/// Yields every entry under a root, breadth first. Directories that can't
+/// be listed come out as errors and the walk goes on; a closed session ends
+/// it after its error.
+pub struct Walker<F> {
+    fs: F,
+    options: WalkOptions,
+    root: PathBuf,
+    // Set once the root is resolved
+    resolved_root: Option<PathBuf>,
+    dirs: VecDeque<Dir>,
+    ready: VecDeque<Result<WalkEntry, AppError>>,
+    visited: HashSet<PathBuf>,
+    skipped: Vec<SkippedLink>,
+    done: bool,
+}
+
+impl<'a> Walker<SessionFs<'a>> {
+    pub fn new(
+        sessions: &'a DashMap<Uuid, SessionState>,
+        uuid: Uuid,
+        op: &'a sftp_ops::Operation,
+        root: &Path,
+        options: WalkOptions,
+    ) -> Self {
+        Self::with_fs(SessionFs { sessions, uuid, op }, root, options)
+    }
+}
+
+impl<F: RemoteFs> Walker<F> {
+    fn with_fs(fs: F, root: &Path, options: WalkOptions) -> Self {
+        Self {
+            fs,
+            options,
+            root: root.to_path_buf(),
+            resolved_root: None,
+            dirs: VecDeque::new(),
+            ready: VecDeque::new(),
+            visited: HashSet::new(),
+            skipped: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// The links skipped along the way.
+    pub fn into_skipped(self) -> Vec<SkippedLink> {
+        self.skipped
+    }
+
+    fn start(&mut self) -> Result<(), AppError> {
+        let resolved = self.fs.realpath(&self.root)?;
+        self.visited.insert(resolved.clone());
+        self.dirs.push_back(Dir {
+            path: self.root.clone(),
+            resolved: resolved.clone(),
+            link_depth: 0,
+        });
+        self.resolved_root = Some(resolved);
+        Ok(())
+    }
+
+    fn skip(&mut self, path: &Path, reason: SkipReason) {
+        self.skipped.push(SkippedLink {
+            path: path.to_string_lossy().into_owned(),
+            reason,
+        });
+    }
+
+    fn list(&mut self, dir: &Dir) -> Result<(), AppError> {
+        let entries = self.fs.read_dir(&dir.path)?;
+        for (path, stat) in entries {
+            if stat.file_type().is_symlink() {
+                if let Err(e) = self.link(dir, path) {
+                    if e.kind == ErrorKind::SessionNotFound {
+                        return Err(e);
+                    }
+                    self.ready.push_back(Err(e));
+                }
+                continue;
+            }
+            if stat.is_dir() {
+                let resolved = dir.resolved.join(path.file_name().unwrap_or_default());
+                // Already walked through a link
+                if self.visited.insert(resolved.clone()) {
+                    self.dirs.push_back(Dir {
+                        path: path.clone(),
+                        resolved,
+                        link_depth: dir.link_depth,
+                    });
+                }
+            }
+            self.ready.push_back(Ok(WalkEntry { path, stat }));
+        }
+        Ok(())
+    }
+
+    fn link(&mut self, dir: &Dir, path: PathBuf) -> Result<(), AppError> {
+        if !self.options.follow_symlinks {
+            self.skip(&path, SkipReason::NotFollowed);
+            return Ok(());
+        }
+        let stat = match self.fs.stat(&path) {
+            Ok(stat) => stat,
+            Err(e) if e.kind == ErrorKind::NotFound => {
+                self.skip(&path, SkipReason::Dangling);
+                return Ok(());
+            }
+            Err(e) => return Err(e.context(&path.to_string_lossy())),
+        };
+        let resolved = self
+            .fs
+            .realpath(&path)
+            .map_err(|e| e.context(&path.to_string_lossy()))?;
+        let root = self.resolved_root.as_deref().unwrap_or(Path::new("/"));
+        let reason = if !resolved.starts_with(root) {
+            Some(SkipReason::OutsideRoot)
+        } else if stat.is_dir()
+            && (dir.resolved.starts_with(&resolved) || self.visited.contains(&resolved))
+        {
+            Some(SkipReason::Cycle)
+        } else if stat.is_dir() && dir.link_depth >= self.options.max_link_depth {
+            Some(SkipReason::TooDeep)
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            self.skip(&path, reason);
+            return Ok(());
+        }
+        if stat.is_dir() {
+            self.visited.insert(resolved.clone());
+            self.dirs.push_back(Dir {
+                path: path.clone(),
+                resolved,
+                link_depth: dir.link_depth + 1,
+            });
+        }
+        self.ready.push_back(Ok(WalkEntry { path, stat }));
+        Ok(())
+    }
+}
+
+impl<F: RemoteFs> Iterator for Walker<F> {
+    type Item = Result<WalkEntry, AppError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.ready.pop_front() {
+                return Some(item);
+            }
+            if self.done {
+                return None;
+            }
+            let result = if self.resolved_root.is_none() {
+                self.start()
+                    .map_err(|e| e.context(&self.root.to_string_lossy()))
+            } else {
+                let dir = self.dirs.pop_front()?;
+                self.list(&dir)
+                    .map_err(|e| e.context(&dir.path.to_string_lossy()))
+            };
+            if let Err(e) = result {
+                // Nothing more can be listed without the session or the root
+                if e.kind == ErrorKind::SessionNotFound || self.resolved_root.is_none() {
+                    self.done = true;
+                }
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[derive(Clone, Copy)]
+    enum Node {
+        Dir,
+        File,
+        Link(&'static str),
+    }
+
+    // A remote tree in memory, keyed by absolute path
+    struct MemoryFs(BTreeMap<PathBuf, Node>);
+
+    impl MemoryFs {
+        fn new(nodes: &[(&str, Node)]) -> Self {
+            let mut tree = BTreeMap::new();
+            tree.insert(PathBuf::from("/"), Node::Dir);
+            for (path, node) in nodes {
+                tree.insert(PathBuf::from(path), *node);
+            }
+            Self(tree)
+        }
+
+        fn not_found(path: &Path) -> AppError {
+            AppError::new(ErrorKind::NotFound, path.to_string_lossy())
+        }
+
+        fn stat_of(node: &Node) -> FileStat {
+            let perm = match node {
+                Node::Dir => 0o040755,
+                Node::File => 0o100644,
+                Node::Link(_) => 0o120777,
+            };
+            FileStat {
+                size: Some(0),
+                uid: None,
+                gid: None,
+                perm: Some(perm),
+                atime: None,
+                mtime: None,
+            }
+        }
+
+        // Resolves every link along the path
+        fn resolve(&self, path: &Path, hops: usize) -> Result<PathBuf, AppError> {
+            let mut resolved = PathBuf::from("/");
+            for component in path.components().skip(1) {
+                if component == std::path::Component::ParentDir {
+                    resolved.pop();
+                    continue;
+                }
+                resolved.push(component);
+                match self.0.get(&resolved) {
+                    None => return Err(Self::not_found(path)),
+                    Some(Node::Link(_)) if hops == 0 => return Err(Self::not_found(path)),
+                    Some(Node::Link(target)) => {
+                        let target = resolved.parent().unwrap_or(Path::new("/")).join(target);
+                        resolved = self.resolve(&target, hops - 1)?;
+                    }
+                    Some(_) => {}
+                }
+            }
+            Ok(resolved)
+        }
+    }
+
+    impl RemoteFs for MemoryFs {
+        fn read_dir(&self, path: &Path) -> Result<Vec<(PathBuf, FileStat)>, AppError> {
+            let dir = self.resolve(path, 8)?;
+            Ok(self
+                .0
+                .iter()
+                .filter(|(p, _)| p.parent() == Some(dir.as_path()))
+                .map(|(p, node)| (path.join(p.file_name().unwrap()), Self::stat_of(node)))
+                .collect())
+        }
+
+        fn stat(&self, path: &Path) -> Result<FileStat, AppError> {
+            let resolved = self.resolve(path, 8)?;
+            Ok(Self::stat_of(&self.0[&resolved]))
+        }
+
+        fn realpath(&self, path: &Path) -> Result<PathBuf, AppError> {
+            self.resolve(path, 8)
+        }
+    }
+
+    fn following() -> WalkOptions {
+        WalkOptions {
+            follow_symlinks: true,
+            ..WalkOptions::default()
+        }
+    }
+
+    fn walk(fs: MemoryFs, options: WalkOptions) -> (Vec<String>, Vec<(String, SkipReason)>) {
+        let mut walker = Walker::with_fs(fs, Path::new("/root"), options);
+        let mut paths: Vec<String> = walker
+            .by_ref()
+            .map(|entry| entry.unwrap().path.to_string_lossy().into_owned())
+            .collect();
+        paths.sort();
+        let skipped = walker
+            .into_skipped()
+            .into_iter()
+            .map(|s| (s.path, s.reason))
+            .collect();
+        (paths, skipped)
+    }
+
+    #[test]
+    fn links_are_skipped_unless_followed() {
+        let fs = MemoryFs::new(&[
+            ("/root", Node::Dir),
+            ("/root/file", Node::File),
+            ("/root/link", Node::Link("file")),
+        ]);
+        let (paths, skipped) = walk(fs, WalkOptions::default());
+        assert_eq!(paths, vec!["/root/file"]);
+        assert_eq!(
+            skipped,
+            vec![("/root/link".to_string(), SkipReason::NotFollowed)]
+        );
+    }
+
+    #[test]
+    fn link_to_an_ancestor_is_a_cycle() {
+        let fs = MemoryFs::new(&[
+            ("/root", Node::Dir),
+            ("/root/a", Node::Dir),
+            ("/root/a/file", Node::File),
+            ("/root/a/up", Node::Link("/root")),
+        ]);
+        let (paths, skipped) = walk(fs, following());
+        assert_eq!(paths, vec!["/root/a", "/root/a/file"]);
+        assert_eq!(skipped, vec![("/root/a/up".to_string(), SkipReason::Cycle)]);
+    }
+
+    #[test]
+    fn absolute_link_outside_the_root_is_skipped() {
+        let fs = MemoryFs::new(&[
+            ("/etc", Node::Dir),
+            ("/etc/passwd", Node::File),
+            ("/root", Node::Dir),
+            ("/root/etc", Node::Link("/etc")),
+        ]);
+        let (paths, skipped) = walk(fs, following());
+        assert!(paths.is_empty());
+        assert_eq!(
+            skipped,
+            vec![("/root/etc".to_string(), SkipReason::OutsideRoot)]
+        );
+    }
+
+    #[test]
+    fn dangling_link_is_skipped() {
+        let fs = MemoryFs::new(&[("/root", Node::Dir), ("/root/gone", Node::Link("missing"))]);
+        let (paths, skipped) = walk(fs, following());
+        assert!(paths.is_empty());
+        assert_eq!(
+            skipped,
+            vec![("/root/gone".to_string(), SkipReason::Dangling)]
+        );
+    }
+
+    #[test]
+    fn followed_link_inside_the_root_is_walked() {
+        let fs = MemoryFs::new(&[
+            ("/root", Node::Dir),
+            ("/root/real", Node::Dir),
+            ("/root/real/file", Node::File),
+            ("/root/z", Node::Dir),
+            ("/root/z/alias", Node::Link("../real/file")),
+        ]);
+        let (paths, skipped) = walk(fs, following());
+        assert_eq!(
+            paths,
+            vec!["/root/real", "/root/real/file", "/root/z", "/root/z/alias"]
+        );
+        assert!(skipped.is_empty());
+    }
+}
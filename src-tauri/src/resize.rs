@@ -0,0 +1,53 @@
+// Coalescing terminal resizes.
+//
+// Dragging a window fires a resize per frame. Only one caller applies sizes
+// at a time; the others leave their size for it to pick up, so a storm turns
+// into a few request_pty_size calls instead of one per event queued on the
+// channel lock behind streaming output.
+
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct QueueState {
+    pending: Option<(u32, u32)>,
+    applied: Option<(u32, u32)>,
+    applying: bool,
+}
+
+#[derive(Default)]
+pub struct ResizeQueue {
+    state: Mutex<QueueState>,
+}
+
+impl ResizeQueue {
+    /// Records the latest size. Returns true if the caller should apply it,
+    /// draining next(); false if the caller already applying will pick it up.
+    pub fn submit(&self, cols: u32, rows: u32) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.pending = Some((cols, rows));
+        !std::mem::replace(&mut state.applying, true)
+    }
+
+    /// Takes the next size to apply, skipping one already in effect. None
+    /// ends the caller's turn.
+    pub fn next(&self) -> Option<(u32, u32)> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.pending.take() {
+            Some(size) if state.applied != Some(size) => {
+                state.applied = Some(size);
+                Some(size)
+            }
+            _ => {
+                state.applying = false;
+                None
+            }
+        }
+    }
+
+    /// Ends the caller's turn after a failed resize so the size is retried.
+    pub fn abort(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.applied = None;
+        state.applying = false;
+    }
+}
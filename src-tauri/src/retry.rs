@@ -0,0 +1,108 @@
+// Retrying transfers that fail on a flaky connection.
+//
+// The policy comes from settings (transfer_retry) and can be overridden per
+// call. Each attempt is the caller's closure, which resumes from whatever it
+// already copied; between attempts "transfer-retrying" is emitted with the
+// error that triggered it. Giving up returns the last attempt's error as is.
+
+use crate::ownership::SessionOwners;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::thread;
+use std::time::Duration;
+use tauri::Window;
+use tracing::warn;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    // Including the first; 1 disables retries
+    pub max_attempts: u32,
+    // Wait before each retry, the last entry repeats
+    pub backoff_ms: Vec<u64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            backoff_ms: vec![1_000, 3_000, 10_000],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=20).contains(&self.max_attempts) {
+            return Err("Transfer attempts must be between 1 and 20".to_string());
+        }
+        if self.backoff_ms.iter().any(|&ms| ms > 10 * 60 * 1000) {
+            return Err("Transfer retry delays cannot exceed 10 minutes".to_string());
+        }
+        Ok(())
+    }
+
+    // Wait before attempt number `attempt` (2 for the first retry)
+    fn delay(&self, attempt: u32) -> Duration {
+        let index = (attempt as usize).saturating_sub(2);
+        let ms = self
+            .backoff_ms
+            .get(index)
+            .or(self.backoff_ms.last())
+            .copied()
+            .unwrap_or(0);
+        Duration::from_millis(ms)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TransferRetryingPayload {
+    session_id: String,
+    file_path: String,
+    attempt: u32,
+    max_attempts: u32,
+    delay_ms: u64,
+    error: String,
+}
+
+// Identifies the transfer in events and logs
+pub struct RetryTarget<'a> {
+    pub window: &'a Window,
+    pub owners: &'a SessionOwners,
+    pub session_id: &'a str,
+    pub file_path: &'a str,
+}
+
+/// Runs `attempt` (called with the attempt number, from 1) until it succeeds,
+/// fails with an error `retryable` rejects, or the policy runs out.
+pub fn run<T, E: Display>(
+    policy: &RetryPolicy,
+    target: &RetryTarget<'_>,
+    retryable: impl Fn(&E) -> bool,
+    mut attempt: impl FnMut(u32) -> Result<T, E>,
+) -> Result<T, E> {
+    let mut number = 1;
+    loop {
+        match attempt(number) {
+            Err(e) if number < policy.max_attempts && retryable(&e) => {
+                number += 1;
+                let delay = policy.delay(number);
+                warn!(target = "transfer", session = %target.session_id, path = %target.file_path, attempt = number, error = %e, "Transfer failed, retrying");
+                target.owners.emit(
+                    target.window,
+                    "transfer-retrying",
+                    TransferRetryingPayload {
+                        session_id: target.session_id.to_string(),
+                        file_path: target.file_path.to_string(),
+                        attempt: number,
+                        max_attempts: policy.max_attempts,
+                        delay_ms: delay.as_millis() as u64,
+                        error: e.to_string(),
+                    },
+                );
+                thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
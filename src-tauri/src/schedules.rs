@@ -0,0 +1,527 @@
+// Snippets run on a schedule against connected sessions.
+//
+// A schedule fires on an interval or a cron expression (evaluated in UTC)
+// and runs its snippet on a side channel of each connected target, so the
+// interactive shell never sees it. Every run emits "scheduled-run-completed"
+// with its exit code and trimmed output. A session target remembers the
+// saved host it was opened from: when the session disconnects the schedule
+// pauses, and it resumes on the next session to that host. Host targets use
+// the host's longest-connected session. Interval schedules run as soon as a
+// target connects, then every interval. Schedules persist in
+// schedules.json; recent runs are kept in memory only.
+
+use crate::crontab::CronSchedule;
+use crate::error::{AppError, ErrorKind};
+use crate::side_channel::run_on_side_channel;
+use crate::snippets::{civil_from_days, find_snippet, render};
+use crate::{config_file, get_config_dir, load_saved_hosts, ownership, AppState, SessionTarget};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const TICK: Duration = Duration::from_secs(1);
+const MIN_INTERVAL_SECS: u64 = 10;
+const RUN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+// Runs kept per schedule for list_schedules
+const RECENT_RUNS: usize = 20;
+// Output beyond this keeps only its end
+const MAX_OUTPUT_BYTES: usize = 4096;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Trigger {
+    Interval { every_secs: u64 },
+    // Five fields or @hourly, @daily, ...; in UTC
+    Cron { expression: String },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScheduleTarget {
+    #[serde(default)]
+    pub session_id: Option<String>,
+    // For a session target, the host it was opened from
+    #[serde(default)]
+    pub host_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub snippet_id: String,
+    pub targets: Vec<ScheduleTarget>,
+    pub trigger: Trigger,
+    // Values for the snippet's placeholders
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    pub enabled: bool,
+    pub created_at: u64, // Unix timestamp
+}
+
+// What create_schedule and update_schedule take
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduleSpec {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub snippet_id: String,
+    #[serde(default)]
+    pub session_ids: Vec<String>,
+    #[serde(default)]
+    pub host_ids: Vec<String>,
+    pub trigger: Trigger,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    // On by default
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledRun {
+    pub schedule_id: String,
+    pub snippet_id: String,
+    pub session_id: String,
+    pub host_id: Option<String>,
+    pub started_at_ms: u64,
+    pub duration_ms: u64,
+    // None when the snippet couldn't run at all, see error
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleStatus {
+    Active,
+    // No target is connected
+    Paused,
+    Disabled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleInfo {
+    #[serde(flatten)]
+    pub schedule: Schedule,
+    pub status: ScheduleStatus,
+    // Sessions the next run would use
+    pub session_ids: Vec<String>,
+    pub recent_runs: Vec<ScheduledRun>,
+}
+
+type RunKey = (String, String); // (schedule id, session id)
+
+#[derive(Default)]
+pub struct Scheduler {
+    schedules: Mutex<Vec<Schedule>>,
+    // Interval runs only; dropped when the session goes, so a reconnect
+    // runs right away
+    last_run: Mutex<HashMap<RunKey, Instant>>,
+    running: Mutex<HashSet<RunKey>>,
+    recent: Mutex<HashMap<String, VecDeque<ScheduledRun>>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Scheduler {
+    fn snapshot(&self) -> Vec<Schedule> {
+        lock(&self.schedules).clone()
+    }
+
+    fn record(&self, run: ScheduledRun) {
+        let mut recent = lock(&self.recent);
+        let runs = recent.entry(run.schedule_id.clone()).or_default();
+        runs.push_front(run);
+        runs.truncate(RECENT_RUNS);
+    }
+
+    fn forget(&self, schedule_id: &str) {
+        lock(&self.last_run).retain(|(id, _), _| id != schedule_id);
+        lock(&self.recent).remove(schedule_id);
+    }
+}
+
+fn get_schedules_path() -> Result<PathBuf, String> {
+    Ok(get_config_dir()?.join("schedules.json"))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// Keeps the end, where a health check's verdict usually is
+fn trim_output(output: &str) -> String {
+    let output = output.trim();
+    if output.len() <= MAX_OUTPUT_BYTES {
+        return output.to_string();
+    }
+    let mut start = output.len() - MAX_OUTPUT_BYTES;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    output[start..].to_string()
+}
+
+// Connected sessions for a schedule's targets, each once
+fn resolve(state: &AppState, targets: &[ScheduleTarget]) -> Vec<(String, SessionTarget)> {
+    let mut found: Vec<(String, SessionTarget)> = Vec::new();
+    for target in targets {
+        let live = target
+            .session_id
+            .as_deref()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .and_then(|uuid| state.sessions.get(&uuid))
+            .map(|session| (session.key().to_string(), session.target.clone()));
+        let session = live.or_else(|| {
+            let host_id = target.host_id.as_deref()?;
+            state
+                .sessions
+                .iter()
+                .filter(|session| session.target.host_id.as_deref() == Some(host_id))
+                .min_by_key(|session| session.target.connected_at)
+                .map(|session| (session.key().to_string(), session.target.clone()))
+        });
+        if let Some(session) = session {
+            if !found.iter().any(|(id, _)| *id == session.0) {
+                found.push(session);
+            }
+        }
+    }
+    found
+}
+
+// (minute, hour, day, month, weekday) in UTC
+fn utc_fields(secs: u64) -> (u32, u32, u32, u32, u32) {
+    let days = secs / 86_400;
+    let (_, month, day) = civil_from_days(days as i64);
+    // 1970-01-01 was a Thursday
+    let weekday = ((days + 4) % 7) as u32;
+    (
+        (secs / 60 % 60) as u32,
+        (secs / 3600 % 24) as u32,
+        day,
+        month,
+        weekday,
+    )
+}
+
+fn run_one(app_handle: &AppHandle, schedule: &Schedule, session_id: &str, target: &SessionTarget) {
+    let state = app_handle.state::<AppState>();
+    let started_at_ms = now_millis();
+    let started = Instant::now();
+    let result = find_snippet(app_handle, &schedule.snippet_id)
+        .and_then(|snippet| Ok(render(&snippet.command, &schedule.variables, target)?))
+        .and_then(|command| {
+            run_on_side_channel(&state.sessions, session_id, &command, RUN_TIMEOUT)
+        });
+    let mut run = ScheduledRun {
+        schedule_id: schedule.id.clone(),
+        snippet_id: schedule.snippet_id.clone(),
+        session_id: session_id.to_string(),
+        host_id: target.host_id.clone(),
+        started_at_ms,
+        duration_ms: started.elapsed().as_millis() as u64,
+        exit_code: None,
+        stdout: String::new(),
+        stderr: String::new(),
+        error: None,
+    };
+    match result {
+        Ok(output) => {
+            run.exit_code = Some(output.exit_status);
+            run.stdout = trim_output(&output.stdout);
+            run.stderr = trim_output(&output.stderr);
+        }
+        Err(e) => {
+            warn!(target = "schedules", schedule = %schedule.id, session = %session_id, error = %e, "Scheduled run failed");
+            run.error = Some(e.message);
+        }
+    }
+    info!(target = "schedules", schedule = %schedule.id, session = %session_id, exit_code = ?run.exit_code, "Scheduled run finished");
+    state.scheduler.record(run.clone());
+    let _ = app_handle.emit("scheduled-run-completed", run);
+}
+
+fn tick(app_handle: &AppHandle, cron_minute: Option<u64>) {
+    let state = app_handle.state::<AppState>();
+    let scheduler = &state.scheduler;
+    let now = Instant::now();
+    let fields = cron_minute.map(|minute| utc_fields(minute * 60));
+    let mut live_keys = HashSet::new();
+
+    for schedule in scheduler.snapshot() {
+        if !schedule.enabled {
+            continue;
+        }
+        let cron_due = match (&schedule.trigger, fields) {
+            (Trigger::Cron { expression }, Some((minute, hour, day, month, weekday))) => {
+                CronSchedule::parse(expression)
+                    .is_ok_and(|cron| cron.matches(minute, hour, day, month, weekday))
+            }
+            _ => false,
+        };
+        for (session_id, target) in resolve(&state, &schedule.targets) {
+            let key = (schedule.id.clone(), session_id.clone());
+            live_keys.insert(key.clone());
+            let due = match &schedule.trigger {
+                Trigger::Interval { every_secs } => lock(&scheduler.last_run)
+                    .get(&key)
+                    .is_none_or(|last| now.duration_since(*last).as_secs() >= *every_secs),
+                Trigger::Cron { .. } => cron_due,
+            };
+            // A run still going when the next is due is skipped, not stacked
+            if !due || !lock(&scheduler.running).insert(key.clone()) {
+                continue;
+            }
+            lock(&scheduler.last_run).insert(key.clone(), now);
+            let app_handle = app_handle.clone();
+            let schedule = schedule.clone();
+            thread::spawn(move || {
+                run_one(&app_handle, &schedule, &session_id, &target);
+                let state = app_handle.state::<AppState>();
+                lock(&state.scheduler.running).remove(&key);
+            });
+        }
+    }
+    // Targets that disconnected start over when they come back
+    lock(&scheduler.last_run).retain(|key, _| live_keys.contains(key));
+}
+
+/// Loads schedules.json and starts the ticker. A broken file is logged and
+/// no schedules run, the app still starts.
+pub fn init(app_handle: &AppHandle) {
+    let loaded = get_schedules_path()
+        .and_then(|path| config_file::load::<Vec<Schedule>>(app_handle, &path))
+        .map(Option::unwrap_or_default);
+    match loaded {
+        Ok(schedules) => {
+            info!(
+                target = "schedules",
+                count = schedules.len(),
+                "Loaded schedules"
+            );
+            *lock(&app_handle.state::<AppState>().scheduler.schedules) = schedules;
+        }
+        Err(e) => warn!(target = "schedules", error = %e, "Could not load schedules"),
+    }
+
+    let app_handle = app_handle.clone();
+    thread::spawn(move || {
+        let minute = || {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() / 60)
+                .unwrap_or(0)
+        };
+        let mut last_minute = minute();
+        loop {
+            thread::sleep(TICK);
+            let current = minute();
+            let cron_minute = (current != last_minute).then_some(current);
+            last_minute = current;
+            tick(&app_handle, cron_minute);
+        }
+    });
+}
+
+// Writes the schedules after `update` changed them, under the file lock
+fn update_schedules<T>(
+    app_handle: &AppHandle,
+    update: impl FnOnce(&mut Vec<Schedule>) -> Result<T, AppError>,
+) -> Result<T, AppError> {
+    let path = get_schedules_path()?;
+    let _lock = config_file::lock(&path)?;
+    let state = app_handle.state::<AppState>();
+    let mut schedules = lock(&state.scheduler.schedules);
+    let mut updated = schedules.clone();
+    let result = update(&mut updated)?;
+    config_file::write(&path, &updated)?;
+    *schedules = updated;
+    Ok(result)
+}
+
+fn validate_trigger(trigger: &Trigger) -> Result<(), AppError> {
+    let invalid = |message: String| AppError::new(ErrorKind::InvalidInput, message);
+    match trigger {
+        Trigger::Interval { every_secs } if *every_secs < MIN_INTERVAL_SECS => {
+            Err(invalid(format!(
+                "The interval must be at least {} seconds",
+                MIN_INTERVAL_SECS
+            )))
+        }
+        Trigger::Interval { .. } => Ok(()),
+        Trigger::Cron { expression } => {
+            CronSchedule::parse(expression).map(|_| ()).map_err(invalid)
+        }
+    }
+}
+
+// Checks the spec and turns its session and host ids into targets
+fn targets(
+    spec: &ScheduleSpec,
+    window: &Window,
+    app_handle: &AppHandle,
+) -> Result<Vec<ScheduleTarget>, AppError> {
+    if spec.session_ids.is_empty() && spec.host_ids.is_empty() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "A schedule needs at least one session or host",
+        ));
+    }
+    let snippet = find_snippet(app_handle, &spec.snippet_id)?;
+    // Every placeholder without a default must have a value
+    render(&snippet.command, &spec.variables, &SessionTarget::default())
+        .map_err(|e| AppError::new(ErrorKind::InvalidInput, e))?;
+    validate_trigger(&spec.trigger)?;
+
+    let state = app_handle.state::<AppState>();
+    let mut targets = Vec::new();
+    for session_id in &spec.session_ids {
+        ownership::authorize(&state, session_id, window)?;
+        let uuid = Uuid::parse_str(session_id)?;
+        let host_id = state
+            .sessions
+            .get(&uuid)
+            .and_then(|session| session.target.host_id.clone());
+        targets.push(ScheduleTarget {
+            session_id: Some(session_id.clone()),
+            host_id,
+        });
+    }
+    let hosts = load_saved_hosts(app_handle.clone())?;
+    for host_id in &spec.host_ids {
+        if !hosts.iter().any(|h| h.id == *host_id) {
+            return Err(AppError::new(
+                ErrorKind::NotFound,
+                format!("Host not found: {}", host_id),
+            ));
+        }
+        targets.push(ScheduleTarget {
+            session_id: None,
+            host_id: Some(host_id.clone()),
+        });
+    }
+    Ok(targets)
+}
+
+/// Lists schedules with whether they are running, paused or disabled and
+/// their most recent runs.
+#[tauri::command]
+pub fn list_schedules(app_handle: AppHandle) -> Vec<ScheduleInfo> {
+    let state = app_handle.state::<AppState>();
+    let recent = lock(&state.scheduler.recent).clone();
+    state
+        .scheduler
+        .snapshot()
+        .into_iter()
+        .map(|schedule| {
+            let session_ids: Vec<String> = resolve(&state, &schedule.targets)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            let status = if !schedule.enabled {
+                ScheduleStatus::Disabled
+            } else if session_ids.is_empty() {
+                ScheduleStatus::Paused
+            } else {
+                ScheduleStatus::Active
+            };
+            ScheduleInfo {
+                recent_runs: recent
+                    .get(&schedule.id)
+                    .map(|runs| runs.iter().cloned().collect())
+                    .unwrap_or_default(),
+                schedule,
+                status,
+                session_ids,
+            }
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn create_schedule(
+    schedule: ScheduleSpec,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<Schedule, AppError> {
+    let created = Schedule {
+        id: Uuid::new_v4().to_string(),
+        name: schedule.name.clone(),
+        snippet_id: schedule.snippet_id.clone(),
+        targets: targets(&schedule, &window, &app_handle)?,
+        trigger: schedule.trigger,
+        variables: schedule.variables,
+        enabled: schedule.enabled.unwrap_or(true),
+        created_at: now_millis() / 1000,
+    };
+    update_schedules(&app_handle, |schedules| {
+        schedules.push(created.clone());
+        Ok(())
+    })?;
+    info!(target = "schedules", schedule = %created.id, snippet = %created.snippet_id, "Created schedule");
+    Ok(created)
+}
+
+/// Replaces a schedule's settings, keeping its id. Interval timing starts
+/// over.
+#[tauri::command]
+pub fn update_schedule(
+    schedule_id: String,
+    schedule: ScheduleSpec,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<Schedule, AppError> {
+    let targets = targets(&schedule, &window, &app_handle)?;
+    let updated = update_schedules(&app_handle, |schedules| {
+        let existing = schedules
+            .iter_mut()
+            .find(|s| s.id == schedule_id)
+            .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Schedule not found"))?;
+        *existing = Schedule {
+            id: existing.id.clone(),
+            name: schedule.name,
+            snippet_id: schedule.snippet_id,
+            targets,
+            trigger: schedule.trigger,
+            variables: schedule.variables,
+            enabled: schedule.enabled.unwrap_or(true),
+            created_at: existing.created_at,
+        };
+        Ok(existing.clone())
+    })?;
+    lock(&app_handle.state::<AppState>().scheduler.last_run)
+        .retain(|(id, _), _| *id != schedule_id);
+    Ok(updated)
+}
+
+#[tauri::command]
+pub fn delete_schedule(schedule_id: String, app_handle: AppHandle) -> Result<(), AppError> {
+    update_schedules(&app_handle, |schedules| {
+        let before = schedules.len();
+        schedules.retain(|s| s.id != schedule_id);
+        if schedules.len() == before {
+            return Err(AppError::new(ErrorKind::NotFound, "Schedule not found"));
+        }
+        Ok(())
+    })?;
+    app_handle
+        .state::<AppState>()
+        .scheduler
+        .forget(&schedule_id);
+    info!(target = "schedules", schedule = %schedule_id, "Deleted schedule");
+    Ok(())
+}
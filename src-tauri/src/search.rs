@@ -0,0 +1,270 @@
+// One fuzzy search over everything the command palette can jump to.
+//
+// Saved hosts match on name, address, tags and notes; snippets on name,
+// command and description; history on user@host of recent connections,
+// one entry per destination. Data is read fresh on every call. Each item
+// scores by its best field, with fields other than the title counting for
+// less, and reports which field matched with the character indices of the
+// match, for highlighting. Results are ranked within their category and
+// capped per category.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{history, load_saved_hosts, load_snippets};
+use nucleo_matcher::pattern::{CaseMatching, Normalization, Pattern};
+use nucleo_matcher::{Config, Matcher, Utf32Str};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use tauri::{async_runtime, AppHandle};
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 100;
+// History entries searched, newest first, after merging repeats
+const RECENT_HISTORY: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchCategory {
+    Host,
+    Snippet,
+    History,
+}
+
+const ALL_CATEGORIES: [SearchCategory; 3] = [
+    SearchCategory::Host,
+    SearchCategory::Snippet,
+    SearchCategory::History,
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub category: SearchCategory,
+    // Host, snippet or history entry id
+    pub id: String,
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub score: u32,
+    // e.g. "name", "host", "tags"; None for an empty query
+    pub matched_field: Option<String>,
+    // The text that matched: the tag or the line of the notes, not all of them
+    pub matched_text: Option<String>,
+    // Character (not byte) offsets into matched_text, ascending
+    pub indices: Vec<u32>,
+}
+
+// A searchable piece of an item, weighted in percent
+struct Field<'a> {
+    name: &'static str,
+    text: &'a str,
+    weight: u32,
+}
+
+fn field<'a>(name: &'static str, text: &'a str, weight: u32) -> Field<'a> {
+    Field { name, text, weight }
+}
+
+// Which field matched and where
+struct Match {
+    field: &'static str,
+    text: String,
+    indices: Vec<u32>,
+}
+
+struct Search {
+    pattern: Pattern,
+    matcher: Matcher,
+    buf: Vec<char>,
+    empty: bool,
+}
+
+impl Search {
+    fn new(query: &str) -> Self {
+        Search {
+            pattern: Pattern::parse(query, CaseMatching::Smart, Normalization::Smart),
+            matcher: Matcher::new(Config::DEFAULT),
+            buf: Vec::new(),
+            empty: query.trim().is_empty(),
+        }
+    }
+
+    // The best-scoring field, or None when nothing matches. With an empty
+    // query every item matches with no field.
+    fn best(&mut self, fields: &[Field]) -> Option<(u32, Option<Match>)> {
+        if self.empty {
+            return Some((0, None));
+        }
+        let mut best: Option<(u32, Match)> = None;
+        for field in fields {
+            let mut indices = Vec::new();
+            let haystack = Utf32Str::new(field.text, &mut self.buf);
+            let Some(score) = self
+                .pattern
+                .indices(haystack, &mut self.matcher, &mut indices)
+            else {
+                continue;
+            };
+            let score = score * field.weight / 100;
+            if best.as_ref().is_none_or(|(b, _)| score > *b) {
+                indices.sort_unstable();
+                indices.dedup();
+                let text = field.text.to_string();
+                let field = field.name;
+                best = Some((
+                    score,
+                    Match {
+                        field,
+                        text,
+                        indices,
+                    },
+                ));
+            }
+        }
+        best.map(|(score, matched)| (score, Some(matched)))
+    }
+
+    fn result(
+        &mut self,
+        category: SearchCategory,
+        id: &str,
+        title: &str,
+        subtitle: Option<String>,
+        fields: &[Field],
+    ) -> Option<SearchResult> {
+        let (score, matched) = self.best(fields)?;
+        let (matched_field, matched_text, indices) = match matched {
+            Some(m) => (Some(m.field.to_string()), Some(m.text), m.indices),
+            None => (None, None, Vec::new()),
+        };
+        Some(SearchResult {
+            category,
+            id: id.to_string(),
+            title: title.to_string(),
+            subtitle,
+            score,
+            matched_field,
+            matched_text,
+            indices,
+        })
+    }
+}
+
+fn search_hosts(
+    app_handle: &AppHandle,
+    search: &mut Search,
+) -> Result<Vec<SearchResult>, AppError> {
+    let hosts = load_saved_hosts(app_handle.clone())?;
+    Ok(hosts
+        .iter()
+        .filter_map(|host| {
+            let address = format!("{}@{}", host.details.username, host.details.host);
+            let mut fields = vec![field("name", &host.name, 100), field("host", &address, 90)];
+            fields.extend(host.tags.iter().map(|tag| field("tags", tag, 80)));
+            if let Some(notes) = &host.notes {
+                fields.extend(notes.lines().map(|line| field("notes", line, 50)));
+            }
+            search.result(
+                SearchCategory::Host,
+                &host.id,
+                &host.name,
+                Some(address.clone()),
+                &fields,
+            )
+        })
+        .collect())
+}
+
+fn search_snippets(
+    app_handle: &AppHandle,
+    search: &mut Search,
+) -> Result<Vec<SearchResult>, AppError> {
+    let snippets = load_snippets(app_handle.clone())?;
+    Ok(snippets
+        .iter()
+        .filter_map(|snippet| {
+            let mut fields = vec![
+                field("name", &snippet.name, 100),
+                field("command", &snippet.command, 80),
+            ];
+            if let Some(description) = &snippet.description {
+                fields.push(field("description", description, 50));
+            }
+            search.result(
+                SearchCategory::Snippet,
+                &snippet.id,
+                &snippet.name,
+                Some(snippet.command.clone()),
+                &fields,
+            )
+        })
+        .collect())
+}
+
+fn search_history(
+    app_handle: &AppHandle,
+    search: &mut Search,
+) -> Result<Vec<SearchResult>, AppError> {
+    let history = history::read(app_handle)?;
+    let mut seen = HashSet::new();
+    Ok(history
+        .iter()
+        .rev()
+        .filter(|log| seen.insert((log.username.clone(), log.host.clone(), log.port)))
+        .take(RECENT_HISTORY)
+        .filter_map(|log| {
+            let destination = match log.port {
+                Some(port) if port != 22 => format!("{}@{}:{}", log.username, log.host, port),
+                _ => format!("{}@{}", log.username, log.host),
+            };
+            search.result(
+                SearchCategory::History,
+                &log.id,
+                &destination,
+                Some(log.status.clone()),
+                &[field("destination", &destination, 100)],
+            )
+        })
+        .collect())
+}
+
+/// Fuzzy-searches hosts, snippets and connection history for the command
+/// palette. Returns at most `limit_per_category` results (10 by default)
+/// from each of `categories` (all by default), best first within each
+/// category, in the order the categories were given. An empty query lists
+/// each category in its usual order.
+#[tauri::command]
+pub async fn search_all(
+    query: String,
+    categories: Option<Vec<SearchCategory>>,
+    limit_per_category: Option<usize>,
+    app_handle: AppHandle,
+) -> Result<Vec<SearchResult>, AppError> {
+    let limit = limit_per_category.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            format!("limit_per_category must be between 1 and {}", MAX_LIMIT),
+        ));
+    }
+    let mut categories = categories.unwrap_or_else(|| ALL_CATEGORIES.to_vec());
+    let mut seen = HashSet::new();
+    categories.retain(|c| seen.insert(*c));
+
+    async_runtime::spawn_blocking(move || {
+        let mut search = Search::new(&query);
+        let mut results = Vec::new();
+        for category in categories {
+            let mut found = match category {
+                SearchCategory::Host => search_hosts(&app_handle, &mut search)?,
+                SearchCategory::Snippet => search_snippets(&app_handle, &mut search)?,
+                SearchCategory::History => search_history(&app_handle, &mut search)?,
+            };
+            // Stable, so ties keep the category's own order
+            found.sort_by_key(|r| Reverse(r.score));
+            found.truncate(limit);
+            results.extend(found);
+        }
+        Ok(results)
+    })
+    .await
+    .map_err(|e| AppError::from(e.to_string()))?
+}
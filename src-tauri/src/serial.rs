@@ -0,0 +1,210 @@
+// Serial console sessions (USB/RS-232 adapters to switches, routers, boards).
+//
+// A serial session lives in the same registry as SSH sessions, so terminal
+// input and output go through the usual commands and events.
+
+use crate::session_meta::{SessionLabel, SessionMeta};
+use crate::error::AppError;
+use crate::output::OutputPipeline;
+use crate::{
+    emit_session_opened, AppState, SessionClosedPayload, SessionSetup, SessionState,
+    SessionTarget, SessionTransport,
+};
+use serde::{Deserialize, Serialize};
+use serialport::{DataBits, FlowControl, Parity, SerialPortType, StopBits};
+use std::io::Read;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{State, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+// Short read timeout so the reader notices a closed session
+const IDLE_POLL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SerialPortEntry {
+    pub name: String,
+    pub port_type: String, // "usb", "pci", "bluetooth" or "unknown"
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerialOptions {
+    pub path: String,
+    pub baud_rate: u32,
+    pub data_bits: Option<u8>,        // 5-8, defaults to 8
+    pub parity: Option<String>,       // "none", "odd", "even"
+    pub stop_bits: Option<u8>,        // 1 or 2
+    pub flow_control: Option<String>, // "none", "software", "hardware"
+}
+
+#[tauri::command]
+pub fn list_serial_ports() -> Result<Vec<SerialPortEntry>, AppError> {
+    let ports = serialport::available_ports().map_err(|e| e.to_string())?;
+
+    Ok(ports
+        .into_iter()
+        .map(|port| match port.port_type {
+            SerialPortType::UsbPort(usb) => SerialPortEntry {
+                name: port.port_name,
+                port_type: "usb".to_string(),
+                vid: Some(usb.vid),
+                pid: Some(usb.pid),
+                manufacturer: usb.manufacturer,
+                product: usb.product,
+                serial_number: usb.serial_number,
+            },
+            other => SerialPortEntry {
+                name: port.port_name,
+                port_type: match other {
+                    SerialPortType::PciPort => "pci",
+                    SerialPortType::BluetoothPort => "bluetooth",
+                    _ => "unknown",
+                }
+                .to_string(),
+                vid: None,
+                pid: None,
+                manufacturer: None,
+                product: None,
+                serial_number: None,
+            },
+        })
+        .collect())
+}
+
+fn parse_options(
+    options: &SerialOptions,
+) -> Result<(DataBits, Parity, StopBits, FlowControl), String> {
+    let data_bits = match options.data_bits.unwrap_or(8) {
+        5 => DataBits::Five,
+        6 => DataBits::Six,
+        7 => DataBits::Seven,
+        8 => DataBits::Eight,
+        other => return Err(format!("Unsupported data bits: {}", other)),
+    };
+    let parity = match options.parity.as_deref().unwrap_or("none") {
+        "none" => Parity::None,
+        "odd" => Parity::Odd,
+        "even" => Parity::Even,
+        other => return Err(format!("Unsupported parity: {}", other)),
+    };
+    let stop_bits = match options.stop_bits.unwrap_or(1) {
+        1 => StopBits::One,
+        2 => StopBits::Two,
+        other => return Err(format!("Unsupported stop bits: {}", other)),
+    };
+    let flow_control = match options.flow_control.as_deref().unwrap_or("none") {
+        "none" => FlowControl::None,
+        "software" => FlowControl::Software,
+        "hardware" => FlowControl::Hardware,
+        other => return Err(format!("Unsupported flow control: {}", other)),
+    };
+    Ok((data_bits, parity, stop_bits, flow_control))
+}
+
+#[tauri::command]
+pub fn connect_serial(
+    options: SerialOptions,
+    // Title and color to show, see session_meta
+    label: Option<SessionLabel>,
+    state: State<'_, AppState>,
+    window: Window,
+) -> Result<String, AppError> {
+    let meta = Arc::new(SessionMeta::new(label)?);
+    let (data_bits, parity, stop_bits, flow_control) = parse_options(&options)?;
+
+    info!(target = "serial", path = %options.path, baud = options.baud_rate, "Opening serial port");
+    let port = serialport::new(&options.path, options.baud_rate)
+        .data_bits(data_bits)
+        .parity(parity)
+        .stop_bits(stop_bits)
+        .flow_control(flow_control)
+        .timeout(IDLE_POLL)
+        .open()
+        .map_err(|e| format!("Failed to open {}: {}", options.path, e))?;
+    let mut reader = port.try_clone().map_err(|e| e.to_string())?;
+
+    let session_id = Uuid::new_v4();
+    let session = SessionState::new(
+        session_id,
+        &window,
+        SessionSetup {
+            transport: SessionTransport::Serial {
+                port: Arc::new(Mutex::new(port)),
+            },
+            target: SessionTarget {
+                host: options.path.clone(),
+                ..SessionTarget::default()
+            },
+            meta,
+            encoding: encoding_rs::UTF_8,
+            scrollback_limit: state.scrollback_limit.load(Ordering::Relaxed),
+            idle_after_secs: None,
+            disconnect_after_secs: None,
+            read_only: false,
+            guard_exempt: false,
+            startup: None,
+        },
+    );
+    let reader_ctx = session.reader_context(session_id, &window);
+    let shutdown_arc = session.shutdown.clone();
+    state.sessions.insert(session_id, session);
+
+    let sessions = state.sessions.clone();
+    let batch_settings = state.output_batching.clone();
+    let reader_shutdown = shutdown_arc.clone();
+    let reader = thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        let mut pipeline = OutputPipeline::new(reader_ctx, batch_settings);
+        loop {
+            if reader_shutdown.is_requested() || !pipeline.wait_if_paused() {
+                break;
+            }
+            // Wake up in time to flush a pending batch
+            let timeout = pipeline
+                .idle_timeout(IDLE_POLL)
+                .max(Duration::from_millis(1));
+            let _ = reader.set_timeout(timeout);
+            match reader.read(&mut buffer) {
+                Ok(0) => continue,
+                Ok(bytes_read) => pipeline.push(&buffer[..bytes_read]),
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                    pipeline.poll();
+                    // close_session removed us, release the port
+                    if !sessions.contains_key(&session_id) {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    warn!(target = "serial", session = %pipeline.ctx.session_id, error = %e, "Serial device read failed");
+                    pipeline.flush();
+                    if sessions.remove(&session_id).is_some() {
+                        pipeline.ctx.emit(
+                            "session-closed",
+                            SessionClosedPayload {
+                                session_id: pipeline.ctx.session_id.clone(),
+                                reason: "device removed".to_string(),
+                            },
+                        );
+                    }
+                    break;
+                }
+            }
+        }
+        pipeline.flush();
+        info!(target = "serial", session = %pipeline.ctx.session_id, "Serial reader stopped");
+    });
+    shutdown_arc.set_reader(reader);
+
+    info!(target = "serial", session = %session_id, "Serial session established");
+    emit_session_opened(&window, &session_id.to_string());
+    Ok(session_id.to_string())
+}
@@ -0,0 +1,173 @@
+// Display names and colors of open sessions.
+//
+// The frontend's own map from session ids to tabs is lost on reload, so
+// each session carries an editable title and color,
+// given at connect time or later with set_session_meta, and list_sessions
+// returns everything a window needs to rebuild its tabs: where each session
+// is connected, since when, its label and whether it is still alive.
+// Changes go out as "session-meta-changed" and relabel the tray.
+//
+// Reconnecting is done by the frontend with a new session, so a session is
+// only ever connected or, for the moment between losing its connection and
+// being removed, dead.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{ownership, tray, AppState, SessionState};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{Manager, State, Window};
+use uuid::Uuid;
+
+const MAX_TITLE_CHARS: usize = 200;
+const MAX_COLOR_CHARS: usize = 32;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionLabel {
+    pub title: Option<String>,
+    // Any CSS color, e.g. "#e11d48"
+    pub color: Option<String>,
+}
+
+impl SessionLabel {
+    // Trims both, blank means unset
+    fn normalize(self) -> Result<Self, AppError> {
+        let clean = |value: Option<String>, max: usize, what: &str| match value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+        {
+            Some(v) if v.chars().count() > max => Err(AppError::new(
+                ErrorKind::InvalidInput,
+                format!("The session {} must be at most {} characters", what, max),
+            )),
+            v => Ok(v),
+        };
+        Ok(Self {
+            title: clean(self.title, MAX_TITLE_CHARS, "title")?,
+            color: clean(self.color, MAX_COLOR_CHARS, "color")?,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct SessionMeta {
+    label: Mutex<SessionLabel>,
+}
+
+impl SessionMeta {
+    /// Checks a label given at connect time.
+    pub fn new(label: Option<SessionLabel>) -> Result<Self, AppError> {
+        Ok(Self {
+            label: Mutex::new(label.unwrap_or_default().normalize()?),
+        })
+    }
+
+    pub fn label(&self) -> SessionLabel {
+        self.label.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn set(&self, label: SessionLabel) {
+        *self.label.lock().unwrap_or_else(|e| e.into_inner()) = label;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    Connected,
+    // The connection is gone and the session is being removed
+    Dead,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenSession {
+    pub session_id: String,
+    pub protocol: String,
+    pub host: String,
+    pub username: String,
+    pub host_id: Option<String>,
+    pub title: Option<String>,
+    pub color: Option<String>,
+    // Unix timestamp
+    pub connected_at: Option<u64>,
+    pub status: SessionStatus,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SessionMetaChangedPayload {
+    session_id: String,
+    title: Option<String>,
+    color: Option<String>,
+}
+
+pub fn describe(session_id: &Uuid, session: &SessionState) -> OpenSession {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let label = session.meta.label();
+    let status = if session.shutdown.is_requested() || session.shutdown.reader_exited() {
+        SessionStatus::Dead
+    } else {
+        SessionStatus::Connected
+    };
+    OpenSession {
+        session_id: session_id.to_string(),
+        protocol: session.transport.protocol().to_string(),
+        host: session.target.host.clone(),
+        username: session.target.username.clone(),
+        host_id: session.target.host_id.clone(),
+        title: label.title,
+        color: label.color,
+        connected_at: session
+            .target
+            .connected_at
+            .map(|at| now.saturating_sub(at.elapsed().as_secs())),
+        status,
+    }
+}
+
+/// The open sessions `window` may use, oldest first.
+#[tauri::command]
+pub fn list_sessions(window: Window, state: State<'_, AppState>) -> Vec<OpenSession> {
+    let mut sessions: Vec<OpenSession> = state
+        .sessions
+        .iter()
+        .filter(|entry| ownership::check(entry, &entry.key().to_string(), &window).is_ok())
+        .map(|entry| describe(entry.key(), &entry))
+        .collect();
+    sessions.sort_by_key(|s| s.connected_at);
+    sessions
+}
+
+/// Sets a session's title and color; a missing or blank one is cleared.
+#[tauri::command]
+pub fn set_session_meta(
+    session_id: String,
+    title: Option<String>,
+    color: Option<String>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let label = SessionLabel { title, color }.normalize()?;
+    let uuid = Uuid::parse_str(&session_id)?;
+    {
+        let session = state
+            .sessions
+            .get(&uuid)
+            .ok_or_else(|| AppError::session_not_found(&session_id))?;
+        ownership::check(&session, &session_id, &window)?;
+        session.meta.set(label.clone());
+        session.owners.emit(
+            &window,
+            "session-meta-changed",
+            SessionMetaChangedPayload {
+                session_id: session_id.clone(),
+                title: label.title,
+                color: label.color,
+            },
+        );
+    }
+    tray::refresh(window.app_handle());
+    Ok(())
+}
@@ -0,0 +1,256 @@
+// App preferences the backend acts on, persisted in settings.json.
+//
+// Every field has a default, so files written before a setting existed still
+// load. The frontend patches individual keys through update_settings; each
+// change is written, applied to live state and broadcast as
+// "settings-changed" for long-lived subsystems to pick up.
+
+use crate::agent::AgentBackend;
+use crate::command_guard::CommandGuardConfig;
+use crate::connect_limit::CooldownPolicy;
+use crate::error::AppError;
+use crate::local_shells::CustomShell;
+use crate::output::OutputBatchConfig;
+use crate::remote_modes::RemoteModes;
+use crate::retry::RetryPolicy;
+use crate::{config_file, get_config_dir, AppState};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Used when a connection doesn't name one
+    pub default_terminal_type: String,
+    // Seconds between SSH keepalives when a host doesn't set its own; 0 is off
+    pub default_keepalive_secs: u32,
+    pub transfer_concurrency: usize,
+    // Used by transfers that don't pass their own
+    pub transfer_retry: RetryPolicy,
+    // Limit for each SFTP metadata call (listing, stat, rename, ...)
+    pub sftp_timeout_secs: u64,
+    // When connect_ssh stops retrying a host that keeps failing
+    pub connect_cooldown: CooldownPolicy,
+    // Closing the main window hides it to the tray, sessions keep running
+    pub close_to_tray: bool,
+    // Session logs go to the config dir when unset
+    pub log_dir: Option<String>,
+    pub confirm_before_delete: bool,
+    pub output_batching: OutputBatchConfig,
+    // Oldest entries are dropped past this; None keeps everything
+    pub history_max_entries: Option<usize>,
+    // Entries older than this are pruned at startup; None keeps them
+    pub history_retention_days: Option<u32>,
+    // Searched for private keys besides ~/.ssh
+    pub key_directories: Vec<String>,
+    // known_hosts files, "~/" allowed; the first gets new keys. Empty means
+    // ~/.ssh/known_hosts
+    pub known_hosts_files: Vec<String>,
+    // Unchanged lines shown around each change by diff_files
+    pub diff_context_lines: usize,
+    // Which SSH agent to use; auto tries each in turn
+    pub agent_backend: AgentBackend,
+    // Listed by list_local_shells after the detected shells
+    pub custom_shells: Vec<CustomShell>,
+    // Terminal input still accepted by read-only sessions, whole chunks only
+    pub read_only_allowed_input: Vec<String>,
+    // Confirmation before submitting lines that match, off by default
+    pub command_guard: CommandGuardConfig,
+    // Finished and abandoned transfer jobs are dropped after this many days
+    pub transfer_retention_days: u32,
+    // Keyboard-interactive prompts answered with a TOTP code, case-insensitive
+    pub totp_prompt_patterns: Vec<String>,
+    // How long a directory listing is served from cache; 0 turns it off
+    pub listing_cache_ttl_secs: u64,
+    // Modes for uploads and new directories, hosts can override them
+    pub remote_modes: RemoteModes,
+    // Sessions a reloaded window hasn't reattached are closed after this;
+    // None keeps them
+    pub reattach_grace_secs: Option<u64>,
+    // Quiet time before a session is reported idle; 0 turns it off
+    pub idle_after_secs: u64,
+    // Idle sessions are disconnected after this; 0 keeps them
+    pub disconnect_after_secs: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            default_terminal_type: "xterm-256color".to_string(),
+            default_keepalive_secs: 0,
+            transfer_concurrency: 3,
+            transfer_retry: RetryPolicy::default(),
+            sftp_timeout_secs: 15,
+            connect_cooldown: CooldownPolicy::default(),
+            close_to_tray: false,
+            log_dir: None,
+            confirm_before_delete: true,
+            output_batching: OutputBatchConfig::default(),
+            history_max_entries: Some(100),
+            history_retention_days: None,
+            key_directories: Vec::new(),
+            known_hosts_files: Vec::new(),
+            diff_context_lines: 3,
+            agent_backend: AgentBackend::default(),
+            custom_shells: Vec::new(),
+            read_only_allowed_input: crate::read_only::default_allowed_input(),
+            command_guard: CommandGuardConfig::default(),
+            transfer_retention_days: 7,
+            totp_prompt_patterns: crate::totp::default_prompt_patterns(),
+            listing_cache_ttl_secs: 0,
+            remote_modes: RemoteModes::default(),
+            reattach_grace_secs: None,
+            idle_after_secs: crate::activity::DEFAULT_IDLE_AFTER_SECS,
+            disconnect_after_secs: 0,
+        }
+    }
+}
+
+impl Settings {
+    fn validate(&self) -> Result<(), String> {
+        if self.default_terminal_type.trim().is_empty() {
+            return Err("Terminal type cannot be empty".to_string());
+        }
+        if !(1..=16).contains(&self.transfer_concurrency) {
+            return Err("Transfer concurrency must be between 1 and 16".to_string());
+        }
+        self.transfer_retry.validate()?;
+        if !(1..=600).contains(&self.sftp_timeout_secs) {
+            return Err("SFTP timeout must be between 1 and 600 seconds".to_string());
+        }
+        self.connect_cooldown.validate()?;
+        if self.history_max_entries == Some(0) {
+            return Err("History size must be at least 1, or unlimited".to_string());
+        }
+        if self.history_retention_days == Some(0) {
+            return Err("History retention must be at least 1 day".to_string());
+        }
+        if self.diff_context_lines > 100 {
+            return Err("Diff context must be at most 100 lines".to_string());
+        }
+        crate::local_shells::validate_custom(&self.custom_shells)?;
+        if self.read_only_allowed_input.iter().any(String::is_empty) {
+            return Err("Read-only allowed input cannot contain empty entries".to_string());
+        }
+        self.command_guard.validate()?;
+        if self.transfer_retention_days == 0 {
+            return Err("Transfer retention must be at least 1 day".to_string());
+        }
+        crate::totp::validate_patterns(&self.totp_prompt_patterns)?;
+        if self.listing_cache_ttl_secs > 3600 {
+            return Err("Listing cache TTL must be at most 3600 seconds".to_string());
+        }
+        self.remote_modes.validate()?;
+        if self.reattach_grace_secs == Some(0) {
+            return Err("Reattach grace period must be at least 1 second".to_string());
+        }
+        Ok(())
+    }
+}
+
+// The settings in effect, read by commands as they need them
+#[derive(Default)]
+pub struct SettingsStore {
+    current: RwLock<Settings>,
+}
+
+impl SettingsStore {
+    pub fn get(&self) -> Settings {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn set(&self, settings: Settings) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = settings;
+    }
+}
+
+fn get_settings_path() -> Result<PathBuf, String> {
+    Ok(get_config_dir()?.join("settings.json"))
+}
+
+fn read(app_handle: &AppHandle) -> Result<Settings, String> {
+    Ok(config_file::load(app_handle, &get_settings_path()?)?.unwrap_or_default())
+}
+
+// Pushes settings that live elsewhere in AppState
+fn apply(state: &AppState, settings: Settings) {
+    state.output_batching.set(settings.output_batching);
+    state.settings.set(settings);
+}
+
+/// Loads settings.json into AppState at startup. A broken file is logged and
+/// the defaults used, the app still starts.
+pub fn init(app_handle: &AppHandle) {
+    let settings = read(app_handle).unwrap_or_else(|e| {
+        warn!(target = "settings", error = %e, "Failed to load settings, using defaults");
+        Settings::default()
+    });
+    apply(&app_handle.state::<AppState>(), settings);
+}
+
+/// Merges `patch` into the stored settings. Unknown keys are rejected so a
+/// typo doesn't silently do nothing.
+pub fn update(
+    app_handle: &AppHandle,
+    state: &AppState,
+    patch: Map<String, Value>,
+) -> Result<Settings, String> {
+    let path = get_settings_path()?;
+    let _lock = config_file::lock(&path)?;
+    let Value::Object(mut merged) =
+        serde_json::to_value(read(app_handle)?).map_err(|e| e.to_string())?
+    else {
+        return Err("Settings are not an object".to_string());
+    };
+    for (key, value) in patch {
+        if !merged.contains_key(&key) {
+            return Err(format!("Unknown setting: {}", key));
+        }
+        merged.insert(key, value);
+    }
+    let settings: Settings =
+        serde_json::from_value(Value::Object(merged)).map_err(|e| e.to_string())?;
+    settings.validate()?;
+
+    config_file::write(&path, &settings)?;
+    apply(state, settings.clone());
+    let _ = app_handle.emit("settings-changed", &settings);
+    info!(target = "settings", "Updated settings");
+    Ok(settings)
+}
+
+/// Updates a single setting.
+pub fn update_one<T: Serialize>(
+    app_handle: &AppHandle,
+    state: &AppState,
+    key: &str,
+    value: T,
+) -> Result<Settings, String> {
+    let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+    update(
+        app_handle,
+        state,
+        Map::from_iter([(key.to_string(), value)]),
+    )
+}
+
+#[tauri::command]
+pub fn load_settings(state: State<'_, AppState>) -> Settings {
+    state.settings.get()
+}
+
+#[tauri::command]
+pub fn update_settings(
+    patch: Map<String, Value>,
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<Settings, AppError> {
+    Ok(update(&app_handle, &state, patch)?)
+}
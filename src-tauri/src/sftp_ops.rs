@@ -0,0 +1,318 @@
+// Timeouts and cancellation for SFTP metadata operations.
+//
+// The SSH session runs in non-blocking mode, so against a hung server an
+// SFTP call returns EAGAIN rather than blocking; run() retries it until the
+// operation's deadline. Waiting for the SFTP lock counts against the same
+// deadline, so one stuck call can't freeze the file panel behind it. After a
+// timeout libssh2's SFTP state is undefined: the handle is dropped and the
+// next call opens a fresh one. Listings take an operation id that
+// cancel_sftp_operation stops, for navigating away from a huge directory.
+
+use crate::error::{AppError, ErrorKind};
+use crate::ownership;
+use crate::{AppState, SessionState};
+use dashmap::DashMap;
+use ssh2::{ErrorCode, FileStat, Sftp};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{async_runtime, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const LIBSSH2_ERROR_EAGAIN: i32 = -37;
+// readdir's end of directory
+const LIBSSH2_ERROR_FILE: i32 = -16;
+const RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+// Cancel flags of running operations that were given an id
+static OPERATIONS: LazyLock<DashMap<String, Arc<AtomicBool>>> = LazyLock::new(DashMap::new);
+
+pub struct Operation {
+    what: &'static str,
+    timeout: Duration,
+    id: Option<String>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Operation {
+    /// `what` names the operation in errors; with an `id` it can be
+    /// cancelled until dropped.
+    pub fn new(what: &'static str, timeout: Duration, id: Option<String>) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Some(id) = &id {
+            OPERATIONS.insert(id.clone(), cancelled.clone());
+        }
+        Self {
+            what,
+            timeout,
+            id,
+            cancelled,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn deadline(&self) -> Instant {
+        Instant::now() + self.timeout
+    }
+
+    // Called before waiting again
+    fn check(&self, deadline: Instant) -> Result<(), AppError> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(AppError::new(
+                ErrorKind::Cancelled,
+                format!("SFTP {} was cancelled", self.what),
+            ));
+        }
+        if Instant::now() >= deadline {
+            return Err(AppError::new(
+                ErrorKind::Timeout,
+                format!(
+                    "SFTP {} timed out after {}s",
+                    self.what,
+                    self.timeout.as_secs()
+                ),
+            ));
+        }
+        thread::sleep(RETRY_INTERVAL);
+        Ok(())
+    }
+
+    // Retries `call` on EAGAIN until the deadline
+    fn retry<T>(
+        &self,
+        deadline: Instant,
+        mut call: impl FnMut() -> Result<T, ssh2::Error>,
+    ) -> Result<T, AppError> {
+        loop {
+            match call() {
+                Ok(value) => return Ok(value),
+                Err(e) if e.code() == ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) => {
+                    self.check(deadline)?
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl Drop for Operation {
+    fn drop(&mut self) {
+        if let Some(id) = &self.id {
+            OPERATIONS.remove(id);
+        }
+    }
+}
+
+// Locks the SFTP handle, opening it if needed, within the deadline
+fn lock_sftp<'a>(
+    session: &'a SessionState,
+    op: &Operation,
+    deadline: Instant,
+) -> Result<MutexGuard<'a, Option<Sftp>>, AppError> {
+    let mut sftp_lock = loop {
+        match session.sftp.try_lock() {
+            Ok(guard) => break guard,
+            Err(TryLockError::Poisoned(_)) => break session.lock(&session.sftp, "sftp"),
+            Err(TryLockError::WouldBlock) => op.check(deadline)?,
+        }
+    };
+    if sftp_lock.is_none() {
+        let ssh = session.ssh_session().ok_or_else(|| {
+            AppError::new(
+                ErrorKind::SftpNotInitialized,
+                "SFTP is not available for this session",
+            )
+        })?;
+        let ssh = session.lock(ssh, "ssh session");
+        let sftp = op
+            .retry(deadline, || ssh.sftp())
+            .map_err(|e| e.context("Failed to initialize SFTP"))?;
+        info!(target = "sftp", "Initialized SFTP session");
+        *sftp_lock = Some(sftp);
+    }
+    Ok(sftp_lock)
+}
+
+// Drops a handle left in an unknown state by a timed out or cancelled call
+fn discard_on_abort<T>(
+    sftp_lock: &mut Option<Sftp>,
+    op: &Operation,
+    result: Result<T, AppError>,
+) -> Result<T, AppError> {
+    if let Err(e) = &result {
+        if matches!(e.kind, ErrorKind::Timeout | ErrorKind::Cancelled) {
+            warn!(target = "sftp", operation = op.what, error = %e, "Resetting SFTP session");
+            *sftp_lock = None;
+        }
+    }
+    result
+}
+
+/// Runs one SFTP call with the operation's timeout.
+pub fn run<T>(
+    session: &SessionState,
+    op: &Operation,
+    mut call: impl FnMut(&Sftp) -> Result<T, ssh2::Error>,
+) -> Result<T, AppError> {
+    let deadline = op.deadline();
+    let mut sftp_lock = lock_sftp(session, op, deadline)?;
+    let result = match sftp_lock.as_ref() {
+        Some(sftp) => op.retry(deadline, || call(sftp)),
+        None => Err(AppError::new(
+            ErrorKind::SftpNotInitialized,
+            "SFTP session not available",
+        )),
+    };
+    discard_on_abort(&mut sftp_lock, op, result)
+}
+
+/// Lists a directory. The timeout applies between entries rather than to
+/// the whole listing, so a large directory that keeps arriving isn't cut off.
+pub fn read_dir(
+    session: &SessionState,
+    op: &Operation,
+    path: &Path,
+) -> Result<Vec<(PathBuf, FileStat)>, AppError> {
+    let mut deadline = op.deadline();
+    let mut sftp_lock = lock_sftp(session, op, deadline)?;
+    let result = (|| {
+        let sftp = sftp_lock.as_ref().ok_or_else(|| {
+            AppError::new(ErrorKind::SftpNotInitialized, "SFTP session not available")
+        })?;
+        let mut dir = op.retry(deadline, || sftp.opendir(path))?;
+        let mut entries = Vec::new();
+        loop {
+            match dir.readdir() {
+                Ok((name, stat)) => {
+                    if name.as_os_str() != "." && name.as_os_str() != ".." {
+                        entries.push((path.join(name), stat));
+                    }
+                    deadline = op.deadline();
+                }
+                Err(e) if e.code() == ErrorCode::Session(LIBSSH2_ERROR_FILE) => return Ok(entries),
+                Err(e) if e.code() == ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) => {
+                    op.check(deadline)?
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    })();
+    discard_on_abort(&mut sftp_lock, op, result)
+}
+
+/// Reads a whole file, failing if it is larger than `limit` bytes.
+pub fn read_file(
+    session: &SessionState,
+    op: &Operation,
+    path: &Path,
+    limit: u64,
+) -> Result<Vec<u8>, AppError> {
+    let data = read_head(session, op, path, limit + 1)?;
+    if data.len() as u64 > limit {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            format!("{} is larger than {} bytes", path.display(), limit),
+        ));
+    }
+    Ok(data)
+}
+
+/// Reads the first `max` bytes of a file, never asking the server for more.
+/// Like read_dir, the timeout applies between chunks.
+pub fn read_head(
+    session: &SessionState,
+    op: &Operation,
+    path: &Path,
+    max: u64,
+) -> Result<Vec<u8>, AppError> {
+    read_part(session, op, path, max, false)
+}
+
+/// Reads the last `max` bytes of a file, e.g. of a history file.
+pub fn read_tail(
+    session: &SessionState,
+    op: &Operation,
+    path: &Path,
+    max: u64,
+) -> Result<Vec<u8>, AppError> {
+    read_part(session, op, path, max, true)
+}
+
+fn read_part(
+    session: &SessionState,
+    op: &Operation,
+    path: &Path,
+    max: u64,
+    from_end: bool,
+) -> Result<Vec<u8>, AppError> {
+    let mut deadline = op.deadline();
+    let mut sftp_lock = lock_sftp(session, op, deadline)?;
+    let result = (|| {
+        let sftp = sftp_lock.as_ref().ok_or_else(|| {
+            AppError::new(ErrorKind::SftpNotInitialized, "SFTP session not available")
+        })?;
+        let mut file = op.retry(deadline, || sftp.open(path))?;
+        if from_end {
+            let size = op.retry(deadline, || file.stat())?.size.unwrap_or(0);
+            file.seek(SeekFrom::Start(size.saturating_sub(max)))?;
+        }
+        let mut data = Vec::new();
+        let mut buffer = [0u8; 32 * 1024];
+        while (data.len() as u64) < max {
+            let want = buffer.len().min((max - data.len() as u64) as usize);
+            match file.read(&mut buffer[..want]) {
+                Ok(0) => break,
+                Ok(n) => {
+                    data.extend_from_slice(&buffer[..n]);
+                    deadline = op.deadline();
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => op.check(deadline)?,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(data)
+    })();
+    discard_on_abort(&mut sftp_lock, op, result)
+}
+
+/// Runs `f` against a session on a blocking thread, so retries don't hold
+/// up the async runtime.
+pub async fn with_session<T: Send + 'static>(
+    state: &AppState,
+    session_id: String,
+    window: &Window,
+    f: impl FnOnce(&SessionState) -> Result<T, AppError> + Send + 'static,
+) -> Result<T, AppError> {
+    let sessions = state.sessions.clone();
+    let window = window.clone();
+    async_runtime::spawn_blocking(move || {
+        let uuid = Uuid::parse_str(&session_id)?;
+        let session = sessions
+            .get(&uuid)
+            .ok_or_else(|| AppError::session_not_found(&session_id))?;
+        ownership::check(&session, &session_id, &window)?;
+        f(&session).map_err(|e| e.with_session(&session_id))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Stops a running operation started with `operation_id`. Returns false if
+/// it already finished.
+#[tauri::command]
+pub fn cancel_sftp_operation(operation_id: String) -> bool {
+    match OPERATIONS.get(&operation_id) {
+        Some(cancelled) => {
+            cancelled.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,134 @@
+// Shell integration: correlates OSC 133 prompt markers into command records.
+//
+//   133;A        prompt start
+//   133;B        prompt end (user is typing)
+//   133;E;<cmd>  command line about to run (also accepted as 633;E)
+//   133;C        command output starts
+//   133;D[;ec]   command finished with optional exit code
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Keep the per-session timeline bounded
+const MAX_COMMAND_RECORDS: usize = 500;
+
+pub const BASH_INTEGRATION: &str = r#"# Terminoda shell integration (bash)
+if [ -z "$__terminoda_integration" ]; then
+  __terminoda_integration=1
+  __terminoda_running=0
+  __terminoda_at_prompt=0
+  # DEBUG fires for every simple command, PROMPT_COMMAND's included, so
+  # only the first one after the prompt is taken as the command line
+  __terminoda_preexec() {
+    [ "$__terminoda_at_prompt" = 1 ] || return
+    __terminoda_at_prompt=0
+    # An empty line goes straight to the prompt hook
+    [ "$BASH_COMMAND" = "__terminoda_precmd" ] && return
+    __terminoda_running=1
+    printf '\033]133;E;%s\007\033]133;C\007' "$BASH_COMMAND"
+  }
+  __terminoda_precmd() {
+    local ec=$?
+    if [ "$__terminoda_running" = 1 ]; then
+      printf '\033]133;D;%s\007' "$ec"
+    fi
+    __terminoda_running=0
+    printf '\033]7;file://%s%s\007\033]133;A\007' "$HOSTNAME" "$PWD"
+  }
+  trap '__terminoda_preexec' DEBUG
+  PROMPT_COMMAND="__terminoda_precmd${PROMPT_COMMAND:+;$PROMPT_COMMAND};__terminoda_at_prompt=1"
+fi
+"#;
+
+pub const ZSH_INTEGRATION: &str = r#"# Terminoda shell integration (zsh)
+if [ -z "$__terminoda_integration" ]; then
+  __terminoda_integration=1
+  __terminoda_precmd() {
+    local ec=$?
+    if [ -n "$__terminoda_running" ]; then
+      printf '\033]133;D;%s\007' "$ec"
+    fi
+    unset __terminoda_running
+    printf '\033]7;file://%s%s\007\033]133;A\007' "$HOST" "$PWD"
+  }
+  __terminoda_preexec() {
+    __terminoda_running=1
+    printf '\033]133;E;%s\007\033]133;C\007' "$1"
+  }
+  autoload -Uz add-zsh-hook
+  add-zsh-hook precmd __terminoda_precmd
+  add-zsh-hook preexec __terminoda_preexec
+fi
+"#;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandRecord {
+    pub command: Option<String>,
+    pub started_at: u64, // Unix timestamp in milliseconds
+    pub finished_at: u64,
+    pub duration_ms: u64,
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Default)]
+pub struct CommandTracker {
+    pending_command: Option<String>,
+    started_at: Option<u64>,
+    records: VecDeque<CommandRecord>,
+}
+
+impl CommandTracker {
+    /// Feeds an OSC payload, returning a record when a command finished.
+    pub fn handle_osc(&mut self, payload: &str) -> Option<CommandRecord> {
+        let rest = payload
+            .strip_prefix("133;")
+            .or_else(|| payload.strip_prefix("633;"))?;
+        let (kind, args) = match rest.split_once(';') {
+            Some((kind, args)) => (kind, Some(args)),
+            None => (rest, None),
+        };
+
+        match kind {
+            "A" | "B" => None,
+            "E" => {
+                self.pending_command = args.map(|a| a.trim().to_string()).filter(|a| !a.is_empty());
+                None
+            }
+            "C" => {
+                self.started_at = Some(now_millis());
+                None
+            }
+            "D" => {
+                let started_at = self.started_at.take()?;
+                let finished_at = now_millis();
+                let exit_code = args.and_then(|a| a.split(';').next()?.trim().parse().ok());
+                let record = CommandRecord {
+                    command: self.pending_command.take(),
+                    started_at,
+                    finished_at,
+                    duration_ms: finished_at.saturating_sub(started_at),
+                    exit_code,
+                };
+
+                if self.records.len() >= MAX_COMMAND_RECORDS {
+                    self.records.pop_front();
+                }
+                self.records.push_back(record.clone());
+                Some(record)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn records(&self) -> Vec<CommandRecord> {
+        self.records.iter().cloned().collect()
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
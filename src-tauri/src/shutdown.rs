@@ -0,0 +1,67 @@
+// Stopping a session's reader thread when the session is closed.
+//
+// Readers check the flag once per loop iteration. Closing sets it, wakes the
+// reader and joins it for a bounded time: a reader stuck in a blocking call
+// is left to notice the flag on its own rather than holding up the close.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// How long closing waits for the reader to exit
+pub const JOIN_TIMEOUT: Duration = Duration::from_secs(2);
+// How long a whole close may take before the connection is cut
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(4);
+const JOIN_POLL: Duration = Duration::from_millis(10);
+
+#[derive(Default)]
+pub struct ReaderShutdown {
+    requested: AtomicBool,
+    reader: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl ReaderShutdown {
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::Acquire)
+    }
+
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::Release);
+    }
+
+    /// Whether the reader thread has ended, by itself or on close.
+    pub fn reader_exited(&self) -> bool {
+        self.reader
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .is_some_and(|handle| handle.is_finished())
+    }
+
+    /// Hands over the reader thread to join on close.
+    pub fn set_reader(&self, handle: JoinHandle<()>) {
+        *self.reader.lock().unwrap_or_else(|e| e.into_inner()) = Some(handle);
+    }
+
+    /// Waits up to `timeout` for the reader to exit. Returns false if it is
+    /// still running; it is then detached.
+    pub fn join(&self, timeout: Duration) -> bool {
+        let Some(handle) = self.reader.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+            return true;
+        };
+        // A reader closing its own session can't wait for itself
+        if handle.thread().id() == thread::current().id() {
+            return true;
+        }
+        let deadline = Instant::now() + timeout;
+        while !handle.is_finished() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(JOIN_POLL);
+        }
+        let _ = handle.join();
+        true
+    }
+}
@@ -0,0 +1,287 @@
+// Exec channels opened next to the interactive shell for background work
+// (OS detection, du, checksums) while the user keeps typing.
+//
+// Commands are serialized per session: libssh2 only makes progress on one
+// channel operation at a time under a Session, and queueing here keeps a slow
+// command from interleaving with others. The SSH session runs in non-blocking
+// mode for the reader thread, so every libssh2 call is retried on EAGAIN.
+
+use crate::error::{AppError, ErrorKind as AppErrorKind};
+use crate::SessionState;
+use dashmap::DashMap;
+use serde::Serialize;
+use ssh2::{Channel, ErrorCode};
+use std::io::{ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const LIBSSH2_ERROR_EAGAIN: i32 = -37;
+const RETRY_INTERVAL: Duration = Duration::from_millis(5);
+// Side-channel commands are for metadata, not bulk data
+const MAX_OUTPUT_BYTES: usize = 8 * 1024 * 1024;
+const STREAM_OPEN_TIMEOUT: Duration = Duration::from_secs(15);
+// Streams wait for output this long between polls
+const STREAM_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_status: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SideChannelMetrics {
+    pub queue_depth: usize,
+    pub in_flight: Option<String>,
+    pub in_flight_ms: Option<u64>,
+    pub completed: u64,
+    pub failed: u64,
+}
+
+#[derive(Default)]
+pub struct ExecPool {
+    // Held for the whole lifetime of a command
+    slot: Mutex<()>,
+    waiting: AtomicUsize,
+    in_flight: Mutex<Option<(String, Instant)>>,
+    completed: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl ExecPool {
+    pub fn metrics(&self) -> SideChannelMetrics {
+        let in_flight = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        SideChannelMetrics {
+            queue_depth: self.waiting.load(Ordering::Relaxed),
+            in_flight_ms: in_flight
+                .as_ref()
+                .map(|(_, started)| started.elapsed().as_millis() as u64),
+            in_flight: in_flight.map(|(command, _)| command),
+            completed: self.completed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Quotes a value as one shell word.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+pub(crate) fn retry<T>(
+    deadline: Instant,
+    mut op: impl FnMut() -> Result<T, ssh2::Error>,
+) -> Result<T, String> {
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(e) if e.code() == ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) => {
+                if Instant::now() >= deadline {
+                    return Err("Timed out waiting for the SSH session".to_string());
+                }
+                thread::sleep(RETRY_INTERVAL);
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+/// Runs a command on a fresh exec channel of an SSH session and collects its
+/// output. Blocks, so call it from a blocking task.
+pub fn run_on_side_channel(
+    sessions: &DashMap<Uuid, SessionState>,
+    session_id: &str,
+    command: &str,
+    timeout: Duration,
+) -> Result<ExecOutput, AppError> {
+    run_with_input(sessions, session_id, command, &[], timeout)
+}
+
+/// Like run_on_side_channel, writing `input` to the command's stdin first.
+pub fn run_with_input(
+    sessions: &DashMap<Uuid, SessionState>,
+    session_id: &str,
+    command: &str,
+    input: &[u8],
+    timeout: Duration,
+) -> Result<ExecOutput, AppError> {
+    let uuid = Uuid::parse_str(session_id)?;
+    let (session, pool) = {
+        let state = sessions
+            .get(&uuid)
+            .ok_or_else(|| AppError::session_not_found(session_id))?;
+        let session = state.ssh_session().ok_or_else(not_ssh)?.clone();
+        (session, state.exec_pool.clone())
+    };
+
+    let deadline = Instant::now() + timeout;
+    pool.waiting.fetch_add(1, Ordering::Relaxed);
+    let _slot = pool.slot.lock().unwrap_or_else(|e| e.into_inner());
+    pool.waiting.fetch_sub(1, Ordering::Relaxed);
+    *pool.in_flight.lock().unwrap_or_else(|e| e.into_inner()) =
+        Some((command.to_string(), Instant::now()));
+
+    let result = (|| -> Result<ExecOutput, String> {
+        let mut channel = {
+            let session = session.lock().map_err(|e| e.to_string())?;
+            retry(deadline, || session.channel_session())?
+        };
+        retry(deadline, || channel.exec(command))?;
+        if !input.is_empty() {
+            write_input(&mut channel, input, deadline)?;
+            retry(deadline, || channel.send_eof())?;
+        }
+        let output = collect_output(&mut channel, deadline);
+        let _ = retry(deadline, || channel.close());
+        let output = output?;
+        let exit_status = channel.exit_status().unwrap_or(-1);
+        Ok(ExecOutput {
+            stdout: String::from_utf8_lossy(&output.0).into_owned(),
+            stderr: String::from_utf8_lossy(&output.1).into_owned(),
+            exit_status,
+        })
+    })();
+
+    *pool.in_flight.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    match &result {
+        Ok(output) => {
+            pool.completed.fetch_add(1, Ordering::Relaxed);
+            info!(target = "side_channel", session = %session_id, exit = output.exit_status, "Background command finished");
+        }
+        Err(e) => {
+            pool.failed.fetch_add(1, Ordering::Relaxed);
+            warn!(target = "side_channel", session = %session_id, error = %e, "Background command failed");
+        }
+    }
+    Ok(result?)
+}
+
+fn not_ssh() -> AppError {
+    AppError::new(
+        AppErrorKind::InvalidInput,
+        "Background commands need an SSH session",
+    )
+}
+
+/// Runs a command that keeps producing output (a log follow) and hands each
+/// chunk of stdout and stderr to `on_output` until it exits, `stop` is set or
+/// the session closes. Returns the exit status, None when stopped. Streams
+/// don't take the queue slot, or one would hold up every other command.
+pub fn stream_on_side_channel(
+    sessions: &DashMap<Uuid, SessionState>,
+    session_id: &str,
+    command: &str,
+    stop: &AtomicBool,
+    mut on_output: impl FnMut(&[u8]),
+) -> Result<Option<i32>, AppError> {
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?
+        .ssh_session()
+        .ok_or_else(not_ssh)?
+        .clone();
+
+    let deadline = Instant::now() + STREAM_OPEN_TIMEOUT;
+    let mut channel = {
+        let session = session.lock().map_err(|e| e.to_string())?;
+        retry(deadline, || session.channel_session())?
+    };
+    retry(deadline, || channel.exec(command))?;
+    info!(target = "side_channel", session = %session_id, "Streaming command started");
+
+    let mut buffer = [0u8; 8192];
+    let result = loop {
+        if stop.load(Ordering::Relaxed) || !sessions.contains_key(&uuid) {
+            break Ok(None);
+        }
+        let mut progressed = false;
+        for stream_id in [0, 1] {
+            match channel.stream(stream_id).read(&mut buffer) {
+                Ok(0) => {}
+                Ok(n) => {
+                    on_output(&buffer[..n]);
+                    progressed = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if progressed {
+            continue;
+        }
+        if channel.eof() {
+            break Ok(Some(channel.exit_status().unwrap_or(-1)));
+        }
+        thread::sleep(STREAM_POLL_INTERVAL);
+    };
+    let close_deadline = Instant::now() + STREAM_OPEN_TIMEOUT;
+    let _ = retry(close_deadline, || channel.close());
+    info!(target = "side_channel", session = %session_id, "Streaming command ended");
+    result
+}
+
+fn write_input(channel: &mut Channel, mut input: &[u8], deadline: Instant) -> Result<(), String> {
+    while !input.is_empty() {
+        match channel.write(input) {
+            Ok(n) => input = &input[n..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return Err("Timed out writing command input".to_string());
+                }
+                thread::sleep(RETRY_INTERVAL);
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(())
+}
+
+fn collect_output(channel: &mut Channel, deadline: Instant) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let mut buffer = [0u8; 8192];
+
+    loop {
+        let mut progressed = false;
+        for stream_id in [0, 1] {
+            let target = if stream_id == 0 {
+                &mut stdout
+            } else {
+                &mut stderr
+            };
+            match channel.stream(stream_id).read(&mut buffer) {
+                Ok(0) => {}
+                Ok(n) => {
+                    if target.len() + n > MAX_OUTPUT_BYTES {
+                        return Err("Command output too large".to_string());
+                    }
+                    target.extend_from_slice(&buffer[..n]);
+                    progressed = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+
+        if progressed {
+            continue;
+        }
+        if channel.eof() {
+            return Ok((stdout, stderr));
+        }
+        if Instant::now() >= deadline {
+            return Err("Command timed out".to_string());
+        }
+        thread::sleep(RETRY_INTERVAL);
+    }
+}
@@ -0,0 +1,264 @@
+// Snippet folders and manual ordering, persisted in snippet_folders.json.
+//
+// Snippet.folder holds a folder id; snippets without one sit at the root.
+// load_snippets returns root snippets first, then each folder's in folder
+// order, so the frontend can render the list as is.
+
+use crate::config_file::{self, ConfigLock};
+use crate::error::{AppError, ErrorKind};
+use crate::{get_config_dir, get_snippets_path, load_snippets, Snippet};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::AppHandle;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnippetFolder {
+    pub id: String,
+    pub name: String,
+    pub sort_order: u32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeleteFolderPolicy {
+    MoveToRoot,
+    DeleteSnippets,
+}
+
+fn get_folders_path() -> Result<PathBuf, String> {
+    Ok(get_config_dir()?.join("snippet_folders.json"))
+}
+
+pub fn lock_folders() -> Result<ConfigLock, String> {
+    config_file::lock(&get_folders_path()?)
+}
+
+pub fn load(app_handle: &AppHandle) -> Result<Vec<SnippetFolder>, String> {
+    let mut folders: Vec<SnippetFolder> =
+        config_file::load(app_handle, &get_folders_path()?)?.unwrap_or_default();
+    folders.sort_by_key(|f| f.sort_order);
+    Ok(folders)
+}
+
+pub fn write_folders(folders: &[SnippetFolder]) -> Result<(), String> {
+    config_file::write(&get_folders_path()?, folders)
+}
+
+// Callers hold the snippets.json lock, as save_snippet does
+fn write_snippets(app_handle: &AppHandle, snippets: &[Snippet]) -> Result<(), String> {
+    config_file::write(&get_snippets_path(app_handle)?, snippets)
+}
+
+/// Root snippets first, then by folder order; within a folder by sort_order
+/// and name. Snippets in folders that no longer exist count as root.
+pub fn sort_snippets(snippets: &mut [Snippet], folders: &[SnippetFolder]) {
+    let position = |s: &Snippet| {
+        s.folder
+            .as_ref()
+            .and_then(|id| folders.iter().position(|f| &f.id == id))
+            .map_or(0, |p| p + 1)
+    };
+    snippets.sort_by(|a, b| {
+        position(a)
+            .cmp(&position(b))
+            .then(a.sort_order.cmp(&b.sort_order))
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+}
+
+/// Sort position for a snippet appended to `folder`.
+pub fn next_sort_order(snippets: &[Snippet], folder: Option<&str>) -> u32 {
+    snippets
+        .iter()
+        .filter(|s| s.folder.as_deref() == folder)
+        .map(|s| s.sort_order + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+pub fn check_folder(app_handle: &AppHandle, folder: Option<&str>) -> Result<(), AppError> {
+    match folder {
+        Some(id) if !load(app_handle)?.iter().any(|f| f.id == id) => Err(AppError::new(
+            ErrorKind::NotFound,
+            "Snippet folder not found",
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[tauri::command]
+pub fn load_snippet_folders(app_handle: AppHandle) -> Result<Vec<SnippetFolder>, AppError> {
+    Ok(load(&app_handle)?)
+}
+
+#[tauri::command]
+pub fn create_snippet_folder(
+    name: String,
+    app_handle: AppHandle,
+) -> Result<SnippetFolder, AppError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Folder name cannot be empty",
+        ));
+    }
+    let _lock = lock_folders()?;
+    let mut folders = load(&app_handle)?;
+    let folder = SnippetFolder {
+        id: Uuid::new_v4().to_string(),
+        name,
+        sort_order: folders.iter().map(|f| f.sort_order + 1).max().unwrap_or(0),
+    };
+    folders.push(folder.clone());
+    write_folders(&folders)?;
+    Ok(folder)
+}
+
+#[tauri::command]
+pub fn rename_snippet_folder(
+    folder_id: String,
+    name: String,
+    app_handle: AppHandle,
+) -> Result<SnippetFolder, AppError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Folder name cannot be empty",
+        ));
+    }
+    let _lock = lock_folders()?;
+    let mut folders = load(&app_handle)?;
+    let folder = folders
+        .iter_mut()
+        .find(|f| f.id == folder_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Snippet folder not found"))?;
+    folder.name = name;
+    let renamed = folder.clone();
+    write_folders(&folders)?;
+    Ok(renamed)
+}
+
+#[tauri::command]
+pub fn delete_snippet_folder(
+    folder_id: String,
+    policy: DeleteFolderPolicy,
+    app_handle: AppHandle,
+) -> Result<(), AppError> {
+    let _lock = lock_folders()?;
+    let mut folders = load(&app_handle)?;
+    let pos = folders
+        .iter()
+        .position(|f| f.id == folder_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Snippet folder not found"))?;
+    folders.remove(pos);
+
+    // Snippets first: a failure here leaves the folder in place
+    let _snippets_lock = config_file::lock(&get_snippets_path(&app_handle)?)?;
+    let mut snippets = load_snippets(app_handle.clone())?;
+    let before = snippets.len();
+    match policy {
+        DeleteFolderPolicy::DeleteSnippets => {
+            snippets.retain(|s| s.folder.as_deref() != Some(folder_id.as_str()))
+        }
+        DeleteFolderPolicy::MoveToRoot => {
+            let mut next = next_sort_order(&snippets, None);
+            for snippet in snippets.iter_mut() {
+                if snippet.folder.as_deref() == Some(folder_id.as_str()) {
+                    snippet.folder = None;
+                    snippet.sort_order = next;
+                    next += 1;
+                }
+            }
+        }
+    }
+    write_snippets(&app_handle, &snippets)?;
+    write_folders(&folders)?;
+    info!(
+        target = "snippets",
+        folder = %folder_id,
+        deleted = before - snippets.len(),
+        "Deleted snippet folder"
+    );
+    Ok(())
+}
+
+/// Moves a snippet to the end of another folder, or to the root with None.
+#[tauri::command]
+pub fn move_snippet_to_folder(
+    snippet_id: String,
+    folder_id: Option<String>,
+    app_handle: AppHandle,
+) -> Result<Snippet, AppError> {
+    check_folder(&app_handle, folder_id.as_deref())?;
+    let _lock = config_file::lock(&get_snippets_path(&app_handle)?)?;
+    let mut snippets = load_snippets(app_handle.clone())?;
+    let sort_order = next_sort_order(&snippets, folder_id.as_deref());
+    let snippet = snippets
+        .iter_mut()
+        .find(|s| s.id == snippet_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Snippet not found"))?;
+    snippet.folder = folder_id;
+    snippet.sort_order = sort_order;
+    let moved = snippet.clone();
+    write_snippets(&app_handle, &snippets)?;
+    Ok(moved)
+}
+
+/// Orders the snippets of one folder (None for the root) by their position
+/// in `snippet_ids`. Snippets not listed keep their relative order after the
+/// listed ones; ids from other folders are ignored.
+#[tauri::command]
+pub fn reorder_snippets(
+    folder_id: Option<String>,
+    snippet_ids: Vec<String>,
+    app_handle: AppHandle,
+) -> Result<Vec<Snippet>, AppError> {
+    let _lock = config_file::lock(&get_snippets_path(&app_handle)?)?;
+    let mut snippets = load_snippets(app_handle.clone())?;
+    let mut in_folder: Vec<&mut Snippet> = snippets
+        .iter_mut()
+        .filter(|s| s.folder == folder_id)
+        .collect();
+    in_folder.sort_by_key(|s| {
+        (
+            snippet_ids
+                .iter()
+                .position(|id| *id == s.id)
+                .unwrap_or(usize::MAX),
+            s.sort_order,
+        )
+    });
+    for (i, snippet) in in_folder.into_iter().enumerate() {
+        snippet.sort_order = i as u32;
+    }
+    write_snippets(&app_handle, &snippets)?;
+    sort_snippets(&mut snippets, &load(&app_handle)?);
+    Ok(snippets)
+}
+
+#[tauri::command]
+pub fn reorder_snippet_folders(
+    folder_ids: Vec<String>,
+    app_handle: AppHandle,
+) -> Result<Vec<SnippetFolder>, AppError> {
+    let _lock = lock_folders()?;
+    let mut folders = load(&app_handle)?;
+    folders.sort_by_key(|f| {
+        (
+            folder_ids
+                .iter()
+                .position(|id| *id == f.id)
+                .unwrap_or(usize::MAX),
+            f.sort_order,
+        )
+    });
+    for (i, folder) in folders.iter_mut().enumerate() {
+        folder.sort_order = i as u32;
+    }
+    write_folders(&folders)?;
+    Ok(folders)
+}
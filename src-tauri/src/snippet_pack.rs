@@ -0,0 +1,200 @@
+// Sharing snippets as a standalone file.
+//
+// A snippet pack is versioned JSON holding the snippets and the folders they
+// sit in. Imports merge by id using the same conflict policies as
+// configuration bundles. Plain text files are accepted too: every line that
+// isn't blank or a # comment becomes a snippet.
+
+use crate::bundle::ConflictPolicy;
+use crate::error::AppError;
+use crate::snippet_folders::{self, SnippetFolder};
+use crate::{config_file, get_snippets_path, load_snippets, tags, Snippet};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+use tracing::info;
+use uuid::Uuid;
+
+const PACK_FORMAT: &str = "terminoda-snippets";
+const PACK_VERSION: u32 = 1;
+// Snippets from text lines are named after their first words
+const NAME_WORDS: usize = 4;
+const NAME_MAX_CHARS: usize = 40;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnippetPack {
+    format: String,
+    version: u32,
+    exported_at: u64, // Unix timestamp
+    #[serde(default)]
+    folders: Vec<SnippetFolder>,
+    snippets: Vec<Snippet>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SnippetImportCounts {
+    pub added: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+/// Writes the given snippets (all when None) and their folders to `path`.
+#[tauri::command]
+pub fn export_snippets(
+    path: String,
+    snippet_ids: Option<Vec<String>>,
+    app_handle: AppHandle,
+) -> Result<usize, AppError> {
+    let snippets: Vec<Snippet> = load_snippets(app_handle.clone())?
+        .into_iter()
+        .filter(|s| snippet_ids.as_ref().is_none_or(|ids| ids.contains(&s.id)))
+        .collect();
+    let folders = snippet_folders::load(&app_handle)?
+        .into_iter()
+        .filter(|f| snippets.iter().any(|s| s.folder.as_ref() == Some(&f.id)))
+        .collect();
+    let pack = SnippetPack {
+        format: PACK_FORMAT.to_string(),
+        version: PACK_VERSION,
+        exported_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        folders,
+        snippets,
+    };
+    let content = serde_json::to_string_pretty(&pack)?;
+    fs::write(&path, content)?;
+    info!(
+        target = "snippets",
+        count = pack.snippets.len(),
+        "Exported snippets"
+    );
+    Ok(pack.snippets.len())
+}
+
+fn parse_pack(content: &str) -> Result<SnippetPack, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(content).map_err(|_| "Not a snippet pack".to_string())?;
+    if value.get("format").and_then(|f| f.as_str()) != Some(PACK_FORMAT) {
+        return Err("Not a snippet pack".to_string());
+    }
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version > PACK_VERSION as u64 {
+        return Err(format!(
+            "This snippet pack was written by a newer version of Terminoda (format {}), please update",
+            version
+        ));
+    }
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+fn name_from_command(command: &str) -> String {
+    let name = command
+        .split_whitespace()
+        .take(NAME_WORDS)
+        .collect::<Vec<_>>()
+        .join(" ");
+    match name.char_indices().nth(NAME_MAX_CHARS) {
+        Some((cut, _)) => format!("{}…", &name[..cut]),
+        None => name,
+    }
+}
+
+fn parse_text(content: &str) -> Vec<Snippet> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| Snippet {
+            id: Uuid::new_v4().to_string(),
+            name: name_from_command(line),
+            command: line.to_string(),
+            description: None,
+            tags: Vec::new(),
+            folder: None,
+            sort_order: 0,
+            host_ids: Vec::new(),
+            group_ids: Vec::new(),
+        })
+        .collect()
+}
+
+/// Imports a snippet pack, or a text file with one command per line. Text
+/// lines whose command already exists are skipped rather than duplicated.
+#[tauri::command]
+pub fn import_snippets(
+    path: String,
+    policy: ConflictPolicy,
+    app_handle: AppHandle,
+) -> Result<SnippetImportCounts, AppError> {
+    let content = fs::read_to_string(&path)?;
+    let (pack_folders, incoming, from_text) = if content.trim_start().starts_with('{') {
+        let pack = parse_pack(&content)?;
+        (pack.folders, pack.snippets, false)
+    } else {
+        (Vec::new(), parse_text(&content), true)
+    };
+
+    let _folders_lock = snippet_folders::lock_folders()?;
+    let mut folders = snippet_folders::load(&app_handle)?;
+    let mut folders_changed = false;
+    for folder in pack_folders {
+        if !folders.iter().any(|f| f.id == folder.id) {
+            folders.push(SnippetFolder {
+                sort_order: folders.iter().map(|f| f.sort_order + 1).max().unwrap_or(0),
+                ..folder
+            });
+            folders_changed = true;
+        }
+    }
+
+    let path = get_snippets_path(&app_handle)?;
+    let _lock = config_file::lock(&path)?;
+    let mut snippets = load_snippets(app_handle.clone())?;
+    let mut counts = SnippetImportCounts::default();
+    for mut snippet in incoming {
+        if !folders
+            .iter()
+            .any(|f| Some(&f.id) == snippet.folder.as_ref())
+        {
+            snippet.folder = None;
+        }
+        snippet.tags = tags::normalize(std::mem::take(&mut snippet.tags));
+        if from_text && snippets.iter().any(|s| s.command == snippet.command) {
+            counts.skipped += 1;
+            continue;
+        }
+        match snippets.iter().position(|s| s.id == snippet.id) {
+            Some(_) if policy == ConflictPolicy::KeepExisting => counts.skipped += 1,
+            Some(pos) if policy == ConflictPolicy::Overwrite => {
+                snippet.sort_order = snippets[pos].sort_order;
+                snippets[pos] = snippet;
+                counts.updated += 1;
+            }
+            existing => {
+                if existing.is_some() {
+                    snippet.id = Uuid::new_v4().to_string();
+                }
+                snippet.sort_order =
+                    snippet_folders::next_sort_order(&snippets, snippet.folder.as_deref());
+                snippets.push(snippet);
+                counts.added += 1;
+            }
+        }
+    }
+
+    if folders_changed {
+        snippet_folders::write_folders(&folders)?;
+    }
+    config_file::write(&path, &snippets)?;
+    info!(
+        target = "snippets",
+        added = counts.added,
+        updated = counts.updated,
+        skipped = counts.skipped,
+        "Imported snippets"
+    );
+    Ok(counts)
+}
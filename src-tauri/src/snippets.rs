@@ -0,0 +1,355 @@
+// Running snippets against live sessions and tracking how often they are used.
+//
+// Snippets are templates: {{name}} placeholders, optionally with a default
+// as {{name:default}}, are filled in before the command is sent. host,
+// username and date come from the target session unless given explicitly.
+
+use crate::command_guard;
+use crate::error::{AppError, ErrorKind};
+use crate::groups::{self, HostGroup};
+use crate::{
+    config_file, get_config_dir, get_snippets_path, load_saved_hosts, load_snippets, ownership,
+    AppState, SavedHost, SessionTarget, Snippet,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, State, Window};
+use tracing::info;
+use uuid::Uuid;
+
+// Keep the usage log bounded, it only feeds a "most used" view
+const MAX_USAGE_ENTRIES: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnippetUsage {
+    pub snippet_id: String,
+    pub session_id: String,
+    pub timestamp: u64, // Unix timestamp
+}
+
+fn get_usage_path() -> Result<PathBuf, String> {
+    Ok(get_config_dir()?.join("snippet_usage.json"))
+}
+
+fn load_usage(app_handle: &AppHandle) -> Result<Vec<SnippetUsage>, String> {
+    Ok(config_file::load(app_handle, &get_usage_path()?)?.unwrap_or_default())
+}
+
+fn record_usage(app_handle: &AppHandle, snippet_id: &str, session_id: &str) -> Result<(), String> {
+    let path = get_usage_path()?;
+    let _lock = config_file::lock(&path)?;
+    let mut usage = load_usage(app_handle).unwrap_or_default();
+    usage.push(SnippetUsage {
+        snippet_id: snippet_id.to_string(),
+        session_id: session_id.to_string(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    });
+    if usage.len() > MAX_USAGE_ENTRIES {
+        let excess = usage.len() - MAX_USAGE_ENTRIES;
+        usage.drain(..excess);
+    }
+
+    config_file::write(&path, &usage)
+}
+
+/// A {{name}} or {{name:default}} placeholder in a snippet.
+#[derive(Debug, Clone, Serialize)]
+pub struct SnippetVariable {
+    pub name: String,
+    pub default: Option<String>,
+    pub builtin: bool, // Filled in from the session, the UI needn't ask
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Variable {
+        name: &'a str,
+        default: Option<&'a str>,
+    },
+}
+
+const BUILTIN_VARIABLES: [&str; 3] = ["host", "username", "date"];
+
+/// Splits a snippet into text and placeholders. `\{{` is a literal `{{`,
+/// and an unclosed `{{` is kept as text.
+fn parse_template(command: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = command;
+    while let Some(start) = rest.find("{{") {
+        if rest[..start].ends_with('\\') {
+            parts.push(Part::Text(&rest[..start - 1]));
+            parts.push(Part::Text("{{"));
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let inner = &rest[start + 2..start + 2 + len];
+        let (name, default) = match inner.split_once(':') {
+            Some((name, default)) => (name.trim(), Some(default)),
+            None => (inner.trim(), None),
+        };
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+        if !valid {
+            return Err(format!("Invalid variable name '{{{{{}}}}}'", inner));
+        }
+        parts.push(Part::Text(&rest[..start]));
+        parts.push(Part::Variable { name, default });
+        rest = &rest[start + 2 + len + 2..];
+    }
+    parts.push(Part::Text(rest));
+    Ok(parts)
+}
+
+/// The placeholders of a snippet in order of first use, each listed once.
+fn template_variables(command: &str) -> Result<Vec<SnippetVariable>, String> {
+    let mut variables: Vec<SnippetVariable> = Vec::new();
+    for part in parse_template(command)? {
+        let Part::Variable { name, default } = part else {
+            continue;
+        };
+        match variables.iter_mut().find(|v| v.name == name) {
+            // A later default still helps if the first use had none
+            Some(existing) => {
+                if existing.default.is_none() {
+                    existing.default = default.map(str::to_string);
+                }
+            }
+            None => variables.push(SnippetVariable {
+                name: name.to_string(),
+                default: default.map(str::to_string),
+                builtin: BUILTIN_VARIABLES.contains(&name),
+            }),
+        }
+    }
+    Ok(variables)
+}
+
+/// Fills in placeholders from `values`, then `builtins`, then their
+/// defaults. Fails listing every variable left without a value.
+fn substitute_variables(
+    command: &str,
+    values: &HashMap<String, String>,
+    builtins: &HashMap<&str, String>,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(command.len());
+    let mut missing: Vec<&str> = Vec::new();
+    for part in parse_template(command)? {
+        match part {
+            Part::Text(text) => out.push_str(text),
+            Part::Variable { name, default } => {
+                let value = values
+                    .get(name)
+                    .map(String::as_str)
+                    .or_else(|| builtins.get(name).map(String::as_str))
+                    .or(default);
+                match value {
+                    Some(value) => out.push_str(value),
+                    None if !missing.contains(&name) => missing.push(name),
+                    None => {}
+                }
+            }
+        }
+    }
+    if !missing.is_empty() {
+        return Err(format!("Missing values for: {}", missing.join(", ")));
+    }
+    Ok(out)
+}
+
+/// (year, month, day) of a count of days since 1970-01-01.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month as u32, day as u32)
+}
+
+// Today's date in UTC as YYYY-MM-DD
+fn utc_date() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn builtin_values(target: &SessionTarget) -> HashMap<&'static str, String> {
+    HashMap::from([
+        ("host", target.host.clone()),
+        ("username", target.username.clone()),
+        ("date", utc_date()),
+    ])
+}
+
+/// Fills in a snippet's placeholders for a session, as run_snippet does.
+pub(crate) fn render(
+    command: &str,
+    values: &HashMap<String, String>,
+    target: &SessionTarget,
+) -> Result<String, String> {
+    substitute_variables(command, values, &builtin_values(target))
+}
+
+pub(crate) fn find_snippet(app_handle: &AppHandle, snippet_id: &str) -> Result<Snippet, AppError> {
+    load_snippets(app_handle.clone())?
+        .into_iter()
+        .find(|s| s.id == snippet_id)
+        .ok_or_else(|| {
+            AppError::new(
+                ErrorKind::NotFound,
+                format!("Snippet not found: {}", snippet_id),
+            )
+        })
+}
+
+impl Snippet {
+    fn is_global(&self) -> bool {
+        self.host_ids.is_empty() && self.group_ids.is_empty()
+    }
+}
+
+// Drops host and group ids that no longer exist, writing back if any did.
+// Deleting a host doesn't touch snippets, so this runs when they're read.
+fn prune_dangling(
+    app_handle: &AppHandle,
+    hosts: &[SavedHost],
+    groups: &[HostGroup],
+) -> Result<Vec<Snippet>, String> {
+    let path = get_snippets_path(app_handle)?;
+    let _lock = config_file::lock(&path)?;
+    let mut snippets = load_snippets(app_handle.clone())?;
+    let mut pruned = 0;
+    for snippet in snippets.iter_mut() {
+        let before = snippet.host_ids.len() + snippet.group_ids.len();
+        snippet
+            .host_ids
+            .retain(|id| hosts.iter().any(|h| &h.id == id));
+        snippet
+            .group_ids
+            .retain(|id| groups.iter().any(|g| &g.id == id));
+        pruned += before - snippet.host_ids.len() - snippet.group_ids.len();
+    }
+    if pruned > 0 {
+        config_file::write(&path, &snippets)?;
+        info!(
+            target = "snippets",
+            ids = pruned,
+            "Pruned deleted hosts and groups from snippets"
+        );
+    }
+    Ok(snippets)
+}
+
+/// Snippets for a session's palette: global ones plus those scoped to the
+/// host, its group or that group's parent. A snippet whose hosts and groups
+/// have all been deleted becomes global again.
+#[tauri::command]
+pub fn get_snippets_for_host(
+    host_id: String,
+    app_handle: AppHandle,
+) -> Result<Vec<Snippet>, AppError> {
+    let hosts = load_saved_hosts(app_handle.clone())?;
+    let groups = groups::load(&app_handle)?;
+    let host = hosts
+        .iter()
+        .find(|h| h.id == host_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Host not found"))?;
+    let group = host
+        .group
+        .as_ref()
+        .and_then(|id| groups.iter().find(|g| &g.id == id));
+    let host_groups: Vec<&str> = group
+        .into_iter()
+        .flat_map(|g| std::iter::once(g.id.as_str()).chain(g.parent_id.as_deref()))
+        .collect();
+
+    Ok(prune_dangling(&app_handle, &hosts, &groups)?
+        .into_iter()
+        .filter(|s| {
+            s.is_global()
+                || s.host_ids.contains(&host_id)
+                || s.group_ids
+                    .iter()
+                    .any(|g| host_groups.contains(&g.as_str()))
+        })
+        .collect())
+}
+
+/// Lists a snippet's placeholders so the UI can ask for their values.
+#[tauri::command]
+pub fn get_snippet_variables(
+    snippet_id: String,
+    app_handle: AppHandle,
+) -> Result<Vec<SnippetVariable>, AppError> {
+    Ok(template_variables(
+        &find_snippet(&app_handle, &snippet_id)?.command,
+    )?)
+}
+
+#[tauri::command]
+pub fn run_snippet(
+    snippet_id: String,
+    session_id: String,
+    variables: Option<HashMap<String, String>>,
+    window: Window,
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<(), AppError> {
+    let snippet = find_snippet(&app_handle, &snippet_id)?;
+
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    ownership::check(&session, &session_id, &window)?;
+    if session.flow.is_paused() {
+        return Err("Session output is paused, resume it before running a snippet".into());
+    }
+
+    let mut command = render(
+        &snippet.command,
+        &variables.unwrap_or_default(),
+        &session.target,
+    )?;
+    if !command.ends_with('\n') {
+        command.push('\n');
+    }
+    // The same path as typed input, so the command guard sees it
+    let settings = state.settings.get();
+    session
+        .read_only
+        .check_input(&command, &settings.read_only_allowed_input)
+        .map_err(|e| e.with_session(&session_id))?;
+    let command = command_guard::filter_input(
+        &session,
+        &session_id,
+        &command,
+        &settings.command_guard,
+        &window,
+    )?;
+    if !command.is_empty() {
+        session.write_input(command.as_bytes())?;
+    }
+    drop(session);
+
+    info!(target = "snippets", snippet = %snippet_id, session = %session_id, "Ran snippet");
+    Ok(record_usage(&app_handle, &snippet_id, &session_id)?)
+}
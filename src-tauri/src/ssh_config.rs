@@ -0,0 +1,345 @@
+// Importing hosts from an OpenSSH client config (~/.ssh/config).
+//
+// Only concrete aliases become hosts, named after the alias. Wildcard and
+// negated patterns and Match blocks are reported as warnings, as is every
+// line that cannot be parsed.
+
+use crate::error::AppError;
+use crate::host_import::{build_preview, local_username, ImportPreview, ImportWarning};
+use crate::{ConnectionDetails, SavedHost};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use uuid::Uuid;
+
+// Include loops are cut off here, like ssh does
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+struct ConfigLine {
+    file: String,
+    number: usize,
+    text: String,
+}
+
+#[derive(Default)]
+struct HostEntry {
+    host_name: Option<String>,
+    user: Option<String>,
+    port: Option<u16>,
+    identity_file: Option<String>,
+    proxy_jump: Option<String>,
+}
+
+struct ParsedConfig {
+    hosts: Vec<(String, HostEntry)>, // by alias, in file order
+    warnings: Vec<ImportWarning>,
+}
+
+fn home_dir() -> Result<PathBuf, String> {
+    std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .map(PathBuf::from)
+        .map_err(|_| "Could not find home directory".to_string())
+}
+
+fn expand_home(value: &str, home: &Path) -> String {
+    match value.strip_prefix("~/") {
+        Some(rest) => home.join(rest).to_string_lossy().into_owned(),
+        None => value.to_string(),
+    }
+}
+
+/// Matches ssh-style patterns with `*` and `?` wildcards.
+fn matches_pattern(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            matches_pattern(&pattern[1..], name)
+                || (!name.is_empty() && matches_pattern(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => matches_pattern(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => matches_pattern(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+fn is_pattern(alias: &str) -> bool {
+    alias.contains(['*', '?', '!'])
+}
+
+// Splits "Keyword value", "Keyword=value" and quoted arguments
+fn split_line(line: &str) -> Option<(String, Vec<String>)> {
+    let end = line
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(line.len());
+    let keyword = line[..end].to_ascii_lowercase();
+    let rest = line[end..].trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest);
+
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut in_arg = false;
+    for c in rest.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if quoted {
+        return None;
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Some((keyword, args))
+}
+
+// Expands an Include argument; relative paths are relative to ~/.ssh and
+// wildcards are supported in the file name
+fn include_targets(argument: &str, home: &Path) -> Vec<PathBuf> {
+    let expanded = PathBuf::from(expand_home(argument, home));
+    let path = if expanded.is_absolute() {
+        expanded
+    } else {
+        home.join(".ssh").join(expanded)
+    };
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if !is_pattern(&name) {
+        return vec![path];
+    }
+
+    let Some(dir) = path.parent() else {
+        return Vec::new();
+    };
+    let mut targets: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| {
+                    p.file_name().is_some_and(|n| {
+                        matches_pattern(name.as_bytes(), n.to_string_lossy().as_bytes())
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    targets.sort();
+    targets
+}
+
+// Reads a config file with its Include directives expanded in place
+fn read_lines(
+    path: &Path,
+    home: &Path,
+    depth: usize,
+    lines: &mut Vec<ConfigLine>,
+    warnings: &mut Vec<ImportWarning>,
+) -> Result<(), String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let file = path.to_string_lossy().into_owned();
+
+    for (i, raw) in content.lines().enumerate() {
+        let text = raw.trim();
+        let warning = |reason: String| ImportWarning {
+            source: file.clone(),
+            line: Some(i + 1),
+            text: text.to_string(),
+            reason,
+        };
+        let include = split_line(text)
+            .filter(|(keyword, _)| keyword == "include")
+            .map(|(_, args)| args);
+        let Some(args) = include else {
+            lines.push(ConfigLine {
+                file: file.clone(),
+                number: i + 1,
+                text: text.to_string(),
+            });
+            continue;
+        };
+        if args.is_empty() {
+            warnings.push(warning("Include without a file".to_string()));
+            continue;
+        }
+        if depth >= MAX_INCLUDE_DEPTH {
+            warnings.push(warning("Include nested too deeply".to_string()));
+            continue;
+        }
+        for argument in args {
+            for target in include_targets(&argument, home) {
+                if let Err(e) = read_lines(&target, home, depth + 1, lines, warnings) {
+                    warnings.push(warning(e));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_config(path: &Path, home: &Path) -> Result<ParsedConfig, String> {
+    let mut lines = Vec::new();
+    let mut warnings = Vec::new();
+    read_lines(path, home, 0, &mut lines, &mut warnings)?;
+
+    let mut order: Vec<String> = Vec::new();
+    let mut entries: HashMap<String, HostEntry> = HashMap::new();
+    // Aliases the current block applies to; empty for global, wildcard and
+    // Match blocks, whose settings are not imported
+    let mut current: Vec<String> = Vec::new();
+
+    for line in lines {
+        if line.text.is_empty() || line.text.starts_with('#') {
+            continue;
+        }
+        let warning = |reason: &str| ImportWarning {
+            source: line.file.clone(),
+            line: Some(line.number),
+            text: line.text.clone(),
+            reason: reason.to_string(),
+        };
+        let Some((keyword, args)) = split_line(&line.text) else {
+            warnings.push(warning("Could not parse line"));
+            continue;
+        };
+        if args.is_empty() {
+            warnings.push(warning("Missing value"));
+            continue;
+        }
+
+        match keyword.as_str() {
+            "host" => {
+                current.clear();
+                for alias in args {
+                    if is_pattern(&alias) {
+                        warnings.push(warning(&format!("Pattern '{}' skipped", alias)));
+                        continue;
+                    }
+                    if !entries.contains_key(&alias) {
+                        order.push(alias.clone());
+                        entries.insert(alias.clone(), HostEntry::default());
+                    }
+                    current.push(alias);
+                }
+            }
+            "match" => {
+                current.clear();
+                warnings.push(warning("Match blocks are not imported"));
+            }
+            "port" => match args[0].parse::<u16>() {
+                Ok(port) => {
+                    for alias in &current {
+                        let entry = entries.get_mut(alias).expect("alias registered on Host");
+                        entry.port = entry.port.or(Some(port));
+                    }
+                }
+                Err(_) => warnings.push(warning("Invalid port")),
+            },
+            "hostname" | "user" | "identityfile" | "proxyjump" => {
+                for alias in &current {
+                    let entry = entries.get_mut(alias).expect("alias registered on Host");
+                    let slot = match keyword.as_str() {
+                        "hostname" => &mut entry.host_name,
+                        "user" => &mut entry.user,
+                        "identityfile" => &mut entry.identity_file,
+                        _ => &mut entry.proxy_jump,
+                    };
+                    // The first value obtained for a host wins, as in ssh
+                    if slot.is_none() {
+                        *slot = Some(args[0].clone());
+                    }
+                }
+            }
+            // Other ssh options are valid but have no equivalent here
+            _ => {}
+        }
+    }
+
+    let hosts = order
+        .into_iter()
+        .map(|alias| {
+            let entry = entries.remove(&alias).unwrap_or_default();
+            (alias, entry)
+        })
+        .collect();
+    Ok(ParsedConfig { hosts, warnings })
+}
+
+fn to_saved_host(alias: &str, entry: HostEntry, home: &Path) -> SavedHost {
+    let host = entry
+        .host_name
+        .map(|h| h.replace("%h", alias))
+        .unwrap_or_else(|| alias.to_string());
+    // ssh falls back to the local user name
+    let username = entry.user.unwrap_or_else(local_username);
+    let private_key_path = entry.identity_file.map(|f| {
+        expand_home(
+            &f.replace("%d", &home.to_string_lossy())
+                .replace("%h", &host),
+            home,
+        )
+    });
+
+    SavedHost {
+        id: Uuid::new_v4().to_string(),
+        name: alias.to_string(),
+        group: None,
+        tags: Vec::new(),
+        details: ConnectionDetails {
+            host,
+            port: entry.port,
+            username,
+            private_key_path,
+            proxy_jump: entry.proxy_jump,
+            ..Default::default()
+        },
+        has_password: false,
+        has_passphrase: false,
+        has_totp_secret: false,
+        pinned: false,
+        sort_order: 0,
+        notes: None,
+        color: None,
+        metadata: HashMap::new(),
+        mac_address: None,
+        broadcast_address: None,
+        reachability_disabled: false,
+        tunnels: Vec::new(),
+    }
+}
+
+/// Parses an ssh config (~/.ssh/config by default) without saving anything.
+#[tauri::command]
+pub fn import_ssh_config(
+    path: Option<String>,
+    app_handle: AppHandle,
+) -> Result<ImportPreview, AppError> {
+    let home = home_dir()?;
+    let path = path
+        .map(|p| PathBuf::from(expand_home(&p, &home)))
+        .unwrap_or_else(|| home.join(".ssh").join("config"));
+    let parsed = parse_config(&path, &home)?;
+    let hosts = parsed
+        .hosts
+        .into_iter()
+        .map(|(alias, entry)| to_saved_host(&alias, entry, &home))
+        .collect();
+    Ok(build_preview(hosts, parsed.warnings, app_handle)?)
+}
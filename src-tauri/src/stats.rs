@@ -0,0 +1,192 @@
+// Per-session traffic counters.
+//
+// The reader, the input path and SFTP transfers bump relaxed atomics, nothing
+// more. A monitor thread samples every session once a second to keep a
+// smoothed rate and emits "session-stats" for sessions the frontend
+// subscribed to. Counters belong to the SessionState, so a reconnect, which
+// creates a new session, starts from zero.
+
+use crate::error::AppError;
+use crate::AppState;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+use uuid::Uuid;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+// Time constant of the rate's moving average
+const RATE_SMOOTHING_SECS: f64 = 3.0;
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct TrafficBreakdown {
+    pub output_bytes: u64,
+    pub input_bytes: u64,
+    pub sftp_download_bytes: u64,
+    pub sftp_upload_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionStatsInfo {
+    pub session_id: String,
+    pub connected_at: u64, // Unix timestamp in milliseconds
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub breakdown: TrafficBreakdown,
+    // Bytes per second, smoothed over a few seconds
+    pub rate_in: f64,
+    pub rate_out: f64,
+    // So the tab can show a badge
+    pub read_only: bool,
+}
+
+struct RateSample {
+    at: Instant,
+    bytes_in: u64,
+    bytes_out: u64,
+    rate_in: f64,
+    rate_out: f64,
+}
+
+pub struct SessionStats {
+    connected_at: u64,
+    output: AtomicU64,
+    input: AtomicU64,
+    sftp_down: AtomicU64,
+    sftp_up: AtomicU64,
+    rate: Mutex<RateSample>,
+    subscribed: AtomicBool,
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        Self {
+            connected_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            output: AtomicU64::new(0),
+            input: AtomicU64::new(0),
+            sftp_down: AtomicU64::new(0),
+            sftp_up: AtomicU64::new(0),
+            rate: Mutex::new(RateSample {
+                at: Instant::now(),
+                bytes_in: 0,
+                bytes_out: 0,
+                rate_in: 0.0,
+                rate_out: 0.0,
+            }),
+            subscribed: AtomicBool::new(false),
+        }
+    }
+}
+
+impl SessionStats {
+    pub fn add_output(&self, bytes: usize) {
+        self.output.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_input(&self, bytes: usize) {
+        self.input.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_sftp_download(&self, bytes: usize) {
+        self.sftp_down.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_sftp_upload(&self, bytes: usize) {
+        self.sftp_up.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn breakdown(&self) -> TrafficBreakdown {
+        TrafficBreakdown {
+            output_bytes: self.output.load(Ordering::Relaxed),
+            input_bytes: self.input.load(Ordering::Relaxed),
+            sftp_download_bytes: self.sftp_down.load(Ordering::Relaxed),
+            sftp_upload_bytes: self.sftp_up.load(Ordering::Relaxed),
+        }
+    }
+
+    // Folds the traffic since the last sample into the moving average
+    fn sample(&self, bytes_in: u64, bytes_out: u64) -> (f64, f64) {
+        let mut rate = self.rate.lock().unwrap_or_else(|e| e.into_inner());
+        let elapsed = rate.at.elapsed().as_secs_f64();
+        if elapsed >= SAMPLE_INTERVAL.as_secs_f64() / 2.0 {
+            let weight = 1.0 - (-elapsed / RATE_SMOOTHING_SECS).exp();
+            let current_in = bytes_in.saturating_sub(rate.bytes_in) as f64 / elapsed;
+            let current_out = bytes_out.saturating_sub(rate.bytes_out) as f64 / elapsed;
+            rate.rate_in += weight * (current_in - rate.rate_in);
+            rate.rate_out += weight * (current_out - rate.rate_out);
+            rate.at = Instant::now();
+            rate.bytes_in = bytes_in;
+            rate.bytes_out = bytes_out;
+        }
+        (rate.rate_in, rate.rate_out)
+    }
+
+    pub fn info(&self, session_id: String, read_only: bool) -> SessionStatsInfo {
+        let breakdown = self.breakdown();
+        let bytes_in = breakdown.output_bytes + breakdown.sftp_download_bytes;
+        let bytes_out = breakdown.input_bytes + breakdown.sftp_upload_bytes;
+        let (rate_in, rate_out) = self.sample(bytes_in, bytes_out);
+        SessionStatsInfo {
+            session_id,
+            connected_at: self.connected_at,
+            bytes_in,
+            bytes_out,
+            breakdown,
+            rate_in,
+            rate_out,
+            read_only,
+        }
+    }
+}
+
+/// Samples every session's rate and emits "session-stats" for subscribers.
+pub fn spawn_stats_monitor(app_handle: AppHandle) {
+    thread::spawn(move || loop {
+        thread::sleep(SAMPLE_INTERVAL);
+        let state = app_handle.state::<AppState>();
+        for entry in state.sessions.iter() {
+            let info = entry
+                .stats
+                .info(entry.key().to_string(), entry.read_only.is_enabled());
+            if entry.stats.subscribed.load(Ordering::Relaxed) {
+                entry.owners.emit(&app_handle, "session-stats", info);
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_session_stats(
+    session_id: String,
+    state: State<'_, AppState>,
+) -> Result<SessionStatsInfo, AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    Ok(session
+        .stats
+        .info(session_id, session.read_only.is_enabled()))
+}
+
+// Turns the periodic "session-stats" event on or off for one session
+#[tauri::command]
+pub fn subscribe_session_stats(
+    session_id: String,
+    enabled: bool,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let uuid = Uuid::parse_str(&session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    session.stats.subscribed.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
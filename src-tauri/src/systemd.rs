@@ -0,0 +1,439 @@
+// systemd services on the remote host: listing, start/stop and logs.
+//
+// Everything runs on side channels. systemctl's JSON output needs systemd
+// 240 or so; older ones get parsed from the plain listing. Hosts without
+// systemd fail with kind "unsupported" before anything else runs.
+//
+// Actions run as the login user first. When systemctl says it needs root
+// they are retried under "sudo -n", and if sudo wants a password, with the
+// one passed in or, for a host with sudo autofill on, its stored password
+// (see triggers::SudoAutofill). Without either the call fails with
+// "password-required" so the frontend can ask and retry. Logs try "sudo -n"
+// and otherwise show what the user may read. A followed log streams
+// "service-log" events until stop_service_logs or the session closes.
+
+use crate::credentials::{self, SecretKind};
+use crate::error::{AppError, ErrorKind};
+use crate::side_channel::{
+    run_on_side_channel, run_with_input, stream_on_side_channel, ExecOutput,
+};
+use crate::{load_saved_hosts, ownership, read_only, AppState};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::thread;
+use std::time::Duration;
+use tauri::{async_runtime, AppHandle, Emitter, Manager, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const EXEC_TIMEOUT: Duration = Duration::from_secs(30);
+const SYSTEMD_CHECK: &str = "command -v systemctl >/dev/null 2>&1 && [ -d /run/systemd/system ]";
+const ACTIONS: &[&str] = &["start", "stop", "restart", "reload", "enable", "disable"];
+const DEFAULT_LOG_LINES: u32 = 200;
+const MAX_LOG_LINES: u32 = 10_000;
+
+// Followed logs by stream id
+static LOG_STREAMS: LazyLock<DashMap<String, Arc<AtomicBool>>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceInfo {
+    #[serde(rename(deserialize = "unit"))]
+    pub name: String,
+    #[serde(rename(deserialize = "load"))]
+    pub load_state: String,
+    #[serde(rename(deserialize = "active"))]
+    pub active_state: String,
+    #[serde(rename(deserialize = "sub"))]
+    pub sub_state: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceActionResult {
+    pub unit: String,
+    pub action: String,
+    pub active_state: String,
+    pub sub_state: String,
+    // "enabled", "disabled", "static"...
+    pub unit_file_state: Option<String>,
+    // What systemctl printed, errors included
+    pub output: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceLogs {
+    // The last lines, when not following
+    pub lines: Vec<String>,
+    // Matches the "service-log" events, when following
+    pub stream_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ServiceLogPayload {
+    stream_id: String,
+    session_id: String,
+    lines: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ServiceLogEndPayload {
+    stream_id: String,
+    session_id: String,
+    // None when stopped
+    exit_status: Option<i32>,
+    error: Option<String>,
+}
+
+fn check_owner(app_handle: &AppHandle, session_id: &str, window: &Window) -> Result<(), AppError> {
+    let state = app_handle.state::<AppState>();
+    let uuid = Uuid::parse_str(session_id)?;
+    let session = state
+        .sessions
+        .get(&uuid)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    ownership::check(&session, session_id, window)
+}
+
+fn run(app_handle: &AppHandle, session_id: &str, command: &str) -> Result<ExecOutput, AppError> {
+    let state = app_handle.state::<AppState>();
+    run_on_side_channel(&state.sessions, session_id, command, EXEC_TIMEOUT)
+}
+
+pub(crate) fn ensure_systemd(app_handle: &AppHandle, session_id: &str) -> Result<(), AppError> {
+    if run(app_handle, session_id, SYSTEMD_CHECK)?.exit_status != 0 {
+        return Err(AppError::new(
+            ErrorKind::Unsupported,
+            "This host does not run systemd",
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_unit(unit: &str) -> Result<(), AppError> {
+    let valid = !unit.is_empty()
+        && !unit.starts_with('-')
+        && unit
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, ':' | '_' | '.' | '@' | '-' | '\\'));
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid unit name: {}", unit),
+        ))
+    }
+}
+
+fn needs_root(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    [
+        "access denied",
+        "interactive authentication required",
+        "authentication is required",
+        "permission denied",
+        "must be root",
+    ]
+    .iter()
+    .any(|hint| stderr.contains(hint))
+}
+
+// The password passed in, else the stored one if the host opted into sudo
+// autofill
+fn find_sudo_password(
+    app_handle: &AppHandle,
+    host_id: Option<&str>,
+    given: Option<String>,
+) -> Result<Option<String>, AppError> {
+    if let Some(password) = given.filter(|p| !p.is_empty()) {
+        return Ok(Some(password));
+    }
+    let Some(host_id) = host_id else {
+        return Ok(None);
+    };
+    let autofill = load_saved_hosts(app_handle.clone())?
+        .into_iter()
+        .find(|h| h.id == host_id)
+        .and_then(|h| h.details.sudo_autofill)
+        .is_some_and(|config| config.enabled);
+    if !autofill {
+        return Ok(None);
+    }
+    Ok(credentials::load(host_id, SecretKind::Password)?)
+}
+
+// Runs a command, retrying under sudo when systemctl says it needs root
+fn run_privileged(
+    app_handle: &AppHandle,
+    session_id: &str,
+    command: &str,
+    password: Option<&str>,
+) -> Result<ExecOutput, AppError> {
+    let output = run(app_handle, session_id, command)?;
+    if output.exit_status == 0 || !needs_root(&output.stderr) {
+        return Ok(output);
+    }
+    let output = run(app_handle, session_id, &format!("sudo -n {}", command))?;
+    if output.exit_status == 0 || !output.stderr.contains("password is required") {
+        return Ok(output);
+    }
+    let Some(password) = password else {
+        return Err(AppError::new(
+            ErrorKind::PasswordRequired,
+            "sudo needs a password for this action",
+        ));
+    };
+    info!(target = "systemd", session = %session_id, "Retrying with the sudo password");
+    let state = app_handle.state::<AppState>();
+    let output = run_with_input(
+        &state.sessions,
+        session_id,
+        &format!("sudo -S -p '' {}", command),
+        format!("{}\n", password).as_bytes(),
+        EXEC_TIMEOUT,
+    )?;
+    if output.stderr.contains("incorrect password") || output.stderr.contains("try again") {
+        return Err(AppError::new(
+            ErrorKind::AuthFailed,
+            "sudo rejected the password",
+        ));
+    }
+    Ok(output)
+}
+
+// "unit load active sub description", failed units marked with a bullet
+fn parse_plain(output: &str) -> Vec<ServiceInfo> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut rest = line.trim_start_matches(['●', '*', ' ']);
+            let mut field = || {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let (field, tail) = rest.split_at(end);
+                rest = tail.trim_start();
+                (!field.is_empty()).then(|| field.to_string())
+            };
+            let name = field()?;
+            let load_state = field()?;
+            let active_state = field()?;
+            let sub_state = field()?;
+            let description = rest.trim().to_string();
+            name.ends_with(".service").then_some(ServiceInfo {
+                name,
+                load_state,
+                active_state,
+                sub_state,
+                description,
+            })
+        })
+        .collect()
+}
+
+fn list(app_handle: &AppHandle, session_id: &str) -> Result<Vec<ServiceInfo>, AppError> {
+    ensure_systemd(app_handle, session_id)?;
+    let output = run(
+        app_handle,
+        session_id,
+        "systemctl list-units --type=service --all --no-pager --output=json",
+    )?;
+    if output.exit_status == 0 {
+        if let Ok(services) = serde_json::from_str::<Vec<ServiceInfo>>(output.stdout.trim()) {
+            return Ok(services);
+        }
+    }
+    let output = run(
+        app_handle,
+        session_id,
+        "systemctl list-units --type=service --all --no-pager --no-legend --plain",
+    )?;
+    if output.exit_status != 0 {
+        return Err(format!("systemctl failed: {}", output.stderr.trim()).into());
+    }
+    Ok(parse_plain(&output.stdout))
+}
+
+#[tauri::command]
+pub async fn get_remote_services(
+    session_id: String,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<Vec<ServiceInfo>, AppError> {
+    check_owner(&app_handle, &session_id, &window)?;
+    async_runtime::spawn_blocking(move || list(&app_handle, &session_id))
+        .await
+        .map_err(|e| AppError::from(e.to_string()))?
+}
+
+/// Runs start, stop, restart, reload, enable or disable on a unit and
+/// returns its state afterwards. `host_id` lets a host with sudo autofill use
+/// its stored password; `sudo_password` is used as given.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn service_action(
+    session_id: String,
+    unit: String,
+    action: String,
+    host_id: Option<String>,
+    sudo_password: Option<String>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<ServiceActionResult, AppError> {
+    check_owner(&app_handle, &session_id, &window)?;
+    read_only::check(&app_handle.state::<AppState>(), &session_id)?;
+    validate_unit(&unit)?;
+    if !ACTIONS.contains(&action.as_str()) {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            format!("Unsupported action: {}", action),
+        ));
+    }
+    let password = find_sudo_password(&app_handle, host_id.as_deref(), sudo_password)?;
+
+    async_runtime::spawn_blocking(move || {
+        ensure_systemd(&app_handle, &session_id)?;
+        let command = format!("systemctl {} -- {}", action, unit);
+        let output = run_privileged(&app_handle, &session_id, &command, password.as_deref())?;
+        let printed = format!("{}{}", output.stdout, output.stderr)
+            .trim()
+            .to_string();
+        if output.exit_status != 0 {
+            let kind = if needs_root(&output.stderr) {
+                ErrorKind::PermissionDenied
+            } else {
+                ErrorKind::Other
+            };
+            return Err(AppError::new(
+                kind,
+                format!("systemctl {} failed: {}", action, printed),
+            ));
+        }
+        info!(target = "systemd", session = %session_id, %unit, %action, "Ran service action");
+
+        let show = run(
+            &app_handle,
+            &session_id,
+            &format!(
+                "systemctl show -p ActiveState -p SubState -p UnitFileState -- {}",
+                unit
+            ),
+        )?;
+        let property = |key: &str| {
+            show.stdout.lines().find_map(|line| {
+                let value = line.strip_prefix(key)?.strip_prefix('=')?;
+                Some(value.trim().to_string())
+            })
+        };
+        Ok(ServiceActionResult {
+            active_state: property("ActiveState").unwrap_or_default(),
+            sub_state: property("SubState").unwrap_or_default(),
+            unit_file_state: property("UnitFileState").filter(|s| !s.is_empty()),
+            unit,
+            action,
+            output: printed,
+        })
+    })
+    .await
+    .map_err(|e| AppError::from(e.to_string()))?
+}
+
+/// Returns the unit's last `lines` log lines, or with `follow` streams them
+/// and everything after as "service-log" events, ending with
+/// "service-log-end".
+#[tauri::command]
+pub async fn get_service_logs(
+    session_id: String,
+    unit: String,
+    lines: Option<u32>,
+    follow: Option<bool>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<ServiceLogs, AppError> {
+    check_owner(&app_handle, &session_id, &window)?;
+    validate_unit(&unit)?;
+    let lines = lines.unwrap_or(DEFAULT_LOG_LINES).clamp(1, MAX_LOG_LINES);
+    let follow = follow.unwrap_or(false);
+    let journalctl = format!(
+        "journalctl -u {} -n {} --no-pager -o short-iso{}",
+        unit,
+        lines,
+        if follow { " -f" } else { "" }
+    );
+    // Root sees the whole journal, others only what their groups allow
+    let command = format!("sudo -n {0} 2>/dev/null || {0}", journalctl);
+
+    async_runtime::spawn_blocking(move || {
+        ensure_systemd(&app_handle, &session_id)?;
+        if !follow {
+            let output = run(&app_handle, &session_id, &command)?;
+            if output.exit_status != 0 {
+                return Err(format!("journalctl failed: {}", output.stderr.trim()).into());
+            }
+            return Ok(ServiceLogs {
+                lines: output.stdout.lines().map(str::to_string).collect(),
+                stream_id: None,
+            });
+        }
+
+        let stream_id = Uuid::new_v4().to_string();
+        let stop = Arc::new(AtomicBool::new(false));
+        LOG_STREAMS.insert(stream_id.clone(), stop.clone());
+        let id = stream_id.clone();
+        thread::spawn(move || {
+            let state = app_handle.state::<AppState>();
+            let mut pending = Vec::new();
+            let result =
+                stream_on_side_channel(&state.sessions, &session_id, &command, &stop, |chunk| {
+                    pending.extend_from_slice(chunk);
+                    // Only whole lines; the rest waits for the next chunk
+                    let Some(end) = pending.iter().rposition(|&b| b == b'\n') else {
+                        return;
+                    };
+                    let text: Vec<u8> = pending.drain(..=end).collect();
+                    let _ = window.emit(
+                        "service-log",
+                        ServiceLogPayload {
+                            stream_id: id.clone(),
+                            session_id: session_id.clone(),
+                            lines: String::from_utf8_lossy(&text)
+                                .lines()
+                                .map(str::to_string)
+                                .collect(),
+                        },
+                    );
+                });
+            if let Err(e) = &result {
+                warn!(target = "systemd", session = %session_id, error = %e, "Log stream failed");
+            }
+            LOG_STREAMS.remove(&id);
+            let (exit_status, error) = match result {
+                Ok(status) => (status, None),
+                Err(e) => (None, Some(e.message)),
+            };
+            let _ = window.emit(
+                "service-log-end",
+                ServiceLogEndPayload {
+                    stream_id: id,
+                    session_id,
+                    exit_status,
+                    error,
+                },
+            );
+        });
+        Ok(ServiceLogs {
+            lines: Vec::new(),
+            stream_id: Some(stream_id),
+        })
+    })
+    .await
+    .map_err(|e| AppError::from(e.to_string()))?
+}
+
+#[tauri::command]
+pub fn stop_service_logs(stream_id: String) {
+    if let Some((_, stop)) = LOG_STREAMS.remove(&stream_id) {
+        stop.store(true, Ordering::Relaxed);
+    }
+}
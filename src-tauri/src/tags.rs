@@ -0,0 +1,118 @@
+// Free-form tags on saved hosts. Unlike groups a host can carry any number of
+// them; the set of known tags is whatever the hosts currently use.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{load_saved_hosts, lock_saved_hosts, write_saved_hosts, SavedHost};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tauri::AppHandle;
+use tracing::info;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub hosts: usize,
+}
+
+/// Trims tags and drops empty and repeated ones, keeping first-seen order.
+pub fn normalize(tags: Vec<String>) -> Vec<String> {
+    let mut out: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !out.iter().any(|t| t == tag) {
+            out.push(tag.to_string());
+        }
+    }
+    out
+}
+
+fn update_host_tags(
+    host_id: &str,
+    app_handle: &AppHandle,
+    update: impl FnOnce(&mut Vec<String>),
+) -> Result<SavedHost, String> {
+    let _lock = lock_saved_hosts(app_handle)?;
+    let mut hosts = load_saved_hosts(app_handle.clone())?;
+    let host = hosts
+        .iter_mut()
+        .find(|h| h.id == host_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Host not found"))?;
+    update(&mut host.tags);
+    host.tags = normalize(std::mem::take(&mut host.tags));
+    let updated = host.clone();
+    write_saved_hosts(app_handle, &hosts)?;
+    Ok(updated)
+}
+
+#[tauri::command]
+pub fn add_host_tags(
+    host_id: String,
+    tags: Vec<String>,
+    app_handle: AppHandle,
+) -> Result<SavedHost, AppError> {
+    Ok(update_host_tags(&host_id, &app_handle, |current| {
+        current.extend(tags)
+    })?)
+}
+
+#[tauri::command]
+pub fn remove_host_tags(
+    host_id: String,
+    tags: Vec<String>,
+    app_handle: AppHandle,
+) -> Result<SavedHost, AppError> {
+    Ok(update_host_tags(&host_id, &app_handle, |current| {
+        current.retain(|t| !tags.iter().any(|r| r.trim() == t))
+    })?)
+}
+
+/// Every tag in use with the number of hosts carrying it, by name.
+#[tauri::command]
+pub fn list_tags(app_handle: AppHandle) -> Result<Vec<TagCount>, AppError> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for host in load_saved_hosts(app_handle)? {
+        for tag in host.tags {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    Ok(counts
+        .into_iter()
+        .map(|(tag, hosts)| TagCount { tag, hosts })
+        .collect())
+}
+
+/// Renames a tag on every host in a single write. Renaming onto an existing
+/// tag merges the two.
+#[tauri::command]
+pub fn rename_tag(
+    old_tag: String,
+    new_tag: String,
+    app_handle: AppHandle,
+) -> Result<usize, AppError> {
+    let new_tag = new_tag.trim().to_string();
+    if new_tag.is_empty() {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "Tag cannot be empty",
+        ));
+    }
+    let _lock = lock_saved_hosts(&app_handle)?;
+    let mut hosts = load_saved_hosts(app_handle.clone())?;
+    let mut renamed = 0;
+    for host in hosts.iter_mut() {
+        if !host.tags.contains(&old_tag) {
+            continue;
+        }
+        let tags = std::mem::take(&mut host.tags)
+            .into_iter()
+            .map(|t| if t == old_tag { new_tag.clone() } else { t })
+            .collect();
+        host.tags = normalize(tags);
+        renamed += 1;
+    }
+    if renamed > 0 {
+        write_saved_hosts(&app_handle, &hosts)?;
+        info!(target = "tags", from = %old_tag, to = %new_tag, hosts = renamed, "Renamed tag");
+    }
+    Ok(renamed)
+}
@@ -0,0 +1,405 @@
+// Telnet sessions for legacy devices that do not speak SSH.
+//
+// Only the options a terminal needs are negotiated (ECHO, SGA, NAWS and
+// TTYPE); everything else is refused. IAC sequences are stripped from the
+// stream before it reaches the terminal.
+
+use crate::session_meta::{SessionLabel, SessionMeta};
+use crate::error::{AppError, ErrorKind};
+use crate::output::OutputPipeline;
+use crate::{
+    emit_session_opened, history, AppState, ConnectionLog, SessionClosedPayload, SessionSetup,
+    SessionState, SessionTarget, SessionTransport,
+};
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{async_runtime, AppHandle, State, Window};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const OPT_ECHO: u8 = 1;
+const OPT_SGA: u8 = 3;
+const OPT_TTYPE: u8 = 24;
+const OPT_NAWS: u8 = 31;
+
+const TTYPE_IS: u8 = 0;
+const TTYPE_SEND: u8 = 1;
+
+// Read timeout so the reader notices a closed session
+const IDLE_POLL: Duration = Duration::from_millis(200);
+
+// Subnegotiations longer than this are dropped
+const MAX_SB_LEN: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseState {
+    Data,
+    Iac,
+    Option(u8),
+    Sub,
+    SubIac,
+    Cr,
+}
+
+pub struct TelnetState {
+    parse_state: ParseState,
+    sub_buffer: Vec<u8>,
+    // Options we agreed to perform / the server agreed to perform
+    local: HashSet<u8>,
+    remote: HashSet<u8>,
+    terminal_type: String,
+    window_size: (u16, u16), // (cols, rows)
+}
+
+impl TelnetState {
+    pub fn new(terminal_type: String) -> Self {
+        Self {
+            parse_state: ParseState::Data,
+            sub_buffer: Vec::new(),
+            local: HashSet::new(),
+            remote: HashSet::new(),
+            terminal_type,
+            window_size: (80, 24),
+        }
+    }
+
+    /// Options we offer up front so the server does not have to ask.
+    pub fn initial_negotiation(&mut self) -> Vec<u8> {
+        self.local.insert(OPT_NAWS);
+        self.remote.insert(OPT_SGA);
+        vec![IAC, DO, OPT_SGA, IAC, WILL, OPT_NAWS]
+    }
+
+    /// Splits incoming bytes into terminal data and negotiation replies.
+    pub fn feed(&mut self, input: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut data = Vec::with_capacity(input.len());
+        let mut replies = Vec::new();
+
+        for &byte in input {
+            match self.parse_state {
+                ParseState::Data => match byte {
+                    IAC => self.parse_state = ParseState::Iac,
+                    b'\r' => {
+                        data.push(byte);
+                        self.parse_state = ParseState::Cr;
+                    }
+                    _ => data.push(byte),
+                },
+                ParseState::Cr => {
+                    // CR NUL is a bare carriage return
+                    self.parse_state = ParseState::Data;
+                    match byte {
+                        0 => {}
+                        IAC => self.parse_state = ParseState::Iac,
+                        _ => data.push(byte),
+                    }
+                }
+                ParseState::Iac => match byte {
+                    IAC => {
+                        data.push(IAC);
+                        self.parse_state = ParseState::Data;
+                    }
+                    DO | DONT | WILL | WONT => self.parse_state = ParseState::Option(byte),
+                    SB => {
+                        self.sub_buffer.clear();
+                        self.parse_state = ParseState::Sub;
+                    }
+                    // NOP, GA, AYT and friends carry no data
+                    _ => self.parse_state = ParseState::Data,
+                },
+                ParseState::Option(verb) => {
+                    self.negotiate(verb, byte, &mut replies);
+                    self.parse_state = ParseState::Data;
+                }
+                ParseState::Sub => match byte {
+                    IAC => self.parse_state = ParseState::SubIac,
+                    _ => {
+                        if self.sub_buffer.len() < MAX_SB_LEN {
+                            self.sub_buffer.push(byte);
+                        }
+                    }
+                },
+                ParseState::SubIac => match byte {
+                    SE => {
+                        self.subnegotiate(&mut replies);
+                        self.parse_state = ParseState::Data;
+                    }
+                    IAC => {
+                        if self.sub_buffer.len() < MAX_SB_LEN {
+                            self.sub_buffer.push(IAC);
+                        }
+                        self.parse_state = ParseState::Sub;
+                    }
+                    _ => self.parse_state = ParseState::Sub,
+                },
+            }
+        }
+
+        (data, replies)
+    }
+
+    fn negotiate(&mut self, verb: u8, option: u8, replies: &mut Vec<u8>) {
+        match verb {
+            WILL => {
+                let accept = matches!(option, OPT_ECHO | OPT_SGA);
+                if accept {
+                    if self.remote.insert(option) {
+                        replies.extend_from_slice(&[IAC, DO, option]);
+                    }
+                } else {
+                    replies.extend_from_slice(&[IAC, DONT, option]);
+                }
+            }
+            DO => {
+                let accept = matches!(option, OPT_NAWS | OPT_TTYPE);
+                if accept {
+                    if self.local.insert(option) {
+                        replies.extend_from_slice(&[IAC, WILL, option]);
+                    }
+                    if option == OPT_NAWS {
+                        replies.extend_from_slice(&self.naws_message());
+                    }
+                } else {
+                    replies.extend_from_slice(&[IAC, WONT, option]);
+                }
+            }
+            WONT | DONT => {
+                let (enabled, reply) = if verb == WONT {
+                    (&mut self.remote, DONT)
+                } else {
+                    (&mut self.local, WONT)
+                };
+                // Only acknowledge actual state changes to avoid loops
+                if enabled.remove(&option) {
+                    replies.extend_from_slice(&[IAC, reply, option]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn subnegotiate(&mut self, replies: &mut Vec<u8>) {
+        if self.sub_buffer.first() == Some(&OPT_TTYPE)
+            && self.sub_buffer.get(1) == Some(&TTYPE_SEND)
+        {
+            replies.extend_from_slice(&[IAC, SB, OPT_TTYPE, TTYPE_IS]);
+            replies.extend_from_slice(self.terminal_type.as_bytes());
+            replies.extend_from_slice(&[IAC, SE]);
+        }
+        self.sub_buffer.clear();
+    }
+
+    fn naws_message(&self) -> Vec<u8> {
+        let (cols, rows) = self.window_size;
+        let mut message = vec![IAC, SB, OPT_NAWS];
+        for byte in cols.to_be_bytes().into_iter().chain(rows.to_be_bytes()) {
+            // A literal 255 inside a subnegotiation must be doubled
+            message.push(byte);
+            if byte == IAC {
+                message.push(IAC);
+            }
+        }
+        message.extend_from_slice(&[IAC, SE]);
+        message
+    }
+
+    /// Records the new size, returning the NAWS update if the server wants it.
+    pub fn resize(&mut self, cols: u32, rows: u32) -> Option<Vec<u8>> {
+        self.window_size = (
+            cols.min(u16::MAX as u32) as u16,
+            rows.min(u16::MAX as u32) as u16,
+        );
+        if self.local.contains(&OPT_NAWS) {
+            Some(self.naws_message())
+        } else {
+            None
+        }
+    }
+}
+
+/// Escapes IAC bytes and bare carriage returns in user input.
+pub fn encode_input(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 4);
+    for (i, &byte) in data.iter().enumerate() {
+        match byte {
+            IAC => out.extend_from_slice(&[IAC, IAC]),
+            b'\r' => {
+                out.push(b'\r');
+                if data.get(i + 1) != Some(&b'\n') {
+                    out.push(0);
+                }
+            }
+            _ => out.push(byte),
+        }
+    }
+    out
+}
+
+#[tauri::command]
+pub async fn connect_telnet(
+    host: String,
+    port: Option<u16>,
+    terminal_type: Option<String>,
+    // Title and color to show, see session_meta
+    label: Option<SessionLabel>,
+    state: State<'_, AppState>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<String, AppError> {
+    let meta = Arc::new(SessionMeta::new(label)?);
+    let sessions = state.sessions.clone();
+    let batch_settings = state.output_batching.clone();
+    let scrollback_limit = state.scrollback_limit.load(Ordering::Relaxed);
+    let terminal_type = terminal_type.unwrap_or_else(|| state.settings.get().default_terminal_type);
+
+    let port = port.unwrap_or(23);
+    let attempt = history::Attempt::start(
+        &app_handle,
+        ConnectionLog {
+            port: Some(port),
+            ..ConnectionLog::new(&host, "", "telnet")
+        },
+    );
+
+    async_runtime::spawn_blocking(move || {
+        let addr = format!("{}:{}", host, port);
+        info!(target = "telnet", %addr, "Connecting telnet");
+
+        let socket_addr = addr
+            .to_socket_addrs()
+            .map_err(|e| attempt.fail("Connect", AppError::from(e).or_kind(ErrorKind::ConnectionFailed)))?
+            .next()
+            .ok_or_else(|| {
+                attempt.fail("Connect", AppError::new(ErrorKind::ConnectionFailed, format!("Could not resolve {}", host)))
+            })?;
+        let stream = TcpStream::connect_timeout(&socket_addr, Duration::from_secs(10)).map_err(|e| {
+            error!(target = "telnet", error = %e, "TCP connect failed");
+            attempt.fail("Connect", AppError::from(e).or_kind(ErrorKind::ConnectionFailed))
+        })?;
+        stream
+            .set_read_timeout(Some(IDLE_POLL))
+            .map_err(|e| attempt.fail("Connect", e))?;
+        let _ = stream.set_nodelay(true);
+        let mut reader = stream
+            .try_clone()
+            .map_err(|e| attempt.fail("Connect", e))?;
+
+        let mut telnet = TelnetState::new(terminal_type);
+        let mut writer = stream;
+        writer
+            .write_all(&telnet.initial_negotiation())
+            .map_err(|e| attempt.fail("Negotiation", e))?;
+
+        attempt.succeed(None);
+
+        let session_id = Uuid::new_v4();
+        let writer_arc = Arc::new(Mutex::new(writer));
+        let telnet_arc = Arc::new(Mutex::new(telnet));
+        let reader_target = SessionTarget {
+            host: host.clone(),
+            history_id: attempt.id(),
+            connected_at: Some(Instant::now()),
+            ..SessionTarget::default()
+        };
+
+        let session = SessionState::new(
+            session_id,
+            &window,
+            SessionSetup {
+                transport: SessionTransport::Telnet {
+                    stream: writer_arc.clone(),
+                    telnet: telnet_arc.clone(),
+                },
+                target: reader_target.clone(),
+                meta,
+                encoding: encoding_rs::UTF_8,
+                scrollback_limit,
+                idle_after_secs: None,
+                disconnect_after_secs: None,
+                read_only: false,
+                guard_exempt: false,
+                startup: None,
+            },
+        );
+        let reader_ctx = session.reader_context(session_id, &window);
+        let shutdown_arc = session.shutdown.clone();
+        sessions.insert(session_id, session);
+
+        let reader_sessions = sessions.clone();
+        let reader_shutdown = shutdown_arc.clone();
+        let reader = thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            let mut pipeline = OutputPipeline::new(reader_ctx, batch_settings);
+            let reason = loop {
+                if reader_shutdown.is_requested() || !pipeline.wait_if_paused() {
+                    return;
+                }
+                // Wake up in time to flush a pending batch
+                let timeout = pipeline.idle_timeout(IDLE_POLL).max(Duration::from_millis(1));
+                let _ = reader.set_read_timeout(Some(timeout));
+                match reader.read(&mut buffer) {
+                    Ok(0) => break "connection closed".to_string(),
+                    Ok(bytes_read) => {
+                        let (data, replies) = match telnet_arc.lock() {
+                            Ok(mut telnet) => telnet.feed(&buffer[..bytes_read]),
+                            Err(_) => break "telnet state poisoned".to_string(),
+                        };
+                        if !replies.is_empty() {
+                            if let Ok(mut writer) = writer_arc.lock() {
+                                let _ = writer.write_all(&replies);
+                            }
+                        }
+                        pipeline.push(&data);
+                    }
+                    Err(e)
+                        if e.kind() == std::io::ErrorKind::WouldBlock
+                            || e.kind() == std::io::ErrorKind::TimedOut =>
+                    {
+                        pipeline.poll();
+                        if !reader_sessions.contains_key(&session_id) {
+                            return;
+                        }
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        warn!(target = "telnet", session = %pipeline.ctx.session_id, error = %e, "Telnet read failed");
+                        break e.to_string();
+                    }
+                }
+            };
+
+            pipeline.flush();
+            info!(target = "telnet", session = %pipeline.ctx.session_id, %reason, "Telnet session ended");
+            if reader_sessions.remove(&session_id).is_some() {
+                history::finish_session(&app_handle, &reader_target, &reason);
+                pipeline.ctx.emit(
+                    "session-closed",
+                    SessionClosedPayload {
+                        session_id: pipeline.ctx.session_id.clone(),
+                        reason,
+                    },
+                );
+            }
+        });
+        shutdown_arc.set_reader(reader);
+
+        info!(target = "telnet", session = %session_id, "Telnet session established");
+        emit_session_opened(&window, &session_id.to_string());
+        Ok(session_id.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
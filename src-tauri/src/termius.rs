@@ -0,0 +1,204 @@
+// Importing hosts from a Termius CSV export.
+//
+// Columns are matched by header name, loosely, since the export layout has
+// changed between Termius versions. Termius groups become tags; the hosts
+// themselves go into a "Termius" group. Passwords are never imported.
+
+use crate::error::{AppError, ErrorKind};
+use crate::host_import::{build_preview, local_username, ImportPreview, ImportWarning};
+use crate::{tags, ConnectionDetails, SavedHost};
+use std::collections::HashMap;
+use std::fs;
+use tauri::AppHandle;
+use uuid::Uuid;
+
+const GROUP: &str = "Termius";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Name,
+    Host,
+    Port,
+    Username,
+    Key,
+    Group,
+    Tags,
+    Password,
+}
+
+fn column_for(header: &str) -> Option<Column> {
+    let normalized: String = header
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let column = match normalized.as_str() {
+        "label" | "name" | "alias" => Column::Name,
+        "hostname" | "host" | "hostnameip" | "address" | "ip" => Column::Host,
+        "port" => Column::Port,
+        "username" | "user" => Column::Username,
+        "key" | "sshkey" | "keypath" | "privatekey" | "identityfile" => Column::Key,
+        "group" | "groups" => Column::Group,
+        "tags" | "tag" => Column::Tags,
+        "password" => Column::Password,
+        _ => return None,
+    };
+    Some(column)
+}
+
+/// Splits CSV into records (RFC 4180: quoted fields, doubled quotes, and
+/// newlines inside quotes). Returns each record with its starting line.
+fn parse_csv(content: &str) -> Vec<(usize, Vec<String>)> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = content.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push((record_line, std::mem::take(&mut record)));
+                line += 1;
+                record_line = line;
+            }
+            c => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((record_line, record));
+    }
+    records.retain(|(_, r)| r.iter().any(|f| !f.trim().is_empty()));
+    records
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split([',', ';'])
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// Previews hosts from a Termius CSV export.
+#[tauri::command]
+pub fn import_termius_csv(path: String, app_handle: AppHandle) -> Result<ImportPreview, AppError> {
+    let content = fs::read_to_string(&path)?;
+    let mut records = parse_csv(&content).into_iter();
+    let (_, headers) = records.next().ok_or("The CSV file is empty")?;
+
+    let columns: Vec<Option<Column>> = headers.iter().map(|h| column_for(h)).collect();
+    if !columns.contains(&Some(Column::Host)) {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            "The CSV file has no hostname column",
+        ));
+    }
+    let mut warnings = Vec::new();
+    let ignored: Vec<&str> = headers
+        .iter()
+        .zip(&columns)
+        .filter(|(h, c)| c.is_none() && !h.trim().is_empty())
+        .map(|(h, _)| h.as_str())
+        .collect();
+    if !ignored.is_empty() {
+        warnings.push(ImportWarning {
+            source: path.clone(),
+            line: Some(1),
+            text: ignored.join(", "),
+            reason: "Columns not imported".to_string(),
+        });
+    }
+
+    let mut hosts = Vec::new();
+    for (line, record) in records {
+        let value = |column: Column| {
+            columns
+                .iter()
+                .position(|c| *c == Some(column))
+                .and_then(|i| record.get(i))
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        // Rows are identified by name, never echoed whole: they may hold a password
+        let label = value(Column::Name)
+            .or_else(|| value(Column::Host))
+            .unwrap_or_default();
+        let mut warn = |reason: &str| {
+            warnings.push(ImportWarning {
+                source: path.clone(),
+                line: Some(line),
+                text: label.clone(),
+                reason: reason.to_string(),
+            })
+        };
+
+        let Some(host) = value(Column::Host) else {
+            warn("Row has no hostname");
+            continue;
+        };
+        let port = match value(Column::Port).map(|p| p.parse::<u16>()) {
+            Some(Ok(port)) => Some(port),
+            Some(Err(_)) => {
+                warn("Invalid port, using the default");
+                None
+            }
+            None => None,
+        };
+        if value(Column::Password).is_some() {
+            warn("Password not imported");
+        }
+
+        let mut tags = value(Column::Group)
+            .map(|g| split_list(&g))
+            .unwrap_or_default();
+        tags.extend(
+            value(Column::Tags)
+                .map(|t| split_list(&t))
+                .unwrap_or_default(),
+        );
+        hosts.push(SavedHost {
+            id: Uuid::new_v4().to_string(),
+            name: value(Column::Name).unwrap_or_else(|| host.clone()),
+            group: Some(GROUP.to_string()),
+            tags: tags::normalize(tags),
+            details: ConnectionDetails {
+                host,
+                port,
+                username: value(Column::Username).unwrap_or_else(local_username),
+                private_key_path: value(Column::Key),
+                ..Default::default()
+            },
+            has_password: false,
+            has_passphrase: false,
+            has_totp_secret: false,
+            pinned: false,
+            sort_order: 0,
+            notes: None,
+            color: None,
+            metadata: HashMap::new(),
+            mac_address: None,
+            broadcast_address: None,
+            reachability_disabled: false,
+            tunnels: Vec::new(),
+        });
+    }
+
+    Ok(build_preview(hosts, warnings, app_handle)?)
+}
@@ -0,0 +1,224 @@
+// TOTP codes for hosts whose login asks for a second factor.
+//
+// A host's base32 secret lives in the credential store next to its password
+// (SecretKind::Totp); connections.json only records that one exists, and
+// neither the secret nor a code is ever logged. generate_totp shows the
+// current code. With totp_autofill on for the host, keyboard-interactive
+// logins answer prompts matching the totp_prompt_patterns setting with a
+// code and password prompts with the stored password.
+//
+// Codes use the RFC 6238 defaults: HMAC-SHA1, 30 second steps, 6 digits.
+// Servers accept a step either side of their own, so close to the end of a
+// step the next code is sent rather than one that may expire in transit,
+// and if the server asks again the neighbouring steps are tried once each.
+
+use crate::credentials::{self, SecretKind};
+use crate::error::{AppError, ErrorKind};
+use crate::ConnectionDetails;
+use hmac::{Hmac, Mac};
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use sha1::Sha1;
+use ssh2::{KeyboardInteractivePrompt, Prompt};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+const PERIOD: u64 = 30;
+const DIGITS: u32 = 6;
+// With fewer seconds left in the step, the next step's code is sent
+const SKEW_MARGIN_SECS: u64 = 3;
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// The default for the totp_prompt_patterns setting.
+pub fn default_prompt_patterns() -> Vec<String> {
+    [
+        r"verification code",
+        r"\botp\b",
+        r"one-time (?:password|code)",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect()
+}
+
+// Prompt patterns match case-insensitively
+fn compile(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+pub fn validate_patterns(patterns: &[String]) -> Result<(), String> {
+    for pattern in patterns {
+        compile(pattern).map_err(|e| format!("Invalid TOTP prompt pattern {}: {}", pattern, e))?;
+    }
+    Ok(())
+}
+
+fn decode_base32(secret: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(secret.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u64, 0u32);
+    for c in secret.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|&a| a == c)? as u64;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// Checks a secret as typed or pasted: spaces, dashes and padding are
+/// dropped and case is ignored. Returns it in canonical form.
+pub fn normalize_secret(secret: &str) -> Result<String, String> {
+    let normalized: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    match decode_base32(&normalized) {
+        Some(key) if !key.is_empty() => Ok(normalized),
+        _ => Err("The TOTP secret must be base32".to_string()),
+    }
+}
+
+fn code_at(key: &[u8], step: u64) -> String {
+    // HMAC takes keys of any length
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        value % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn stored_key(host_id: &str) -> Result<Option<Vec<u8>>, String> {
+    let Some(secret) = credentials::load(host_id, SecretKind::Totp)? else {
+        return Ok(None);
+    };
+    decode_base32(&normalize_secret(&secret)?)
+        .map(Some)
+        .ok_or_else(|| "The stored TOTP secret is not base32".to_string())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TotpCode {
+    pub code: String,
+    // Until the code changes
+    pub seconds_remaining: u64,
+    pub period: u64,
+}
+
+/// Returns the current TOTP code of a saved host, and how long it stays
+/// valid.
+#[tauri::command]
+pub fn generate_totp(host_id: String) -> Result<TotpCode, AppError> {
+    let key = stored_key(&host_id)?.ok_or_else(|| {
+        AppError::new(
+            ErrorKind::NotFound,
+            "No TOTP secret is stored for this host",
+        )
+    })?;
+    let now = now();
+    Ok(TotpCode {
+        code: code_at(&key, now / PERIOD),
+        seconds_remaining: PERIOD - now % PERIOD,
+        period: PERIOD,
+    })
+}
+
+/// Answers keyboard-interactive prompts for a login: codes for prompts
+/// matching the TOTP patterns, the password for password prompts and an
+/// empty answer for anything else.
+pub struct Prompter<'a> {
+    password: Option<&'a str>,
+    key: Option<Vec<u8>>,
+    patterns: Vec<Regex>,
+    // Steps still to try, filled at the first code prompt
+    steps: Option<Vec<u64>>,
+}
+
+impl<'a> Prompter<'a> {
+    /// Answers codes only when the details have totp_autofill on and carry
+    /// the secret.
+    pub fn new(details: &'a ConnectionDetails, patterns: &[String]) -> Self {
+        let key = details
+            .totp_secret
+            .as_deref()
+            .filter(|_| details.totp_autofill == Some(true))
+            .and_then(|secret| normalize_secret(secret).ok())
+            .and_then(|secret| decode_base32(&secret));
+        Self {
+            password: details.password.as_deref(),
+            key,
+            // validate() already rejected bad patterns, this only skips them
+            patterns: patterns.iter().filter_map(|p| compile(p).ok()).collect(),
+            steps: None,
+        }
+    }
+
+    pub fn answers_codes(&self) -> bool {
+        self.key.is_some()
+    }
+
+    fn next_code(&mut self) -> Option<String> {
+        let key = self.key.as_ref()?;
+        let steps = self.steps.get_or_insert_with(|| {
+            let now = now();
+            let current = now / PERIOD;
+            let previous = current.saturating_sub(1);
+            let order = if PERIOD - now % PERIOD < SKEW_MARGIN_SECS {
+                [current + 1, current, previous]
+            } else {
+                [current, previous, current + 1]
+            };
+            // Popped from the end
+            order.into_iter().rev().collect()
+        });
+        steps.pop().map(|step| code_at(key, step))
+    }
+}
+
+impl KeyboardInteractivePrompt for Prompter<'_> {
+    fn prompt<'b>(
+        &mut self,
+        _username: &str,
+        _instructions: &str,
+        prompts: &[Prompt<'b>],
+    ) -> Vec<String> {
+        prompts
+            .iter()
+            .map(|prompt| {
+                if self.patterns.iter().any(|p| p.is_match(&prompt.text)) {
+                    let code = self.next_code();
+                    match code {
+                        Some(_) => info!(target = "totp", "Answering a verification code prompt"),
+                        None => warn!(target = "totp", "No TOTP code left to answer a prompt"),
+                    }
+                    code.unwrap_or_default()
+                } else if prompt.text.to_lowercase().contains("password") {
+                    self.password.unwrap_or_default().to_string()
+                } else {
+                    warn!(target = "totp", prompt = %prompt.text, "Unrecognized keyboard-interactive prompt");
+                    String::new()
+                }
+            })
+            .collect()
+    }
+}
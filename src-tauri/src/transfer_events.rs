@@ -0,0 +1,231 @@
+// "transfer-completed" and "transfer-failed" events.
+//
+// Progress events alone can't say a transfer ended: a zero-byte file never
+// shows progress and a failed one stops short, so the frontend was left with
+// progress bars that never went away. Every transfer command wraps its work in
+// a Transfer, which emits exactly one of the two events: finish() reports the
+// outcome, and dropping it unfinished reports a failure. A completed transfer
+// may carry warnings about what went wrong without failing it.
+
+use crate::error::{AppError, ErrorKind};
+use crate::ownership::SessionOwners;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tauri::Window;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize)]
+struct TransferCompletedPayload {
+    transfer_id: String,
+    session_id: String,
+    source_path: String,
+    destination_path: String,
+    bytes: u64,
+    duration_ms: u64,
+    warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TransferFailedPayload {
+    transfer_id: String,
+    session_id: String,
+    source_path: String,
+    destination_path: String,
+    // Bytes moved before the failure
+    bytes: u64,
+    duration_ms: u64,
+    error: AppError,
+}
+
+// Sent as "transfer-completed" or "transfer-failed"
+enum TransferEnded {
+    Completed(TransferCompletedPayload),
+    Failed(TransferFailedPayload),
+}
+
+pub struct Transfer {
+    sink: Box<dyn Fn(TransferEnded) + Send + Sync>,
+    transfer_id: String,
+    session_id: String,
+    source_path: String,
+    destination_path: String,
+    started: Instant,
+    bytes: Arc<AtomicU64>,
+    warnings: Vec<String>,
+    finished: bool,
+}
+
+impl Transfer {
+    /// Starts tracking a transfer; the frontend may pass its own id to match
+    /// the events against, otherwise one is generated.
+    pub fn start(
+        window: &Window,
+        owners: Arc<SessionOwners>,
+        transfer_id: Option<String>,
+        session_id: &str,
+        source_path: &str,
+        destination_path: &str,
+    ) -> Self {
+        let window = window.clone();
+        Self::start_with(
+            move |ended| match ended {
+                TransferEnded::Completed(payload) => {
+                    owners.emit(&window, "transfer-completed", payload)
+                }
+                TransferEnded::Failed(payload) => owners.emit(&window, "transfer-failed", payload),
+            },
+            transfer_id,
+            session_id,
+            source_path,
+            destination_path,
+        )
+    }
+
+    fn start_with(
+        sink: impl Fn(TransferEnded) + Send + Sync + 'static,
+        transfer_id: Option<String>,
+        session_id: &str,
+        source_path: &str,
+        destination_path: &str,
+    ) -> Self {
+        Self {
+            sink: Box::new(sink),
+            transfer_id: transfer_id.unwrap_or_else(|| Uuid::new_v4().to_string()),
+            session_id: session_id.to_string(),
+            source_path: source_path.to_string(),
+            destination_path: destination_path.to_string(),
+            started: Instant::now(),
+            bytes: Arc::new(AtomicU64::new(0)),
+            warnings: Vec::new(),
+            finished: false,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.transfer_id
+    }
+
+    /// Where the copy loop records the bytes moved so far.
+    pub fn bytes(&self) -> Arc<AtomicU64> {
+        self.bytes.clone()
+    }
+
+    /// Adds a warning to the "transfer-completed" event.
+    pub fn warn(&mut self, warning: String) {
+        self.warnings.push(warning);
+    }
+
+    pub fn finish(mut self, result: &Result<(), AppError>) {
+        self.emit(result.as_ref().err().cloned());
+    }
+
+    fn emit(&mut self, error: Option<AppError>) {
+        self.finished = true;
+        let transfer_id = self.transfer_id.clone();
+        let session_id = self.session_id.clone();
+        let source_path = self.source_path.clone();
+        let destination_path = self.destination_path.clone();
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let duration_ms = self.started.elapsed().as_millis() as u64;
+        (self.sink)(match error {
+            None => TransferEnded::Completed(TransferCompletedPayload {
+                transfer_id,
+                session_id,
+                source_path,
+                destination_path,
+                bytes,
+                duration_ms,
+                warnings: std::mem::take(&mut self.warnings),
+            }),
+            Some(error) => TransferEnded::Failed(TransferFailedPayload {
+                transfer_id,
+                session_id,
+                source_path,
+                destination_path,
+                bytes,
+                duration_ms,
+                error,
+            }),
+        });
+    }
+}
+
+// A command future dropped before it finished, or a panic in the copy
+impl Drop for Transfer {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.emit(Some(AppError::new(
+                ErrorKind::Cancelled,
+                "The transfer ended before it finished",
+            )));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // The terminal events a transfer emitted: (completed, bytes, error kind)
+    type Events = Arc<Mutex<Vec<(bool, u64, Option<ErrorKind>)>>>;
+
+    fn recording() -> (Transfer, Events) {
+        let events: Events = Arc::default();
+        let sink = events.clone();
+        let transfer = Transfer::start_with(
+            move |ended| {
+                sink.lock().unwrap().push(match ended {
+                    TransferEnded::Completed(p) => (true, p.bytes, None),
+                    TransferEnded::Failed(p) => (false, p.bytes, Some(p.error.kind)),
+                })
+            },
+            Some("t".to_string()),
+            "s",
+            "/src",
+            "/dst",
+        );
+        (transfer, events)
+    }
+
+    #[test]
+    fn zero_byte_transfer_completes_once() {
+        let (transfer, events) = recording();
+        transfer.finish(&Ok(()));
+        assert_eq!(*events.lock().unwrap(), vec![(true, 0, None)]);
+    }
+
+    #[test]
+    fn error_mid_transfer_fails_once() {
+        let (transfer, events) = recording();
+        transfer.bytes().store(4096, Ordering::Relaxed);
+        transfer.finish(&Err(AppError::new(ErrorKind::ConnectionLost, "gone")));
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![(false, 4096, Some(ErrorKind::ConnectionLost))]
+        );
+    }
+
+    #[test]
+    fn cancelled_transfer_fails_once() {
+        let (transfer, events) = recording();
+        transfer.finish(&Err(AppError::new(ErrorKind::Cancelled, "Cancelled")));
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![(false, 0, Some(ErrorKind::Cancelled))]
+        );
+    }
+
+    #[test]
+    fn dropping_an_unfinished_transfer_reports_a_failure() {
+        let (transfer, events) = recording();
+        transfer.bytes().store(10, Ordering::Relaxed);
+        drop(transfer);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![(false, 10, Some(ErrorKind::Cancelled))]
+        );
+    }
+}
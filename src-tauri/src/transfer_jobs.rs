@@ -0,0 +1,484 @@
+// Transfer jobs kept on disk, so a transfer cut short by a crash can resume.
+//
+// upload_file and download_file record each transfer in transfers.json:
+// where it goes, over which host, its retry policy and the bytes confirmed so
+// far. A checkpoint thread writes the progress of running jobs every few
+// seconds. A job still marked running at startup was interrupted.
+// resume_transfer continues an interrupted or failed job from its confirmed
+// offset. It reuses a session to the job's saved host, or connects one, and
+// first checks that the files still fit. Completed and abandoned jobs are
+// dropped at startup once older than transfer_retention_days.
+
+use crate::error::{AppError, ErrorKind};
+use crate::retry::RetryPolicy;
+use crate::session_meta::SessionLabel;
+use crate::transfer_events::Transfer;
+use crate::{
+    config_file, connect_ssh, download, get_config_dir, load_saved_hosts, ownership, sftp_ops,
+    sftp_timeout, upload, AppState,
+};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State, Window};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(2);
+
+// Byte counters of the jobs running in this process, by job id
+static ACTIVE: LazyLock<DashMap<String, Arc<AtomicU64>>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferDirection {
+    Upload,
+    Download,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    // The app stopped while it was running
+    Interrupted,
+    Failed,
+    Completed,
+    Abandoned,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferJob {
+    // Also the transfer id of its events
+    pub id: String,
+    pub direction: TransferDirection,
+    // The session it last ran on
+    pub session_id: String,
+    pub host_id: Option<String>,
+    pub host: String,
+    pub username: String,
+    pub source_path: String,
+    pub destination_path: String,
+    // Known up front for uploads only
+    pub total_bytes: Option<u64>,
+    pub confirmed_bytes: u64,
+    pub retry: Option<RetryPolicy>,
+    pub status: JobStatus,
+    pub error: Option<String>,
+    pub started_at: u64, // Unix timestamp
+    pub updated_at: u64,
+}
+
+impl TransferJob {
+    fn resumable(&self) -> bool {
+        matches!(self.status, JobStatus::Interrupted | JobStatus::Failed)
+    }
+}
+
+pub(crate) struct JobSpec<'a> {
+    pub id: &'a str,
+    pub direction: TransferDirection,
+    pub session_id: &'a str,
+    pub source_path: &'a str,
+    pub destination_path: &'a str,
+    pub total_bytes: Option<u64>,
+    pub retry: Option<RetryPolicy>,
+}
+
+fn get_jobs_path() -> Result<PathBuf, String> {
+    Ok(get_config_dir()?.join("transfers.json"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn load_jobs(app_handle: &AppHandle) -> Result<Vec<TransferJob>, String> {
+    Ok(config_file::load(app_handle, &get_jobs_path()?)?.unwrap_or_default())
+}
+
+// Loads, changes and writes the jobs under the file lock; `update` says
+// whether anything changed
+fn update_jobs<T>(
+    app_handle: &AppHandle,
+    update: impl FnOnce(&mut Vec<TransferJob>) -> (T, bool),
+) -> Result<T, String> {
+    let path = get_jobs_path()?;
+    let _lock = config_file::lock(&path)?;
+    let mut jobs = load_jobs(app_handle)?;
+    let (result, changed) = update(&mut jobs);
+    if changed {
+        config_file::write(&path, &jobs)?;
+    }
+    Ok(result)
+}
+
+/// Records a starting transfer; `bytes` is read by the checkpoint thread.
+/// Failures are logged, the transfer goes ahead regardless.
+pub(crate) fn begin(app_handle: &AppHandle, spec: JobSpec, bytes: Arc<AtomicU64>) {
+    let state = app_handle.state::<AppState>();
+    let target = Uuid::parse_str(spec.session_id)
+        .ok()
+        .and_then(|uuid| state.sessions.get(&uuid).map(|s| s.target.clone()))
+        .unwrap_or_default();
+    let at = now();
+    let job = TransferJob {
+        id: spec.id.to_string(),
+        direction: spec.direction,
+        session_id: spec.session_id.to_string(),
+        host_id: target.host_id,
+        host: target.host,
+        username: target.username,
+        source_path: spec.source_path.to_string(),
+        destination_path: spec.destination_path.to_string(),
+        total_bytes: spec.total_bytes,
+        confirmed_bytes: bytes.load(Ordering::Relaxed),
+        retry: spec.retry,
+        status: JobStatus::Running,
+        error: None,
+        started_at: at,
+        updated_at: at,
+    };
+    let result = update_jobs(app_handle, |jobs| {
+        let mut job = job;
+        // A resumed job keeps its original start
+        if let Some(old) = jobs.iter().find(|j| j.id == job.id) {
+            job.started_at = old.started_at;
+        }
+        jobs.retain(|j| j.id != job.id);
+        jobs.push(job);
+        ((), true)
+    });
+    match result {
+        Ok(()) => {
+            ACTIVE.insert(spec.id.to_string(), bytes);
+        }
+        Err(e) => {
+            warn!(target = "transfer_jobs", job = %spec.id, error = %e, "Failed to record transfer")
+        }
+    }
+}
+
+/// Records how a transfer begun with `begin` ended.
+pub(crate) fn finish(app_handle: &AppHandle, id: &str, result: &Result<(), AppError>) {
+    let Some((_, bytes)) = ACTIVE.remove(id) else {
+        return;
+    };
+    let confirmed = bytes.load(Ordering::Relaxed);
+    let result = update_jobs(app_handle, |jobs| {
+        let Some(job) = jobs.iter_mut().find(|j| j.id == id) else {
+            return ((), false);
+        };
+        job.confirmed_bytes = confirmed;
+        job.updated_at = now();
+        match result {
+            Ok(()) => {
+                job.status = JobStatus::Completed;
+                job.error = None;
+            }
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(e.message.clone());
+            }
+        }
+        ((), true)
+    });
+    if let Err(e) = result {
+        warn!(target = "transfer_jobs", job = %id, error = %e, "Failed to record transfer outcome");
+    }
+}
+
+fn checkpoint(app_handle: &AppHandle) -> Result<(), String> {
+    if ACTIVE.is_empty() {
+        return Ok(());
+    }
+    update_jobs(app_handle, |jobs| {
+        let mut changed = false;
+        for job in jobs.iter_mut() {
+            let Some(bytes) = ACTIVE.get(&job.id) else {
+                continue;
+            };
+            let confirmed = bytes.load(Ordering::Relaxed);
+            if confirmed != job.confirmed_bytes {
+                job.confirmed_bytes = confirmed;
+                job.updated_at = now();
+                changed = true;
+            }
+        }
+        ((), changed)
+    })
+}
+
+/// Marks jobs left running by the last run as interrupted, drops old
+/// finished ones and starts the checkpoint thread. Called from setup after
+/// the settings are loaded; failures are only logged.
+pub fn init(app_handle: &AppHandle) {
+    let retention_secs = u64::from(
+        app_handle
+            .state::<AppState>()
+            .settings
+            .get()
+            .transfer_retention_days,
+    ) * 86_400;
+    let cutoff = now().saturating_sub(retention_secs);
+    let result = update_jobs(app_handle, |jobs| {
+        let before = jobs.len();
+        jobs.retain(|j| {
+            !matches!(j.status, JobStatus::Completed | JobStatus::Abandoned)
+                || j.updated_at >= cutoff
+        });
+        let mut interrupted = 0;
+        for job in jobs.iter_mut().filter(|j| j.status == JobStatus::Running) {
+            job.status = JobStatus::Interrupted;
+            interrupted += 1;
+        }
+        let pruned = before - jobs.len();
+        ((pruned, interrupted), pruned > 0 || interrupted > 0)
+    });
+    match result {
+        Ok((pruned, interrupted)) => {
+            info!(
+                target = "transfer_jobs",
+                pruned, interrupted, "Loaded transfer jobs"
+            )
+        }
+        Err(e) => warn!(target = "transfer_jobs", error = %e, "Failed to load transfer jobs"),
+    }
+
+    let app_handle = app_handle.clone();
+    thread::spawn(move || loop {
+        thread::sleep(CHECKPOINT_INTERVAL);
+        if let Err(e) = checkpoint(&app_handle) {
+            warn!(target = "transfer_jobs", error = %e, "Failed to checkpoint transfers");
+        }
+    });
+}
+
+/// Interrupted and failed transfers, newest first.
+#[tauri::command]
+pub fn list_resumable_transfers(app_handle: AppHandle) -> Result<Vec<TransferJob>, AppError> {
+    let mut jobs: Vec<TransferJob> = load_jobs(&app_handle)?
+        .into_iter()
+        .filter(TransferJob::resumable)
+        .collect();
+    jobs.sort_by_key(|j| std::cmp::Reverse(j.updated_at));
+    Ok(jobs)
+}
+
+/// Gives up on a resumable transfer. The partial file stays where it is.
+#[tauri::command]
+pub fn abandon_transfer(job_id: String, app_handle: AppHandle) -> Result<(), AppError> {
+    let found = update_jobs(&app_handle, |jobs| {
+        match jobs.iter_mut().find(|j| j.id == job_id && j.resumable()) {
+            Some(job) => {
+                job.status = JobStatus::Abandoned;
+                job.updated_at = now();
+                (true, true)
+            }
+            None => (false, false),
+        }
+    })?;
+    if !found {
+        return Err(AppError::new(
+            ErrorKind::NotFound,
+            "No resumable transfer with that id",
+        ));
+    }
+    Ok(())
+}
+
+// An open session for the job: the given one, one already open to its
+// saved host in this window, or a new connection to that host
+async fn job_session(
+    job: &TransferJob,
+    session_id: Option<String>,
+    window: &Window,
+    state: &State<'_, AppState>,
+    app_handle: &AppHandle,
+) -> Result<String, AppError> {
+    if let Some(session_id) = session_id {
+        ownership::authorize(state, &session_id, window)?;
+        return Ok(session_id);
+    }
+    let Some(host_id) = job.host_id.clone() else {
+        return Err(AppError::new(
+            ErrorKind::InvalidInput,
+            format!("Connect to {} first, then resume on that session", job.host),
+        ));
+    };
+    let open = state
+        .sessions
+        .iter()
+        .filter(|s| s.target.host_id.as_deref() == Some(&host_id))
+        .map(|s| s.key().to_string())
+        .find(|id| ownership::authorize(state, id, window).is_ok());
+    if let Some(session_id) = open {
+        return Ok(session_id);
+    }
+    let host = load_saved_hosts(app_handle.clone())?
+        .into_iter()
+        .find(|h| h.id == host_id)
+        .ok_or_else(|| AppError::new(ErrorKind::HostDeleted, "The transfer's host was deleted"))?;
+    info!(target = "transfer_jobs", job = %job.id, host = %host_id, "Connecting to resume transfer");
+    let label = SessionLabel {
+        title: Some(host.name),
+        color: host.color,
+    };
+    connect_ssh(
+        host.details,
+        Some(host_id),
+        None,
+        None,
+        Some(label),
+        state.clone(),
+        window.clone(),
+        app_handle.clone(),
+    )
+    .await
+}
+
+// Where to continue from, after checking both files still fit the job
+async fn resume_offset(
+    job: &TransferJob,
+    session_id: &str,
+    window: &Window,
+    state: &State<'_, AppState>,
+) -> Result<u64, AppError> {
+    let changed = |what: &str| {
+        AppError::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} changed since the transfer started, start it again",
+                what
+            ),
+        )
+    };
+    let remote_path = match job.direction {
+        TransferDirection::Upload => job.destination_path.clone(),
+        TransferDirection::Download => job.source_path.clone(),
+    };
+    let op = sftp_ops::Operation::new("stat", sftp_timeout(state), None);
+    let remote_size =
+        sftp_ops::with_session(state, session_id.to_string(), window, move |session| {
+            let stat = sftp_ops::run(session, &op, |sftp| sftp.stat(Path::new(&remote_path)));
+            match stat {
+                Ok(stat) => Ok(stat.size),
+                Err(e) if e.kind == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+        .await?;
+
+    match job.direction {
+        TransferDirection::Upload => {
+            let local_size = fs::metadata(&job.source_path)?.len();
+            if job.total_bytes.is_some_and(|total| total != local_size) {
+                return Err(changed("The local file"));
+            }
+            // upload checks again what actually landed
+            Ok(job.confirmed_bytes.min(remote_size.unwrap_or(0)))
+        }
+        TransferDirection::Download => {
+            let remote_size = remote_size
+                .ok_or_else(|| AppError::new(ErrorKind::NotFound, "The remote file is gone"))?;
+            if remote_size < job.confirmed_bytes {
+                return Err(changed("The remote file"));
+            }
+            let local_size = fs::metadata(&job.destination_path)
+                .map(|m| m.len())
+                .unwrap_or(0);
+            Ok(job.confirmed_bytes.min(local_size))
+        }
+    }
+}
+
+/// Continues an interrupted or failed transfer from its confirmed offset,
+/// on `session_id` or else a session to the job's saved host, connecting
+/// one if none is open. Events carry the job id as the transfer id.
+#[tauri::command]
+pub async fn resume_transfer(
+    job_id: String,
+    session_id: Option<String>,
+    window: Window,
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<(), AppError> {
+    let job = load_jobs(&app_handle)?
+        .into_iter()
+        .find(|j| j.id == job_id && j.resumable())
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "No resumable transfer with that id"))?;
+    if ACTIVE.contains_key(&job.id) {
+        return Err(AppError::new(
+            ErrorKind::AlreadyExists,
+            "The transfer is already running",
+        ));
+    }
+    let session_id = job_session(&job, session_id, &window, &state, &app_handle).await?;
+    let start = resume_offset(&job, &session_id, &window, &state).await?;
+    info!(target = "transfer_jobs", job = %job.id, session = %session_id, offset = start, "Resuming transfer");
+
+    let owners = ownership::authorize(&state, &session_id, &window)?;
+    let mut transfer = Transfer::start(
+        &window,
+        owners,
+        Some(job.id.clone()),
+        &session_id,
+        &job.source_path,
+        &job.destination_path,
+    );
+    // download and upload continue from the counter's value
+    transfer.bytes().store(start, Ordering::Relaxed);
+    begin(
+        &app_handle,
+        JobSpec {
+            id: &job.id,
+            direction: job.direction,
+            session_id: &session_id,
+            source_path: &job.source_path,
+            destination_path: &job.destination_path,
+            total_bytes: job.total_bytes,
+            retry: job.retry.clone(),
+        },
+        transfer.bytes(),
+    );
+    let result = match job.direction {
+        TransferDirection::Upload => upload(
+            session_id,
+            job.source_path,
+            job.destination_path,
+            job.retry,
+            transfer.bytes(),
+            window,
+            state,
+        )
+        .await
+        .map(|warning| {
+            if let Some(warning) = warning {
+                transfer.warn(warning);
+            }
+        }),
+        TransferDirection::Download => {
+            download(
+                session_id,
+                job.source_path,
+                job.destination_path,
+                job.retry,
+                transfer.bytes(),
+                window,
+                state,
+            )
+            .await
+        }
+    };
+    finish(&app_handle, &job_id, &result);
+    transfer.finish(&result);
+    result
+}
@@ -0,0 +1,261 @@
+// Tray icon listing the open sessions.
+//
+// The menu is rebuilt from AppState whenever a session opens or closes
+// ("session-opened" / "session-closed") and when the saved hosts change, so
+// it never needs its own bookkeeping. Each session can be shown or
+// disconnected; pinned hosts are handed to the main window to connect, since
+// the frontend owns the tabs and credential prompts. Sessions show their
+// title when they have one. With the close_to_tray setting, closing the main
+// window hides it and sessions keep running.
+
+use crate::{load_saved_hosts, start_close, AppState};
+use serde::Serialize;
+use tauri::menu::{IsMenuItem, Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem, Submenu};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Emitter, Listener, Manager, Window};
+use tracing::warn;
+use uuid::Uuid;
+
+const TRAY_ID: &str = "main";
+const MAIN_WINDOW: &str = "main";
+
+#[derive(Debug, Clone, Serialize)]
+struct FocusSessionPayload {
+    session_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ConnectHostPayload {
+    host_id: String,
+}
+
+// What a menu id asks for
+enum Action {
+    ShowSession(Uuid),
+    CloseSession(Uuid),
+    CloseAll,
+    Connect(String),
+    ShowMain,
+    Quit,
+}
+
+impl Action {
+    fn parse(id: &str) -> Option<Self> {
+        match id.split_once(':') {
+            Some(("show", id)) => Uuid::parse_str(id).ok().map(Self::ShowSession),
+            Some(("close", id)) => Uuid::parse_str(id).ok().map(Self::CloseSession),
+            Some(("connect", id)) => Some(Self::Connect(id.to_string())),
+            _ => match id {
+                "close-all" => Some(Self::CloseAll),
+                "show-main" => Some(Self::ShowMain),
+                "quit" => Some(Self::Quit),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Creates the tray icon. Failing here only costs the tray; some Linux
+/// desktops have none.
+pub fn init(app_handle: &AppHandle) {
+    let menu = match build_menu(app_handle) {
+        Ok(menu) => menu,
+        Err(e) => {
+            warn!(target = "tray", error = %e, "Failed to build tray menu");
+            return;
+        }
+    };
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .menu(&menu)
+        .tooltip(tooltip(0))
+        .show_menu_on_left_click(false)
+        .on_menu_event(handle_menu_event)
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                show_window(tray.app_handle(), MAIN_WINDOW);
+            }
+        });
+    if let Some(icon) = app_handle.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    if let Err(e) = builder.build(app_handle) {
+        warn!(target = "tray", error = %e, "Failed to create tray icon");
+        return;
+    }
+
+    for event in ["session-opened", "session-closed"] {
+        let handle = app_handle.clone();
+        app_handle.listen_any(event, move |_| refresh(&handle));
+    }
+    refresh(app_handle);
+}
+
+/// Rebuilds the menu and the session count.
+pub fn refresh(app_handle: &AppHandle) {
+    let Some(tray) = app_handle.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let count = app_handle.state::<AppState>().sessions.len();
+    match build_menu(app_handle) {
+        Ok(menu) => {
+            let _ = tray.set_menu(Some(menu));
+        }
+        Err(e) => warn!(target = "tray", error = %e, "Failed to rebuild tray menu"),
+    }
+    let _ = tray.set_tooltip(Some(tooltip(count)));
+    // Shown next to the icon on macOS, ignored elsewhere
+    let _ = tray.set_title((count > 0).then(|| count.to_string()));
+}
+
+fn tooltip(count: usize) -> String {
+    match count {
+        0 => "Terminoda".to_string(),
+        1 => "Terminoda - 1 active session".to_string(),
+        n => format!("Terminoda - {} active sessions", n),
+    }
+}
+
+fn build_menu(app_handle: &AppHandle) -> tauri::Result<Menu> {
+    let state = app_handle.state::<AppState>();
+    let mut sessions: Vec<(Uuid, String)> = state
+        .sessions
+        .iter()
+        .map(|entry| {
+            let target = &entry.target;
+            let label = match entry.meta.label().title {
+                Some(title) => title,
+                None if target.username.is_empty() => target.host.clone(),
+                None => format!("{}@{}", target.username, target.host),
+            };
+            (*entry.key(), label)
+        })
+        .collect();
+    sessions.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let menu = Menu::new(app_handle)?;
+    let summary = match sessions.len() {
+        0 => "No active sessions".to_string(),
+        1 => "1 active session".to_string(),
+        n => format!("{} active sessions", n),
+    };
+    menu.append(&item(app_handle, "summary", summary, false)?)?;
+    for (uuid, label) in &sessions {
+        let show = item(app_handle, format!("show:{}", uuid), "Show", true)?;
+        let close = item(app_handle, format!("close:{}", uuid), "Disconnect", true)?;
+        menu.append(&Submenu::with_items(
+            app_handle,
+            label,
+            true,
+            &[&show, &close],
+        )?)?;
+    }
+    menu.append(&item(
+        app_handle,
+        "close-all",
+        "Disconnect All",
+        !sessions.is_empty(),
+    )?)?;
+    menu.append(&PredefinedMenuItem::separator(app_handle)?)?;
+
+    let pinned: Vec<_> = load_saved_hosts(app_handle.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|host| host.pinned)
+        .collect();
+    let hosts: Vec<MenuItem> = pinned
+        .iter()
+        .map(|host| item(app_handle, format!("connect:{}", host.id), &host.name, true))
+        .collect::<tauri::Result<_>>()?;
+    let items: Vec<&dyn IsMenuItem<_>> = hosts
+        .iter()
+        .map(|item| item as &dyn IsMenuItem<_>)
+        .collect();
+    menu.append(&Submenu::with_items(
+        app_handle,
+        "Connect To",
+        !hosts.is_empty(),
+        &items,
+    )?)?;
+    menu.append(&PredefinedMenuItem::separator(app_handle)?)?;
+
+    menu.append(&item(app_handle, "show-main", "Show Terminoda", true)?)?;
+    menu.append(&item(app_handle, "quit", "Quit", true)?)?;
+    Ok(menu)
+}
+
+fn item(
+    app_handle: &AppHandle,
+    id: impl Into<MenuId>,
+    text: impl AsRef<str>,
+    enabled: bool,
+) -> tauri::Result<MenuItem> {
+    MenuItem::with_id(app_handle, id, text, enabled, None::<&str>)
+}
+
+fn handle_menu_event(app_handle: &AppHandle, event: MenuEvent) {
+    let Some(action) = Action::parse(event.id().as_ref()) else {
+        return;
+    };
+    let state = app_handle.state::<AppState>();
+    match action {
+        Action::ShowSession(uuid) => {
+            let Some(owner) = state
+                .sessions
+                .get(&uuid)
+                .map(|s| s.owners.owner().to_string())
+            else {
+                return;
+            };
+            show_window(app_handle, &owner);
+            let _ = app_handle.emit_to(
+                owner.as_str(),
+                "focus-session",
+                FocusSessionPayload {
+                    session_id: uuid.to_string(),
+                },
+            );
+        }
+        Action::CloseSession(uuid) => {
+            start_close(app_handle, uuid, "closed from tray");
+        }
+        Action::CloseAll => {
+            let all: Vec<Uuid> = state.sessions.iter().map(|entry| *entry.key()).collect();
+            for uuid in all {
+                start_close(app_handle, uuid, "closed from tray");
+            }
+        }
+        Action::Connect(host_id) => {
+            show_window(app_handle, MAIN_WINDOW);
+            let _ = app_handle.emit_to(
+                MAIN_WINDOW,
+                "tray-connect-host",
+                ConnectHostPayload { host_id },
+            );
+        }
+        Action::ShowMain => show_window(app_handle, MAIN_WINDOW),
+        Action::Quit => app_handle.exit(0),
+    }
+}
+
+fn show_window(app_handle: &AppHandle, label: &str) {
+    if let Some(window) = app_handle.get_webview_window(label) {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+/// With close_to_tray on, hides the main window instead of closing it. Only
+/// when the tray exists, or there would be no way back.
+pub fn hide_on_close(window: &Window) -> bool {
+    let app_handle = window.app_handle();
+    window.label() == MAIN_WINDOW
+        && app_handle.state::<AppState>().settings.get().close_to_tray
+        && app_handle.tray_by_id(TRAY_ID).is_some()
+        && window.hide().is_ok()
+}
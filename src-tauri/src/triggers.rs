@@ -0,0 +1,236 @@
+// Output triggers that react to what is currently at the end of the terminal.
+//
+// Triggers are only evaluated once the transport has gone idle, so a prompt
+// that merely scrolls past (in a cat or a log tail) never fires.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Enough to hold the last line or two of output
+const TAIL_LEN: usize = 512;
+
+const DEFAULT_SUDO_PROMPT: &str = r"\[sudo\] password for .*:";
+
+// A wrong password makes sudo ask again right away, don't answer twice
+const SUDO_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SudoAutofillConfig {
+    pub enabled: bool,
+    pub prompt_pattern: Option<String>,
+}
+
+/// The most recent output with escape sequences stripped.
+#[derive(Default)]
+pub struct OutputTail {
+    bytes: Vec<u8>,
+    // Set when new output arrived since the last trigger check
+    dirty: bool,
+}
+
+impl OutputTail {
+    pub fn push(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        self.bytes.extend_from_slice(data);
+        if self.bytes.len() > TAIL_LEN {
+            let excess = self.bytes.len() - TAIL_LEN;
+            self.bytes.drain(..excess);
+        }
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+        self.dirty = false;
+    }
+
+    /// Returns the tail text if it changed since the last call.
+    pub fn take_changed(&mut self) -> Option<String> {
+        if !std::mem::take(&mut self.dirty) {
+            return None;
+        }
+        Some(strip_escapes(&self.bytes))
+    }
+}
+
+pub(crate) fn strip_escapes(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters up to the final byte
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: up to BEL or ST
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Answers the sudo password prompt with the host's stored password.
+pub struct SudoAutofill {
+    prompt: Regex,
+    password: String,
+    last_fired: Option<Instant>,
+}
+
+impl SudoAutofill {
+    /// Returns None when the feature is off for the host or no password is stored.
+    pub fn from_config(
+        config: Option<&SudoAutofillConfig>,
+        password: Option<&str>,
+    ) -> Result<Option<Self>, String> {
+        let Some(config) = config.filter(|c| c.enabled) else {
+            return Ok(None);
+        };
+        let Some(password) = password.filter(|p| !p.is_empty()) else {
+            return Ok(None);
+        };
+        let pattern = config
+            .prompt_pattern
+            .as_deref()
+            .filter(|p| !p.trim().is_empty())
+            .unwrap_or(DEFAULT_SUDO_PROMPT);
+        // Anchor at the end so only a prompt waiting for input matches
+        let prompt = Regex::new(&format!(r"(?:{})\s*$", pattern))
+            .map_err(|e| format!("Invalid sudo prompt pattern: {}", e))?;
+        Ok(Some(Self {
+            prompt,
+            password: password.to_string(),
+            last_fired: None,
+        }))
+    }
+
+    /// Returns the bytes to send if the tail ends with the sudo prompt.
+    pub fn check(&mut self, tail: &str) -> Option<Vec<u8>> {
+        let last_line = tail.rsplit(['\n', '\r']).next()?;
+        if !self.prompt.is_match(last_line) {
+            return None;
+        }
+        if self
+            .last_fired
+            .is_some_and(|at| at.elapsed() < SUDO_RETRY_INTERVAL)
+        {
+            return None;
+        }
+        self.last_fired = Some(Instant::now());
+        Some(format!("{}\n", self.password).into_bytes())
+    }
+}
+
+// Give up on detecting the first prompt after this and assume the shell is up
+const SHELL_READY_FALLBACK: Duration = Duration::from_secs(2);
+// How long a step waits for the previous command's prompt before going ahead
+const PROMPT_WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartupCommand {
+    pub snippet_id: Option<String>,
+    pub command: Option<String>,
+    // Wait for the previous command to return to the prompt first
+    #[serde(default)]
+    pub wait_for_prompt: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StartupStatus {
+    pub total: usize,
+    pub sent: usize,
+    pub applied: bool,
+    pub missing_snippets: Vec<String>,
+}
+
+/// Rough check for a shell prompt at the end of the output.
+pub fn looks_like_prompt(tail: &str) -> bool {
+    let line = tail.rsplit(['\n', '\r']).next().unwrap_or("").trim_end();
+    line.ends_with(['$', '#', '%', '>', '❯'])
+}
+
+pub struct StartupSequence {
+    steps: VecDeque<(String, bool)>,
+    waiting_since: Instant,
+    // The first step always waits for the shell to be ready
+    awaiting_prompt: bool,
+    first: bool,
+    status: Arc<Mutex<Option<StartupStatus>>>,
+}
+
+impl StartupSequence {
+    pub fn new(steps: Vec<(String, bool)>, status: Arc<Mutex<Option<StartupStatus>>>) -> Self {
+        Self {
+            steps: steps.into(),
+            waiting_since: Instant::now(),
+            awaiting_prompt: true,
+            first: true,
+            status,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Returns the next batch of commands to type once the shell is ready.
+    pub fn next(&mut self, at_prompt: bool) -> Option<Vec<u8>> {
+        if self.steps.is_empty() {
+            return None;
+        }
+        if self.awaiting_prompt && !at_prompt {
+            let timeout = if self.first {
+                SHELL_READY_FALLBACK
+            } else {
+                PROMPT_WAIT_TIMEOUT
+            };
+            if self.waiting_since.elapsed() < timeout {
+                return None;
+            }
+        }
+        self.first = false;
+
+        // Send everything up to the next step that wants to see a prompt
+        let mut input = Vec::new();
+        let mut sent = 0;
+        while let Some((command, wait)) = self.steps.pop_front() {
+            if sent > 0 && wait {
+                self.steps.push_front((command, wait));
+                break;
+            }
+            input.extend_from_slice(command.trim_end().as_bytes());
+            input.push(b'\n');
+            sent += 1;
+        }
+        self.awaiting_prompt = true;
+        self.waiting_since = Instant::now();
+
+        if let Ok(mut status) = self.status.lock() {
+            if let Some(status) = status.as_mut() {
+                status.sent += sent;
+                status.applied = self.steps.is_empty();
+            }
+        }
+        Some(input)
+    }
+}
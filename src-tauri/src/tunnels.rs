@@ -0,0 +1,186 @@
+// Port forwards saved on a host, e.g. "5433 -> db:5432".
+//
+// Definitions live in SavedHost.tunnels: local (-L) and remote (-R) forwards
+// with a target, dynamic (-D) SOCKS forwards without one. A host can't have
+// two tunnels of the same kind listening on the same address and port.
+// auto_start is kept for opening tunnels on connect, which needs the port
+// forwarding the app doesn't have yet.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{load_saved_hosts, lock_saved_hosts, write_saved_hosts};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TunnelKind {
+    Local,
+    Remote,
+    Dynamic,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedTunnel {
+    // Assigned by add_host_tunnel
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub kind: TunnelKind,
+    // Loopback when unset
+    #[serde(default)]
+    pub bind_address: Option<String>,
+    pub bind_port: u16,
+    // None for dynamic tunnels
+    #[serde(default)]
+    pub target_host: Option<String>,
+    #[serde(default)]
+    pub target_port: Option<u16>,
+    #[serde(default)]
+    pub auto_start: bool,
+}
+
+impl SavedTunnel {
+    fn validate(&self) -> Result<(), String> {
+        if self.bind_port == 0 {
+            return Err("Bind port must be between 1 and 65535".to_string());
+        }
+        if self
+            .bind_address
+            .as_deref()
+            .is_some_and(|a| a.trim().is_empty() || a.contains(char::is_whitespace))
+        {
+            return Err("Invalid bind address".to_string());
+        }
+        match self.kind {
+            TunnelKind::Dynamic => {
+                if self.target_host.is_some() || self.target_port.is_some() {
+                    return Err("Dynamic tunnels have no target".to_string());
+                }
+            }
+            TunnelKind::Local | TunnelKind::Remote => {
+                let host = self.target_host.as_deref().unwrap_or("").trim();
+                if host.is_empty() || host.contains(char::is_whitespace) {
+                    return Err("A target host is required".to_string());
+                }
+                if self.target_port.is_none_or(|p| p == 0) {
+                    return Err("Target port must be between 1 and 65535".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Two tunnels with the same key can't both listen
+    fn bind_key(&self) -> (TunnelKind, &str, u16) {
+        (
+            self.kind,
+            self.bind_address.as_deref().unwrap_or("localhost"),
+            self.bind_port,
+        )
+    }
+}
+
+fn update_host_tunnels<T>(
+    host_id: &str,
+    app_handle: &AppHandle,
+    update: impl FnOnce(&mut Vec<SavedTunnel>) -> Result<T, AppError>,
+) -> Result<T, AppError> {
+    let _lock = lock_saved_hosts(app_handle)?;
+    let mut hosts = load_saved_hosts(app_handle.clone())?;
+    let host = hosts
+        .iter_mut()
+        .find(|h| h.id == host_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Host not found"))?;
+    let result = update(&mut host.tunnels)?;
+    validate_all(&host.tunnels)?;
+    write_saved_hosts(app_handle, &hosts)?;
+    Ok(result)
+}
+
+/// Checks every tunnel of a host and that no two bind the same port.
+pub fn validate_all(tunnels: &[SavedTunnel]) -> Result<(), AppError> {
+    for (i, tunnel) in tunnels.iter().enumerate() {
+        tunnel
+            .validate()
+            .map_err(|e| AppError::new(ErrorKind::InvalidInput, e))?;
+        if tunnels[..i]
+            .iter()
+            .any(|t| t.bind_key() == tunnel.bind_key())
+        {
+            return Err(AppError::new(
+                ErrorKind::AlreadyExists,
+                format!(
+                    "Another tunnel already listens on port {}",
+                    tunnel.bind_port
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_host_tunnels(
+    host_id: String,
+    app_handle: AppHandle,
+) -> Result<Vec<SavedTunnel>, AppError> {
+    load_saved_hosts(app_handle)?
+        .into_iter()
+        .find(|h| h.id == host_id)
+        .map(|h| h.tunnels)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Host not found"))
+}
+
+/// Saves a new tunnel on a host; its id is assigned here.
+#[tauri::command]
+pub fn add_host_tunnel(
+    host_id: String,
+    tunnel: SavedTunnel,
+    app_handle: AppHandle,
+) -> Result<SavedTunnel, AppError> {
+    let tunnel = SavedTunnel {
+        id: Uuid::new_v4().to_string(),
+        ..tunnel
+    };
+    let added = update_host_tunnels(&host_id, &app_handle, |tunnels| {
+        tunnels.push(tunnel.clone());
+        Ok(tunnel)
+    })?;
+    info!(target = "tunnels", host = %host_id, tunnel = %added.id, "Saved tunnel");
+    Ok(added)
+}
+
+#[tauri::command]
+pub fn update_host_tunnel(
+    host_id: String,
+    tunnel: SavedTunnel,
+    app_handle: AppHandle,
+) -> Result<SavedTunnel, AppError> {
+    update_host_tunnels(&host_id, &app_handle, |tunnels| {
+        let existing = tunnels
+            .iter_mut()
+            .find(|t| t.id == tunnel.id)
+            .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Tunnel not found"))?;
+        *existing = tunnel.clone();
+        Ok(tunnel)
+    })
+}
+
+#[tauri::command]
+pub fn delete_host_tunnel(
+    host_id: String,
+    tunnel_id: String,
+    app_handle: AppHandle,
+) -> Result<(), AppError> {
+    update_host_tunnels(&host_id, &app_handle, |tunnels| {
+        let before = tunnels.len();
+        tunnels.retain(|t| t.id != tunnel_id);
+        if tunnels.len() == before {
+            return Err(AppError::new(ErrorKind::NotFound, "Tunnel not found"));
+        }
+        Ok(())
+    })
+}
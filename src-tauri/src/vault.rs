@@ -0,0 +1,241 @@
+// Optional master-password encryption of the config files (hosts, groups,
+// snippets, history), independent of the OS credential store.
+//
+// The files are encrypted with a random data key. vault.json holds that key
+// wrapped with an Argon2id-derived key from the master password, so changing
+// the password rewrites only the header, in a single atomic rename. A header
+// that fails to unwrap means a wrong password; a data file that fails to
+// decrypt with an unwrapped key means the file itself is damaged.
+
+use crate::error::AppError;
+use crate::{config_file, crypto, get_config_dir};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use tracing::info;
+
+// Error string the frontend maps to an unlock prompt
+pub const LOCKED: &str = "vault-locked";
+
+const HEADER_VERSION: u32 = 1;
+const MAGIC: &str = "TERMINODA-VAULT-1:";
+const SALT_LEN: usize = 16;
+const VAULT_FILES: [&str; 5] = [
+    "connections.json",
+    "groups.json",
+    "snippets.json",
+    "snippet_folders.json",
+    "history.json",
+];
+
+// Unwrapped data key while the vault is unlocked
+static DATA_KEY: Mutex<Option<crypto::SecretKey>> = Mutex::new(None);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VaultHeader {
+    version: u32,
+    salt: String,
+    wrapped_key: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VaultStatus {
+    pub enabled: bool,
+    pub unlocked: bool,
+}
+
+fn header_path() -> Result<PathBuf, String> {
+    Ok(get_config_dir()?.join("vault.json"))
+}
+
+fn data_key() -> MutexGuard<'static, Option<crypto::SecretKey>> {
+    DATA_KEY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn is_enabled() -> bool {
+    header_path().map(|p| p.exists()).unwrap_or(false)
+}
+
+fn load_header() -> Result<VaultHeader, String> {
+    let path = header_path()?;
+    // Losing the header loses everything, so fall back to its backup
+    [path.clone(), config_file::backup_path(&path)]
+        .iter()
+        .find_map(|p| {
+            let content = fs::read_to_string(p).ok()?;
+            serde_json::from_str(&content).ok()
+        })
+        .ok_or_else(|| "Vault header is corrupted".to_string())
+}
+
+fn unwrap_key(header: &VaultHeader, password: &str) -> Result<crypto::SecretKey, String> {
+    if header.version != HEADER_VERSION {
+        return Err(format!("Unsupported vault version: {}", header.version));
+    }
+    let salt = BASE64
+        .decode(&header.salt)
+        .map_err(|_| "Vault header is corrupted".to_string())?;
+    let wrapped = BASE64
+        .decode(&header.wrapped_key)
+        .map_err(|_| "Vault header is corrupted".to_string())?;
+    let kek = crypto::derive_key(password, &salt)?;
+    crypto::decrypt(&kek, &wrapped)
+        .map_err(|_| "Wrong master password".to_string())?
+        .try_into()
+        .map_err(|_| "Vault header is corrupted".to_string())
+}
+
+fn wrap_key(key: &crypto::SecretKey, password: &str) -> Result<VaultHeader, String> {
+    let salt = crypto::random_bytes::<SALT_LEN>();
+    let kek = crypto::derive_key(password, &salt)?;
+    Ok(VaultHeader {
+        version: HEADER_VERSION,
+        salt: BASE64.encode(salt),
+        wrapped_key: BASE64.encode(crypto::encrypt(&kek, key)?),
+    })
+}
+
+fn seal(key: &crypto::SecretKey, content: &str) -> Result<String, String> {
+    let encrypted = crypto::encrypt(key, content.as_bytes())?;
+    Ok(format!("{}{}", MAGIC, BASE64.encode(encrypted)))
+}
+
+fn open(key: &crypto::SecretKey, path: &Path, sealed: &str) -> Result<String, String> {
+    let corrupted = || format!("{} is corrupted", path.display());
+    let encrypted = BASE64.decode(sealed.trim()).map_err(|_| corrupted())?;
+    let plaintext = crypto::decrypt(key, &encrypted).map_err(|_| corrupted())?;
+    String::from_utf8(plaintext).map_err(|_| corrupted())
+}
+
+/// Reads a config file, decrypting it if it was written by the vault.
+/// Plaintext files are passed through so a half-finished migration still loads.
+pub fn read_to_string(path: &Path) -> Result<String, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let Some(sealed) = content.strip_prefix(MAGIC) else {
+        return Ok(content);
+    };
+    let key = data_key().ok_or(LOCKED)?;
+    open(&key, path, sealed)
+}
+
+pub fn is_sealed(content: &str) -> bool {
+    content.starts_with(MAGIC)
+}
+
+/// Checks a sealed file's outer layer without the key.
+pub fn check_sealed(content: &str) -> Result<(), String> {
+    let sealed = content.strip_prefix(MAGIC).ok_or("Not a sealed file")?;
+    BASE64
+        .decode(sealed.trim())
+        .map(|_| ())
+        .map_err(|_| "Sealed data is corrupted".to_string())
+}
+
+/// Returns the bytes to store for a config file: sealed when the vault is
+/// enabled and the file is one it covers, unchanged otherwise.
+pub fn seal_for(path: &Path, content: &str) -> Result<String, String> {
+    let covered = path
+        .file_name()
+        .is_some_and(|name| VAULT_FILES.iter().any(|f| name == *f));
+    if !covered || !is_enabled() {
+        return Ok(content.to_string());
+    }
+    let key = data_key().ok_or(LOCKED)?;
+    seal(&key, content)
+}
+
+// Re-seals every vault file with `seal_with` (None writes plaintext)
+fn rewrite_files(
+    key: &crypto::SecretKey,
+    seal_with: Option<&crypto::SecretKey>,
+) -> Result<(), String> {
+    let dir = get_config_dir()?;
+    for name in VAULT_FILES {
+        let path = dir.join(name);
+        let _lock = config_file::lock(&path)?;
+        if !path.exists() {
+            continue;
+        }
+        let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        let plaintext = match content.strip_prefix(MAGIC) {
+            Some(sealed) => open(key, &path, sealed)?,
+            None => content,
+        };
+        let output = match seal_with {
+            Some(key) => seal(key, &plaintext)?,
+            None => plaintext,
+        };
+        config_file::write_raw(&path, output.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Enables, changes or (with no new password) disables the master password.
+#[tauri::command]
+pub fn set_master_password(
+    current_password: Option<String>,
+    new_password: Option<String>,
+) -> Result<(), AppError> {
+    let new_password = new_password.filter(|p| !p.is_empty());
+    let mut unlocked = data_key();
+
+    if !is_enabled() {
+        let password = new_password.ok_or("A master password is required")?;
+        let key = crypto::random_key();
+        // Header first: files sealed afterwards are always readable with it
+        let header = serde_json::to_vec_pretty(&wrap_key(&key, &password)?)?;
+        config_file::write_raw(&header_path()?, &header)?;
+        *unlocked = Some(key);
+        rewrite_files(&key, Some(&key))?;
+        info!(target = "vault", "Enabled master password");
+        return Ok(());
+    }
+
+    let current = current_password.ok_or("The current master password is required")?;
+    let key = unwrap_key(&load_header()?, &current)?;
+    match new_password {
+        Some(password) => {
+            let header = serde_json::to_vec_pretty(&wrap_key(&key, &password)?)?;
+            config_file::write_raw(&header_path()?, &header)?;
+            *unlocked = Some(key);
+            info!(target = "vault", "Changed master password");
+        }
+        None => {
+            // Files first: the header stays until nothing depends on it
+            rewrite_files(&key, None)?;
+            config_file::remove(&header_path()?)?;
+            *unlocked = None;
+            info!(target = "vault", "Disabled master password");
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn unlock_vault(password: String) -> Result<(), AppError> {
+    if !is_enabled() {
+        return Err("No master password is set".into());
+    }
+    let key = unwrap_key(&load_header()?, &password)?;
+    *data_key() = Some(key);
+    info!(target = "vault", "Vault unlocked");
+    Ok(())
+}
+
+#[tauri::command]
+pub fn lock_vault() -> Result<(), AppError> {
+    *data_key() = None;
+    info!(target = "vault", "Vault locked");
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_vault_status() -> Result<VaultStatus, AppError> {
+    Ok(VaultStatus {
+        enabled: is_enabled(),
+        unlocked: data_key().is_some(),
+    })
+}
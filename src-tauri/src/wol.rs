@@ -0,0 +1,170 @@
+// Wake-on-LAN for saved hosts.
+//
+// A host with a mac_address can be woken by wake_host, which broadcasts the
+// magic packet (six 0xff bytes, then the MAC sixteen times) over UDP. With
+// wait_secs it then polls the host's SSH port and emits "host-awake" once it
+// accepts connections. A connect to such a host that can't reach it fails
+// with details.action set to "wake-host", so the frontend can offer to wake
+// it and retry.
+
+use crate::error::{AppError, ErrorKind};
+use crate::{load_saved_hosts, SavedHost};
+use serde::Serialize;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Window};
+use tracing::{info, warn};
+
+const DEFAULT_PORT: u16 = 9;
+const DEFAULT_BROADCAST: &str = "255.255.255.255";
+const MAX_WAIT_SECS: u64 = 600;
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+pub const WAKE_ACTION: &str = "wake-host";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WakeResult {
+    pub sent: bool,
+    pub target: String,
+    // Whether "host-awake" will follow once the host answers
+    pub polling: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct HostAwakePayload {
+    host_id: String,
+    waited_secs: u64,
+}
+
+/// Parses "aa:bb:cc:dd:ee:ff", with '-' separators or none.
+pub fn parse_mac(mac: &str) -> Result<[u8; 6], String> {
+    let hex: String = mac
+        .chars()
+        .filter(|c| !matches!(c, ':' | '-' | '.'))
+        .collect();
+    let invalid = || format!("Invalid MAC address: {}", mac);
+    if hex.len() != 12 {
+        return Err(invalid());
+    }
+    let mut bytes = [0u8; 6];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(bytes)
+}
+
+/// Rejects a host whose wake settings can't be used.
+pub fn validate(host: &SavedHost) -> Result<(), String> {
+    if let Some(mac) = host.mac_address.as_deref().filter(|m| !m.is_empty()) {
+        parse_mac(mac)?;
+    }
+    if let Some(broadcast) = host.broadcast_address.as_deref().filter(|b| !b.is_empty()) {
+        broadcast
+            .parse::<std::net::IpAddr>()
+            .map_err(|_| format!("Invalid broadcast address: {}", broadcast))?;
+    }
+    Ok(())
+}
+
+fn magic_packet(mac: [u8; 6]) -> Vec<u8> {
+    let mut packet = vec![0xff; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac);
+    }
+    packet
+}
+
+fn send(mac: [u8; 6], target: &str) -> Result<(), AppError> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_broadcast(true)?;
+    socket.send_to(&magic_packet(mac), target)?;
+    Ok(())
+}
+
+fn find_host(app_handle: &AppHandle, host_id: &str) -> Result<SavedHost, AppError> {
+    load_saved_hosts(app_handle.clone())?
+        .into_iter()
+        .find(|h| h.id == host_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, "Host not found"))
+}
+
+/// Marks a connect failure to a wakeable saved host with the wake action.
+pub fn offer_wake(app_handle: &AppHandle, host_id: Option<&str>, error: AppError) -> AppError {
+    if !matches!(error.kind, ErrorKind::ConnectionFailed | ErrorKind::Timeout) {
+        return error;
+    }
+    let wakeable = host_id
+        .and_then(|id| find_host(app_handle, id).ok())
+        .is_some_and(|host| host.mac_address.is_some_and(|m| !m.is_empty()));
+    if !wakeable {
+        return error;
+    }
+    let mut error = error;
+    error.details.get_or_insert_with(Default::default).action = Some(WAKE_ACTION.to_string());
+    error
+}
+
+// Polls the SSH port until it accepts a connection or the time is up
+fn wait_for_ssh(window: Window, host_id: String, addr: String, wait: Duration) {
+    let started = Instant::now();
+    while started.elapsed() < wait {
+        let reachable = addr.to_socket_addrs().ok().and_then(|mut a| a.next());
+        if let Some(socket_addr) = reachable {
+            if TcpStream::connect_timeout(&socket_addr, PROBE_TIMEOUT).is_ok() {
+                let waited_secs = started.elapsed().as_secs();
+                info!(target = "wol", host = %host_id, waited_secs, "Host is awake");
+                let _ = window.emit(
+                    "host-awake",
+                    HostAwakePayload {
+                        host_id,
+                        waited_secs,
+                    },
+                );
+                return;
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    warn!(target = "wol", host = %host_id, "Host did not answer after wake packet");
+}
+
+// port defaults to 9; wait_secs > 0 polls SSH afterwards, up to 600
+#[tauri::command]
+pub fn wake_host(
+    host_id: String,
+    port: Option<u16>,
+    wait_secs: Option<u64>,
+    window: Window,
+    app_handle: AppHandle,
+) -> Result<WakeResult, AppError> {
+    let host = find_host(&app_handle, &host_id)?;
+    let mac = host
+        .mac_address
+        .as_deref()
+        .filter(|m| !m.is_empty())
+        .ok_or_else(|| AppError::new(ErrorKind::InvalidInput, "Host has no MAC address"))?;
+    let mac = parse_mac(mac).map_err(|e| AppError::new(ErrorKind::InvalidInput, e))?;
+    let broadcast = host
+        .broadcast_address
+        .as_deref()
+        .filter(|b| !b.is_empty())
+        .unwrap_or(DEFAULT_BROADCAST);
+    let target = format!("{}:{}", broadcast, port.unwrap_or(DEFAULT_PORT));
+
+    send(mac, &target).map_err(|e| e.context("Failed to send wake packet"))?;
+    info!(target = "wol", host = %host_id, %target, "Sent wake packet");
+
+    let wait_secs = wait_secs.unwrap_or(0).min(MAX_WAIT_SECS);
+    let polling = wait_secs > 0;
+    if polling {
+        let addr = format!("{}:{}", host.details.host, host.details.port.unwrap_or(22));
+        let wait = Duration::from_secs(wait_secs);
+        thread::spawn(move || wait_for_ssh(window, host_id, addr, wait));
+    }
+    Ok(WakeResult {
+        sent: true,
+        target,
+        polling,
+    })
+}
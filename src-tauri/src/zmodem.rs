@@ -0,0 +1,838 @@
+// Minimal ZMODEM implementation for sz/rz transfers inside a terminal session.
+//
+// The reader thread watches the output stream for the ZRQINIT / ZRINIT hex
+// headers that sz and rz print on startup. Once the frontend has chosen what
+// to do, the protocol runs over the same channel and normal output resumes
+// when the transfer completes or is cancelled.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const ZPAD: u8 = b'*';
+const ZDLE: u8 = 0x18;
+const ZBIN: u8 = b'A';
+const ZHEX: u8 = b'B';
+const ZBIN32: u8 = b'C';
+const XON: u8 = 0x11;
+
+const ZCRCE: u8 = b'h';
+const ZCRCG: u8 = b'i';
+const ZCRCQ: u8 = b'j';
+const ZCRCW: u8 = b'k';
+const ZRUB0: u8 = b'l';
+const ZRUB1: u8 = b'm';
+
+const ZRQINIT: u8 = 0;
+const ZRINIT: u8 = 1;
+const ZSINIT: u8 = 2;
+const ZACK: u8 = 3;
+const ZFILE: u8 = 4;
+const ZSKIP: u8 = 5;
+const ZNAK: u8 = 6;
+const ZABORT: u8 = 7;
+const ZFIN: u8 = 8;
+const ZRPOS: u8 = 9;
+const ZDATA: u8 = 10;
+const ZEOF: u8 = 11;
+const ZFERR: u8 = 12;
+const ZCAN: u8 = 16;
+
+// ZRINIT capability flags: full duplex, overlapped I/O, 32-bit CRC
+const CANFDX: u8 = 0x01;
+const CANOVIO: u8 = 0x02;
+const CANFC32: u8 = 0x20;
+
+const SUBPACKET_LEN: usize = 1024;
+const MAX_SUBPACKET_LEN: usize = 8192;
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_RETRIES: usize = 10;
+
+/// Sequence sz prints when it wants to send (we receive).
+pub const SZ_MARKER: &[u8] = b"**\x18B00";
+/// Sequence rz prints when it is ready to receive (we send).
+pub const RZ_MARKER: &[u8] = b"**\x18B01";
+
+/// Eight CANs abort the remote side, the backspaces clean up the echo.
+pub const CANCEL_SEQUENCE: &[u8] = &[
+    0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08,
+    0x08,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Remote ran sz, we save files locally
+    Receive,
+    /// Remote ran rz, we upload local files
+    Send,
+}
+
+/// Looks for a ZMODEM start header, returning its offset and direction.
+fn detect(data: &[u8]) -> Option<(usize, Direction)> {
+    data.windows(SZ_MARKER.len()).enumerate().find_map(|(i, window)| {
+        if window == SZ_MARKER {
+            Some((i, Direction::Receive))
+        } else if window == RZ_MARKER {
+            Some((i, Direction::Send))
+        } else {
+            None
+        }
+    })
+}
+
+/// Finds start headers in the output stream, including ones split across
+/// reads. The last few bytes of each chunk are kept to match against the
+/// next; they're forwarded as usual, so a split header's first bytes reach
+/// the terminal before the transfer starts.
+#[derive(Default)]
+pub struct Detector {
+    tail: Vec<u8>,
+}
+
+impl Detector {
+    /// Returns where in `chunk` forwarding stops, the bytes the protocol
+    /// starts from (the whole header, even the part that came earlier) and
+    /// the direction.
+    pub fn scan(&mut self, chunk: &[u8]) -> Option<(usize, Vec<u8>, Direction)> {
+        let carried = self.tail.len();
+        let mut joined = std::mem::take(&mut self.tail);
+        joined.extend_from_slice(chunk);
+        if let Some((start, direction)) = detect(&joined) {
+            return Some((start.saturating_sub(carried), joined[start..].to_vec(), direction));
+        }
+        let keep = joined.len().min(SZ_MARKER.len() - 1);
+        self.tail = joined.split_off(joined.len() - keep);
+        None
+    }
+}
+
+pub enum ZmodemCommand {
+    Receive { save_dir: String },
+    Send { paths: Vec<String> },
+    Cancel,
+}
+
+#[derive(Default)]
+pub struct ZmodemControl {
+    pub pending: Mutex<Option<std::sync::mpsc::Sender<ZmodemCommand>>>,
+    pub cancel: AtomicBool,
+}
+
+#[derive(Debug)]
+pub enum ZmodemError {
+    Cancelled,
+    RemoteAborted,
+    Timeout,
+    Protocol(String),
+    Io(String),
+}
+
+impl std::fmt::Display for ZmodemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cancelled => write!(f, "Transfer cancelled"),
+            Self::RemoteAborted => write!(f, "Remote side aborted the transfer"),
+            Self::Timeout => write!(f, "Timed out waiting for the remote side"),
+            Self::Protocol(msg) => write!(f, "ZMODEM protocol error: {}", msg),
+            Self::Io(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<std::io::Error> for ZmodemError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value.to_string())
+    }
+}
+
+type ZResult<T> = Result<T, ZmodemError>;
+
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xffff_ffff;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    frame_type: u8,
+    data: [u8; 4],
+    crc32: bool,
+}
+
+impl Header {
+    fn position(&self) -> u64 {
+        u32::from_le_bytes(self.data) as u64
+    }
+}
+
+/// Byte-level transport over a shared, non-blocking channel.
+pub struct Port<T: Read + Write> {
+    channel: Arc<Mutex<T>>,
+    pending: Vec<u8>,
+    pending_pos: usize,
+    cancel: Arc<ZmodemControl>,
+}
+
+impl<T: Read + Write> Port<T> {
+    pub fn new(channel: Arc<Mutex<T>>, prefetched: Vec<u8>, cancel: Arc<ZmodemControl>) -> Self {
+        Self {
+            channel,
+            pending: prefetched,
+            pending_pos: 0,
+            cancel,
+        }
+    }
+
+    fn check_cancel(&self) -> ZResult<()> {
+        if self.cancel.cancel.load(Ordering::SeqCst) {
+            Err(ZmodemError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read_byte(&mut self) -> ZResult<u8> {
+        self.read_byte_within(READ_TIMEOUT)
+    }
+
+    fn read_byte_within(&mut self, timeout: Duration) -> ZResult<u8> {
+        if self.pending_pos < self.pending.len() {
+            let byte = self.pending[self.pending_pos];
+            self.pending_pos += 1;
+            return Ok(byte);
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut buffer = [0u8; 4096];
+        loop {
+            self.check_cancel()?;
+            let result = {
+                let mut channel = self
+                    .channel
+                    .lock()
+                    .map_err(|_| ZmodemError::Io("Channel lock poisoned".to_string()))?;
+                channel.read(&mut buffer)
+            };
+            match result {
+                Ok(0) => return Err(ZmodemError::Io("Channel closed".to_string())),
+                Ok(n) => {
+                    self.pending = buffer[..n].to_vec();
+                    self.pending_pos = 1;
+                    return Ok(self.pending[0]);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        return Err(ZmodemError::Timeout);
+                    }
+                    thread::sleep(Duration::from_millis(5));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Bytes received after the protocol finished belong to the terminal again.
+    pub fn into_remaining(self) -> Vec<u8> {
+        self.pending[self.pending_pos.min(self.pending.len())..].to_vec()
+    }
+
+    pub fn write_all(&mut self, mut data: &[u8]) -> ZResult<()> {
+        while !data.is_empty() {
+            self.check_cancel()?;
+            let result = {
+                let mut channel = self
+                    .channel
+                    .lock()
+                    .map_err(|_| ZmodemError::Io("Channel lock poisoned".to_string()))?;
+                channel.write(data)
+            };
+            match result {
+                Ok(0) => return Err(ZmodemError::Io("Channel closed".to_string())),
+                Ok(n) => data = &data[n..],
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    // ---- framing ----
+
+    fn write_hex_header(&mut self, frame_type: u8, data: [u8; 4]) -> ZResult<()> {
+        let mut raw = vec![frame_type];
+        raw.extend_from_slice(&data);
+        let crc = crc16(&raw);
+        raw.extend_from_slice(&crc.to_be_bytes());
+
+        let mut out = vec![ZPAD, ZPAD, ZDLE, ZHEX];
+        for byte in raw {
+            out.extend_from_slice(format!("{:02x}", byte).as_bytes());
+        }
+        out.extend_from_slice(&[b'\r', 0x8a]);
+        if frame_type != ZFIN && frame_type != ZACK {
+            out.push(XON);
+        }
+        self.write_all(&out)
+    }
+
+    fn write_bin_header(&mut self, frame_type: u8, data: [u8; 4]) -> ZResult<()> {
+        let mut raw = vec![frame_type];
+        raw.extend_from_slice(&data);
+        let crc = crc16(&raw);
+        raw.extend_from_slice(&crc.to_be_bytes());
+
+        let mut out = vec![ZPAD, ZDLE, ZBIN];
+        escape_into(&raw, &mut out);
+        self.write_all(&out)
+    }
+
+    fn write_subpacket(&mut self, data: &[u8], frame_end: u8) -> ZResult<()> {
+        let mut out = Vec::with_capacity(data.len() * 2 + 8);
+        escape_into(data, &mut out);
+        out.push(ZDLE);
+        out.push(frame_end);
+
+        let mut crc_input = data.to_vec();
+        crc_input.push(frame_end);
+        let crc = crc16(&crc_input);
+        escape_into(&crc.to_be_bytes(), &mut out);
+        if frame_end == ZCRCW {
+            out.push(XON);
+        }
+        self.write_all(&out)
+    }
+
+    /// Reads a byte that was ZDLE-escaped, reporting frame ends separately.
+    fn read_escaped(&mut self) -> ZResult<Escaped> {
+        loop {
+            let byte = self.read_byte()?;
+            match byte {
+                XON | 0x13 | 0x91 | 0x93 => continue,
+                ZDLE => {
+                    // Five consecutive CANs mean the remote side gave up
+                    let mut cancels = 1;
+                    loop {
+                        let next = self.read_byte()?;
+                        match next {
+                            ZDLE => {
+                                cancels += 1;
+                                if cancels >= 5 {
+                                    return Err(ZmodemError::RemoteAborted);
+                                }
+                            }
+                            XON | 0x13 | 0x91 | 0x93 => continue,
+                            ZCRCE | ZCRCG | ZCRCQ | ZCRCW => return Ok(Escaped::FrameEnd(next)),
+                            _ => return Ok(Escaped::Byte(unescape(next)?)),
+                        }
+                    }
+                }
+                _ => return Ok(Escaped::Byte(byte)),
+            }
+        }
+    }
+
+    fn read_escaped_byte(&mut self) -> ZResult<u8> {
+        match self.read_escaped()? {
+            Escaped::Byte(b) => Ok(b),
+            Escaped::FrameEnd(_) => Err(ZmodemError::Protocol("unexpected frame end".to_string())),
+        }
+    }
+
+    fn read_header(&mut self) -> ZResult<Header> {
+        let mut garbage = 0usize;
+        let mut cancels = 0;
+        loop {
+            let byte = self.read_byte()?;
+            if byte == ZDLE {
+                cancels += 1;
+                if cancels >= 5 {
+                    return Err(ZmodemError::RemoteAborted);
+                }
+            } else {
+                cancels = 0;
+            }
+            if byte != ZPAD {
+                garbage += 1;
+                if garbage > 4096 {
+                    return Err(ZmodemError::Protocol("no header received".to_string()));
+                }
+                continue;
+            }
+
+            // One or more ZPADs followed by ZDLE and the format byte
+            let mut next = self.read_byte()?;
+            while next == ZPAD {
+                next = self.read_byte()?;
+            }
+            if next != ZDLE {
+                continue;
+            }
+            let format = self.read_byte()?;
+            let header = match format {
+                ZHEX => self.read_hex_header_body(),
+                ZBIN => self.read_bin_header_body(false),
+                ZBIN32 => self.read_bin_header_body(true),
+                _ => continue,
+            };
+            match header {
+                Ok(h) => return Ok(h),
+                Err(ZmodemError::Protocol(_)) => {
+                    garbage += 1;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn read_hex_byte(&mut self) -> ZResult<u8> {
+        let hi = self.read_byte()?;
+        let lo = self.read_byte()?;
+        let text = [hi, lo];
+        std::str::from_utf8(&text)
+            .ok()
+            .and_then(|t| u8::from_str_radix(t, 16).ok())
+            .ok_or_else(|| ZmodemError::Protocol("bad hex header".to_string()))
+    }
+
+    fn read_hex_header_body(&mut self) -> ZResult<Header> {
+        let mut raw = [0u8; 7];
+        for slot in raw.iter_mut() {
+            *slot = self.read_hex_byte()?;
+        }
+        if crc16(&raw[..5]) != u16::from_be_bytes([raw[5], raw[6]]) {
+            return Err(ZmodemError::Protocol("hex header CRC mismatch".to_string()));
+        }
+        // Trailing CR LF (and XON) are skipped by the next read_header
+        Ok(Header {
+            frame_type: raw[0],
+            data: [raw[1], raw[2], raw[3], raw[4]],
+            crc32: false,
+        })
+    }
+
+    fn read_bin_header_body(&mut self, use_crc32: bool) -> ZResult<Header> {
+        let len = if use_crc32 { 9 } else { 7 };
+        let mut raw = Vec::with_capacity(len);
+        for _ in 0..len {
+            raw.push(self.read_escaped_byte()?);
+        }
+        let valid = if use_crc32 {
+            crc32(&raw[..5]) == u32::from_le_bytes([raw[5], raw[6], raw[7], raw[8]])
+        } else {
+            crc16(&raw[..5]) == u16::from_be_bytes([raw[5], raw[6]])
+        };
+        if !valid {
+            return Err(ZmodemError::Protocol("binary header CRC mismatch".to_string()));
+        }
+        Ok(Header {
+            frame_type: raw[0],
+            data: [raw[1], raw[2], raw[3], raw[4]],
+            crc32: use_crc32,
+        })
+    }
+
+    /// Reads one data subpacket, returning its payload and frame end type.
+    fn read_subpacket(&mut self, use_crc32: bool) -> ZResult<(Vec<u8>, u8)> {
+        let mut data = Vec::with_capacity(SUBPACKET_LEN);
+        loop {
+            match self.read_escaped()? {
+                Escaped::Byte(b) => {
+                    if data.len() >= MAX_SUBPACKET_LEN {
+                        return Err(ZmodemError::Protocol("subpacket too long".to_string()));
+                    }
+                    data.push(b);
+                }
+                Escaped::FrameEnd(end) => {
+                    let mut crc_input = data.clone();
+                    crc_input.push(end);
+                    let valid = if use_crc32 {
+                        let mut crc = [0u8; 4];
+                        for slot in crc.iter_mut() {
+                            *slot = self.read_escaped_byte()?;
+                        }
+                        crc32(&crc_input) == u32::from_le_bytes(crc)
+                    } else {
+                        let hi = self.read_escaped_byte()?;
+                        let lo = self.read_escaped_byte()?;
+                        crc16(&crc_input) == u16::from_be_bytes([hi, lo])
+                    };
+                    if !valid {
+                        return Err(ZmodemError::Protocol("data CRC mismatch".to_string()));
+                    }
+                    return Ok((data, end));
+                }
+            }
+        }
+    }
+}
+
+enum Escaped {
+    Byte(u8),
+    FrameEnd(u8),
+}
+
+fn unescape(byte: u8) -> ZResult<u8> {
+    match byte {
+        ZRUB0 => Ok(0x7f),
+        ZRUB1 => Ok(0xff),
+        b if b & 0x60 == 0x40 => Ok(b ^ 0x40),
+        _ => Err(ZmodemError::Protocol("bad escape sequence".to_string())),
+    }
+}
+
+fn escape_into(data: &[u8], out: &mut Vec<u8>) {
+    let mut last = 0u8;
+    for &byte in data {
+        let needs_escape = matches!(byte, ZDLE | 0x10 | 0x11 | 0x13 | 0x90 | 0x91 | 0x93)
+            || (byte & 0x7f == b'\r' && last & 0x7f == b'@');
+        if needs_escape {
+            out.push(ZDLE);
+            out.push(byte ^ 0x40);
+        } else {
+            out.push(byte);
+        }
+        last = byte;
+    }
+}
+
+fn position_bytes(pos: u64) -> [u8; 4] {
+    (pos as u32).to_le_bytes()
+}
+
+/// Progress callback: (file name, transferred bytes, total bytes)
+pub type Progress<'a> = &'a mut dyn FnMut(&str, u64, u64);
+
+/// Receives files from a remote `sz` into `save_dir`.
+pub fn receive<T: Read + Write>(
+    port: &mut Port<T>,
+    save_dir: &Path,
+    progress: Progress<'_>,
+) -> ZResult<Vec<PathBuf>> {
+    let mut received = Vec::new();
+    let rinit = [0, 0, 0, CANFDX | CANOVIO | CANFC32];
+    port.write_hex_header(ZRINIT, rinit)?;
+
+    let mut retries = 0;
+    loop {
+        let header = match port.read_header() {
+            Ok(h) => h,
+            Err(ZmodemError::Timeout) if retries < MAX_RETRIES => {
+                retries += 1;
+                port.write_hex_header(ZRINIT, rinit)?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        match header.frame_type {
+            ZRQINIT => port.write_hex_header(ZRINIT, rinit)?,
+            ZSINIT => {
+                // Attention string is not used, just acknowledge it
+                let _ = port.read_subpacket(header.crc32)?;
+                port.write_hex_header(ZACK, [0; 4])?;
+            }
+            ZFILE => {
+                let (info, _) = match port.read_subpacket(header.crc32) {
+                    Ok(sub) => sub,
+                    Err(ZmodemError::Protocol(_)) => {
+                        port.write_hex_header(ZNAK, [0; 4])?;
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                let (name, size) = parse_file_info(&info);
+                let path = save_dir.join(&name);
+                match receive_file(port, &path, &name, size, progress) {
+                    Ok(()) => received.push(path),
+                    Err(e) => {
+                        let _ = std::fs::remove_file(&path);
+                        return Err(e);
+                    }
+                }
+                port.write_hex_header(ZRINIT, rinit)?;
+            }
+            ZFIN => {
+                port.write_hex_header(ZFIN, [0; 4])?;
+                // Swallow the trailing "OO" if it arrives promptly
+                for _ in 0..2 {
+                    match port.read_byte_within(Duration::from_millis(500)) {
+                        Ok(b'O') => {}
+                        Ok(_) => {
+                            port.pending_pos -= 1;
+                            break;
+                        }
+                        Err(_) => break,
+                    }
+                }
+                return Ok(received);
+            }
+            ZCAN | ZABORT => return Err(ZmodemError::RemoteAborted),
+            _ => port.write_hex_header(ZRINIT, rinit)?,
+        }
+    }
+}
+
+fn receive_file<T: Read + Write>(
+    port: &mut Port<T>,
+    path: &Path,
+    name: &str,
+    size: u64,
+    progress: Progress<'_>,
+) -> ZResult<()> {
+    let mut file = File::create(path)?;
+    let mut offset = 0u64;
+    let mut retries = 0;
+    port.write_hex_header(ZRPOS, position_bytes(offset))?;
+
+    loop {
+        let header = match port.read_header() {
+            Ok(h) => h,
+            Err(ZmodemError::Protocol(_)) | Err(ZmodemError::Timeout) if retries < MAX_RETRIES => {
+                retries += 1;
+                port.write_hex_header(ZRPOS, position_bytes(offset))?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        match header.frame_type {
+            ZDATA => {
+                if header.position() != offset {
+                    port.write_hex_header(ZRPOS, position_bytes(offset))?;
+                    continue;
+                }
+                // Read subpackets until the frame ends
+                loop {
+                    let (data, end) = match port.read_subpacket(header.crc32) {
+                        Ok(sub) => sub,
+                        Err(ZmodemError::Protocol(_)) if retries < MAX_RETRIES => {
+                            retries += 1;
+                            port.write_hex_header(ZRPOS, position_bytes(offset))?;
+                            break;
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    file.write_all(&data)?;
+                    offset += data.len() as u64;
+                    progress(name, offset, size);
+
+                    match end {
+                        ZCRCW => {
+                            port.write_hex_header(ZACK, position_bytes(offset))?;
+                            break;
+                        }
+                        ZCRCQ => port.write_hex_header(ZACK, position_bytes(offset))?,
+                        ZCRCE => break,
+                        _ => {}
+                    }
+                }
+            }
+            ZEOF => {
+                if header.position() != offset {
+                    // Stale EOF from before a ZRPOS, ignore it
+                    continue;
+                }
+                file.flush()?;
+                progress(name, offset, size.max(offset));
+                return Ok(());
+            }
+            ZFILE => {
+                // Our ZRPOS got lost, the sender is repeating the file header
+                let _ = port.read_subpacket(header.crc32);
+                port.write_hex_header(ZRPOS, position_bytes(offset))?;
+            }
+            ZCAN | ZABORT | ZFERR => return Err(ZmodemError::RemoteAborted),
+            _ => port.write_hex_header(ZRPOS, position_bytes(offset))?,
+        }
+    }
+}
+
+/// File info subpacket: "name\0size mtime mode ...\0"
+fn parse_file_info(info: &[u8]) -> (String, u64) {
+    let mut parts = info.split(|&b| b == 0);
+    let raw_name = String::from_utf8_lossy(parts.next().unwrap_or_default()).into_owned();
+    // Never trust remote paths, keep only the file name
+    let name = Path::new(&raw_name)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .filter(|n| !n.is_empty() && n != "." && n != "..")
+        .unwrap_or_else(|| "zmodem-download".to_string());
+    let size = parts
+        .next()
+        .and_then(|meta| String::from_utf8_lossy(meta).split_whitespace().next().map(str::to_string))
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+    (name, size)
+}
+
+/// Sends local files to a remote `rz`.
+pub fn send<T: Read + Write>(
+    port: &mut Port<T>,
+    paths: &[PathBuf],
+    progress: Progress<'_>,
+) -> ZResult<()> {
+    // rz already sent ZRINIT; consume it (and any repeats) first
+    wait_for(port, &[ZRINIT])?;
+
+    for path in paths {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "upload".to_string());
+
+        let mut info = name.as_bytes().to_vec();
+        info.push(0);
+        info.extend_from_slice(format!("{} 0 100644", size).as_bytes());
+        info.push(0);
+
+        let mut retries = 0;
+        let start = loop {
+            port.write_bin_header(ZFILE, [0; 4])?;
+            port.write_subpacket(&info, ZCRCW)?;
+            let header = wait_for(port, &[ZRPOS, ZSKIP, ZRINIT])?;
+            match header.frame_type {
+                ZRPOS => break Some(header.position()),
+                ZSKIP => break None,
+                _ => {
+                    retries += 1;
+                    if retries > MAX_RETRIES {
+                        return Err(ZmodemError::Protocol("receiver did not accept file".to_string()));
+                    }
+                }
+            }
+        };
+
+        let Some(mut offset) = start else {
+            continue;
+        };
+
+        loop {
+            file.seek(SeekFrom::Start(offset))?;
+            port.write_bin_header(ZDATA, position_bytes(offset))?;
+
+            let mut buffer = [0u8; SUBPACKET_LEN];
+            loop {
+                let n = file.read(&mut buffer)?;
+                let end = if n < SUBPACKET_LEN || offset + n as u64 >= size {
+                    ZCRCE
+                } else {
+                    ZCRCG
+                };
+                port.write_subpacket(&buffer[..n], end)?;
+                offset += n as u64;
+                progress(&name, offset, size);
+                if end == ZCRCE {
+                    break;
+                }
+            }
+
+            port.write_hex_header(ZEOF, position_bytes(offset))?;
+            let header = wait_for(port, &[ZRINIT, ZRPOS, ZSKIP])?;
+            match header.frame_type {
+                ZRPOS => offset = header.position(),
+                _ => break,
+            }
+        }
+    }
+
+    port.write_hex_header(ZFIN, [0; 4])?;
+    if wait_for(port, &[ZFIN]).is_ok() {
+        port.write_all(b"OO")?;
+    }
+    Ok(())
+}
+
+fn wait_for<T: Read + Write>(port: &mut Port<T>, types: &[u8]) -> ZResult<Header> {
+    let mut skipped = 0;
+    loop {
+        let header = port.read_header()?;
+        if types.contains(&header.frame_type) {
+            return Ok(header);
+        }
+        match header.frame_type {
+            ZCAN | ZABORT | ZFERR => return Err(ZmodemError::RemoteAborted),
+            ZNAK | ZACK | ZRQINIT => {}
+            _ => {
+                skipped += 1;
+                if skipped > MAX_RETRIES {
+                    return Err(ZmodemError::Protocol(format!(
+                        "unexpected frame type {}",
+                        header.frame_type
+                    )));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_a_header_within_a_chunk() {
+        let mut detector = Detector::default();
+        let (offset, header, direction) = detector.scan(b"$ sz f\r\n**\x18B00000").unwrap();
+        assert_eq!(offset, 8);
+        assert_eq!(header, b"**\x18B00000");
+        assert_eq!(direction, Direction::Receive);
+    }
+
+    #[test]
+    fn detects_a_header_split_across_chunks() {
+        for split in 1..RZ_MARKER.len() {
+            let mut detector = Detector::default();
+            let mut first = b"$ rz\r\n".to_vec();
+            first.extend_from_slice(&RZ_MARKER[..split]);
+            assert!(detector.scan(&first).is_none());
+            let mut second = RZ_MARKER[split..].to_vec();
+            second.extend_from_slice(b"0000");
+            let (offset, header, direction) = detector.scan(&second).unwrap();
+            assert_eq!(offset, 0);
+            assert_eq!(header, b"**\x18B010000");
+            assert_eq!(direction, Direction::Send);
+        }
+    }
+
+    #[test]
+    fn ignores_plain_output() {
+        let mut detector = Detector::default();
+        assert!(detector.scan(b"ls **").is_none());
+        assert!(detector.scan(b"\x18A").is_none());
+        assert!(detector.scan(b"hello").is_none());
+    }
+}
@@ -1,7 +1,8 @@
-import { useState } from "react";
+import { useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { Toaster, toast } from "sonner";
-import { ConnectionDetails } from "./components/VaultSidebar";
+import { ConnectionDetails, SavedHost } from "./components/VaultSidebar";
 import { DashboardView } from "./components/views/DashboardView";
 import { HostsView } from "@/components/views/HostsView";
 import { KeychainView } from "@/components/views/KeychainView";
@@ -15,6 +16,7 @@ import { PlaceholderView } from "./components/PlaceholderView";
 import { Icons } from "@/components/ui/icons";
 import { useSettings } from "@/context/SettingsContext";
 import { motion, AnimatePresence } from "framer-motion";
+import { errorMessage } from "@/lib/errors";
 
 // Export this interface so TerminalView can use it
 export interface Session {
@@ -60,7 +62,7 @@ function App() {
       }, 800);
     } catch (err) {
       setIsConnecting(false);
-      toast.error(`Connection failed: ${err}`);
+      toast.error(`Connection failed: ${errorMessage(err)}`);
     }
   };
 
@@ -84,6 +86,23 @@ function App() {
     }
   };
 
+  // Tray menu: "Show" on a session and "Connect To" on a pinned host
+  useEffect(() => {
+    const unlistenFocus = listen<{ session_id: string }>("focus-session", (event) => {
+      setActiveSessionId(event.payload.session_id);
+      setActiveNavItem("terminal");
+    });
+    const unlistenConnect = listen<{ host_id: string }>("tray-connect-host", async (event) => {
+      const hosts = await invoke<SavedHost[]>("load_saved_hosts");
+      const host = hosts.find((h) => h.id === event.payload.host_id);
+      if (host) handleConnect(host.details, host.name);
+    });
+    return () => {
+      unlistenFocus.then((unlisten) => unlisten());
+      unlistenConnect.then((unlisten) => unlisten());
+    };
+  }, [settings]);
+
   const handleDuplicateSession = async (sessionId: string) => {
     const sourceSession = sessions.find((s) => s.id === sessionId);
     if (!sourceSession) return;
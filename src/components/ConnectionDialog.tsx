@@ -27,6 +27,7 @@ import {
 import { Tabs, TabsContent, TabsList, TabsTrigger } from "@/components/ui/tabs"
 import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from "@/components/ui/select"
 import type { SavedHost } from "./VaultSidebar"
+import { errorMessage } from "@/lib/errors";
 
 const formSchema = z.object({
   name: z.string().min(1, "Host name is required"),
@@ -188,7 +189,7 @@ export function ConnectionDialog({ isOpen, setIsOpen, onSave, editingHost }: Con
       toast.success(isEditing ? "Connection updated" : "Connection saved");
     } catch (error) {
       console.error("Failed to save host:", error);
-      setSaveError(typeof error === "string" ? error : "Failed to save connection");
+      setSaveError(errorMessage(error) || "Failed to save connection");
     }
   }
 
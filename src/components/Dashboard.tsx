@@ -23,6 +23,7 @@ import {
   AlertDialogTitle,
 } from "@/components/ui/alert-dialog";
 import { cn } from '@/lib/utils';
+import { errorMessage } from "@/lib/errors";
 
 interface DashboardProps {
   onConnect: (details: ConnectionDetails, name: string) => void;
@@ -67,7 +68,7 @@ export function Dashboard({ onConnect }: DashboardProps) {
         setDeletingHost(null);
         return "Host deleted successfully.";
       },
-      error: (err) => `Failed to delete: ${err}`,
+      error: (err) => `Failed to delete: ${errorMessage(err)}`,
     });
   };
 
@@ -9,6 +9,7 @@ import { Dialog, DialogContent, DialogHeader, DialogTitle, DialogFooter } from "
 import { Input } from "@/components/ui/input";
 import { Button } from "@/components/ui/button";
 import { useSettings } from "@/context/SettingsContext";
+import { errorKind, errorMessage } from "@/lib/errors";
 
 interface SftpFile {
   name: string;
@@ -23,6 +24,7 @@ interface SftpBrowserProps {
 }
 
 interface TransferState {
+  transferId: string;
   filePath: string;
   transferredBytes: number;
   totalBytes: number;
@@ -36,6 +38,12 @@ interface TransferProgressPayload {
   total_bytes: number;
 }
 
+// Sent once when a transfer ends, whether it succeeded or not
+interface TransferEndedPayload {
+  transfer_id: string;
+  session_id: string;
+}
+
 export function SftpBrowser({ sessionId }: SftpBrowserProps) {
   const { settings } = useSettings();
   const [files, setFiles] = useState<SftpFile[]>([]);
@@ -53,21 +61,26 @@ export function SftpBrowser({ sessionId }: SftpBrowserProps) {
   const [permValue, setPermValue] = useState("755");
   const [fileToEdit, setFileToEdit] = useState<SftpFile | null>(null);
 
-  const fetchFiles = async () => {
+  const fetchFiles = async (operationId?: string) => {
     setIsLoading(true);
     try {
-      const result = await invoke<SftpFile[]>("list_directory", { sessionId, path: currentPath });
+      const result = await invoke<SftpFile[]>("list_directory", { sessionId, path: currentPath, operationId });
       setFiles(result);
     } catch (error) {
-      const errorMsg = typeof error === "string" ? error : String(error);
-      toast.error(`Failed to list directory: ${errorMsg}`);
+      if (errorKind(error) === "cancelled") return;
+      toast.error(`Failed to list directory: ${errorMessage(error)}`);
     } finally {
       setIsLoading(false);
     }
   };
 
   useEffect(() => {
-    fetchFiles();
+    // Navigating away stops a listing that is still running
+    const operationId = crypto.randomUUID();
+    fetchFiles(operationId);
+    return () => {
+      invoke("cancel_sftp_operation", { operationId }).catch(() => {});
+    };
   }, [sessionId, currentPath]);
 
   useEffect(() => {
@@ -85,8 +98,16 @@ export function SftpBrowser({ sessionId }: SftpBrowserProps) {
       });
     });
 
+    const clearEnded = (event: { payload: TransferEndedPayload }) => {
+      setTransferState((prev) => (prev?.transferId === event.payload.transfer_id ? null : prev));
+    };
+    const unlistenCompleted = listen<TransferEndedPayload>("transfer-completed", clearEnded);
+    const unlistenFailed = listen<TransferEndedPayload>("transfer-failed", clearEnded);
+
     return () => {
       unlistenPromise.then((unlisten) => unlisten());
+      unlistenCompleted.then((unlisten) => unlisten());
+      unlistenFailed.then((unlisten) => unlisten());
     };
   }, [sessionId]);
 
@@ -132,7 +153,7 @@ export function SftpBrowser({ sessionId }: SftpBrowserProps) {
         setIsMkdirOpen(false);
         fetchFiles();
     } catch (e) {
-        toast.error(`Failed to create folder: ${e}`);
+        toast.error(`Failed to create folder: ${errorMessage(e)}`);
     }
   };
 
@@ -144,7 +165,7 @@ export function SftpBrowser({ sessionId }: SftpBrowserProps) {
         toast.success("Item deleted");
         fetchFiles();
     } catch (e) {
-        toast.error(`Failed to delete: ${e}`);
+        toast.error(`Failed to delete: ${errorMessage(e)}`);
     }
   };
 
@@ -160,7 +181,7 @@ export function SftpBrowser({ sessionId }: SftpBrowserProps) {
         setFileToEdit(null);
         fetchFiles();
     } catch (e) {
-        toast.error(`Rename failed: ${e}`);
+        toast.error(`Rename failed: ${errorMessage(e)}`);
     }
   };
 
@@ -174,7 +195,9 @@ export function SftpBrowser({ sessionId }: SftpBrowserProps) {
     if (!localPath) return;
 
     const remotePath = getFullPath(selectedFile.name);
+    const transferId = crypto.randomUUID();
     setTransferState({
+      transferId,
       filePath: remotePath,
       transferredBytes: 0,
       totalBytes: selectedFile.size,
@@ -186,6 +209,7 @@ export function SftpBrowser({ sessionId }: SftpBrowserProps) {
         sessionId,
         remotePath,
         localPath,
+        transferId,
       }),
       {
         loading: `Downloading ${selectedFile.name}...`,
@@ -195,7 +219,7 @@ export function SftpBrowser({ sessionId }: SftpBrowserProps) {
         },
         error: (err) => {
           setTransferState(null);
-          return typeof err === "string" ? err : "Download failed";
+          return errorMessage(err) || "Download failed";
         },
       }
     );
@@ -212,7 +236,9 @@ export function SftpBrowser({ sessionId }: SftpBrowserProps) {
     }
 
     const remotePath = getFullPath(fileName);
+    const transferId = crypto.randomUUID();
     setTransferState({
+      transferId,
       filePath: localPath,
       transferredBytes: 0,
       totalBytes: 0,
@@ -224,6 +250,7 @@ export function SftpBrowser({ sessionId }: SftpBrowserProps) {
         sessionId,
         localPath,
         remotePath,
+        transferId,
       }),
       {
         loading: `Uploading ${fileName}...`,
@@ -234,7 +261,7 @@ export function SftpBrowser({ sessionId }: SftpBrowserProps) {
         },
         error: (err) => {
           setTransferState(null);
-          return typeof err === "string" ? err : "Upload failed";
+          return errorMessage(err) || "Upload failed";
         },
       }
     );
@@ -258,7 +285,7 @@ export function SftpBrowser({ sessionId }: SftpBrowserProps) {
         setFileToEdit(null);
         fetchFiles();
     } catch (e) {
-        toast.error(`Chmod failed: ${e}`);
+        toast.error(`Chmod failed: ${errorMessage(e)}`);
     }
   };
 
@@ -18,6 +18,23 @@ export interface SavedHost {
   group?: string;
   tags?: string[];
   details: ConnectionDetails;
+  // Wake-on-LAN, see wake_host
+  mac_address?: string;
+  broadcast_address?: string;
+  // Skipped by start_host_monitor
+  reachability_disabled?: boolean;
+  tunnels?: SavedTunnel[];
+}
+
+export interface SavedTunnel {
+  id: string;
+  name?: string | null;
+  kind: "local" | "remote" | "dynamic";
+  bind_address?: string | null;
+  bind_port: number;
+  target_host?: string | null;
+  target_port?: number | null;
+  auto_start: boolean;
 }
 
 interface SidebarProps {
@@ -8,6 +8,7 @@ import { ConnectionDialog } from '../ConnectionDialog';
 import { DropdownMenu, DropdownMenuContent, DropdownMenuItem, DropdownMenuTrigger } from "@/components/ui/dropdown-menu";
 import { AlertDialog, AlertDialogAction, AlertDialogCancel, AlertDialogContent, AlertDialogDescription, AlertDialogFooter, AlertDialogHeader, AlertDialogTitle } from "@/components/ui/alert-dialog";
 import { toast } from "sonner";
+import { errorMessage } from "@/lib/errors";
 
 interface DashboardViewProps {
   onConnect: (details: ConnectionDetails, name: string) => void
@@ -101,7 +102,7 @@ export function DashboardView({ onConnect, onViewChange, activeSessions }: Dashb
       setDeletingHost(null);
       toast.success("Host deleted");
     } catch(e) { 
-      toast.error(String(e)); 
+      toast.error(errorMessage(e));
     }
   };
 
@@ -8,6 +8,7 @@ import { DropdownMenu, DropdownMenuContent, DropdownMenuItem, DropdownMenuTrigge
 import { AlertDialog, AlertDialogAction, AlertDialogCancel, AlertDialogContent, AlertDialogDescription, AlertDialogFooter, AlertDialogHeader, AlertDialogTitle } from "@/components/ui/alert-dialog";
 import { cn } from "@/lib/utils";
 import { toast } from "sonner";
+import { errorMessage } from "@/lib/errors";
 
 interface HostsViewProps {
   onConnect: (details: ConnectionDetails, name: string) => void;
@@ -53,7 +54,7 @@ export function HostsView({ onConnect }: HostsViewProps) {
       setDeletingHost(null);
       toast.success("Host deleted");
     } catch (e) {
-      toast.error(String(e));
+      toast.error(errorMessage(e));
     }
   };
 
@@ -4,6 +4,7 @@ import { Trash2, ShieldAlert, RefreshCw, Search } from "lucide-react";
 import { motion, AnimatePresence } from "framer-motion";
 import { toast } from "sonner";
 import { cn } from "@/lib/utils";
+import { errorMessage } from "@/lib/errors";
 
 interface KnownHostEntry {
   line_number: number;
@@ -11,6 +12,8 @@ interface KnownHostEntry {
   hostnames: string;
   key_type: string;
   key_preview: string;
+  hashed: boolean;
+  file: string;
 }
 
 export function KnownHostsView() {
@@ -35,19 +38,20 @@ export function KnownHostsView() {
     loadEntries();
   }, []);
 
-  const handleDelete = async (lineNum: number, host: string) => {
+  const handleDelete = async (entry: KnownHostEntry) => {
     try {
-        await invoke("delete_known_host_entry", { lineNumber: lineNum });
-        toast.success(`Removed ${host} from known_hosts`);
+        await invoke("delete_known_host_entry", { lineNumber: entry.line_number, expected: entry });
+        toast.success(`Removed ${entry.hostnames} from known_hosts`);
         loadEntries(); // Reload list
     } catch (err) {
-        toast.error(`Failed to delete entry: ${err}`);
+        toast.error(`Failed to delete entry: ${errorMessage(err)}`);
     }
   };
 
   const filteredEntries = entries.filter(entry => 
     entry.hostnames.toLowerCase().includes(searchQuery.toLowerCase()) ||
-    entry.key_type.toLowerCase().includes(searchQuery.toLowerCase())
+    entry.key_type.toLowerCase().includes(searchQuery.toLowerCase()) ||
+    entry.file.toLowerCase().includes(searchQuery.toLowerCase())
   );
 
   return (
@@ -100,7 +104,7 @@ export function KnownHostsView() {
                     ) : (
                         filteredEntries.map((entry, i) => (
                             <motion.div
-                                key={entry.line_number}
+                                key={`${entry.file}:${entry.line_number}`}
                                 initial={{ opacity: 0, y: 10 }}
                                 animate={{ opacity: 1, y: 0 }}
                                 exit={{ opacity: 0, scale: 0.95 }}
@@ -121,11 +125,14 @@ export function KnownHostsView() {
                                         <p className="text-xs text-muted-foreground font-mono truncate max-w-md">
                                             {entry.key_preview}
                                         </p>
+                                        <p className="text-[10px] text-muted-foreground truncate max-w-md" title={entry.file}>
+                                            {entry.file}:{entry.line_number}
+                                        </p>
                                     </div>
                                 </div>
 
                                 <button
-                                    onClick={() => handleDelete(entry.line_number, entry.hostnames)}
+                                    onClick={() => handleDelete(entry)}
                                     className="p-2 rounded-lg text-muted-foreground hover:text-destructive hover:bg-destructive/10 transition-colors opacity-0 group-hover:opacity-100 focus:opacity-100"
                                     title="Remove from known_hosts"
                                 >
@@ -1,4 +1,5 @@
 import { useState, useEffect } from "react";
+import { invoke } from "@tauri-apps/api/core";
 import { Icons } from "@/components/ui/icons";
 import { cn } from "@/lib/utils";
 import { Terminal } from "../Terminal"; // Real Terminal
@@ -29,6 +30,15 @@ export function TerminalView({
   const [activeMode, setActiveMode] = useState<'terminal' | 'sftp'>('terminal');
   const [showSnippets, setShowSnippets] = useState(false);
 
+  // Armed notifications are held back for the tab being looked at
+  useEffect(() => {
+    if (!activeSessionId) return;
+    invoke("set_notification_focus", { sessionId: activeSessionId, focused: true }).catch(() => {});
+    return () => {
+      invoke("set_notification_focus", { sessionId: activeSessionId, focused: false }).catch(() => {});
+    };
+  }, [activeSessionId]);
+
   // Hyprland-style keyboard shortcuts
   useEffect(() => {
     const handleKeyDown = (e: KeyboardEvent) => {
@@ -0,0 +1,80 @@
+// Errors returned by backend commands (src-tauri/src/error.rs).
+// Branch on `kind`; `message` is for display only.
+export type AppErrorKind =
+  | "other"
+  | "invalid-input"
+  | "not-found"
+  | "already-exists"
+  | "permission-denied"
+  | "session-not-found"
+  | "sftp-not-initialized"
+  | "connection-failed"
+  | "connection-lost"
+  | "timeout"
+  | "handshake-failed"
+  | "auth-failed"
+  | "host-key-changed"
+  | "no-space"
+  | "config-busy"
+  | "vault-locked"
+  | "password-required"
+  | "passphrase-required"
+  | "host-deleted"
+  | "cancelled"
+  | "rate-limited"
+  | "unsupported"
+  | "read-only";
+
+// A connect attempt's phases (src-tauri/src/connect_timeline.rs)
+export type ConnectPhase =
+  | "resolve"
+  | "tcp_connect"
+  | "handshake"
+  | "host_key"
+  | "auth"
+  | "channel"
+  | "pty"
+  | "shell";
+
+export interface ConnectTimeline {
+  attempt_id: string;
+  // Unix timestamp in milliseconds
+  started_at: number;
+  phases: {
+    phase: ConnectPhase;
+    detail: string | null;
+    started_ms: number;
+    duration_ms: number;
+    error: string | null;
+  }[];
+}
+
+export interface AppError {
+  kind: AppErrorKind;
+  message: string;
+  details: {
+    errno?: number;
+    ssh_code?: number;
+    sftp_status?: number;
+    retry_after_secs?: number;
+    action?: "wake-host";
+    line?: number;
+    timeline?: ConnectTimeline;
+  } | null;
+  session_id: string | null;
+}
+
+export function isAppError(err: unknown): err is AppError {
+  return typeof err === "object" && err !== null && "kind" in err && "message" in err;
+}
+
+export function errorMessage(err: unknown): string {
+  if (isAppError(err)) return err.message;
+  if (typeof err === "string") return err;
+  if (err instanceof Error) return err.message;
+  return String(err);
+}
+
+export function errorKind(err: unknown): AppErrorKind {
+  return isAppError(err) ? err.kind : "other";
+}